@@ -4,7 +4,11 @@ extern crate blockstack_lib;
 extern crate rand;
 
 use blockstack_lib::chainstate::burn::BlockHeaderHash;
-use blockstack_lib::chainstate::stacks::index::{marf::MARF, storage::TrieFileStorage, MARFValue};
+use blockstack_lib::chainstate::stacks::index::{
+    marf::MARF,
+    storage::{TrieFileStorage, DEFAULT_NODE_CACHE_CAPACITY},
+    MARFValue,
+};
 
 use criterion::Criterion;
 use rand::prelude::*;
@@ -69,13 +73,20 @@ fn benchmark_marf_usage(
     marf.commit().unwrap();
 }
 
-fn benchmark_marf_read(filename: &str, reads: u32, block: u32, writes_per_block: u32) {
+fn benchmark_marf_read(
+    filename: &str,
+    reads: u32,
+    block: u32,
+    writes_per_block: u32,
+    node_cache_capacity: usize,
+) {
     let f = TrieFileStorage::new(filename).unwrap();
     let mut block_header = block.to_le_bytes().to_vec();
     block_header.resize(32, 0);
     let block_header = BlockHeaderHash::from_bytes(block_header.as_slice()).unwrap();
 
     let mut marf = MARF::from_storage(f);
+    marf.set_node_cache_capacity(node_cache_capacity);
 
     let mut rng = rand::thread_rng();
 
@@ -95,10 +106,26 @@ pub fn basic_usage_benchmark(c: &mut Criterion) {
         b.iter(|| benchmark_marf_usage("/tmp/db.400.sqlite", 1000, 5000, 0, false))
     });
     c.bench_function("marf_read_1000b_1kW", |b| {
-        b.iter(|| benchmark_marf_read("/tmp/db.1k.sqlite", 1000, 1000, 5000))
+        b.iter(|| {
+            benchmark_marf_read(
+                "/tmp/db.1k.sqlite",
+                1000,
+                1000,
+                5000,
+                DEFAULT_NODE_CACHE_CAPACITY,
+            )
+        })
     });
     c.bench_function("marf_read_400b_1kW", |b| {
-        b.iter(|| benchmark_marf_read("/tmp/db.400.sqlite", 1000, 400, 5000))
+        b.iter(|| {
+            benchmark_marf_read(
+                "/tmp/db.400.sqlite",
+                1000,
+                400,
+                5000,
+                DEFAULT_NODE_CACHE_CAPACITY,
+            )
+        })
     });
 
     c.bench_function("marf_usage_1b_10kW_0kR", |b| {
@@ -117,5 +144,25 @@ pub fn basic_usage_benchmark(c: &mut Criterion) {
 
 pub fn scaling_read_ratio(_c: &mut Criterion) {}
 
-criterion_group!(benches, basic_usage_benchmark);
+pub fn node_cache_benchmark(c: &mut Criterion) {
+    c.bench_function("marf_setup_100b_5kW", |b| {
+        b.iter(|| benchmark_marf_usage("/tmp/db.100.sqlite", 100, 5000, 0, false))
+    });
+    c.bench_function("marf_read_100b_10kR_no_node_cache", |b| {
+        b.iter(|| benchmark_marf_read("/tmp/db.100.sqlite", 10000, 100, 5000, 0))
+    });
+    c.bench_function("marf_read_100b_10kR_node_cache", |b| {
+        b.iter(|| {
+            benchmark_marf_read(
+                "/tmp/db.100.sqlite",
+                10000,
+                100,
+                5000,
+                DEFAULT_NODE_CACHE_CAPACITY,
+            )
+        })
+    });
+}
+
+criterion_group!(benches, basic_usage_benchmark, node_cache_benchmark);
 criterion_main!(benches);
@@ -256,6 +256,15 @@ impl BurnchainDB {
         Ok(opt.expect("CORRUPTION: No canonical burnchain tip"))
     }
 
+    pub fn get_burnchain_header(
+        &self,
+        block: &BurnchainHeaderHash,
+    ) -> Result<Option<BurnchainBlockHeader>, BurnchainError> {
+        let qry = "SELECT * FROM burnchain_db_block_headers WHERE block_hash = ? LIMIT 1";
+        let res = query_row(&self.conn, qry, &[block])?;
+        Ok(res)
+    }
+
     pub fn get_burnchain_block(
         &self,
         block: &BurnchainHeaderHash,
@@ -0,0 +1,347 @@
+// Copyright (C) 2013-2020 Blockstack PBC, a public benefit corporation
+// Copyright (C) 2020 Stacks Open Internet Foundation
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <http://www.gnu.org/licenses/>.
+
+use std::cmp;
+
+use burnchains::BurnchainSigner;
+use chainstate::burn::db::sortdb::{SortitionDB, SortitionDBConn};
+use chainstate::burn::operations::LeaderBlockCommitOp;
+use chainstate::burn::BlockSnapshot;
+use util::db::Error as db_error;
+
+/// How a miner's block-commits fared against everyone else's in recent sortitions
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct BurnBidStats {
+    /// number of burnchain blocks sampled
+    pub num_blocks: u64,
+    /// number of sampled blocks that contained a block-commit from another miner
+    pub num_contested: u64,
+    /// total burnchain tokens committed by other miners in the sampled blocks
+    pub others_burn: u64,
+    /// number of block-commits sent by other miners in the sampled blocks
+    pub others_commits: u64,
+    /// total burnchain tokens committed by this miner in the sampled blocks
+    pub our_burn: u64,
+    /// number of block-commits sent by this miner in the sampled blocks
+    pub our_commits: u64,
+}
+
+impl BurnBidStats {
+    /// Tally the block-commits in the `window` burnchain blocks that end at (and include) `tip`,
+    /// in `tip`'s fork.  Block-commits sent by `miner` are counted as ours.
+    pub fn from_sortitions(
+        ic: &SortitionDBConn,
+        tip: &BlockSnapshot,
+        window: u64,
+        miner: &BurnchainSigner,
+    ) -> Result<BurnBidStats, db_error> {
+        let mut stats = BurnBidStats::default();
+        for i in 0..cmp::min(window, tip.block_height + 1) {
+            let snapshot = if i == 0 {
+                tip.clone()
+            } else {
+                match SortitionDB::get_ancestor_snapshot(
+                    ic,
+                    tip.block_height - i,
+                    &tip.sortition_id,
+                )? {
+                    Some(sn) => sn,
+                    None => break,
+                }
+            };
+
+            let block_commits =
+                SortitionDB::get_block_commits_by_block(ic, &snapshot.sortition_id)?;
+            stats.add_block(&block_commits, miner);
+        }
+        Ok(stats)
+    }
+
+    fn add_block(&mut self, block_commits: &[LeaderBlockCommitOp], miner: &BurnchainSigner) {
+        self.num_blocks += 1;
+        let mut contested = false;
+        for op in block_commits.iter() {
+            if op.apparent_sender == *miner {
+                self.our_burn = self.our_burn.saturating_add(op.burn_fee);
+                self.our_commits += 1;
+            } else {
+                self.others_burn = self.others_burn.saturating_add(op.burn_fee);
+                self.others_commits += 1;
+                contested = true;
+            }
+        }
+        if contested {
+            self.num_contested += 1;
+        }
+    }
+
+    /// Average total burn committed by other miners in a block that they competed in
+    pub fn avg_others_block_burn(&self) -> u64 {
+        if self.num_contested == 0 {
+            0
+        } else {
+            self.others_burn / self.num_contested
+        }
+    }
+
+    /// Average burn of a single block-commit sent by another miner
+    pub fn avg_others_commit_burn(&self) -> u64 {
+        if self.others_commits == 0 {
+            0
+        } else {
+            self.others_burn / self.others_commits
+        }
+    }
+}
+
+/// The range of burnchain tokens a miner is willing to commit to a single block
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct BurnBidBudget {
+    pub min_burn: u64,
+    pub max_burn: u64,
+}
+
+impl BurnBidBudget {
+    /// Bring a bid within this budget
+    pub fn clamp(&self, bid: u64) -> u64 {
+        cmp::max(self.min_burn, cmp::min(self.max_burn, bid))
+    }
+}
+
+/// Split `burn` evenly across a block-commit's `num_outputs` PoX outputs, each of which has to
+/// carry at least `min_output_burn` (e.g. so it isn't dust).  Returns how much of `burn` the
+/// outputs carry, which is the largest multiple of `num_outputs` that fits, or None if `burn`
+/// can't give every output `min_output_burn`.
+pub fn split_commit_burn(burn: u64, num_outputs: u64, min_output_burn: u64) -> Option<u64> {
+    if num_outputs == 0 {
+        return None;
+    }
+    let output_burn = burn / num_outputs;
+    if output_burn < min_output_burn {
+        None
+    } else {
+        Some(output_burn * num_outputs)
+    }
+}
+
+/// A policy for how much a miner commits to its next block
+pub trait BurnBidStrategy {
+    /// Choose how many burnchain tokens to commit, given how recent sortitions went and how much
+    /// the block is worth to the miner (its coinbase plus its transaction fees, in microSTX).
+    /// The caller brings the bid within the miner's budget.
+    fn choose_bid(&self, stats: &BurnBidStats, expected_reward_ustx: u128) -> u64;
+}
+
+/// Always commit the same amount
+pub struct FixedBurnBid {
+    pub amount: u64,
+}
+
+impl BurnBidStrategy for FixedBurnBid {
+    fn choose_bid(&self, _stats: &BurnBidStats, _expected_reward_ustx: u128) -> u64 {
+        self.amount
+    }
+}
+
+/// Commit a little more than other miners' average block-commit.  With no competition, the
+/// budget's minimum is enough.
+pub struct MatchAverageBurnBid {
+    /// by how much (in percent) to outbid the average
+    pub bump_pct: u64,
+}
+
+impl BurnBidStrategy for MatchAverageBurnBid {
+    fn choose_bid(&self, stats: &BurnBidStats, _expected_reward_ustx: u128) -> u64 {
+        let avg = stats.avg_others_commit_burn() as u128;
+        let bid = avg * (100 + self.bump_pct as u128) / 100;
+        cmp::min(bid, u64::max_value() as u128) as u64
+    }
+}
+
+/// Commit whatever maximizes the expected profit of mining, given the other miners' recent total
+/// burn and the value of the block.  A miner that commits `x` against other miners' total burn
+/// `B` wins with probability `x / (x + B)`, so the expected profit of winning a block worth `R`
+/// is `R * x / (x + B) - x`, which peaks at `x = sqrt(R * B) - B`.  If the block is not worth
+/// more than the competition, this bids nothing (i.e. the budget's minimum).
+pub struct ExpectedValueBurnBid {
+    /// price of one STX in burnchain tokens (e.g. satoshis)
+    pub burn_per_stx: u64,
+}
+
+impl ExpectedValueBurnBid {
+    /// Value of the given amount of microSTX in burnchain tokens
+    pub fn reward_value(&self, reward_ustx: u128) -> u64 {
+        let value = reward_ustx.saturating_mul(self.burn_per_stx as u128) / 1_000_000;
+        cmp::min(value, u64::max_value() as u128) as u64
+    }
+}
+
+impl BurnBidStrategy for ExpectedValueBurnBid {
+    fn choose_bid(&self, stats: &BurnBidStats, expected_reward_ustx: u128) -> u64 {
+        let reward = self.reward_value(expected_reward_ustx);
+        let others = stats.avg_others_block_burn();
+        if others == 0 || reward <= others {
+            return 0;
+        }
+        let optimum = ((reward as f64) * (others as f64)).sqrt() as u64;
+        optimum.saturating_sub(others)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use address::AddressHashMode;
+    use burnchains::{BurnchainHeaderHash, Txid};
+    use chainstate::burn::{BlockHeaderHash, VRFSeed};
+    use chainstate::stacks::StacksPublicKey;
+
+    fn make_signer(pubkey_hex: &str) -> BurnchainSigner {
+        BurnchainSigner {
+            hash_mode: AddressHashMode::SerializeP2PKH,
+            num_sigs: 1,
+            public_keys: vec![StacksPublicKey::from_hex(pubkey_hex).unwrap()],
+        }
+    }
+
+    fn make_commit(sender: &BurnchainSigner, txid: u8, burn_fee: u64) -> LeaderBlockCommitOp {
+        LeaderBlockCommitOp {
+            sunset_burn: 0,
+            block_header_hash: BlockHeaderHash([txid; 32]),
+            new_seed: VRFSeed([0x01; 32]),
+            parent_block_ptr: 0,
+            parent_vtxindex: 0,
+            key_block_ptr: 0,
+            key_vtxindex: 0,
+            memo: vec![],
+            commit_outs: vec![],
+            burn_fee,
+            input: (Txid([0; 32]), 0),
+            apparent_sender: sender.clone(),
+            txid: Txid([txid; 32]),
+            vtxindex: txid as u32,
+            block_height: 100,
+            burn_parent_modulus: 0,
+            burn_header_hash: BurnchainHeaderHash([0x02; 32]),
+        }
+    }
+
+    #[test]
+    fn test_burn_bid_stats() {
+        let us = make_signer("02d8015134d9db8178ac93acbc43170a2f20febba5087a5b0437058765ad5133d0");
+        let them =
+            make_signer("0283d603abdd2392646dbdd0dc80beb39c25bfab96a8a921ea5e7517ce533f8cd5");
+
+        let mut stats = BurnBidStats::default();
+
+        // we competed against one other miner
+        stats.add_block(
+            &[make_commit(&us, 1, 1000), make_commit(&them, 2, 3000)],
+            &us,
+        );
+        // we competed against two other commits
+        stats.add_block(
+            &[
+                make_commit(&us, 3, 1000),
+                make_commit(&them, 4, 2000),
+                make_commit(&them, 5, 1000),
+            ],
+            &us,
+        );
+        // nobody competed with us
+        stats.add_block(&[make_commit(&us, 6, 1000)], &us);
+        // empty block
+        stats.add_block(&[], &us);
+
+        assert_eq!(
+            stats,
+            BurnBidStats {
+                num_blocks: 4,
+                num_contested: 2,
+                others_burn: 6000,
+                others_commits: 3,
+                our_burn: 3000,
+                our_commits: 3,
+            }
+        );
+        assert_eq!(stats.avg_others_block_burn(), 3000);
+        assert_eq!(stats.avg_others_commit_burn(), 2000);
+
+        let empty = BurnBidStats::default();
+        assert_eq!(empty.avg_others_block_burn(), 0);
+        assert_eq!(empty.avg_others_commit_burn(), 0);
+    }
+
+    #[test]
+    fn test_burn_bid_strategies() {
+        let stats = BurnBidStats {
+            num_blocks: 10,
+            num_contested: 10,
+            others_burn: 100_000,
+            others_commits: 20,
+            our_burn: 0,
+            our_commits: 0,
+        };
+        let budget = BurnBidBudget {
+            min_burn: 1000,
+            max_burn: 40_000,
+        };
+
+        // 1000 STX reward
+        let reward_ustx = 1_000_000_000u128;
+
+        assert_eq!(
+            FixedBurnBid { amount: 20_000 }.choose_bid(&stats, reward_ustx),
+            20_000
+        );
+
+        // average commit is 5000; outbid it by 10%
+        assert_eq!(
+            MatchAverageBurnBid { bump_pct: 10 }.choose_bid(&stats, reward_ustx),
+            5500
+        );
+        assert_eq!(
+            MatchAverageBurnBid { bump_pct: 10 }.choose_bid(&BurnBidStats::default(), reward_ustx),
+            0
+        );
+
+        // the block is worth 40,000 against 10,000 of competition: sqrt(40,000 * 10,000) - 10,000
+        let strategy = ExpectedValueBurnBid { burn_per_stx: 40 };
+        assert_eq!(strategy.reward_value(reward_ustx), 40_000);
+        assert_eq!(strategy.choose_bid(&stats, reward_ustx), 10_000);
+
+        // not worth bidding on a block that's worth less than the competition
+        let strategy = ExpectedValueBurnBid { burn_per_stx: 5 };
+        assert_eq!(strategy.choose_bid(&stats, reward_ustx), 0);
+
+        assert_eq!(budget.clamp(0), 1000);
+        assert_eq!(budget.clamp(5500), 5500);
+        assert_eq!(budget.clamp(1_000_000), 40_000);
+    }
+
+    #[test]
+    fn test_split_commit_burn() {
+        assert_eq!(split_commit_burn(20_000, 2, 5500), Some(20_000));
+        // the odd unit can't be split
+        assert_eq!(split_commit_burn(20_001, 2, 5500), Some(20_000));
+        assert_eq!(split_commit_burn(11_000, 2, 5500), Some(11_000));
+        // each output would be dust
+        assert_eq!(split_commit_burn(10_999, 2, 5500), None);
+        assert_eq!(split_commit_burn(5500, 1, 5500), Some(5500));
+        assert_eq!(split_commit_burn(5500, 0, 5500), None);
+    }
+}
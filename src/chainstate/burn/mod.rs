@@ -14,6 +14,7 @@
 // You should have received a copy of the GNU General Public License
 // along with this program.  If not, see <http://www.gnu.org/licenses/>.
 
+pub mod bidding;
 /// This module contains the code for processing the burn chain state database
 pub mod db;
 pub mod distribution;
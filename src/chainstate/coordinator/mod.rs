@@ -41,6 +41,7 @@ use chainstate::stacks::{
     Error as ChainstateError, StacksAddress, StacksBlock, StacksBlockHeader, StacksBlockId,
     TransactionPayload,
 };
+use core::mempool::{MemPoolDB, MemPoolEventDispatcher, MemPoolSettings};
 use monitoring::increment_stx_blocks_processed_counter;
 use net::atlas::{AtlasConfig, AttachmentInstance};
 use util::db::Error as DBError;
@@ -109,7 +110,7 @@ impl RewardCycleInfo {
     }
 }
 
-pub trait BlockEventDispatcher {
+pub trait BlockEventDispatcher: MemPoolEventDispatcher {
     fn announce_block(
         &self,
         block: StacksBlock,
@@ -154,6 +155,7 @@ pub struct ChainsCoordinator<
     reward_set_provider: R,
     notifier: N,
     atlas_config: AtlasConfig,
+    mempool: Option<MemPoolDB>,
 }
 
 #[derive(Debug)]
@@ -255,6 +257,7 @@ impl<'a, T: BlockEventDispatcher>
         dispatcher: &mut T,
         comms: CoordinatorReceivers,
         atlas_config: AtlasConfig,
+        mempool_settings: MemPoolSettings,
     ) where
         T: BlockEventDispatcher,
     {
@@ -273,6 +276,15 @@ impl<'a, T: BlockEventDispatcher>
             sortitions_processed,
         };
 
+        // new blocks can confirm nonces that pending transactions also spend
+        let mempool = match MemPoolDB::open_with_chainstate(&chain_state_db, mempool_settings) {
+            Ok(mempool) => Some(mempool),
+            Err(e) => {
+                warn!("Failed to open mempool: {:?}", &e);
+                None
+            }
+        };
+
         let mut inst = ChainsCoordinator {
             canonical_chain_tip: None,
             canonical_sortition_tip: Some(canonical_sortition_tip),
@@ -286,6 +298,7 @@ impl<'a, T: BlockEventDispatcher>
             notifier: arc_notices,
             reward_set_provider: OnChainRewardSetProvider(),
             atlas_config,
+            mempool,
         };
 
         loop {
@@ -352,6 +365,7 @@ impl<'a, T: BlockEventDispatcher, U: RewardSetProvider> ChainsCoordinator<'a, T,
             notifier: (),
             attachments_tx,
             atlas_config: AtlasConfig::default(),
+            mempool: None,
         }
     }
 }
@@ -572,6 +586,10 @@ impl<'a, T: BlockEventDispatcher, N: CoordinatorNotices, U: RewardSetProvider>
             }
         }
 
+        // if this is a pruned node, some blocks may have become old enough to discard
+        self.chain_state_db
+            .prune_old_blocks(&self.burnchain, canonical_burnchain_tip.block_height)?;
+
         Ok(())
     }
 
@@ -637,7 +655,7 @@ impl<'a, T: BlockEventDispatcher, N: CoordinatorNotices, U: RewardSetProvider>
                     ));
                     let new_canonical_stacks_block =
                         new_canonical_block_snapshot.get_canonical_stacks_block_id();
-                    self.canonical_chain_tip = Some(new_canonical_stacks_block);
+                    self.canonical_chain_tip = Some(new_canonical_stacks_block.clone());
                     debug!("Bump blocks processed");
                     self.notifier.notify_stacks_block_processed();
                     increment_stx_blocks_processed_counter();
@@ -682,6 +700,40 @@ impl<'a, T: BlockEventDispatcher, N: CoordinatorNotices, U: RewardSetProvider>
                         };
                     }
 
+                    // a block on a losing Stacks fork can spend a nonce that a pending
+                    // transaction still needs to spend on the canonical fork, so only the
+                    // canonical tip's transactions make pending ones unmineable.
+                    let processed_block_id =
+                        StacksBlockId::new(&block_receipt.header.consensus_hash, &block_hash);
+                    if processed_block_id != new_canonical_stacks_block {
+                        debug!(
+                            "Processed block {} is not the canonical Stacks tip {}; not dropping conflicting mempool transactions",
+                            &processed_block_id, &new_canonical_stacks_block
+                        );
+                    } else if let Some(ref mut mempool) = self.mempool {
+                        let confirmed_txs: Vec<_> = block_receipt
+                            .tx_receipts
+                            .iter()
+                            .filter_map(|receipt| match receipt.transaction {
+                                TransactionOrigin::Stacks(ref transaction) => {
+                                    Some(transaction.clone())
+                                }
+                                _ => None,
+                            })
+                            .collect();
+                        let event_observer = self
+                            .dispatcher
+                            .map(|dispatcher| dispatcher as &dyn MemPoolEventDispatcher);
+                        if let Err(e) =
+                            mempool.drop_conflicting_nonces(&confirmed_txs, event_observer)
+                        {
+                            warn!(
+                                "Failed to drop mempool transactions conflicting with {}: {:?}",
+                                &block_hash, &e
+                            );
+                        }
+                    }
+
                     if let Some(dispatcher) = self.dispatcher {
                         let metadata = &block_receipt.header;
                         let winner_txid = SortitionDB::get_block_snapshot_for_winning_stacks_block(
@@ -51,6 +51,9 @@ use vm::{
 
 use address;
 use chainstate;
+use chainstate::stacks::miner::test::make_user_stacks_transfer;
+use core::mempool::{MemPoolDB, MemPoolSettings};
+use net::StacksMessageCodec;
 
 lazy_static! {
     static ref BURN_BLOCK_HEADERS: Arc<AtomicU64> = Arc::new(AtomicU64::new(1));
@@ -323,6 +326,10 @@ impl BlockEventDispatcher for NullEventDispatcher {
     fn dispatch_boot_receipts(&mut self, _receipts: Vec<StacksTransactionReceipt>) {}
 }
 
+impl MemPoolEventDispatcher for NullEventDispatcher {
+    fn mempool_txs_dropped(&self, _txids: Vec<Txid>, _reason: core::mempool::MemPoolDropReason) {}
+}
+
 pub fn make_coordinator<'a>(
     path: &str,
     burnchain: Option<Burnchain>,
@@ -612,6 +619,43 @@ fn make_stacks_block_with_input(
     sunset_burn: u64,
     post_sunset_burn: bool,
     input: (Txid, u32),
+) -> (BlockstackOperationType, StacksBlock) {
+    make_stacks_block_with_input_and_txs(
+        sort_db,
+        state,
+        burnchain,
+        parent_block,
+        parent_height,
+        miner,
+        my_burn,
+        vrf_key,
+        key_index,
+        recipients,
+        sunset_burn,
+        post_sunset_burn,
+        input,
+        &[],
+    )
+}
+
+/// build a stacks block with the coinbase and then `txs` off of
+///  parent_block, in the canonical sortition fork of SortitionDB.
+/// parent_block _must_ be included in the StacksChainState
+fn make_stacks_block_with_input_and_txs(
+    sort_db: &SortitionDB,
+    state: &mut StacksChainState,
+    burnchain: &Burnchain,
+    parent_block: &BlockHeaderHash,
+    parent_height: u64,
+    miner: &StacksPrivateKey,
+    my_burn: u64,
+    vrf_key: &VRFPrivateKey,
+    key_index: u32,
+    recipients: Option<&RewardSetInfo>,
+    sunset_burn: u64,
+    post_sunset_burn: bool,
+    input: (Txid, u32),
+    txs: &[StacksTransaction],
 ) -> (BlockstackOperationType, StacksBlock) {
     let tx_auth = TransactionAuth::from_p2pkh(miner).unwrap();
 
@@ -667,6 +711,9 @@ fn make_stacks_block_with_input(
     .unwrap();
     let mut epoch_tx = builder.epoch_begin(state, &iconn).unwrap();
     builder.try_mine_tx(&mut epoch_tx, &coinbase_op).unwrap();
+    for tx in txs.iter() {
+        builder.try_mine_tx(&mut epoch_tx, tx).unwrap();
+    }
 
     let block = builder.mine_anchored_block(&mut epoch_tx);
     builder.epoch_finish(epoch_tx);
@@ -3534,6 +3581,127 @@ fn test_pox_fork_out_of_order() {
     );
 }
 
+#[test]
+fn test_mempool_keeps_txs_conflicting_with_non_canonical_blocks() {
+    let path = "/tmp/stacks-blockchain-mempool-non-canonical-conflict";
+    let _r = std::fs::remove_dir_all(path);
+
+    let vrf_keys: Vec<_> = (0..5).map(|_| VRFPrivateKey::new()).collect();
+    let committers: Vec<_> = (0..5).map(|_| StacksPrivateKey::new()).collect();
+
+    let sender = StacksPrivateKey::new();
+    let recipient = PrincipalData::from(p2pkh_from(&StacksPrivateKey::new()));
+    let initial_balances = vec![(PrincipalData::from(p2pkh_from(&sender)), 1_000_000)];
+
+    setup_states(
+        &[path],
+        &vrf_keys,
+        &committers,
+        None,
+        Some(initial_balances),
+    );
+
+    let mut coord = make_coordinator(path, None);
+    coord.handle_new_burnchain_block().unwrap();
+    coord.mempool = Some(
+        MemPoolDB::open_with_chainstate(&coord.chain_state_db, MemPoolSettings::default()).unwrap(),
+    );
+
+    let sort_db = get_sortition_db(path, None);
+    let b = get_burnchain(path, None);
+    let mut mempool = MemPoolDB::open(false, 0x80000000, &get_chainstate_path(path)).unwrap();
+
+    let pending_tx = make_user_stacks_transfer(&sender, 0, 1000, &recipient, 100);
+    let sibling_tx = make_user_stacks_transfer(&sender, 0, 2000, &recipient, 100);
+    let canonical_tx = make_user_stacks_transfer(&sender, 0, 3000, &recipient, 100);
+
+    // canonical fork: a1 <- a2 <- a3 <- a4.  Losing fork: a1 <- b2, whose sortition comes after
+    // a3's, so b2 is processed once the higher a3 is already the canonical Stacks tip.
+    // Each entry is the index of the block's parent (None for the first block) and its txs.
+    let forks: Vec<(Option<usize>, Vec<StacksTransaction>)> = vec![
+        (None, vec![]),
+        (Some(0), vec![]),
+        (Some(1), vec![]),
+        (Some(0), vec![sibling_tx.clone()]),
+        (Some(2), vec![canonical_tx.clone()]),
+    ];
+
+    let mut blocks: Vec<StacksBlock> = vec![];
+    for (ix, (parent_ix, txs)) in forks.iter().enumerate() {
+        let mut burnchain = get_burnchain_db(path, None);
+        let mut chainstate = get_chainstate(path);
+        let burnchain_tip = burnchain.get_canonical_chain_tip().unwrap();
+
+        let (op, block) = match parent_ix {
+            None => make_genesis_block(
+                &sort_db,
+                &mut chainstate,
+                &BlockHeaderHash([0; 32]),
+                &committers[ix],
+                10000,
+                &vrf_keys[ix],
+                ix as u32,
+            ),
+            Some(parent_ix) => make_stacks_block_with_input_and_txs(
+                &sort_db,
+                &mut chainstate,
+                &b,
+                &blocks[*parent_ix].block_hash(),
+                burnchain_tip.block_height,
+                &committers[ix],
+                10000,
+                &vrf_keys[ix],
+                ix as u32,
+                None,
+                0,
+                false,
+                (Txid([0; 32]), 0),
+                txs,
+            ),
+        };
+
+        produce_burn_block(
+            &mut burnchain,
+            &burnchain_tip.block_hash,
+            vec![op],
+            vec![].iter_mut(),
+        );
+        coord.handle_new_burnchain_block().unwrap();
+
+        let tip = SortitionDB::get_canonical_burn_chain_tip(sort_db.conn()).unwrap();
+        assert_eq!(&tip.winning_stacks_block_hash, &block.block_hash());
+        preprocess_block(&mut chainstate, &sort_db, &tip, block.clone());
+        coord.handle_new_stacks_block().unwrap();
+
+        let stacks_tip = SortitionDB::get_canonical_stacks_chain_tip_hash(sort_db.conn()).unwrap();
+        if ix == 2 {
+            // the tx spends the sender's next nonce on the canonical fork
+            assert_eq!(stacks_tip.1, block.block_hash());
+            mempool
+                .submit_raw(
+                    &mut chainstate,
+                    &stacks_tip.0,
+                    &stacks_tip.1,
+                    pending_tx.serialize_to_vec(),
+                )
+                .unwrap();
+        }
+        if ix == 3 {
+            // b2 spent the same nonce, but it is not the canonical tip, so the pending tx can
+            // still be mined on a3
+            assert_eq!(stacks_tip.1, blocks[2].block_hash());
+            assert!(mempool.has_tx(&pending_tx.txid()));
+        }
+
+        blocks.push(block);
+    }
+
+    // a4 spent the nonce on the canonical fork
+    let stacks_tip = SortitionDB::get_canonical_stacks_chain_tip_hash(sort_db.conn()).unwrap();
+    assert_eq!(stacks_tip.1, blocks[4].block_hash());
+    assert!(!mempool.has_tx(&pending_tx.txid()));
+}
+
 fn eval_at_chain_tip(chainstate_path: &str, sort_db: &SortitionDB, eval: &str) -> Value {
     let stacks_tip = SortitionDB::get_canonical_stacks_chain_tip_hash(sort_db.conn()).unwrap();
     let mut chainstate = get_chainstate(chainstate_path);
@@ -40,6 +40,8 @@ use chainstate::stacks::*;
 
 use chainstate::burn::BlockSnapshot;
 
+use burnchains::Burnchain;
+
 use std::path::{Path, PathBuf};
 
 use util::db::Error as db_error;
@@ -143,6 +145,12 @@ pub enum MemPoolRejection {
     NoCoinbaseViaMempool,
     NoSuchChainTip(ConsensusHash, BlockHeaderHash),
     ConflictingNonceInMempool,
+    InsufficientReplaceFee {
+        prior_txid: Txid,
+        min_fee: u64,
+        actual_fee: u64,
+    },
+    MemPoolFull,
     TooMuchChaining {
         max_nonce: u64,
         actual_nonce: u64,
@@ -161,6 +169,12 @@ impl MemPoolRejection {
             DeserializationFailure(e) => {
                 ("Deserialization", Some(json!({"message": e.to_string()})))
             }
+            MemPoolFull => (
+                "MemPoolFull",
+                Some(json!({
+                    "message": "Mempool is full, and the fee rate is too low to evict anything"
+                })),
+            ),
             TooMuchChaining {
                 max_nonce,
                 actual_nonce,
@@ -216,6 +230,19 @@ impl MemPoolRejection {
                 Some(json!({"message": e.to_string()})),
             ),
             ConflictingNonceInMempool => ("ConflictingNonceInMempool", None),
+            InsufficientReplaceFee {
+                prior_txid,
+                min_fee,
+                actual_fee,
+            } => (
+                "InsufficientReplaceFee",
+                Some(json!({
+                    "message": "Fee is too low to replace the transaction with the same nonce",
+                    "prior_txid": prior_txid.to_hex(),
+                    "expected": min_fee,
+                    "actual": actual_fee
+                })),
+            ),
             ContractAlreadyExists(id) => (
                 "ContractAlreadyExists",
                 Some(json!({ "contract_identifier": id.to_string() })),
@@ -671,6 +698,7 @@ impl StacksChainState {
     }
 
     /// Have we processed and stored a particular block?
+    /// A block whose body was pruned still counts as processed and stored.
     pub fn has_stored_block(
         blocks_db: &DBConn,
         blocks_dir: &String,
@@ -678,9 +706,9 @@ impl StacksChainState {
         block_hash: &BlockHeaderHash,
     ) -> Result<bool, Error> {
         let staging_status =
-            StacksChainState::has_staging_block(blocks_db, consensus_hash, block_hash)?;
+            StacksChainState::get_staging_block_status(blocks_db, consensus_hash, block_hash)?;
         let index_block_hash = StacksBlockHeader::make_index_block_hash(consensus_hash, block_hash);
-        if staging_status {
+        if staging_status == Some(false) {
             // not committed yet
             test_debug!(
                 "Block {}/{} ({}) is staging",
@@ -692,7 +720,34 @@ impl StacksChainState {
         }
 
         // only accepted if we stored it
-        StacksChainState::has_block_indexed(blocks_dir, &index_block_hash)
+        if StacksChainState::has_block_indexed(blocks_dir, &index_block_hash)? {
+            return Ok(true);
+        }
+
+        // ...or if we stored it and then pruned it
+        Ok(staging_status == Some(true))
+    }
+
+    /// Did we process a block, but then prune its body?
+    /// Processed blocks always have a (possibly empty) file in the chunk store until they are
+    /// pruned.
+    pub fn is_block_pruned(
+        blocks_db: &DBConn,
+        blocks_dir: &String,
+        consensus_hash: &ConsensusHash,
+        block_hash: &BlockHeaderHash,
+    ) -> Result<bool, Error> {
+        match StacksChainState::get_staging_block_status(blocks_db, consensus_hash, block_hash)? {
+            Some(true) => {
+                let index_block_hash =
+                    StacksBlockHeader::make_index_block_hash(consensus_hash, block_hash);
+                Ok(!StacksChainState::has_block_indexed(
+                    blocks_dir,
+                    &index_block_hash,
+                )?)
+            }
+            _ => Ok(false),
+        }
     }
 
     /// Store a block to the chunk store, named by its hash
@@ -758,6 +813,159 @@ impl StacksChainState {
         StacksChainState::free_block(blocks_path, consensus_hash, &block_header.block_hash())
     }
 
+    /// Get the burnchain height below which a pruned node discards block bodies, given the
+    /// height of the burnchain tip.  Blocks chosen in the last `reward_cycles` full reward
+    /// cycles, as well as in the current reward cycle, are kept.
+    /// Returns None if nothing is old enough to be pruned yet.
+    pub fn get_prune_horizon(
+        burnchain: &Burnchain,
+        burn_tip_height: u64,
+        reward_cycles: u64,
+    ) -> Option<u64> {
+        let cur_reward_cycle = burnchain.block_height_to_reward_cycle(burn_tip_height)?;
+        if cur_reward_cycle <= reward_cycles {
+            return None;
+        }
+        Some(burnchain.reward_cycle_to_block_height(cur_reward_cycle - reward_cycles))
+    }
+
+    /// Get the burnchain height below which a peer that advertises itself as pruned may have
+    /// discarded block bodies, given the height of the burnchain tip.
+    /// Returns None if no pruned peer can have discarded anything yet.
+    pub fn get_pruned_peer_horizon(burnchain: &Burnchain, burn_tip_height: u64) -> Option<u64> {
+        StacksChainState::get_prune_horizon(burnchain, burn_tip_height, MIN_PRUNE_REWARD_CYCLES)
+    }
+
+    /// Delete the bodies of all processed, accepted anchored blocks whose sortitions happened at
+    /// burnchain heights in [start_burn_height, end_burn_height), as well as the data of the
+    /// processed microblocks they produced.  The blocks' headers, the microblocks' metadata,
+    /// and all of their effects on the chainstate are retained, so new blocks can still be
+    /// validated against them.
+    /// Returns the number of anchored blocks pruned.
+    pub fn prune_blocks(
+        &mut self,
+        start_burn_height: u64,
+        end_burn_height: u64,
+    ) -> Result<u64, Error> {
+        let sql = "SELECT staging_blocks.consensus_hash,staging_blocks.anchored_block_hash,staging_blocks.index_block_hash \
+                   FROM staging_blocks JOIN block_headers ON staging_blocks.index_block_hash = block_headers.index_block_hash \
+                   WHERE staging_blocks.processed = 1 AND staging_blocks.orphaned = 0 \
+                   AND block_headers.burn_header_height >= ?1 AND block_headers.burn_header_height < ?2";
+        let args: &[&dyn ToSql] = &[
+            &u64_to_sql(start_burn_height)?,
+            &u64_to_sql(end_burn_height)?,
+        ];
+
+        let candidates: Vec<(ConsensusHash, BlockHeaderHash, StacksBlockId)> = {
+            let mut stmt = self
+                .db()
+                .prepare(sql)
+                .map_err(|e| Error::DBError(db_error::SqliteError(e)))?;
+            let rows = stmt
+                .query_and_then(args, |row| {
+                    let consensus_hash = ConsensusHash::from_column(row, "consensus_hash")?;
+                    let block_hash = BlockHeaderHash::from_column(row, "anchored_block_hash")?;
+                    let index_block_hash = StacksBlockId::from_column(row, "index_block_hash")?;
+                    Ok((consensus_hash, block_hash, index_block_hash))
+                })
+                .map_err(|e| Error::DBError(db_error::SqliteError(e)))?;
+            rows.collect::<Result<Vec<_>, db_error>>()
+                .map_err(Error::DBError)?
+        };
+
+        let blocks_path = self.blocks_path.clone();
+        let mut num_pruned = 0;
+        let tx = self.db_tx_begin()?;
+        for (consensus_hash, block_hash, index_block_hash) in candidates.into_iter() {
+            // microblocks produced by this block
+            let microblocks_sql = "DELETE FROM staging_microblocks_data WHERE block_hash IN \
+                                   (SELECT microblock_hash FROM staging_microblocks WHERE index_block_hash = ?1 AND processed = 1)";
+            tx.execute(microblocks_sql, &[&index_block_hash])
+                .map_err(|e| Error::DBError(db_error::SqliteError(e)))?;
+
+            let block_path =
+                StacksChainState::get_index_block_path(&blocks_path, &index_block_hash)?;
+            match fs::remove_file(&block_path) {
+                Ok(_) => {
+                    debug!(
+                        "Pruned block {}/{} ({})",
+                        &consensus_hash, &block_hash, &index_block_hash
+                    );
+                    num_pruned += 1;
+                }
+                Err(e) => {
+                    if e.kind() != io::ErrorKind::NotFound {
+                        return Err(Error::DBError(db_error::IOError(e)));
+                    }
+                    // already pruned
+                }
+            }
+        }
+        tx.commit()
+            .map_err(|e| Error::DBError(db_error::SqliteError(e)))?;
+
+        Ok(num_pruned)
+    }
+
+    /// If this chainstate is pruned, discard the bodies of blocks that have become old enough
+    /// given the new burnchain tip height.
+    /// Returns the number of anchored blocks pruned.
+    pub fn prune_old_blocks(
+        &mut self,
+        burnchain: &Burnchain,
+        burn_tip_height: u64,
+    ) -> Result<u64, Error> {
+        let reward_cycles = match self.prune_reward_cycles {
+            Some(n) => n,
+            None => return Ok(0),
+        };
+        let horizon =
+            match StacksChainState::get_prune_horizon(burnchain, burn_tip_height, reward_cycles) {
+                Some(h) => h,
+                None => return Ok(0),
+            };
+        if horizon <= self.pruned_burn_height {
+            return Ok(0);
+        }
+
+        let num_pruned = self.prune_blocks(self.pruned_burn_height, horizon)?;
+        info!(
+            "Pruned {} block(s) chosen below burnchain height {}",
+            num_pruned, horizon
+        );
+
+        // remember how far we got, so a restarted node neither rescans nor advertises blocks
+        // it no longer has
+        let tx = self.db_tx_begin()?;
+        StacksChainState::set_pruned_burn_height(&tx, horizon)?;
+        tx.commit()
+            .map_err(|e| Error::DBError(db_error::SqliteError(e)))?;
+
+        self.pruned_burn_height = horizon;
+        Ok(num_pruned)
+    }
+
+    /// Get the burnchain height below which this chainstate's block bodies have been pruned.
+    /// Returns 0 if nothing has been pruned.
+    pub fn get_pruned_burn_height(conn: &DBConn) -> Result<u64, Error> {
+        let sql = "SELECT IFNULL(MAX(pruned_burn_height), 0) FROM pruned_blocks_state";
+        let height = query_int(conn, sql, NO_PARAMS).map_err(Error::DBError)?;
+        Ok(height as u64)
+    }
+
+    /// Record the burnchain height below which this chainstate's block bodies have been pruned
+    fn set_pruned_burn_height(tx: &DBTx, pruned_burn_height: u64) -> Result<(), Error> {
+        let args: &[&dyn ToSql] = &[&u64_to_sql(pruned_burn_height)?];
+        tx.execute("DELETE FROM pruned_blocks_state", NO_PARAMS)
+            .map_err(|e| Error::DBError(db_error::SqliteError(e)))?;
+        tx.execute(
+            "INSERT INTO pruned_blocks_state (pruned_burn_height) VALUES (?1)",
+            args,
+        )
+        .map_err(|e| Error::DBError(db_error::SqliteError(e)))?;
+        Ok(())
+    }
+
     /// Get a list of all anchored blocks' hashes, and their burnchain headers
     pub fn list_blocks(
         blocks_conn: &DBConn,
@@ -1813,6 +2021,20 @@ impl StacksChainState {
         Ok(res)
     }
 
+    /// Was the parent of the given block pruned?
+    fn has_pruned_parent(&self, child_index_block_hash: &StacksBlockId) -> Result<bool, Error> {
+        match StacksChainState::get_parent_block_header_hashes(&self.db(), child_index_block_hash)?
+        {
+            Some((parent_consensus_hash, parent_block_hash)) => StacksChainState::is_block_pruned(
+                &self.db(),
+                &self.blocks_path,
+                &parent_consensus_hash,
+                &parent_block_hash,
+            ),
+            None => Ok(false),
+        }
+    }
+
     /// Generate a blocks inventory message, given the output of
     /// SortitionDB::get_stacks_header_hashes().  Note that header_hashes must be less than or equal to
     /// pox_constants.reward_cycle_length, in order to generate a valid BlocksInvData payload.
@@ -1873,8 +2095,11 @@ impl StacksChainState {
                     }
 
                     // check for microblocks that are confirmed by this block, and are already
-                    // processed.
-                    if !orphaned && self.has_processed_microblocks(&index_block_hash)? {
+                    // processed (and not pruned along with the block that produced them).
+                    if !orphaned
+                        && self.has_processed_microblocks(&index_block_hash)?
+                        && !self.has_pruned_parent(&index_block_hash)?
+                    {
                         // There exists a confirmed, processed microblock that is the parent of
                         // this block.  This can only be the case if we processed a microblock
                         // stream that connects the parent to this child.
@@ -2487,6 +2712,18 @@ impl StacksChainState {
         query_row(blocks_conn, sql, &args).map_err(Error::DBError)
     }
 
+    /// Load up the metadata on every microblock stored for the given anchored block, across all of
+    /// the (possibly forked) streams it produced, including orphaned microblocks.  The microblock
+    /// data itself is not loaded.  Microblocks are ordered by sequence number.
+    pub fn get_staging_microblocks_info(
+        blocks_conn: &DBConn,
+        parent_index_block_hash: &StacksBlockId,
+    ) -> Result<Vec<StagingMicroblock>, Error> {
+        let sql = "SELECT * FROM staging_microblocks WHERE index_block_hash = ?1 ORDER BY sequence ASC, microblock_hash ASC";
+        let args = [parent_index_block_hash as &dyn ToSql];
+        query_rows::<StagingMicroblock, _>(blocks_conn, sql, &args).map_err(Error::DBError)
+    }
+
     /// Load up the metadata on a microblock stream (but don't get the data itself)
     /// DO NOT USE IN PRODUCTION -- doesn't work for microblock forks.
     #[cfg(test)]
@@ -2960,20 +3197,92 @@ impl StacksChainState {
         return Some((end, None));
     }
 
-    /// Validate an anchored block against the burn chain state.
-    /// Returns Some(commit burn, total burn) if valid
+    /// Check that a sequence of microblock headers, pushed to us ahead of their microblocks, could
+    /// belong to the microblock stream produced by the given anchored block.
+    /// * the headers must be a non-empty contiguous sequence, in order by sequence number
+    /// * if the first header has sequence number 0, then it must build off of the anchored block
+    /// * each header must be signed by the anchored block's microblock key
+    /// Returns NoSuchBlockError if we don't have the anchored block.
+    pub fn validate_microblock_headers(
+        &self,
+        parent_consensus_hash: &ConsensusHash,
+        parent_anchored_block_hash: &BlockHeaderHash,
+        headers: &[StacksMicroblockHeader],
+    ) -> Result<bool, Error> {
+        let pubkey_hash = match StacksChainState::load_block_pubkey_hash(
+            &self.db(),
+            &self.blocks_path,
+            parent_consensus_hash,
+            parent_anchored_block_hash,
+        ) {
+            Ok(Some(pubkey_hash)) => pubkey_hash,
+            Ok(None) | Err(Error::DBError(db_error::NotFoundError)) => {
+                return Err(Error::NoSuchBlockError);
+            }
+            Err(e) => {
+                return Err(e);
+            }
+        };
+
+        if headers.len() == 0 {
+            return Ok(false);
+        }
+
+        if headers[0].sequence == 0 && headers[0].prev_block != *parent_anchored_block_hash {
+            debug!(
+                "Microblock header {} does not build off of {}/{}",
+                &headers[0].block_hash(),
+                parent_consensus_hash,
+                parent_anchored_block_hash
+            );
+            return Ok(false);
+        }
+
+        for i in 0..headers.len() {
+            if let Err(e) = headers[i].verify(&pubkey_hash) {
+                warn!(
+                    "Microblock header {} not signed by {}: {:?}",
+                    &headers[i].block_hash(),
+                    &pubkey_hash,
+                    &e
+                );
+                return Ok(false);
+            }
+
+            if i > 0 {
+                let prev = &headers[i - 1];
+                if Some(headers[i].sequence) != prev.sequence.checked_add(1)
+                    || headers[i].prev_block != prev.block_hash()
+                {
+                    debug!(
+                        "Discontiguous microblock headers: {} ({}) does not build off of {} ({})",
+                        &headers[i].block_hash(),
+                        headers[i].sequence,
+                        &prev.block_hash(),
+                        prev.sequence
+                    );
+                    return Ok(false);
+                }
+            }
+        }
+
+        Ok(true)
+    }
+
+    /// Validate an anchored block header against the burn chain state, without looking at the
+    /// block's transactions.  This is what a peer can check before it has downloaded the block
+    /// body.
+    /// Returns Some(winning block commit, penultimate sortition snapshot) if valid
     /// Returns None if not valid
     /// * consensus_hash is the PoX history hash of the burnchain block whose sortition
     /// (ostensibly) selected this block for inclusion.
-    pub fn validate_anchored_block_burnchain(
+    pub fn validate_anchored_block_header_burnchain(
         db_handle: &SortitionHandleConn,
         consensus_hash: &ConsensusHash,
-        block: &StacksBlock,
-        mainnet: bool,
-        chain_id: u32,
-    ) -> Result<Option<(u64, u64)>, Error> {
+        header: &StacksBlockHeader,
+    ) -> Result<Option<(LeaderBlockCommitOp, BlockSnapshot)>, Error> {
         // sortition-winning block commit for this block?
-        let block_hash = block.block_hash();
+        let block_hash = header.block_hash();
         let (block_commit, stacks_chain_tip) = match db_handle
             .get_block_snapshot_of_parent_stacks_block(consensus_hash, &block_hash)
         {
@@ -3018,7 +3327,7 @@ impl StacksChainState {
             .expect("FATAL: have block commit but no leader key");
 
         // attaches to burn chain
-        match block.header.validate_burnchain(
+        match header.validate_burnchain(
             &burn_chain_tip,
             &penultimate_sortition_snapshot,
             &leader_key,
@@ -3036,6 +3345,34 @@ impl StacksChainState {
             }
         };
 
+        Ok(Some((block_commit, penultimate_sortition_snapshot)))
+    }
+
+    /// Validate an anchored block against the burn chain state.
+    /// Returns Some(commit burn, total burn) if valid
+    /// Returns None if not valid
+    /// * consensus_hash is the PoX history hash of the burnchain block whose sortition
+    /// (ostensibly) selected this block for inclusion.
+    pub fn validate_anchored_block_burnchain(
+        db_handle: &SortitionHandleConn,
+        consensus_hash: &ConsensusHash,
+        block: &StacksBlock,
+        mainnet: bool,
+        chain_id: u32,
+    ) -> Result<Option<(u64, u64)>, Error> {
+        let block_hash = block.block_hash();
+        let (block_commit, penultimate_sortition_snapshot) =
+            match StacksChainState::validate_anchored_block_header_burnchain(
+                db_handle,
+                consensus_hash,
+                &block.header,
+            )? {
+                Some(x) => x,
+                None => {
+                    return Ok(None);
+                }
+            };
+
         // static checks on transactions all pass
         let valid = block.validate_transactions_static(mainnet, chain_id);
         if !valid {
@@ -4442,6 +4779,12 @@ impl StacksChainState {
         )
         .expect("FATAL: failed to advance chain tip");
 
+        StacksChainState::insert_stacks_block_tx_count(
+            &mut chainstate_tx.tx,
+            &new_tip.index_block_hash(),
+            block.txs.len() as u64,
+        )?;
+
         chainstate_tx.log_transactions_processed(&new_tip.index_block_hash(), &tx_receipts);
 
         let epoch_receipt = StacksEpochReceipt {
@@ -4986,6 +5329,28 @@ impl StacksChainState {
         query_row(&self.db(), sql, args).map_err(Error::DBError)
     }
 
+    /// Get the header of the block at the given height on the canonical Stacks fork (i.e. the
+    /// fork that ends at get_stacks_chain_tip()).
+    /// Returns None if there is no chain tip yet, or if the chain tip is lower than `height`.
+    pub fn get_canonical_block_header_at_height(
+        &self,
+        sortdb: &SortitionDB,
+        height: u64,
+    ) -> Result<Option<StacksHeaderInfo>, Error> {
+        let tip = match self.get_stacks_chain_tip(sortdb)? {
+            Some(tip) => tip,
+            None => {
+                return Ok(None);
+            }
+        };
+        if tip.height < height {
+            return Ok(None);
+        }
+        let tip_index_hash =
+            StacksBlockHeader::make_index_block_hash(&tip.consensus_hash, &tip.anchored_block_hash);
+        StacksChainState::get_index_tip_ancestor_conn(&self.index_conn()?, &tip_index_hash, height)
+    }
+
     /// Get the height of a staging block
     pub fn get_stacks_block_height(
         &self,
@@ -6724,6 +7089,73 @@ pub mod test {
         );
     }
 
+    #[test]
+    fn stacks_db_validate_microblock_headers() {
+        let mut chainstate =
+            instantiate_chainstate(false, 0x80000000, "stacks_db_validate_microblock_headers");
+        let privk = StacksPrivateKey::from_hex(
+            "eb05c83546fdd2c79f10f5ad5434a90dd28f7e3acb7c092157aa1bc3656b012c01",
+        )
+        .unwrap();
+        let block = make_empty_coinbase_block(&privk);
+        let microblocks = make_sample_microblock_stream(&privk, &block.block_hash());
+        let headers: Vec<StacksMicroblockHeader> = microblocks
+            .iter()
+            .map(|mblock| mblock.header.clone())
+            .collect();
+
+        // don't have the anchored block yet
+        match chainstate.validate_microblock_headers(
+            &ConsensusHash([2u8; 20]),
+            &block.block_hash(),
+            &headers,
+        ) {
+            Err(chainstate_error::NoSuchBlockError) => {}
+            x => panic!("Expected NoSuchBlockError, got {:?}", &x),
+        }
+
+        store_staging_block(
+            &mut chainstate,
+            &ConsensusHash([2u8; 20]),
+            &block,
+            &ConsensusHash([1u8; 20]),
+            1,
+            2,
+        );
+
+        // whole stream, or any contiguous part of it
+        assert!(chainstate
+            .validate_microblock_headers(&ConsensusHash([2u8; 20]), &block.block_hash(), &headers)
+            .unwrap());
+        assert!(chainstate
+            .validate_microblock_headers(
+                &ConsensusHash([2u8; 20]),
+                &block.block_hash(),
+                &headers[1..]
+            )
+            .unwrap());
+
+        // no headers
+        assert!(!chainstate
+            .validate_microblock_headers(&ConsensusHash([2u8; 20]), &block.block_hash(), &[])
+            .unwrap());
+
+        // gap in the stream
+        let mut gapped = headers.clone();
+        gapped.remove(1);
+        assert!(!chainstate
+            .validate_microblock_headers(&ConsensusHash([2u8; 20]), &block.block_hash(), &gapped)
+            .unwrap());
+
+        // signed by someone else
+        let other_privk = StacksPrivateKey::new();
+        let mut bad_sig = headers.clone();
+        bad_sig[0].sign(&other_privk).unwrap();
+        assert!(!chainstate
+            .validate_microblock_headers(&ConsensusHash([2u8; 20]), &block.block_hash(), &bad_sig)
+            .unwrap());
+    }
+
     #[test]
     fn stacks_db_validate_parent_microblock_stream() {
         let privk = StacksPrivateKey::from_hex(
@@ -8842,6 +9274,208 @@ pub mod test {
         }
     }
 
+    #[test]
+    fn stacks_db_prune_blocks() {
+        let peer_config = TestPeerConfig::new("stacks_db_prune_blocks", 21315, 21316);
+        let burnchain = peer_config.burnchain.clone();
+        let mut peer = TestPeer::new(peer_config);
+
+        let chainstate_path = peer.chainstate_path.clone();
+
+        let num_blocks = 10;
+        let mut mined = vec![];
+        for tenure_id in 0..num_blocks {
+            let tip =
+                SortitionDB::get_canonical_burn_chain_tip(&peer.sortdb.as_ref().unwrap().conn())
+                    .unwrap();
+
+            let (burn_ops, stacks_block, microblocks) = peer.make_tenure(
+                |ref mut miner,
+                 ref mut sortdb,
+                 ref mut chainstate,
+                 vrf_proof,
+                 ref parent_opt,
+                 ref parent_microblock_header_opt| {
+                    let parent_tip = match parent_opt {
+                        None => StacksChainState::get_genesis_header_info(chainstate.db()).unwrap(),
+                        Some(block) => {
+                            let ic = sortdb.index_conn();
+                            let snapshot =
+                                SortitionDB::get_block_snapshot_for_winning_stacks_block(
+                                    &ic,
+                                    &tip.sortition_id,
+                                    &block.block_hash(),
+                                )
+                                .unwrap()
+                                .unwrap(); // succeeds because we don't fork
+                            StacksChainState::get_anchored_block_header_info(
+                                chainstate.db(),
+                                &snapshot.consensus_hash,
+                                &snapshot.winning_stacks_block_hash,
+                            )
+                            .unwrap()
+                            .unwrap()
+                        }
+                    };
+
+                    let mempool = MemPoolDB::open(false, 0x80000000, &chainstate_path).unwrap();
+                    let coinbase_tx = make_coinbase(miner, tenure_id);
+
+                    let anchored_block = StacksBlockBuilder::build_anchored_block(
+                        chainstate,
+                        &sortdb.index_conn(),
+                        &mempool,
+                        &parent_tip,
+                        tip.total_burn,
+                        vrf_proof,
+                        Hash160([tenure_id as u8; 20]),
+                        &coinbase_tx,
+                        ExecutionCost::max_value(),
+                    )
+                    .unwrap();
+                    (anchored_block.0, vec![])
+                },
+            );
+
+            let (burn_height, _, consensus_hash) = peer.next_burnchain_block(burn_ops.clone());
+            peer.process_stacks_epoch_at_tip(&stacks_block, &microblocks);
+
+            mined.push((burn_height, consensus_hash, stacks_block.block_hash()));
+        }
+
+        // prune the first half of the chain
+        let prune_height = mined[num_blocks / 2].0;
+        let num_pruned = peer.chainstate().prune_blocks(0, prune_height).unwrap();
+        assert_eq!(num_pruned, (num_blocks / 2) as u64);
+
+        // pruning again does nothing
+        let num_pruned = peer.chainstate().prune_blocks(0, prune_height).unwrap();
+        assert_eq!(num_pruned, 0);
+
+        let header_hashes: Vec<(ConsensusHash, Option<BlockHeaderHash>)> = mined
+            .iter()
+            .map(|(_, ch, bhh)| (ch.clone(), Some(bhh.clone())))
+            .collect();
+        let inv = peer
+            .chainstate()
+            .get_blocks_inventory(&header_hashes)
+            .unwrap();
+
+        let blocks_path = peer.chainstate().blocks_path.clone();
+        for (i, (burn_height, consensus_hash, block_hash)) in mined.iter().enumerate() {
+            let pruned = *burn_height < prune_height;
+            let index_block_hash =
+                StacksBlockHeader::make_index_block_hash(consensus_hash, block_hash);
+
+            // pruned blocks still count as processed...
+            assert!(StacksChainState::has_stored_block(
+                &peer.chainstate().db(),
+                &blocks_path,
+                consensus_hash,
+                block_hash
+            )
+            .unwrap());
+
+            // ...but their bodies are gone
+            assert_eq!(
+                StacksChainState::is_block_pruned(
+                    &peer.chainstate().db(),
+                    &blocks_path,
+                    consensus_hash,
+                    block_hash
+                )
+                .unwrap(),
+                pruned
+            );
+            assert_eq!(
+                StacksChainState::has_block_indexed(&blocks_path, &index_block_hash).unwrap(),
+                !pruned
+            );
+            assert_eq!(
+                StacksChainState::load_block(&blocks_path, consensus_hash, block_hash).is_ok(),
+                !pruned
+            );
+
+            // ...and they are not advertised
+            assert_eq!(inv.has_ith_block(i as u16), !pruned);
+
+            // ...but their headers remain
+            assert!(StacksChainState::get_anchored_block_header_info(
+                &peer.chainstate().db(),
+                consensus_hash,
+                block_hash
+            )
+            .unwrap()
+            .is_some());
+
+            // ...as do their transaction counts (coinbase only)
+            assert_eq!(
+                StacksChainState::get_stacks_block_tx_count(
+                    &peer.chainstate().db(),
+                    &index_block_hash
+                )
+                .unwrap(),
+                Some(1)
+            );
+        }
+
+        // nothing is pruned until we're past the given number of reward cycles
+        let tip_height = mined[num_blocks - 1].0;
+        let cur_reward_cycle = burnchain.block_height_to_reward_cycle(tip_height).unwrap();
+        assert_eq!(
+            StacksChainState::get_prune_horizon(&burnchain, tip_height, cur_reward_cycle),
+            None
+        );
+        assert_eq!(
+            StacksChainState::get_prune_horizon(&burnchain, tip_height, cur_reward_cycle + 1),
+            None
+        );
+        if cur_reward_cycle > 0 {
+            assert_eq!(
+                StacksChainState::get_prune_horizon(&burnchain, tip_height, cur_reward_cycle - 1),
+                Some(burnchain.reward_cycle_to_block_height(1))
+            );
+        }
+
+        // an unpruned node never prunes
+        assert_eq!(
+            peer.chainstate()
+                .prune_old_blocks(&burnchain, tip_height)
+                .unwrap(),
+            0
+        );
+
+        // a pruned node that keeps nothing but the current reward cycle prunes everything before it
+        peer.chainstate().prune_reward_cycles = Some(0);
+        let horizon = StacksChainState::get_prune_horizon(&burnchain, tip_height, 0).unwrap_or(0);
+        let expected = mined
+            .iter()
+            .filter(|(burn_height, _, _)| *burn_height >= prune_height && *burn_height < horizon)
+            .count();
+        assert_eq!(
+            peer.chainstate()
+                .prune_old_blocks(&burnchain, tip_height)
+                .unwrap(),
+            expected as u64
+        );
+        assert_eq!(peer.chainstate().pruned_burn_height, horizon);
+
+        // the pruned height survives a restart
+        assert_eq!(
+            StacksChainState::get_pruned_burn_height(peer.chainstate().db()).unwrap(),
+            horizon
+        );
+        let (reopened, _) =
+            StacksChainState::open(false, peer.config.network_id, &peer.chainstate_path).unwrap();
+        assert_eq!(reopened.pruned_burn_height, horizon);
+
+        // peers assume that a pruned node keeps at least MIN_PRUNE_REWARD_CYCLES reward cycles
+        assert_eq!(
+            StacksChainState::get_pruned_peer_horizon(&burnchain, tip_height),
+            StacksChainState::get_prune_horizon(&burnchain, tip_height, MIN_PRUNE_REWARD_CYCLES)
+        );
+    }
+
     #[test]
     fn stacks_db_staging_microblocks_fork() {
         // multiple anchored blocks build off of a forked microblock stream
@@ -0,0 +1,886 @@
+// Copyright (C) 2013-2020 Blockstack PBC, a public benefit corporation
+// Copyright (C) 2020 Stacks Open Internet Foundation
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <http://www.gnu.org/licenses/>.
+
+//! Offline integrity checks of a node's chainstate.
+//!
+//! The check walks the canonical Stacks chain (as named by the sortition DB) from its tip down to
+//! genesis, and verifies that
+//! * each block's body is stored and hashes to the block's hash (unless it was pruned),
+//! * each block's header commits to the root hash the index MARF and the Clarity MARF actually
+//! have for that block,
+//! * each block is marked as processed and not orphaned, and was chosen by its sortition,
+//! * each processed, non-orphaned staging block (in any fork) has a header, and
+//! * the balance and nonce entries of every account that the chain's transactions touched can be
+//! resolved through the Clarity MARF and side store and decoded.
+//!
+//! A chainstate with problems can be repaired by rolling it back to the highest block below
+//! every problem: both MARFs and the headers DB forget every block above that height, in every
+//! fork, and the blocks' staging entries are marked as unprocessed so that the node processes
+//! them again when it next starts.  Blocks whose bodies are missing or corrupt are discarded
+//! instead, so that they get downloaded again.  The sortition DB is left alone.
+//!
+//! Both the check and the repair must be run while the node is stopped.
+
+use rusqlite::types::ToSql;
+use rusqlite::Connection;
+use rusqlite::OpenFlags;
+use rusqlite::OptionalExtension;
+use rusqlite::NO_PARAMS;
+
+use std::collections::HashMap;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use chainstate::burn::db::sortdb::SortitionDB;
+use chainstate::burn::ConsensusHash;
+use chainstate::stacks::db::StacksChainState;
+use chainstate::stacks::index::marf::{MarfConnection, MARF};
+use chainstate::stacks::index::storage::TrieFileStorage;
+use chainstate::stacks::index::MARFValue;
+use chainstate::stacks::Error;
+use chainstate::stacks::*;
+
+use util::db::{tx_begin_immediate, u64_to_sql, Error as db_error, FromColumn};
+use util::hash::hex_bytes;
+
+use vm::database::{ClarityDatabase, STXBalance, SqliteConnection};
+use vm::types::{PrincipalData, QualifiedContractIdentifier, StandardPrincipalData};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ChainstateProblemKind {
+    /// a processed block's body is not in the chunk store
+    MissingBlock,
+    /// a processed block's body can't be decoded, or has the wrong hash
+    CorruptBlock,
+    /// a header's index root does not match the index MARF
+    IndexRootMismatch,
+    /// a header's state root does not match the Clarity MARF
+    StateRootMismatch,
+    /// a block's staging entry is missing, or its processed/orphaned flags are wrong
+    StagingStatus,
+    /// a block was not chosen by the sortition its consensus hash names
+    Sortition,
+    /// an account's balance or nonce can't be loaded
+    AccountEntry,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ChainstateProblem {
+    /// height of the block at which the problem first shows up
+    pub block_height: u64,
+    pub index_block_hash: StacksBlockId,
+    pub kind: ChainstateProblemKind,
+    pub description: String,
+}
+
+impl fmt::Display for ChainstateProblem {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{:?} at height {} ({}): {}",
+            self.kind, self.block_height, &self.index_block_hash, &self.description
+        )
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ChainstateCheckReport {
+    /// canonical Stacks chain tip that was checked
+    pub tip: StacksBlockId,
+    pub tip_height: u64,
+    /// blocks above the tip that the sortition DB accepted, but that are not processed (yet)
+    pub pending_blocks: u64,
+    pub blocks_checked: u64,
+    pub accounts_checked: u64,
+    pub problems: Vec<ChainstateProblem>,
+}
+
+impl ChainstateCheckReport {
+    pub fn is_ok(&self) -> bool {
+        self.problems.len() == 0
+    }
+
+    /// Height of the highest block below every problem, which the chainstate can be rolled back
+    /// to.  Returns None if there are no problems, or if genesis itself is damaged.
+    pub fn last_good_height(&self) -> Option<u64> {
+        self.problems
+            .iter()
+            .map(|p| p.block_height)
+            .min()
+            .and_then(|height| height.checked_sub(1))
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ChainstateRepair {
+    /// block the chainstate was rolled back to
+    pub last_good_block: StacksBlockId,
+    pub last_good_height: u64,
+    /// blocks that will be processed again
+    pub reprocess_blocks: u64,
+    /// blocks whose bodies were discarded, and which must be downloaded again
+    pub refetch_blocks: u64,
+}
+
+/// Paths to the parts of a node's chainstate
+struct CheckPaths {
+    blocks_dir: String,
+    headers_db: PathBuf,
+    clarity_marf: PathBuf,
+}
+
+impl CheckPaths {
+    fn new(mainnet: bool, chain_id: u32, chainstate_path: &str) -> Result<Self, Error> {
+        let chain_path = StacksChainState::get_chain_path(mainnet, chain_id, chainstate_path);
+        let paths = CheckPaths {
+            blocks_dir: path_to_string(&chain_path.join("blocks"))?,
+            headers_db: chain_path.join("vm").join("index"),
+            clarity_marf: chain_path.join("vm").join("clarity").join("marf"),
+        };
+        for path in [&paths.headers_db, &paths.clarity_marf].iter() {
+            if fs::metadata(path).is_err() {
+                error!("No chainstate database at {}", path.display());
+                return Err(Error::InvalidChainstateDB);
+            }
+        }
+        Ok(paths)
+    }
+}
+
+fn path_to_string(path: &Path) -> Result<String, Error> {
+    path.to_str()
+        .map(|s| s.to_string())
+        .ok_or_else(|| Error::DBError(db_error::ParseError))
+}
+
+fn open_marf(path: &Path, readonly: bool) -> Result<MARF<StacksBlockId>, Error> {
+    let path = path_to_string(path)?;
+    let storage = if readonly {
+        TrieFileStorage::open_readonly(&path)?
+    } else {
+        TrieFileStorage::open(&path)?
+    };
+    Ok(MARF::from_storage(storage))
+}
+
+/// Get a staging block's (processed, orphaned) flags
+fn get_staging_flags(
+    conn: &Connection,
+    index_block_hash: &StacksBlockId,
+) -> Result<Option<(bool, bool)>, Error> {
+    conn.query_row(
+        "SELECT processed, orphaned FROM staging_blocks WHERE index_block_hash = ?1",
+        &[index_block_hash],
+        |row| {
+            let processed: i64 = row.get(0)?;
+            let orphaned: i64 = row.get(1)?;
+            Ok((processed != 0, orphaned != 0))
+        },
+    )
+    .optional()
+    .map_err(|e| Error::DBError(db_error::SqliteError(e)))
+}
+
+/// Check that a block's body is stored intact.  Returns the block if it could be loaded, and a
+/// description of what's wrong with it otherwise.
+fn check_block_file(
+    blocks_dir: &String,
+    consensus_hash: &ConsensusHash,
+    block_hash: &BlockHeaderHash,
+) -> Result<StacksBlock, (ChainstateProblemKind, String)> {
+    match StacksChainState::load_block(blocks_dir, consensus_hash, block_hash) {
+        Ok(Some(block)) => {
+            if block.block_hash() == *block_hash {
+                Ok(block)
+            } else {
+                Err((
+                    ChainstateProblemKind::CorruptBlock,
+                    format!("Stored block hashes to {}", &block.block_hash()),
+                ))
+            }
+        }
+        Ok(None) => Err((
+            ChainstateProblemKind::CorruptBlock,
+            "Stored block is empty".to_string(),
+        )),
+        Err(Error::DBError(db_error::NotFoundError)) => Err((
+            ChainstateProblemKind::MissingBlock,
+            "Block is not stored".to_string(),
+        )),
+        Err(e) => Err((
+            ChainstateProblemKind::CorruptBlock,
+            format!("Failed to load stored block: {}", &e),
+        )),
+    }
+}
+
+/// Principals whose account entries a transaction may have written
+fn touched_principals(tx: &StacksTransaction) -> Vec<PrincipalData> {
+    let mut principals = vec![tx.origin_address().to_account_principal()];
+    if let Some(sponsor) = tx.sponsor_address() {
+        principals.push(sponsor.to_account_principal());
+    }
+    match &tx.payload {
+        TransactionPayload::TokenTransfer(recipient, ..) => {
+            principals.push(recipient.clone());
+        }
+        TransactionPayload::ContractCall(call) => {
+            principals.push(PrincipalData::Contract(QualifiedContractIdentifier::new(
+                StandardPrincipalData::from(call.address.clone()),
+                call.contract_name.clone(),
+            )));
+        }
+        TransactionPayload::SmartContract(contract) => {
+            principals.push(PrincipalData::Contract(QualifiedContractIdentifier::new(
+                StandardPrincipalData::from(tx.origin_address()),
+                contract.name.clone(),
+            )));
+        }
+        TransactionPayload::PoisonMicroblock(..) | TransactionPayload::Coinbase(..) => {}
+    }
+    principals
+}
+
+/// Resolve a key through the Clarity MARF and side store at the given block.
+/// Returns None if the key is not set.
+fn get_clarity_value(
+    marf: &mut MARF<StacksBlockId>,
+    block: &StacksBlockId,
+    key: &str,
+) -> Result<Option<String>, String> {
+    let marf_value = match marf.get(block, key) {
+        Ok(Some(marf_value)) => marf_value,
+        Ok(None) => {
+            return Ok(None);
+        }
+        Err(e) => {
+            return Err(format!("Failed to look up {} in the MARF: {}", key, &e));
+        }
+    };
+
+    let value = SqliteConnection::get(marf.sqlite_conn(), &marf_value.to_hex())
+        .ok_or_else(|| format!("No side store entry for {} ({})", key, &marf_value))?;
+
+    if MARFValue::from_value(&value) != marf_value {
+        return Err(format!(
+            "Side store entry for {} does not hash to {}",
+            key, &marf_value
+        ));
+    }
+    Ok(Some(value))
+}
+
+/// Check that a principal's balance and nonce can be loaded at the given block
+fn check_account(
+    marf: &mut MARF<StacksBlockId>,
+    block: &StacksBlockId,
+    principal: &PrincipalData,
+) -> Result<(), String> {
+    let balance_key = ClarityDatabase::make_key_for_account_balance(principal);
+    if let Some(value) = get_clarity_value(marf, block, &balance_key)? {
+        match hex_bytes(&value) {
+            Ok(ref bytes) if bytes.len() == STXBalance::size_of => {}
+            _ => {
+                return Err(format!("Invalid balance for {}: '{}'", principal, &value));
+            }
+        }
+    }
+
+    let nonce_key = ClarityDatabase::make_key_for_account_nonce(principal);
+    if let Some(value) = get_clarity_value(marf, block, &nonce_key)? {
+        if serde_json::from_str::<u64>(&value).is_err() {
+            return Err(format!("Invalid nonce for {}: '{}'", principal, &value));
+        }
+    }
+    Ok(())
+}
+
+/// Find the highest processed block on the canonical Stacks chain, starting from the tip the
+/// sortition DB names.  After a repair, the sortition DB's tip and some of its ancestors may not
+/// have been processed again yet; these are walked through their staging entries, and if one of
+/// them is awaiting download, the highest processed block in any fork is used instead.
+/// Returns the block, and how many blocks above it are still pending.
+fn find_processed_tip(
+    conn: &Connection,
+    sortition_tip: &StacksBlockId,
+) -> Result<(StacksBlockId, u64), Error> {
+    let mut tip = sortition_tip.clone();
+    let mut pending_blocks = 0;
+    while !StacksChainState::has_stacks_block(conn, &tip)? {
+        let parent = conn
+            .query_row(
+                "SELECT parent_consensus_hash, parent_anchored_block_hash FROM staging_blocks WHERE index_block_hash = ?1",
+                &[&tip],
+                |row| {
+                    let consensus_hash: ConsensusHash = row.get(0)?;
+                    let block_hash: BlockHeaderHash = row.get(1)?;
+                    Ok((consensus_hash, block_hash))
+                },
+            )
+            .optional()
+            .map_err(|e| Error::DBError(db_error::SqliteError(e)))?;
+
+        pending_blocks += 1;
+        match parent {
+            Some((consensus_hash, block_hash)) => {
+                tip = StacksBlockHeader::make_index_block_hash(&consensus_hash, &block_hash);
+            }
+            None => {
+                warn!(
+                    "Canonical block {} is not stored; checking the highest processed block instead",
+                    &tip
+                );
+                let highest: Option<StacksBlockId> = conn
+                    .query_row(
+                        "SELECT index_block_hash FROM block_headers ORDER BY block_height DESC, index_block_hash LIMIT 1",
+                        NO_PARAMS,
+                        |row| row.get(0),
+                    )
+                    .optional()
+                    .map_err(|e| Error::DBError(db_error::SqliteError(e)))?;
+                tip = highest.ok_or(Error::NoSuchBlockError)?;
+                break;
+            }
+        }
+    }
+    Ok((tip, pending_blocks))
+}
+
+/// Check the chainstate's canonical Stacks chain, as named by the sortition DB.
+/// All databases are opened read-only.
+/// If `pruned` is true, processed blocks whose bodies are missing are assumed to have been
+/// pruned.
+pub fn check_chainstate(
+    mainnet: bool,
+    chain_id: u32,
+    chainstate_path: &str,
+    sortdb_path: &str,
+    pruned: bool,
+) -> Result<ChainstateCheckReport, Error> {
+    let paths = CheckPaths::new(mainnet, chain_id, chainstate_path)?;
+    let headers_conn =
+        Connection::open_with_flags(&paths.headers_db, OpenFlags::SQLITE_OPEN_READ_ONLY)
+            .map_err(|e| Error::DBError(db_error::SqliteError(e)))?;
+    let mut index_marf = open_marf(&paths.headers_db, true)?;
+    let mut clarity_marf = open_marf(&paths.clarity_marf, true)?;
+    let sortdb = SortitionDB::open(sortdb_path, false).map_err(Error::DBError)?;
+
+    let (tip_consensus_hash, tip_block_hash) =
+        SortitionDB::get_canonical_stacks_chain_tip_hash(sortdb.conn())?;
+    let (tip, pending_blocks) = find_processed_tip(
+        &headers_conn,
+        &StacksBlockHeader::make_index_block_hash(&tip_consensus_hash, &tip_block_hash),
+    )?;
+
+    let mut problems = vec![];
+    let mut blocks_checked = 0;
+    let mut tip_height = 0;
+
+    // each principal, and the height of the last block that touched it
+    let mut principals: HashMap<PrincipalData, (u64, StacksBlockId)> = HashMap::new();
+
+    let mut next = Some(tip.clone());
+    while let Some(index_block_hash) = next.take() {
+        let header_info = StacksChainState::get_stacks_block_header_info_by_index_block_hash(
+            &headers_conn,
+            &index_block_hash,
+        )?
+        .ok_or_else(|| {
+            error!("No header for canonical block {}", &index_block_hash);
+            Error::NoSuchBlockError
+        })?;
+
+        let height = header_info.block_height;
+        let consensus_hash = &header_info.consensus_hash;
+        let block_hash = header_info.anchored_header.block_hash();
+        if blocks_checked == 0 {
+            tip_height = height;
+        }
+        blocks_checked += 1;
+
+        let mut problem = |kind: ChainstateProblemKind, description: String| {
+            problems.push(ChainstateProblem {
+                block_height: height,
+                index_block_hash: index_block_hash.clone(),
+                kind,
+                description,
+            })
+        };
+
+        match index_marf.get_root_hash_at(&index_block_hash) {
+            Ok(root_hash) => {
+                if root_hash != header_info.index_root {
+                    problem(
+                        ChainstateProblemKind::IndexRootMismatch,
+                        format!(
+                            "Header has index root {}, but the index MARF has {}",
+                            &header_info.index_root, &root_hash
+                        ),
+                    );
+                }
+            }
+            Err(e) => problem(
+                ChainstateProblemKind::IndexRootMismatch,
+                format!("Failed to load the index MARF root: {}", &e),
+            ),
+        }
+
+        if height == 0 {
+            // the boot block has no body, staging entry, sortition, or state root commitment
+            break;
+        }
+
+        match clarity_marf.get_root_hash_at(&index_block_hash) {
+            Ok(root_hash) => {
+                if root_hash != header_info.anchored_header.state_index_root {
+                    problem(
+                        ChainstateProblemKind::StateRootMismatch,
+                        format!(
+                            "Header has state root {}, but the Clarity MARF has {}",
+                            &header_info.anchored_header.state_index_root, &root_hash
+                        ),
+                    );
+                }
+            }
+            Err(e) => problem(
+                ChainstateProblemKind::StateRootMismatch,
+                format!("Failed to load the Clarity MARF root: {}", &e),
+            ),
+        }
+
+        match get_staging_flags(&headers_conn, &index_block_hash)? {
+            Some((true, false)) => {}
+            Some((processed, orphaned)) => problem(
+                ChainstateProblemKind::StagingStatus,
+                format!(
+                    "Staging block has processed = {}, orphaned = {}",
+                    processed, orphaned
+                ),
+            ),
+            None => problem(
+                ChainstateProblemKind::StagingStatus,
+                "No staging block".to_string(),
+            ),
+        }
+
+        match SortitionDB::get_block_snapshot_consensus(sortdb.conn(), consensus_hash)? {
+            Some(sn) => {
+                if !sn.sortition || sn.winning_stacks_block_hash != block_hash {
+                    problem(
+                        ChainstateProblemKind::Sortition,
+                        format!(
+                            "Sortition {} chose block {}",
+                            consensus_hash, &sn.winning_stacks_block_hash
+                        ),
+                    );
+                }
+            }
+            None => problem(
+                ChainstateProblemKind::Sortition,
+                format!("No sortition {}", consensus_hash),
+            ),
+        }
+
+        let block_pruned = pruned
+            && StacksChainState::is_block_pruned(
+                &headers_conn,
+                &paths.blocks_dir,
+                consensus_hash,
+                &block_hash,
+            )?;
+        if !block_pruned {
+            match check_block_file(&paths.blocks_dir, consensus_hash, &block_hash) {
+                Ok(block) => {
+                    for tx in block.txs.iter() {
+                        for principal in touched_principals(tx).into_iter() {
+                            // walking down, so the first block seen is the last to touch it
+                            principals
+                                .entry(principal)
+                                .or_insert((height, index_block_hash.clone()));
+                        }
+                    }
+                }
+                Err((kind, description)) => problem(kind, description),
+            }
+        }
+
+        next = StacksChainState::get_parent_block_id(&headers_conn, &index_block_hash)?;
+    }
+
+    // every accepted block must have been recorded as such
+    {
+        let sql = "SELECT index_block_hash, height FROM staging_blocks WHERE processed = 1 AND orphaned = 0 \
+                   AND index_block_hash NOT IN (SELECT index_block_hash FROM block_headers)";
+        let mut stmt = headers_conn
+            .prepare(sql)
+            .map_err(|e| Error::DBError(db_error::SqliteError(e)))?;
+        let rows = stmt
+            .query_and_then(NO_PARAMS, |row| {
+                let index_block_hash = StacksBlockId::from_column(row, "index_block_hash")?;
+                let height: i64 = row.get_unwrap("height");
+                Ok((index_block_hash, height as u64))
+            })
+            .map_err(|e| Error::DBError(db_error::SqliteError(e)))?;
+        for row in rows {
+            let (index_block_hash, height) = row.map_err(Error::DBError)?;
+            problems.push(ChainstateProblem {
+                block_height: height,
+                index_block_hash,
+                kind: ChainstateProblemKind::StagingStatus,
+                description: "Staging block is processed, but has no header".to_string(),
+            });
+        }
+    }
+
+    let accounts_checked = principals.len() as u64;
+    for (principal, (height, index_block_hash)) in principals.into_iter() {
+        if let Err(description) = check_account(&mut clarity_marf, &tip, &principal) {
+            problems.push(ChainstateProblem {
+                block_height: height,
+                index_block_hash,
+                kind: ChainstateProblemKind::AccountEntry,
+                description,
+            });
+        }
+    }
+
+    problems.sort_by_key(|p| p.block_height);
+
+    info!(
+        "Checked {} blocks and {} accounts up to {} (height {}): {} problems",
+        blocks_checked,
+        accounts_checked,
+        &tip,
+        tip_height,
+        problems.len()
+    );
+
+    Ok(ChainstateCheckReport {
+        tip,
+        tip_height,
+        pending_blocks,
+        blocks_checked,
+        accounts_checked,
+        problems,
+    })
+}
+
+/// Repair the problems found by a check, by rolling the chainstate back to the highest block
+/// on the canonical chain below all of them.
+pub fn repair_chainstate(
+    mainnet: bool,
+    chain_id: u32,
+    chainstate_path: &str,
+    report: &ChainstateCheckReport,
+) -> Result<ChainstateRepair, Error> {
+    let paths = CheckPaths::new(mainnet, chain_id, chainstate_path)?;
+
+    let last_good_height = if report.is_ok() {
+        report.tip_height
+    } else {
+        report.last_good_height().ok_or_else(|| {
+            error!("Genesis is damaged; the chainstate must be re-synced from scratch");
+            Error::InvalidChainstateDB
+        })?
+    };
+
+    let mut headers_conn = Connection::open(&paths.headers_db)
+        .map_err(|e| Error::DBError(db_error::SqliteError(e)))?;
+
+    // find the last good block on the canonical chain
+    let mut last_good_block = report.tip.clone();
+    loop {
+        let header_info = StacksChainState::get_stacks_block_header_info_by_index_block_hash(
+            &headers_conn,
+            &last_good_block,
+        )?
+        .ok_or(Error::NoSuchBlockError)?;
+        if header_info.block_height <= last_good_height {
+            break;
+        }
+        last_good_block = StacksChainState::get_parent_block_id(&headers_conn, &last_good_block)?
+            .ok_or(Error::NoSuchBlockError)?;
+    }
+
+    if report.is_ok() {
+        return Ok(ChainstateRepair {
+            last_good_block,
+            last_good_height,
+            reprocess_blocks: 0,
+            refetch_blocks: 0,
+        });
+    }
+
+    // decide which blocks above the last good height can be processed again, and which must
+    // be downloaded again
+    let mut reprocess = vec![];
+    let mut refetch = vec![];
+    {
+        let sql = "SELECT consensus_hash, anchored_block_hash, index_block_hash, height FROM staging_blocks \
+                   WHERE processed = 1 AND orphaned = 0 AND height > ?1";
+        let mut stmt = headers_conn
+            .prepare(sql)
+            .map_err(|e| Error::DBError(db_error::SqliteError(e)))?;
+        let rows = stmt
+            .query_and_then(&[&u64_to_sql(last_good_height)?], |row| {
+                let consensus_hash = ConsensusHash::from_column(row, "consensus_hash")?;
+                let block_hash = BlockHeaderHash::from_column(row, "anchored_block_hash")?;
+                let height: i64 = row.get_unwrap("height");
+                Ok((consensus_hash, block_hash, height as u64))
+            })
+            .map_err(|e| Error::DBError(db_error::SqliteError(e)))?;
+        for row in rows {
+            let (consensus_hash, block_hash, height) = row.map_err(Error::DBError)?;
+            if check_block_file(&paths.blocks_dir, &consensus_hash, &block_hash).is_ok() {
+                reprocess.push((consensus_hash, block_hash, height));
+            } else {
+                refetch.push((consensus_hash, block_hash));
+            }
+        }
+    }
+
+    // headers without a processed staging block have nothing to re-process, so their bodies
+    // (if any) must be downloaded again as well
+    {
+        let sql = "SELECT consensus_hash, block_hash FROM block_headers WHERE block_height > ?1 \
+                   AND index_block_hash NOT IN (SELECT index_block_hash FROM staging_blocks WHERE processed = 1 AND orphaned = 0)";
+        let mut stmt = headers_conn
+            .prepare(sql)
+            .map_err(|e| Error::DBError(db_error::SqliteError(e)))?;
+        let rows = stmt
+            .query_and_then(&[&u64_to_sql(last_good_height)?], |row| {
+                let consensus_hash = ConsensusHash::from_column(row, "consensus_hash")?;
+                let block_hash = BlockHeaderHash::from_column(row, "block_hash")?;
+                Ok((consensus_hash, block_hash))
+            })
+            .map_err(|e| Error::DBError(db_error::SqliteError(e)))?;
+        for row in rows {
+            refetch.push(row.map_err(Error::DBError)?);
+        }
+    }
+
+    // the Clarity MARF goes first, and the headers DB last, so that an interrupted repair
+    // still shows up as a problem at the same height
+    {
+        let mut clarity_marf = open_marf(&paths.clarity_marf, false)?;
+        let removed = clarity_marf.rollback(&last_good_block)?;
+        for block in removed.iter() {
+            clarity_marf
+                .sqlite_conn()
+                .execute("DELETE FROM metadata_table WHERE blockhash = ?1", &[block])
+                .map_err(|e| Error::DBError(db_error::SqliteError(e)))?;
+        }
+    }
+    {
+        let mut index_marf = open_marf(&paths.headers_db, false)?;
+        index_marf.rollback(&last_good_block)?;
+    }
+
+    let height_arg = u64_to_sql(last_good_height)?;
+    let tx = tx_begin_immediate(&mut headers_conn).map_err(Error::DBError)?;
+    {
+        let mut sqls = vec![
+            // microblock streams that rolled-back blocks confirmed
+            "UPDATE staging_microblocks SET processed = 0 WHERE orphaned = 0 AND index_block_hash IN \
+             (SELECT index_block_hash FROM staging_blocks WHERE height >= ?1)",
+            "DELETE FROM user_supporters WHERE EXISTS (SELECT 1 FROM block_headers WHERE block_headers.block_height > ?1 \
+             AND block_headers.block_hash = user_supporters.block_hash AND block_headers.consensus_hash = user_supporters.consensus_hash)",
+            "DELETE FROM payments WHERE stacks_block_height > ?1",
+            "DELETE FROM block_tx_counts WHERE index_block_hash IN \
+             (SELECT index_block_hash FROM block_headers WHERE block_height > ?1)",
+            "DELETE FROM block_headers WHERE block_height > ?1",
+            // only the children of the last good height have a processed parent
+            "UPDATE staging_blocks SET attachable = 0 WHERE processed = 0 AND orphaned = 0 AND height > ?1 + 1",
+        ];
+        if cfg!(feature = "tx_log") {
+            sqls.insert(
+                0,
+                "DELETE FROM transactions WHERE index_block_hash IN \
+                 (SELECT index_block_hash FROM block_headers WHERE block_height > ?1)",
+            );
+        }
+        for sql in sqls.into_iter() {
+            tx.execute(sql, &[&height_arg])
+                .map_err(|e| Error::DBError(db_error::SqliteError(e)))?;
+        }
+
+        for (consensus_hash, block_hash, height) in reprocess.iter() {
+            let args: &[&dyn ToSql] = &[
+                &(*height == last_good_height + 1),
+                consensus_hash,
+                block_hash,
+            ];
+            tx.execute("UPDATE staging_blocks SET processed = 0, processed_time = 0, attachable = ?1 WHERE consensus_hash = ?2 AND anchored_block_hash = ?3", args)
+                .map_err(|e| Error::DBError(db_error::SqliteError(e)))?;
+        }
+
+        for (consensus_hash, block_hash) in refetch.iter() {
+            let args: &[&dyn ToSql] = &[consensus_hash, block_hash];
+            tx.execute(
+                "DELETE FROM staging_blocks WHERE consensus_hash = ?1 AND anchored_block_hash = ?2",
+                args,
+            )
+            .map_err(|e| Error::DBError(db_error::SqliteError(e)))?;
+            tx.execute("DELETE FROM staging_user_burn_support WHERE consensus_hash = ?1 AND anchored_block_hash = ?2", args)
+                .map_err(|e| Error::DBError(db_error::SqliteError(e)))?;
+        }
+    }
+    tx.commit()
+        .map_err(|e| Error::DBError(db_error::SqliteError(e)))?;
+
+    // a block that's still in the chunk store counts as stored, so it would never be downloaded
+    // again
+    for (consensus_hash, block_hash) in refetch.iter() {
+        let block_path =
+            StacksChainState::get_block_path(&paths.blocks_dir, consensus_hash, block_hash)?;
+        match fs::remove_file(&block_path) {
+            Ok(_) => {}
+            Err(ref e) if e.kind() == io::ErrorKind::NotFound => {}
+            Err(e) => {
+                return Err(Error::WriteError(e));
+            }
+        }
+    }
+
+    info!(
+        "Rolled back chainstate to {} (height {}): {} blocks will be processed again, {} blocks will be downloaded again",
+        &last_good_block,
+        last_good_height,
+        reprocess.len(),
+        refetch.len()
+    );
+
+    Ok(ChainstateRepair {
+        last_good_block,
+        last_good_height,
+        reprocess_blocks: reprocess.len() as u64,
+        refetch_blocks: refetch.len() as u64,
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use net::relay::Relayer;
+    use net::test::*;
+
+    #[test]
+    fn test_check_and_repair_chainstate() {
+        let peer_config = TestPeerConfig::new("test_check_and_repair_chainstate", 31040, 31041);
+        let mut peer = TestPeer::new(peer_config);
+
+        let mut blocks = vec![];
+        for _ in 0..5 {
+            let (burn_ops, stacks_block, microblocks) = peer.make_default_tenure();
+            let (_, _, consensus_hash) = peer.next_burnchain_block(burn_ops);
+            peer.process_stacks_epoch_at_tip(&stacks_block, &microblocks);
+            blocks.push((consensus_hash, stacks_block, microblocks));
+        }
+
+        let chain_id = peer.config.network_id;
+        let sortdb_path = peer.config.burnchain.get_db_path();
+        let chainstate_path = peer.chainstate_path.clone();
+        let blocks_dir = peer.chainstate().blocks_path.clone();
+
+        let report =
+            check_chainstate(false, chain_id, &chainstate_path, &sortdb_path, false).unwrap();
+        assert!(report.is_ok(), "{:?}", &report.problems);
+        assert_eq!(report.tip_height, 5);
+        assert_eq!(report.pending_blocks, 0);
+        assert_eq!(report.blocks_checked, 6);
+        assert!(report.accounts_checked > 0);
+        assert_eq!(report.last_good_height(), None);
+
+        // wrong chain
+        assert!(check_chainstate(true, chain_id, &chainstate_path, &sortdb_path, false).is_err());
+
+        // nothing to repair
+        let repair = repair_chainstate(false, chain_id, &chainstate_path, &report).unwrap();
+        assert_eq!(repair.last_good_height, 5);
+        assert_eq!(repair.last_good_block, report.tip);
+        assert_eq!(repair.reprocess_blocks, 0);
+        assert_eq!(repair.refetch_blocks, 0);
+
+        // damage the block at height 3
+        let (consensus_hash, block, microblocks) = blocks[2].clone();
+        let block_path =
+            StacksChainState::get_block_path(&blocks_dir, &consensus_hash, &block.block_hash())
+                .unwrap();
+        {
+            let f = fs::OpenOptions::new()
+                .write(true)
+                .open(&block_path)
+                .unwrap();
+            let len = f.metadata().unwrap().len();
+            f.set_len(len / 2).unwrap();
+        }
+
+        let report =
+            check_chainstate(false, chain_id, &chainstate_path, &sortdb_path, false).unwrap();
+        assert_eq!(report.tip_height, 5);
+        assert_eq!(report.problems.len(), 1);
+        assert_eq!(report.problems[0].kind, ChainstateProblemKind::CorruptBlock);
+        assert_eq!(report.problems[0].block_height, 3);
+        assert_eq!(report.last_good_height(), Some(2));
+
+        // roll back to height 2; the blocks at heights 4 and 5 are processed again, and the
+        // damaged block is downloaded again
+        let repair = repair_chainstate(false, chain_id, &chainstate_path, &report).unwrap();
+        assert_eq!(repair.last_good_height, 2);
+        assert_eq!(repair.reprocess_blocks, 2);
+        assert_eq!(repair.refetch_blocks, 1);
+        assert!(fs::metadata(&block_path).is_err());
+
+        let report =
+            check_chainstate(false, chain_id, &chainstate_path, &sortdb_path, false).unwrap();
+        assert!(report.is_ok(), "{:?}", &report.problems);
+        assert_eq!(report.tip_height, 2);
+        assert_eq!(report.pending_blocks, 3);
+
+        // once the damaged block arrives again, the chain is re-processed up to its old tip.
+        // Repairs are made while the node is stopped, so the peer's open databases are stale by
+        // now; carry on with fresh ones, as a restarted node would.
+        let mut sortdb = SortitionDB::open(&sortdb_path, true).unwrap();
+        let (mut chainstate, _) =
+            StacksChainState::open(false, chain_id, &chainstate_path).unwrap();
+        {
+            let ic = sortdb.index_conn();
+            Relayer::process_new_anchored_block(&ic, &mut chainstate, &consensus_hash, &block, 0)
+                .unwrap();
+        }
+        for mblock in microblocks.iter() {
+            chainstate
+                .preprocess_streamed_microblock(&consensus_hash, &block.block_hash(), mblock)
+                .unwrap();
+        }
+        loop {
+            let tip = SortitionDB::get_canonical_burn_chain_tip(sortdb.conn()).unwrap();
+            let sort_tx = sortdb.tx_handle_begin(&tip.sortition_id).unwrap();
+            if chainstate.process_blocks(sort_tx, 1).unwrap().is_empty() {
+                break;
+            }
+        }
+
+        let report =
+            check_chainstate(false, chain_id, &chainstate_path, &sortdb_path, false).unwrap();
+        assert!(report.is_ok(), "{:?}", &report.problems);
+        assert_eq!(report.tip_height, 5);
+        assert_eq!(report.pending_blocks, 0);
+    }
+}
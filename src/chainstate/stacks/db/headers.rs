@@ -196,6 +196,33 @@ impl StacksChainState {
         Ok(())
     }
 
+    /// Record the number of transactions in a processed anchored block
+    pub fn insert_stacks_block_tx_count(
+        tx: &mut StacksDBTx,
+        index_block_hash: &StacksBlockId,
+        tx_count: u64,
+    ) -> Result<(), Error> {
+        let args: &[&dyn ToSql] = &[index_block_hash, &(tx_count as i64)];
+        tx.execute(
+            "INSERT OR REPLACE INTO block_tx_counts (index_block_hash, tx_count) VALUES (?1, ?2)",
+            args,
+        )
+        .map_err(|e| Error::DBError(db_error::SqliteError(e)))?;
+        Ok(())
+    }
+
+    /// Get the number of transactions in a processed anchored block, if it was recorded
+    pub fn get_stacks_block_tx_count(
+        conn: &DBConn,
+        block: &StacksBlockId,
+    ) -> Result<Option<u64>, Error> {
+        let qry = "SELECT tx_count FROM block_tx_counts WHERE index_block_hash = ?";
+        conn.query_row(qry, &[block], |row| row.get::<_, i64>(0))
+            .optional()
+            .map(|count_opt| count_opt.map(|count| count as u64))
+            .map_err(|e| Error::from(db_error::from(e)))
+    }
+
     pub fn get_stacks_block_anchored_cost(
         conn: &DBConn,
         block: &StacksBlockId,
@@ -317,6 +344,26 @@ impl StacksChainState {
         Ok(rows.pop())
     }
 
+    /// Get up to `count` headers from the given block's fork, starting with the block itself and
+    /// walking back through its ancestors.  Headers are returned in descending order of height.
+    pub fn get_ancestor_block_headers(
+        conn: &Connection,
+        block_id: &StacksBlockId,
+        count: u32,
+    ) -> Result<Vec<StacksHeaderInfo>, Error> {
+        let sql = "WITH RECURSIVE ancestors(index_block_hash, depth) AS ( \
+                       SELECT ?1, 0 \
+                       UNION ALL \
+                       SELECT block_headers.parent_block_id, ancestors.depth + 1 \
+                       FROM block_headers JOIN ancestors ON block_headers.index_block_hash = ancestors.index_block_hash \
+                       WHERE ancestors.depth + 1 < ?2 \
+                   ) \
+                   SELECT block_headers.* FROM block_headers JOIN ancestors ON block_headers.index_block_hash = ancestors.index_block_hash \
+                   ORDER BY ancestors.depth ASC";
+        let args: &[&dyn ToSql] = &[block_id, &count];
+        query_rows::<StacksHeaderInfo, _>(conn, sql, args).map_err(Error::DBError)
+    }
+
     /// Is this block present and processed?
     pub fn has_stacks_block(conn: &Connection, block_id: &StacksBlockId) -> Result<bool, Error> {
         let sql = "SELECT 1 FROM block_headers WHERE index_block_hash = ?1 LIMIT 1";
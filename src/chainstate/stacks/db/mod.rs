@@ -16,8 +16,10 @@
 
 pub mod accounts;
 pub mod blocks;
+pub mod check;
 pub mod contracts;
 pub mod headers;
+pub mod snapshot;
 pub mod transactions;
 pub mod unconfirmed;
 
@@ -108,6 +110,10 @@ pub struct StacksChainState {
     pub root_path: String,
     pub block_limit: ExecutionCost,
     pub unconfirmed_state: Option<UnconfirmedState>,
+    /// If set, discard the bodies of blocks older than this many reward cycles
+    pub prune_reward_cycles: Option<u64>,
+    /// Blocks chosen below this burnchain height have already been pruned
+    pub pruned_burn_height: u64,
 }
 
 #[derive(Debug, Clone, PartialEq)]
@@ -606,6 +612,24 @@ const STACKS_CHAIN_STATE_SQL: &'static [&'static str] = &[
     "#,
 ];
 
+// Added after the original schema, so these are also created when opening an existing chainstate.
+const STACKS_CHAIN_STATE_SQL_ADDITIONS: &'static [&'static str] = &[
+    r#"
+    -- Burnchain height below which a pruned node has discarded block bodies
+    CREATE TABLE IF NOT EXISTS pruned_blocks_state(
+        pruned_burn_height INTEGER NOT NULL
+    );
+    "#,
+    r#"
+    -- Number of transactions in each processed anchored block, so it's known without loading the
+    -- block.  Blocks processed before this table existed have no row.
+    CREATE TABLE IF NOT EXISTS block_tx_counts(
+        index_block_hash TEXT PRIMARY KEY NOT NULL,
+        tx_count INTEGER NOT NULL
+    );
+    "#,
+];
+
 #[cfg(test)]
 pub const MINER_REWARD_MATURITY: u64 = 2; // small for testing purposes
 
@@ -712,6 +736,9 @@ impl StacksChainState {
             for cmd in STACKS_CHAIN_STATE_SQL {
                 tx.execute(cmd, NO_PARAMS)?;
             }
+            for cmd in STACKS_CHAIN_STATE_SQL_ADDITIONS {
+                tx.execute(cmd, NO_PARAMS)?;
+            }
 
             tx.execute(
                 "INSERT INTO db_config (version,mainnet,chain_id) VALUES (?1,?2,?3)",
@@ -772,6 +799,9 @@ impl StacksChainState {
                 return Err(Error::InvalidChainstateDB);
             }
 
+            for cmd in STACKS_CHAIN_STATE_SQL_ADDITIONS {
+                marf.sqlite_conn().execute(cmd, NO_PARAMS)?;
+            }
             Ok(marf)
         }
     }
@@ -1306,13 +1336,8 @@ impl StacksChainState {
         StacksChainState::open_and_exec(mainnet, chain_id, path_str, None, block_limit)
     }
 
-    pub fn open_and_exec(
-        mainnet: bool,
-        chain_id: u32,
-        path_str: &str,
-        boot_data: Option<&mut ChainStateBootData>,
-        block_limit: ExecutionCost,
-    ) -> Result<(StacksChainState, Vec<StacksTransactionReceipt>), Error> {
+    /// Get the directory that holds this chain's state, given the root path passed to open()
+    pub fn get_chain_path(mainnet: bool, chain_id: u32, path_str: &str) -> PathBuf {
         let mut path = PathBuf::from(path_str);
 
         let chain_id_str = if mainnet {
@@ -1322,6 +1347,17 @@ impl StacksChainState {
         };
 
         path.push(chain_id_str);
+        path
+    }
+
+    pub fn open_and_exec(
+        mainnet: bool,
+        chain_id: u32,
+        path_str: &str,
+        boot_data: Option<&mut ChainStateBootData>,
+        block_limit: ExecutionCost,
+    ) -> Result<(StacksChainState, Vec<StacksTransactionReceipt>), Error> {
+        let path = StacksChainState::get_chain_path(mainnet, chain_id, path_str);
         StacksChainState::mkdirs(&path)?;
 
         let mut blocks_path = path.clone();
@@ -1366,6 +1402,8 @@ impl StacksChainState {
         };
 
         let state_index = StacksChainState::open_db(mainnet, chain_id, &header_index_root)?;
+        let pruned_burn_height =
+            StacksChainState::get_pruned_burn_height(state_index.sqlite_conn())?;
 
         let vm_state = MarfedKV::open(
             &clarity_state_index_root,
@@ -1389,6 +1427,8 @@ impl StacksChainState {
             root_path: path_str.to_string(),
             block_limit: block_limit,
             unconfirmed_state: None,
+            prune_reward_cycles: None,
+            pruned_burn_height: pruned_burn_height,
         };
 
         let mut receipts = vec![];
@@ -0,0 +1,962 @@
+// Copyright (C) 2013-2020 Blockstack PBC, a public benefit corporation
+// Copyright (C) 2020 Stacks Open Internet Foundation
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <http://www.gnu.org/licenses/>.
+
+//! Export and import of chainstate snapshots.
+//!
+//! A snapshot is a directory that holds consistent copies of the sortition DB, the Stacks
+//! headers DB (with its index MARF), and the Clarity state MARF, along with a manifest that
+//! identifies the Stacks chain tip the snapshot was taken at.  A node can import a snapshot to
+//! skip replaying the chain from genesis.  Block and microblock bodies are not included, nor is
+//! the burnchain DB -- the importing node re-syncs the burnchain headers itself.
+//!
+//! Before a snapshot is installed, the MARF root hash recorded in the manifest is checked
+//! against the tip's block header (which the sortition DB commits to), and against the root
+//! hash actually stored in the Clarity MARF.
+//!
+//! A MARF whose tries are kept in an external blobs file is snapshotted along with that file.
+//! The database is copied first, so the file copy is a superset of what the copied database
+//! refers to.
+
+use rusqlite::types::ToSql;
+use rusqlite::Connection;
+use rusqlite::OpenFlags;
+
+use std::collections::HashSet;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use sha2::Sha512Trunc256;
+
+use burnchains::db::BurnchainDB;
+use chainstate::burn::db::sortdb::SortitionDB;
+use chainstate::burn::operations::BlockstackOperationType;
+use chainstate::burn::ConsensusHash;
+use chainstate::stacks::db::StacksChainState;
+use chainstate::stacks::index::file::TrieFile;
+use chainstate::stacks::index::marf::MARF;
+use chainstate::stacks::index::storage::TrieFileStorage;
+use chainstate::stacks::index::TrieHash;
+use chainstate::stacks::Error;
+use chainstate::stacks::*;
+
+use core::FIRST_STACKS_BLOCK_HASH;
+
+use util::db::Error as db_error;
+use util::hash::{to_hex, Sha512Trunc256Sum};
+
+/// Version of the snapshot format produced by this code
+pub const SNAPSHOT_VERSION: u32 = 1;
+
+/// Name of the manifest file within a snapshot directory
+pub const SNAPSHOT_MANIFEST: &'static str = "manifest.json";
+
+/// Location of the sortition DB within a snapshot directory
+pub const SNAPSHOT_SORTITION_DB: &'static str = "sortition.db/marf";
+
+/// Location of the Stacks headers DB and index MARF within a snapshot directory
+pub const SNAPSHOT_HEADERS_DB: &'static str = "chainstate/vm/index";
+
+/// Location of the Clarity state MARF within a snapshot directory
+pub const SNAPSHOT_CLARITY_MARF: &'static str = "chainstate/vm/clarity/marf";
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SnapshotFile {
+    /// path relative to the snapshot directory
+    pub path: String,
+    pub size: u64,
+    /// hex-encoded SHA512/256 digest of the file
+    pub sha512_256: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SnapshotManifest {
+    pub version: u32,
+    pub mainnet: bool,
+    pub chain_id: u32,
+    pub tip_consensus_hash: ConsensusHash,
+    pub tip_block_hash: BlockHeaderHash,
+    pub tip_index_block_hash: StacksBlockId,
+    pub tip_height: u64,
+    pub burn_block_height: u64,
+    /// Clarity MARF root hash at the tip (i.e. the tip header's state_index_root)
+    pub marf_root_hash: TrieHash,
+    pub files: Vec<SnapshotFile>,
+}
+
+impl SnapshotManifest {
+    /// Load a snapshot manifest from a snapshot directory
+    pub fn load(snapshot_dir: &str) -> Result<SnapshotManifest, Error> {
+        let path = Path::new(snapshot_dir).join(SNAPSHOT_MANIFEST);
+        let file = fs::File::open(&path).map_err(Error::ReadError)?;
+        serde_json::from_reader(file).map_err(|e| {
+            Error::InvalidSnapshot(format!("Failed to parse {}: {:?}", path.display(), &e))
+        })
+    }
+
+    /// Store this manifest to a snapshot directory
+    pub fn store(&self, snapshot_dir: &str) -> Result<(), Error> {
+        let path = Path::new(snapshot_dir).join(SNAPSHOT_MANIFEST);
+        let file = fs::File::create(&path).map_err(Error::WriteError)?;
+        serde_json::to_writer_pretty(file, self).map_err(|e| {
+            Error::WriteError(io::Error::new(io::ErrorKind::Other, format!("{:?}", &e)))
+        })
+    }
+}
+
+/// Paths to the live databases that make up a node's chainstate
+struct ChainstatePaths {
+    sortition_db: PathBuf,
+    headers_db: PathBuf,
+    clarity_marf: PathBuf,
+}
+
+impl ChainstatePaths {
+    fn new(mainnet: bool, chain_id: u32, chainstate_path: &str, sortdb_path: &str) -> Self {
+        let chain_path = StacksChainState::get_chain_path(mainnet, chain_id, chainstate_path);
+        ChainstatePaths {
+            sortition_db: Path::new(sortdb_path).join("marf"),
+            headers_db: chain_path.join("vm").join("index"),
+            clarity_marf: chain_path.join("vm").join("clarity").join("marf"),
+        }
+    }
+
+    /// (live path, path within a snapshot) pairs, in the order in which they must be copied.
+    /// The sortition DB is copied first, so the Stacks tip it names is guaranteed to be present
+    /// in the headers DB and Clarity MARF copies taken after it.
+    fn files(&self) -> Vec<(&Path, &'static str)> {
+        vec![
+            (self.sortition_db.as_path(), SNAPSHOT_SORTITION_DB),
+            (self.headers_db.as_path(), SNAPSHOT_HEADERS_DB),
+            (self.clarity_marf.as_path(), SNAPSHOT_CLARITY_MARF),
+        ]
+    }
+}
+
+fn path_to_str(path: &Path) -> Result<&str, Error> {
+    path.to_str()
+        .ok_or_else(|| Error::InvalidSnapshot(format!("Invalid path {}", path.display())))
+}
+
+fn mkdirs_for(path: &Path) -> Result<(), Error> {
+    match path.parent() {
+        Some(parent) => fs::create_dir_all(parent).map_err(Error::WriteError),
+        None => Ok(()),
+    }
+}
+
+fn open_readonly(path: &Path) -> Result<Connection, Error> {
+    Connection::open_with_flags(path, OpenFlags::SQLITE_OPEN_READ_ONLY).map_err(Error::from)
+}
+
+/// Describe a file in a snapshot: its size and SHA512/256 digest
+fn digest_file(snapshot_dir: &Path, rel_path: &str) -> Result<SnapshotFile, Error> {
+    let path = snapshot_dir.join(rel_path);
+    let mut file = fs::File::open(&path).map_err(Error::ReadError)?;
+    let mut hasher = Sha512Trunc256::default();
+    let size = io::copy(&mut file, &mut hasher).map_err(Error::ReadError)?;
+    Ok(SnapshotFile {
+        path: rel_path.to_string(),
+        size: size,
+        sha512_256: to_hex(Sha512Trunc256Sum::from_hasher(hasher).as_bytes()),
+    })
+}
+
+/// Check that the snapshot in snapshot_dir contains exactly the files the manifest lists, with
+/// the given sizes and digests.  Each database may be accompanied by a MARF blobs file.
+fn verify_snapshot_files(snapshot_dir: &Path, manifest: &SnapshotManifest) -> Result<(), Error> {
+    let expected = [
+        SNAPSHOT_SORTITION_DB,
+        SNAPSHOT_HEADERS_DB,
+        SNAPSHOT_CLARITY_MARF,
+    ];
+    let listed: HashSet<&str> = manifest.files.iter().map(|f| f.path.as_str()).collect();
+    let mut allowed = HashSet::new();
+    for p in expected.iter() {
+        allowed.insert(p.to_string());
+        let blobs_path = TrieFile::blobs_path(p);
+        if !listed.contains(blobs_path.as_str())
+            && fs::metadata(snapshot_dir.join(&blobs_path)).is_ok()
+        {
+            return Err(Error::InvalidSnapshot(format!(
+                "Snapshot manifest does not list {}",
+                &blobs_path
+            )));
+        }
+        allowed.insert(blobs_path);
+    }
+
+    if listed.len() != manifest.files.len()
+        || !expected.iter().all(|p| listed.contains(p))
+        || !listed.iter().all(|p| allowed.contains(*p))
+    {
+        return Err(Error::InvalidSnapshot(
+            "Snapshot manifest does not list the expected files".to_string(),
+        ));
+    }
+
+    for file in manifest.files.iter() {
+        let actual = digest_file(snapshot_dir, &file.path)?;
+        if actual != *file {
+            return Err(Error::InvalidSnapshot(format!(
+                "Snapshot file {} does not match its manifest entry (expected {} bytes with digest {}, got {} bytes with digest {})",
+                &file.path, file.size, &file.sha512_256, actual.size, &actual.sha512_256
+            )));
+        }
+    }
+    Ok(())
+}
+
+/// Check that the databases in the snapshot agree with the manifest's chain tip:
+/// * the sortition DB must have chosen the tip block in a valid sortition, and must consider
+/// it the canonical Stacks chain tip;
+/// * the headers DB must have the tip's header, which must hash to the tip block hash and commit
+/// to the manifest's MARF root hash;
+/// * the Clarity MARF must have that root hash at the tip.
+/// All databases are opened read-only.
+fn verify_snapshot_state(snapshot_dir: &Path, manifest: &SnapshotManifest) -> Result<(), Error> {
+    let index_block_hash = StacksBlockHeader::make_index_block_hash(
+        &manifest.tip_consensus_hash,
+        &manifest.tip_block_hash,
+    );
+    if index_block_hash != manifest.tip_index_block_hash {
+        return Err(Error::InvalidSnapshot(format!(
+            "Snapshot tip {}/{} has index block hash {}, but manifest says {}",
+            &manifest.tip_consensus_hash,
+            &manifest.tip_block_hash,
+            &index_block_hash,
+            &manifest.tip_index_block_hash
+        )));
+    }
+
+    // the sortition DB must have committed to this block
+    {
+        let conn = open_readonly(&snapshot_dir.join(SNAPSHOT_SORTITION_DB))?;
+        let sn = SortitionDB::get_block_snapshot_consensus(&conn, &manifest.tip_consensus_hash)?
+            .ok_or_else(|| {
+                Error::InvalidSnapshot(format!(
+                    "No sortition for snapshot tip consensus hash {}",
+                    &manifest.tip_consensus_hash
+                ))
+            })?;
+
+        if !sn.pox_valid || !sn.sortition || sn.winning_stacks_block_hash != manifest.tip_block_hash
+        {
+            return Err(Error::InvalidSnapshot(format!(
+                "Sortition {} did not choose snapshot tip block {}",
+                &manifest.tip_consensus_hash, &manifest.tip_block_hash
+            )));
+        }
+
+        if sn.block_height != manifest.burn_block_height {
+            return Err(Error::InvalidSnapshot(format!(
+                "Sortition {} is at burn height {}, but manifest says {}",
+                &manifest.tip_consensus_hash, sn.block_height, manifest.burn_block_height
+            )));
+        }
+
+        let (tip_ch, tip_bhh) = SortitionDB::get_canonical_stacks_chain_tip_hash(&conn)?;
+        if tip_ch != manifest.tip_consensus_hash || tip_bhh != manifest.tip_block_hash {
+            return Err(Error::InvalidSnapshot(format!(
+                "Snapshot sortition DB has canonical Stacks tip {}/{}, but manifest says {}/{}",
+                &tip_ch, &tip_bhh, &manifest.tip_consensus_hash, &manifest.tip_block_hash
+            )));
+        }
+    }
+
+    // the block header must commit to the MARF root hash
+    {
+        let conn = open_readonly(&snapshot_dir.join(SNAPSHOT_HEADERS_DB))?;
+        let header_info = match StacksChainState::get_anchored_block_header_info(
+            &conn,
+            &manifest.tip_consensus_hash,
+            &manifest.tip_block_hash,
+        ) {
+            Ok(Some(header_info)) => header_info,
+            Ok(None) => {
+                return Err(Error::InvalidSnapshot(format!(
+                    "No header for snapshot tip {}/{}",
+                    &manifest.tip_consensus_hash, &manifest.tip_block_hash
+                )));
+            }
+            Err(Error::DBError(db_error::ParseError)) => {
+                // the header row does not hash to its block hash
+                return Err(Error::InvalidSnapshot(format!(
+                    "Corrupt header for snapshot tip {}/{}",
+                    &manifest.tip_consensus_hash, &manifest.tip_block_hash
+                )));
+            }
+            Err(e) => {
+                return Err(e);
+            }
+        };
+
+        let block_hash = header_info.anchored_header.block_hash();
+        if block_hash != manifest.tip_block_hash {
+            return Err(Error::InvalidSnapshot(format!(
+                "Snapshot tip header hashes to {}, but manifest says {}",
+                &block_hash, &manifest.tip_block_hash
+            )));
+        }
+
+        if header_info.block_height != manifest.tip_height {
+            return Err(Error::InvalidSnapshot(format!(
+                "Snapshot tip is at height {}, but manifest says {}",
+                header_info.block_height, manifest.tip_height
+            )));
+        }
+
+        if header_info.anchored_header.state_index_root != manifest.marf_root_hash {
+            return Err(Error::InvalidSnapshot(format!(
+                "Snapshot tip header commits to MARF root hash {}, but manifest says {}",
+                &header_info.anchored_header.state_index_root, &manifest.marf_root_hash
+            )));
+        }
+    }
+
+    // the Clarity MARF must actually have that root hash
+    {
+        let storage = TrieFileStorage::open_readonly(path_to_str(
+            &snapshot_dir.join(SNAPSHOT_CLARITY_MARF),
+        )?)?;
+        let mut marf: MARF<StacksBlockId> = MARF::from_storage(storage);
+        let root_hash = marf.get_root_hash_at(&manifest.tip_index_block_hash)?;
+        if root_hash != manifest.marf_root_hash {
+            return Err(Error::InvalidSnapshot(format!(
+                "Snapshot Clarity MARF has root hash {} at {}, but manifest says {}",
+                &root_hash, &manifest.tip_index_block_hash, &manifest.marf_root_hash
+            )));
+        }
+    }
+
+    Ok(())
+}
+
+/// Check the snapshot's tip sortition against a burnchain DB that this node synced itself, so a
+/// snapshot cannot vouch for its own burnchain history:
+/// * the sortition's burnchain block must be on the node's canonical burnchain fork;
+/// * the node must have seen the winning block-commit in that burnchain block, and it must commit
+/// to the manifest's tip block.
+fn verify_snapshot_burnchain(
+    snapshot_dir: &Path,
+    manifest: &SnapshotManifest,
+    burnchain_db_path: &str,
+) -> Result<(), Error> {
+    let sn = {
+        let conn = open_readonly(&snapshot_dir.join(SNAPSHOT_SORTITION_DB))?;
+        SortitionDB::get_block_snapshot_consensus(&conn, &manifest.tip_consensus_hash)?.ok_or_else(
+            || {
+                Error::InvalidSnapshot(format!(
+                    "No sortition for snapshot tip consensus hash {}",
+                    &manifest.tip_consensus_hash
+                ))
+            },
+        )?
+    };
+
+    if fs::metadata(burnchain_db_path).is_err() {
+        return Err(Error::InvalidSnapshot(format!(
+            "No such burnchain DB {}; sync the burnchain before importing a snapshot",
+            burnchain_db_path
+        )));
+    }
+    let burnchain_db = BurnchainDB::open(burnchain_db_path, false).map_err(|e| {
+        Error::InvalidSnapshot(format!(
+            "Failed to open burnchain DB {}: {:?}",
+            burnchain_db_path, &e
+        ))
+    })?;
+    let burnchain_err = |e| {
+        Error::InvalidSnapshot(format!(
+            "Failed to read burnchain DB {}: {:?}",
+            burnchain_db_path, &e
+        ))
+    };
+
+    let mut header = burnchain_db
+        .get_canonical_chain_tip()
+        .map_err(burnchain_err)?;
+    if header.block_height < sn.block_height {
+        return Err(Error::InvalidSnapshot(format!(
+            "Burnchain is only synced to height {}, but the snapshot is at burn height {}",
+            header.block_height, sn.block_height
+        )));
+    }
+    while header.block_height > sn.block_height {
+        header = burnchain_db
+            .get_burnchain_header(&header.parent_block_hash)
+            .map_err(burnchain_err)?
+            .ok_or_else(|| {
+                Error::InvalidSnapshot(format!(
+                    "Burnchain DB {} is missing block {}",
+                    burnchain_db_path, &header.parent_block_hash
+                ))
+            })?;
+    }
+    if header.block_hash != sn.burn_header_hash {
+        return Err(Error::InvalidSnapshot(format!(
+            "Sortition {} is in burnchain block {}, but this node's burnchain has block {} at height {}",
+            &manifest.tip_consensus_hash, &sn.burn_header_hash, &header.block_hash, sn.block_height
+        )));
+    }
+
+    match burnchain_db.get_burnchain_op(&sn.winning_block_txid) {
+        Some(BlockstackOperationType::LeaderBlockCommit(ref op))
+            if op.block_header_hash == manifest.tip_block_hash
+                && op.burn_header_hash == sn.burn_header_hash => {}
+        _ => {
+            return Err(Error::InvalidSnapshot(format!(
+                "This node's burnchain has no block-commit {} for snapshot tip block {} in burnchain block {}",
+                &sn.winning_block_txid, &manifest.tip_block_hash, &sn.burn_header_hash
+            )));
+        }
+    }
+
+    Ok(())
+}
+
+/// Verify a snapshot directory: its manifest, its files' digests, the consistency of its
+/// databases with the manifest's chain tip, and the tip's sortition against the burnchain DB at
+/// burnchain_db_path.
+pub fn verify_snapshot(
+    mainnet: bool,
+    chain_id: u32,
+    snapshot_dir: &str,
+    burnchain_db_path: &str,
+) -> Result<SnapshotManifest, Error> {
+    let manifest = SnapshotManifest::load(snapshot_dir)?;
+    if manifest.version != SNAPSHOT_VERSION {
+        return Err(Error::InvalidSnapshot(format!(
+            "Unsupported snapshot version {} (expected {})",
+            manifest.version, SNAPSHOT_VERSION
+        )));
+    }
+    if manifest.mainnet != mainnet || manifest.chain_id != chain_id {
+        return Err(Error::InvalidSnapshot(format!(
+            "Snapshot is for chain {:08x} (mainnet = {}), not chain {:08x} (mainnet = {})",
+            manifest.chain_id, manifest.mainnet, chain_id, mainnet
+        )));
+    }
+
+    let snapshot_path = Path::new(snapshot_dir);
+    verify_snapshot_files(snapshot_path, &manifest)?;
+    verify_snapshot_state(snapshot_path, &manifest)?;
+    verify_snapshot_burnchain(snapshot_path, &manifest, burnchain_db_path)?;
+    Ok(manifest)
+}
+
+/// Export a snapshot of the chainstate rooted at chainstate_path and the sortition DB at
+/// sortdb_path into the directory output_dir, which must not already exist.
+/// The node may be running while this happens.
+pub fn export_snapshot(
+    mainnet: bool,
+    chain_id: u32,
+    chainstate_path: &str,
+    sortdb_path: &str,
+    output_dir: &str,
+) -> Result<SnapshotManifest, Error> {
+    if fs::metadata(output_dir).is_ok() {
+        return Err(Error::InvalidSnapshot(format!(
+            "Snapshot output directory {} already exists",
+            output_dir
+        )));
+    }
+
+    let paths = ChainstatePaths::new(mainnet, chain_id, chainstate_path, sortdb_path);
+    let output_path = Path::new(output_dir);
+
+    for (src, rel_path) in paths.files().into_iter() {
+        if fs::metadata(src).is_err() {
+            return Err(Error::InvalidSnapshot(format!(
+                "No such database {}",
+                src.display()
+            )));
+        }
+
+        let dest = output_path.join(rel_path);
+        mkdirs_for(&dest)?;
+
+        debug!("Snapshot {} to {}", src.display(), dest.display());
+        let conn = open_readonly(src)?;
+        let args: &[&dyn ToSql] = &[&path_to_str(&dest)?];
+        conn.execute("VACUUM INTO ?1", args)?;
+
+        if TrieFile::exists(path_to_str(src)?) {
+            let src_blobs = TrieFile::blobs_path(path_to_str(src)?);
+            let dest_blobs = TrieFile::blobs_path(path_to_str(&dest)?);
+            debug!("Snapshot {} to {}", &src_blobs, &dest_blobs);
+            fs::copy(&src_blobs, &dest_blobs).map_err(Error::WriteError)?;
+        }
+    }
+
+    // find the tip from the copies, not the live DBs
+    let (tip_consensus_hash, tip_block_hash) = {
+        let conn = open_readonly(&output_path.join(SNAPSHOT_SORTITION_DB))?;
+        SortitionDB::get_canonical_stacks_chain_tip_hash(&conn)?
+    };
+
+    if tip_block_hash == FIRST_STACKS_BLOCK_HASH {
+        return Err(Error::InvalidSnapshot(
+            "No Stacks blocks have been processed yet".to_string(),
+        ));
+    }
+
+    let burn_block_height = {
+        let conn = open_readonly(&output_path.join(SNAPSHOT_SORTITION_DB))?;
+        SortitionDB::get_block_snapshot_consensus(&conn, &tip_consensus_hash)?
+            .ok_or_else(|| {
+                Error::InvalidSnapshot(format!(
+                    "No sortition for canonical Stacks tip consensus hash {}",
+                    &tip_consensus_hash
+                ))
+            })?
+            .block_height
+    };
+
+    let header_info = {
+        let conn = open_readonly(&output_path.join(SNAPSHOT_HEADERS_DB))?;
+        StacksChainState::get_anchored_block_header_info(
+            &conn,
+            &tip_consensus_hash,
+            &tip_block_hash,
+        )?
+        .ok_or_else(|| {
+            Error::InvalidSnapshot(format!(
+                "No header for canonical Stacks tip {}/{}",
+                &tip_consensus_hash, &tip_block_hash
+            ))
+        })?
+    };
+
+    let mut manifest = SnapshotManifest {
+        version: SNAPSHOT_VERSION,
+        mainnet: mainnet,
+        chain_id: chain_id,
+        tip_index_block_hash: StacksBlockHeader::make_index_block_hash(
+            &tip_consensus_hash,
+            &tip_block_hash,
+        ),
+        tip_consensus_hash: tip_consensus_hash,
+        tip_block_hash: tip_block_hash,
+        tip_height: header_info.block_height,
+        burn_block_height: burn_block_height,
+        marf_root_hash: header_info.anchored_header.state_index_root.clone(),
+        files: vec![],
+    };
+
+    verify_snapshot_state(output_path, &manifest)?;
+
+    for (_, rel_path) in paths.files().into_iter() {
+        manifest.files.push(digest_file(output_path, rel_path)?);
+
+        let blobs_rel_path = TrieFile::blobs_path(rel_path);
+        if fs::metadata(output_path.join(&blobs_rel_path)).is_ok() {
+            manifest
+                .files
+                .push(digest_file(output_path, &blobs_rel_path)?);
+        }
+    }
+
+    manifest.store(output_dir)?;
+
+    info!(
+        "Exported chainstate snapshot at {}/{} (height {}) to {}",
+        &manifest.tip_consensus_hash, &manifest.tip_block_hash, manifest.tip_height, output_dir
+    );
+    Ok(manifest)
+}
+
+/// Install the snapshot in input_dir as the chainstate rooted at chainstate_path and the
+/// sortition DB at sortdb_path.  The snapshot is verified in full before anything is copied,
+/// including against this node's own burnchain DB at burnchain_db_path, and existing databases
+/// are never overwritten.
+pub fn import_snapshot(
+    mainnet: bool,
+    chain_id: u32,
+    input_dir: &str,
+    chainstate_path: &str,
+    sortdb_path: &str,
+    burnchain_db_path: &str,
+) -> Result<SnapshotManifest, Error> {
+    let manifest = verify_snapshot(mainnet, chain_id, input_dir, burnchain_db_path)?;
+
+    let paths = ChainstatePaths::new(mainnet, chain_id, chainstate_path, sortdb_path);
+    let input_path = Path::new(input_dir);
+
+    // (snapshot path, live path) pairs, including any MARF blobs files
+    let mut installs = vec![];
+    for (dest, rel_path) in paths.files().into_iter() {
+        installs.push((input_path.join(rel_path), dest.to_path_buf()));
+
+        let blobs_rel_path = TrieFile::blobs_path(rel_path);
+        if manifest.files.iter().any(|f| f.path == blobs_rel_path) {
+            installs.push((
+                input_path.join(&blobs_rel_path),
+                PathBuf::from(TrieFile::blobs_path(path_to_str(dest)?)),
+            ));
+        }
+    }
+
+    for (_, dest) in installs.iter() {
+        if fs::metadata(dest).is_ok() {
+            return Err(Error::InvalidSnapshot(format!(
+                "Refusing to overwrite existing database {}",
+                dest.display()
+            )));
+        }
+    }
+
+    for (src, dest) in installs.iter() {
+        mkdirs_for(dest)?;
+
+        debug!("Install {} to {}", src.display(), dest.display());
+        fs::copy(src, dest).map_err(Error::WriteError)?;
+    }
+
+    info!(
+        "Imported chainstate snapshot at {}/{} (height {}) from {}",
+        &manifest.tip_consensus_hash, &manifest.tip_block_hash, manifest.tip_height, input_dir
+    );
+    Ok(manifest)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use std::io::{Seek, SeekFrom, Write};
+
+    use chainstate::stacks::db::*;
+
+    use net::test::*;
+
+    use util::db::u64_to_sql;
+
+    fn snapshot_test_dir(name: &str) -> String {
+        let path = format!("/tmp/blockstack-test-snapshot-{}", name);
+        if fs::metadata(&path).is_ok() {
+            fs::remove_dir_all(&path).unwrap();
+        }
+        path
+    }
+
+    fn mine_tenures(peer: &mut TestPeer, num_blocks: usize) {
+        for _ in 0..num_blocks {
+            let (burn_ops, stacks_block, microblocks) = peer.make_default_tenure();
+            peer.next_burnchain_block(burn_ops);
+            peer.process_stacks_epoch_at_tip(&stacks_block, &microblocks);
+        }
+    }
+
+    #[test]
+    fn test_snapshot_export_import() {
+        let peer_config = TestPeerConfig::new("test_snapshot_export_import", 31030, 31031);
+        let mut peer = TestPeer::new(peer_config);
+        mine_tenures(&mut peer, 5);
+
+        let chain_id = peer.config.network_id;
+        let sortdb_path = peer.config.burnchain.get_db_path();
+        let chainstate_path = peer.chainstate_path.clone();
+        let burnchain_db_path = peer.config.burnchain.get_burnchaindb_path();
+
+        let (tip_ch, tip_bhh) =
+            SortitionDB::get_canonical_stacks_chain_tip_hash(peer.sortdb().conn()).unwrap();
+
+        let export_dir = snapshot_test_dir("export_import-out");
+        let manifest =
+            export_snapshot(false, chain_id, &chainstate_path, &sortdb_path, &export_dir).unwrap();
+
+        assert_eq!(manifest.tip_consensus_hash, tip_ch);
+        assert_eq!(manifest.tip_block_hash, tip_bhh);
+        assert_eq!(manifest.tip_height, 5);
+        assert_eq!(manifest.files.len(), 3);
+        assert_eq!(SnapshotManifest::load(&export_dir).unwrap(), manifest);
+
+        // can't export over an existing snapshot
+        assert!(
+            export_snapshot(false, chain_id, &chainstate_path, &sortdb_path, &export_dir).is_err()
+        );
+
+        // wrong chain
+        assert!(verify_snapshot(true, chain_id, &export_dir, &burnchain_db_path).is_err());
+        assert!(verify_snapshot(false, chain_id + 1, &export_dir, &burnchain_db_path).is_err());
+
+        // can't clobber the existing chainstate
+        assert!(import_snapshot(
+            false,
+            chain_id,
+            &export_dir,
+            &chainstate_path,
+            &sortdb_path,
+            &burnchain_db_path
+        )
+        .is_err());
+
+        let import_root = snapshot_test_dir("export_import-in");
+        let import_chainstate_path = format!("{}/chainstate", &import_root);
+        let import_sortdb_path = format!("{}/sortition.db", &import_root);
+
+        let imported = import_snapshot(
+            false,
+            chain_id,
+            &export_dir,
+            &import_chainstate_path,
+            &import_sortdb_path,
+            &burnchain_db_path,
+        )
+        .unwrap();
+        assert_eq!(imported, manifest);
+
+        // the imported databases open and agree on the tip
+        let sortdb = SortitionDB::open(&import_sortdb_path, false).unwrap();
+        assert_eq!(
+            SortitionDB::get_canonical_stacks_chain_tip_hash(sortdb.conn()).unwrap(),
+            (tip_ch.clone(), tip_bhh.clone())
+        );
+
+        let (mut chainstate, _) =
+            StacksChainState::open(false, chain_id, &import_chainstate_path).unwrap();
+        let header_info =
+            StacksChainState::get_anchored_block_header_info(chainstate.db(), &tip_ch, &tip_bhh)
+                .unwrap()
+                .unwrap();
+        assert_eq!(header_info.block_height, 5);
+        assert_eq!(
+            chainstate
+                .clarity_state
+                .with_marf(|marf| marf.get_root_hash_at(&manifest.tip_index_block_hash))
+                .unwrap(),
+            manifest.marf_root_hash
+        );
+    }
+
+    #[test]
+    fn test_snapshot_import_tampered() {
+        let peer_config = TestPeerConfig::new("test_snapshot_import_tampered", 31032, 31033);
+        let mut peer = TestPeer::new(peer_config);
+        mine_tenures(&mut peer, 3);
+
+        let chain_id = peer.config.network_id;
+        let sortdb_path = peer.config.burnchain.get_db_path();
+        let chainstate_path = peer.chainstate_path.clone();
+        let burnchain_db_path = peer.config.burnchain.get_burnchaindb_path();
+
+        let export_dir = snapshot_test_dir("tampered-out");
+        let manifest =
+            export_snapshot(false, chain_id, &chainstate_path, &sortdb_path, &export_dir).unwrap();
+
+        let import_root = snapshot_test_dir("tampered-in");
+        let import_chainstate_path = format!("{}/chainstate", &import_root);
+        let import_sortdb_path = format!("{}/sortition.db", &import_root);
+
+        // manifest lies about the MARF root hash
+        let mut bad_manifest = manifest.clone();
+        bad_manifest.marf_root_hash = TrieHash([0x11; 32]);
+        bad_manifest.store(&export_dir).unwrap();
+
+        match import_snapshot(
+            false,
+            chain_id,
+            &export_dir,
+            &import_chainstate_path,
+            &import_sortdb_path,
+            &burnchain_db_path,
+        ) {
+            Err(Error::InvalidSnapshot(_)) => {}
+            res => panic!("Expected InvalidSnapshot, got {:?}", &res),
+        }
+
+        // manifest lies about the tip
+        let mut bad_manifest = manifest.clone();
+        bad_manifest.tip_height += 1;
+        bad_manifest.store(&export_dir).unwrap();
+
+        match import_snapshot(
+            false,
+            chain_id,
+            &export_dir,
+            &import_chainstate_path,
+            &import_sortdb_path,
+            &burnchain_db_path,
+        ) {
+            Err(Error::InvalidSnapshot(_)) => {}
+            res => panic!("Expected InvalidSnapshot, got {:?}", &res),
+        }
+
+        // a file is corrupted
+        manifest.store(&export_dir).unwrap();
+        {
+            let mut f = fs::OpenOptions::new()
+                .write(true)
+                .open(Path::new(&export_dir).join(SNAPSHOT_CLARITY_MARF))
+                .unwrap();
+            f.seek(SeekFrom::Start(100)).unwrap();
+            f.write_all(&[0xff; 4]).unwrap();
+        }
+
+        match import_snapshot(
+            false,
+            chain_id,
+            &export_dir,
+            &import_chainstate_path,
+            &import_sortdb_path,
+            &burnchain_db_path,
+        ) {
+            Err(Error::InvalidSnapshot(_)) => {}
+            res => panic!("Expected InvalidSnapshot, got {:?}", &res),
+        }
+
+        // nothing was installed
+        assert!(fs::metadata(&import_root).is_err());
+    }
+
+    #[test]
+    fn test_snapshot_import_tampered_header() {
+        let peer_config = TestPeerConfig::new("test_snapshot_import_tampered_header", 31034, 31035);
+        let mut peer = TestPeer::new(peer_config);
+        mine_tenures(&mut peer, 3);
+
+        let chain_id = peer.config.network_id;
+        let sortdb_path = peer.config.burnchain.get_db_path();
+        let chainstate_path = peer.chainstate_path.clone();
+        let burnchain_db_path = peer.config.burnchain.get_burnchaindb_path();
+
+        let export_dir = snapshot_test_dir("tampered-header-out");
+        let mut manifest =
+            export_snapshot(false, chain_id, &chainstate_path, &sortdb_path, &export_dir).unwrap();
+
+        // rewrite the tip header so it no longer hashes to the tip block hash, but still has the
+        // right height and MARF root hash, and re-digest the files so the manifest is
+        // self-consistent
+        {
+            let conn = Connection::open(Path::new(&export_dir).join(SNAPSHOT_HEADERS_DB)).unwrap();
+            let args: &[&dyn ToSql] = &[&manifest.tip_consensus_hash, &manifest.tip_block_hash];
+            let updated = conn
+                .execute(
+                    "UPDATE block_headers SET parent_microblock_sequence = parent_microblock_sequence + 1 WHERE consensus_hash = ?1 AND block_hash = ?2",
+                    args,
+                )
+                .unwrap();
+            assert_eq!(updated, 1);
+        }
+        let export_path = Path::new(&export_dir);
+        manifest.files = manifest
+            .files
+            .iter()
+            .map(|f| digest_file(export_path, &f.path).unwrap())
+            .collect();
+        manifest.store(&export_dir).unwrap();
+
+        let import_root = snapshot_test_dir("tampered-header-in");
+        match import_snapshot(
+            false,
+            chain_id,
+            &export_dir,
+            &format!("{}/chainstate", &import_root),
+            &format!("{}/sortition.db", &import_root),
+            &burnchain_db_path,
+        ) {
+            Err(Error::InvalidSnapshot(msg)) => assert!(msg.contains("Corrupt header")),
+            res => panic!("Expected InvalidSnapshot, got {:?}", &res),
+        }
+        assert!(fs::metadata(&import_root).is_err());
+    }
+
+    #[test]
+    fn test_snapshot_import_wrong_burnchain() {
+        let peer_config = TestPeerConfig::new("test_snapshot_import_wrong_burnchain", 31036, 31037);
+        let mut peer = TestPeer::new(peer_config);
+        mine_tenures(&mut peer, 3);
+
+        let chain_id = peer.config.network_id;
+        let sortdb_path = peer.config.burnchain.get_db_path();
+        let chainstate_path = peer.chainstate_path.clone();
+        let burnchain_db_path = peer.config.burnchain.get_burnchaindb_path();
+
+        let export_dir = snapshot_test_dir("wrong-burnchain-out");
+        let manifest =
+            export_snapshot(false, chain_id, &chainstate_path, &sortdb_path, &export_dir).unwrap();
+        let sn = SortitionDB::get_block_snapshot_consensus(
+            peer.sortdb().conn(),
+            &manifest.tip_consensus_hash,
+        )
+        .unwrap()
+        .unwrap();
+
+        let import_root = snapshot_test_dir("wrong-burnchain-in");
+        let try_import = |burnchain_db_path: &str| {
+            import_snapshot(
+                false,
+                chain_id,
+                &export_dir,
+                &format!("{}/chainstate", &import_root),
+                &format!("{}/sortition.db", &import_root),
+                burnchain_db_path,
+            )
+        };
+
+        // no burnchain DB at all
+        match try_import(&format!("{}/no-such-burnchain.db", &export_dir)) {
+            Err(Error::InvalidSnapshot(_)) => {}
+            res => panic!("Expected InvalidSnapshot, got {:?}", &res),
+        }
+
+        // burnchain DB that isn't synced up to the snapshot's sortition
+        let scratch_dir = snapshot_test_dir("wrong-burnchain-dbs");
+        fs::create_dir_all(&scratch_dir).unwrap();
+        let short_burnchain_db_path = format!("{}/short-burnchain.db", &scratch_dir);
+        fs::copy(&burnchain_db_path, &short_burnchain_db_path).unwrap();
+        {
+            let conn = Connection::open(&short_burnchain_db_path).unwrap();
+            let args: &[&dyn ToSql] = &[&u64_to_sql(sn.block_height).unwrap()];
+            conn.execute(
+                "DELETE FROM burnchain_db_block_ops WHERE block_hash IN (SELECT block_hash FROM burnchain_db_block_headers WHERE block_height >= ?1)",
+                args,
+            )
+            .unwrap();
+            conn.execute(
+                "DELETE FROM burnchain_db_block_headers WHERE block_height >= ?1",
+                args,
+            )
+            .unwrap();
+        }
+        match try_import(&short_burnchain_db_path) {
+            Err(Error::InvalidSnapshot(msg)) => assert!(msg.contains("only synced")),
+            res => panic!("Expected InvalidSnapshot, got {:?}", &res),
+        }
+
+        // burnchain DB that never saw the winning block-commit
+        let missing_burnchain_db_path = format!("{}/missing-burnchain.db", &scratch_dir);
+        fs::copy(&burnchain_db_path, &missing_burnchain_db_path).unwrap();
+        {
+            let conn = Connection::open(&missing_burnchain_db_path).unwrap();
+            let args: &[&dyn ToSql] = &[&sn.winning_block_txid];
+            let deleted = conn
+                .execute("DELETE FROM burnchain_db_block_ops WHERE txid = ?1", args)
+                .unwrap();
+            assert_eq!(deleted, 1);
+        }
+        match try_import(&missing_burnchain_db_path) {
+            Err(Error::InvalidSnapshot(msg)) => assert!(msg.contains("block-commit")),
+            res => panic!("Expected InvalidSnapshot, got {:?}", &res),
+        }
+
+        assert!(fs::metadata(&import_root).is_err());
+
+        // the node's own burnchain DB is fine
+        try_import(&burnchain_db_path).unwrap();
+    }
+}
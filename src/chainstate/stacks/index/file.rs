@@ -0,0 +1,646 @@
+// Copyright (C) 2013-2020 Blockstack PBC, a public benefit corporation
+// Copyright (C) 2020 Stacks Open Internet Foundation
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <http://www.gnu.org/licenses/>.
+
+//! Storage of confirmed trie blobs outside of SQLite.
+//!
+//! By default, every serialized trie is a BLOB in the `marf_data` table.  If a MARF's database
+//! has a sibling blobs file (the database path with `.blobs` appended), then newly-confirmed
+//! tries are instead appended to that file, and `marf_data` only records each trie's offset and
+//! length within it.  Unconfirmed and mined tries are always kept in SQLite, since they get
+//! rewritten or thrown away.
+//!
+//! The blobs file is append-only.  A trie is appended (and synced) before its row is inserted,
+//! so a crash can only leave unreferenced bytes at the end of the file.
+//!
+//! Space taken up by tries that garbage collection removes is reclaimed by compaction, which
+//! copies the remaining tries to a new file next to the blobs file (the blobs file's path with
+//! `.compact` appended).  The rows are then pointed at their new offsets in the same transaction
+//! that marks the compaction as pending, and the new file is renamed over the old one.  If this
+//! is interrupted, the next read-write open of the MARF either finishes the rename (if the
+//! transaction committed) or deletes the new file (if it did not).
+
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::io::{Read, Seek, SeekFrom, Write};
+use std::path::Path;
+
+use rusqlite::Connection;
+
+use chainstate::stacks::index::bits::{read_node_hash_bytes, read_nodetype};
+use chainstate::stacks::index::node::{TrieNodeType, TriePtr};
+use chainstate::stacks::index::{trie_sql, Error, MarfTrieId, TrieHash};
+
+use util::db::{tx_begin_immediate, tx_busy_handler};
+
+/// Suffix of a MARF database's path that names its blobs file
+pub const TRIE_BLOBS_FILE_SUFFIX: &'static str = ".blobs";
+
+/// Suffix of a blobs file's path that names the file it is being compacted into
+const TRIE_BLOBS_COMPACT_SUFFIX: &'static str = ".compact";
+
+/// An append-only file of serialized tries
+pub struct TrieFile {
+    path: String,
+    fd: fs::File,
+    /// (offset, length) of each trie looked up so far, by local block ID.  A trie's blob does
+    /// not move once written, so entries only go stale when tries are removed and their local
+    /// IDs can be reused.
+    offsets: HashMap<u32, (u64, u64)>,
+}
+
+/// A single trie's blob within a blobs file.  Seeks are relative to the start of the trie, so the
+/// node-reading routines in `bits` can be used on it as-is.
+struct TrieFileBlob<'a> {
+    fd: &'a mut fs::File,
+    offset: u64,
+    length: u64,
+    pos: u64,
+}
+
+impl<'a> TrieFileBlob<'a> {
+    fn new(fd: &'a mut fs::File, offset: u64, length: u64) -> Result<TrieFileBlob<'a>, Error> {
+        fd.seek(SeekFrom::Start(offset))?;
+        Ok(TrieFileBlob {
+            fd: fd,
+            offset: offset,
+            length: length,
+            pos: 0,
+        })
+    }
+}
+
+impl Read for TrieFileBlob<'_> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let remaining = self.length.saturating_sub(self.pos);
+        let max_len = if (buf.len() as u64) < remaining {
+            buf.len()
+        } else {
+            remaining as usize
+        };
+        let nr = self.fd.read(&mut buf[..max_len])?;
+        self.pos += nr as u64;
+        Ok(nr)
+    }
+}
+
+impl Seek for TrieFileBlob<'_> {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        let new_pos = match pos {
+            SeekFrom::Start(p) => Some(p),
+            SeekFrom::Current(p) => {
+                if p < 0 {
+                    self.pos.checked_sub(p.wrapping_neg() as u64)
+                } else {
+                    self.pos.checked_add(p as u64)
+                }
+            }
+            SeekFrom::End(p) => {
+                if p < 0 {
+                    self.length.checked_sub(p.wrapping_neg() as u64)
+                } else {
+                    self.length.checked_add(p as u64)
+                }
+            }
+        }
+        .ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "Seek to a negative or overflowing position",
+            )
+        })?;
+
+        self.fd.seek(SeekFrom::Start(self.offset + new_pos))?;
+        self.pos = new_pos;
+        Ok(new_pos)
+    }
+}
+
+impl TrieFile {
+    /// Get the path to the blobs file of the MARF database at db_path
+    pub fn blobs_path(db_path: &str) -> String {
+        format!("{}{}", db_path, TRIE_BLOBS_FILE_SUFFIX)
+    }
+
+    /// Does the MARF database at db_path have a blobs file?
+    pub fn exists(db_path: &str) -> bool {
+        db_path != ":memory:" && fs::metadata(TrieFile::blobs_path(db_path)).is_ok()
+    }
+
+    /// Open the blobs file of the MARF database at db_path.  If not readonly, the file is created
+    /// if it doesn't exist.
+    pub fn open(db_path: &str, readonly: bool) -> Result<TrieFile, Error> {
+        let path = TrieFile::blobs_path(db_path);
+        let fd = fs::OpenOptions::new()
+            .read(true)
+            .write(!readonly)
+            .create(!readonly)
+            .open(&path)
+            .map_err(|e| {
+                if e.kind() == io::ErrorKind::NotFound {
+                    Error::NotFoundError
+                } else {
+                    Error::IOError(e)
+                }
+            })?;
+        Ok(TrieFile {
+            path: path,
+            fd: fd,
+            offsets: HashMap::new(),
+        })
+    }
+
+    pub fn path(&self) -> &str {
+        &self.path
+    }
+
+    /// Get the path to the file that the blobs file at blobs_path is compacted into
+    fn compact_path(blobs_path: &str) -> String {
+        format!("{}{}", blobs_path, TRIE_BLOBS_COMPACT_SUFFIX)
+    }
+
+    /// Finish or undo an interrupted compaction of the blobs file of the MARF database at
+    /// db_path, whose connection is db.  If the database already points into the compacted file,
+    /// that file replaces the blobs file; otherwise, it is deleted.  Must be called before the
+    /// blobs file is opened.
+    pub fn recover_compaction(db_path: &str, db: &Connection) -> Result<(), Error> {
+        TrieFile::finish_compaction(&TrieFile::blobs_path(db_path), db)
+    }
+
+    fn finish_compaction(blobs_path: &str, db: &Connection) -> Result<(), Error> {
+        let compact_path = TrieFile::compact_path(blobs_path);
+        if trie_sql::is_blobs_compaction_pending(db)? {
+            if fs::metadata(&compact_path).is_ok() {
+                fs::rename(&compact_path, blobs_path)?;
+                if let Some(dir) = Path::new(blobs_path).parent() {
+                    if dir.as_os_str().len() > 0 {
+                        fs::File::open(dir)?.sync_all()?;
+                    }
+                }
+            }
+            trie_sql::clear_blobs_compaction_pending(db)?;
+            debug!("Replaced {} with {}", blobs_path, &compact_path);
+        } else if fs::metadata(&compact_path).is_ok() {
+            warn!(
+                "Remove {}, left behind by an interrupted compaction",
+                &compact_path
+            );
+            fs::remove_file(&compact_path)?;
+        }
+        Ok(())
+    }
+
+    /// Append a blob to the end of the file, and sync it to disk.  Returns its offset.
+    fn append(&mut self, data: &[u8]) -> Result<u64, Error> {
+        let offset = self.fd.seek(SeekFrom::End(0))?;
+        self.fd.write_all(data)?;
+        self.fd.sync_data()?;
+        Ok(offset)
+    }
+
+    /// Append the serialized trie for block_hash, and record it as a confirmed trie.
+    /// Returns its local block ID.
+    pub fn append_trie_blob<T: MarfTrieId>(
+        &mut self,
+        db: &Connection,
+        block_hash: &T,
+        data: &[u8],
+    ) -> Result<u32, Error> {
+        let offset = self.append(data)?;
+        trie_sql::write_external_trie_blob(db, block_hash, offset, data.len() as u64)
+    }
+
+    /// Read the serialized trie at (offset, length)
+    fn read_blob(&mut self, offset: u64, length: u64) -> Result<Vec<u8>, Error> {
+        let mut data = vec![];
+        TrieFileBlob::new(&mut self.fd, offset, length)?.read_to_end(&mut data)?;
+        if (data.len() as u64) != length {
+            return Err(Error::CorruptionError(format!(
+                "Read {} bytes of a {}-byte trie at {} in {}",
+                data.len(),
+                length,
+                offset,
+                &self.path
+            )));
+        }
+        Ok(data)
+    }
+
+    /// Get the (offset, length) of the trie at block_id in this file, or None if it is stored in
+    /// the database.  Only tries in this file are remembered: a trie in the database may be an
+    /// unconfirmed one, whose local ID is reused once it is dropped.
+    fn get_trie_offset_length(
+        &mut self,
+        db: &Connection,
+        block_id: u32,
+    ) -> Result<Option<(u64, u64)>, Error> {
+        if let Some(offset_length) = self.offsets.get(&block_id) {
+            return Ok(Some(*offset_length));
+        }
+        let offset_length = trie_sql::get_external_trie_offset_length(db, block_id)?;
+        if let Some(offset_length) = offset_length {
+            self.offsets.insert(block_id, offset_length);
+        }
+        Ok(offset_length)
+    }
+
+    /// Forget the remembered trie locations.  Must be called whenever confirmed tries are
+    /// removed.
+    pub fn clear_offsets(&mut self) {
+        self.offsets.clear();
+    }
+
+    /// Read a node from the trie at block_id, wherever it is stored
+    pub fn read_node_type(
+        &mut self,
+        db: &Connection,
+        block_id: u32,
+        ptr: &TriePtr,
+    ) -> Result<(TrieNodeType, TrieHash), Error> {
+        match self.get_trie_offset_length(db, block_id)? {
+            Some((offset, length)) => {
+                let mut blob = TrieFileBlob::new(&mut self.fd, offset, length)?;
+                read_nodetype(&mut blob, ptr)
+            }
+            None => trie_sql::read_node_type(db, block_id, ptr),
+        }
+    }
+
+    /// Read a node's hash from the trie at block_id, wherever it is stored
+    pub fn get_node_hash_bytes(
+        &mut self,
+        db: &Connection,
+        block_id: u32,
+        ptr: &TriePtr,
+    ) -> Result<TrieHash, Error> {
+        match self.get_trie_offset_length(db, block_id)? {
+            Some((offset, length)) => {
+                let mut blob = TrieFileBlob::new(&mut self.fd, offset, length)?;
+                Ok(TrieHash(read_node_hash_bytes(&mut blob, ptr)?))
+            }
+            None => trie_sql::get_node_hash_bytes(db, block_id, ptr),
+        }
+    }
+
+    /// Write a node's hash from the trie at block_id, wherever it is stored
+    pub fn read_node_hash_bytes<W: Write>(
+        &mut self,
+        db: &Connection,
+        w: &mut W,
+        block_id: u32,
+        ptr: &TriePtr,
+    ) -> Result<(), Error> {
+        let hash = self.get_node_hash_bytes(db, block_id, ptr)?;
+        w.write_all(hash.as_bytes()).map_err(|e| e.into())
+    }
+
+    /// Rewrite this file so that it only holds the tries that the MARF database db still refers
+    /// to, in the same order.  The tries are copied to a new file, which is synced and checked
+    /// before the database is pointed at it, and which then replaces this one.  Returns the number
+    /// of bytes reclaimed.
+    /// Nothing else may be reading or writing the MARF while this runs.
+    pub fn compact(&mut self, db: &mut Connection) -> Result<u64, Error> {
+        let old_len = self.fd.seek(SeekFrom::End(0))?;
+        let tries = trie_sql::get_external_trie_offsets_lengths(db)?;
+
+        let compact_path = TrieFile::compact_path(&self.path);
+        let fd = fs::OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .open(&compact_path)?;
+        let mut compacted = TrieFile {
+            path: compact_path,
+            fd: fd,
+            offsets: HashMap::new(),
+        };
+
+        let mut new_len = 0;
+        for (_, offset, length) in tries.iter() {
+            let data = self.read_blob(*offset, *length)?;
+            compacted.fd.write_all(&data)?;
+            new_len += *length;
+        }
+        compacted.fd.sync_all()?;
+
+        let tx = tx_begin_immediate(db)?;
+        let mut new_offset = 0;
+        for (block_id, offset, length) in tries.iter() {
+            if compacted.read_blob(new_offset, *length)? != self.read_blob(*offset, *length)? {
+                return Err(Error::CorruptionError(format!(
+                    "Trie {} was not copied intact to {}",
+                    block_id, &compacted.path
+                )));
+            }
+            trie_sql::set_external_trie_blob(&tx, *block_id, new_offset, *length)?;
+            new_offset += *length;
+        }
+        trie_sql::set_blobs_compaction_pending(&tx)?;
+        tx.commit()?;
+
+        drop(compacted);
+        TrieFile::finish_compaction(&self.path, db)?;
+        self.fd = fs::OpenOptions::new()
+            .read(true)
+            .write(true)
+            .open(&self.path)?;
+        self.offsets.clear();
+
+        info!(
+            "Compacted {} from {} to {} bytes ({} tries)",
+            &self.path,
+            old_len,
+            new_len,
+            tries.len()
+        );
+        Ok(old_len.saturating_sub(new_len))
+    }
+
+    /// Move every confirmed trie in the MARF database at db_path into its blobs file, creating
+    /// the file if need be.  Each trie is read back and checked before its data column is
+    /// cleared, and all rows are updated in a single transaction.  Returns the number of tries
+    /// moved.
+    /// Nothing else may be writing to the MARF while this runs.  The database is not vacuumed.
+    pub fn migrate_blobs(db_path: &str) -> Result<u64, Error> {
+        let mut db = Connection::open(db_path)?;
+        db.busy_handler(Some(tx_busy_handler))?;
+        trie_sql::create_tables_if_needed(&mut db)?;
+        trie_sql::add_external_blob_columns_if_needed(&mut db)?;
+        let mut blobs = TrieFile::open(db_path, false)?;
+
+        let tx = tx_begin_immediate(&mut db)?;
+        let block_ids = trie_sql::get_internal_confirmed_block_ids(&tx)?;
+        for block_id in block_ids.iter() {
+            let data = trie_sql::read_trie_blob(&tx, *block_id)?;
+            let offset = blobs.append(&data)?;
+            if blobs.read_blob(offset, data.len() as u64)? != data {
+                return Err(Error::CorruptionError(format!(
+                    "Trie {} was not written intact to {}",
+                    block_id, &blobs.path
+                )));
+            }
+            trie_sql::set_external_trie_blob(&tx, *block_id, offset, data.len() as u64)?;
+            debug!(
+                "Moved trie {} ({} bytes) to offset {} in {}",
+                block_id,
+                data.len(),
+                offset,
+                &blobs.path
+            );
+        }
+        tx.commit()?;
+
+        info!(
+            "Moved {} tries from {} to {}",
+            block_ids.len(),
+            db_path,
+            &blobs.path
+        );
+        Ok(block_ids.len() as u64)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use chainstate::burn::BlockHeaderHash;
+    use chainstate::stacks::index::marf::{MarfConnection, MARF};
+    use chainstate::stacks::index::storage::TrieFileStorage;
+    use chainstate::stacks::index::MARFValue;
+
+    fn block_hash(height: u8) -> BlockHeaderHash {
+        BlockHeaderHash([height + 1; 32])
+    }
+
+    fn setup_db(path: &str) {
+        for p in [path.to_string(), TrieFile::blobs_path(path)].iter() {
+            if fs::metadata(p).is_ok() {
+                fs::remove_file(p).unwrap();
+            }
+        }
+    }
+
+    /// Build the blocks at heights [start, end) of a single fork, each of which writes 8 keys
+    fn make_blocks(marf: &mut MARF<BlockHeaderHash>, start: u8, end: u8) {
+        for height in start..end {
+            let parent = if height == 0 {
+                BlockHeaderHash::sentinel()
+            } else {
+                block_hash(height - 1)
+            };
+            marf.begin(&parent, &block_hash(height)).unwrap();
+            for k in 0..8 {
+                marf.insert(
+                    &format!("key-{}-{}", height, k),
+                    MARFValue::from_value(&format!("value-{}-{}", height, k)),
+                )
+                .unwrap();
+            }
+            marf.commit().unwrap();
+        }
+    }
+
+    fn check_blocks(marf: &mut MARF<BlockHeaderHash>, end: u8) {
+        let tip = block_hash(end - 1);
+        for height in 0..end {
+            for k in 0..8 {
+                assert_eq!(
+                    marf.get(&tip, &format!("key-{}-{}", height, k)).unwrap(),
+                    Some(MARFValue::from_value(&format!("value-{}-{}", height, k)))
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn trie_file_blob_seek() {
+        let path = "/tmp/rust_trie_file_blob_seek";
+        setup_db(path);
+
+        let mut blobs = TrieFile::open(path, false).unwrap();
+        assert_eq!(blobs.append(&[0u8, 1, 2, 3]).unwrap(), 0);
+        assert_eq!(blobs.append(&[4u8, 5, 6, 7, 8, 9]).unwrap(), 4);
+
+        let mut blob = TrieFileBlob::new(&mut blobs.fd, 4, 6).unwrap();
+        let mut buf = [0u8; 2];
+        blob.seek(SeekFrom::Start(2)).unwrap();
+        blob.read_exact(&mut buf).unwrap();
+        assert_eq!(buf, [6u8, 7]);
+
+        blob.seek(SeekFrom::End(-1)).unwrap();
+        let mut rest = vec![];
+        blob.read_to_end(&mut rest).unwrap();
+        assert_eq!(rest, vec![9u8]);
+
+        assert!(blob.seek(SeekFrom::Current(-10)).is_err());
+        assert_eq!(blobs.read_blob(0, 4).unwrap(), vec![0u8, 1, 2, 3]);
+        assert!(blobs.read_blob(8, 4).is_err());
+    }
+
+    #[test]
+    fn trie_file_remembers_offsets() {
+        let path = "/tmp/rust_trie_file_remembers_offsets";
+        setup_db(path);
+
+        let mut db = Connection::open(path).unwrap();
+        trie_sql::create_tables_if_needed(&mut db).unwrap();
+        trie_sql::add_external_blob_columns_if_needed(&mut db).unwrap();
+        let mut blobs = TrieFile::open(path, false).unwrap();
+
+        blobs.append(&[0u8; 16]).unwrap();
+        let block_id = blobs
+            .append_trie_blob(&db, &block_hash(0), &[1u8, 2, 3])
+            .unwrap();
+        assert_eq!(
+            blobs.get_trie_offset_length(&db, block_id).unwrap(),
+            Some((16, 3))
+        );
+
+        // looked up once, and then remembered
+        trie_sql::set_external_trie_blob(&db, block_id, 100, 5).unwrap();
+        assert_eq!(
+            blobs.get_trie_offset_length(&db, block_id).unwrap(),
+            Some((16, 3))
+        );
+
+        blobs.clear_offsets();
+        assert_eq!(
+            blobs.get_trie_offset_length(&db, block_id).unwrap(),
+            Some((100, 5))
+        );
+
+        // tries in the database are not remembered
+        let internal_block_id = trie_sql::write_trie_blob(&db, &block_hash(1), &[4u8, 5]).unwrap();
+        assert_eq!(
+            blobs
+                .get_trie_offset_length(&db, internal_block_id)
+                .unwrap(),
+            None
+        );
+        assert_eq!(blobs.offsets.len(), 1);
+    }
+
+    #[test]
+    fn trie_file_recover_compaction() {
+        let path = "/tmp/rust_trie_file_recover_compaction";
+        setup_db(path);
+
+        let f = TrieFileStorage::open_external_blobs(path).unwrap();
+        let mut marf: MARF<BlockHeaderHash> = MARF::from_storage(f);
+        make_blocks(&mut marf, 0, 5);
+        drop(marf);
+
+        let blobs_path = TrieFile::blobs_path(path);
+        let compact_path = TrieFile::compact_path(&blobs_path);
+        let blobs_len = fs::metadata(&blobs_path).unwrap().len();
+
+        // interrupted before the database was updated: the new file is thrown away
+        fs::write(&compact_path, &[0u8; 4]).unwrap();
+        let f = TrieFileStorage::open(path).unwrap();
+        let mut marf: MARF<BlockHeaderHash> = MARF::from_storage(f);
+        assert!(fs::metadata(&compact_path).is_err());
+        assert_eq!(fs::metadata(&blobs_path).unwrap().len(), blobs_len);
+        check_blocks(&mut marf, 5);
+        drop(marf);
+
+        // interrupted after the database was updated: the new file replaces the old one
+        let mut db = Connection::open(path).unwrap();
+        fs::copy(&blobs_path, &compact_path).unwrap();
+        fs::write(&blobs_path, &[0u8; 4]).unwrap();
+        trie_sql::set_blobs_compaction_pending(&db).unwrap();
+
+        // ...but only once the MARF is opened read-write
+        assert!(TrieFileStorage::<BlockHeaderHash>::open_readonly(path).is_err());
+
+        let f = TrieFileStorage::open(path).unwrap();
+        let mut marf: MARF<BlockHeaderHash> = MARF::from_storage(f);
+        assert!(fs::metadata(&compact_path).is_err());
+        assert_eq!(fs::metadata(&blobs_path).unwrap().len(), blobs_len);
+        assert!(!trie_sql::is_blobs_compaction_pending(&db).unwrap());
+        check_blocks(&mut marf, 5);
+
+        // compacting a file with nothing to remove changes nothing
+        let mut blobs = TrieFile::open(path, false).unwrap();
+        assert_eq!(blobs.compact(&mut db).unwrap(), 0);
+        assert_eq!(fs::metadata(&blobs_path).unwrap().len(), blobs_len);
+        check_blocks(&mut marf, 5);
+    }
+
+    #[test]
+    fn trie_file_storage_external_blobs() {
+        let path = "/tmp/rust_trie_file_storage_external_blobs";
+        setup_db(path);
+
+        let f = TrieFileStorage::open_external_blobs(path).unwrap();
+        let mut marf: MARF<BlockHeaderHash> = MARF::from_storage(f);
+        make_blocks(&mut marf, 0, 10);
+        check_blocks(&mut marf, 10);
+
+        // everything is in the blobs file
+        assert!(fs::metadata(TrieFile::blobs_path(path)).unwrap().len() > 0);
+        assert_eq!(
+            trie_sql::get_internal_confirmed_block_ids(marf.sqlite_conn())
+                .unwrap()
+                .len(),
+            0
+        );
+
+        // read-only views use it too
+        let mut ro_marf = marf.reopen_readonly().unwrap();
+        check_blocks(&mut ro_marf, 10);
+
+        // as do reopened MARFs
+        let f = TrieFileStorage::open(path).unwrap();
+        let mut marf: MARF<BlockHeaderHash> = MARF::from_storage(f);
+        check_blocks(&mut marf, 10);
+    }
+
+    #[test]
+    fn trie_file_migrate_blobs() {
+        let path = "/tmp/rust_trie_file_migrate_blobs";
+        setup_db(path);
+
+        let f = TrieFileStorage::open(path).unwrap();
+        let mut marf: MARF<BlockHeaderHash> = MARF::from_storage(f);
+        make_blocks(&mut marf, 0, 10);
+        let root_hash = marf.get_root_hash_at(&block_hash(9)).unwrap();
+        drop(marf);
+
+        assert!(!TrieFile::exists(path));
+        assert_eq!(TrieFile::migrate_blobs(path).unwrap(), 10);
+        assert!(TrieFile::exists(path));
+
+        // nothing left to move
+        assert_eq!(TrieFile::migrate_blobs(path).unwrap(), 0);
+
+        // migrated tries are readable, and new tries go to the blobs file too
+        let f = TrieFileStorage::open(path).unwrap();
+        let mut marf: MARF<BlockHeaderHash> = MARF::from_storage(f);
+        assert_eq!(marf.get_root_hash_at(&block_hash(9)).unwrap(), root_hash);
+        check_blocks(&mut marf, 10);
+
+        make_blocks(&mut marf, 10, 15);
+        check_blocks(&mut marf, 15);
+        assert_eq!(
+            trie_sql::get_internal_confirmed_block_ids(marf.sqlite_conn())
+                .unwrap()
+                .len(),
+            0
+        );
+    }
+}
@@ -0,0 +1,579 @@
+// Copyright (C) 2013-2020 Blockstack PBC, a public benefit corporation
+// Copyright (C) 2020 Stacks Open Internet Foundation
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <http://www.gnu.org/licenses/>.
+
+//! Garbage collection of orphaned tries.
+//!
+//! Every trie ever written to a MARF stays in its `marf_data` table, including tries for blocks
+//! that were orphaned by a fork, and miners write their candidate tries to `mined_blocks`, where
+//! nothing ever reads them again.  Garbage collection removes every trie that is neither on the
+//! canonical fork nor needed by a fork whose tip is within `keep_depth` blocks of the canonical
+//! tip's height, and drops all mined tries.
+//!
+//! Back-pointers refer to other tries by their local block ID (the `marf_data` rowid), and a
+//! retained trie only ever points back to its own ancestors, which are retained too.  Removal
+//! therefore leaves the local IDs of the retained tries untouched, so that none of their
+//! back-pointers need to be rewritten.  Before the removal is committed, the root hash of every
+//! retained trie is checked against its value beforehand, and every retained trie's parent is
+//! resolved through the trie's own back-pointers.
+//!
+//! If the MARF keeps its tries in an external blobs file (see `file`), the removed tries' rows are
+//! deleted, and their space in the blobs file is reclaimed by compacting it along with the
+//! vacuum.
+//!
+//! A MARF can also be rolled back to a given block, which removes every trie above that block's
+//! height so that the blocks they belong to can be processed again.
+//!
+//! This is meant to be run offline: nothing else may be writing to the MARF while it runs.
+
+use std::collections::HashSet;
+
+use chainstate::stacks::index::marf::{MarfConnection, MARF};
+use chainstate::stacks::index::{trie_sql, Error, MarfTrieId, TrieHash};
+
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct MARFGCStats {
+    /// tries on the canonical fork
+    pub canonical_tries: u64,
+    /// tries off of the canonical fork that were kept because they are (ancestors of) recent forks
+    pub retained_orphan_tries: u64,
+    /// tries that were removed
+    pub removed_tries: u64,
+    /// miner tries that were removed
+    pub removed_mined_tries: u64,
+    /// bytes reclaimed from the external blobs file
+    pub reclaimed_blob_bytes: u64,
+}
+
+impl<T: MarfTrieId> MARF<T> {
+    /// Remove all tries that are not ancestors of either `canonical_tip` or a fork tip whose height
+    /// is no less than `keep_depth` below `canonical_tip`'s height, and remove all mined tries.
+    /// Unconfirmed tries are left alone.
+    /// Removal happens in a single transaction, which is rolled back if verification fails.
+    /// If `vacuum` is true, the underlying database is vacuumed and the external blobs file (if
+    /// any) is compacted afterwards to give the space back to the filesystem.
+    pub fn gc(
+        &mut self,
+        canonical_tip: &T,
+        keep_depth: u32,
+        vacuum: bool,
+    ) -> Result<MARFGCStats, Error> {
+        if self.get_open_chain_tip().is_some() {
+            error!("Cannot garbage-collect a MARF that is in the process of writing");
+            return Err(Error::InProgressError);
+        }
+
+        let mut stats = MARFGCStats::default();
+        let mut tx = self.begin_tx()?;
+
+        let tip_height = tx
+            .get_block_height(canonical_tip, canonical_tip)?
+            .ok_or(Error::NotFoundError)?;
+        let horizon = tip_height.saturating_sub(keep_depth);
+
+        // the currently-open block may be removed, so work from the canonical tip instead
+        tx.with_conn(|c| c.open_block(canonical_tip))?;
+
+        // everything on the canonical fork stays
+        let mut keep: HashSet<u32> = HashSet::new();
+        for height in 0..(tip_height + 1) {
+            let block_hash = tx
+                .get_block_at_height(height, canonical_tip)?
+                .ok_or_else(|| {
+                    Error::CorruptionError(format!(
+                        "No block at height {} in the fork of {}",
+                        height, canonical_tip
+                    ))
+                })?;
+            let block_id = trie_sql::get_block_identifier(tx.sqlite_tx(), &block_hash)?;
+            keep.insert(block_id);
+        }
+        stats.canonical_tries = keep.len() as u64;
+
+        // recent forks stay, as do all of their ancestors
+        let all_tries: Vec<(u32, T)> =
+            trie_sql::get_confirmed_block_ids_and_hashes(tx.sqlite_tx())?;
+        let mut heights = vec![];
+        for (block_id, block_hash) in all_tries.iter() {
+            let height = tx
+                .get_block_height(block_hash, block_hash)?
+                .ok_or_else(|| {
+                    Error::CorruptionError(format!("No block height for {}", block_hash))
+                })?;
+            heights.push(height);
+
+            if height < horizon || keep.contains(block_id) {
+                continue;
+            }
+
+            keep.insert(*block_id);
+            for ancestor_height in (0..height).rev() {
+                let ancestor_hash = tx
+                    .get_block_at_height(ancestor_height, block_hash)?
+                    .ok_or_else(|| {
+                        Error::CorruptionError(format!(
+                            "No block at height {} in the fork of {}",
+                            ancestor_height, block_hash
+                        ))
+                    })?;
+                let ancestor_id = trie_sql::get_block_identifier(tx.sqlite_tx(), &ancestor_hash)?;
+                if !keep.insert(ancestor_id) {
+                    // this ancestor's ancestors are already kept
+                    break;
+                }
+            }
+        }
+        stats.retained_orphan_tries = (keep.len() as u64) - stats.canonical_tries;
+
+        let mut retained: Vec<(T, u32, TrieHash)> = vec![];
+        for ((block_id, block_hash), height) in all_tries.iter().zip(heights.into_iter()) {
+            if keep.contains(block_id) {
+                let root_hash = tx.get_root_hash_at(block_hash)?;
+                retained.push((block_hash.clone(), height, root_hash));
+            }
+        }
+
+        for (block_id, block_hash) in all_tries.iter() {
+            if !keep.contains(block_id) {
+                debug!("Remove orphaned trie {} (rowid {})", block_hash, block_id);
+                trie_sql::delete_trie_blob(tx.sqlite_tx(), *block_id)?;
+                stats.removed_tries += 1;
+            }
+        }
+        stats.removed_mined_tries = trie_sql::clear_mined_blocks(tx.sqlite_tx())?;
+
+        // verify: retained tries are intact, and can still reach their parents
+        for (block_hash, height, root_hash) in retained.iter() {
+            let new_root_hash = tx.get_root_hash_at(block_hash)?;
+            if new_root_hash != *root_hash {
+                return Err(Error::CorruptionError(format!(
+                    "Root hash of {} changed from {} to {} during garbage collection",
+                    block_hash, root_hash, &new_root_hash
+                )));
+            }
+
+            if *height > 0 {
+                let parent_hash = tx
+                    .get_block_at_height(*height - 1, block_hash)?
+                    .ok_or_else(|| {
+                        Error::CorruptionError(format!(
+                            "Parent of {} is unreachable after garbage collection",
+                            block_hash
+                        ))
+                    })?;
+                trie_sql::get_block_identifier(tx.sqlite_tx(), &parent_hash)?;
+            }
+        }
+
+        tx.commit()?;
+
+        // removed tries' local IDs can be reused
+        self.clear_node_cache();
+
+        info!(
+            "Garbage-collected MARF at canonical tip {} (height {}): kept {} canonical and {} other tries, removed {} tries and {} mined tries",
+            canonical_tip,
+            tip_height,
+            stats.canonical_tries,
+            stats.retained_orphan_tries,
+            stats.removed_tries,
+            stats.removed_mined_tries
+        );
+
+        if vacuum {
+            self.sqlite_conn().execute_batch("VACUUM")?;
+            stats.reclaimed_blob_bytes = self.compact_blobs()?;
+        }
+
+        Ok(stats)
+    }
+
+    /// Remove every trie that is higher than `last_good` in any fork, as well as all unconfirmed
+    /// and mined tries, so that the blocks they were written for can be processed again.
+    /// A trie that can't be read is removed only if it was written after `last_good` (and so
+    /// can't be one of its ancestors).
+    /// Removal happens in a single transaction, which is rolled back if `last_good` does not
+    /// survive intact.
+    /// Returns the block hashes of the removed confirmed tries.
+    pub fn rollback(&mut self, last_good: &T) -> Result<Vec<T>, Error> {
+        if self.get_open_chain_tip().is_some() {
+            error!("Cannot roll back a MARF that is in the process of writing");
+            return Err(Error::InProgressError);
+        }
+
+        let mut tx = self.begin_tx()?;
+
+        let good_height = tx
+            .get_block_height(last_good, last_good)?
+            .ok_or(Error::NotFoundError)?;
+        let good_block_id = trie_sql::get_block_identifier(tx.sqlite_tx(), last_good)?;
+
+        // the currently-open block may be removed, so work from the last good block instead
+        tx.with_conn(|c| c.open_block(last_good))?;
+        let good_root_hash = tx.get_root_hash_at(last_good)?;
+
+        let all_tries: Vec<(u32, T)> =
+            trie_sql::get_confirmed_block_ids_and_hashes(tx.sqlite_tx())?;
+        let mut removed = vec![];
+        for (block_id, block_hash) in all_tries.into_iter() {
+            let remove = match tx.get_block_height(&block_hash, &block_hash) {
+                Ok(Some(height)) => height > good_height,
+                Ok(None) | Err(_) => {
+                    if block_id <= good_block_id {
+                        return Err(Error::CorruptionError(format!(
+                            "Cannot read the height of {}, which may be an ancestor of {}",
+                            &block_hash, last_good
+                        )));
+                    }
+                    warn!(
+                        "Cannot read the height of {} (rowid {}); removing it",
+                        &block_hash, block_id
+                    );
+                    true
+                }
+            };
+
+            if remove {
+                debug!("Roll back trie {} (rowid {})", &block_hash, block_id);
+                trie_sql::delete_trie_blob(tx.sqlite_tx(), block_id)?;
+                removed.push(block_hash);
+            }
+        }
+        let removed_unconfirmed = trie_sql::clear_unconfirmed_tries(tx.sqlite_tx())?;
+        let removed_mined = trie_sql::clear_mined_blocks(tx.sqlite_tx())?;
+
+        // verify: the last good trie is intact, and can still reach its parent
+        let new_root_hash = tx.get_root_hash_at(last_good)?;
+        if new_root_hash != good_root_hash {
+            return Err(Error::CorruptionError(format!(
+                "Root hash of {} changed from {} to {} during rollback",
+                last_good, &good_root_hash, &new_root_hash
+            )));
+        }
+        if good_height > 0 {
+            tx.get_block_at_height(good_height - 1, last_good)?
+                .ok_or_else(|| {
+                    Error::CorruptionError(format!(
+                        "Parent of {} is unreachable after rollback",
+                        last_good
+                    ))
+                })?;
+        }
+
+        tx.commit()?;
+
+        // removed tries' local IDs can be reused
+        self.clear_node_cache();
+
+        info!(
+            "Rolled back MARF to {} (height {}): removed {} tries, {} unconfirmed tries, and {} mined tries",
+            last_good,
+            good_height,
+            removed.len(),
+            removed_unconfirmed,
+            removed_mined
+        );
+
+        Ok(removed)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use std::fs;
+
+    use chainstate::burn::BlockHeaderHash;
+    use chainstate::stacks::index::file::TrieFile;
+    use chainstate::stacks::index::storage::TrieFileStorage;
+    use chainstate::stacks::index::MARFValue;
+
+    fn block_hash(fork: u8, height: u32) -> BlockHeaderHash {
+        let mut bytes = [0u8; 32];
+        bytes[0] = fork;
+        bytes[1..5].copy_from_slice(&height.to_be_bytes());
+        BlockHeaderHash(bytes)
+    }
+
+    /// Extend the MARF with a fork of blocks at heights (start, end], each of which writes one
+    /// key.  Returns the fork's tip.
+    fn make_fork(
+        marf: &mut MARF<BlockHeaderHash>,
+        parent: &BlockHeaderHash,
+        fork: u8,
+        start: u32,
+        end: u32,
+    ) -> BlockHeaderHash {
+        let mut parent = parent.clone();
+        for height in (start + 1)..(end + 1) {
+            let next = block_hash(fork, height);
+            marf.begin(&parent, &next).unwrap();
+            marf.insert(
+                &format!("key-{}-{}", fork, height),
+                MARFValue::from_value(&format!("value-{}-{}", fork, height)),
+            )
+            .unwrap();
+            marf.commit().unwrap();
+            parent = next;
+        }
+        parent
+    }
+
+    #[test]
+    fn marf_gc_orphaned_forks() {
+        let f = TrieFileStorage::new_memory().unwrap();
+        let mut marf: MARF<BlockHeaderHash> = MARF::from_storage(f);
+
+        // genesis, then the canonical fork a up to height 10
+        let genesis = block_hash(0, 0);
+        marf.begin(&BlockHeaderHash::sentinel(), &genesis).unwrap();
+        marf.insert("key-0-0", MARFValue::from_value("value-0-0"))
+            .unwrap();
+        marf.commit().unwrap();
+
+        let canonical_tip = make_fork(&mut marf, &genesis, 1, 0, 10);
+
+        // fork b off of a at height 2, abandoned at height 4
+        let b_tip = make_fork(&mut marf, &block_hash(1, 2), 2, 2, 4);
+
+        // fork c off of a at height 8, abandoned at height 9
+        let c_tip = make_fork(&mut marf, &block_hash(1, 8), 3, 8, 9);
+
+        // fork d off of a at height 3, still going at height 10
+        let d_tip = make_fork(&mut marf, &block_hash(1, 3), 4, 3, 10);
+
+        // a mined block
+        let mined = block_hash(5, 11);
+        marf.begin(&canonical_tip, &mined).unwrap();
+        marf.insert("key-5-11", MARFValue::from_value("value-5-11"))
+            .unwrap();
+        marf.commit_mined(&mined).unwrap();
+
+        // the mined trie isn't a block, so it can't stay open
+        marf.open_block(&canonical_tip).unwrap();
+
+        let canonical_root_hash = marf.get_root_hash_at(&canonical_tip).unwrap();
+        let d_root_hash = marf.get_root_hash_at(&d_tip).unwrap();
+
+        // can't gc from an unknown tip
+        assert!(marf.gc(&block_hash(9, 9), 3, false).is_err());
+
+        let stats = marf.gc(&canonical_tip, 3, true).unwrap();
+        assert_eq!(
+            stats,
+            MARFGCStats {
+                canonical_tries: 11,
+                retained_orphan_tries: 8,
+                removed_tries: 2,
+                removed_mined_tries: 1,
+                reclaimed_blob_bytes: 0,
+            }
+        );
+
+        // fork b is gone
+        assert!(marf.get_root_hash_at(&b_tip).is_err());
+        assert!(marf.get_root_hash_at(&block_hash(2, 3)).is_err());
+
+        // the canonical fork and forks c and d are intact
+        assert_eq!(
+            marf.get_root_hash_at(&canonical_tip).unwrap(),
+            canonical_root_hash
+        );
+        assert_eq!(marf.get_root_hash_at(&d_tip).unwrap(), d_root_hash);
+        assert!(marf.get_root_hash_at(&c_tip).is_ok());
+
+        for height in 0..11 {
+            let fork = if height == 0 { 0 } else { 1 };
+            assert_eq!(
+                marf.get(&canonical_tip, &format!("key-{}-{}", fork, height))
+                    .unwrap(),
+                Some(MARFValue::from_value(&format!("value-{}-{}", fork, height)))
+            );
+        }
+        for height in 4..11 {
+            assert_eq!(
+                marf.get(&d_tip, &format!("key-4-{}", height)).unwrap(),
+                Some(MARFValue::from_value(&format!("value-4-{}", height)))
+            );
+        }
+        assert_eq!(
+            marf.get(&d_tip, "key-1-3").unwrap(),
+            Some(MARFValue::from_value("value-1-3"))
+        );
+        assert_eq!(marf.get(&d_tip, "key-1-4").unwrap(), None);
+
+        // running it again removes nothing
+        let stats = marf.gc(&canonical_tip, 3, false).unwrap();
+        assert_eq!(stats.removed_tries, 0);
+        assert_eq!(stats.removed_mined_tries, 0);
+
+        // a shallower horizon removes fork c, but not fork d, which is as long as the canonical
+        // fork
+        let stats = marf.gc(&canonical_tip, 0, false).unwrap();
+        assert_eq!(stats.retained_orphan_tries, 7);
+        assert_eq!(stats.removed_tries, 1);
+        assert!(marf.get_root_hash_at(&c_tip).is_err());
+        assert_eq!(marf.get_root_hash_at(&d_tip).unwrap(), d_root_hash);
+        assert_eq!(
+            marf.get_root_hash_at(&canonical_tip).unwrap(),
+            canonical_root_hash
+        );
+    }
+
+    #[test]
+    fn marf_gc_compacts_external_blobs() {
+        let path = "/tmp/rust_marf_gc_compacts_external_blobs";
+        for p in [path.to_string(), TrieFile::blobs_path(path)].iter() {
+            if fs::metadata(p).is_ok() {
+                fs::remove_file(p).unwrap();
+            }
+        }
+
+        let f = TrieFileStorage::open_external_blobs(path).unwrap();
+        let mut marf: MARF<BlockHeaderHash> = MARF::from_storage(f);
+
+        let genesis = block_hash(0, 0);
+        marf.begin(&BlockHeaderHash::sentinel(), &genesis).unwrap();
+        marf.insert("key-0-0", MARFValue::from_value("value-0-0"))
+            .unwrap();
+        marf.commit().unwrap();
+
+        // fork b off of genesis, abandoned at height 5, and then the canonical fork a up to
+        // height 10, so that the removed tries come before the kept ones in the blobs file
+        let b_tip = make_fork(&mut marf, &genesis, 2, 0, 5);
+        let canonical_tip = make_fork(&mut marf, &genesis, 1, 0, 10);
+        let canonical_root_hash = marf.get_root_hash_at(&canonical_tip).unwrap();
+
+        let old_len = fs::metadata(TrieFile::blobs_path(path)).unwrap().len();
+
+        // without a vacuum, the blobs file is left alone
+        let stats = marf.gc(&canonical_tip, 0, false).unwrap();
+        assert_eq!(stats.removed_tries, 5);
+        assert_eq!(stats.reclaimed_blob_bytes, 0);
+        assert_eq!(
+            fs::metadata(TrieFile::blobs_path(path)).unwrap().len(),
+            old_len
+        );
+
+        let stats = marf.gc(&canonical_tip, 0, true).unwrap();
+        assert_eq!(stats.removed_tries, 0);
+        assert!(stats.reclaimed_blob_bytes > 0);
+        assert_eq!(
+            fs::metadata(TrieFile::blobs_path(path)).unwrap().len(),
+            old_len - stats.reclaimed_blob_bytes
+        );
+        assert!(fs::metadata(format!("{}.compact", TrieFile::blobs_path(path))).is_err());
+
+        assert!(marf.get_root_hash_at(&b_tip).is_err());
+        assert_eq!(
+            marf.get_root_hash_at(&canonical_tip).unwrap(),
+            canonical_root_hash
+        );
+        for height in 1..11 {
+            assert_eq!(
+                marf.get(&canonical_tip, &format!("key-1-{}", height))
+                    .unwrap(),
+                Some(MARFValue::from_value(&format!("value-1-{}", height)))
+            );
+        }
+
+        // new tries go after the compacted ones, and everything survives a reopen
+        let new_tip = make_fork(&mut marf, &canonical_tip, 1, 10, 12);
+        drop(marf);
+
+        let f = TrieFileStorage::open(path).unwrap();
+        let mut marf: MARF<BlockHeaderHash> = MARF::from_storage(f);
+        assert_eq!(
+            marf.get_root_hash_at(&canonical_tip).unwrap(),
+            canonical_root_hash
+        );
+        assert_eq!(
+            marf.get(&new_tip, "key-1-12").unwrap(),
+            Some(MARFValue::from_value("value-1-12"))
+        );
+        assert_eq!(
+            marf.get(&new_tip, "key-0-0").unwrap(),
+            Some(MARFValue::from_value("value-0-0"))
+        );
+    }
+
+    #[test]
+    fn marf_rollback() {
+        let f = TrieFileStorage::new_memory().unwrap();
+        let mut marf: MARF<BlockHeaderHash> = MARF::from_storage(f);
+
+        let genesis = block_hash(0, 0);
+        marf.begin(&BlockHeaderHash::sentinel(), &genesis).unwrap();
+        marf.insert("key-0-0", MARFValue::from_value("value-0-0"))
+            .unwrap();
+        marf.commit().unwrap();
+
+        // canonical fork a up to height 6, and fork b off of a at height 2 up to height 5
+        let canonical_tip = make_fork(&mut marf, &genesis, 1, 0, 6);
+        let b_tip = make_fork(&mut marf, &block_hash(1, 2), 2, 2, 5);
+
+        // a mined block
+        let mined = block_hash(5, 7);
+        marf.begin(&canonical_tip, &mined).unwrap();
+        marf.insert("key-5-7", MARFValue::from_value("value-5-7"))
+            .unwrap();
+        marf.commit_mined(&mined).unwrap();
+
+        // the mined trie isn't a block, so it can't stay open
+        marf.open_block(&canonical_tip).unwrap();
+
+        let last_good = block_hash(1, 3);
+        let last_good_root_hash = marf.get_root_hash_at(&last_good).unwrap();
+
+        // can't roll back to an unknown block
+        assert!(marf.rollback(&block_hash(9, 9)).is_err());
+
+        let mut removed = marf.rollback(&last_good).unwrap();
+        removed.sort();
+        let mut expected = vec![
+            block_hash(1, 4),
+            block_hash(1, 5),
+            block_hash(1, 6),
+            block_hash(2, 4),
+            block_hash(2, 5),
+        ];
+        expected.sort();
+        assert_eq!(removed, expected);
+
+        assert_eq!(
+            marf.get_root_hash_at(&last_good).unwrap(),
+            last_good_root_hash
+        );
+        assert!(marf.get_root_hash_at(&canonical_tip).is_err());
+        assert!(marf.get_root_hash_at(&b_tip).is_err());
+        assert!(marf.get_root_hash_at(&block_hash(2, 3)).is_ok());
+        assert_eq!(
+            marf.get(&last_good, "key-1-1").unwrap(),
+            Some(MARFValue::from_value("value-1-1"))
+        );
+
+        // the removed blocks can be processed again
+        let new_tip = make_fork(&mut marf, &last_good, 1, 3, 6);
+        assert_eq!(new_tip, canonical_tip);
+        assert_eq!(
+            marf.get(&canonical_tip, "key-1-6").unwrap(),
+            Some(MARFValue::from_value("value-1-6"))
+        );
+
+        // rolling back to the tip removes nothing
+        assert_eq!(marf.rollback(&canonical_tip).unwrap().len(), 0);
+    }
+}
@@ -40,7 +40,8 @@ use chainstate::stacks::index::storage::{
 };
 
 use chainstate::stacks::index::{
-    proofs::TrieMerkleProof, MARFValue, MarfTrieId, TrieHash, TRIEHASH_ENCODED_SIZE,
+    proofs::{TrieMerkleMultiProof, TrieMerkleProof},
+    MARFValue, MarfTrieId, TrieHash, TRIEHASH_ENCODED_SIZE,
 };
 
 use chainstate::stacks::index::trie::Trie;
@@ -103,6 +104,24 @@ pub trait MarfConnection<T: MarfTrieId> {
         })
     }
 
+    /// Resolve several keys at once, along with a single proof for all of them.
+    fn get_with_multi_proof(
+        &mut self,
+        block_hash: &T,
+        keys: &[&str],
+    ) -> Result<(Vec<Option<MARFValue>>, TrieMerkleMultiProof<T>), Error> {
+        self.with_conn(|conn| {
+            let mut entries = Vec::with_capacity(keys.len());
+            for key in keys.iter() {
+                let marf_value_opt = MARF::get_by_key(conn, block_hash, key)?;
+                entries.push((*key, marf_value_opt));
+            }
+            let proof = TrieMerkleMultiProof::from_raw_entries(conn, &entries, block_hash)?;
+            let values = entries.into_iter().map(|(_, value)| value).collect();
+            Ok((values, proof))
+        })
+    }
+
     fn get_block_at_height(&mut self, height: u32, tip: &T) -> Result<Option<T>, Error> {
         self.with_conn(|c| MARF::get_block_at_height(c, height, tip))
     }
@@ -1400,6 +1419,27 @@ impl<T: MarfTrieId> MARF<T> {
         self.open_chain_tip.as_ref().map(|x| x.height)
     }
 
+    /// Set the maximum size, in bytes, of the storage's node cache.  0 disables it.
+    pub fn set_node_cache_capacity(&mut self, capacity: usize) {
+        self.storage.connection().set_node_cache_capacity(capacity)
+    }
+
+    /// Get the (hits, misses) counts of the storage's node cache
+    pub fn node_cache_stats(&mut self) -> (u64, u64) {
+        self.storage.connection().node_cache_stats()
+    }
+
+    /// Drop everything in the storage's node cache
+    pub fn clear_node_cache(&mut self) {
+        self.storage.connection().clear_node_cache()
+    }
+
+    /// Compact the storage's external blobs file, if it has one.  Returns the number of bytes
+    /// reclaimed.
+    pub fn compact_blobs(&mut self) -> Result<u64, Error> {
+        self.storage.compact_blobs()
+    }
+
     /// Access internal storage
     #[cfg(test)]
     pub fn borrow_storage_backend(&mut self) -> TrieStorageConnection<T> {
@@ -15,6 +15,8 @@
 // along with this program.  If not, see <http://www.gnu.org/licenses/>.
 
 pub mod bits;
+pub mod file;
+pub mod gc;
 pub mod marf;
 pub mod node;
 pub mod proofs;
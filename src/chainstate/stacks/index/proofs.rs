@@ -1596,6 +1596,172 @@ impl<T: MarfTrieId> TrieMerkleProof<T> {
     }
 }
 
+/// A proof of several key/value pairs at once, against the same trie root.  Each key's proof is
+/// an ordinary `TrieMerkleProof`, but proof elements that appear in more than one of them --
+/// nodes on a common path prefix, and the shunt proofs of common back-pointer segments -- are
+/// stored only once.
+/// There are no proofs of non-inclusion, so a key without a value has an empty proof.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TrieMerkleMultiProof<T: MarfTrieId> {
+    /// distinct proof elements, shared by the proofs
+    pub elements: Vec<TrieMerkleProofType<T>>,
+    /// each key's proof, as indexes into `elements`
+    pub proofs: Vec<Vec<u32>>,
+}
+
+impl<T: MarfTrieId> StacksMessageCodec for TrieMerkleMultiProof<T> {
+    fn consensus_serialize<W: Write>(&self, fd: &mut W) -> Result<(), ::net::Error> {
+        self.elements.consensus_serialize(fd)?;
+        self.proofs.consensus_serialize(fd)
+    }
+
+    fn consensus_deserialize<R: Read>(fd: &mut R) -> Result<TrieMerkleMultiProof<T>, ::net::Error> {
+        let elements: Vec<TrieMerkleProofType<T>> = read_next(fd)?;
+        let proofs: Vec<Vec<u32>> = read_next(fd)?;
+
+        for proof in proofs.iter() {
+            for idx in proof.iter() {
+                if (*idx as usize) >= elements.len() {
+                    return Err(::net::Error::DeserializeError(format!(
+                        "Invalid Trie Merkle multi-proof: no element {} (have {})",
+                        idx,
+                        elements.len()
+                    )));
+                }
+            }
+        }
+
+        Ok(TrieMerkleMultiProof { elements, proofs })
+    }
+}
+
+impl<T: MarfTrieId> TrieMerkleMultiProof<T> {
+    pub fn to_hex(&self) -> String {
+        let mut marf_proof = vec![];
+        self.consensus_serialize(&mut marf_proof)
+            .expect("Write error on memory buffer");
+        to_hex(&marf_proof)
+    }
+
+    /// Combine individual proofs into a multi-proof.  None stands for a key without a value.
+    pub fn from_proofs(proofs: &[Option<TrieMerkleProof<T>>]) -> TrieMerkleMultiProof<T> {
+        let mut elements = vec![];
+        let mut element_indexes: HashMap<Vec<u8>, u32> = HashMap::new();
+        let mut multi_proofs = Vec::with_capacity(proofs.len());
+
+        for proof_opt in proofs.iter() {
+            let mut indexes = vec![];
+            if let Some(proof) = proof_opt {
+                for element in proof.iter() {
+                    let mut element_bytes = vec![];
+                    element
+                        .consensus_serialize(&mut element_bytes)
+                        .expect("Write error on memory buffer");
+
+                    let idx = match element_indexes.get(&element_bytes) {
+                        Some(idx) => *idx,
+                        None => {
+                            let idx = elements.len() as u32;
+                            elements.push(element.clone());
+                            element_indexes.insert(element_bytes, idx);
+                            idx
+                        }
+                    };
+                    indexes.push(idx);
+                }
+            }
+            multi_proofs.push(indexes);
+        }
+
+        TrieMerkleMultiProof {
+            elements,
+            proofs: multi_proofs,
+        }
+    }
+
+    /// Make a multi-proof of inclusion of each of the given key/value pairs.  None stands for a
+    /// key without a value, which gets an empty proof.
+    /// If a key's value does not match, return an error (NotFoundError)
+    pub fn from_raw_entries(
+        storage: &mut TrieStorageConnection<T>,
+        entries: &[(&str, Option<MARFValue>)],
+        root_block_header: &T,
+    ) -> Result<TrieMerkleMultiProof<T>, Error> {
+        let mut proofs = Vec::with_capacity(entries.len());
+        for (key, value_opt) in entries.iter() {
+            let proof_opt = match value_opt {
+                Some(value) => Some(TrieMerkleProof::from_raw_entry(
+                    storage,
+                    key,
+                    value,
+                    root_block_header,
+                )?),
+                None => None,
+            };
+            proofs.push(proof_opt);
+        }
+        Ok(TrieMerkleMultiProof::from_proofs(&proofs))
+    }
+
+    /// Get the ith key's proof, if it is present and well-formed.
+    pub fn get_proof(&self, i: usize) -> Option<TrieMerkleProof<T>> {
+        let indexes = self.proofs.get(i)?;
+        if indexes.len() == 0 {
+            return None;
+        }
+
+        let mut proof = Vec::with_capacity(indexes.len());
+        for idx in indexes.iter() {
+            proof.push(self.elements.get(*idx as usize)?.clone());
+        }
+        Some(TrieMerkleProof(proof))
+    }
+
+    /// Verify this multi-proof.  There must be one entry per proof, in order.  A key with a
+    /// value must have a valid proof, and a key without a value must have an empty one (which
+    /// proves nothing about it).
+    pub fn verify(
+        &self,
+        entries: &[(TriePath, Option<MARFValue>)],
+        root_hash: &TrieHash,
+        root_to_block: &HashMap<TrieHash, T>,
+    ) -> bool {
+        if entries.len() != self.proofs.len() {
+            trace!(
+                "Invalid multi-proof -- have {} proofs for {} entries",
+                self.proofs.len(),
+                entries.len()
+            );
+            return false;
+        }
+
+        for (i, (path, value_opt)) in entries.iter().enumerate() {
+            match (value_opt, self.get_proof(i)) {
+                (Some(value), Some(proof)) => {
+                    if !proof.verify(path, value, root_hash, root_to_block) {
+                        trace!("Invalid multi-proof -- proof {} does not verify", i);
+                        return false;
+                    }
+                }
+                (None, None) => {
+                    if self.proofs[i].len() != 0 {
+                        trace!("Invalid multi-proof -- proof {} is malformed", i);
+                        return false;
+                    }
+                }
+                (_, _) => {
+                    trace!(
+                        "Invalid multi-proof -- proof {} is missing or unexpected",
+                        i
+                    );
+                    return false;
+                }
+            }
+        }
+        true
+    }
+}
+
 #[cfg(test)]
 mod test {
     use super::*;
@@ -1774,4 +1940,99 @@ mod test {
         println!("DEBUG: verify(old_v)");
         assert!(!proof_5.verify(&triepath_4, &marf_value_4, &root_hash_5, &root_to_block));
     }
+
+    #[test]
+    fn multi_proof_verify() {
+        let mut m = MARF::from_path(":memory:").unwrap();
+
+        let sentinel_block = BlockHeaderHash::sentinel();
+        let blocks: Vec<BlockHeaderHash> = (0..4).map(|i| BlockHeaderHash([i as u8; 32])).collect();
+
+        // each block writes a few keys, so proofs of later keys need back-pointers
+        let mut parent = sentinel_block;
+        for (i, block) in blocks.iter().enumerate() {
+            m.begin(&parent, block).unwrap();
+            for j in 0..8 {
+                m.insert(
+                    &format!("key-{}-{}", i, j),
+                    MARFValue::from_value(&format!("value-{}-{}", i, j)),
+                )
+                .unwrap();
+            }
+            m.commit().unwrap();
+            parent = block.clone();
+        }
+
+        let tip = blocks[3].clone();
+        let root_hash = m.get_root_hash_at(&tip).unwrap();
+        let root_to_block = m
+            .borrow_storage_backend()
+            .read_root_to_block_table()
+            .unwrap();
+
+        let keys = vec!["key-0-1", "key-0-2", "key-2-5", "key-3-7", "no-such-key"];
+        let (values, multi_proof) = m.get_with_multi_proof(&tip, &keys).unwrap();
+        assert_eq!(values.len(), keys.len());
+        assert_eq!(values[4], None);
+        assert_eq!(multi_proof.proofs[4].len(), 0);
+
+        let entries: Vec<(TriePath, Option<MARFValue>)> = keys
+            .iter()
+            .zip(values.iter())
+            .map(|(key, value)| (TriePath::from_key(key), value.clone()))
+            .collect();
+        assert!(multi_proof.verify(&entries, &root_hash, &root_to_block));
+
+        // each proof is the same as the single-key proof
+        for (i, key) in keys[0..4].iter().enumerate() {
+            let (value, proof) = m.get_with_proof(&tip, key).unwrap().unwrap();
+            assert_eq!(values[i], Some(value));
+            assert_eq!(multi_proof.get_proof(i).unwrap().0, proof.0);
+        }
+
+        // shared elements are stored once
+        let num_elements: usize = multi_proof.proofs.iter().map(|p| p.len()).sum();
+        assert!(multi_proof.elements.len() < num_elements);
+
+        // survives encoding
+        let mut bytes = vec![];
+        multi_proof.consensus_serialize(&mut bytes).unwrap();
+        let decoded: TrieMerkleMultiProof<BlockHeaderHash> = read_next(&mut &bytes[..]).unwrap();
+        assert_eq!(decoded, multi_proof);
+        assert!(decoded.verify(&entries, &root_hash, &root_to_block));
+
+        // a wrong value doesn't verify
+        let mut bad_entries = entries.clone();
+        bad_entries[2].1 = Some(MARFValue::from_value("value-2-6"));
+        assert!(!multi_proof.verify(&bad_entries, &root_hash, &root_to_block));
+
+        // a value can't go without a proof
+        let mut bad_entries = entries.clone();
+        bad_entries[4].1 = Some(MARFValue::from_value("value-2-6"));
+        assert!(!multi_proof.verify(&bad_entries, &root_hash, &root_to_block));
+
+        // there must be as many entries as there are proofs
+        assert!(!multi_proof.verify(&entries[0..4], &root_hash, &root_to_block));
+
+        // a proof can't be swapped for another
+        let mut bad_proof = multi_proof.clone();
+        bad_proof.proofs.swap(0, 2);
+        assert!(!bad_proof.verify(&entries, &root_hash, &root_to_block));
+
+        // a proof against an older root doesn't verify against the tip
+        let old_root_hash = m.get_root_hash_at(&blocks[2]).unwrap();
+        assert!(!multi_proof.verify(&entries, &old_root_hash, &root_to_block));
+
+        // indexes must refer to elements
+        let mut bad_proof = multi_proof.clone();
+        bad_proof.proofs[0].push(bad_proof.elements.len() as u32);
+        let mut bytes = vec![];
+        bad_proof.consensus_serialize(&mut bytes).unwrap();
+        assert!(
+            TrieMerkleMultiProof::<BlockHeaderHash>::consensus_deserialize(&mut &bytes[..])
+                .is_err()
+        );
+        assert!(bad_proof.get_proof(0).is_none());
+        assert!(!bad_proof.verify(&entries, &root_hash, &root_to_block));
+    }
 }
@@ -20,9 +20,11 @@ use std::io::{BufWriter, Cursor, Read, Seek, SeekFrom, Write};
 use std::{cmp, error};
 
 use std::char::from_digit;
-use std::collections::{HashMap, HashSet, VecDeque};
+use std::collections::{BTreeMap, HashMap, HashSet, VecDeque};
 use std::marker::PhantomData;
 use std::ops::{Deref, DerefMut};
+use std::sync::atomic::{AtomicUsize, Ordering as AtomicOrdering};
+use std::sync::{Arc, Mutex};
 
 use std::fs;
 use std::path::{Path, PathBuf};
@@ -37,6 +39,8 @@ use chainstate::burn::BLOCK_HEADER_HASH_ENCODED_SIZE;
 
 use chainstate::stacks::index::{trie_sql, BlockMap, MarfTrieId, TrieHash, TRIEHASH_ENCODED_SIZE};
 
+use chainstate::stacks::index::file::TrieFile;
+
 use chainstate::stacks::index::bits::{
     get_node_byte_len, get_node_hash, read_block_identifier, read_hash_bytes, read_node_hash_bytes,
     read_nodetype, read_root_hash, write_nodetype_bytes,
@@ -62,6 +66,8 @@ use util::db::tx_busy_handler;
 use util::db::Error as db_error;
 use util::log;
 
+use monitoring;
+
 pub fn ftell<F: Seek>(f: &mut F) -> Result<u64, Error> {
     f.seek(SeekFrom::Current(0)).map_err(Error::IOError)
 }
@@ -615,6 +621,7 @@ impl<T: MarfTrieId> NodeHashReader for TrieRAM<T> {
 
 pub struct TrieSqlCursor<'a> {
     db: &'a Connection,
+    blobs: Option<&'a mut TrieFile>,
     block_id: u32,
 }
 
@@ -625,7 +632,10 @@ pub struct TrieSqlHashMapCursor<'a, T: MarfTrieId> {
 
 impl NodeHashReader for TrieSqlCursor<'_> {
     fn read_node_hash_bytes<W: Write>(&mut self, ptr: &TriePtr, w: &mut W) -> Result<(), Error> {
-        trie_sql::read_node_hash_bytes(self.db, w, self.block_id, ptr)
+        match self.blobs {
+            Some(ref mut blobs) => blobs.read_node_hash_bytes(self.db, w, self.block_id, ptr),
+            None => trie_sql::read_node_hash_bytes(self.db, w, self.block_id, ptr),
+        }
     }
 }
 
@@ -675,6 +685,7 @@ pub struct TrieStorageTransaction<'a, T: MarfTrieId>(TrieStorageConnection<'a, T
 pub struct TrieStorageConnection<'a, T: MarfTrieId> {
     pub db_path: &'a str,
     db: SqliteConnection<'a>,
+    blobs: Option<&'a mut TrieFile>,
     data: &'a mut TrieStorageTransientData<T>,
 
     // used in testing in order to short-circuit block-height lookups
@@ -683,6 +694,168 @@ pub struct TrieStorageConnection<'a, T: MarfTrieId> {
     pub test_genesis_block: &'a mut Option<T>,
 }
 
+/// Default number of bytes of trie nodes held in a TrieFileStorage's node cache
+pub const DEFAULT_NODE_CACHE_CAPACITY: usize = 16 * 1024 * 1024;
+
+/// Node cache size, in bytes, given to each TrieFileStorage opened from now on
+static NODE_CACHE_CAPACITY: AtomicUsize = AtomicUsize::new(DEFAULT_NODE_CACHE_CAPACITY);
+
+/// Set the node cache size, in bytes, of each subsequently-opened TrieFileStorage.  Read-only
+/// views share their parent's cache, but every MARF opened from disk gets its own, so a node
+/// that opens N MARFs spends up to N times this much memory on node caches.  0 disables caching.
+pub fn set_node_cache_capacity(capacity: usize) {
+    NODE_CACHE_CAPACITY.store(capacity, AtomicOrdering::SeqCst);
+}
+
+pub fn get_node_cache_capacity() -> usize {
+    NODE_CACHE_CAPACITY.load(AtomicOrdering::SeqCst)
+}
+
+///
+///  TrieNodeCache is a size-bounded, least-recently-used cache of trie nodes read from the
+///   SQL storage, keyed by the local block ID of the trie and the node's pointer within it.
+///   Its capacity is measured in bytes, as the encoded size of each node plus its hash.
+///   Only nodes of confirmed tries are cached, since unconfirmed tries are rewritten in place.
+///   The cache is shared between a TrieFileStorage and all of its read-only views.
+///
+pub struct TrieNodeCache<T: MarfTrieId> {
+    capacity: usize,
+    /// total size of all cached nodes, in bytes
+    size: usize,
+    nodes: HashMap<(u32, u32), (TrieNodeType, TrieHash, u64)>,
+    /// tick of last use --> key, so the least-recently-used node is first
+    lru: BTreeMap<u64, (u32, u32)>,
+    tick: u64,
+    /// local block ID --> (block hash, is confirmed), so rowid reuse is detected
+    blocks: HashMap<u32, (T, bool)>,
+    hits: u64,
+    misses: u64,
+}
+
+impl<T: MarfTrieId> TrieNodeCache<T> {
+    pub fn new(capacity: usize) -> TrieNodeCache<T> {
+        TrieNodeCache {
+            capacity: capacity,
+            size: 0,
+            nodes: HashMap::new(),
+            lru: BTreeMap::new(),
+            tick: 0,
+            blocks: HashMap::new(),
+            hits: 0,
+            misses: 0,
+        }
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    pub fn len(&self) -> usize {
+        self.nodes.len()
+    }
+
+    /// Total size of the cached nodes, in bytes
+    pub fn size(&self) -> usize {
+        self.size
+    }
+
+    fn entry_size(node: &TrieNodeType) -> usize {
+        node.byte_len() + TRIEHASH_ENCODED_SIZE
+    }
+
+    /// Change the maximum size of the cache in bytes, evicting nodes as needed.
+    pub fn set_capacity(&mut self, capacity: usize) {
+        self.capacity = capacity;
+        self.evict();
+    }
+
+    /// Get the (hits, misses) counts of this cache
+    pub fn stats(&self) -> (u64, u64) {
+        (self.hits, self.misses)
+    }
+
+    /// Drop all cached nodes and block statuses
+    pub fn clear(&mut self) {
+        self.size = 0;
+        self.nodes.clear();
+        self.lru.clear();
+        self.blocks.clear();
+    }
+
+    /// Is the trie at `block_id` known to be the confirmed trie for `block_hash`?
+    /// Returns None if this cache doesn't know anything about `block_id` and `block_hash`.
+    fn is_confirmed(&mut self, block_id: u32, block_hash: &T) -> Option<bool> {
+        match self.blocks.get(&block_id) {
+            Some((cached_hash, confirmed)) => {
+                if cached_hash == block_hash {
+                    return Some(*confirmed);
+                }
+            }
+            None => {
+                return None;
+            }
+        }
+        // the local ID now refers to a different trie
+        self.clear();
+        None
+    }
+
+    fn set_confirmed(&mut self, block_id: u32, block_hash: &T, confirmed: bool) {
+        self.blocks
+            .insert(block_id, (block_hash.clone(), confirmed));
+    }
+
+    fn get(&mut self, block_id: u32, ptr: u32) -> Option<(TrieNodeType, TrieHash)> {
+        self.tick += 1;
+        let tick = self.tick;
+        match self.nodes.get_mut(&(block_id, ptr)) {
+            Some((node, hash, last_used)) => {
+                self.lru.remove(&*last_used);
+                self.lru.insert(tick, (block_id, ptr));
+                *last_used = tick;
+                self.hits += 1;
+                Some((node.clone(), hash.clone()))
+            }
+            None => {
+                self.misses += 1;
+                None
+            }
+        }
+    }
+
+    fn insert(&mut self, block_id: u32, ptr: u32, node: &TrieNodeType, hash: &TrieHash) {
+        let entry_size = TrieNodeCache::<T>::entry_size(node);
+        if entry_size > self.capacity {
+            return;
+        }
+        self.tick += 1;
+        self.size += entry_size;
+        if let Some((old_node, _, last_used)) = self
+            .nodes
+            .insert((block_id, ptr), (node.clone(), hash.clone(), self.tick))
+        {
+            self.size -= TrieNodeCache::<T>::entry_size(&old_node);
+            self.lru.remove(&last_used);
+        }
+        self.lru.insert(self.tick, (block_id, ptr));
+        self.evict();
+    }
+
+    fn evict(&mut self) {
+        while self.size > self.capacity {
+            let oldest = match self.lru.keys().next() {
+                Some(tick) => *tick,
+                None => break,
+            };
+            if let Some(key) = self.lru.remove(&oldest) {
+                if let Some((node, _, _)) = self.nodes.remove(&key) {
+                    self.size -= TrieNodeCache::<T>::entry_size(&node);
+                }
+            }
+        }
+    }
+}
+
 ///
 ///  TrieStorageTransientData holds all the data that _isn't_ committed
 ///   to the underlying SQL storage. Used internally to simplify
@@ -709,6 +882,8 @@ struct TrieStorageTransientData<T: MarfTrieId> {
 
     block_hash_cache: HashMap<u32, T>,
 
+    node_cache: Arc<Mutex<TrieNodeCache<T>>>,
+
     readonly: bool,
     unconfirmed: bool,
 }
@@ -720,6 +895,8 @@ pub struct TrieFileStorage<T: MarfTrieId> {
     pub db_path: String,
 
     db: Connection,
+    /// if present, confirmed tries are stored here instead of in `db`
+    blobs: Option<TrieFile>,
     data: TrieStorageTransientData<T>,
 
     // used in testing in order to short-circuit block-height lookups
@@ -733,6 +910,7 @@ impl<T: MarfTrieId> TrieFileStorage<T> {
         TrieStorageConnection {
             db: SqliteConnection::ConnRef(&self.db),
             db_path: &self.db_path,
+            blobs: self.blobs.as_mut(),
             data: &mut self.data,
 
             #[cfg(test)]
@@ -749,6 +927,7 @@ impl<T: MarfTrieId> TrieFileStorage<T> {
         Ok(TrieStorageTransaction(TrieStorageConnection {
             db: SqliteConnection::Tx(tx),
             db_path: &self.db_path,
+            blobs: self.blobs.as_mut(),
             data: &mut self.data,
 
             #[cfg(test)]
@@ -812,11 +991,35 @@ impl<T: MarfTrieId> TrieFileStorage<T> {
             trie_sql::create_tables_if_needed(&mut db)?;
         }
 
-        debug!("Opened TrieFileStorage {};", db_path);
+        let blobs = if TrieFile::exists(&db_path) {
+            if !readonly {
+                trie_sql::add_external_blob_columns_if_needed(&mut db)?;
+                TrieFile::recover_compaction(&db_path, &db)?;
+            } else if trie_sql::is_blobs_compaction_pending(&db)? {
+                error!(
+                    "Compaction of the blobs file of {} was interrupted; open it read-write to finish it",
+                    &db_path
+                );
+                return Err(Error::CorruptionError(format!(
+                    "Unfinished compaction of the blobs file of {}",
+                    &db_path
+                )));
+            }
+            Some(TrieFile::open(&db_path, readonly)?)
+        } else {
+            None
+        };
+
+        debug!(
+            "Opened TrieFileStorage {}; external blobs: {}",
+            db_path,
+            blobs.is_some()
+        );
 
         let ret = TrieFileStorage {
             db_path,
             db,
+            blobs,
 
             data: TrieStorageTransientData {
                 last_extended: None,
@@ -835,6 +1038,8 @@ impl<T: MarfTrieId> TrieFileStorage<T> {
                 trie_ancestor_hash_bytes_cache: None,
                 block_hash_cache: HashMap::new(),
 
+                node_cache: Arc::new(Mutex::new(TrieNodeCache::new(get_node_cache_capacity()))),
+
                 readonly: readonly,
                 unconfirmed: unconfirmed,
             },
@@ -865,6 +1070,37 @@ impl<T: MarfTrieId> TrieFileStorage<T> {
         TrieFileStorage::open_opts(db_path, false, true)
     }
 
+    /// Open the storage at db_path, such that confirmed tries are stored in an append-only
+    /// blobs file next to the database instead of in the database itself.  The blobs file is
+    /// created if it doesn't exist yet; once it does, every subsequent open uses it.
+    /// Tries already in the database stay there (see `TrieFile::migrate_blobs`).
+    pub fn open_external_blobs(db_path: &str) -> Result<TrieFileStorage<T>, Error> {
+        if db_path == ":memory:" {
+            return Err(Error::NotFoundError);
+        }
+        if !TrieFile::exists(db_path) {
+            TrieFile::open(db_path, false)?;
+        }
+        TrieFileStorage::open_opts(db_path, false, false)
+    }
+
+    /// Are confirmed tries stored in an external blobs file?
+    pub fn external_blobs(&self) -> bool {
+        self.blobs.is_some()
+    }
+
+    /// Compact the external blobs file, if there is one (see `TrieFile::compact`).  Returns the
+    /// number of bytes reclaimed.
+    pub fn compact_blobs(&mut self) -> Result<u64, Error> {
+        if self.readonly() {
+            return Err(Error::ReadOnlyError);
+        }
+        match self.blobs {
+            Some(ref mut blobs) => blobs.compact(&mut self.db),
+            None => Ok(0),
+        }
+    }
+
     pub fn readonly(&self) -> bool {
         self.data.readonly
     }
@@ -880,9 +1116,16 @@ impl<T: MarfTrieId> TrieFileStorage<T> {
         trace!("Make read-only view of TrieFileStorage: {}", &self.db_path);
 
         // TODO: borrow self.last_extended and self.block_hash_cache; don't copy them
+        let blobs = if self.blobs.is_some() {
+            Some(TrieFile::open(&self.db_path, true)?)
+        } else {
+            None
+        };
+
         let ret = TrieFileStorage {
             db_path: self.db_path.clone(),
             db: db,
+            blobs: blobs,
 
             data: TrieStorageTransientData {
                 last_extended: self.data.last_extended.clone(),
@@ -901,6 +1144,8 @@ impl<T: MarfTrieId> TrieFileStorage<T> {
                 trie_ancestor_hash_bytes_cache: None,
                 block_hash_cache: self.data.block_hash_cache.clone(),
 
+                node_cache: self.data.node_cache.clone(),
+
                 readonly: true,
                 unconfirmed: true,
             },
@@ -928,9 +1173,16 @@ impl<'a, T: MarfTrieId> TrieStorageTransaction<'a, T> {
         );
 
         // TODO: borrow self.last_extended and self.block_hash_cache; don't copy them
+        let blobs = if self.blobs.is_some() {
+            Some(TrieFile::open(&self.db_path, true)?)
+        } else {
+            None
+        };
+
         let ret = TrieFileStorage {
             db_path: self.db_path.to_string(),
             db: db,
+            blobs: blobs,
 
             data: TrieStorageTransientData {
                 last_extended: None,
@@ -949,6 +1201,8 @@ impl<'a, T: MarfTrieId> TrieStorageTransaction<'a, T> {
                 trie_ancestor_hash_bytes_cache: None,
                 block_hash_cache: HashMap::new(),
 
+                node_cache: self.data.node_cache.clone(),
+
                 readonly: true,
                 unconfirmed: true,
             },
@@ -987,7 +1241,7 @@ impl<'a, T: MarfTrieId> TrieStorageTransaction<'a, T> {
                     if self.data.unconfirmed {
                         return Err(Error::UnconfirmedError);
                     }
-                    trie_sql::write_trie_blob(&self.db, &bhh, &buffer)?
+                    self.write_confirmed_trie_blob(&bhh, &buffer)?
                 }
                 FlushOptions::NewHeader(real_bhh) => {
                     // If we opened a block with a given hash, but want to store it as a block with a *different*
@@ -1006,7 +1260,7 @@ impl<'a, T: MarfTrieId> TrieStorageTransaction<'a, T> {
                         // switch over state
                         self.data.cur_block = real_bhh.clone();
                     }
-                    trie_sql::write_trie_blob(&self.db, real_bhh, &buffer)?
+                    self.write_confirmed_trie_blob(real_bhh, &buffer)?
                 }
                 FlushOptions::MinedTable(real_bhh) => {
                     if self.data.unconfirmed {
@@ -1030,6 +1284,14 @@ impl<'a, T: MarfTrieId> TrieStorageTransaction<'a, T> {
         Ok(())
     }
 
+    /// Store a confirmed trie, either in the blobs file or in the database
+    fn write_confirmed_trie_blob(&mut self, bhh: &T, buffer: &[u8]) -> Result<u32, Error> {
+        match self.0.blobs {
+            Some(ref mut blobs) => blobs.append_trie_blob(&self.0.db, bhh, buffer),
+            None => trie_sql::write_trie_blob(&self.0.db, bhh, buffer),
+        }
+    }
+
     pub fn flush(&mut self) -> Result<(), Error> {
         if self.data.unconfirmed {
             self.inner_flush(FlushOptions::UnconfirmedTable)
@@ -1168,6 +1430,7 @@ impl<'a, T: MarfTrieId> TrieStorageTransaction<'a, T> {
         self.data.cur_block_id = None;
         self.data.last_extended = None;
         self.clear_cached_ancestor_hashes_bytes();
+        self.clear_node_cache();
 
         Ok(())
     }
@@ -1534,6 +1797,10 @@ impl<'a, T: MarfTrieId> TrieStorageConnection<'a, T> {
         // otherwise, the current block is open as an FD
         let mut cursor = TrieSqlCursor {
             db: &self.db,
+            blobs: match self.blobs {
+                Some(ref mut blobs) => Some(&mut **blobs),
+                None => None,
+            },
             block_id: self.data.cur_block_id.ok_or_else(|| {
                 error!("Failed to get cur block as hash reader");
                 Error::NotFoundError
@@ -1579,7 +1846,10 @@ impl<'a, T: MarfTrieId> TrieStorageConnection<'a, T> {
 
         // some other block or ptr, or cache miss
         match self.data.cur_block_id {
-            Some(block_id) => trie_sql::get_node_hash_bytes(&self.db, block_id, ptr),
+            Some(block_id) => match self.blobs {
+                Some(ref mut blobs) => blobs.get_node_hash_bytes(&self.db, block_id, ptr),
+                None => trie_sql::get_node_hash_bytes(&self.db, block_id, ptr),
+            },
             None => {
                 error!("Not found (no file is open)");
                 Err(Error::NotFoundError)
@@ -1611,7 +1881,7 @@ impl<'a, T: MarfTrieId> TrieStorageConnection<'a, T> {
 
         // some other block
         match self.data.cur_block_id {
-            Some(id) => trie_sql::read_node_type(&self.db, id, &clear_ptr),
+            Some(id) => self.read_nodetype_cached(id, &clear_ptr),
             None => {
                 error!("Not found (no file is open)");
                 Err(Error::NotFoundError)
@@ -1619,6 +1889,86 @@ impl<'a, T: MarfTrieId> TrieStorageConnection<'a, T> {
         }
     }
 
+    /// Read a node from the trie at local ID `block_id` (which must be `cur_block`), going
+    /// through the node cache.
+    fn read_nodetype_cached(
+        &mut self,
+        block_id: u32,
+        ptr: &TriePtr,
+    ) -> Result<(TrieNodeType, TrieHash), Error> {
+        let mut cache = self
+            .data
+            .node_cache
+            .lock()
+            .expect("FATAL: MARF node cache lock is poisoned");
+
+        let cacheable = if cache.capacity() == 0 {
+            false
+        } else {
+            match cache.is_confirmed(block_id, &self.data.cur_block) {
+                Some(confirmed) => confirmed,
+                None => {
+                    let confirmed = trie_sql::is_confirmed_block_identifier(
+                        &self.db,
+                        block_id,
+                        &self.data.cur_block,
+                    )?;
+                    cache.set_confirmed(block_id, &self.data.cur_block, confirmed);
+                    confirmed
+                }
+            }
+        };
+
+        if cacheable {
+            if let Some(node_and_hash) = cache.get(block_id, ptr.ptr()) {
+                monitoring::increment_marf_node_cache_hits_counter();
+                return Ok(node_and_hash);
+            }
+            monitoring::increment_marf_node_cache_misses_counter();
+        }
+
+        let (node, hash) = match self.blobs {
+            Some(ref mut blobs) => blobs.read_node_type(&self.db, block_id, ptr)?,
+            None => trie_sql::read_node_type(&self.db, block_id, ptr)?,
+        };
+        if cacheable {
+            cache.insert(block_id, ptr.ptr(), &node, &hash);
+        }
+        Ok((node, hash))
+    }
+
+    /// Set the maximum size of the node cache, in bytes.  0 disables the node cache.
+    pub fn set_node_cache_capacity(&mut self, capacity: usize) {
+        self.data
+            .node_cache
+            .lock()
+            .expect("FATAL: MARF node cache lock is poisoned")
+            .set_capacity(capacity);
+    }
+
+    /// Get the (hits, misses) counts of the node cache, which is shared with all read-only views
+    /// of this storage.
+    pub fn node_cache_stats(&self) -> (u64, u64) {
+        self.data
+            .node_cache
+            .lock()
+            .expect("FATAL: MARF node cache lock is poisoned")
+            .stats()
+    }
+
+    /// Drop everything in the node cache, and the remembered locations of tries in the blobs
+    /// file.  Must be called whenever confirmed tries are removed.
+    pub fn clear_node_cache(&mut self) {
+        self.data
+            .node_cache
+            .lock()
+            .expect("FATAL: MARF node cache lock is poisoned")
+            .clear();
+        if let Some(ref mut blobs) = self.blobs {
+            blobs.clear_offsets();
+        }
+    }
+
     pub fn write_nodetype(
         &mut self,
         disk_ptr: u32,
@@ -1989,4 +2339,132 @@ pub mod test {
     fn load_store_trie_4_256_unique() {
         load_store_trie_m_n_same(4, 256, false);
     }
+
+    #[test]
+    fn node_cache_lru() {
+        let node = TrieNodeType::Leaf(TrieLeaf::new(&vec![], &MARFValue([0u8; 40]).to_vec()));
+        let hash = TrieHash([1u8; 32]);
+        let entry_size = TrieNodeCache::<BlockHeaderHash>::entry_size(&node);
+        let mut cache: TrieNodeCache<BlockHeaderHash> = TrieNodeCache::new(2 * entry_size);
+
+        assert!(cache.get(1, 10).is_none());
+        cache.insert(1, 10, &node, &hash);
+        cache.insert(1, 20, &node, &hash);
+        assert!(cache.get(1, 10).is_some());
+
+        // (1, 20) is the least-recently used
+        cache.insert(2, 10, &node, &hash);
+        assert_eq!(cache.len(), 2);
+        assert_eq!(cache.size(), 2 * entry_size);
+        assert!(cache.get(1, 20).is_none());
+        assert!(cache.get(1, 10).is_some());
+        assert!(cache.get(2, 10).is_some());
+        assert_eq!(cache.stats(), (3, 2));
+
+        // re-inserting a cached node doesn't count it twice
+        cache.insert(2, 10, &node, &hash);
+        assert_eq!(cache.size(), 2 * entry_size);
+
+        // a capacity that isn't a multiple of the node size rounds down
+        cache.set_capacity(2 * entry_size - 1);
+        assert_eq!(cache.len(), 1);
+        assert_eq!(cache.size(), entry_size);
+        assert!(cache.get(2, 10).is_some());
+
+        // a node bigger than the whole cache isn't cached
+        let big_node = TrieNodeType::Node256(Box::new(TrieNode256::new(&vec![0u8; 31])));
+        cache.insert(4, 10, &big_node, &hash);
+        assert!(cache.get(4, 10).is_none());
+        assert_eq!(cache.size(), entry_size);
+
+        cache.set_capacity(0);
+        assert_eq!(cache.size(), 0);
+        cache.insert(3, 10, &node, &hash);
+        assert_eq!(cache.len(), 0);
+
+        // a block ID that is reused for a different block flushes the cache
+        cache.set_capacity(2 * entry_size);
+        cache.set_confirmed(1, &BlockHeaderHash([1u8; 32]), true);
+        cache.insert(1, 10, &node, &hash);
+        assert_eq!(
+            cache.is_confirmed(1, &BlockHeaderHash([1u8; 32])),
+            Some(true)
+        );
+        assert_eq!(cache.is_confirmed(1, &BlockHeaderHash([2u8; 32])), None);
+        assert_eq!(cache.len(), 0);
+        assert_eq!(cache.size(), 0);
+    }
+
+    #[test]
+    fn node_cache_marf_reads() {
+        let f = TrieFileStorage::new_memory().unwrap();
+        let mut marf: MARF<BlockHeaderHash> = MARF::from_storage(f);
+
+        let mut parent = BlockHeaderHash::sentinel();
+        let mut blocks = vec![];
+        for i in 0..4u8 {
+            let block = BlockHeaderHash([i + 1; 32]);
+            marf.begin(&parent, &block).unwrap();
+            for k in 0..16 {
+                marf.insert(
+                    &format!("key-{}-{}", i, k),
+                    MARFValue::from_value(&format!("value-{}-{}", i, k)),
+                )
+                .unwrap();
+            }
+            marf.commit().unwrap();
+            blocks.push(block.clone());
+            parent = block;
+        }
+
+        let tip = blocks[3].clone();
+        let read_all = |marf: &mut MARF<BlockHeaderHash>| {
+            for i in 0..4u8 {
+                for k in 0..16 {
+                    assert_eq!(
+                        marf.get(&tip, &format!("key-{}-{}", i, k)).unwrap(),
+                        Some(MARFValue::from_value(&format!("value-{}-{}", i, k)))
+                    );
+                }
+            }
+        };
+
+        // cold cache
+        read_all(&mut marf);
+        let (hits_1, misses_1) = marf.node_cache_stats();
+        assert!(misses_1 > 0);
+
+        // warm cache: no new misses
+        read_all(&mut marf);
+        let (hits_2, misses_2) = marf.node_cache_stats();
+        assert_eq!(misses_2, misses_1);
+        assert!(hits_2 > hits_1);
+
+        // a bounded cache stays bounded, and still reads correctly
+        marf.set_node_cache_capacity(1024);
+        read_all(&mut marf);
+        {
+            let node_cache = marf.borrow_storage_backend().data.node_cache.clone();
+            let cache = node_cache.lock().unwrap();
+            assert!(cache.len() > 0);
+            assert!(cache.size() <= 1024);
+        }
+
+        // a disabled cache isn't consulted
+        marf.set_node_cache_capacity(0);
+        let (hits_3, misses_3) = marf.node_cache_stats();
+        read_all(&mut marf);
+        assert_eq!(marf.node_cache_stats(), (hits_3, misses_3));
+
+        marf.clear_node_cache();
+        assert_eq!(
+            marf.borrow_storage_backend()
+                .data
+                .node_cache
+                .lock()
+                .unwrap()
+                .len(),
+            0
+        );
+    }
 }
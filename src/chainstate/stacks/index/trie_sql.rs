@@ -76,6 +76,18 @@ CREATE TABLE IF NOT EXISTS marf_data (
 CREATE INDEX IF NOT EXISTS block_hash_marf_data ON marf_data(block_hash);
 CREATE INDEX IF NOT EXISTS unconfirmed_marf_data ON marf_data(unconfirmed);
 ";
+/// Columns added to marf_data when trie blobs are stored in an external blobs file.  A trie
+/// whose external_length is 0 is stored in the data column instead.
+static SQL_MARF_DATA_EXTERNAL_BLOBS: &str = "
+ALTER TABLE marf_data ADD COLUMN external_offset INTEGER DEFAULT 0 NOT NULL;
+ALTER TABLE marf_data ADD COLUMN external_length INTEGER DEFAULT 0 NOT NULL;
+";
+/// Records that the external blobs file has been compacted, but that the compacted file has not
+/// yet replaced it.  Its row is inserted in the same transaction that points every trie at its
+/// offset in the compacted file.
+static SQL_MARF_BLOBS_COMPACTION_TABLE: &str = "
+CREATE TABLE IF NOT EXISTS marf_blobs_compaction (pending INTEGER NOT NULL);
+";
 static SQL_MARF_MINED_TABLE: &str = "
 CREATE TABLE IF NOT EXISTS mined_blocks (
    block_id INTEGER PRIMARY KEY, 
@@ -100,6 +112,27 @@ pub fn create_tables_if_needed(conn: &mut Connection) -> Result<(), Error> {
     tx.commit().map_err(|e| e.into())
 }
 
+/// Does marf_data have the columns for tracking tries stored in an external blobs file?
+pub fn has_external_blob_columns(conn: &Connection) -> Result<bool, Error> {
+    let mut s = conn.prepare("PRAGMA table_info(marf_data)")?;
+    let columns = s.query_and_then(NO_PARAMS, |row| row.get::<_, String>("name"))?;
+    for column in columns {
+        if column? == "external_offset" {
+            return Ok(true);
+        }
+    }
+    Ok(false)
+}
+
+pub fn add_external_blob_columns_if_needed(conn: &mut Connection) -> Result<(), Error> {
+    let tx = tx_begin_immediate(conn)?;
+    if !has_external_blob_columns(&tx)? {
+        debug!("Add external blob columns to marf_data");
+        tx.execute_batch(SQL_MARF_DATA_EXTERNAL_BLOBS)?;
+    }
+    tx.commit().map_err(|e| e.into())
+}
+
 pub fn get_block_identifier<T: MarfTrieId>(conn: &Connection, bhh: &T) -> Result<u32, Error> {
     conn.query_row(
         "SELECT block_id FROM marf_data WHERE block_hash = ?",
@@ -135,6 +168,21 @@ pub fn get_unconfirmed_block_identifier<T: MarfTrieId>(
     .map_err(|e| e.into())
 }
 
+/// Is the trie at `local_id` a confirmed trie for `bhh`?
+pub fn is_confirmed_block_identifier<T: MarfTrieId>(
+    conn: &Connection,
+    local_id: u32,
+    bhh: &T,
+) -> Result<bool, Error> {
+    let args: &[&dyn ToSql] = &[&local_id, bhh];
+    let count: i64 = conn.query_row(
+        "SELECT COUNT(*) FROM marf_data WHERE block_id = ?1 AND block_hash = ?2 AND unconfirmed = 0",
+        args,
+        |row| row.get(0),
+    )?;
+    Ok(count > 0)
+}
+
 pub fn get_block_hash<T: MarfTrieId>(conn: &Connection, local_id: u32) -> Result<T, Error> {
     let result = conn
         .query_row(
@@ -166,6 +214,139 @@ pub fn write_trie_blob<T: MarfTrieId>(
     Ok(block_id)
 }
 
+/// Record a confirmed trie whose blob was written to the external blobs file at `offset`.
+pub fn write_external_trie_blob<T: MarfTrieId>(
+    conn: &Connection,
+    block_hash: &T,
+    offset: u64,
+    length: u64,
+) -> Result<u32, Error> {
+    let empty: &[u8] = &[];
+    let args: &[&dyn ToSql] = &[block_hash, &empty, &0, &(offset as i64), &(length as i64)];
+    let mut s = conn.prepare(
+        "INSERT INTO marf_data (block_hash, data, unconfirmed, external_offset, external_length) VALUES (?, ?, ?, ?, ?)",
+    )?;
+    let block_id = s
+        .insert(args)?
+        .try_into()
+        .expect("EXHAUSTION: MARF cannot track more than 2**31 - 1 blocks");
+
+    debug!(
+        "Wrote external block trie {} ({} bytes at {}) to rowid {}",
+        block_hash, length, offset, block_id
+    );
+    Ok(block_id)
+}
+
+/// Move the trie at `block_id` out of its data column, now that its blob has been written to the
+/// external blobs file at `offset`.
+pub fn set_external_trie_blob(
+    conn: &Connection,
+    block_id: u32,
+    offset: u64,
+    length: u64,
+) -> Result<(), Error> {
+    let empty: &[u8] = &[];
+    let args: &[&dyn ToSql] = &[&empty, &(offset as i64), &(length as i64), &block_id];
+    conn.execute(
+        "UPDATE marf_data SET data = ?, external_offset = ?, external_length = ? WHERE block_id = ?",
+        args,
+    )?;
+    Ok(())
+}
+
+/// Get the (offset, length) of the trie at `block_id` in the external blobs file, or None if
+/// the trie is stored in its data column.
+pub fn get_external_trie_offset_length(
+    conn: &Connection,
+    block_id: u32,
+) -> Result<Option<(u64, u64)>, Error> {
+    let (offset, length): (i64, i64) = conn
+        .query_row(
+            "SELECT external_offset, external_length FROM marf_data WHERE block_id = ?",
+            &[block_id],
+            |row| Ok((row.get("external_offset")?, row.get("external_length")?)),
+        )
+        .optional()?
+        .ok_or_else(|| {
+            error!("No trie with local ID {}", block_id);
+            Error::NotFoundError
+        })?;
+    if length == 0 {
+        Ok(None)
+    } else {
+        Ok(Some((offset as u64, length as u64)))
+    }
+}
+
+/// Get the local IDs of all confirmed tries that are stored in their data column
+pub fn get_internal_confirmed_block_ids(conn: &Connection) -> Result<Vec<u32>, Error> {
+    let mut s = conn.prepare(
+        "SELECT block_id FROM marf_data WHERE unconfirmed = 0 AND external_length = 0 ORDER BY block_id",
+    )?;
+    let rows = s.query_and_then(NO_PARAMS, |row| row.get("block_id").map_err(Error::from))?;
+    rows.collect()
+}
+
+/// Get the local IDs, offsets, and lengths of all tries that are stored in the external blobs
+/// file, in the order they appear in it
+pub fn get_external_trie_offsets_lengths(conn: &Connection) -> Result<Vec<(u32, u64, u64)>, Error> {
+    let mut s = conn.prepare(
+        "SELECT block_id, external_offset, external_length FROM marf_data WHERE external_length > 0 ORDER BY external_offset",
+    )?;
+    let rows = s.query_and_then(NO_PARAMS, |row| {
+        let block_id: u32 = row.get("block_id")?;
+        let offset: i64 = row.get("external_offset")?;
+        let length: i64 = row.get("external_length")?;
+        Ok((block_id, offset as u64, length as u64))
+    })?;
+    rows.collect()
+}
+
+/// Record that the external blobs file has been compacted into a new file that has yet to
+/// replace it
+pub fn set_blobs_compaction_pending(conn: &Connection) -> Result<(), Error> {
+    conn.execute_batch(SQL_MARF_BLOBS_COMPACTION_TABLE)?;
+    conn.execute(
+        "INSERT INTO marf_blobs_compaction (pending) VALUES (1)",
+        NO_PARAMS,
+    )?;
+    Ok(())
+}
+
+/// Has the external blobs file been compacted into a new file that has yet to replace it?
+pub fn is_blobs_compaction_pending(conn: &Connection) -> Result<bool, Error> {
+    let has_table: i64 = conn.query_row(
+        "SELECT COUNT(*) FROM sqlite_master WHERE type = 'table' AND name = 'marf_blobs_compaction'",
+        NO_PARAMS,
+        |row| row.get(0),
+    )?;
+    if has_table == 0 {
+        return Ok(false);
+    }
+    let pending: i64 = conn.query_row(
+        "SELECT COUNT(*) FROM marf_blobs_compaction",
+        NO_PARAMS,
+        |row| row.get(0),
+    )?;
+    Ok(pending > 0)
+}
+
+/// Record that the compacted blobs file has replaced the old one
+pub fn clear_blobs_compaction_pending(conn: &Connection) -> Result<(), Error> {
+    conn.execute_batch(SQL_MARF_BLOBS_COMPACTION_TABLE)?;
+    conn.execute("DELETE FROM marf_blobs_compaction", NO_PARAMS)?;
+    Ok(())
+}
+
+/// Read the whole blob of the trie at `block_id` from its data column
+pub fn read_trie_blob(conn: &Connection, block_id: u32) -> Result<Vec<u8>, Error> {
+    let mut blob = open_trie_blob(conn, block_id)?;
+    let mut data = vec![];
+    blob.read_to_end(&mut data)?;
+    Ok(data)
+}
+
 pub fn write_trie_blob_to_mined<T: MarfTrieId>(
     conn: &Connection,
     block_hash: &T,
@@ -406,6 +587,42 @@ pub fn drop_unconfirmed_trie<T: MarfTrieId>(conn: &Connection, bhh: &T) -> Resul
     Ok(())
 }
 
+/// Drop all unconfirmed tries.  Returns how many were dropped.
+pub fn clear_unconfirmed_tries(conn: &Connection) -> Result<u64, Error> {
+    let count = conn.execute("DELETE FROM marf_data WHERE unconfirmed = 1", NO_PARAMS)?;
+    Ok(count as u64)
+}
+
+/// Get the local IDs and block hashes of all confirmed tries, in the order they were written
+pub fn get_confirmed_block_ids_and_hashes<T: MarfTrieId>(
+    conn: &Connection,
+) -> Result<Vec<(u32, T)>, Error> {
+    let mut s = conn.prepare(
+        "SELECT block_id, block_hash FROM marf_data WHERE unconfirmed = 0 ORDER BY block_id",
+    )?;
+    let rows = s.query_and_then(NO_PARAMS, |row| {
+        let block_id: u32 = row.get_unwrap("block_id");
+        let block_hash: T = row.get_unwrap("block_hash");
+        Ok((block_id, block_hash))
+    })?;
+    rows.collect()
+}
+
+pub fn delete_trie_blob(conn: &Connection, block_id: u32) -> Result<(), Error> {
+    conn.execute(
+        "DELETE FROM marf_data WHERE block_id = ? AND unconfirmed = 0",
+        &[block_id],
+    )?;
+    debug!("Deleted trie sqlite blob at rowid {}", block_id);
+    Ok(())
+}
+
+/// Drop all tries written by miners.  Returns how many were dropped.
+pub fn clear_mined_blocks(conn: &Connection) -> Result<u64, Error> {
+    let count = conn.execute("DELETE FROM mined_blocks", NO_PARAMS)?;
+    Ok(count as u64)
+}
+
 pub fn clear_lock_data(conn: &Connection) -> Result<(), Error> {
     conn.execute("DELETE FROM block_extension_locks", NO_PARAMS)?;
     Ok(())
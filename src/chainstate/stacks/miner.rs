@@ -22,9 +22,14 @@ use chainstate::stacks::events::StacksTransactionReceipt;
 use chainstate::stacks::index::TrieHash;
 use chainstate::stacks::Error;
 use chainstate::stacks::*;
+use std::cmp;
+use std::cmp::Ordering;
+use std::collections::BinaryHeap;
 use std::collections::HashMap;
 use std::collections::HashSet;
+use std::collections::VecDeque;
 use std::convert::From;
+use std::fmt;
 use std::fs;
 use std::mem;
 
@@ -80,6 +85,586 @@ impl From<&UnconfirmedState> for MicroblockMinerRuntime {
     }
 }
 
+/// Resolution with which the block assembler measures a transaction's share of the block's
+/// size and execution budget (i.e. parts per million).
+pub const BLOCK_BUDGET_RESOLUTION: u64 = 1_000_000;
+
+/// A mempool transaction that the miner is considering
+pub struct BlockCandidate {
+    pub info: MemPoolTxInfo,
+    /// lower bound on the execution cost of this transaction
+    pub estimated_cost: ExecutionCost,
+    /// estimated share of the block's budget this transaction consumes, in parts per
+    /// BLOCK_BUDGET_RESOLUTION of its scarcest dimension (bytes or any execution cost dimension)
+    pub weight: u64,
+}
+
+impl BlockCandidate {
+    fn new(
+        clarity_tx: &mut ClarityTx,
+        info: MemPoolTxInfo,
+        execution_budget: &ExecutionCost,
+    ) -> BlockCandidate {
+        let estimated_cost = StacksBlockBuilder::estimate_tx_cost(clarity_tx, &info.tx);
+        let size_weight = (info.metadata.len as u128) * (BLOCK_BUDGET_RESOLUTION as u128)
+            / (MAX_EPOCH_SIZE as u128);
+        let cost_weight = estimated_cost
+            .proportion_largest_dimension(execution_budget, BLOCK_BUDGET_RESOLUTION)
+            as u128;
+
+        // never zero, so every transaction's fee rate is well-defined
+        let weight = cmp::max(
+            1,
+            cmp::min(cmp::max(size_weight, cost_weight), u64::max_value() as u128),
+        );
+        BlockCandidate {
+            info,
+            estimated_cost,
+            weight: weight as u64,
+        }
+    }
+}
+
+/// How urgently the miner wants to mine a package of transactions.  Packages are mined in
+/// descending order of priority.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TxPriority {
+    /// packages in a higher tier are always mined before packages in a lower tier
+    pub tier: u64,
+    /// within a tier, packages with a higher score are mined first
+    pub score: u64,
+    /// remaining ties go to the package that arrived in the mempool first
+    pub accept_time: u64,
+}
+
+impl Ord for TxPriority {
+    fn cmp(&self, other: &TxPriority) -> Ordering {
+        self.tier
+            .cmp(&other.tier)
+            .then_with(|| self.score.cmp(&other.score))
+            .then_with(|| other.accept_time.cmp(&self.accept_time))
+    }
+}
+
+impl PartialOrd for TxPriority {
+    fn partial_cmp(&self, other: &TxPriority) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+/// A policy for choosing which mempool transactions the miner puts into its anchored blocks and
+/// microblocks, and in what order.
+///
+/// The miner groups the candidate transactions by origin account into chains of consecutive
+/// nonces, since a transaction can only be mined after all of its origin's lower-nonce
+/// transactions.  The selector decides which transactions are eligible at all, and which
+/// transactions from the front of each chain to mine next, and how urgently.  The miner then
+/// repeatedly mines the highest-priority package of all the chains.
+pub trait MinerTxSelector {
+    /// Whether or not the miner may include this transaction.  A chain is cut off at its first
+    /// ineligible transaction.
+    fn is_eligible(&self, _tx: &StacksTransaction) -> bool {
+        true
+    }
+
+    /// Choose the next package from the front of a non-empty chain.  Returns the number of
+    /// transactions in the package (at least 1, at most `chain.len()`), and its priority.
+    fn next_package(&self, chain: &[BlockCandidate]) -> (usize, TxPriority);
+}
+
+/// Mine the transactions that pay the most per unit of block budget first.  A transaction
+/// that pays a high fee makes its unmined ancestors more attractive too, since they have to be
+/// mined first (i.e. packages are ranked by their ancestor fee rate).
+pub struct FeeRateTxSelector;
+
+impl FeeRateTxSelector {
+    /// Score a fee rate as fee per BLOCK_BUDGET_RESOLUTION units of weight
+    fn fee_rate_score(fee: u64, weight: u64) -> u64 {
+        let score =
+            (fee as u128) * (BLOCK_BUDGET_RESOLUTION as u128) / (cmp::max(weight, 1) as u128);
+        cmp::min(score, u64::max_value() as u128) as u64
+    }
+}
+
+impl MinerTxSelector for FeeRateTxSelector {
+    fn next_package(&self, chain: &[BlockCandidate]) -> (usize, TxPriority) {
+        // find the prefix of this chain with the highest fee per unit weight
+        let (mut best_len, mut best_fee, mut best_weight) = (0usize, 0u64, 1u64);
+        let (mut fee, mut weight) = (0u64, 0u64);
+        for (i, candidate) in chain.iter().enumerate() {
+            fee = fee.saturating_add(candidate.info.metadata.tx_fee);
+            weight = weight.saturating_add(candidate.weight);
+            if best_len == 0
+                || (fee as u128) * (best_weight as u128) > (best_fee as u128) * (weight as u128)
+            {
+                best_len = i + 1;
+                best_fee = fee;
+                best_weight = weight;
+            }
+        }
+
+        let priority = TxPriority {
+            tier: 0,
+            score: FeeRateTxSelector::fee_rate_score(best_fee, best_weight),
+            accept_time: chain
+                .first()
+                .map(|c| c.info.metadata.accept_time)
+                .unwrap_or(0),
+        };
+        (cmp::max(best_len, 1), priority)
+    }
+}
+
+/// Mine transactions in the order in which they arrived in the mempool, regardless of fee
+pub struct FifoTxSelector;
+
+impl MinerTxSelector for FifoTxSelector {
+    fn next_package(&self, chain: &[BlockCandidate]) -> (usize, TxPriority) {
+        let priority = TxPriority {
+            tier: 0,
+            score: 0,
+            accept_time: chain
+                .first()
+                .map(|c| c.info.metadata.accept_time)
+                .unwrap_or(0),
+        };
+        (1, priority)
+    }
+}
+
+/// Only mine contract-calls to the given contracts, and don't mine any new contracts.  Other
+/// transactions are eligible if the inner selector says so, and all transactions are ordered by
+/// the inner selector.
+pub struct ContractAllowlistTxSelector {
+    allowed_contracts: HashSet<QualifiedContractIdentifier>,
+    inner: Box<dyn MinerTxSelector>,
+}
+
+impl ContractAllowlistTxSelector {
+    pub fn new(
+        allowed_contracts: HashSet<QualifiedContractIdentifier>,
+        inner: Box<dyn MinerTxSelector>,
+    ) -> ContractAllowlistTxSelector {
+        ContractAllowlistTxSelector {
+            allowed_contracts,
+            inner,
+        }
+    }
+}
+
+impl MinerTxSelector for ContractAllowlistTxSelector {
+    fn is_eligible(&self, tx: &StacksTransaction) -> bool {
+        match tx.payload {
+            TransactionPayload::ContractCall(ref cc) => {
+                let contract_id = QualifiedContractIdentifier::new(
+                    StandardPrincipalData::from(cc.address.clone()),
+                    cc.contract_name.clone(),
+                );
+                self.allowed_contracts.contains(&contract_id) && self.inner.is_eligible(tx)
+            }
+            TransactionPayload::SmartContract(..) => false,
+            _ => self.inner.is_eligible(tx),
+        }
+    }
+
+    fn next_package(&self, chain: &[BlockCandidate]) -> (usize, TxPriority) {
+        self.inner.next_package(chain)
+    }
+}
+
+/// Always mine the transactions that the given addresses originate or sponsor first (along with
+/// any transactions they depend on), and always consider them eligible.  All other transactions
+/// are handled by the inner selector.
+pub struct PriorityAddressTxSelector {
+    addresses: HashSet<StacksAddress>,
+    inner: Box<dyn MinerTxSelector>,
+}
+
+impl PriorityAddressTxSelector {
+    pub fn new(
+        addresses: HashSet<StacksAddress>,
+        inner: Box<dyn MinerTxSelector>,
+    ) -> PriorityAddressTxSelector {
+        PriorityAddressTxSelector { addresses, inner }
+    }
+
+    fn is_priority(&self, tx: &StacksTransaction) -> bool {
+        self.addresses.contains(&tx.origin_address())
+            || tx
+                .sponsor_address()
+                .map(|addr| self.addresses.contains(&addr))
+                .unwrap_or(false)
+    }
+}
+
+impl MinerTxSelector for PriorityAddressTxSelector {
+    fn is_eligible(&self, tx: &StacksTransaction) -> bool {
+        self.is_priority(tx) || self.inner.is_eligible(tx)
+    }
+
+    fn next_package(&self, chain: &[BlockCandidate]) -> (usize, TxPriority) {
+        let (num_txs, priority) = self.inner.next_package(chain);
+        match chain
+            .iter()
+            .rposition(|candidate| self.is_priority(&candidate.info.tx))
+        {
+            Some(last_priority_index) => (
+                last_priority_index + 1,
+                TxPriority {
+                    tier: priority.tier.saturating_add(1),
+                    ..priority
+                },
+            ),
+            None => (num_txs, priority),
+        }
+    }
+}
+
+/// A package of transactions from the front of one origin account's chain
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct CandidatePackage {
+    origin: StacksAddress,
+    num_txs: usize,
+    priority: TxPriority,
+}
+
+impl Ord for CandidatePackage {
+    fn cmp(&self, other: &CandidatePackage) -> Ordering {
+        self.priority
+            .cmp(&other.priority)
+            .then_with(|| other.origin.to_string().cmp(&self.origin.to_string()))
+    }
+}
+
+impl PartialOrd for CandidatePackage {
+    fn partial_cmp(&self, other: &CandidatePackage) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+/// Why the block assembler left a candidate mempool transaction out of a block
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum TxSkipReason {
+    /// the transaction's nonce was already used by the origin account
+    StaleNonce,
+    /// the transaction's nonce is past the origin account's next nonce, or its sponsor nonce is
+    /// past the sponsor account's next nonce
+    NonceGap,
+    /// the miner's selection policy does not mine this transaction
+    Ineligible,
+    /// the transaction is predicted to exceed, or did exceed, the remaining block budget
+    ExceedsBudget,
+    /// an earlier transaction from the same origin account was not mined
+    AncestorNotMined,
+    /// the transaction could not be applied to the block
+    Failed(String),
+}
+
+impl fmt::Display for TxSkipReason {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            TxSkipReason::StaleNonce => write!(f, "StaleNonce"),
+            TxSkipReason::NonceGap => write!(f, "NonceGap"),
+            TxSkipReason::Ineligible => write!(f, "Ineligible"),
+            TxSkipReason::ExceedsBudget => write!(f, "ExceedsBudget"),
+            TxSkipReason::AncestorNotMined => write!(f, "AncestorNotMined"),
+            TxSkipReason::Failed(ref msg) => write!(f, "Failed({})", msg),
+        }
+    }
+}
+
+impl TxSkipReason {
+    /// Name of this reason, without any error message
+    pub fn name(&self) -> &'static str {
+        match self {
+            TxSkipReason::StaleNonce => "StaleNonce",
+            TxSkipReason::NonceGap => "NonceGap",
+            TxSkipReason::Ineligible => "Ineligible",
+            TxSkipReason::ExceedsBudget => "ExceedsBudget",
+            TxSkipReason::AncestorNotMined => "AncestorNotMined",
+            TxSkipReason::Failed(_) => "Failed",
+        }
+    }
+}
+
+/// What the block assembler did with the mempool transactions it considered
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct BlockAssemblyReport {
+    /// number of mempool transactions considered
+    pub num_considered: u64,
+    /// transactions that were considered but not mined, and why
+    pub skipped: Vec<(Txid, TxSkipReason)>,
+}
+
+/// The mineable transactions of a single origin account, in nonce order.  The first
+/// transaction uses the account's next nonce, and each subsequent transaction uses the nonce
+/// after its predecessor's.
+struct CandidateChain {
+    origin: StacksAddress,
+    txs: Vec<BlockCandidate>,
+    /// transactions from this origin account that cannot be mined, and why
+    skipped: Vec<(Txid, TxSkipReason)>,
+}
+
+impl CandidateChain {
+    /// Build the chain of transactions that the origin account can mine next, given its
+    /// account nonce.  Transactions with stale nonces, or after a nonce gap or an ineligible
+    /// transaction, are left out.  If there are several transactions with the same nonce (e.g.
+    /// from different forks), the one with the highest fee is used.
+    fn new(
+        origin: StacksAddress,
+        account_nonce: u64,
+        mut candidates: Vec<BlockCandidate>,
+        selector: &dyn MinerTxSelector,
+    ) -> CandidateChain {
+        candidates.sort_by(|c1, c2| {
+            c1.info
+                .metadata
+                .origin_nonce
+                .cmp(&c2.info.metadata.origin_nonce)
+                .then_with(|| c2.info.metadata.tx_fee.cmp(&c1.info.metadata.tx_fee))
+        });
+
+        let mut txs = vec![];
+        let mut skipped = vec![];
+        let mut next_nonce = account_nonce;
+        let mut chain_ok = true;
+        for candidate in candidates.into_iter() {
+            let txid = candidate.info.tx.txid();
+            let nonce = candidate.info.metadata.origin_nonce;
+            if !chain_ok {
+                skipped.push((txid, TxSkipReason::AncestorNotMined));
+            } else if nonce < next_nonce {
+                skipped.push((txid, TxSkipReason::StaleNonce));
+            } else if nonce > next_nonce {
+                skipped.push((txid, TxSkipReason::NonceGap));
+            } else if !selector.is_eligible(&candidate.info.tx) {
+                skipped.push((txid, TxSkipReason::Ineligible));
+                chain_ok = false;
+            } else {
+                txs.push(candidate);
+                next_nonce += 1;
+            }
+        }
+        CandidateChain {
+            origin,
+            txs,
+            skipped,
+        }
+    }
+
+    /// Get the next package to mine from this chain, if there are any transactions left
+    fn next_package(&self, selector: &dyn MinerTxSelector) -> Option<CandidatePackage> {
+        if self.txs.len() == 0 {
+            return None;
+        }
+        let (num_txs, priority) = selector.next_package(&self.txs);
+        Some(CandidatePackage {
+            origin: self.origin.clone(),
+            num_txs: cmp::min(cmp::max(num_txs, 1), self.txs.len()),
+            priority,
+        })
+    }
+}
+
+/// If the candidate is sponsored, and it spends a sponsor nonce that the sponsor's earlier
+/// transactions have yet to reach, get the sponsor's address.  The candidate can't be mined
+/// until the sponsor's account nonce catches up.
+fn get_pending_sponsor<'a>(
+    clarity_tx: &mut ClarityTx<'a>,
+    candidate: &BlockCandidate,
+) -> Option<StacksAddress> {
+    let metadata = &candidate.info.metadata;
+    if metadata.sponsor_address == metadata.origin_address {
+        return None;
+    }
+    let sponsor_nonce =
+        StacksChainState::get_account(clarity_tx, &metadata.sponsor_address.to_account_principal())
+            .nonce;
+    if metadata.sponsor_nonce > sponsor_nonce {
+        Some(metadata.sponsor_address.clone())
+    } else {
+        None
+    }
+}
+
+/// Load every mempool transaction that can be mined off of the given chain tip
+fn get_mempool_candidates(
+    mempool: &MemPoolDB,
+    tip_consensus_hash: &ConsensusHash,
+    tip_block_hash: &BlockHeaderHash,
+    tip_height: u64,
+    header_reader_chainstate: &mut StacksChainState,
+) -> Result<Vec<MemPoolTxInfo>, Error> {
+    let mut considered = HashSet::new();
+    let mut candidates = vec![];
+    mempool.iterate_candidates(
+        tip_consensus_hash,
+        tip_block_hash,
+        tip_height,
+        header_reader_chainstate,
+        |available_txs| {
+            for txinfo in available_txs.into_iter() {
+                if considered.insert(txinfo.tx.txid()) {
+                    candidates.push(txinfo);
+                }
+            }
+            Ok::<(), Error>(())
+        },
+    )?;
+    Ok(candidates)
+}
+
+/// Mine the given candidate transactions in the order chosen by `selector`.
+///
+/// Candidates are grouped by origin account into nonce chains, and the selector's
+/// highest-priority package across all chains is mined next, one transaction at a time via
+/// `try_mine_tx`, which returns why the transaction was not mined if it was not.  A transaction
+/// that is predicted to exceed the remaining execution budget, or that cannot be mined, is
+/// skipped along with the rest of its chain; other chains are still considered, so one
+/// transaction that does not fit does not end the block.
+///
+/// A sponsored transaction also depends on its sponsor's earlier transactions, which may be in
+/// other chains.  If one of them has yet to be mined, the sponsored transaction's chain waits
+/// until the sponsor's account nonce catches up, and is skipped if it never does.
+/// Returns a report of every candidate that was skipped, and why.
+fn mine_candidates<'a, F>(
+    clarity_tx: &mut ClarityTx<'a>,
+    selector: &dyn MinerTxSelector,
+    candidates: Vec<MemPoolTxInfo>,
+    execution_budget: &ExecutionCost,
+    mut try_mine_tx: F,
+) -> BlockAssemblyReport
+where
+    F: FnMut(&mut ClarityTx<'a>, &MemPoolTxInfo) -> Result<(), TxSkipReason>,
+{
+    let num_candidates = candidates.len();
+    let mut report = BlockAssemblyReport {
+        num_considered: num_candidates as u64,
+        skipped: vec![],
+    };
+    let mut candidates_by_origin: HashMap<StacksAddress, Vec<BlockCandidate>> = HashMap::new();
+    for txinfo in candidates.into_iter() {
+        let candidate = BlockCandidate::new(clarity_tx, txinfo, execution_budget);
+        candidates_by_origin
+            .entry(candidate.info.metadata.origin_address.clone())
+            .or_insert_with(|| vec![])
+            .push(candidate);
+    }
+
+    let mut chains = HashMap::new();
+    let mut packages = BinaryHeap::new();
+    for (origin, candidates) in candidates_by_origin.into_iter() {
+        let account_nonce =
+            StacksChainState::get_account(clarity_tx, &origin.to_account_principal()).nonce;
+        let mut chain = CandidateChain::new(origin.clone(), account_nonce, candidates, selector);
+        report.skipped.append(&mut chain.skipped);
+        if let Some(package) = chain.next_package(selector) {
+            packages.push(package);
+            chains.insert(origin, chain);
+        }
+    }
+
+    debug!(
+        "Consider {} transactions from {} origin accounts",
+        num_candidates,
+        chains.len()
+    );
+
+    // chains whose next transaction is waiting for its sponsor's earlier transactions, by
+    // sponsor address
+    let mut waiting_for_sponsor: HashMap<StacksAddress, Vec<CandidateChain>> = HashMap::new();
+
+    while let Some(package) = packages.pop() {
+        let mut chain = match chains.remove(&package.origin) {
+            Some(chain) => chain,
+            None => {
+                continue;
+            }
+        };
+
+        let mut chain_ok = true;
+        let mut pending_sponsor = None;
+        for _ in 0..package.num_txs {
+            if let Some(sponsor) = get_pending_sponsor(clarity_tx, &chain.txs[0]) {
+                debug!(
+                    "Defer tx {}: sponsor {} has earlier transactions to mine",
+                    &chain.txs[0].info.tx.txid(),
+                    &sponsor
+                );
+                pending_sponsor = Some(sponsor);
+                break;
+            }
+
+            let candidate = chain.txs.remove(0);
+            let mut predicted_cost = clarity_tx.cost_so_far();
+            if predicted_cost.add(&candidate.estimated_cost).is_err()
+                || predicted_cost.exceeds(execution_budget)
+            {
+                debug!(
+                    "Skip tx {}: predicted to exceed the block budget",
+                    &candidate.info.tx.txid()
+                );
+                report
+                    .skipped
+                    .push((candidate.info.tx.txid(), TxSkipReason::ExceedsBudget));
+                chain_ok = false;
+                break;
+            }
+
+            if let Err(reason) = try_mine_tx(clarity_tx, &candidate.info) {
+                report.skipped.push((candidate.info.tx.txid(), reason));
+                chain_ok = false;
+                break;
+            }
+
+            // the origin and sponsor nonces advanced, so chains waiting on them may be mineable
+            let metadata = &candidate.info.metadata;
+            for addr in [&metadata.origin_address, &metadata.sponsor_address].iter() {
+                for waiting_chain in waiting_for_sponsor.remove(*addr).unwrap_or(vec![]) {
+                    if let Some(package) = waiting_chain.next_package(selector) {
+                        packages.push(package);
+                        chains.insert(waiting_chain.origin.clone(), waiting_chain);
+                    }
+                }
+            }
+        }
+
+        // the rest of a chain can only be mined if all of its ancestors were
+        if !chain_ok {
+            for candidate in chain.txs.into_iter() {
+                report
+                    .skipped
+                    .push((candidate.info.tx.txid(), TxSkipReason::AncestorNotMined));
+            }
+        } else if let Some(sponsor) = pending_sponsor {
+            waiting_for_sponsor
+                .entry(sponsor)
+                .or_insert_with(|| vec![])
+                .push(chain);
+        } else if let Some(package) = chain.next_package(selector) {
+            packages.push(package);
+            chains.insert(chain.origin.clone(), chain);
+        }
+    }
+
+    // the sponsors' earlier transactions of these chains were never mined
+    for (_, waiting_chains) in waiting_for_sponsor.into_iter() {
+        for waiting_chain in waiting_chains.into_iter() {
+            for (i, candidate) in waiting_chain.txs.into_iter().enumerate() {
+                let reason = if i == 0 {
+                    TxSkipReason::NonceGap
+                } else {
+                    TxSkipReason::AncestorNotMined
+                };
+                report.skipped.push((candidate.info.tx.txid(), reason));
+            }
+        }
+    }
+
+    report
+}
+
 ///
 ///    Independent structure for building microblocks:
 ///       StacksBlockBuilder cannot be used, since microblocks should only be broadcasted
@@ -94,6 +679,7 @@ pub struct StacksMicroblockBuilder<'a> {
     anchor_block_consensus_hash: ConsensusHash,
     anchor_block_height: u64,
     header_reader: StacksChainState,
+    block_limit: ExecutionCost,
     clarity_tx: Option<ClarityTx<'a>>,
     unconfirmed: bool,
     runtime: MicroblockMinerRuntime,
@@ -114,6 +700,7 @@ impl<'a> StacksMicroblockBuilder<'a> {
         };
 
         let (header_reader, _) = chainstate.reopen()?;
+        let block_limit = chainstate.block_limit.clone();
         let anchor_block_height = StacksChainState::get_anchored_block_header_info(
             header_reader.db(),
             &anchor_block_consensus_hash,
@@ -151,6 +738,7 @@ impl<'a> StacksMicroblockBuilder<'a> {
             runtime: runtime,
             clarity_tx: Some(clarity_tx),
             header_reader,
+            block_limit,
             unconfirmed: false,
         })
     }
@@ -169,6 +757,7 @@ impl<'a> StacksMicroblockBuilder<'a> {
         };
 
         let (header_reader, _) = chainstate.reopen()?;
+        let block_limit = chainstate.block_limit.clone();
         let (anchored_consensus_hash, anchored_block_hash, anchored_block_height) =
             if let Some(unconfirmed) = chainstate.unconfirmed_state.as_ref() {
                 let header_info =
@@ -211,6 +800,7 @@ impl<'a> StacksMicroblockBuilder<'a> {
             runtime: runtime,
             clarity_tx: Some(clarity_tx),
             header_reader,
+            block_limit,
             unconfirmed: true,
         })
     }
@@ -364,10 +954,23 @@ impl<'a> StacksMicroblockBuilder<'a> {
         return self.make_next_microblock(txs_included, miner_key);
     }
 
+    /// Mine the next microblock from the mempool, choosing transactions by fee rate (see
+    /// FeeRateTxSelector).
     pub fn mine_next_microblock(
         &mut self,
         mem_pool: &MemPoolDB,
         miner_key: &Secp256k1PrivateKey,
+    ) -> Result<StacksMicroblock, Error> {
+        self.mine_next_microblock_with_selector(mem_pool, miner_key, &FeeRateTxSelector)
+    }
+
+    /// Mine the next microblock from the mempool, using the given policy to choose its
+    /// transactions.
+    pub fn mine_next_microblock_with_selector(
+        &mut self,
+        mem_pool: &MemPoolDB,
+        miner_key: &Secp256k1PrivateKey,
+        selector: &dyn MinerTxSelector,
     ) -> Result<StacksMicroblock, Error> {
         let mut txs_included = vec![];
 
@@ -384,16 +987,23 @@ impl<'a> StacksMicroblockBuilder<'a> {
 
         let mut bytes_so_far = self.runtime.bytes_so_far;
 
-        let result = mem_pool.iterate_candidates(
+        let block_limit = self.block_limit.clone();
+        let result = get_mempool_candidates(
+            mem_pool,
             &self.anchor_block_consensus_hash,
             &self.anchor_block,
             self.anchor_block_height,
             &mut self.header_reader,
-            |micro_txs| {
-                let mut result = Ok(());
-                for mempool_tx in micro_txs.into_iter() {
+        )
+        .map(|candidates| {
+            mine_candidates(
+                &mut clarity_tx,
+                selector,
+                candidates,
+                &block_limit,
+                |clarity_tx, mempool_tx| {
                     match StacksMicroblockBuilder::mine_next_transaction(
-                        &mut clarity_tx,
+                        clarity_tx,
                         mempool_tx.tx.clone(),
                         mempool_tx.metadata.len,
                         &mut considered,
@@ -401,34 +1011,33 @@ impl<'a> StacksMicroblockBuilder<'a> {
                     ) {
                         Ok(true) => {
                             bytes_so_far += mempool_tx.metadata.len;
-                            txs_included.push(mempool_tx.tx);
+                            txs_included.push(mempool_tx.tx.clone());
+                            Ok(())
                         }
-                        Ok(false) => {
-                            continue;
+                        Ok(false) => Err(TxSkipReason::Failed(
+                            "not mineable in this microblock".to_string(),
+                        )),
+                        Err(Error::BlockTooBigError) => {
+                            // this transaction doesn't fit, but others might
+                            debug!("Microblock budget exceeded on tx {}", &mempool_tx.tx.txid());
+                            Err(TxSkipReason::ExceedsBudget)
                         }
                         Err(e) => {
-                            result = Err(e);
-                            break;
+                            warn!("Failed to apply tx {}: {:?}", &mempool_tx.tx.txid(), &e);
+                            Err(TxSkipReason::Failed(e.to_string()))
                         }
                     }
-                }
-                result
-            },
-        );
+                },
+            )
+        });
 
         self.runtime.bytes_so_far = bytes_so_far;
         self.clarity_tx.replace(clarity_tx);
         self.runtime.considered.replace(considered);
 
-        match result {
-            Ok(_) => {}
-            Err(Error::BlockTooBigError) => {
-                info!("Block budget reached with microblocks");
-            }
-            Err(e) => {
-                warn!("Error producing microblock: {}", e);
-                return Err(e);
-            }
+        if let Err(e) = result {
+            warn!("Error producing microblock: {}", e);
+            return Err(e);
         }
 
         return self.make_next_microblock(txs_included, miner_key);
@@ -1222,8 +1831,39 @@ impl StacksBlockBuilder {
         Ok(builder)
     }
 
+    /// Estimate a lower bound on the execution cost of a transaction from what it must read or
+    /// write before running it: a contract-call loads the contract it calls, and a
+    /// smart-contract stores its code.
+    pub fn estimate_tx_cost(clarity_tx: &mut ClarityTx, tx: &StacksTransaction) -> ExecutionCost {
+        let mut cost = ExecutionCost::zero();
+        match tx.payload {
+            TransactionPayload::ContractCall(ref cc) => {
+                let contract_id = QualifiedContractIdentifier::new(
+                    StandardPrincipalData::from(cc.address.clone()),
+                    cc.contract_name.clone(),
+                );
+                let contract_size = clarity_tx.with_clarity_db_readonly(|db| {
+                    if db.has_contract(&contract_id) {
+                        db.get_contract_size(&contract_id).unwrap_or(0)
+                    } else {
+                        0
+                    }
+                });
+                cost.read_count = 1;
+                cost.read_length = contract_size;
+            }
+            TransactionPayload::SmartContract(ref sc) => {
+                cost.write_count = 1;
+                cost.write_length = sc.code_body.len() as u64;
+            }
+            _ => {}
+        }
+        cost
+    }
+
     /// Given access to the mempool, mine an anchored block with no more than the given execution cost.
     ///   returns the assembled block, and the consumed execution budget.
+    /// Transactions are chosen by fee rate (see FeeRateTxSelector).
     pub fn build_anchored_block(
         chainstate_handle: &StacksChainState, // not directly used; used as a handle to open other chainstates
         burn_dbconn: &SortitionDBConn,
@@ -1235,6 +1875,66 @@ impl StacksBlockBuilder {
         coinbase_tx: &StacksTransaction,
         execution_budget: ExecutionCost,
     ) -> Result<(StacksBlock, ExecutionCost, u64), Error> {
+        StacksBlockBuilder::build_anchored_block_with_selector(
+            chainstate_handle,
+            burn_dbconn,
+            mempool,
+            parent_stacks_header,
+            total_burn,
+            proof,
+            pubkey_hash,
+            coinbase_tx,
+            execution_budget,
+            &FeeRateTxSelector,
+        )
+    }
+
+    /// Given access to the mempool, mine an anchored block with no more than the given execution
+    /// cost, using the given policy to choose its transactions.
+    ///   returns the assembled block, and the consumed execution budget.
+    pub fn build_anchored_block_with_selector(
+        chainstate_handle: &StacksChainState, // not directly used; used as a handle to open other chainstates
+        burn_dbconn: &SortitionDBConn,
+        mempool: &MemPoolDB,
+        parent_stacks_header: &StacksHeaderInfo, // Stacks header we're building off of
+        total_burn: u64, // the burn so far on the burnchain (i.e. from the last burnchain block)
+        proof: VRFProof, // proof over the burnchain's last seed
+        pubkey_hash: Hash160,
+        coinbase_tx: &StacksTransaction,
+        execution_budget: ExecutionCost,
+        selector: &dyn MinerTxSelector,
+    ) -> Result<(StacksBlock, ExecutionCost, u64), Error> {
+        StacksBlockBuilder::build_anchored_block_with_report(
+            chainstate_handle,
+            burn_dbconn,
+            mempool,
+            parent_stacks_header,
+            total_burn,
+            proof,
+            pubkey_hash,
+            coinbase_tx,
+            execution_budget,
+            selector,
+        )
+        .map(|(block, consumed, size, _)| (block, consumed, size))
+    }
+
+    /// Given access to the mempool, mine an anchored block with no more than the given execution
+    /// cost, using the given policy to choose its transactions.
+    ///   returns the assembled block, the consumed execution budget, the block's size, and a
+    ///   report of which mempool transactions were considered and which were skipped.
+    pub fn build_anchored_block_with_report(
+        chainstate_handle: &StacksChainState, // not directly used; used as a handle to open other chainstates
+        burn_dbconn: &SortitionDBConn,
+        mempool: &MemPoolDB,
+        parent_stacks_header: &StacksHeaderInfo, // Stacks header we're building off of
+        total_burn: u64, // the burn so far on the burnchain (i.e. from the last burnchain block)
+        proof: VRFProof, // proof over the burnchain's last seed
+        pubkey_hash: Hash160,
+        coinbase_tx: &StacksTransaction,
+        execution_budget: ExecutionCost,
+        selector: &dyn MinerTxSelector,
+    ) -> Result<(StacksBlock, ExecutionCost, u64, BlockAssemblyReport), Error> {
         if let TransactionPayload::Coinbase(..) = coinbase_tx.payload {
         } else {
             return Err(Error::MemPoolError(
@@ -1254,7 +1954,7 @@ impl StacksBlockBuilder {
         );
 
         let (mut header_reader_chainstate, _) = chainstate_handle.reopen()?; // used for reading block headers during an epoch
-        let (mut chainstate, _) = chainstate_handle.reopen_limited(execution_budget)?; // used for processing a block up to the given limit
+        let (mut chainstate, _) = chainstate_handle.reopen_limited(execution_budget.clone())?; // used for processing a block up to the given limit
 
         let mut builder = StacksBlockBuilder::make_block_builder(
             chainstate.mainnet,
@@ -1267,79 +1967,46 @@ impl StacksBlockBuilder {
         let mut epoch_tx = builder.epoch_begin(&mut chainstate, burn_dbconn)?;
         builder.try_mine_tx(&mut epoch_tx, coinbase_tx)?;
 
-        let mut considered = HashSet::new(); // txids of all transactions we looked at
-        let mut mined_origin_nonces: HashMap<StacksAddress, u64> = HashMap::new(); // map addrs of mined transaction origins to the nonces we used
-        let mut mined_sponsor_nonces: HashMap<StacksAddress, u64> = HashMap::new(); // map addrs of mined transaction sponsors to the nonces we used
-
-        let result = mempool.iterate_candidates(
+        let result = get_mempool_candidates(
+            mempool,
             &tip_consensus_hash,
             &tip_block_hash,
             tip_height,
             &mut header_reader_chainstate,
-            |available_txs| {
-                for txinfo in available_txs.into_iter() {
-                    // skip transactions early if we can
-                    if considered.contains(&txinfo.tx.txid()) {
-                        continue;
-                    }
-                    if let Some(nonce) = mined_origin_nonces.get(&txinfo.tx.origin_address()) {
-                        if *nonce >= txinfo.tx.get_origin_nonce() {
-                            continue;
-                        }
-                    }
-                    if let Some(sponsor_addr) = txinfo.tx.sponsor_address() {
-                        if let Some(nonce) = mined_sponsor_nonces.get(&sponsor_addr) {
-                            if let Some(sponsor_nonce) = txinfo.tx.get_sponsor_nonce() {
-                                if *nonce >= sponsor_nonce {
-                                    continue;
-                                }
-                            }
-                        }
-                    }
-
-                    considered.insert(txinfo.tx.txid());
+        );
 
-                    match builder.try_mine_tx_with_len(
-                        &mut epoch_tx,
-                        &txinfo.tx,
-                        txinfo.metadata.len,
-                    ) {
-                        Ok(_) => {}
+        let report = match result {
+            Ok(candidates) => mine_candidates(
+                &mut epoch_tx,
+                selector,
+                candidates,
+                &execution_budget,
+                |clarity_tx, txinfo| {
+                    match builder.try_mine_tx_with_len(clarity_tx, &txinfo.tx, txinfo.metadata.len)
+                    {
+                        Ok(_) => Ok(()),
                         Err(Error::BlockTooBigError) => {
-                            // done mining -- our execution budget is exceeded.
-                            // Make the block from the transactions we did manage to get
+                            // this transaction doesn't fit, but others might
                             debug!("Block budget exceeded on tx {}", &txinfo.tx.txid());
+                            Err(TxSkipReason::ExceedsBudget)
                         }
-                        Err(Error::InvalidStacksTransaction(_, true)) => {
+                        Err(Error::InvalidStacksTransaction(msg, true)) => {
                             // if we have an invalid transaction that was quietly ignored, don't warn here either
-                            continue;
+                            Err(TxSkipReason::Failed(msg))
                         }
                         Err(e) => {
                             warn!("Failed to apply tx {}: {:?}", &txinfo.tx.txid(), &e);
-                            continue;
+                            Err(TxSkipReason::Failed(e.to_string()))
                         }
                     }
-
-                    mined_origin_nonces
-                        .insert(txinfo.tx.origin_address(), txinfo.tx.get_origin_nonce());
-                    if let (Some(sponsor_addr), Some(sponsor_nonce)) =
-                        (txinfo.tx.sponsor_address(), txinfo.tx.get_sponsor_nonce())
-                    {
-                        mined_sponsor_nonces.insert(sponsor_addr, sponsor_nonce);
-                    }
-                }
-                Ok(())
-            },
-        );
-
-        match result {
-            Ok(_) => {}
+                },
+            ),
             Err(e) => {
                 warn!("Failure building block: {}", e);
                 epoch_tx.rollback_block();
                 return Err(e);
             }
-        }
+        };
 
         // the prior do_rebuild logic wasn't necessary
         // a transaction that caused a budget exception is rolled back in process_transaction
@@ -1348,7 +2015,7 @@ impl StacksBlockBuilder {
         let block = builder.mine_anchored_block(&mut epoch_tx);
         let size = builder.bytes_so_far;
         let consumed = builder.epoch_finish(epoch_tx);
-        Ok((block, consumed, size))
+        Ok((block, consumed, size, report))
     }
 }
 
@@ -6253,6 +6920,7 @@ pub mod test {
                                 &parent_consensus_hash,
                                 &parent_header_hash,
                                 &stx_transfer,
+                                None,
                             )
                             .unwrap();
                     }
@@ -6391,6 +7059,7 @@ pub mod test {
                                     &parent_consensus_hash,
                                     &parent_header_hash,
                                     &stx_transfer,
+                                    None,
                                 )
                                 .unwrap();
                         }
@@ -6413,6 +7082,7 @@ pub mod test {
                                     &parent_consensus_hash,
                                     &parent_header_hash,
                                     &stx_transfer,
+                                    None,
                                 )
                                 .unwrap();
                         }
@@ -6595,6 +7265,7 @@ pub mod test {
                                 &parent_consensus_hash,
                                 &parent_header_hash,
                                 &stx_transfer,
+                                None,
                             )
                             .unwrap();
 
@@ -6613,6 +7284,7 @@ pub mod test {
                                 &parent_consensus_hash,
                                 &parent_header_hash,
                                 &contract_tx,
+                                None,
                             )
                             .unwrap();
 
@@ -6630,6 +7302,7 @@ pub mod test {
                                 &parent_consensus_hash,
                                 &parent_header_hash,
                                 &stx_transfer,
+                                None,
                             )
                             .unwrap();
 
@@ -6683,6 +7356,613 @@ pub mod test {
         }
     }
 
+    #[test]
+    fn test_build_anchored_blocks_package_fee_rate() {
+        let privk = StacksPrivateKey::from_hex(
+            "42faca653724860da7a41bfcef7e6ba78db55146f6900de8cb2a9f760ffac70c01",
+        )
+        .unwrap();
+        let privk_extra = StacksPrivateKey::from_hex(
+            "f67c7437f948ca1834602b28595c12ac744f287a4efaf70d437042a6afed81bc01",
+        )
+        .unwrap();
+        let addr = StacksAddress::from_public_keys(
+            C32_ADDRESS_VERSION_TESTNET_SINGLESIG,
+            &AddressHashMode::SerializeP2PKH,
+            1,
+            &vec![StacksPublicKey::from_private(&privk)],
+        )
+        .unwrap();
+        let addr_extra = StacksAddress::from_public_keys(
+            C32_ADDRESS_VERSION_TESTNET_SINGLESIG,
+            &AddressHashMode::SerializeP2PKH,
+            1,
+            &vec![StacksPublicKey::from_private(&privk_extra)],
+        )
+        .unwrap();
+
+        let mut peer_config =
+            TestPeerConfig::new("test_build_anchored_blocks_package_fee_rate", 2016, 2017);
+        peer_config.initial_balances = vec![
+            (addr.to_account_principal(), 1000000000),
+            (addr_extra.to_account_principal(), 1000000000),
+        ];
+
+        let mut peer = TestPeer::new(peer_config);
+
+        let chainstate_path = peer.chainstate_path.clone();
+
+        let num_blocks = 5;
+        let recipient_addr_str = "ST1RFD5Q2QPK3E0F08HG9XDX7SSC7CNRS0QR0SGEV";
+        let recipient = StacksAddress::from_string(recipient_addr_str).unwrap();
+        let mut sender_nonce = 0;
+        let mut sender_extra_nonce = 0;
+
+        for tenure_id in 0..num_blocks {
+            // send transactions to the mempool
+            let tip =
+                SortitionDB::get_canonical_burn_chain_tip(&peer.sortdb.as_ref().unwrap().conn())
+                    .unwrap();
+
+            let (burn_ops, stacks_block, microblocks) = peer.make_tenure(
+                |ref mut miner,
+                 ref mut sortdb,
+                 ref mut chainstate,
+                 vrf_proof,
+                 ref parent_opt,
+                 ref parent_microblock_header_opt| {
+                    let parent_tip = match parent_opt {
+                        None => StacksChainState::get_genesis_header_info(chainstate.db()).unwrap(),
+                        Some(block) => {
+                            let ic = sortdb.index_conn();
+                            let snapshot =
+                                SortitionDB::get_block_snapshot_for_winning_stacks_block(
+                                    &ic,
+                                    &tip.sortition_id,
+                                    &block.block_hash(),
+                                )
+                                .unwrap()
+                                .unwrap(); // succeeds because we don't fork
+                            StacksChainState::get_anchored_block_header_info(
+                                chainstate.db(),
+                                &snapshot.consensus_hash,
+                                &snapshot.winning_stacks_block_hash,
+                            )
+                            .unwrap()
+                            .unwrap()
+                        }
+                    };
+
+                    let parent_header_hash = parent_tip.anchored_header.block_hash();
+                    let parent_consensus_hash = parent_tip.consensus_hash.clone();
+
+                    let mut mempool = MemPoolDB::open(false, 0x80000000, &chainstate_path).unwrap();
+
+                    let coinbase_tx = make_coinbase(miner, tenure_id);
+
+                    if tenure_id > 0 {
+                        // a low-fee transaction whose child pays a high fee
+                        // (submitted in order so the mempool accepts the chain)
+                        let parent_transfer = make_user_stacks_transfer(
+                            &privk,
+                            sender_nonce,
+                            200,
+                            &recipient.to_account_principal(),
+                            1,
+                        );
+                        let child_transfer = make_user_stacks_transfer(
+                            &privk,
+                            sender_nonce + 1,
+                            2000,
+                            &recipient.to_account_principal(),
+                            1,
+                        );
+                        sender_nonce += 2;
+
+                        // a transaction with a higher fee than the parent's, but a lower fee
+                        // rate than the parent-and-child package
+                        let extra_transfer = make_user_stacks_transfer(
+                            &privk_extra,
+                            sender_extra_nonce,
+                            800,
+                            &recipient.to_account_principal(),
+                            1,
+                        );
+                        sender_extra_nonce += 1;
+
+                        for tx in [parent_transfer, child_transfer, extra_transfer].iter() {
+                            mempool
+                                .submit(
+                                    chainstate,
+                                    &parent_consensus_hash,
+                                    &parent_header_hash,
+                                    tx,
+                                    None,
+                                )
+                                .unwrap();
+                        }
+                    }
+                    let anchored_block = StacksBlockBuilder::build_anchored_block(
+                        chainstate,
+                        &sortdb.index_conn(),
+                        &mempool,
+                        &parent_tip,
+                        tip.total_burn,
+                        vrf_proof,
+                        Hash160([tenure_id as u8; 20]),
+                        &coinbase_tx,
+                        ExecutionCost::max_value(),
+                    )
+                    .unwrap();
+                    (anchored_block.0, vec![])
+                },
+            );
+
+            peer.next_burnchain_block(burn_ops.clone());
+            peer.process_stacks_epoch_at_tip(&stacks_block, &microblocks);
+
+            if tenure_id > 0 {
+                // the parent-and-child package was mined before the other transaction
+                assert_eq!(stacks_block.txs.len(), 4);
+                let origins: Vec<_> = stacks_block.txs[1..]
+                    .iter()
+                    .map(|tx| (tx.origin_address(), tx.get_origin_nonce()))
+                    .collect();
+                assert_eq!(
+                    origins,
+                    vec![
+                        (addr.clone(), sender_nonce - 2),
+                        (addr.clone(), sender_nonce - 1),
+                        (addr_extra.clone(), sender_extra_nonce - 1),
+                    ]
+                );
+            }
+        }
+    }
+
+    pub fn make_sponsored_stacks_transfer(
+        origin: &StacksPrivateKey,
+        origin_nonce: u64,
+        sponsor: &StacksPrivateKey,
+        sponsor_nonce: u64,
+        tx_fee: u64,
+        recipient: &PrincipalData,
+        amount: u64,
+    ) -> StacksTransaction {
+        let payload = TransactionPayload::TokenTransfer(
+            recipient.clone(),
+            amount,
+            TokenTransferMemo([0; 34]),
+        );
+        let mut origin_auth = TransactionSpendingCondition::new_singlesig_p2pkh(
+            StacksPublicKey::from_private(origin),
+        )
+        .unwrap();
+        origin_auth.set_nonce(origin_nonce);
+
+        // will be replaced once the origin finishes signing
+        let auth = TransactionAuth::Sponsored(
+            origin_auth,
+            TransactionSpendingCondition::new_singlesig_p2pkh(StacksPublicKey::from_private(
+                sponsor,
+            ))
+            .unwrap(),
+        );
+        let mut unsigned_tx = StacksTransaction::new(TransactionVersion::Testnet, auth, payload);
+        unsigned_tx.chain_id = 0x80000000;
+        unsigned_tx.post_condition_mode = TransactionPostConditionMode::Allow;
+
+        let mut tx_signer = StacksTransactionSigner::new(&unsigned_tx);
+        tx_signer.sign_origin(origin).unwrap();
+        let origin_tx = tx_signer.get_tx_incomplete();
+
+        let mut sponsor_auth = TransactionSpendingCondition::new_singlesig_p2pkh(
+            StacksPublicKey::from_private(sponsor),
+        )
+        .unwrap();
+        sponsor_auth.set_nonce(sponsor_nonce);
+        sponsor_auth.set_tx_fee(tx_fee);
+
+        let mut tx_signer = StacksTransactionSigner::new_sponsor(&origin_tx, sponsor_auth).unwrap();
+        tx_signer.sign_sponsor(sponsor).unwrap();
+        tx_signer.get_tx().unwrap()
+    }
+
+    #[test]
+    fn test_build_anchored_blocks_sponsored_across_origins() {
+        let privk_origin = StacksPrivateKey::new();
+        let privk_origin_other = StacksPrivateKey::new();
+        let privk_sponsor = StacksPrivateKey::new();
+        let addrs: Vec<StacksAddress> = [&privk_origin, &privk_origin_other, &privk_sponsor]
+            .iter()
+            .map(|privk| {
+                StacksAddress::from_public_keys(
+                    C32_ADDRESS_VERSION_TESTNET_SINGLESIG,
+                    &AddressHashMode::SerializeP2PKH,
+                    1,
+                    &vec![StacksPublicKey::from_private(privk)],
+                )
+                .unwrap()
+            })
+            .collect();
+
+        let mut peer_config = TestPeerConfig::new(
+            "test_build_anchored_blocks_sponsored_across_origins",
+            2018,
+            2019,
+        );
+        peer_config.initial_balances = addrs
+            .iter()
+            .map(|addr| (addr.to_account_principal(), 1000000000))
+            .collect();
+
+        let mut peer = TestPeer::new(peer_config);
+
+        let chainstate_path = peer.chainstate_path.clone();
+
+        let num_blocks = 2;
+        let recipient = StacksAddress::from_string("ST1RFD5Q2QPK3E0F08HG9XDX7SSC7CNRS0QR0SGEV")
+            .unwrap()
+            .to_account_principal();
+
+        for tenure_id in 0..num_blocks {
+            // send transactions to the mempool
+            let tip =
+                SortitionDB::get_canonical_burn_chain_tip(&peer.sortdb.as_ref().unwrap().conn())
+                    .unwrap();
+
+            let (burn_ops, stacks_block, microblocks) = peer.make_tenure(
+                |ref mut miner,
+                 ref mut sortdb,
+                 ref mut chainstate,
+                 vrf_proof,
+                 ref parent_opt,
+                 ref parent_microblock_header_opt| {
+                    let parent_tip = match parent_opt {
+                        None => StacksChainState::get_genesis_header_info(chainstate.db()).unwrap(),
+                        Some(block) => {
+                            let ic = sortdb.index_conn();
+                            let snapshot =
+                                SortitionDB::get_block_snapshot_for_winning_stacks_block(
+                                    &ic,
+                                    &tip.sortition_id,
+                                    &block.block_hash(),
+                                )
+                                .unwrap()
+                                .unwrap(); // succeeds because we don't fork
+                            StacksChainState::get_anchored_block_header_info(
+                                chainstate.db(),
+                                &snapshot.consensus_hash,
+                                &snapshot.winning_stacks_block_hash,
+                            )
+                            .unwrap()
+                            .unwrap()
+                        }
+                    };
+
+                    let parent_header_hash = parent_tip.anchored_header.block_hash();
+                    let parent_consensus_hash = parent_tip.consensus_hash.clone();
+
+                    let mut mempool = MemPoolDB::open(false, 0x80000000, &chainstate_path).unwrap();
+
+                    let coinbase_tx = make_coinbase(miner, tenure_id);
+
+                    if tenure_id > 0 {
+                        // the sponsor's first transaction pays a low fee.  Its second, from
+                        // another origin, pays a high fee, so that origin's chain is mined first
+                        // and has to wait for the first.
+                        let first_sponsored = make_sponsored_stacks_transfer(
+                            &privk_origin,
+                            0,
+                            &privk_sponsor,
+                            0,
+                            300,
+                            &recipient,
+                            1,
+                        );
+                        let second_sponsored = make_sponsored_stacks_transfer(
+                            &privk_origin_other,
+                            0,
+                            &privk_sponsor,
+                            1,
+                            2000,
+                            &recipient,
+                            1,
+                        );
+                        let second_child =
+                            make_user_stacks_transfer(&privk_origin_other, 1, 2000, &recipient, 1);
+
+                        for tx in [first_sponsored, second_sponsored, second_child].iter() {
+                            mempool
+                                .submit(
+                                    chainstate,
+                                    &parent_consensus_hash,
+                                    &parent_header_hash,
+                                    tx,
+                                    None,
+                                )
+                                .unwrap();
+                        }
+                    }
+                    let anchored_block = StacksBlockBuilder::build_anchored_block(
+                        chainstate,
+                        &sortdb.index_conn(),
+                        &mempool,
+                        &parent_tip,
+                        tip.total_burn,
+                        vrf_proof,
+                        Hash160([tenure_id as u8; 20]),
+                        &coinbase_tx,
+                        ExecutionCost::max_value(),
+                    )
+                    .unwrap();
+                    (anchored_block.0, vec![])
+                },
+            );
+
+            peer.next_burnchain_block(burn_ops.clone());
+            peer.process_stacks_epoch_at_tip(&stacks_block, &microblocks);
+
+            if tenure_id > 0 {
+                // every transaction was mined, with the sponsor's nonces in order
+                assert_eq!(stacks_block.txs.len(), 4);
+                let nonces: Vec<_> = stacks_block.txs[1..]
+                    .iter()
+                    .map(|tx| {
+                        (
+                            tx.origin_address(),
+                            tx.get_origin_nonce(),
+                            tx.sponsor_address(),
+                            tx.get_sponsor_nonce(),
+                        )
+                    })
+                    .collect();
+                assert_eq!(
+                    nonces,
+                    vec![
+                        (addrs[0].clone(), 0, Some(addrs[2].clone()), Some(0)),
+                        (addrs[1].clone(), 0, Some(addrs[2].clone()), Some(1)),
+                        (addrs[1].clone(), 1, None, None),
+                    ]
+                );
+            }
+        }
+    }
+
+    fn make_block_candidate(
+        privk: &StacksPrivateKey,
+        nonce: u64,
+        tx_fee: u64,
+        weight: u64,
+    ) -> BlockCandidate {
+        let recipient = StacksAddress::from_string("ST1RFD5Q2QPK3E0F08HG9XDX7SSC7CNRS0QR0SGEV")
+            .unwrap()
+            .to_account_principal();
+        let tx = make_user_stacks_transfer(privk, nonce, tx_fee, &recipient, 1);
+        let origin_address = tx.origin_address();
+        let metadata = MemPoolTxMetadata {
+            txid: tx.txid(),
+            len: 180,
+            tx_fee,
+            estimated_fee: tx_fee,
+            consensus_hash: ConsensusHash([0x01; 20]),
+            block_header_hash: BlockHeaderHash([0x02; 32]),
+            block_height: 1,
+            origin_address: origin_address.clone(),
+            origin_nonce: nonce,
+            sponsor_address: origin_address,
+            sponsor_nonce: nonce,
+            accept_time: 1000 + nonce,
+        };
+        BlockCandidate {
+            info: MemPoolTxInfo { tx, metadata },
+            estimated_cost: ExecutionCost::zero(),
+            weight,
+        }
+    }
+
+    #[test]
+    fn test_candidate_chain_packages() {
+        let privk = StacksPrivateKey::new();
+        let privk_other = StacksPrivateKey::new();
+        let selector = FeeRateTxSelector;
+
+        // nonce 2 is missing, so nonce 3 can't be mined.  There are two transactions with nonce 0.
+        let chain = CandidateChain::new(
+            StacksAddress::burn_address(false),
+            0,
+            vec![
+                make_block_candidate(&privk, 3, 5000, 1),
+                make_block_candidate(&privk, 1, 1000, 1),
+                make_block_candidate(&privk, 0, 10, 1),
+                make_block_candidate(&privk, 0, 50, 1),
+            ],
+            &selector,
+        );
+        let nonces_and_fees: Vec<_> = chain
+            .txs
+            .iter()
+            .map(|c| (c.info.metadata.origin_nonce, c.info.metadata.tx_fee))
+            .collect();
+        assert_eq!(nonces_and_fees, vec![(0, 50), (1, 1000)]);
+
+        // the lower-fee duplicate and the transaction after the gap are reported as skipped
+        let skipped: Vec<_> = chain.skipped.iter().map(|(_, r)| r.clone()).collect();
+        assert_eq!(
+            skipped,
+            vec![TxSkipReason::StaleNonce, TxSkipReason::NonceGap]
+        );
+
+        // the child's fee pulls in its parent
+        let package = chain.next_package(&selector).unwrap();
+        assert_eq!(package.num_txs, 2);
+        assert_eq!(
+            package.priority.score,
+            FeeRateTxSelector::fee_rate_score(1050, 2)
+        );
+
+        // stale nonces are dropped
+        let chain = CandidateChain::new(
+            StacksAddress::burn_address(false),
+            1,
+            vec![
+                make_block_candidate(&privk, 0, 10, 1),
+                make_block_candidate(&privk, 1, 1000, 1),
+            ],
+            &selector,
+        );
+        assert_eq!(chain.txs.len(), 1);
+        assert_eq!(chain.txs[0].info.metadata.origin_nonce, 1);
+        assert_eq!(chain.skipped.len(), 1);
+        assert_eq!(chain.skipped[0].1, TxSkipReason::StaleNonce);
+
+        // a high-fee parent isn't dragged down by a low-fee child
+        let chain = CandidateChain::new(
+            StacksAddress::burn_address(false),
+            0,
+            vec![
+                make_block_candidate(&privk, 0, 1000, 1),
+                make_block_candidate(&privk, 1, 10, 1),
+            ],
+            &selector,
+        );
+        let package = chain.next_package(&selector).unwrap();
+        assert_eq!(package.num_txs, 1);
+        assert_eq!(
+            package.priority.score,
+            FeeRateTxSelector::fee_rate_score(1000, 1)
+        );
+
+        // packages are ranked by fee per unit weight
+        let heavy_chain = CandidateChain::new(
+            StacksAddress::burn_address(true),
+            0,
+            vec![make_block_candidate(&privk_other, 0, 1500, 2)],
+            &selector,
+        );
+        let heavy_package = heavy_chain.next_package(&selector).unwrap();
+        assert!(package > heavy_package);
+
+        let empty_chain =
+            CandidateChain::new(StacksAddress::burn_address(false), 0, vec![], &selector);
+        assert!(empty_chain.next_package(&selector).is_none());
+    }
+
+    #[test]
+    fn test_miner_tx_selectors() {
+        let privk = StacksPrivateKey::new();
+        let privk_ours = StacksPrivateKey::new();
+        let chain = vec![
+            make_block_candidate(&privk, 0, 1000, 1),
+            make_block_candidate(&privk, 1, 10, 1),
+            make_block_candidate(&privk, 2, 10, 1),
+        ];
+
+        // FIFO mines one transaction at a time, oldest first
+        let (num_txs, priority) = FifoTxSelector.next_package(&chain);
+        assert_eq!(num_txs, 1);
+        assert_eq!(priority.accept_time, 1000);
+        let (_, later_priority) = FifoTxSelector.next_package(&chain[1..]);
+        assert!(priority > later_priority);
+
+        // our transactions are mined first, along with everything they depend on
+        let ours = make_block_candidate(&privk_ours, 0, 1, 1);
+        let ours_addr = ours.info.tx.origin_address();
+        let mut priority_addrs = HashSet::new();
+        priority_addrs.insert(ours_addr);
+        let priority_selector =
+            PriorityAddressTxSelector::new(priority_addrs, Box::new(FeeRateTxSelector));
+
+        let (num_txs, priority) = priority_selector.next_package(&chain);
+        let (fee_rate_num_txs, fee_rate_priority) = FeeRateTxSelector.next_package(&chain);
+        assert_eq!(num_txs, fee_rate_num_txs);
+        assert_eq!(priority, fee_rate_priority);
+
+        let (num_txs, our_priority) = priority_selector.next_package(&[ours]);
+        assert_eq!(num_txs, 1);
+        assert_eq!(our_priority.tier, 1);
+        assert!(our_priority > priority);
+
+        // only calls to allowed contracts are eligible
+        let contract_addr =
+            StacksAddress::from_string("ST1RFD5Q2QPK3E0F08HG9XDX7SSC7CNRS0QR0SGEV").unwrap();
+        let allowed_contract_call = sign_standard_singlesig_tx(
+            TransactionPayload::new_contract_call(contract_addr.clone(), "allowed", "foo", vec![])
+                .unwrap(),
+            &privk,
+            0,
+            1000,
+        );
+        let other_contract_call = sign_standard_singlesig_tx(
+            TransactionPayload::new_contract_call(contract_addr.clone(), "other", "foo", vec![])
+                .unwrap(),
+            &privk,
+            0,
+            1000,
+        );
+        let contract_publish =
+            make_user_contract_publish(&privk, 0, 1000, "new-contract", "(ok 1)");
+        let transfer = &chain[0].info.tx;
+
+        let mut allowed_contracts = HashSet::new();
+        allowed_contracts.insert(QualifiedContractIdentifier::new(
+            StandardPrincipalData::from(contract_addr.clone()),
+            ContractName::from("allowed"),
+        ));
+        let allowlist_selector =
+            ContractAllowlistTxSelector::new(allowed_contracts, Box::new(FeeRateTxSelector));
+        assert!(allowlist_selector.is_eligible(&allowed_contract_call));
+        assert!(!allowlist_selector.is_eligible(&other_contract_call));
+        assert!(!allowlist_selector.is_eligible(&contract_publish));
+        assert!(allowlist_selector.is_eligible(transfer));
+
+        // ...unless they're ours
+        let mut priority_addrs = HashSet::new();
+        priority_addrs.insert(transfer.origin_address());
+        let combined_selector =
+            PriorityAddressTxSelector::new(priority_addrs, Box::new(allowlist_selector));
+        assert!(combined_selector.is_eligible(&other_contract_call));
+        assert!(combined_selector.is_eligible(&contract_publish));
+    }
+
+    #[test]
+    fn test_execution_cost_proportion_largest_dimension() {
+        let limit = ExecutionCost {
+            write_length: 1000,
+            write_count: 10,
+            read_length: 1000,
+            read_count: 10,
+            runtime: 0,
+        };
+
+        assert_eq!(
+            ExecutionCost::zero().proportion_largest_dimension(&limit, BLOCK_BUDGET_RESOLUTION),
+            0
+        );
+
+        let cost = ExecutionCost {
+            write_length: 100,
+            write_count: 5,
+            read_length: 0,
+            read_count: 0,
+            runtime: 0,
+        };
+        assert_eq!(
+            cost.proportion_largest_dimension(&limit, BLOCK_BUDGET_RESOLUTION),
+            BLOCK_BUDGET_RESOLUTION / 2
+        );
+
+        // a zero limit is treated as 1
+        assert_eq!(
+            ExecutionCost::runtime(3).proportion_largest_dimension(&limit, 100),
+            300
+        );
+        assert_eq!(
+            ExecutionCost::max_value().proportion_largest_dimension(&limit, u64::max_value()),
+            u64::max_value()
+        );
+    }
+
     #[test]
     fn test_build_anchored_blocks_multiple_chaintips() {
         let mut privks = vec![];
@@ -6780,6 +8060,7 @@ pub mod test {
                                 &parent_consensus_hash,
                                 &parent_header_hash,
                                 &contract_tx,
+                                None,
                             )
                             .unwrap();
                     }
@@ -6938,6 +8219,7 @@ pub mod test {
                                 &parent_consensus_hash,
                                 &parent_header_hash,
                                 &contract_tx,
+                                None,
                             )
                             .unwrap();
                     }
@@ -173,6 +173,7 @@ pub enum Error {
     PoxAlreadyLocked,
     PoxInsufficientBalance,
     PoxNoRewardCycle,
+    InvalidSnapshot(String),
 }
 
 impl From<marf_error> for Error {
@@ -228,6 +229,7 @@ impl fmt::Display for Error {
             Error::PoxAlreadyLocked => write!(f, "Account has already locked STX for PoX"),
             Error::PoxInsufficientBalance => write!(f, "Not enough STX to lock"),
             Error::PoxNoRewardCycle => write!(f, "No such reward cycle"),
+            Error::InvalidSnapshot(ref s) => fmt::Display::fmt(s, f),
         }
     }
 }
@@ -258,6 +260,7 @@ impl error::Error for Error {
             Error::PoxAlreadyLocked => None,
             Error::PoxInsufficientBalance => None,
             Error::PoxNoRewardCycle => None,
+            Error::InvalidSnapshot(ref _s) => None,
         }
     }
 }
@@ -288,6 +291,7 @@ impl Error {
             Error::PoxAlreadyLocked => "PoxAlreadyLocked",
             Error::PoxInsufficientBalance => "PoxInsufficientBalance",
             Error::PoxNoRewardCycle => "PoxNoRewardCycle",
+            Error::InvalidSnapshot(ref _s) => "InvalidSnapshot",
         }
     }
 
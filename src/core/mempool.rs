@@ -23,12 +23,15 @@ use rusqlite::Transaction;
 use rusqlite::NO_PARAMS;
 
 use std::cmp;
+use std::fmt;
 use std::ops::Deref;
 use std::ops::DerefMut;
 
 use burnchains::Txid;
 use chainstate::burn::ConsensusHash;
 
+use net::MemPoolSyncData;
+use net::RPCMempoolFeeHistogram;
 use net::StacksMessageCodec;
 
 use chainstate::burn::BlockHeaderHash;
@@ -41,6 +44,7 @@ use std::io::Read;
 use std::path::{Path, PathBuf};
 
 use util::db::query_row;
+use util::db::query_row_columns;
 use util::db::query_rows;
 use util::db::tx_begin_immediate;
 use util::db::tx_busy_handler;
@@ -50,6 +54,7 @@ use util::db::FromColumn;
 use util::db::{DBConn, DBTx, FromRow};
 use util::get_epoch_time_secs;
 
+use chainstate::stacks::db::blocks::MINIMUM_TX_FEE_RATE_PER_BYTE;
 use core::FIRST_BURNCHAIN_CONSENSUS_HASH;
 use core::FIRST_STACKS_BLOCK_HASH;
 
@@ -59,9 +64,110 @@ use rusqlite::Error as SqliteError;
 pub const MEMPOOL_MAX_TRANSACTION_AGE: u64 = 256;
 pub const MAXIMUM_MEMPOOL_TX_CHAINING: u64 = 25;
 
+// by how much (in percent) a transaction's fee rate must exceed that of the transaction it
+// replaces in the same fork
+pub const MEMPOOL_DEFAULT_REPLACE_BY_FEE_MIN_BUMP_PCT: u64 = 10;
+
+// how big the mempool can get before we start evicting the lowest fee-rate transactions
+pub const MEMPOOL_DEFAULT_MAX_TX_COUNT: u64 = 100_000;
+pub const MEMPOOL_DEFAULT_MAX_BYTES: u64 = 256 * 1024 * 1024;
+
+/// Mempool admission policy knobs, which are node-local and not part of consensus
+#[derive(Debug, Clone, PartialEq)]
+pub struct MemPoolSettings {
+    /// A transaction that spends the same origin or sponsor nonce as one already in the mempool
+    /// (in the same fork) replaces it only if its fee rate is at least this many percent higher.
+    pub replace_by_fee_min_bump_pct: u64,
+    /// Maximum number of transactions the mempool holds
+    pub max_tx_count: u64,
+    /// Maximum total size of the transactions the mempool holds
+    pub max_bytes: u64,
+    /// Minimum fee rate (in microSTX per byte) a transaction must pay to be admitted
+    pub min_relay_fee_rate: u64,
+}
+
+impl std::default::Default for MemPoolSettings {
+    fn default() -> MemPoolSettings {
+        MemPoolSettings {
+            replace_by_fee_min_bump_pct: MEMPOOL_DEFAULT_REPLACE_BY_FEE_MIN_BUMP_PCT,
+            max_tx_count: MEMPOOL_DEFAULT_MAX_TX_COUNT,
+            max_bytes: MEMPOOL_DEFAULT_MAX_BYTES,
+            min_relay_fee_rate: MINIMUM_TX_FEE_RATE_PER_BYTE,
+        }
+    }
+}
+
+/// Why a transaction left the mempool without being mined
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum MemPoolDropReason {
+    /// The transaction was accepted too many blocks ago
+    StaleGarbageCollect,
+    /// A transaction spending the same origin or sponsor nonce has been confirmed
+    ConflictingNonceConfirmed,
+    /// The transaction would no longer be admitted at the chain tip
+    Invalid,
+    /// A transaction spending the same nonce outbid it in the same fork
+    ReplaceByFee,
+    /// A transaction spending the same nonce was submitted in a different fork
+    ReplaceAcrossFork,
+    /// The mempool was full, and this transaction had the lowest fee rate
+    MemPoolFull,
+}
+
+impl fmt::Display for MemPoolDropReason {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            MemPoolDropReason::StaleGarbageCollect => write!(f, "StaleGarbageCollect"),
+            MemPoolDropReason::ConflictingNonceConfirmed => write!(f, "ConflictingNonceConfirmed"),
+            MemPoolDropReason::Invalid => write!(f, "Invalid"),
+            MemPoolDropReason::ReplaceByFee => write!(f, "ReplaceByFee"),
+            MemPoolDropReason::ReplaceAcrossFork => write!(f, "ReplaceAcrossFork"),
+            MemPoolDropReason::MemPoolFull => write!(f, "MemPoolFull"),
+        }
+    }
+}
+
+/// Receiver of mempool events that don't come from transaction admission
+pub trait MemPoolEventDispatcher {
+    fn mempool_txs_dropped(&self, txids: Vec<Txid>, reason: MemPoolDropReason);
+}
+
+/// Announce dropped transactions to the observer, if there is one and if there are any
+fn announce_dropped_txs(
+    event_observer: Option<&dyn MemPoolEventDispatcher>,
+    txids: Vec<Txid>,
+    reason: MemPoolDropReason,
+) {
+    if let Some(observer) = event_observer {
+        if txids.len() > 0 {
+            observer.mempool_txs_dropped(txids, reason);
+        }
+    }
+}
+
+/// What happened when the stored transactions were re-checked against a chain tip
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct MemPoolRevalidationStats {
+    pub num_checked: u64,
+    pub num_kept: u64,
+    pub dropped: Vec<(Txid, MemPoolDropReason)>,
+}
+
+impl MemPoolRevalidationStats {
+    /// Txids of the transactions that were dropped for the given reason
+    pub fn dropped_txids(&self, reason: MemPoolDropReason) -> Vec<Txid> {
+        self.dropped
+            .iter()
+            .filter(|(_, r)| *r == reason)
+            .map(|(txid, _)| txid.clone())
+            .collect()
+    }
+}
+
 pub struct MemPoolAdmitter {
     cur_block: BlockHeaderHash,
     cur_consensus_hash: ConsensusHash,
+    min_relay_fee_rate: u64,
 }
 
 impl MemPoolAdmitter {
@@ -69,9 +175,14 @@ impl MemPoolAdmitter {
         MemPoolAdmitter {
             cur_block,
             cur_consensus_hash,
+            min_relay_fee_rate: MINIMUM_TX_FEE_RATE_PER_BYTE,
         }
     }
 
+    pub fn set_min_relay_fee_rate(&mut self, min_relay_fee_rate: u64) {
+        self.min_relay_fee_rate = min_relay_fee_rate;
+    }
+
     pub fn set_block(&mut self, cur_block: &BlockHeaderHash, cur_consensus_hash: ConsensusHash) {
         self.cur_consensus_hash = cur_consensus_hash.clone();
         self.cur_block = cur_block.clone();
@@ -83,6 +194,11 @@ impl MemPoolAdmitter {
         tx: &StacksTransaction,
         tx_size: u64,
     ) -> Result<(), MemPoolRejection> {
+        // this node's relay policy may be stricter than the protocol's minimum fee rate
+        let min_fee = tx_size.saturating_mul(self.min_relay_fee_rate);
+        if tx.get_tx_fee() < min_fee {
+            return Err(MemPoolRejection::FeeTooLow(tx.get_tx_fee(), min_fee));
+        }
         chainstate.will_admit_mempool_tx(&self.cur_consensus_hash, &self.cur_block, tx, tx_size)
     }
 }
@@ -190,15 +306,50 @@ const MEMPOOL_SQL: &'static [&'static str] = &[
     "#,
 ];
 
+// Added after the original schema, so it's also applied to existing mempools when they're opened
+// (see `MemPoolDB::add_size_tracking_if_needed`).
+const MEMPOOL_SIZE_TRACKING_SQL: &'static str = r#"
+    -- fee per byte, indexed so the transaction to evict can be found without a table scan
+    ALTER TABLE mempool ADD COLUMN fee_rate REAL NOT NULL DEFAULT 0;
+    UPDATE mempool SET fee_rate = CAST(tx_fee AS REAL) / length;
+    CREATE INDEX by_fee_rate ON mempool(fee_rate ASC, accept_time DESC);
+
+    -- number and total length of the transactions in the mempool, and the number of times it has
+    -- changed (so derived data can be cached), kept up to date by triggers
+    CREATE TABLE mempool_size(
+        tx_count INTEGER NOT NULL,
+        total_bytes INTEGER NOT NULL,
+        change_count INTEGER NOT NULL
+    );
+    INSERT INTO mempool_size (tx_count, total_bytes, change_count)
+        SELECT COUNT(*), IFNULL(SUM(length), 0), 0 FROM mempool;
+    CREATE TRIGGER mempool_size_insert AFTER INSERT ON mempool
+    BEGIN
+        UPDATE mempool_size SET tx_count = tx_count + 1, total_bytes = total_bytes + NEW.length,
+            change_count = change_count + 1;
+    END;
+    CREATE TRIGGER mempool_size_delete AFTER DELETE ON mempool
+    BEGIN
+        UPDATE mempool_size SET tx_count = tx_count - 1, total_bytes = total_bytes - OLD.length,
+            change_count = change_count + 1;
+    END;
+"#;
+
 pub struct MemPoolDB {
     db: DBConn,
     path: String,
     admitter: MemPoolAdmitter,
+    settings: MemPoolSettings,
+    /// fee rate histogram, and the mempool change count it was computed at
+    fee_histogram_cache: Option<(u64, RPCMempoolFeeHistogram)>,
 }
 
 pub struct MemPoolTx<'a> {
     tx: DBTx<'a>,
     admitter: &'a mut MemPoolAdmitter,
+    settings: &'a MemPoolSettings,
+    /// transactions dropped so far, which are only announced once this commits
+    dropped_txs: Vec<(Txid, MemPoolDropReason)>,
 }
 
 impl<'a> Deref for MemPoolTx<'a> {
@@ -215,14 +366,56 @@ impl<'a> DerefMut for MemPoolTx<'a> {
 }
 
 impl<'a> MemPoolTx<'a> {
-    pub fn new(tx: DBTx<'a>, admitter: &'a mut MemPoolAdmitter) -> MemPoolTx<'a> {
-        MemPoolTx { tx, admitter }
+    pub fn new(
+        tx: DBTx<'a>,
+        admitter: &'a mut MemPoolAdmitter,
+        settings: &'a MemPoolSettings,
+    ) -> MemPoolTx<'a> {
+        MemPoolTx {
+            tx,
+            admitter,
+            settings,
+            dropped_txs: vec![],
+        }
     }
 
     pub fn commit(self) -> Result<(), db_error> {
         self.tx.commit().map_err(db_error::SqliteError)
     }
 
+    /// Commit, and then announce the transactions that were dropped to the observer, grouped
+    /// by reason.  If the commit fails, the transactions were not dropped after all, so nothing
+    /// is announced.
+    pub fn commit_and_announce(
+        self,
+        event_observer: Option<&dyn MemPoolEventDispatcher>,
+    ) -> Result<(), db_error> {
+        let dropped_txs = self.dropped_txs;
+        self.tx.commit().map_err(db_error::SqliteError)?;
+
+        let mut reasons = vec![];
+        for (_, reason) in dropped_txs.iter() {
+            if !reasons.contains(reason) {
+                reasons.push(*reason);
+            }
+        }
+        for reason in reasons.into_iter() {
+            let txids = dropped_txs
+                .iter()
+                .filter(|(_, r)| *r == reason)
+                .map(|(txid, _)| txid.clone())
+                .collect();
+            announce_dropped_txs(event_observer, txids, reason);
+        }
+        Ok(())
+    }
+
+    /// Record that transactions were dropped, to be announced once this commits
+    fn record_dropped_txs(&mut self, txids: Vec<Txid>, reason: MemPoolDropReason) {
+        self.dropped_txs
+            .extend(txids.into_iter().map(|txid| (txid, reason)));
+    }
+
     fn is_block_in_fork(
         &mut self,
         chainstate: &mut StacksChainState,
@@ -307,12 +500,55 @@ impl MemPoolDB {
         Ok(())
     }
 
-    /// Open the mempool db within the chainstate directory.
+    /// Does the mempool table have the fee rate column (and thus the size tracking table)?
+    fn has_size_tracking(conn: &DBConn) -> Result<bool, db_error> {
+        let mut s = conn
+            .prepare("PRAGMA table_info(mempool)")
+            .map_err(db_error::SqliteError)?;
+        let columns = s
+            .query_and_then(NO_PARAMS, |row| row.get::<_, String>("name"))
+            .map_err(db_error::SqliteError)?;
+        for column in columns {
+            if column.map_err(db_error::SqliteError)? == "fee_rate" {
+                return Ok(true);
+            }
+        }
+        Ok(false)
+    }
+
+    fn add_size_tracking_if_needed(conn: &mut DBConn) -> Result<(), db_error> {
+        let tx = tx_begin_immediate(conn)?;
+        if !MemPoolDB::has_size_tracking(&tx)? {
+            debug!("Add fee rate column and size tracking to mempool");
+            tx.execute_batch(MEMPOOL_SIZE_TRACKING_SQL)
+                .map_err(db_error::SqliteError)?;
+        }
+        tx.commit().map_err(db_error::SqliteError)?;
+        Ok(())
+    }
+
+    /// Open the mempool db within the chainstate directory, with the default admission settings.
     /// The chainstate must be instantiated already.
     pub fn open(
         mainnet: bool,
         chain_id: u32,
         chainstate_path: &str,
+    ) -> Result<MemPoolDB, db_error> {
+        MemPoolDB::open_with_settings(
+            mainnet,
+            chain_id,
+            chainstate_path,
+            MemPoolSettings::default(),
+        )
+    }
+
+    /// Open the mempool db within the chainstate directory.
+    /// The chainstate must be instantiated already.
+    pub fn open_with_settings(
+        mainnet: bool,
+        chain_id: u32,
+        chainstate_path: &str,
+        settings: MemPoolSettings,
     ) -> Result<MemPoolDB, db_error> {
         match fs::metadata(chainstate_path) {
             Ok(md) => {
@@ -328,9 +564,20 @@ impl MemPoolDB {
         let (chainstate, _) = StacksChainState::open(mainnet, chain_id, chainstate_path)
             .map_err(|e| db_error::Other(format!("Failed to open chainstate: {:?}", &e)))?;
 
+        MemPoolDB::open_with_chainstate(&chainstate, settings)
+    }
+
+    /// Open the mempool db within the directory of an already-open chainstate, without
+    /// re-opening the chainstate.
+    pub fn open_with_chainstate(
+        chainstate: &StacksChainState,
+        settings: MemPoolSettings,
+    ) -> Result<MemPoolDB, db_error> {
         let mut path = PathBuf::from(chainstate.root_path.clone());
 
-        let admitter = MemPoolAdmitter::new(BlockHeaderHash([0u8; 32]), ConsensusHash([0u8; 20]));
+        let mut admitter =
+            MemPoolAdmitter::new(BlockHeaderHash([0u8; 32]), ConsensusHash([0u8; 20]));
+        admitter.set_min_relay_fee_rate(settings.min_relay_fee_rate);
 
         path.push("mempool.db");
         let db_path = path
@@ -353,15 +600,22 @@ impl MemPoolDB {
         conn.busy_handler(Some(tx_busy_handler))
             .map_err(db_error::SqliteError)?;
 
+        // INSERT OR REPLACE must fire the delete trigger that keeps mempool_size up to date
+        conn.execute_batch("PRAGMA recursive_triggers = ON;")
+            .map_err(db_error::SqliteError)?;
+
         if create_flag {
             // instantiate!
             MemPoolDB::instantiate_mempool_db(&mut conn)?;
         }
+        MemPoolDB::add_size_tracking_if_needed(&mut conn)?;
 
         Ok(MemPoolDB {
             db: conn,
             path: db_path.to_string(),
             admitter: admitter,
+            settings: settings,
+            fee_histogram_cache: None,
         })
     }
 
@@ -572,9 +826,13 @@ impl MemPoolDB {
         &self.db
     }
 
+    pub fn settings(&self) -> &MemPoolSettings {
+        &self.settings
+    }
+
     pub fn tx_begin<'a>(&'a mut self) -> Result<MemPoolTx<'a>, db_error> {
         let tx = tx_begin_immediate(&mut self.db)?;
-        Ok(MemPoolTx::new(tx, &mut self.admitter))
+        Ok(MemPoolTx::new(tx, &mut self.admitter, &self.settings))
     }
 
     fn db_has_tx(conn: &DBConn, txid: &Txid) -> Result<bool, db_error> {
@@ -603,13 +861,73 @@ impl MemPoolDB {
     }
 
     /// Get all transactions across all tips
-    #[cfg(test)]
     pub fn get_all_txs(conn: &DBConn) -> Result<Vec<MemPoolTxInfo>, db_error> {
         let sql = "SELECT * FROM mempool";
         let rows = query_rows::<MemPoolTxInfo, _>(conn, &sql, NO_PARAMS)?;
         Ok(rows)
     }
 
+    /// Get the txids of all transactions across all tips
+    pub fn get_all_txids(conn: &DBConn) -> Result<Vec<Txid>, db_error> {
+        let sql = "SELECT txid FROM mempool";
+        let mut stmt = conn.prepare(sql).map_err(db_error::SqliteError)?;
+        let mut rows = stmt.query(NO_PARAMS).map_err(db_error::SqliteError)?;
+
+        let mut txids = vec![];
+        while let Some(row) = rows.next().map_err(|e| db_error::SqliteError(e))? {
+            let txid = Txid::from_column(&row, "txid")?;
+            txids.push(txid);
+        }
+        Ok(txids)
+    }
+
+    /// Make a bloom filter over all of the txids in the mempool, so a peer can tell us which
+    /// transactions we're missing.
+    pub fn make_mempool_sync_data(conn: &DBConn, seed: u32) -> Result<MemPoolSyncData, db_error> {
+        let txids = MemPoolDB::get_all_txids(conn)?;
+        let mut sync_data = MemPoolSyncData::new(seed, txids.len() as u64);
+        for txid in txids.iter() {
+            sync_data.insert(txid);
+        }
+        Ok(sync_data)
+    }
+
+    /// Find the transactions in the mempool that are not in the given bloom filter.
+    /// Transactions are considered in descending order of estimated fee, and at most `max_txs`
+    /// transactions totalling at most `max_bytes` bytes are returned.
+    pub fn find_missing_transactions(
+        conn: &DBConn,
+        sync_data: &MemPoolSyncData,
+        max_txs: u64,
+        max_bytes: u64,
+    ) -> Result<Vec<StacksTransaction>, db_error> {
+        let sql = "SELECT * FROM mempool ORDER BY estimated_fee DESC";
+        let mut stmt = conn.prepare(sql).map_err(db_error::SqliteError)?;
+        let mut rows = stmt.query(NO_PARAMS).map_err(db_error::SqliteError)?;
+
+        let mut txs = vec![];
+        let mut total_bytes = 0;
+        while let Some(row) = rows.next().map_err(|e| db_error::SqliteError(e))? {
+            if (txs.len() as u64) >= max_txs {
+                break;
+            }
+
+            let txid = Txid::from_column(&row, "txid")?;
+            if sync_data.contains(&txid) {
+                continue;
+            }
+
+            let tx_info = MemPoolTxInfo::from_row(&row)?;
+            if total_bytes + tx_info.metadata.len > max_bytes {
+                continue;
+            }
+
+            total_bytes += tx_info.metadata.len;
+            txs.push(tx_info.tx);
+        }
+        Ok(txs)
+    }
+
     /// Get the next timestamp after this one that occurs in this chain tip.
     pub fn get_next_timestamp(
         conn: &DBConn,
@@ -740,8 +1058,135 @@ impl MemPoolDB {
         Ok(cmp::max(as_origin, as_sponsor))
     }
 
-    /// Add a transaction to the mempool.  If it already exists, then replace it if the given fee
-    /// is higher than the one that's already there.
+    /// Get a page of the transactions that the given address originates or sponsors, ordered by
+    /// nonce.
+    pub fn get_txs_for_address(
+        conn: &DBConn,
+        addr: &StacksAddress,
+        offset: u64,
+        limit: u64,
+    ) -> Result<Vec<MemPoolTxMetadata>, db_error> {
+        let sql = "SELECT
+                          txid,
+                          origin_address,
+                          origin_nonce,
+                          sponsor_address,
+                          sponsor_nonce,
+                          estimated_fee,
+                          tx_fee,
+                          length,
+                          consensus_hash,
+                          block_header_hash,
+                          height,
+                          accept_time
+                          FROM mempool WHERE origin_address = ?1 OR sponsor_address = ?1
+                          ORDER BY origin_nonce ASC, sponsor_nonce ASC, txid ASC LIMIT ?2 OFFSET ?3";
+        let args: &[&dyn ToSql] = &[&addr.to_string(), &u64_to_sql(limit)?, &u64_to_sql(offset)?];
+        query_rows(conn, sql, args)
+    }
+
+    /// Get every transaction that spends the given nonce of the given address, either as the
+    /// origin or as the sponsor.  There can be one of each.
+    fn get_txs_spending_nonce(
+        conn: &DBConn,
+        addr: &StacksAddress,
+        nonce: u64,
+    ) -> Result<Vec<MemPoolTxMetadata>, db_error> {
+        let sql = "SELECT
+                          txid,
+                          origin_address,
+                          origin_nonce,
+                          sponsor_address,
+                          sponsor_nonce,
+                          estimated_fee,
+                          tx_fee,
+                          length,
+                          consensus_hash,
+                          block_header_hash,
+                          height,
+                          accept_time
+                          FROM mempool WHERE (origin_address = ?1 AND origin_nonce = ?2)
+                          OR (sponsor_address = ?1 AND sponsor_nonce = ?2)";
+        let args: &[&dyn ToSql] = &[&addr.to_string(), &u64_to_sql(nonce)?];
+        query_rows(conn, sql, args)
+    }
+
+    /// Does a transaction in the mempool spend the given nonce of the given address, either as
+    /// the origin or as the sponsor?
+    fn is_nonce_pending(conn: &DBConn, addr: &StacksAddress, nonce: u64) -> Result<bool, db_error> {
+        let sql = "SELECT 1 FROM mempool WHERE (origin_address = ?1 AND origin_nonce = ?2) OR (sponsor_address = ?1 AND sponsor_nonce = ?2) LIMIT 1";
+        let args: &[&dyn ToSql] = &[&addr.to_string(), &u64_to_sql(nonce)?];
+        conn.query_row(sql, args, |_row| Ok(()))
+            .optional()
+            .map(|row_opt| row_opt.is_some())
+            .map_err(db_error::SqliteError)
+    }
+
+    /// Get the next nonce the given address can use, given its confirmed nonce.  This is the
+    /// first nonce at or after the confirmed nonce that no transaction in the mempool already
+    /// spends, so a transaction with this nonce extends the address's chain of pending
+    /// transactions without leaving a gap.
+    pub fn get_next_chained_nonce(
+        conn: &DBConn,
+        addr: &StacksAddress,
+        confirmed_nonce: u64,
+    ) -> Result<u64, db_error> {
+        let mut next_nonce = confirmed_nonce;
+        while MemPoolDB::is_nonce_pending(conn, addr, next_nonce)? {
+            next_nonce += 1;
+        }
+        Ok(next_nonce)
+    }
+
+    /// Get the number of times transactions have been added to or removed from the mempool.
+    /// Data derived from the mempool's contents stays valid until this changes.
+    pub fn get_change_count(conn: &DBConn) -> Result<u64, db_error> {
+        let sql = "SELECT change_count FROM mempool_size";
+        conn.query_row(sql, NO_PARAMS, |row| {
+            let change_count: i64 = row.get_unwrap(0);
+            Ok(change_count as u64)
+        })
+        .map_err(db_error::SqliteError)
+    }
+
+    /// Get the fee rate histogram cached at the given change count, if there is one
+    pub fn get_cached_fee_histogram(&self, change_count: u64) -> Option<&RPCMempoolFeeHistogram> {
+        match self.fee_histogram_cache {
+            Some((cached_change_count, ref histogram)) if cached_change_count == change_count => {
+                Some(histogram)
+            }
+            _ => None,
+        }
+    }
+
+    /// Cache the fee rate histogram computed at the given change count
+    pub fn set_cached_fee_histogram(
+        &mut self,
+        change_count: u64,
+        histogram: RPCMempoolFeeHistogram,
+    ) {
+        self.fee_histogram_cache = Some((change_count, histogram));
+    }
+
+    /// Get the fee and length of every transaction in the mempool
+    pub fn get_fees_and_lengths(conn: &DBConn) -> Result<Vec<(u64, u64)>, db_error> {
+        let sql = "SELECT tx_fee, length FROM mempool";
+        let mut stmt = conn.prepare(sql).map_err(db_error::SqliteError)?;
+        let mut rows = stmt.query(NO_PARAMS).map_err(db_error::SqliteError)?;
+
+        let mut fees_and_lengths = vec![];
+        while let Some(row) = rows.next().map_err(|e| db_error::SqliteError(e))? {
+            let tx_fee = u64::from_column(&row, "tx_fee")?;
+            let length = u64::from_column(&row, "length")?;
+            fees_and_lengths.push((tx_fee, length));
+        }
+        Ok(fees_and_lengths)
+    }
+
+    /// Add a transaction to the mempool.  If there's already a transaction with the same origin or
+    /// sponsor nonce in this fork, then replace it if the given fee rate outbids it by at least the
+    /// configured minimum bump.  Transactions with the same nonces in other forks are always
+    /// replaced.  Returns the txids of the replaced transactions, and how they were replaced.
     /// Carry out the mempool admission test before adding.
     /// Don't call directly; use submit()
     fn try_add_tx<'a>(
@@ -758,56 +1203,77 @@ impl MemPoolDB {
         origin_nonce: u64,
         sponsor_address: &StacksAddress,
         sponsor_nonce: u64,
-    ) -> Result<(), MemPoolRejection> {
+    ) -> Result<Vec<(Txid, MemPoolDropReason)>, MemPoolRejection> {
         let length = tx_bytes.len() as u64;
 
         // do we already have txs with either the same origin nonce or sponsor nonce ?
-        let prior_tx = {
-            match MemPoolDB::get_tx_metadata_by_address(tx, true, origin_address, origin_nonce)? {
-                Some(prior_tx) => Some(prior_tx),
-                None => MemPoolDB::get_tx_metadata_by_address(
-                    tx,
-                    false,
-                    sponsor_address,
-                    sponsor_nonce,
-                )?,
+        let mut prior_txs =
+            MemPoolDB::get_tx_metadata_by_address(tx, true, origin_address, origin_nonce)?
+                .into_iter()
+                .collect::<Vec<_>>();
+        if let Some(prior_sponsor_tx) =
+            MemPoolDB::get_tx_metadata_by_address(tx, false, sponsor_address, sponsor_nonce)?
+        {
+            if prior_txs
+                .iter()
+                .all(|prior_tx| prior_tx.txid != prior_sponsor_tx.txid)
+            {
+                prior_txs.push(prior_sponsor_tx);
             }
-        };
+        }
 
         // if so, is this a replace-by-fee? or a replace-in-chain-tip?
-        let add_tx = if let Some(prior_tx) = prior_tx {
-            if estimated_fee > prior_tx.estimated_fee {
-                // is this a replace-by-fee ?
-                true
-            } else if !tx.is_block_in_fork(
+        let mut replaced = vec![];
+        for prior_tx in prior_txs.iter() {
+            let min_bump_pct = tx.settings.replace_by_fee_min_bump_pct;
+            let min_replace_fee = MemPoolDB::get_min_replace_fee(prior_tx, length, min_bump_pct);
+            if tx_fee >= min_replace_fee {
+                // this is a replace-by-fee
+                replaced.push((prior_tx.txid.clone(), MemPoolDropReason::ReplaceByFee));
+                continue;
+            }
+            if !tx.is_block_in_fork(
                 chainstate,
                 &prior_tx.consensus_hash,
                 &prior_tx.block_header_hash,
                 consensus_hash,
                 block_header_hash,
             )? {
-                // is this a replace-across-fork ?
-                true
+                // this is a replace-across-fork
+                replaced.push((prior_tx.txid.clone(), MemPoolDropReason::ReplaceAcrossFork));
+                continue;
+            }
+
+            // there's a tx in this fork that this one does not sufficiently outbid
+            info!("TX conflicts with sponsor/origin nonce in same fork without a sufficient fee bump";
+                  "new_txid" => %txid,
+                  "old_txid" => %prior_tx.txid,
+                  "origin_addr" => %origin_address,
+                  "origin_nonce" => origin_nonce,
+                  "sponsor_addr" => %sponsor_address,
+                  "sponsor_nonce" => sponsor_nonce,
+                  "new_fee" => tx_fee,
+                  "old_fee" => prior_tx.tx_fee,
+                  "min_replace_fee" => min_replace_fee);
+
+            if (tx_fee as u128) * (prior_tx.len as u128)
+                > (prior_tx.tx_fee as u128) * (length as u128)
+            {
+                // it outbids the prior tx, just not by enough
+                return Err(MemPoolRejection::InsufficientReplaceFee {
+                    prior_txid: prior_tx.txid.clone(),
+                    min_fee: min_replace_fee,
+                    actual_fee: tx_fee,
+                });
             } else {
-                // there's a >= fee tx in this fork, cannot add
-                info!("TX conflicts with sponsor/origin nonce in same fork with >= fee";
-                      "new_txid" => %txid, 
-                      "old_txid" => %prior_tx.txid,
-                      "origin_addr" => %origin_address,
-                      "origin_nonce" => origin_nonce,
-                      "sponsor_addr" => %sponsor_address,
-                      "sponsor_nonce" => sponsor_nonce,
-                      "new_fee" => estimated_fee,
-                      "old_fee" => prior_tx.estimated_fee);
-                false
+                return Err(MemPoolRejection::ConflictingNonceInMempool);
             }
-        } else {
-            // no conflicting TX with this origin/sponsor, go ahead and add
-            true
-        };
+        }
 
-        if !add_tx {
-            return Err(MemPoolRejection::ConflictingNonceInMempool);
+        // evict whatever this tx replaces
+        for (prior_txid, _) in replaced.iter() {
+            debug!("Replace mempool tx {} with {}", prior_txid, &txid);
+            MemPoolDB::remove_tx(tx, prior_txid)?;
         }
 
         let sql = "INSERT OR REPLACE INTO mempool (
@@ -823,8 +1289,11 @@ impl MemPoolDB {
             block_header_hash,
             height,
             accept_time,
-            tx)
-            VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13)";
+            tx,
+            fee_rate)
+            VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14)";
+
+        let fee_rate = (tx_fee as f64) / (cmp::max(length, 1) as f64);
 
         let args: &[&dyn ToSql] = &[
             &txid,
@@ -840,19 +1309,174 @@ impl MemPoolDB {
             &u64_to_sql(height)?,
             &u64_to_sql(get_epoch_time_secs())?,
             &tx_bytes,
+            &fee_rate,
         ];
 
         tx.execute(sql, args)
             .map_err(|e| MemPoolRejection::DBError(db_error::SqliteError(e)))?;
+        Ok(replaced)
+    }
+
+    /// Get the smallest fee that a transaction of the given length must pay in order to replace
+    /// the given transaction in the same fork.  Its fee rate must exceed the prior transaction's
+    /// fee rate by at least `min_bump_pct` percent, and must be strictly higher.
+    pub fn get_min_replace_fee(prior_tx: &MemPoolTxMetadata, len: u64, min_bump_pct: u64) -> u64 {
+        let prior_len = cmp::max(prior_tx.len, 1) as u128;
+        let scaled_fee = (prior_tx.tx_fee as u128) * (len as u128);
+
+        // smallest fee whose rate is strictly higher than the prior tx's
+        let higher_fee = scaled_fee / prior_len + 1;
+
+        // smallest fee whose rate is at least min_bump_pct percent higher than the prior tx's
+        let bump_denom = prior_len * 100;
+        let bumped_fee = scaled_fee
+            .saturating_mul(100 + min_bump_pct as u128)
+            .saturating_add(bump_denom - 1)
+            / bump_denom;
+
+        let min_fee = cmp::max(higher_fee, bumped_fee);
+        if min_fee > (u64::max_value() as u128) {
+            u64::max_value()
+        } else {
+            min_fee as u64
+        }
+    }
+
+    /// Get the number of transactions in the mempool, and their total size
+    pub fn get_size(conn: &DBConn) -> Result<(u64, u64), db_error> {
+        let sql = "SELECT tx_count, total_bytes FROM mempool_size";
+        conn.query_row(sql, NO_PARAMS, |row| {
+            let count: i64 = row.get_unwrap(0);
+            let bytes: i64 = row.get_unwrap(1);
+            Ok((count as u64, bytes as u64))
+        })
+        .map_err(db_error::SqliteError)
+    }
+
+    /// Get the metadata of the transaction with the lowest fee rate.  Ties go to the most recently
+    /// accepted transaction.
+    fn get_lowest_fee_rate_tx_metadata(
+        conn: &DBConn,
+    ) -> Result<Option<MemPoolTxMetadata>, db_error> {
+        let sql = "SELECT
+                          txid,
+                          origin_address,
+                          origin_nonce,
+                          sponsor_address,
+                          sponsor_nonce,
+                          estimated_fee,
+                          tx_fee,
+                          length,
+                          consensus_hash,
+                          block_header_hash,
+                          height,
+                          accept_time
+                          FROM mempool ORDER BY fee_rate ASC, accept_time DESC LIMIT 1";
+        query_row(conn, sql, NO_PARAMS)
+    }
+
+    /// Get the metadata of every transaction that spends the given nonce, or a later one, of the
+    /// given account, either as the origin or as the sponsor.
+    fn get_nonce_chain_tx_metadata(
+        conn: &DBConn,
+        addr: &StacksAddress,
+        nonce: u64,
+    ) -> Result<Vec<MemPoolTxMetadata>, db_error> {
+        let sql = "SELECT
+                          txid,
+                          origin_address,
+                          origin_nonce,
+                          sponsor_address,
+                          sponsor_nonce,
+                          estimated_fee,
+                          tx_fee,
+                          length,
+                          consensus_hash,
+                          block_header_hash,
+                          height,
+                          accept_time
+                          FROM mempool WHERE (origin_address = ?1 AND origin_nonce >= ?2) OR (sponsor_address = ?1 AND sponsor_nonce >= ?2)";
+        let args: &[&dyn ToSql] = &[&addr.to_string(), &u64_to_sql(nonce)?];
+        query_rows(conn, sql, args)
+    }
+
+    /// Remove a transaction from the mempool, as well as every transaction that can't be mined
+    /// without it (i.e. those that spend a later nonce of its origin or sponsor).
+    /// Returns the txids of all removed transactions.
+    fn remove_tx_and_descendants<'a>(
+        tx: &mut MemPoolTx<'a>,
+        tx_metadata: &MemPoolTxMetadata,
+    ) -> Result<Vec<Txid>, db_error> {
+        let mut removed = vec![];
+        let mut frontier = vec![tx_metadata.clone()];
+        while let Some(next_tx) = frontier.pop() {
+            if removed.contains(&next_tx.txid) {
+                continue;
+            }
+            MemPoolDB::remove_tx(tx, &next_tx.txid)?;
+            removed.push(next_tx.txid.clone());
+
+            frontier.append(&mut MemPoolDB::get_nonce_chain_tx_metadata(
+                tx,
+                &next_tx.origin_address,
+                next_tx.origin_nonce,
+            )?);
+            frontier.append(&mut MemPoolDB::get_nonce_chain_tx_metadata(
+                tx,
+                &next_tx.sponsor_address,
+                next_tx.sponsor_nonce,
+            )?);
+        }
+        Ok(removed)
+    }
+
+    /// Evict the lowest fee-rate transactions (and their descendants) until the mempool is back
+    /// under its configured size limits.  Returns the txids of all evicted transactions.
+    pub fn enforce_size_limits<'a>(tx: &mut MemPoolTx<'a>) -> Result<Vec<Txid>, db_error> {
+        let max_tx_count = tx.settings.max_tx_count;
+        let max_bytes = tx.settings.max_bytes;
+
+        let mut evicted = vec![];
+        loop {
+            let (count, bytes) = MemPoolDB::get_size(tx)?;
+            if count <= max_tx_count && bytes <= max_bytes {
+                break;
+            }
+
+            let lowest_tx = match MemPoolDB::get_lowest_fee_rate_tx_metadata(tx)? {
+                Some(lowest_tx) => lowest_tx,
+                None => {
+                    break;
+                }
+            };
+
+            debug!(
+                "Mempool is full ({} txs, {} bytes); evict {} (fee {}, length {})",
+                count, bytes, &lowest_tx.txid, lowest_tx.tx_fee, lowest_tx.len
+            );
+            evicted.append(&mut MemPoolDB::remove_tx_and_descendants(tx, &lowest_tx)?);
+        }
+        Ok(evicted)
+    }
+
+    /// Remove a transaction from the mempool
+    fn remove_tx<'a>(tx: &mut MemPoolTx<'a>, txid: &Txid) -> Result<(), db_error> {
+        let sql = "DELETE FROM mempool WHERE txid = ?1";
+        let args: &[&dyn ToSql] = &[txid];
+        tx.execute(sql, args).map_err(db_error::SqliteError)?;
         Ok(())
     }
 
     /// Garbage-collect the mempool.  Remove transactions that have a given number of
-    /// confirmations.
+    /// confirmations.  They are announced to the event observer once `tx` commits.
     pub fn garbage_collect<'a>(tx: &mut MemPoolTx<'a>, min_height: u64) -> Result<(), db_error> {
-        let sql = "DELETE FROM mempool WHERE height < ?1";
         let args: &[&dyn ToSql] = &[&u64_to_sql(min_height)?];
 
+        let sql = "SELECT txid FROM mempool WHERE height < ?1".to_string();
+        let txids = query_row_columns(tx, &sql, args, "txid")?;
+        tx.record_dropped_txs(txids, MemPoolDropReason::StaleGarbageCollect);
+
+        let sql = "DELETE FROM mempool WHERE height < ?1";
         tx.execute(sql, args).map_err(db_error::SqliteError)?;
         Ok(())
     }
@@ -864,35 +1488,170 @@ impl MemPoolDB {
         Ok(())
     }
 
-    /// Scan the chain tip for all available transactions (but do not remove them!)
-    pub fn poll(
+    /// Remove the transactions that can never be mined on top of a newly-processed canonical
+    /// Stacks tip, because they spend an origin or sponsor nonce that one of the block's
+    /// transactions (or one of the microblock transactions it confirms) already spent.  Only
+    /// call this for the canonical tip: the mempool holds transactions for other forks too.
+    /// Returns their txids.
+    pub fn drop_conflicting_nonces(
         &mut self,
-        consensus_hash: &ConsensusHash,
-        block_hash: &BlockHeaderHash,
-    ) -> Vec<StacksTransaction> {
-        test_debug!("Mempool poll at {}/{}", consensus_hash, block_hash);
-        MemPoolDB::get_txs_after(
-            &self.db,
-            consensus_hash,
-            block_hash,
-            0,
-            (i64::max_value() - 1) as u64,
-        )
-        .unwrap_or(vec![])
-        .into_iter()
-        .map(|tx_info| {
-            test_debug!(
-                "Mempool poll {} at {}/{}",
-                &tx_info.tx.txid(),
-                consensus_hash,
-                block_hash
-            );
-            tx_info.tx
-        })
-        .collect()
-    }
+        confirmed_txs: &[StacksTransaction],
+        event_observer: Option<&dyn MemPoolEventDispatcher>,
+    ) -> Result<Vec<Txid>, db_error> {
+        let mut mempool_tx = self.tx_begin()?;
+        let mut dropped = vec![];
+        for confirmed_tx in confirmed_txs.iter() {
+            let confirmed_txid = confirmed_tx.txid();
+
+            // a spent nonce conflicts with mempool transactions that spend it as the origin or
+            // as the sponsor, regardless of which one the confirmed transaction spent it as
+            let mut spent_nonces = vec![(
+                confirmed_tx.origin_address(),
+                confirmed_tx.get_origin_nonce(),
+            )];
+            if let (Some(sponsor_address), Some(sponsor_nonce)) = (
+                confirmed_tx.sponsor_address(),
+                confirmed_tx.get_sponsor_nonce(),
+            ) {
+                spent_nonces.push((sponsor_address, sponsor_nonce));
+            }
+
+            for (address, nonce) in spent_nonces.into_iter() {
+                for conflict in
+                    MemPoolDB::get_txs_spending_nonce(&mempool_tx, &address, nonce)?.into_iter()
+                {
+                    if conflict.txid != confirmed_txid && !dropped.contains(&conflict.txid) {
+                        debug!(
+                            "Drop mempool tx {}, since {} confirmed {}'s nonce {}",
+                            &conflict.txid, &confirmed_txid, &address, nonce
+                        );
+                        MemPoolDB::remove_tx(&mut mempool_tx, &conflict.txid)?;
+                        dropped.push(conflict.txid);
+                    }
+                }
+            }
+        }
+        mempool_tx.commit()?;
+
+        announce_dropped_txs(
+            event_observer,
+            dropped.clone(),
+            MemPoolDropReason::ConflictingNonceConfirmed,
+        );
+        Ok(dropped)
+    }
+
+    /// Re-run the admission checks on every stored transaction against the given chain tip, and
+    /// drop the ones that can no longer be mined on it.  The mempool outlives the node process,
+    /// so this is meant to be run on startup, once the chain tip the stored transactions were
+    /// admitted against may have moved on.  Dropped transactions are announced to the
+    /// observer, grouped by reason.
+    pub fn revalidate(
+        &mut self,
+        chainstate: &mut StacksChainState,
+        tip_consensus_hash: &ConsensusHash,
+        tip_block_hash: &BlockHeaderHash,
+        event_observer: Option<&dyn MemPoolEventDispatcher>,
+    ) -> Result<MemPoolRevalidationStats, db_error> {
+        let tip_height = match chainstate
+            .get_stacks_block_height(tip_consensus_hash, tip_block_hash)
+            .map_err(|e| db_error::Other(format!("Failed to load chain tip: {:?}", &e)))?
+        {
+            Some(height) => height,
+            None => {
+                if *tip_consensus_hash == FIRST_BURNCHAIN_CONSENSUS_HASH {
+                    0
+                } else {
+                    return Err(db_error::NotFoundError);
+                }
+            }
+        };
+        let min_height = tip_height.saturating_sub(MEMPOOL_MAX_TRANSACTION_AGE);
+
+        let mut stats = MemPoolRevalidationStats::default();
+        let candidates = MemPoolDB::get_all_txs(&self.db)?;
+
+        let mut mempool_tx = self.tx_begin()?;
+        mempool_tx
+            .admitter
+            .set_block(tip_block_hash, tip_consensus_hash.clone());
 
-    /// Submit a transaction to the mempool at a particular chain tip.
+        for candidate in candidates.into_iter() {
+            stats.num_checked += 1;
+            let txid = candidate.metadata.txid;
+
+            let reason = if candidate.metadata.block_height < min_height {
+                MemPoolDropReason::StaleGarbageCollect
+            } else {
+                match mempool_tx.admitter.will_admit_tx(
+                    chainstate,
+                    &candidate.tx,
+                    candidate.metadata.len,
+                ) {
+                    Ok(()) => {
+                        stats.num_kept += 1;
+                        continue;
+                    }
+                    Err(MemPoolRejection::BadNonces(ref mismatch))
+                        if mismatch.actual < mismatch.expected =>
+                    {
+                        MemPoolDropReason::ConflictingNonceConfirmed
+                    }
+                    Err(e) => {
+                        debug!("Mempool tx {} is no longer admissible: {:?}", &txid, &e);
+                        MemPoolDropReason::Invalid
+                    }
+                }
+            };
+
+            MemPoolDB::remove_tx(&mut mempool_tx, &txid)?;
+            stats.dropped.push((txid, reason));
+        }
+        mempool_tx.commit()?;
+
+        for reason in [
+            MemPoolDropReason::StaleGarbageCollect,
+            MemPoolDropReason::ConflictingNonceConfirmed,
+            MemPoolDropReason::Invalid,
+        ]
+        .iter()
+        {
+            announce_dropped_txs(event_observer, stats.dropped_txids(*reason), *reason);
+        }
+
+        Ok(stats)
+    }
+
+    /// Scan the chain tip for all available transactions (but do not remove them!)
+    pub fn poll(
+        &mut self,
+        consensus_hash: &ConsensusHash,
+        block_hash: &BlockHeaderHash,
+    ) -> Vec<StacksTransaction> {
+        test_debug!("Mempool poll at {}/{}", consensus_hash, block_hash);
+        MemPoolDB::get_txs_after(
+            &self.db,
+            consensus_hash,
+            block_hash,
+            0,
+            (i64::max_value() - 1) as u64,
+        )
+        .unwrap_or(vec![])
+        .into_iter()
+        .map(|tx_info| {
+            test_debug!(
+                "Mempool poll {} at {}/{}",
+                &tx_info.tx.txid(),
+                consensus_hash,
+                block_hash
+            );
+            tx_info.tx
+        })
+        .collect()
+    }
+
+    /// Submit a transaction to the mempool at a particular chain tip.  Transactions it replaces or
+    /// evicts are announced to the event observer once `mempool_tx` commits.
     pub fn tx_submit(
         mempool_tx: &mut MemPoolTx,
         chainstate: &mut StacksChainState,
@@ -956,7 +1715,7 @@ impl MemPoolDB {
             mempool_tx.admitter.will_admit_tx(chainstate, tx, len)?;
         }
 
-        MemPoolDB::try_add_tx(
+        let replaced = MemPoolDB::try_add_tx(
             mempool_tx,
             chainstate,
             &consensus_hash,
@@ -972,6 +1731,15 @@ impl MemPoolDB {
             sponsor_nonce,
         )?;
 
+        // make room, if need be
+        let evicted = MemPoolDB::enforce_size_limits(mempool_tx)?;
+        if evicted.contains(&txid) {
+            return Err(MemPoolRejection::MemPoolFull);
+        }
+
+        mempool_tx.dropped_txs.extend(replaced.into_iter());
+        mempool_tx.record_dropped_txs(evicted, MemPoolDropReason::MemPoolFull);
+
         Ok(())
     }
 
@@ -982,6 +1750,7 @@ impl MemPoolDB {
         consensus_hash: &ConsensusHash,
         block_hash: &BlockHeaderHash,
         tx: &StacksTransaction,
+        event_observer: Option<&dyn MemPoolEventDispatcher>,
     ) -> Result<(), MemPoolRejection> {
         let mut mempool_tx = self.tx_begin().map_err(MemPoolRejection::DBError)?;
         MemPoolDB::tx_submit(
@@ -992,7 +1761,9 @@ impl MemPoolDB {
             tx,
             true,
         )?;
-        mempool_tx.commit().map_err(MemPoolRejection::DBError)?;
+        mempool_tx
+            .commit_and_announce(event_observer)
+            .map_err(MemPoolRejection::DBError)?;
         Ok(())
     }
 
@@ -1064,13 +1835,23 @@ mod tests {
         C32_ADDRESS_VERSION_TESTNET_SINGLESIG,
     };
 
-    use super::MemPoolDB;
-    use util::db::{DBConn, FromRow};
+    use super::{
+        MemPoolAdmitter, MemPoolDB, MemPoolDropReason, MemPoolEventDispatcher, MemPoolSettings,
+        MemPoolTxMetadata, MEMPOOL_SQL,
+    };
+    use burnchains::Txid;
+    use net::MemPoolSyncData;
+    use net::RPCMempoolFeeHistogram;
+    use rusqlite::NO_PARAMS;
+    use std::cell::RefCell;
+    use util::db::{DBConn, Error as db_error, FromRow};
 
     use chainstate::burn::ConsensusHash;
     use chainstate::stacks::db::test::chainstate_path;
     use chainstate::stacks::db::test::instantiate_chainstate;
     use chainstate::stacks::db::test::instantiate_chainstate_with_balances;
+    use chainstate::stacks::miner::test::make_sponsored_stacks_transfer;
+    use chainstate::stacks::miner::test::make_user_stacks_transfer;
     use chainstate::stacks::test::codec_all_transactions;
     use core::FIRST_BURNCHAIN_CONSENSUS_HASH;
     use core::FIRST_STACKS_BLOCK_HASH;
@@ -1254,6 +2035,560 @@ mod tests {
         assert!(!MemPoolDB::db_has_tx(&mempool_tx, &txid).unwrap());
     }
 
+    #[test]
+    fn mempool_min_replace_fee() {
+        let mut prior_tx = MemPoolTxMetadata {
+            txid: Txid([0x01; 32]),
+            len: 100,
+            tx_fee: 1000,
+            estimated_fee: 100000,
+            consensus_hash: ConsensusHash([0x1; 20]),
+            block_header_hash: BlockHeaderHash([0x2; 32]),
+            block_height: 1,
+            origin_address: StacksAddress {
+                version: 22,
+                bytes: Hash160([0x3; 20]),
+            },
+            origin_nonce: 0,
+            sponsor_address: StacksAddress {
+                version: 22,
+                bytes: Hash160([0x3; 20]),
+            },
+            sponsor_nonce: 0,
+            accept_time: 0,
+        };
+
+        // no bump required -- just a strictly higher fee rate
+        assert_eq!(MemPoolDB::get_min_replace_fee(&prior_tx, 100, 0), 1001);
+
+        // 10% bump, at the same length and at double the length
+        assert_eq!(MemPoolDB::get_min_replace_fee(&prior_tx, 100, 10), 1100);
+        assert_eq!(MemPoolDB::get_min_replace_fee(&prior_tx, 200, 10), 2200);
+
+        // a shorter replacement can pay a lower absolute fee
+        assert_eq!(MemPoolDB::get_min_replace_fee(&prior_tx, 50, 10), 550);
+
+        // rounds up
+        prior_tx.tx_fee = 123;
+        assert_eq!(MemPoolDB::get_min_replace_fee(&prior_tx, 100, 10), 136);
+    }
+
+    #[test]
+    fn mempool_evict_lowest_fee_rate_txs() {
+        let mut chainstate =
+            instantiate_chainstate(false, 0x80000000, "mempool_evict_lowest_fee_rate_txs");
+        let chainstate_path = chainstate_path("mempool_evict_lowest_fee_rate_txs");
+
+        let mut settings = MemPoolSettings::default();
+        settings.max_tx_count = 3;
+        let mut mempool =
+            MemPoolDB::open_with_settings(false, 0x80000000, &chainstate_path, settings).unwrap();
+
+        let mut txs = codec_all_transactions(
+            &TransactionVersion::Testnet,
+            0x80000000,
+            &TransactionAnchorMode::Any,
+            &TransactionPostConditionMode::Allow,
+        );
+        let template_tx = txs.pop().unwrap();
+
+        let byte_limited_settings = MemPoolSettings {
+            max_bytes: 1,
+            ..MemPoolSettings::default()
+        };
+
+        let mut mempool_tx = mempool.tx_begin().unwrap();
+
+        // (origin account, origin nonce, fee).  All txs have the same length, so the account 0's
+        // first tx has the lowest fee rate, and evicting it must also evict its second tx.
+        let tx_specs: Vec<(u8, u64, u64)> =
+            vec![(0, 0, 100), (0, 1, 1000), (1, 0, 500), (2, 0, 400)];
+        let mut txids = vec![];
+        for (account, nonce, fee) in tx_specs.into_iter() {
+            let mut tx = template_tx.clone();
+            tx.set_tx_fee(fee);
+
+            let txid = tx.txid();
+            let tx_bytes = tx.serialize_to_vec();
+            let len = tx_bytes.len() as u64;
+            let addr = StacksAddress {
+                version: 22,
+                bytes: Hash160([account; 20]),
+            };
+
+            MemPoolDB::try_add_tx(
+                &mut mempool_tx,
+                &mut chainstate,
+                &ConsensusHash([0x1; 20]),
+                &BlockHeaderHash([0x2; 32]),
+                txid.clone(),
+                tx_bytes,
+                fee * len,
+                fee,
+                100,
+                &addr,
+                nonce,
+                &addr,
+                nonce,
+            )
+            .unwrap();
+            txids.push(txid);
+        }
+
+        assert_eq!(MemPoolDB::get_size(&mempool_tx).unwrap().0, 4);
+
+        let mut evicted = MemPoolDB::enforce_size_limits(&mut mempool_tx).unwrap();
+        evicted.sort();
+        let mut expected_evicted = vec![txids[0].clone(), txids[1].clone()];
+        expected_evicted.sort();
+        assert_eq!(evicted, expected_evicted);
+
+        assert_eq!(MemPoolDB::get_size(&mempool_tx).unwrap().0, 2);
+        assert!(!MemPoolDB::db_has_tx(&mempool_tx, &txids[0]).unwrap());
+        assert!(!MemPoolDB::db_has_tx(&mempool_tx, &txids[1]).unwrap());
+        assert!(MemPoolDB::db_has_tx(&mempool_tx, &txids[2]).unwrap());
+        assert!(MemPoolDB::db_has_tx(&mempool_tx, &txids[3]).unwrap());
+
+        // nothing more to evict
+        assert_eq!(
+            MemPoolDB::enforce_size_limits(&mut mempool_tx)
+                .unwrap()
+                .len(),
+            0
+        );
+
+        // the byte limit is enforced too
+        mempool_tx.settings = &byte_limited_settings;
+        let mut evicted = MemPoolDB::enforce_size_limits(&mut mempool_tx).unwrap();
+        evicted.sort();
+        let mut expected_evicted = vec![txids[2].clone(), txids[3].clone()];
+        expected_evicted.sort();
+        assert_eq!(evicted, expected_evicted);
+        assert_eq!(MemPoolDB::get_size(&mempool_tx).unwrap(), (0, 0));
+    }
+
+    fn get_size_by_scan(conn: &DBConn) -> (u64, u64) {
+        conn.query_row(
+            "SELECT COUNT(*), IFNULL(SUM(length), 0) FROM mempool",
+            NO_PARAMS,
+            |row| {
+                let count: i64 = row.get_unwrap(0);
+                let bytes: i64 = row.get_unwrap(1);
+                Ok((count as u64, bytes as u64))
+            },
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn mempool_size_tracking() {
+        let mut chainstate = instantiate_chainstate(false, 0x80000000, "mempool_size_tracking");
+        let chainstate_path = chainstate_path("mempool_size_tracking");
+        let mut mempool = MemPoolDB::open(false, 0x80000000, &chainstate_path).unwrap();
+
+        let mut txs = codec_all_transactions(
+            &TransactionVersion::Testnet,
+            0x80000000,
+            &TransactionAnchorMode::Any,
+            &TransactionPostConditionMode::Allow,
+        );
+        let template_tx = txs.pop().unwrap();
+
+        let mut mempool_tx = mempool.tx_begin().unwrap();
+        assert_eq!(MemPoolDB::get_size(&mempool_tx).unwrap(), (0, 0));
+
+        // (origin account, fee, height).  The last tx replaces the first.
+        let tx_specs: Vec<(u8, u64, u64)> =
+            vec![(0, 100, 100), (1, 200, 100), (2, 300, 101), (0, 1000, 100)];
+        for (account, fee, height) in tx_specs.into_iter() {
+            let mut tx = template_tx.clone();
+            tx.set_tx_fee(fee);
+
+            let txid = tx.txid();
+            let tx_bytes = tx.serialize_to_vec();
+            let len = tx_bytes.len() as u64;
+            let addr = StacksAddress {
+                version: 22,
+                bytes: Hash160([account; 20]),
+            };
+
+            MemPoolDB::try_add_tx(
+                &mut mempool_tx,
+                &mut chainstate,
+                &ConsensusHash([0x1; 20]),
+                &BlockHeaderHash([0x2; 32]),
+                txid,
+                tx_bytes,
+                fee * len,
+                fee,
+                height,
+                &addr,
+                0,
+                &addr,
+                0,
+            )
+            .unwrap();
+
+            assert_eq!(
+                MemPoolDB::get_size(&mempool_tx).unwrap(),
+                get_size_by_scan(&mempool_tx)
+            );
+        }
+        assert_eq!(MemPoolDB::get_size(&mempool_tx).unwrap().0, 3);
+
+        // the lowest fee rate tx is found through the index
+        let lowest_tx = MemPoolDB::get_lowest_fee_rate_tx_metadata(&mempool_tx)
+            .unwrap()
+            .unwrap();
+        assert_eq!(lowest_tx.tx_fee, 200);
+        let plan: Vec<String> = {
+            let mut stmt = mempool_tx
+                .prepare("EXPLAIN QUERY PLAN SELECT * FROM mempool ORDER BY fee_rate ASC, accept_time DESC LIMIT 1")
+                .unwrap();
+            let rows = stmt
+                .query_map(NO_PARAMS, |row| row.get::<_, String>(3))
+                .unwrap();
+            rows.map(|row| row.unwrap()).collect()
+        };
+        assert!(plan.iter().any(|detail| detail.contains("by_fee_rate")));
+
+        MemPoolDB::garbage_collect(&mut mempool_tx, 101).unwrap();
+        assert_eq!(MemPoolDB::get_size(&mempool_tx).unwrap().0, 1);
+        assert_eq!(
+            MemPoolDB::get_size(&mempool_tx).unwrap(),
+            get_size_by_scan(&mempool_tx)
+        );
+
+        // 3 inserts, a replacement (a delete and an insert), and 2 garbage-collected txs
+        assert_eq!(MemPoolDB::get_change_count(&mempool_tx).unwrap(), 7);
+
+        // rolled-back changes don't count
+        drop(mempool_tx);
+        assert_eq!(MemPoolDB::get_size(mempool.conn()).unwrap(), (0, 0));
+        assert_eq!(MemPoolDB::get_change_count(mempool.conn()).unwrap(), 0);
+
+        // a mempool from before size tracking gets it when it's opened
+        let mut conn = DBConn::open_in_memory().unwrap();
+        for cmd in MEMPOOL_SQL {
+            conn.execute(cmd, NO_PARAMS).unwrap();
+        }
+        conn.execute(
+            "INSERT INTO mempool (txid, origin_address, origin_nonce, sponsor_address, sponsor_nonce, estimated_fee, \
+             tx_fee, length, consensus_hash, block_header_hash, height, accept_time, tx) \
+             VALUES ('00', 'a', 0, 'a', 0, 100, 100, 50, '01', '02', 1, 1, X'00')",
+            NO_PARAMS,
+        )
+        .unwrap();
+        assert!(!MemPoolDB::has_size_tracking(&conn).unwrap());
+        MemPoolDB::add_size_tracking_if_needed(&mut conn).unwrap();
+        assert!(MemPoolDB::has_size_tracking(&conn).unwrap());
+        assert_eq!(MemPoolDB::get_size(&conn).unwrap(), (1, 50));
+        let fee_rate: f64 = conn
+            .query_row("SELECT fee_rate FROM mempool", NO_PARAMS, |row| row.get(0))
+            .unwrap();
+        assert_eq!(fee_rate, 2.0);
+
+        // ...only once
+        MemPoolDB::add_size_tracking_if_needed(&mut conn).unwrap();
+        assert_eq!(MemPoolDB::get_size(&conn).unwrap(), (1, 50));
+    }
+
+    #[test]
+    fn mempool_address_pages_and_fee_histogram_cache() {
+        let mut chainstate = instantiate_chainstate(
+            false,
+            0x80000000,
+            "mempool_address_pages_and_fee_histogram_cache",
+        );
+        let chainstate_path = chainstate_path("mempool_address_pages_and_fee_histogram_cache");
+        let mut mempool = MemPoolDB::open(false, 0x80000000, &chainstate_path).unwrap();
+
+        let mut txs = codec_all_transactions(
+            &TransactionVersion::Testnet,
+            0x80000000,
+            &TransactionAnchorMode::Any,
+            &TransactionPostConditionMode::Allow,
+        );
+        let template_tx = txs.pop().unwrap();
+        let addr = StacksAddress {
+            version: 22,
+            bytes: Hash160([0x11; 20]),
+        };
+
+        let add_txs = |mempool: &mut MemPoolDB,
+                       chainstate: &mut StacksChainState,
+                       nonces: std::ops::Range<u64>| {
+            let mut mempool_tx = mempool.tx_begin().unwrap();
+            for nonce in nonces {
+                let fee = 100 * (nonce + 1);
+                let mut tx = template_tx.clone();
+                tx.set_tx_fee(fee);
+                let txid = tx.txid();
+                let tx_bytes = tx.serialize_to_vec();
+                let len = tx_bytes.len() as u64;
+                MemPoolDB::try_add_tx(
+                    &mut mempool_tx,
+                    chainstate,
+                    &ConsensusHash([0x1; 20]),
+                    &BlockHeaderHash([0x2; 32]),
+                    txid,
+                    tx_bytes,
+                    fee * len,
+                    fee,
+                    100,
+                    &addr,
+                    nonce,
+                    &addr,
+                    nonce,
+                )
+                .unwrap();
+            }
+            mempool_tx.commit().unwrap();
+        };
+
+        add_txs(&mut mempool, &mut chainstate, 0..5);
+
+        let page_nonces = |offset, limit| -> Vec<u64> {
+            MemPoolDB::get_txs_for_address(mempool.conn(), &addr, offset, limit)
+                .unwrap()
+                .into_iter()
+                .map(|tx| tx.origin_nonce)
+                .collect()
+        };
+        assert_eq!(page_nonces(0, 2), vec![0, 1]);
+        assert_eq!(page_nonces(2, 2), vec![2, 3]);
+        assert_eq!(page_nonces(4, 2), vec![4]);
+        assert_eq!(page_nonces(5, 2), Vec::<u64>::new());
+
+        assert_eq!(
+            MemPoolDB::get_next_chained_nonce(mempool.conn(), &addr, 0).unwrap(),
+            5
+        );
+        assert_eq!(
+            MemPoolDB::get_next_chained_nonce(mempool.conn(), &addr, 7).unwrap(),
+            7
+        );
+
+        // the histogram is only recomputed once the mempool changes
+        let change_count = MemPoolDB::get_change_count(mempool.conn()).unwrap();
+        assert_eq!(change_count, 5);
+        assert!(mempool.get_cached_fee_histogram(change_count).is_none());
+        let histogram = RPCMempoolFeeHistogram::from_mempool(&mut mempool).unwrap();
+        assert_eq!(histogram.tx_count, 5);
+        assert_eq!(
+            mempool.get_cached_fee_histogram(change_count),
+            Some(&histogram)
+        );
+        assert_eq!(
+            RPCMempoolFeeHistogram::from_mempool(&mut mempool).unwrap(),
+            histogram
+        );
+
+        add_txs(&mut mempool, &mut chainstate, 5..6);
+        let change_count = MemPoolDB::get_change_count(mempool.conn()).unwrap();
+        assert!(mempool.get_cached_fee_histogram(change_count).is_none());
+        let histogram = RPCMempoolFeeHistogram::from_mempool(&mut mempool).unwrap();
+        assert_eq!(histogram.tx_count, 6);
+    }
+
+    #[test]
+    fn mempool_min_relay_fee_rate() {
+        let mut chainstate =
+            instantiate_chainstate(false, 0x80000000, "mempool_min_relay_fee_rate");
+
+        let mut txs = codec_all_transactions(
+            &TransactionVersion::Testnet,
+            0x80000000,
+            &TransactionAnchorMode::Any,
+            &TransactionPostConditionMode::Allow,
+        );
+        let mut tx = txs.pop().unwrap();
+        let len = tx.serialize_to_vec().len() as u64;
+        tx.set_tx_fee(10 * len - 1);
+
+        let mut admitter =
+            MemPoolAdmitter::new(BlockHeaderHash([0x2; 32]), ConsensusHash([0x1; 20]));
+        admitter.set_min_relay_fee_rate(10);
+
+        match admitter
+            .will_admit_tx(&mut chainstate, &tx, len)
+            .unwrap_err()
+        {
+            MemPoolRejection::FeeTooLow(actual, expected) => {
+                assert_eq!(actual, 10 * len - 1);
+                assert_eq!(expected, 10 * len);
+            }
+            e => panic!("Unexpected rejection: {:?}", &e),
+        }
+    }
+
+    struct TestMemPoolObserver {
+        dropped: RefCell<Vec<(Vec<Txid>, MemPoolDropReason)>>,
+    }
+
+    impl MemPoolEventDispatcher for TestMemPoolObserver {
+        fn mempool_txs_dropped(&self, txids: Vec<Txid>, reason: MemPoolDropReason) {
+            self.dropped.borrow_mut().push((txids, reason));
+        }
+    }
+
+    #[test]
+    fn mempool_revalidate_on_new_tip() {
+        let funded_privk = StacksPrivateKey::from_hex(SK_1).unwrap();
+        let broke_privk = StacksPrivateKey::from_hex(SK_2).unwrap();
+        let recipient = PrincipalData::from(StacksAddress {
+            version: C32_ADDRESS_VERSION_TESTNET_SINGLESIG,
+            bytes: Hash160([0x11; 20]),
+        });
+
+        let funded_txs: Vec<_> = (0..2)
+            .map(|nonce| make_user_stacks_transfer(&funded_privk, nonce, 1000, &recipient, 100))
+            .collect();
+        let broke_tx = make_user_stacks_transfer(&broke_privk, 0, 1000, &recipient, 100);
+
+        let mut chainstate = instantiate_chainstate_with_balances(
+            false,
+            0x80000000,
+            "mempool_revalidate_on_new_tip",
+            vec![(funded_txs[0].origin_address(), 1_000_000)],
+        );
+        let chainstate_path = chainstate_path("mempool_revalidate_on_new_tip");
+        let mut mempool = MemPoolDB::open(false, 0x80000000, &chainstate_path).unwrap();
+
+        // none of these were checked on the way in, as if they had been admitted at a tip that
+        // is no longer canonical
+        for tx in funded_txs.iter().chain([broke_tx.clone()].iter()) {
+            mempool
+                .submit_raw(
+                    &mut chainstate,
+                    &FIRST_BURNCHAIN_CONSENSUS_HASH,
+                    &FIRST_STACKS_BLOCK_HASH,
+                    tx.serialize_to_vec(),
+                )
+                .unwrap();
+        }
+
+        // an unknown tip is an error, and leaves the mempool alone
+        match mempool.revalidate(
+            &mut chainstate,
+            &ConsensusHash([0x1; 20]),
+            &BlockHeaderHash([0x2; 32]),
+            None,
+        ) {
+            Err(db_error::NotFoundError) => {}
+            x => panic!("Unexpected revalidation result: {:?}", &x),
+        }
+        assert_eq!(MemPoolDB::get_size(mempool.conn()).unwrap().0, 3);
+
+        let observer = TestMemPoolObserver {
+            dropped: RefCell::new(vec![]),
+        };
+        let stats = mempool
+            .revalidate(
+                &mut chainstate,
+                &FIRST_BURNCHAIN_CONSENSUS_HASH,
+                &FIRST_STACKS_BLOCK_HASH,
+                Some(&observer),
+            )
+            .unwrap();
+
+        // the chained transaction is still admissible, but the unfunded one is not
+        assert_eq!(stats.num_checked, 3);
+        assert_eq!(stats.num_kept, 2);
+        assert_eq!(
+            stats.dropped,
+            vec![(broke_tx.txid(), MemPoolDropReason::Invalid)]
+        );
+        assert_eq!(
+            *observer.dropped.borrow(),
+            vec![(vec![broke_tx.txid()], MemPoolDropReason::Invalid)]
+        );
+
+        assert!(mempool.has_tx(&funded_txs[0].txid()));
+        assert!(mempool.has_tx(&funded_txs[1].txid()));
+        assert!(!mempool.has_tx(&broke_tx.txid()));
+    }
+
+    #[test]
+    fn mempool_drop_conflicting_nonces() {
+        let mut chainstate =
+            instantiate_chainstate(false, 0x80000000, "mempool_drop_conflicting_nonces");
+        let chainstate_path = chainstate_path("mempool_drop_conflicting_nonces");
+        let mut mempool = MemPoolDB::open(false, 0x80000000, &chainstate_path).unwrap();
+
+        let privk = StacksPrivateKey::from_hex(SK_1).unwrap();
+        let recipient = PrincipalData::from(StacksAddress {
+            version: C32_ADDRESS_VERSION_TESTNET_SINGLESIG,
+            bytes: Hash160([0x11; 20]),
+        });
+
+        let pending_txs: Vec<_> = (0..2)
+            .map(|nonce| make_user_stacks_transfer(&privk, nonce, 1000, &recipient, 100))
+            .collect();
+        for tx in pending_txs.iter() {
+            mempool
+                .submit_raw(
+                    &mut chainstate,
+                    &FIRST_BURNCHAIN_CONSENSUS_HASH,
+                    &FIRST_STACKS_BLOCK_HASH,
+                    tx.serialize_to_vec(),
+                )
+                .unwrap();
+        }
+
+        // a block that confirms a pending tx as-is drops nothing
+        let observer = TestMemPoolObserver {
+            dropped: RefCell::new(vec![]),
+        };
+        let dropped = mempool
+            .drop_conflicting_nonces(&[pending_txs[0].clone()], Some(&observer))
+            .unwrap();
+        assert_eq!(dropped.len(), 0);
+        assert_eq!(observer.dropped.borrow().len(), 0);
+
+        // a block that confirms a different tx with the same nonce drops the pending one, but not
+        // the pending tx with the next nonce
+        let confirmed_tx = make_user_stacks_transfer(&privk, 0, 2000, &recipient, 100);
+        let dropped = mempool
+            .drop_conflicting_nonces(&[confirmed_tx], Some(&observer))
+            .unwrap();
+        assert_eq!(dropped, vec![pending_txs[0].txid()]);
+        assert_eq!(
+            *observer.dropped.borrow(),
+            vec![(
+                vec![pending_txs[0].txid()],
+                MemPoolDropReason::ConflictingNonceConfirmed
+            )]
+        );
+
+        assert!(!mempool.has_tx(&pending_txs[0].txid()));
+        assert!(mempool.has_tx(&pending_txs[1].txid()));
+
+        // a confirmed sponsored tx conflicts with a pending tx that spends its origin nonce as a
+        // sponsor
+        let privk_other = StacksPrivateKey::from_hex(SK_2).unwrap();
+        let privk_sponsor = StacksPrivateKey::from_hex(SK_3).unwrap();
+        let pending_sponsored_tx =
+            make_sponsored_stacks_transfer(&privk_other, 0, &privk, 2, 1000, &recipient, 100);
+        mempool
+            .submit_raw(
+                &mut chainstate,
+                &FIRST_BURNCHAIN_CONSENSUS_HASH,
+                &FIRST_STACKS_BLOCK_HASH,
+                pending_sponsored_tx.serialize_to_vec(),
+            )
+            .unwrap();
+
+        let confirmed_tx =
+            make_sponsored_stacks_transfer(&privk, 2, &privk_sponsor, 0, 2000, &recipient, 100);
+        let dropped = mempool
+            .drop_conflicting_nonces(&[confirmed_tx], None)
+            .unwrap();
+        assert_eq!(dropped, vec![pending_sponsored_tx.txid()]);
+        assert!(!mempool.has_tx(&pending_sponsored_tx.txid()));
+        assert!(mempool.has_tx(&pending_txs[1].txid()));
+    }
+
     #[test]
     fn mempool_db_load_store_replace_tx() {
         let mut chainstate =
@@ -1341,12 +2676,54 @@ mod tests {
             );
             assert_eq!(tx_info.metadata.block_height, height);
 
-            // test replace-by-fee with a higher fee
+            // test replace-by-fee with a higher fee, but not high enough
             let old_txid = txid;
 
             tx.set_tx_fee(124);
             assert!(txid != tx.txid());
 
+            let txid = tx.txid();
+            let mut tx_bytes = vec![];
+            tx.consensus_serialize(&mut tx_bytes).unwrap();
+            let estimated_fee = tx.get_tx_fee() * len; // TODO: use clarity analysis data to make this estimate
+
+            match MemPoolDB::try_add_tx(
+                &mut mempool_tx,
+                &mut chainstate,
+                &ConsensusHash([0x1; 20]),
+                &BlockHeaderHash([0x2; 32]),
+                txid,
+                tx_bytes,
+                estimated_fee,
+                tx.get_tx_fee(),
+                height,
+                &origin_address,
+                origin_nonce,
+                &sponsor_address,
+                sponsor_nonce,
+            )
+            .unwrap_err()
+            {
+                MemPoolRejection::InsufficientReplaceFee {
+                    prior_txid,
+                    min_fee,
+                    actual_fee,
+                } => {
+                    assert_eq!(prior_txid, old_txid);
+                    assert_eq!(min_fee, 136);
+                    assert_eq!(actual_fee, 124);
+                }
+                e => panic!("Unexpected rejection: {:?}", &e),
+            };
+
+            // was NOT replaced
+            assert!(MemPoolDB::db_has_tx(&mempool_tx, &old_txid).unwrap());
+            assert!(!MemPoolDB::db_has_tx(&mempool_tx, &txid).unwrap());
+
+            // test replace-by-fee with a sufficiently higher fee
+            tx.set_tx_fee(136);
+            assert!(txid != tx.txid());
+
             let txid = tx.txid();
             let mut tx_bytes = vec![];
             tx.consensus_serialize(&mut tx_bytes).unwrap();
@@ -1365,7 +2742,7 @@ mod tests {
             .unwrap();
             assert_eq!(tx_info_before, tx_info.metadata);
 
-            MemPoolDB::try_add_tx(
+            let replaced = MemPoolDB::try_add_tx(
                 &mut mempool_tx,
                 &mut chainstate,
                 &ConsensusHash([0x1; 20]),
@@ -1383,6 +2760,10 @@ mod tests {
             .unwrap();
 
             // was replaced
+            assert_eq!(
+                replaced,
+                vec![(old_txid.clone(), MemPoolDropReason::ReplaceByFee)]
+            );
             assert!(!MemPoolDB::db_has_tx(&mempool_tx, &old_txid).unwrap());
             assert!(MemPoolDB::db_has_tx(&mempool_tx, &txid).unwrap());
 
@@ -1406,7 +2787,7 @@ mod tests {
             assert_eq!(tx_info.tx, expected_tx);
             assert_eq!(tx_info.metadata.len, len);
             assert_eq!(tx_info.metadata.estimated_fee, estimated_fee);
-            assert_eq!(tx_info.metadata.tx_fee, 124);
+            assert_eq!(tx_info.metadata.tx_fee, 136);
             assert_eq!(tx_info.metadata.origin_address, origin_address);
             assert_eq!(tx_info.metadata.origin_nonce, origin_nonce);
             assert_eq!(tx_info.metadata.sponsor_address, sponsor_address);
@@ -1491,9 +2872,29 @@ mod tests {
         assert_eq!(txs.len(), 0);
 
         eprintln!("garbage-collect");
+        let mut expected_dropped = MemPoolDB::get_all_txids(&mempool.db).unwrap();
+        let observer = TestMemPoolObserver {
+            dropped: RefCell::new(vec![]),
+        };
+        // nothing is announced if the garbage collection is rolled back
         let mut mempool_tx = mempool.tx_begin().unwrap();
         MemPoolDB::garbage_collect(&mut mempool_tx, 101).unwrap();
-        mempool_tx.commit().unwrap();
+        assert_eq!(observer.dropped.borrow().len(), 0);
+        drop(mempool_tx);
+        assert_eq!(observer.dropped.borrow().len(), 0);
+
+        let mut mempool_tx = mempool.tx_begin().unwrap();
+        MemPoolDB::garbage_collect(&mut mempool_tx, 101).unwrap();
+        assert_eq!(observer.dropped.borrow().len(), 0);
+        mempool_tx.commit_and_announce(Some(&observer)).unwrap();
+
+        let dropped = observer.dropped.borrow();
+        assert_eq!(dropped.len(), 1);
+        let mut dropped_txids = dropped[0].0.clone();
+        dropped_txids.sort();
+        expected_dropped.sort();
+        assert_eq!(dropped_txids, expected_dropped);
+        assert_eq!(dropped[0].1, MemPoolDropReason::StaleGarbageCollect);
 
         let txs = MemPoolDB::get_txs_after(
             &mempool.db,
@@ -1505,4 +2906,117 @@ mod tests {
         .unwrap();
         assert_eq!(txs.len(), 0);
     }
+
+    #[test]
+    fn mempool_sync_find_missing_transactions() {
+        let mut chainstate =
+            instantiate_chainstate(false, 0x80000000, "mempool_sync_find_missing_transactions");
+        let chainstate_path = chainstate_path("mempool_sync_find_missing_transactions");
+        let mut mempool = MemPoolDB::open(false, 0x80000000, &chainstate_path).unwrap();
+
+        let mut txs = codec_all_transactions(
+            &TransactionVersion::Testnet,
+            0x80000000,
+            &TransactionAnchorMode::Any,
+            &TransactionPostConditionMode::Allow,
+        );
+
+        let mut mempool_tx = mempool.tx_begin().unwrap();
+        let mut all_txids = vec![];
+        for (i, mut tx) in txs.drain(..).enumerate() {
+            let origin_address = StacksAddress {
+                version: 22,
+                bytes: Hash160::from_data(&i.to_be_bytes()),
+            };
+            let sponsor_address = StacksAddress {
+                version: 22,
+                bytes: Hash160::from_data(&(i + 1).to_be_bytes()),
+            };
+
+            tx.set_tx_fee(100 + (i as u64));
+
+            let txid = tx.txid();
+            let tx_bytes = tx.serialize_to_vec();
+            let len = tx_bytes.len() as u64;
+            let origin_nonce = tx.get_origin_nonce();
+            let sponsor_nonce = match tx.get_sponsor_nonce() {
+                Some(n) => n,
+                None => origin_nonce,
+            };
+
+            MemPoolDB::try_add_tx(
+                &mut mempool_tx,
+                &mut chainstate,
+                &ConsensusHash([0x1; 20]),
+                &BlockHeaderHash([0x2; 32]),
+                txid.clone(),
+                tx_bytes,
+                tx.get_tx_fee() * len,
+                tx.get_tx_fee(),
+                100,
+                &origin_address,
+                origin_nonce,
+                &sponsor_address,
+                sponsor_nonce,
+            )
+            .unwrap();
+
+            all_txids.push(txid);
+        }
+        mempool_tx.commit().unwrap();
+
+        let mut txids = MemPoolDB::get_all_txids(mempool.conn()).unwrap();
+        txids.sort();
+        all_txids.sort();
+        assert_eq!(txids, all_txids);
+
+        // a peer with none of our transactions is missing all of them
+        let empty_sync_data = MemPoolSyncData::new(0x01020304, 0);
+        let missing = MemPoolDB::find_missing_transactions(
+            mempool.conn(),
+            &empty_sync_data,
+            u64::max_value(),
+            u64::max_value(),
+        )
+        .unwrap();
+        assert_eq!(missing.len(), all_txids.len());
+
+        // a peer with all of our transactions is missing none of them
+        let full_sync_data = MemPoolDB::make_mempool_sync_data(mempool.conn(), 0x01020304).unwrap();
+        let missing = MemPoolDB::find_missing_transactions(
+            mempool.conn(),
+            &full_sync_data,
+            u64::max_value(),
+            u64::max_value(),
+        )
+        .unwrap();
+        assert_eq!(missing.len(), 0);
+
+        // a peer with half of our transactions is missing (at most) the other half
+        let mut half_sync_data = MemPoolSyncData::new(0x01020304, all_txids.len() as u64);
+        for txid in all_txids.iter().step_by(2) {
+            half_sync_data.insert(txid);
+        }
+        let missing = MemPoolDB::find_missing_transactions(
+            mempool.conn(),
+            &half_sync_data,
+            u64::max_value(),
+            u64::max_value(),
+        )
+        .unwrap();
+        for tx in missing.iter() {
+            assert!(!half_sync_data.contains(&tx.txid()));
+        }
+        assert!(missing.len() <= all_txids.len() / 2);
+
+        // replies are bounded in count
+        let missing = MemPoolDB::find_missing_transactions(
+            mempool.conn(),
+            &empty_sync_data,
+            1,
+            u64::max_value(),
+        )
+        .unwrap();
+        assert_eq!(missing.len(), 1);
+    }
 }
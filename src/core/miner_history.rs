@@ -0,0 +1,681 @@
+// Copyright (C) 2013-2020 Blockstack PBC, a public benefit corporation
+// Copyright (C) 2020 Stacks Open Internet Foundation
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <http://www.gnu.org/licenses/>.
+
+use rusqlite::types::ToSql;
+use rusqlite::OpenFlags;
+use rusqlite::Row;
+use rusqlite::NO_PARAMS;
+
+use std::collections::BTreeMap;
+use std::fmt;
+use std::fs;
+use std::path::PathBuf;
+
+use burnchains::{BurnchainHeaderHash, Txid};
+use chainstate::burn::{BlockHeaderHash, ConsensusHash};
+use chainstate::stacks::miner::{BlockAssemblyReport, TxSkipReason};
+use chainstate::stacks::StacksBlock;
+use vm::costs::ExecutionCost;
+
+use util::db::query_count;
+use util::db::query_rows;
+use util::db::tx_begin_immediate;
+use util::db::tx_busy_handler;
+use util::db::u64_to_sql;
+use util::db::Error as db_error;
+use util::db::FromColumn;
+use util::db::{DBConn, FromRow};
+use util::get_epoch_time_secs;
+
+/// How many of the transactions left out of a mined block are kept with its attempt, as examples
+pub const MINED_BLOCK_ATTEMPT_MAX_SKIPPED_TXS: usize = 10;
+
+/// How many of the most recent mined block attempts the miner history keeps
+pub const MINER_HISTORY_MAX_ATTEMPTS: u64 = 1000;
+
+/// Whether or not a mined block attempt's block-commit won its sortition
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum MinedBlockOutcome {
+    /// the sortition the block-commit is competing in has not been processed yet
+    Pending,
+    Won,
+    Lost,
+}
+
+impl MinedBlockOutcome {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            MinedBlockOutcome::Pending => "pending",
+            MinedBlockOutcome::Won => "won",
+            MinedBlockOutcome::Lost => "lost",
+        }
+    }
+
+    pub fn from_str(s: &str) -> Option<MinedBlockOutcome> {
+        match s {
+            "pending" => Some(MinedBlockOutcome::Pending),
+            "won" => Some(MinedBlockOutcome::Won),
+            "lost" => Some(MinedBlockOutcome::Lost),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for MinedBlockOutcome {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.as_str())
+    }
+}
+
+/// A mempool transaction that the miner considered but left out of its block
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SkippedTx {
+    pub txid: Txid,
+    pub reason: TxSkipReason,
+}
+
+/// One attempt by this node to mine an anchored block and commit to it on the burnchain
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct MinedBlockAttempt {
+    /// assigned when the attempt is stored
+    pub attempt_id: u64,
+    /// the burnchain block the block-commit was built off of
+    pub burn_block_height: u64,
+    pub burn_header_hash: BurnchainHeaderHash,
+    /// the Stacks block the mined block builds off of
+    pub parent_consensus_hash: ConsensusHash,
+    pub parent_block_hash: BlockHeaderHash,
+    pub block_hash: BlockHeaderHash,
+    pub stacks_block_height: u64,
+    /// how many times the miner assembled a block in this burnchain block, including this one
+    pub attempt: u64,
+    /// number of transactions in the block, including the coinbase
+    pub num_txs: u64,
+    /// number of mempool transactions the block assembler considered
+    pub num_considered: u64,
+    /// number of considered transactions left out of the block, by skip reason name
+    pub skipped_tx_counts: BTreeMap<String, u64>,
+    /// the first few transactions left out of the block (at most
+    /// MINED_BLOCK_ATTEMPT_MAX_SKIPPED_TXS), in the order they were skipped
+    pub skipped_txs: Vec<SkippedTx>,
+    pub execution_cost: ExecutionCost,
+    pub block_size: u64,
+    /// sum of the fees of the block's transactions, in microSTX
+    pub total_fees: u64,
+    /// total burnchain tokens spent by the block-commit (including any sunset burn)
+    pub burn_fee: u64,
+    pub outcome: MinedBlockOutcome,
+    /// when the block was assembled, in seconds since the epoch
+    pub attempt_time: u64,
+}
+
+impl MinedBlockAttempt {
+    /// Describe a freshly-assembled block, whose block-commit spends `burn_fee`.  The attempt is
+    /// pending until the sortition it competes in is processed.
+    pub fn new(
+        burn_block_height: u64,
+        burn_header_hash: BurnchainHeaderHash,
+        parent_consensus_hash: ConsensusHash,
+        block: &StacksBlock,
+        stacks_block_height: u64,
+        attempt: u64,
+        report: BlockAssemblyReport,
+        execution_cost: ExecutionCost,
+        block_size: u64,
+        burn_fee: u64,
+    ) -> MinedBlockAttempt {
+        let total_fees = block
+            .txs
+            .iter()
+            .fold(0u64, |total, tx| total.saturating_add(tx.get_tx_fee()));
+        let (skipped_tx_counts, skipped_txs) = MinedBlockAttempt::summarize_skipped(report.skipped);
+        MinedBlockAttempt {
+            attempt_id: 0,
+            burn_block_height,
+            burn_header_hash,
+            parent_consensus_hash,
+            parent_block_hash: block.header.parent_block.clone(),
+            block_hash: block.block_hash(),
+            stacks_block_height,
+            attempt,
+            num_txs: block.txs.len() as u64,
+            num_considered: report.num_considered,
+            skipped_tx_counts,
+            skipped_txs,
+            execution_cost,
+            block_size,
+            total_fees,
+            burn_fee,
+            outcome: MinedBlockOutcome::Pending,
+            attempt_time: get_epoch_time_secs(),
+        }
+    }
+
+    /// Count the skipped transactions by reason, and keep the first
+    /// MINED_BLOCK_ATTEMPT_MAX_SKIPPED_TXS of them.  Once a block fills up, nearly every
+    /// mempool transaction gets skipped, so they can't all be stored.
+    fn summarize_skipped(
+        skipped: Vec<(Txid, TxSkipReason)>,
+    ) -> (BTreeMap<String, u64>, Vec<SkippedTx>) {
+        let mut counts = BTreeMap::new();
+        let mut samples = vec![];
+        for (txid, reason) in skipped.into_iter() {
+            *counts.entry(reason.name().to_string()).or_insert(0) += 1;
+            if samples.len() < MINED_BLOCK_ATTEMPT_MAX_SKIPPED_TXS {
+                samples.push(SkippedTx { txid, reason });
+            }
+        }
+        (counts, samples)
+    }
+}
+
+/// How many of the stored attempts won, lost, or are still pending
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct MinedBlockOutcomeCounts {
+    pub won: u64,
+    pub lost: u64,
+    pub pending: u64,
+}
+
+impl FromRow<MinedBlockAttempt> for MinedBlockAttempt {
+    fn from_row<'a>(row: &'a Row) -> Result<MinedBlockAttempt, db_error> {
+        let attempt_id = u64::from_column(row, "attempt_id")?;
+        let burn_block_height = u64::from_column(row, "burn_block_height")?;
+        let burn_header_hash = BurnchainHeaderHash::from_column(row, "burn_header_hash")?;
+        let parent_consensus_hash = ConsensusHash::from_column(row, "parent_consensus_hash")?;
+        let parent_block_hash = BlockHeaderHash::from_column(row, "parent_block_hash")?;
+        let block_hash = BlockHeaderHash::from_column(row, "block_hash")?;
+        let stacks_block_height = u64::from_column(row, "stacks_block_height")?;
+        let attempt = u64::from_column(row, "attempt")?;
+        let num_txs = u64::from_column(row, "num_txs")?;
+        let num_considered = u64::from_column(row, "num_considered")?;
+        let block_size = u64::from_column(row, "block_size")?;
+        let total_fees = u64::from_column(row, "total_fees")?;
+        let burn_fee = u64::from_column(row, "burn_fee")?;
+        let attempt_time = u64::from_column(row, "attempt_time")?;
+
+        let skipped_tx_counts_json: String = row.get_unwrap("skipped_tx_counts");
+        let skipped_tx_counts =
+            serde_json::from_str(&skipped_tx_counts_json).map_err(|_e| db_error::ParseError)?;
+
+        let skipped_txs_json: String = row.get_unwrap("skipped_txs");
+        let skipped_txs =
+            serde_json::from_str(&skipped_txs_json).map_err(|_e| db_error::ParseError)?;
+
+        let execution_cost: ExecutionCost = row.get_unwrap("execution_cost");
+
+        let outcome_str: String = row.get_unwrap("outcome");
+        let outcome = MinedBlockOutcome::from_str(&outcome_str).ok_or(db_error::ParseError)?;
+
+        Ok(MinedBlockAttempt {
+            attempt_id,
+            burn_block_height,
+            burn_header_hash,
+            parent_consensus_hash,
+            parent_block_hash,
+            block_hash,
+            stacks_block_height,
+            attempt,
+            num_txs,
+            num_considered,
+            skipped_tx_counts,
+            skipped_txs,
+            execution_cost,
+            block_size,
+            total_fees,
+            burn_fee,
+            outcome,
+            attempt_time,
+        })
+    }
+}
+
+const MINER_HISTORY_SQL: &'static [&'static str] = &[
+    r#"
+    CREATE TABLE mined_block_attempts(
+        attempt_id INTEGER PRIMARY KEY AUTOINCREMENT,
+        burn_block_height INTEGER NOT NULL,
+        burn_header_hash TEXT NOT NULL,
+        parent_consensus_hash TEXT NOT NULL,
+        parent_block_hash TEXT NOT NULL,
+        block_hash TEXT NOT NULL,
+        stacks_block_height INTEGER NOT NULL,
+        attempt INTEGER NOT NULL,
+        num_txs INTEGER NOT NULL,
+        num_considered INTEGER NOT NULL,
+        skipped_tx_counts TEXT NOT NULL,    -- JSON-encoded map from skip reason to count
+        skipped_txs TEXT NOT NULL,      -- JSON-encoded list of (some) skipped transactions
+        execution_cost TEXT NOT NULL,   -- JSON-encoded execution cost
+        block_size INTEGER NOT NULL,
+        total_fees INTEGER NOT NULL,
+        burn_fee INTEGER NOT NULL,
+        outcome TEXT NOT NULL,
+        attempt_time INTEGER NOT NULL
+    );
+    "#,
+    r#"
+    CREATE INDEX by_outcome_and_burn_height ON mined_block_attempts(outcome, burn_block_height);
+    "#,
+];
+
+/// Node-local record of the blocks this node has tried to mine.  This is not part of
+/// consensus; it exists so miner operators can see why their blocks lose or are light.
+/// Only the most recent attempts are kept.
+pub struct MinerHistoryDB {
+    db: DBConn,
+    path: String,
+    /// how many of the most recent attempts to keep
+    max_attempts: u64,
+}
+
+impl MinerHistoryDB {
+    fn instantiate_miner_history_db(conn: &mut DBConn) -> Result<(), db_error> {
+        let tx = tx_begin_immediate(conn)?;
+
+        for cmd in MINER_HISTORY_SQL {
+            tx.execute(cmd, NO_PARAMS).map_err(db_error::SqliteError)?;
+        }
+
+        tx.commit().map_err(db_error::SqliteError)?;
+        Ok(())
+    }
+
+    /// Open the miner history db within the chainstate directory.  If `readwrite` is true, the
+    /// db is created if it does not exist; otherwise, NotFoundError is returned.
+    pub fn open(chainstate_path: &str, readwrite: bool) -> Result<MinerHistoryDB, db_error> {
+        match fs::metadata(chainstate_path) {
+            Ok(md) => {
+                if !md.is_dir() {
+                    return Err(db_error::NotFoundError);
+                }
+            }
+            Err(_e) => {
+                return Err(db_error::NotFoundError);
+            }
+        }
+
+        let mut path = PathBuf::from(chainstate_path);
+        path.push("miner_history.db");
+        let db_path = path
+            .to_str()
+            .ok_or_else(|| db_error::ParseError)?
+            .to_string();
+
+        let mut create_flag = false;
+        let open_flags = if fs::metadata(&db_path).is_err() {
+            if !readwrite {
+                return Err(db_error::NotFoundError);
+            }
+            // need to create
+            create_flag = true;
+            OpenFlags::SQLITE_OPEN_READ_WRITE | OpenFlags::SQLITE_OPEN_CREATE
+        } else if readwrite {
+            OpenFlags::SQLITE_OPEN_READ_WRITE
+        } else {
+            OpenFlags::SQLITE_OPEN_READ_ONLY
+        };
+
+        let mut conn =
+            DBConn::open_with_flags(&db_path, open_flags).map_err(db_error::SqliteError)?;
+        conn.busy_handler(Some(tx_busy_handler))
+            .map_err(db_error::SqliteError)?;
+
+        if create_flag {
+            // instantiate!
+            MinerHistoryDB::instantiate_miner_history_db(&mut conn)?;
+        }
+
+        Ok(MinerHistoryDB {
+            db: conn,
+            path: db_path,
+            max_attempts: MINER_HISTORY_MAX_ATTEMPTS,
+        })
+    }
+
+    pub fn conn(&self) -> &DBConn {
+        &self.db
+    }
+
+    pub fn path(&self) -> &str {
+        &self.path
+    }
+
+    /// Store a new mined block attempt, and forget the oldest attempts beyond the retention
+    /// limit.  Its attempt_id is ignored; returns the assigned one.
+    pub fn insert_attempt(&mut self, attempt: &MinedBlockAttempt) -> Result<u64, db_error> {
+        let skipped_tx_counts_json = serde_json::to_string(&attempt.skipped_tx_counts)
+            .map_err(|e| db_error::SerializationError(e))?;
+        let skipped_txs_json = serde_json::to_string(&attempt.skipped_txs)
+            .map_err(|e| db_error::SerializationError(e))?;
+
+        let sql = "INSERT INTO mined_block_attempts (
+            burn_block_height,
+            burn_header_hash,
+            parent_consensus_hash,
+            parent_block_hash,
+            block_hash,
+            stacks_block_height,
+            attempt,
+            num_txs,
+            num_considered,
+            skipped_tx_counts,
+            skipped_txs,
+            execution_cost,
+            block_size,
+            total_fees,
+            burn_fee,
+            outcome,
+            attempt_time) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14, ?15, ?16, ?17)";
+        let args: &[&dyn ToSql] = &[
+            &u64_to_sql(attempt.burn_block_height)?,
+            &attempt.burn_header_hash,
+            &attempt.parent_consensus_hash,
+            &attempt.parent_block_hash,
+            &attempt.block_hash,
+            &u64_to_sql(attempt.stacks_block_height)?,
+            &u64_to_sql(attempt.attempt)?,
+            &u64_to_sql(attempt.num_txs)?,
+            &u64_to_sql(attempt.num_considered)?,
+            &skipped_tx_counts_json,
+            &skipped_txs_json,
+            &attempt.execution_cost,
+            &u64_to_sql(attempt.block_size)?,
+            &u64_to_sql(attempt.total_fees)?,
+            &u64_to_sql(attempt.burn_fee)?,
+            &attempt.outcome.as_str(),
+            &u64_to_sql(attempt.attempt_time)?,
+        ];
+
+        let tx = tx_begin_immediate(&mut self.db)?;
+        tx.execute(sql, args)
+            .map_err(|e| db_error::SqliteError(e))?;
+        let attempt_id = tx.last_insert_rowid() as u64;
+
+        if attempt_id > self.max_attempts {
+            let args: &[&dyn ToSql] = &[&u64_to_sql(attempt_id - self.max_attempts)?];
+            tx.execute(
+                "DELETE FROM mined_block_attempts WHERE attempt_id <= ?1",
+                args,
+            )
+            .map_err(db_error::SqliteError)?;
+        }
+
+        tx.commit().map_err(db_error::SqliteError)?;
+        Ok(attempt_id)
+    }
+
+    /// Resolve every pending attempt that was built off of a burnchain block below the given
+    /// sortition's height.  An attempt wins if it was built off of the sortition's parent
+    /// burnchain block and mined the sortition's winning block; otherwise it lost (including
+    /// if its block-commit never made it into the burnchain).
+    /// Returns the attempts that were resolved.
+    pub fn resolve_sortition(
+        &mut self,
+        sortition_burn_height: u64,
+        winning_block_hash: Option<&BlockHeaderHash>,
+    ) -> Result<Vec<MinedBlockAttempt>, db_error> {
+        let tx = tx_begin_immediate(&mut self.db)?;
+
+        let sql = "SELECT * FROM mined_block_attempts WHERE outcome = ?1 AND burn_block_height < ?2 ORDER BY attempt_id ASC";
+        let args: &[&dyn ToSql] = &[
+            &MinedBlockOutcome::Pending.as_str(),
+            &u64_to_sql(sortition_burn_height)?,
+        ];
+        let mut attempts: Vec<MinedBlockAttempt> = query_rows(&tx, sql, args)?;
+
+        for attempt in attempts.iter_mut() {
+            attempt.outcome = match winning_block_hash {
+                Some(winner)
+                    if attempt.burn_block_height + 1 == sortition_burn_height
+                        && attempt.block_hash == *winner =>
+                {
+                    MinedBlockOutcome::Won
+                }
+                _ => MinedBlockOutcome::Lost,
+            };
+
+            let args: &[&dyn ToSql] =
+                &[&attempt.outcome.as_str(), &u64_to_sql(attempt.attempt_id)?];
+            tx.execute(
+                "UPDATE mined_block_attempts SET outcome = ?1 WHERE attempt_id = ?2",
+                args,
+            )
+            .map_err(db_error::SqliteError)?;
+        }
+
+        tx.commit().map_err(db_error::SqliteError)?;
+        Ok(attempts)
+    }
+
+    /// Get up to `limit` of the most recent attempts, newest first
+    pub fn get_recent_attempts(
+        conn: &DBConn,
+        limit: u64,
+    ) -> Result<Vec<MinedBlockAttempt>, db_error> {
+        let sql = "SELECT * FROM mined_block_attempts ORDER BY attempt_id DESC LIMIT ?1";
+        let args: &[&dyn ToSql] = &[&u64_to_sql(limit)?];
+        query_rows(conn, sql, args)
+    }
+
+    /// Count how many of the kept attempts won, lost, or are still pending
+    pub fn get_outcome_counts(conn: &DBConn) -> Result<MinedBlockOutcomeCounts, db_error> {
+        let count_outcome = |outcome: MinedBlockOutcome| -> Result<u64, db_error> {
+            let sql = "SELECT COUNT(*) FROM mined_block_attempts WHERE outcome = ?1";
+            let args: &[&dyn ToSql] = &[&outcome.as_str()];
+            query_count(conn, sql, args).map(|count| count as u64)
+        };
+        Ok(MinedBlockOutcomeCounts {
+            won: count_outcome(MinedBlockOutcome::Won)?,
+            lost: count_outcome(MinedBlockOutcome::Lost)?,
+            pending: count_outcome(MinedBlockOutcome::Pending)?,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    fn make_attempt(
+        burn_block_height: u64,
+        block_hash: BlockHeaderHash,
+        skipped_txs: Vec<SkippedTx>,
+    ) -> MinedBlockAttempt {
+        MinedBlockAttempt {
+            attempt_id: 0,
+            burn_block_height,
+            burn_header_hash: BurnchainHeaderHash([burn_block_height as u8; 32]),
+            parent_consensus_hash: ConsensusHash([0x01; 20]),
+            parent_block_hash: BlockHeaderHash([0x02; 32]),
+            block_hash,
+            stacks_block_height: 10,
+            attempt: 1,
+            num_txs: 1 + skipped_txs.len() as u64,
+            num_considered: 2 * skipped_txs.len() as u64,
+            skipped_tx_counts: MinedBlockAttempt::summarize_skipped(
+                skipped_txs
+                    .iter()
+                    .map(|skipped| (skipped.txid.clone(), skipped.reason.clone()))
+                    .collect(),
+            )
+            .0,
+            skipped_txs,
+            execution_cost: ExecutionCost {
+                write_length: 1,
+                write_count: 2,
+                read_length: 3,
+                read_count: 4,
+                runtime: 5,
+            },
+            block_size: 500,
+            total_fees: 1000,
+            burn_fee: 20000,
+            outcome: MinedBlockOutcome::Pending,
+            attempt_time: 1234,
+        }
+    }
+
+    #[test]
+    fn miner_history_store_and_resolve() {
+        let path = "/tmp/miner_history_store_and_resolve";
+        if fs::metadata(path).is_ok() {
+            fs::remove_dir_all(path).unwrap();
+        }
+        fs::create_dir_all(path).unwrap();
+
+        // can't open a db that doesn't exist yet read-only
+        assert!(MinerHistoryDB::open(path, false).is_err());
+        let mut db = MinerHistoryDB::open(path, true).unwrap();
+
+        let skipped = vec![
+            SkippedTx {
+                txid: Txid([0x11; 32]),
+                reason: TxSkipReason::NonceGap,
+            },
+            SkippedTx {
+                txid: Txid([0x22; 32]),
+                reason: TxSkipReason::Failed("bad tx".to_string()),
+            },
+        ];
+
+        // two attempts in burn block 100, and one left behind in burn block 99
+        let stale = make_attempt(99, BlockHeaderHash([0x03; 32]), vec![]);
+        let loser = make_attempt(100, BlockHeaderHash([0x04; 32]), vec![]);
+        let winner = make_attempt(100, BlockHeaderHash([0x05; 32]), skipped.clone());
+        let later = make_attempt(101, BlockHeaderHash([0x06; 32]), vec![]);
+
+        let stale_id = db.insert_attempt(&stale).unwrap();
+        let loser_id = db.insert_attempt(&loser).unwrap();
+        let winner_id = db.insert_attempt(&winner).unwrap();
+        let later_id = db.insert_attempt(&later).unwrap();
+
+        let attempts = MinerHistoryDB::get_recent_attempts(db.conn(), 10).unwrap();
+        assert_eq!(attempts.len(), 4);
+        assert_eq!(attempts[0].attempt_id, later_id);
+        assert_eq!(attempts[1].attempt_id, winner_id);
+        assert_eq!(attempts[1].skipped_txs, skipped);
+        assert_eq!(attempts[1].skipped_tx_counts, winner.skipped_tx_counts);
+        assert_eq!(attempts[1].execution_cost, winner.execution_cost);
+
+        let attempts = MinerHistoryDB::get_recent_attempts(db.conn(), 1).unwrap();
+        assert_eq!(attempts.len(), 1);
+
+        // sortition at burn height 101 picks the winner
+        let resolved = db
+            .resolve_sortition(101, Some(&BlockHeaderHash([0x05; 32])))
+            .unwrap();
+        let outcomes: Vec<_> = resolved
+            .iter()
+            .map(|attempt| (attempt.attempt_id, attempt.outcome))
+            .collect();
+        assert_eq!(
+            outcomes,
+            vec![
+                (stale_id, MinedBlockOutcome::Lost),
+                (loser_id, MinedBlockOutcome::Lost),
+                (winner_id, MinedBlockOutcome::Won),
+            ]
+        );
+
+        // already-resolved attempts are left alone
+        let resolved = db.resolve_sortition(101, None).unwrap();
+        assert_eq!(resolved.len(), 0);
+
+        let counts = MinerHistoryDB::get_outcome_counts(db.conn()).unwrap();
+        assert_eq!(
+            counts,
+            MinedBlockOutcomeCounts {
+                won: 1,
+                lost: 2,
+                pending: 1
+            }
+        );
+
+        // a sortition without a winner resolves the rest as lost
+        let resolved = db.resolve_sortition(102, None).unwrap();
+        assert_eq!(resolved.len(), 1);
+        assert_eq!(resolved[0].attempt_id, later_id);
+        assert_eq!(resolved[0].outcome, MinedBlockOutcome::Lost);
+
+        // can be read back read-only
+        let db = MinerHistoryDB::open(path, false).unwrap();
+        let counts = MinerHistoryDB::get_outcome_counts(db.conn()).unwrap();
+        assert_eq!(counts.lost, 3);
+    }
+
+    #[test]
+    fn miner_history_summarize_skipped() {
+        let mut skipped = vec![];
+        for i in 0..(MINED_BLOCK_ATTEMPT_MAX_SKIPPED_TXS + 5) {
+            skipped.push((Txid([i as u8; 32]), TxSkipReason::ExceedsBudget));
+        }
+        skipped.push((Txid([0xf0; 32]), TxSkipReason::NonceGap));
+        skipped.push((Txid([0xf1; 32]), TxSkipReason::Failed("bad tx".to_string())));
+        skipped.push((
+            Txid([0xf2; 32]),
+            TxSkipReason::Failed("worse tx".to_string()),
+        ));
+
+        let (counts, samples) = MinedBlockAttempt::summarize_skipped(skipped.clone());
+
+        // every skipped tx is counted...
+        let mut expected_counts = BTreeMap::new();
+        expected_counts.insert(
+            "ExceedsBudget".to_string(),
+            (MINED_BLOCK_ATTEMPT_MAX_SKIPPED_TXS + 5) as u64,
+        );
+        expected_counts.insert("NonceGap".to_string(), 1);
+        expected_counts.insert("Failed".to_string(), 2);
+        assert_eq!(counts, expected_counts);
+
+        // ...but only the first few are kept
+        assert_eq!(samples.len(), MINED_BLOCK_ATTEMPT_MAX_SKIPPED_TXS);
+        for (sample, (txid, reason)) in samples.iter().zip(skipped.iter()) {
+            assert_eq!(&sample.txid, txid);
+            assert_eq!(&sample.reason, reason);
+        }
+    }
+
+    #[test]
+    fn miner_history_keeps_recent_attempts() {
+        let path = "/tmp/miner_history_keeps_recent_attempts";
+        if fs::metadata(path).is_ok() {
+            fs::remove_dir_all(path).unwrap();
+        }
+        fs::create_dir_all(path).unwrap();
+
+        let mut db = MinerHistoryDB::open(path, true).unwrap();
+        db.max_attempts = 3;
+
+        let mut attempt_ids = vec![];
+        for i in 0..5 {
+            let attempt = make_attempt(100 + i, BlockHeaderHash([i as u8; 32]), vec![]);
+            attempt_ids.push(db.insert_attempt(&attempt).unwrap());
+        }
+
+        // only the last 3 are kept
+        let attempts = MinerHistoryDB::get_recent_attempts(db.conn(), 10).unwrap();
+        let kept_ids: Vec<_> = attempts.iter().map(|attempt| attempt.attempt_id).collect();
+        assert_eq!(
+            kept_ids,
+            vec![attempt_ids[4], attempt_ids[3], attempt_ids[2]]
+        );
+
+        let counts = MinerHistoryDB::get_outcome_counts(db.conn()).unwrap();
+        assert_eq!(counts.pending, 3);
+    }
+}
@@ -22,6 +22,7 @@ use chainstate::coordinator::comm::CoordinatorCommunication;
 use util::log;
 
 pub mod mempool;
+pub mod miner_history;
 pub use self::mempool::MemPoolDB;
 
 // fork set identifier -- to be mixed with the consensus hash (encodes the version)
@@ -37,6 +38,10 @@ pub const NETWORK_ID_TESTNET: u32 = 0xff000000;
 // default port
 pub const NETWORK_P2P_PORT: u16 = 6265;
 
+// a pruned node keeps the blocks chosen in at least this many full reward cycles before the
+// current one, so its peers know which blocks they can still ask it for
+pub const MIN_PRUNE_REWARD_CYCLES: u64 = 2;
+
 // sliding burnchain window over which a miner's past block-commit payouts will be used to weight
 // its current block-commit in a sortition
 pub const MINING_COMMITMENT_WINDOW: u8 = 6;
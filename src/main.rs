@@ -42,6 +42,7 @@ use blockstack_lib::burnchains::BurnchainHeaderHash;
 use blockstack_lib::chainstate::burn::BlockHeaderHash;
 use blockstack_lib::chainstate::burn::ConsensusHash;
 use blockstack_lib::chainstate::stacks::db::ChainStateBootData;
+use blockstack_lib::chainstate::stacks::index::file::TrieFile;
 use blockstack_lib::chainstate::stacks::index::marf::MarfConnection;
 use blockstack_lib::chainstate::stacks::index::marf::MARF;
 use blockstack_lib::chainstate::stacks::StacksBlockHeader;
@@ -310,6 +311,53 @@ fn main() {
         return;
     }
 
+    if argv[1] == "marf-gc" {
+        if argv.len() < 6 {
+            eprintln!(
+                "Usage: {} marf-gc MARF_PATH BLOCK_HASH CONSENSUS_HASH KEEP_DEPTH [--vacuum]",
+                argv[0]
+            );
+            process::exit(1);
+        }
+        let path = &argv[2];
+        let tip = BlockHeaderHash::from_hex(&argv[3]).unwrap();
+        let consensustip = ConsensusHash::from_hex(&argv[4]).unwrap();
+        let itip = StacksBlockHeader::make_index_block_hash(&consensustip, &tip);
+        let keep_depth: u32 = argv[5].parse().expect("Failed to parse KEEP_DEPTH");
+        let vacuum = argv.len() > 6 && argv[6] == "--vacuum";
+        let mut marf = MARF::from_path(path).unwrap();
+        match marf.gc(&itip, keep_depth, vacuum) {
+            Ok(stats) => println!("{:?}", &stats),
+            Err(e) => {
+                eprintln!("MARF garbage collection failed: {:?}", &e);
+                process::exit(1);
+            }
+        };
+        return;
+    }
+
+    if argv[1] == "marf-migrate-blobs" {
+        if argv.len() < 3 {
+            eprintln!("Usage: {} marf-migrate-blobs MARF_PATH [--vacuum]", argv[0]);
+            process::exit(1);
+        }
+        let path = &argv[2];
+        let vacuum = argv.len() > 3 && argv[3] == "--vacuum";
+        match TrieFile::migrate_blobs(path) {
+            Ok(count) => println!("Moved {} tries to {}", count, TrieFile::blobs_path(path)),
+            Err(e) => {
+                eprintln!("MARF blob migration failed: {:?}", &e);
+                process::exit(1);
+            }
+        };
+        if vacuum {
+            let conn = Connection::open(path).expect("Failed to open MARF database");
+            conn.execute_batch("VACUUM")
+                .expect("Failed to vacuum MARF database");
+        }
+        return;
+    }
+
     if argv[1] == "get-ancestors" {
         let path = &argv[2];
         let tip = BlockHeaderHash::from_hex(&argv[3]).unwrap();
@@ -107,6 +107,37 @@ pub fn increment_stx_blocks_mined_counter() {
     prometheus::STX_BLOCKS_MINED_COUNTER.inc();
 }
 
+pub fn increment_miner_block_attempts_counter() {
+    #[cfg(feature = "monitoring_prom")]
+    prometheus::MINER_BLOCK_ATTEMPTS_COUNTER.inc();
+}
+
+#[allow(unused_variables)]
+pub fn increment_miner_block_attempt_outcomes_counter(outcome: &str) {
+    #[cfg(feature = "monitoring_prom")]
+    prometheus::MINER_BLOCK_ATTEMPT_OUTCOMES_COUNTER
+        .with_label_values(&[outcome])
+        .inc();
+}
+
+#[allow(unused_variables)]
+pub fn increment_miner_txs_skipped_counter(reason: &str) {
+    #[cfg(feature = "monitoring_prom")]
+    prometheus::MINER_TXS_SKIPPED_COUNTER
+        .with_label_values(&[reason])
+        .inc();
+}
+
+pub fn increment_marf_node_cache_hits_counter() {
+    #[cfg(feature = "monitoring_prom")]
+    prometheus::MARF_NODE_CACHE_HITS_COUNTER.inc();
+}
+
+pub fn increment_marf_node_cache_misses_counter() {
+    #[cfg(feature = "monitoring_prom")]
+    prometheus::MARF_NODE_CACHE_MISSES_COUNTER.inc();
+}
+
 pub fn increment_warning_emitted_counter() {
     #[cfg(feature = "monitoring_prom")]
     prometheus::WARNING_EMITTED_COUNTER.inc();
@@ -122,3 +153,40 @@ pub fn update_active_miners_count_gauge(value: i64) {
     #[cfg(feature = "monitoring_prom")]
     prometheus::ACTIVE_MINERS_COUNT_GAUGE.set(value);
 }
+
+#[allow(unused_variables)]
+pub fn increment_p2p_bytes_sent_counter(msg_type: &str, num_bytes: u64) {
+    #[cfg(feature = "monitoring_prom")]
+    prometheus::P2P_BYTES_SENT_COUNTER
+        .with_label_values(&[msg_type])
+        .inc_by(num_bytes as i64);
+}
+
+#[allow(unused_variables)]
+pub fn increment_p2p_bytes_received_counter(msg_type: &str, num_bytes: u64) {
+    #[cfg(feature = "monitoring_prom")]
+    prometheus::P2P_BYTES_RECEIVED_COUNTER
+        .with_label_values(&[msg_type])
+        .inc_by(num_bytes as i64);
+}
+
+#[allow(unused_variables)]
+pub fn increment_http_bytes_sent_counter(num_bytes: u64) {
+    #[cfg(feature = "monitoring_prom")]
+    prometheus::HTTP_BYTES_SENT_COUNTER.inc_by(num_bytes as i64);
+}
+
+#[allow(unused_variables)]
+pub fn increment_http_bytes_received_counter(num_bytes: u64) {
+    #[cfg(feature = "monitoring_prom")]
+    prometheus::HTTP_BYTES_RECEIVED_COUNTER.inc_by(num_bytes as i64);
+}
+
+#[allow(unused_variables)]
+pub fn update_bandwidth_period_gauges(bytes_sent: u64, bytes_received: u64) {
+    #[cfg(feature = "monitoring_prom")]
+    {
+        prometheus::BANDWIDTH_PERIOD_BYTES_SENT_GAUGE.set(bytes_sent as i64);
+        prometheus::BANDWIDTH_PERIOD_BYTES_RECEIVED_GAUGE.set(bytes_received as i64);
+    }
+}
@@ -14,7 +14,7 @@
 // You should have received a copy of the GNU General Public License
 // along with this program.  If not, see <http://www.gnu.org/licenses/>.
 
-use prometheus::{IntCounter, IntGauge};
+use prometheus::{IntCounter, IntCounterVec, IntGauge};
 
 lazy_static! {
     pub static ref RPC_CALL_COUNTER: IntCounter = register_int_counter!(opts!(
@@ -142,4 +142,70 @@ lazy_static! {
         "Total number of active miners.",
         labels! {"handler" => "all",}
     )).unwrap();
+
+    pub static ref P2P_BYTES_SENT_COUNTER: IntCounterVec = register_int_counter_vec!(opts!(
+        "stacks_node_p2p_bytes_sent_total",
+        "Total number of bytes sent in p2p messages, by message type.",
+        labels! {"handler" => "all",}
+    ), &["msg_type"]).unwrap();
+
+    pub static ref P2P_BYTES_RECEIVED_COUNTER: IntCounterVec = register_int_counter_vec!(opts!(
+        "stacks_node_p2p_bytes_received_total",
+        "Total number of bytes received in p2p messages, by message type.",
+        labels! {"handler" => "all",}
+    ), &["msg_type"]).unwrap();
+
+    pub static ref HTTP_BYTES_SENT_COUNTER: IntCounter = register_int_counter!(opts!(
+        "stacks_node_http_bytes_sent_total",
+        "Total number of bytes sent to HTTP clients and servers.",
+        labels! {"handler" => "all",}
+    )).unwrap();
+
+    pub static ref HTTP_BYTES_RECEIVED_COUNTER: IntCounter = register_int_counter!(opts!(
+        "stacks_node_http_bytes_received_total",
+        "Total number of bytes received from HTTP clients and servers.",
+        labels! {"handler" => "all",}
+    )).unwrap();
+
+    pub static ref MARF_NODE_CACHE_HITS_COUNTER: IntCounter = register_int_counter!(opts!(
+        "stacks_node_marf_node_cache_hits_total",
+        "Total number of MARF trie node reads served from the node cache.",
+        labels! {"handler" => "all",}
+    )).unwrap();
+
+    pub static ref MARF_NODE_CACHE_MISSES_COUNTER: IntCounter = register_int_counter!(opts!(
+        "stacks_node_marf_node_cache_misses_total",
+        "Total number of MARF trie node reads that missed the node cache.",
+        labels! {"handler" => "all",}
+    )).unwrap();
+
+    pub static ref BANDWIDTH_PERIOD_BYTES_SENT_GAUGE: IntGauge = register_int_gauge!(opts!(
+        "stacks_node_bandwidth_period_bytes_sent",
+        "Number of bytes sent in the current bandwidth quota period.",
+        labels! {"handler" => "all",}
+    )).unwrap();
+
+    pub static ref BANDWIDTH_PERIOD_BYTES_RECEIVED_GAUGE: IntGauge = register_int_gauge!(opts!(
+        "stacks_node_bandwidth_period_bytes_received",
+        "Number of bytes received in the current bandwidth quota period.",
+        labels! {"handler" => "all",}
+    )).unwrap();
+
+    pub static ref MINER_BLOCK_ATTEMPTS_COUNTER: IntCounter = register_int_counter!(opts!(
+        "stacks_node_miner_block_attempts_total",
+        "Total number of anchored blocks assembled and committed to by the miner.",
+        labels! {"handler" => "all",}
+    )).unwrap();
+
+    pub static ref MINER_BLOCK_ATTEMPT_OUTCOMES_COUNTER: IntCounterVec = register_int_counter_vec!(opts!(
+        "stacks_node_miner_block_attempt_outcomes_total",
+        "Total number of the miner's block attempts whose sortition was processed, by outcome.",
+        labels! {"handler" => "all",}
+    ), &["outcome"]).unwrap();
+
+    pub static ref MINER_TXS_SKIPPED_COUNTER: IntCounterVec = register_int_counter_vec!(opts!(
+        "stacks_node_miner_txs_skipped_total",
+        "Total number of mempool transactions the miner considered but left out of its blocks, by reason.",
+        labels! {"handler" => "all",}
+    ), &["reason"]).unwrap();
 }
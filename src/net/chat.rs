@@ -27,6 +27,7 @@ use net::codec::*;
 use net::relay::*;
 use net::*;
 
+use net::connection::BandwidthAccount;
 use net::connection::ConnectionOptions;
 use net::connection::ConnectionP2P;
 use net::connection::ReplyHandleP2P;
@@ -147,10 +148,11 @@ pub struct NeighborStats {
     pub microblocks_push_rx_counts: VecDeque<(u64, u64)>, // (count, num bytes)
     pub transaction_push_rx_counts: VecDeque<(u64, u64)>, // (count, num bytes)
     pub relayed_messages: HashMap<NeighborAddress, RelayStats>,
+    pub bandwidth: BandwidthAccount,
 }
 
 impl NeighborStats {
-    pub fn new(outbound: bool) -> NeighborStats {
+    pub fn new(outbound: bool, bandwidth_quota_period: u64) -> NeighborStats {
         NeighborStats {
             outbound: outbound,
             first_contact_time: 0,
@@ -170,6 +172,7 @@ impl NeighborStats {
             microblocks_push_rx_counts: VecDeque::new(),
             transaction_push_rx_counts: VecDeque::new(),
             relayed_messages: HashMap::new(),
+            bandwidth: BandwidthAccount::new(bandwidth_quota_period),
         }
     }
 
@@ -325,6 +328,12 @@ pub struct ConversationP2P {
 
     pub stats: NeighborStats,
 
+    // set by the PeerNetwork when this node has used up its upload quota
+    pub upload_quota_exhausted: bool,
+
+    // when we last accepted a GetMempool request from this peer
+    pub last_getmempool_time: u64,
+
     // outbound replies
     pub reply_handles: VecDeque<ReplyHandleP2P>,
 }
@@ -516,7 +525,9 @@ impl ConversationP2P {
             burnchain_stable_tip_height: 0,
             burnchain_stable_tip_burn_header_hash: BurnchainHeaderHash::zero(),
 
-            stats: NeighborStats::new(outbound),
+            stats: NeighborStats::new(outbound, conn_opts.bandwidth_quota_period),
+            upload_quota_exhausted: false,
+            last_getmempool_time: 0,
             reply_handles: VecDeque::new(),
         }
     }
@@ -828,6 +839,56 @@ impl ConversationP2P {
         self.sign_and_reply(local_peer, burnchain_view, preamble, nack_payload)
     }
 
+    /// Account for the bytes of a message we're sending to this peer
+    fn add_message_tx(&mut self, msg: &StacksMessage) -> () {
+        let num_bytes = (PREAMBLE_ENCODED_SIZE as u64) + (msg.preamble.payload_len as u64);
+        self.stats
+            .bandwidth
+            .add_tx(Some(msg.payload.get_message_id()), num_bytes);
+        monitoring::increment_p2p_bytes_sent_counter(msg.payload.get_message_name(), num_bytes);
+    }
+
+    /// Account for the bytes of a message we received from this peer
+    fn add_message_rx(&mut self, msg: &StacksMessage) -> () {
+        let num_bytes = (PREAMBLE_ENCODED_SIZE as u64) + (msg.preamble.payload_len as u64);
+        self.stats
+            .bandwidth
+            .add_rx(Some(msg.payload.get_message_id()), num_bytes);
+        monitoring::increment_p2p_bytes_received_counter(msg.payload.get_message_name(), num_bytes);
+    }
+
+    /// Should we refuse to serve this peer's requests for data?  This is the case if we have sent
+    /// this peer more than its upload quota, or if this node has used up its own upload quota.
+    pub fn is_upload_throttled(&self) -> bool {
+        self.upload_quota_exhausted
+            || self.stats.bandwidth.exceeds_upload_quota(
+                self.connection.options.max_peer_upload_per_period,
+                self.connection.options.max_peer_upload_bandwidth,
+            )
+    }
+
+    /// Should we refuse to accept data pushed by this peer?  This is the case if we have received
+    /// more than this peer's download quota.
+    pub fn is_download_throttled(&self) -> bool {
+        self.stats
+            .bandwidth
+            .exceeds_download_quota(self.connection.options.max_peer_download_per_period)
+    }
+
+    /// Should we refuse this peer's GetMempool request because it asked for one too recently?
+    /// Answering a GetMempool means scanning the whole mempool, so a peer only gets one every
+    /// `min_getmempool_interval` seconds.  If the request is allowed, it counts towards the limit.
+    fn is_getmempool_rate_limited(&mut self) -> bool {
+        let now = get_epoch_time_secs();
+        if self.last_getmempool_time > 0
+            && now < self.last_getmempool_time + self.connection.options.min_getmempool_interval
+        {
+            return true;
+        }
+        self.last_getmempool_time = now;
+        false
+    }
+
     /// Queue up this message to this peer, and update our stats.
     /// This is a non-blocking operation. The caller needs to call .try_flush() or .flush() on the
     /// returned Write to finish sending.
@@ -842,6 +903,7 @@ impl ConversationP2P {
         msg.consensus_serialize(&mut handle)?;
 
         self.stats.msgs_tx += 1;
+        self.add_message_tx(&msg);
 
         debug!(
             "{:?}: relay-send({}) {} seq {}",
@@ -867,6 +929,7 @@ impl ConversationP2P {
         msg.consensus_serialize(&mut handle)?;
 
         self.stats.msgs_tx += 1;
+        self.add_message_tx(&msg);
 
         debug!(
             "{:?}: request-send({}) {} seq {}",
@@ -1081,6 +1144,14 @@ impl ConversationP2P {
             handshake_data.expire_block_height
         );
 
+        if let Some(onion) = handshake_data.onion_address() {
+            // remember how to reach this onion service, so we can tell other peers
+            let mut tx = peerdb.tx_begin().map_err(net_error::DBError)?;
+            PeerDB::set_onion_address(&mut tx, &onion).map_err(net_error::DBError)?;
+            tx.commit()
+                .map_err(|e| net_error::DBError(db_error::SqliteError(e)))?;
+        }
+
         if updated {
             // save the new key
             let mut tx = peerdb.tx_begin().map_err(net_error::DBError)?;
@@ -1198,6 +1269,7 @@ impl ConversationP2P {
             MAX_NEIGHBORS_DATA_LEN,
             chain_view.burn_block_height,
             false,
+            &mut thread_rng(),
         )
         .map_err(net_error::DBError)?;
 
@@ -1210,21 +1282,44 @@ impl ConversationP2P {
             neighbors.clear();
         }
 
-        let neighbor_addrs: Vec<NeighborAddress> = neighbors
-            .iter()
-            .map(|n| NeighborAddress::from_neighbor(n))
-            .collect();
+        // only peers that can reach onion services learn about them, and only if we can tell
+        // them the full onion address
+        let peer_reaches_onions = (self.peer_services & (ServiceFlags::ONION as u16)) != 0;
+        let mut neighbor_addrs = vec![];
+        let mut onions = vec![];
+        for neighbor in neighbors.iter() {
+            if neighbor.addr.addrbytes.is_onion() {
+                if !peer_reaches_onions {
+                    continue;
+                }
+                match PeerDB::get_onion_address(peer_dbconn, &neighbor.addr.addrbytes)
+                    .map_err(net_error::DBError)?
+                {
+                    Some(onion) => onions.push(onion),
+                    None => continue,
+                }
+            }
+            neighbor_addrs.push(NeighborAddress::from_neighbor(neighbor));
+        }
 
         debug!(
-            "{:?}: handle GetNeighbors from {:?}. Reply with {} neighbors",
+            "{:?}: handle GetNeighbors from {:?}. Reply with {} neighbors ({} onion)",
             &local_peer,
             &self,
-            neighbor_addrs.len()
+            neighbor_addrs.len(),
+            onions.len()
         );
 
-        let payload = StacksMessageType::Neighbors(NeighborsData {
-            neighbors: neighbor_addrs,
-        });
+        let payload = if onions.len() > 0 {
+            StacksMessageType::OnionNeighbors(OnionNeighborsData {
+                neighbors: neighbor_addrs,
+                onions: onions,
+            })
+        } else {
+            StacksMessageType::Neighbors(NeighborsData {
+                neighbors: neighbor_addrs,
+            })
+        };
         let reply = self.sign_reply(chain_view, &local_peer.private_key, payload, preamble.seq)?;
         let reply_handle = self.relay_signed_message(reply).map_err(|e| {
             debug!(
@@ -1703,6 +1798,48 @@ impl ConversationP2P {
         chain_view: &BurnchainView,
         msg: StacksMessage,
     ) -> Result<Option<StacksMessage>, net_error> {
+        let throttled = match msg.payload {
+            StacksMessageType::GetNeighbors
+            | StacksMessageType::GetPoxInv(_)
+            | StacksMessageType::GetBlocksInv(_) => self.is_upload_throttled(),
+            StacksMessageType::GetMempool(_) => {
+                if self.is_upload_throttled() {
+                    true
+                } else if self.is_getmempool_rate_limited() {
+                    debug!(
+                        "{:?}: Neighbor {:?} sent GetMempool less than {} seconds after its last one",
+                        local_peer,
+                        &self.to_neighbor_key(),
+                        self.connection.options.min_getmempool_interval
+                    );
+                    true
+                } else {
+                    false
+                }
+            }
+            StacksMessageType::Blocks(_)
+            | StacksMessageType::Microblocks(_)
+            | StacksMessageType::Transaction(_)
+            | StacksMessageType::MemPoolTxs(_) => self.is_download_throttled(),
+            _ => false,
+        };
+        if throttled {
+            debug!(
+                "{:?}: Neighbor {:?} exceeded its bandwidth quota; throttling {}",
+                local_peer,
+                &self.to_neighbor_key(),
+                msg.payload.get_message_name()
+            );
+            let handle = self.reply_nack(
+                local_peer,
+                chain_view,
+                &msg.preamble,
+                NackErrorCodes::Throttled,
+            )?;
+            self.reply_handles.push_back(handle);
+            return Ok(None);
+        }
+
         let res = match msg.payload {
             StacksMessageType::GetNeighbors => {
                 self.handle_getneighbors(peerdb.conn(), local_peer, chain_view, &msg.preamble)
@@ -2137,6 +2274,8 @@ impl ConversationP2P {
                 Some(m) => m,
             };
 
+            self.add_message_rx(&msg);
+
             if !self.validate_inbound_message(&msg, burnchain_view)? {
                 continue;
             }
@@ -3530,6 +3669,204 @@ mod test {
         assert!(convo_2.connection.get_public_key().is_none());
     }
 
+    #[test]
+    fn convo_handshake_getneighbors_upload_quota() {
+        let mut conn_opts = ConnectionOptions::default();
+        conn_opts.max_peer_upload_per_period = 1;
+
+        let socketaddr_1 = SocketAddr::new(IpAddr::V4(Ipv4Addr::new(127, 0, 0, 1)), 8080);
+        let socketaddr_2 = SocketAddr::new(IpAddr::V4(Ipv4Addr::new(1, 2, 3, 4)), 8081);
+
+        let burnchain = testing_burnchain_config();
+
+        let mut chain_view = BurnchainView {
+            burn_block_height: 12348,
+            burn_block_hash: BurnchainHeaderHash([0x11; 32]),
+            burn_stable_block_height: 12341,
+            burn_stable_block_hash: BurnchainHeaderHash([0x22; 32]),
+            last_burn_block_hashes: HashMap::new(),
+        };
+        chain_view.make_test_data();
+
+        let (mut peerdb_1, mut sortdb_1, pox_id_1, mut chainstate_1) = make_test_chain_dbs(
+            "convo_handshake_getneighbors_upload_quota_1",
+            &burnchain,
+            0x9abcdef0,
+            12350,
+            "http://peer1.com".into(),
+            &vec![],
+            &vec![],
+        );
+        let (mut peerdb_2, mut sortdb_2, pox_id_2, mut chainstate_2) = make_test_chain_dbs(
+            "convo_handshake_getneighbors_upload_quota_2",
+            &burnchain,
+            0x9abcdef0,
+            12351,
+            "http://peer2.com".into(),
+            &vec![],
+            &vec![],
+        );
+
+        db_setup(&mut peerdb_1, &mut sortdb_1, &socketaddr_1, &chain_view);
+        db_setup(&mut peerdb_2, &mut sortdb_2, &socketaddr_2, &chain_view);
+
+        let local_peer_1 = PeerDB::get_local_peer(&peerdb_1.conn()).unwrap();
+        let local_peer_2 = PeerDB::get_local_peer(&peerdb_2.conn()).unwrap();
+
+        let mut convo_1 =
+            ConversationP2P::new(123, 456, &burnchain, &socketaddr_2, &conn_opts, true, 0);
+        let mut convo_2 =
+            ConversationP2P::new(123, 456, &burnchain, &socketaddr_1, &conn_opts, true, 0);
+
+        // convo_1 handshakes with convo_2
+        let handshake_data_1 = HandshakeData::from_local_peer(&local_peer_1);
+        let handshake_1 = convo_1
+            .sign_message(
+                &chain_view,
+                &local_peer_1.private_key,
+                StacksMessageType::Handshake(handshake_data_1.clone()),
+            )
+            .unwrap();
+        let mut rh_1 = convo_1.send_signed_request(handshake_1, 1000000).unwrap();
+
+        convo_send_recv(&mut convo_1, vec![&mut rh_1], &mut convo_2);
+        convo_2
+            .chat(
+                &local_peer_2,
+                &mut peerdb_2,
+                &sortdb_2,
+                &pox_id_2,
+                &mut chainstate_2,
+                &mut BlockHeaderCache::new(),
+                &chain_view,
+            )
+            .unwrap();
+
+        convo_send_recv(&mut convo_2, vec![&mut rh_1], &mut convo_1);
+        convo_1
+            .chat(
+                &local_peer_1,
+                &mut peerdb_1,
+                &sortdb_1,
+                &pox_id_1,
+                &mut chainstate_1,
+                &mut BlockHeaderCache::new(),
+                &chain_view,
+            )
+            .unwrap();
+
+        let reply_1 = rh_1.recv(0).unwrap();
+        match reply_1.payload {
+            StacksMessageType::HandshakeAccept(_) => {}
+            _ => {
+                assert!(false);
+            }
+        };
+
+        // both sides accounted for the handshake, by message type
+        assert!(convo_1
+            .stats
+            .bandwidth
+            .total
+            .msg_bytes_tx
+            .get(&StacksMessageID::Handshake)
+            .is_some());
+        assert_eq!(
+            convo_1
+                .stats
+                .bandwidth
+                .total
+                .msg_bytes_tx
+                .get(&StacksMessageID::Handshake),
+            convo_2
+                .stats
+                .bandwidth
+                .total
+                .msg_bytes_rx
+                .get(&StacksMessageID::Handshake)
+        );
+
+        // the handshake-accept used up convo_2's upload quota for convo_1
+        assert!(convo_2.stats.bandwidth.period.bytes_tx > 1);
+        assert!(convo_2.is_upload_throttled());
+
+        // so, convo_1's getneighbors gets NACK'ed
+        let getneighbors_1 = convo_1
+            .sign_message(
+                &chain_view,
+                &local_peer_1.private_key,
+                StacksMessageType::GetNeighbors,
+            )
+            .unwrap();
+        let mut rh_1 = convo_1
+            .send_signed_request(getneighbors_1, 1000000)
+            .unwrap();
+
+        convo_send_recv(&mut convo_1, vec![&mut rh_1], &mut convo_2);
+        let unhandled_2 = convo_2
+            .chat(
+                &local_peer_2,
+                &mut peerdb_2,
+                &sortdb_2,
+                &pox_id_2,
+                &mut chainstate_2,
+                &mut BlockHeaderCache::new(),
+                &chain_view,
+            )
+            .unwrap();
+
+        convo_send_recv(&mut convo_2, vec![&mut rh_1], &mut convo_1);
+        let unhandled_1 = convo_1
+            .chat(
+                &local_peer_1,
+                &mut peerdb_1,
+                &sortdb_1,
+                &pox_id_1,
+                &mut chainstate_1,
+                &mut BlockHeaderCache::new(),
+                &chain_view,
+            )
+            .unwrap();
+
+        assert_eq!(unhandled_1.len(), 0);
+        assert_eq!(unhandled_2.len(), 0);
+
+        let reply_1 = rh_1.recv(0).unwrap();
+        match reply_1.payload {
+            StacksMessageType::Nack(ref data) => {
+                assert_eq!(data.error_code, NackErrorCodes::Throttled);
+            }
+            _ => {
+                assert!(false);
+            }
+        };
+    }
+
+    #[test]
+    fn convo_getmempool_rate_limit() {
+        let mut conn_opts = ConnectionOptions::default();
+        conn_opts.min_getmempool_interval = 3600;
+
+        let socketaddr = SocketAddr::new(IpAddr::V4(Ipv4Addr::new(1, 2, 3, 4)), 8081);
+        let burnchain = testing_burnchain_config();
+
+        let mut convo =
+            ConversationP2P::new(123, 456, &burnchain, &socketaddr, &conn_opts, true, 0);
+
+        // first request is served, and a second one right away is not
+        assert!(!convo.is_getmempool_rate_limited());
+        assert!(convo.is_getmempool_rate_limited());
+
+        // once the interval passes, the peer may ask again
+        convo.last_getmempool_time = get_epoch_time_secs() - 3601;
+        assert!(!convo.is_getmempool_rate_limited());
+        assert!(convo.is_getmempool_rate_limited());
+
+        // no limit
+        convo.connection.options.min_getmempool_interval = 0;
+        assert!(!convo.is_getmempool_rate_limited());
+    }
+
     #[test]
     fn convo_handshake_getblocksinv() {
         with_timeout(100, || {
@@ -14,6 +14,7 @@
 // You should have received a copy of the GNU General Public License
 // along with this program.  If not, see <http://www.gnu.org/licenses/>.
 
+use std::cmp;
 use std::collections::HashSet;
 use std::convert::TryFrom;
 use std::io;
@@ -24,6 +25,7 @@ use burnchains::BurnchainHeaderHash;
 use burnchains::BurnchainView;
 use burnchains::PrivateKey;
 use burnchains::PublicKey;
+use burnchains::Txid;
 
 use chainstate::burn::BlockHeaderHash;
 use chainstate::burn::ConsensusHash;
@@ -31,6 +33,7 @@ use chainstate::burn::ConsensusHash;
 use chainstate::stacks::StacksBlock;
 use chainstate::stacks::StacksBlockHeader;
 use chainstate::stacks::StacksMicroblock;
+use chainstate::stacks::StacksMicroblockHeader;
 use chainstate::stacks::StacksTransaction;
 
 use chainstate::stacks::MAX_BLOCK_LEN;
@@ -40,6 +43,7 @@ use chainstate::stacks::StacksPublicKey;
 use util::hash::DoubleSha256;
 use util::hash::Hash160;
 use util::hash::MerkleHashFunc;
+use util::hash::Sha512Trunc256Sum;
 use util::secp256k1::{Secp256k1PrivateKey, Secp256k1PublicKey};
 
 use net::db::LocalPeer;
@@ -652,6 +656,195 @@ impl StacksMessageCodec for MicroblocksData {
     }
 }
 
+impl StacksMessageCodec for (ConsensusHash, StacksBlockHeader) {
+    fn consensus_serialize<W: Write>(&self, fd: &mut W) -> Result<(), net_error> {
+        write_next(fd, &self.0)?;
+        write_next(fd, &self.1)?;
+        Ok(())
+    }
+
+    fn consensus_deserialize<R: Read>(
+        fd: &mut R,
+    ) -> Result<(ConsensusHash, StacksBlockHeader), net_error> {
+        let ch: ConsensusHash = read_next(fd)?;
+        let header: StacksBlockHeader = read_next(fd)?;
+        Ok((ch, header))
+    }
+}
+
+impl BlockHeadersData {
+    pub fn new() -> BlockHeadersData {
+        BlockHeadersData { headers: vec![] }
+    }
+
+    pub fn try_push(
+        &mut self,
+        ch: ConsensusHash,
+        header: StacksBlockHeader,
+    ) -> Result<(), net_error> {
+        if self.headers.len() < BLOCKS_AVAILABLE_MAX_LEN as usize {
+            self.headers.push((ch, header));
+            return Ok(());
+        } else {
+            return Err(net_error::InvalidMessage);
+        }
+    }
+}
+
+impl StacksMessageCodec for BlockHeadersData {
+    fn consensus_serialize<W: Write>(&self, fd: &mut W) -> Result<(), net_error> {
+        write_next(fd, &self.headers)?;
+        Ok(())
+    }
+
+    fn consensus_deserialize<R: Read>(fd: &mut R) -> Result<BlockHeadersData, net_error> {
+        let headers: Vec<(ConsensusHash, StacksBlockHeader)> =
+            read_next_at_most::<_, (ConsensusHash, StacksBlockHeader)>(
+                fd,
+                BLOCKS_AVAILABLE_MAX_LEN,
+            )?;
+
+        // only valid if there are no dups
+        let mut present = HashSet::new();
+        for (consensus_hash, _header) in headers.iter() {
+            if present.contains(consensus_hash) {
+                // no dups allowed
+                return Err(net_error::DeserializeError(
+                    "Invalid BlockHeadersData: duplicate block header".to_string(),
+                ));
+            }
+
+            present.insert((*consensus_hash).clone());
+        }
+
+        Ok(BlockHeadersData { headers })
+    }
+}
+
+impl StacksMessageCodec for MicroblockHeadersData {
+    fn consensus_serialize<W: Write>(&self, fd: &mut W) -> Result<(), net_error> {
+        write_next(fd, &self.index_anchor_block)?;
+        write_next(fd, &self.headers)?;
+        Ok(())
+    }
+
+    fn consensus_deserialize<R: Read>(fd: &mut R) -> Result<MicroblockHeadersData, net_error> {
+        let index_anchor_block = read_next(fd)?;
+        let headers: Vec<StacksMicroblockHeader> =
+            read_next_at_most::<_, StacksMicroblockHeader>(fd, MAX_MICROBLOCKS_UNCONFIRMED as u32)?;
+
+        Ok(MicroblockHeadersData {
+            index_anchor_block,
+            headers,
+        })
+    }
+}
+
+impl StacksMessageCodec for MemPoolSyncData {
+    fn consensus_serialize<W: Write>(&self, fd: &mut W) -> Result<(), net_error> {
+        write_next(fd, &self.seed)?;
+        write_next(fd, &self.num_hashes)?;
+        write_next(fd, &self.bloom)?;
+        Ok(())
+    }
+
+    fn consensus_deserialize<R: Read>(fd: &mut R) -> Result<MemPoolSyncData, net_error> {
+        let seed: u32 = read_next(fd)?;
+        let num_hashes: u8 = read_next(fd)?;
+        if num_hashes == 0 || num_hashes > MEMPOOL_SYNC_BLOOM_MAX_HASHES {
+            return Err(net_error::DeserializeError(format!(
+                "MemPoolSyncData must use between 1 and {} hash functions",
+                MEMPOOL_SYNC_BLOOM_MAX_HASHES
+            )));
+        }
+
+        let bloom: Vec<u8> = read_next_at_most::<_, u8>(fd, MEMPOOL_SYNC_BLOOM_MAX_LEN)?;
+        if bloom.len() == 0 {
+            return Err(net_error::DeserializeError(
+                "MemPoolSyncData must have a non-empty bloom filter".to_string(),
+            ));
+        }
+
+        Ok(MemPoolSyncData {
+            seed,
+            num_hashes,
+            bloom,
+        })
+    }
+}
+
+impl MemPoolSyncData {
+    /// Make an empty bloom filter, sized for the given number of txids
+    pub fn new(seed: u32, num_txs: u64) -> MemPoolSyncData {
+        let num_bytes = cmp::min(
+            cmp::max(1, (num_txs * MEMPOOL_SYNC_BLOOM_BITS_PER_TX + 7) / 8),
+            MEMPOOL_SYNC_BLOOM_MAX_LEN as u64,
+        );
+        MemPoolSyncData {
+            seed: seed,
+            num_hashes: MEMPOOL_SYNC_BLOOM_MAX_HASHES,
+            bloom: vec![0u8; num_bytes as usize],
+        }
+    }
+
+    /// Get the bloom filter bit indexes for a txid
+    fn bit_indexes(&self, txid: &Txid) -> Vec<u64> {
+        let mut preimage = Vec::with_capacity(36);
+        preimage.extend_from_slice(&self.seed.to_be_bytes());
+        preimage.extend_from_slice(txid.as_bytes());
+
+        let digest = Sha512Trunc256Sum::from_data(&preimage);
+        let digest_bytes = digest.as_bytes();
+        let num_bits = (self.bloom.len() as u64) * 8;
+
+        let mut indexes = Vec::with_capacity(self.num_hashes as usize);
+        for i in 0..(self.num_hashes as usize) {
+            let mut word = [0u8; 4];
+            word.copy_from_slice(&digest_bytes[4 * i..4 * (i + 1)]);
+            indexes.push((u32::from_be_bytes(word) as u64) % num_bits);
+        }
+        indexes
+    }
+
+    /// Add a txid to the bloom filter
+    pub fn insert(&mut self, txid: &Txid) -> () {
+        for bit in self.bit_indexes(txid) {
+            self.bloom[(bit / 8) as usize] |= 1 << (bit % 8);
+        }
+    }
+
+    /// Is the txid (probably) in the bloom filter?
+    /// False positives are possible; false negatives are not.
+    pub fn contains(&self, txid: &Txid) -> bool {
+        if self.bloom.len() == 0 {
+            return false;
+        }
+        for bit in self.bit_indexes(txid) {
+            if self.bloom[(bit / 8) as usize] & (1 << (bit % 8)) == 0 {
+                return false;
+            }
+        }
+        true
+    }
+}
+
+impl StacksMessageCodec for MemPoolTxsData {
+    fn consensus_serialize<W: Write>(&self, fd: &mut W) -> Result<(), net_error> {
+        write_next(fd, &self.txs)?;
+        Ok(())
+    }
+
+    fn consensus_deserialize<R: Read>(fd: &mut R) -> Result<MemPoolTxsData, net_error> {
+        let txs: Vec<StacksTransaction> = {
+            // loose upper-bound
+            let mut bound_read = BoundReader::from_reader(fd, MAX_MESSAGE_LEN as u64);
+            read_next_at_most::<_, StacksTransaction>(&mut bound_read, MEMPOOL_SYNC_TXS_MAX)
+        }?;
+
+        Ok(MemPoolTxsData { txs })
+    }
+}
+
 impl NeighborAddress {
     pub fn from_neighbor(n: &Neighbor) -> NeighborAddress {
         NeighborAddress {
@@ -697,6 +890,31 @@ impl StacksMessageCodec for NeighborsData {
     }
 }
 
+impl StacksMessageCodec for OnionNeighborsData {
+    fn consensus_serialize<W: Write>(&self, fd: &mut W) -> Result<(), net_error> {
+        write_next(fd, &self.neighbors)?;
+        write_next(fd, &self.onions)?;
+        Ok(())
+    }
+
+    fn consensus_deserialize<R: Read>(fd: &mut R) -> Result<OnionNeighborsData, net_error> {
+        let neighbors: Vec<NeighborAddress> =
+            read_next_at_most::<_, NeighborAddress>(fd, MAX_NEIGHBORS_DATA_LEN)?;
+        let onions: Vec<OnionAddress> =
+            read_next_at_most::<_, OnionAddress>(fd, MAX_NEIGHBORS_DATA_LEN)?;
+
+        // every onion address must correspond to one of the neighbors
+        for onion in onions.iter() {
+            if !neighbors.iter().any(|n| n.addrbytes.is_onion_of(onion)) {
+                return Err(net_error::DeserializeError(
+                    "Onion address does not correspond to any neighbor".to_string(),
+                ));
+            }
+        }
+        Ok(OnionNeighborsData { neighbors, onions })
+    }
+}
+
 impl HandshakeData {
     pub fn from_local_peer(local_peer: &LocalPeer) -> HandshakeData {
         let (addrbytes, port) = match local_peer.public_ip_address {
@@ -727,6 +945,20 @@ impl HandshakeData {
             data_url: data_url,
         }
     }
+
+    /// An onion service advertises its stand-in address as its address, and its full onion
+    /// address as the host of its data URL.  Get the full onion address, if this is the case.
+    pub fn onion_address(&self) -> Option<OnionAddress> {
+        if !self.addrbytes.is_onion() {
+            return None;
+        }
+        let url = self.data_url.parse_to_block_url().ok()?;
+        let onion = OnionAddress::from_hostname(url.host_str()?)?;
+        if !self.addrbytes.is_onion_of(&onion) {
+            return None;
+        }
+        Some(onion)
+    }
 }
 
 impl StacksMessageCodec for HandshakeData {
@@ -904,6 +1136,11 @@ impl StacksMessageType {
             StacksMessageType::Pong(ref _m) => StacksMessageID::Pong,
             StacksMessageType::NatPunchRequest(ref _m) => StacksMessageID::NatPunchRequest,
             StacksMessageType::NatPunchReply(ref _m) => StacksMessageID::NatPunchReply,
+            StacksMessageType::GetMempool(ref _m) => StacksMessageID::GetMempool,
+            StacksMessageType::MemPoolTxs(ref _m) => StacksMessageID::MemPoolTxs,
+            StacksMessageType::OnionNeighbors(ref _m) => StacksMessageID::OnionNeighbors,
+            StacksMessageType::BlockHeaders(ref _m) => StacksMessageID::BlockHeaders,
+            StacksMessageType::MicroblockHeaders(ref _m) => StacksMessageID::MicroblockHeaders,
         }
     }
 
@@ -928,6 +1165,11 @@ impl StacksMessageType {
             StacksMessageType::Pong(ref _m) => "Pong",
             StacksMessageType::NatPunchRequest(ref _m) => "NatPunchRequest",
             StacksMessageType::NatPunchReply(ref _m) => "NatPunchReply",
+            StacksMessageType::GetMempool(ref _m) => "GetMempool",
+            StacksMessageType::MemPoolTxs(ref _m) => "MemPoolTxs",
+            StacksMessageType::OnionNeighbors(ref _m) => "OnionNeighbors",
+            StacksMessageType::BlockHeaders(ref _m) => "BlockHeaders",
+            StacksMessageType::MicroblockHeaders(ref _m) => "MicroblockHeaders",
         }
     }
 
@@ -986,6 +1228,31 @@ impl StacksMessageType {
             StacksMessageType::NatPunchReply(ref m) => {
                 format!("NatPunchReply({},{}:{})", m.nonce, &m.addrbytes, m.port)
             }
+            StacksMessageType::GetMempool(ref m) => {
+                format!("GetMempool({},{},{})", m.seed, m.num_hashes, m.bloom.len())
+            }
+            StacksMessageType::MemPoolTxs(ref m) => format!(
+                "MemPoolTxs({:?})",
+                m.txs.iter().map(|tx| tx.txid()).collect::<Vec<Txid>>()
+            ),
+            StacksMessageType::OnionNeighbors(ref m) => {
+                format!("OnionNeighbors({:?},{:?})", m.neighbors, m.onions)
+            }
+            StacksMessageType::BlockHeaders(ref m) => format!(
+                "BlockHeaders({:?})",
+                m.headers
+                    .iter()
+                    .map(|(ch, hdr)| (ch.clone(), hdr.block_hash()))
+                    .collect::<Vec<(ConsensusHash, BlockHeaderHash)>>()
+            ),
+            StacksMessageType::MicroblockHeaders(ref m) => format!(
+                "MicroblockHeaders({},{:?})",
+                &m.index_anchor_block,
+                m.headers
+                    .iter()
+                    .map(|hdr| hdr.block_hash())
+                    .collect::<Vec<BlockHeaderHash>>()
+            ),
         }
     }
 }
@@ -1019,6 +1286,13 @@ impl StacksMessageCodec for StacksMessageID {
             x if x == StacksMessageID::Pong as u8 => StacksMessageID::Pong,
             x if x == StacksMessageID::NatPunchRequest as u8 => StacksMessageID::NatPunchRequest,
             x if x == StacksMessageID::NatPunchReply as u8 => StacksMessageID::NatPunchReply,
+            x if x == StacksMessageID::GetMempool as u8 => StacksMessageID::GetMempool,
+            x if x == StacksMessageID::MemPoolTxs as u8 => StacksMessageID::MemPoolTxs,
+            x if x == StacksMessageID::OnionNeighbors as u8 => StacksMessageID::OnionNeighbors,
+            x if x == StacksMessageID::BlockHeaders as u8 => StacksMessageID::BlockHeaders,
+            x if x == StacksMessageID::MicroblockHeaders as u8 => {
+                StacksMessageID::MicroblockHeaders
+            }
             _ => {
                 return Err(net_error::DeserializeError(
                     "Unknown message ID".to_string(),
@@ -1052,6 +1326,11 @@ impl StacksMessageCodec for StacksMessageType {
             StacksMessageType::Pong(ref m) => write_next(fd, m)?,
             StacksMessageType::NatPunchRequest(ref nonce) => write_next(fd, nonce)?,
             StacksMessageType::NatPunchReply(ref m) => write_next(fd, m)?,
+            StacksMessageType::GetMempool(ref m) => write_next(fd, m)?,
+            StacksMessageType::MemPoolTxs(ref m) => write_next(fd, m)?,
+            StacksMessageType::OnionNeighbors(ref m) => write_next(fd, m)?,
+            StacksMessageType::BlockHeaders(ref m) => write_next(fd, m)?,
+            StacksMessageType::MicroblockHeaders(ref m) => write_next(fd, m)?,
         }
         Ok(())
     }
@@ -1129,6 +1408,26 @@ impl StacksMessageCodec for StacksMessageType {
                 let m: NatPunchData = read_next(fd)?;
                 StacksMessageType::NatPunchReply(m)
             }
+            StacksMessageID::GetMempool => {
+                let m: MemPoolSyncData = read_next(fd)?;
+                StacksMessageType::GetMempool(m)
+            }
+            StacksMessageID::MemPoolTxs => {
+                let m: MemPoolTxsData = read_next(fd)?;
+                StacksMessageType::MemPoolTxs(m)
+            }
+            StacksMessageID::OnionNeighbors => {
+                let m: OnionNeighborsData = read_next(fd)?;
+                StacksMessageType::OnionNeighbors(m)
+            }
+            StacksMessageID::BlockHeaders => {
+                let m: BlockHeadersData = read_next(fd)?;
+                StacksMessageType::BlockHeaders(m)
+            }
+            StacksMessageID::MicroblockHeaders => {
+                let m: MicroblockHeadersData = read_next(fd)?;
+                StacksMessageType::MicroblockHeaders(m)
+            }
             StacksMessageID::Reserved => {
                 return Err(net_error::DeserializeError(
                     "Unsupported message ID 'reserved'".to_string(),
@@ -1883,6 +2182,118 @@ pub mod test {
         check_codec_and_corruption::<NeighborsData>(&data, &bytes);
     }
 
+    #[test]
+    fn codec_OnionNeighborsData() {
+        let onion = OnionAddress([0x33; 32]);
+        let data = OnionNeighborsData {
+            neighbors: vec![NeighborAddress {
+                addrbytes: PeerAddress::from_onion(&onion),
+                port: 12345,
+                public_key_hash: Hash160::from_bytes(
+                    &hex_bytes("1111111111111111111111111111111111111111").unwrap(),
+                )
+                .unwrap(),
+            }],
+            onions: vec![onion],
+        };
+        let bytes = vec![
+            // length
+            0x00, 0x00, 0x00, 0x01, // addrbytes
+            0xfd, 0x87, 0xd8, 0x7e, 0xeb, 0x43, 0x33, 0x33, 0x33, 0x33, 0x33, 0x33, 0x33, 0x33,
+            0x33, 0x33, // port
+            0x30, 0x39, // public key hash
+            0x11, 0x11, 0x11, 0x11, 0x11, 0x11, 0x11, 0x11, 0x11, 0x11, 0x11, 0x11, 0x11, 0x11,
+            0x11, 0x11, 0x11, 0x11, 0x11, 0x11, // length
+            0x00, 0x00, 0x00, 0x01, // onion address
+            0x33, 0x33, 0x33, 0x33, 0x33, 0x33, 0x33, 0x33, 0x33, 0x33, 0x33, 0x33, 0x33, 0x33,
+            0x33, 0x33, 0x33, 0x33, 0x33, 0x33, 0x33, 0x33, 0x33, 0x33, 0x33, 0x33, 0x33, 0x33,
+            0x33, 0x33, 0x33, 0x33,
+        ];
+
+        check_codec_and_corruption::<OnionNeighborsData>(&data, &bytes);
+
+        // onion addresses must correspond to a neighbor
+        let mut bad_data = data.clone();
+        bad_data.onions = vec![OnionAddress([0x44; 32])];
+        assert!(check_deserialize_failure::<OnionNeighborsData>(&bad_data));
+    }
+
+    #[test]
+    fn codec_BlockHeadersData() {
+        let header_1 = StacksBlockHeader::genesis_block_header();
+        let mut header_2 = StacksBlockHeader::genesis_block_header();
+        header_2.total_work.work = 1;
+        header_2.parent_block = header_1.block_hash();
+
+        let data = BlockHeadersData {
+            headers: vec![
+                (ConsensusHash([0x11; 20]), header_1.clone()),
+                (ConsensusHash([0x22; 20]), header_2.clone()),
+            ],
+        };
+
+        let mut bytes = vec![
+            // length
+            0x00, 0x00, 0x00, 0x02, // consensus hash
+            0x11, 0x11, 0x11, 0x11, 0x11, 0x11, 0x11, 0x11, 0x11, 0x11, 0x11, 0x11, 0x11, 0x11,
+            0x11, 0x11, 0x11, 0x11, 0x11, 0x11,
+        ];
+        header_1.consensus_serialize(&mut bytes).unwrap();
+        bytes.append(&mut vec![0x22; 20]);
+        header_2.consensus_serialize(&mut bytes).unwrap();
+
+        check_codec_and_corruption::<BlockHeadersData>(&data, &bytes);
+
+        // no duplicate consensus hashes
+        let mut bad_data = data.clone();
+        bad_data.headers[1].0 = ConsensusHash([0x11; 20]);
+        assert!(check_deserialize_failure::<BlockHeadersData>(&bad_data));
+
+        // no more than BLOCKS_AVAILABLE_MAX_LEN headers
+        let mut too_many = BlockHeadersData::new();
+        for i in 0..BLOCKS_AVAILABLE_MAX_LEN {
+            too_many
+                .try_push(ConsensusHash([i as u8; 20]), header_1.clone())
+                .unwrap();
+        }
+        assert!(too_many
+            .try_push(ConsensusHash([0xff; 20]), header_1.clone())
+            .is_err());
+
+        too_many
+            .headers
+            .push((ConsensusHash([0xff; 20]), header_1.clone()));
+        assert!(check_deserialize_failure::<BlockHeadersData>(&too_many));
+    }
+
+    #[test]
+    fn codec_MicroblockHeadersData() {
+        let header_1 = StacksMicroblockHeader::first_unsigned(
+            &BlockHeaderHash([0x11; 32]),
+            &Sha512Trunc256Sum([0x22; 32]),
+        );
+        let header_2 =
+            StacksMicroblockHeader::from_parent_unsigned(&header_1, &Sha512Trunc256Sum([0x33; 32]))
+                .unwrap();
+
+        let data = MicroblockHeadersData {
+            index_anchor_block: StacksBlockId([0x44; 32]),
+            headers: vec![header_1.clone(), header_2.clone()],
+        };
+
+        let mut bytes = vec![
+            // index anchor block
+            0x44, 0x44, 0x44, 0x44, 0x44, 0x44, 0x44, 0x44, 0x44, 0x44, 0x44, 0x44, 0x44, 0x44,
+            0x44, 0x44, 0x44, 0x44, 0x44, 0x44, 0x44, 0x44, 0x44, 0x44, 0x44, 0x44, 0x44, 0x44,
+            0x44, 0x44, 0x44, 0x44, // length
+            0x00, 0x00, 0x00, 0x02,
+        ];
+        header_1.consensus_serialize(&mut bytes).unwrap();
+        header_2.consensus_serialize(&mut bytes).unwrap();
+
+        check_codec_and_corruption::<MicroblockHeadersData>(&data, &bytes);
+    }
+
     #[test]
     fn codec_HandshakeData() {
         let data = HandshakeData {
@@ -2047,6 +2458,88 @@ pub mod test {
         check_codec_and_corruption::<NatPunchData>(&data, &bytes);
     }
 
+    #[test]
+    fn codec_MemPoolSyncData() {
+        let data = MemPoolSyncData {
+            seed: 0x01020304,
+            num_hashes: 8,
+            bloom: vec![0x01, 0x02, 0x03],
+        };
+        let bytes = vec![
+            // seed
+            0x01, 0x02, 0x03, 0x04, // num hashes
+            0x08, // bloom length
+            0x00, 0x00, 0x00, 0x03, // bloom
+            0x01, 0x02, 0x03,
+        ];
+
+        check_codec_and_corruption::<MemPoolSyncData>(&data, &bytes);
+
+        // must have at least one hash function
+        let no_hashes = MemPoolSyncData {
+            seed: 0x01020304,
+            num_hashes: 0,
+            bloom: vec![0x01, 0x02, 0x03],
+        };
+        assert!(check_deserialize_failure::<MemPoolSyncData>(&no_hashes));
+
+        // can't have more hash functions than the digest supports
+        let too_many_hashes = MemPoolSyncData {
+            seed: 0x01020304,
+            num_hashes: MEMPOOL_SYNC_BLOOM_MAX_HASHES + 1,
+            bloom: vec![0x01, 0x02, 0x03],
+        };
+        assert!(check_deserialize_failure::<MemPoolSyncData>(
+            &too_many_hashes
+        ));
+
+        // must have a bloom filter
+        let empty_bloom = MemPoolSyncData {
+            seed: 0x01020304,
+            num_hashes: 8,
+            bloom: vec![],
+        };
+        assert!(check_deserialize_failure::<MemPoolSyncData>(&empty_bloom));
+    }
+
+    #[test]
+    fn mempool_sync_bloom_filter() {
+        let mut data = MemPoolSyncData::new(0x12345678, 1000);
+        assert_eq!(
+            data.bloom.len() as u64,
+            (1000 * MEMPOOL_SYNC_BLOOM_BITS_PER_TX) / 8
+        );
+
+        let mut txids = vec![];
+        for i in 0..1000u64 {
+            let txid = Txid(Sha512Trunc256Sum::from_data(&i.to_be_bytes()).0);
+            data.insert(&txid);
+            txids.push(txid);
+        }
+
+        // no false negatives
+        for txid in txids.iter() {
+            assert!(data.contains(txid));
+        }
+
+        // few false positives
+        let mut false_positives = 0;
+        for i in 1000..11000u64 {
+            let txid = Txid(Sha512Trunc256Sum::from_data(&i.to_be_bytes()).0);
+            if data.contains(&txid) {
+                false_positives += 1;
+            }
+        }
+        assert!(false_positives < 100);
+
+        // a different seed sets different bits
+        let mut other_data = MemPoolSyncData::new(0x87654321, 1000);
+        for txid in txids.iter() {
+            other_data.insert(txid);
+        }
+        assert!(data.bloom != other_data.bloom);
+    }
+
     #[test]
     fn codec_StacksMessage() {
         let payloads: Vec<StacksMessageType> = vec![
@@ -2163,6 +2656,36 @@ pub mod test {
                 port: 12345,
                 nonce: 0x12345678,
             }),
+            StacksMessageType::GetMempool(MemPoolSyncData {
+                seed: 0x12345678,
+                num_hashes: 8,
+                bloom: vec![0x01, 0x02, 0x03, 0x04],
+            }),
+            StacksMessageType::MemPoolTxs(MemPoolTxsData { txs: vec![] }),
+            StacksMessageType::OnionNeighbors(OnionNeighborsData {
+                neighbors: vec![NeighborAddress {
+                    addrbytes: PeerAddress::from_onion(&OnionAddress([0x33; 32])),
+                    port: 12345,
+                    public_key_hash: Hash160::from_bytes(
+                        &hex_bytes("1111111111111111111111111111111111111111").unwrap(),
+                    )
+                    .unwrap(),
+                }],
+                onions: vec![OnionAddress([0x33; 32])],
+            }),
+            StacksMessageType::BlockHeaders(BlockHeadersData {
+                headers: vec![(
+                    ConsensusHash([0x11; 20]),
+                    StacksBlockHeader::genesis_block_header(),
+                )],
+            }),
+            StacksMessageType::MicroblockHeaders(MicroblockHeadersData {
+                index_anchor_block: StacksBlockId([0x44; 32]),
+                headers: vec![StacksMicroblockHeader::first_unsigned(
+                    &BlockHeaderHash([0x11; 32]),
+                    &Sha512Trunc256Sum([0x22; 32]),
+                )],
+            }),
         ];
 
         let mut maximal_relayers: Vec<RelayData> = vec![];
@@ -14,6 +14,7 @@
 // You should have received a copy of the GNU General Public License
 // along with this program.  If not, see <http://www.gnu.org/licenses/>.
 
+use std::collections::HashMap;
 use std::collections::VecDeque;
 use std::convert::TryFrom;
 use std::io;
@@ -45,8 +46,10 @@ use net::ProtocolFamily;
 use net::RelayData;
 use net::StacksHttp;
 use net::StacksMessageCodec;
+use net::StacksMessageID;
 use net::StacksP2P;
 use net::MAX_MESSAGE_LEN;
+use net::MEMPOOL_SYNC_TXS_MAX;
 
 use net::download::BLOCK_DOWNLOAD_INTERVAL;
 use net::inv::INV_SYNC_INTERVAL;
@@ -366,6 +369,19 @@ pub struct ConnectionOptions {
     pub max_buffered_microblocks_available: u64,
     pub max_buffered_blocks: u64,
     pub max_buffered_microblocks: u64,
+    pub mempool_sync_interval: u64,
+    pub mempool_sync_max_txs: u64,
+    pub min_getmempool_interval: u64,
+    pub socks_proxy: Option<net::SocketAddr>,
+    pub socks_proxy_timeout: u64,
+    pub bandwidth_quota_period: u64,
+    pub max_peer_upload_per_period: u64,
+    pub max_peer_download_per_period: u64,
+    pub max_peer_upload_bandwidth: u64,
+    pub max_upload_per_period: u64,
+    pub max_download_per_period: u64,
+    pub headers_first_push: bool,
+    pub pruned: bool,
 
     // fault injection
     pub disable_neighbor_walk: bool,
@@ -380,6 +396,7 @@ pub struct ConnectionOptions {
     pub disable_inbound_walks: bool,
     pub disable_natpunch: bool,
     pub disable_inbound_handshakes: bool,
+    pub disable_mempool_sync: bool,
     pub force_disconnect_interval: Option<u64>,
 }
 
@@ -442,6 +459,19 @@ impl std::default::Default for ConnectionOptions {
             max_buffered_microblocks_available: 1,
             max_buffered_blocks: 1,
             max_buffered_microblocks: 10,
+            mempool_sync_interval: 30, // how often to ask a neighbor for the mempool transactions we're missing
+            mempool_sync_max_txs: MEMPOOL_SYNC_TXS_MAX as u64, // maximum number of transactions to send in reply to a GetMempool
+            min_getmempool_interval: 10, // how often a single peer may ask us for mempool transactions, in seconds
+            socks_proxy: None,           // connect to peers directly by default
+            socks_proxy_timeout: 10_000, // how long to wait for the SOCKS proxy to resolve a name, in millis
+            bandwidth_quota_period: 86400, // bandwidth quotas reset once a (UTC) day
+            max_peer_upload_per_period: 0, // infinite bytes per period may be sent to a single peer
+            max_peer_download_per_period: 0, // infinite bytes per period may be received from a single peer
+            max_peer_upload_bandwidth: 0,    // infinite upload bandwidth allowed per peer
+            max_upload_per_period: 0,        // infinite bytes per period may be sent by this node
+            max_download_per_period: 0, // infinite bytes per period may be received by this node
+            headers_first_push: false, // announce new blocks with BlocksAvailable, not BlockHeaders
+            pruned: false,             // this node keeps all block bodies
 
             // no faults on by default
             disable_neighbor_walk: false,
@@ -456,11 +486,227 @@ impl std::default::Default for ConnectionOptions {
             disable_inbound_walks: false,
             disable_natpunch: false,
             disable_inbound_handshakes: false,
+            disable_mempool_sync: false,
             force_disconnect_interval: None,
         }
     }
 }
 
+/// How long a bandwidth sample counts towards a connection's ongoing bandwidth, in seconds
+pub const BANDWIDTH_POINT_LIFETIME: u64 = 60;
+
+/// Maximum number of (per-second) bandwidth samples to remember per direction
+pub const NUM_BANDWIDTH_POINTS: usize = 2 * (BANDWIDTH_POINT_LIFETIME as usize);
+
+/// Bytes sent and received, broken down by p2p message type.  Bytes that do not belong to a p2p
+/// message (i.e. HTTP traffic) only count towards the totals.
+#[derive(Debug, Clone, PartialEq)]
+pub struct BandwidthStats {
+    pub bytes_tx: u64,
+    pub bytes_rx: u64,
+    pub msg_bytes_tx: HashMap<StacksMessageID, u64>,
+    pub msg_bytes_rx: HashMap<StacksMessageID, u64>,
+}
+
+impl BandwidthStats {
+    pub fn new() -> BandwidthStats {
+        BandwidthStats {
+            bytes_tx: 0,
+            bytes_rx: 0,
+            msg_bytes_tx: HashMap::new(),
+            msg_bytes_rx: HashMap::new(),
+        }
+    }
+
+    pub fn add_tx(&mut self, msg_id: Option<StacksMessageID>, num_bytes: u64) -> () {
+        self.bytes_tx += num_bytes;
+        if let Some(msg_id) = msg_id {
+            *self.msg_bytes_tx.entry(msg_id).or_insert(0) += num_bytes;
+        }
+    }
+
+    pub fn add_rx(&mut self, msg_id: Option<StacksMessageID>, num_bytes: u64) -> () {
+        self.bytes_rx += num_bytes;
+        if let Some(msg_id) = msg_id {
+            *self.msg_bytes_rx.entry(msg_id).or_insert(0) += num_bytes;
+        }
+    }
+
+    /// Add another set of stats to this one
+    pub fn merge(&mut self, other: &BandwidthStats) -> () {
+        self.bytes_tx += other.bytes_tx;
+        self.bytes_rx += other.bytes_rx;
+        for (msg_id, num_bytes) in other.msg_bytes_tx.iter() {
+            *self.msg_bytes_tx.entry(*msg_id).or_insert(0) += *num_bytes;
+        }
+        for (msg_id, num_bytes) in other.msg_bytes_rx.iter() {
+            *self.msg_bytes_rx.entry(*msg_id).or_insert(0) += *num_bytes;
+        }
+    }
+}
+
+/// Bandwidth accounting for a peer (or for the whole node).
+/// Tracks lifetime usage, usage within the current quota period, and recent samples from which
+/// we estimate ongoing bandwidth.  Quota periods are aligned to the epoch, so that accounts
+/// created at different times can be summed.
+#[derive(Debug, Clone, PartialEq)]
+pub struct BandwidthAccount {
+    pub total: BandwidthStats,
+    pub period: BandwidthStats,
+    pub period_id: u64,
+    pub period_length: u64,
+    tx_points: VecDeque<(u64, u64)>, // (time, num bytes)
+    rx_points: VecDeque<(u64, u64)>, // (time, num bytes)
+}
+
+impl BandwidthAccount {
+    pub fn new(period_length: u64) -> BandwidthAccount {
+        let mut acct = BandwidthAccount {
+            total: BandwidthStats::new(),
+            period: BandwidthStats::new(),
+            period_id: 0,
+            period_length: period_length,
+            tx_points: VecDeque::new(),
+            rx_points: VecDeque::new(),
+        };
+        acct.period_id = acct.get_period_id(get_epoch_time_secs());
+        acct
+    }
+
+    fn get_period_id(&self, now: u64) -> u64 {
+        if self.period_length == 0 {
+            0
+        } else {
+            now / self.period_length
+        }
+    }
+
+    /// Start a new quota period if the current one has passed
+    fn roll_period(&mut self, now: u64) -> () {
+        let period_id = self.get_period_id(now);
+        if period_id != self.period_id {
+            self.period_id = period_id;
+            self.period = BandwidthStats::new();
+        }
+    }
+
+    fn add_point(points: &mut VecDeque<(u64, u64)>, now: u64, num_bytes: u64) -> () {
+        // one sample per second, so the samples always span the bandwidth window
+        if let Some(last) = points.back_mut() {
+            if last.0 == now {
+                last.1 += num_bytes;
+                return;
+            }
+        }
+        points.push_back((now, num_bytes));
+        while points.len() > NUM_BANDWIDTH_POINTS {
+            points.pop_front();
+        }
+    }
+
+    /// Record bytes sent
+    pub fn add_tx(&mut self, msg_id: Option<StacksMessageID>, num_bytes: u64) -> () {
+        let now = get_epoch_time_secs();
+        self.roll_period(now);
+        self.total.add_tx(msg_id, num_bytes);
+        self.period.add_tx(msg_id, num_bytes);
+        BandwidthAccount::add_point(&mut self.tx_points, now, num_bytes);
+    }
+
+    /// Record bytes received
+    pub fn add_rx(&mut self, msg_id: Option<StacksMessageID>, num_bytes: u64) -> () {
+        let now = get_epoch_time_secs();
+        self.roll_period(now);
+        self.total.add_rx(msg_id, num_bytes);
+        self.period.add_rx(msg_id, num_bytes);
+        BandwidthAccount::add_point(&mut self.rx_points, now, num_bytes);
+    }
+
+    /// Is this account's period stats for the current quota period?
+    pub fn is_current(&self) -> bool {
+        self.period_id == self.get_period_id(get_epoch_time_secs())
+    }
+
+    /// How many bytes have been sent in the current quota period?
+    pub fn get_period_bytes_tx(&self) -> u64 {
+        if self.is_current() {
+            self.period.bytes_tx
+        } else {
+            0
+        }
+    }
+
+    /// How many bytes have been received in the current quota period?
+    pub fn get_period_bytes_rx(&self) -> u64 {
+        if self.is_current() {
+            self.period.bytes_rx
+        } else {
+            0
+        }
+    }
+
+    fn get_bandwidth(points: &VecDeque<(u64, u64)>) -> f64 {
+        let now = get_epoch_time_secs();
+        let total_bytes: u64 = points
+            .iter()
+            .filter(|(time, _)| now < time + BANDWIDTH_POINT_LIFETIME)
+            .map(|(_, num_bytes)| *num_bytes)
+            .sum();
+
+        (total_bytes as f64) / (BANDWIDTH_POINT_LIFETIME as f64)
+    }
+
+    /// Ongoing upload bandwidth, in bytes/sec
+    pub fn get_upload_bandwidth(&self) -> f64 {
+        BandwidthAccount::get_bandwidth(&self.tx_points)
+    }
+
+    /// Ongoing download bandwidth, in bytes/sec
+    pub fn get_download_bandwidth(&self) -> f64 {
+        BandwidthAccount::get_bandwidth(&self.rx_points)
+    }
+
+    /// Fold another account into this one -- e.g. when a conversation closes, or when summing up
+    /// all conversations' usage.  Period stats are only carried over if they're current.
+    pub fn merge(&mut self, other: &BandwidthAccount) -> () {
+        self.roll_period(get_epoch_time_secs());
+        self.total.merge(&other.total);
+        if other.period_id == self.period_id {
+            self.period.merge(&other.period);
+        }
+        BandwidthAccount::merge_points(&mut self.tx_points, &other.tx_points);
+        BandwidthAccount::merge_points(&mut self.rx_points, &other.rx_points);
+    }
+
+    fn merge_points(points: &mut VecDeque<(u64, u64)>, other_points: &VecDeque<(u64, u64)>) -> () {
+        let now = get_epoch_time_secs();
+        let mut merged: Vec<(u64, u64)> = points
+            .iter()
+            .chain(other_points.iter())
+            .filter(|(time, _)| now < time + BANDWIDTH_POINT_LIFETIME)
+            .map(|point| point.clone())
+            .collect();
+
+        merged.sort();
+        points.clear();
+        points.extend(merged.into_iter());
+        while points.len() > NUM_BANDWIDTH_POINTS {
+            points.pop_front();
+        }
+    }
+
+    /// Has this account used up its upload allowance?  A limit of 0 means no limit.
+    pub fn exceeds_upload_quota(&self, max_per_period: u64, max_bandwidth: u64) -> bool {
+        (max_per_period > 0 && self.get_period_bytes_tx() >= max_per_period)
+            || (max_bandwidth > 0 && self.get_upload_bandwidth() > (max_bandwidth as f64))
+    }
+
+    /// Has this account used up its download allowance?  A limit of 0 means no limit.
+    pub fn exceeds_download_quota(&self, max_per_period: u64) -> bool {
+        max_per_period > 0 && self.get_period_bytes_rx() >= max_per_period
+    }
+}
+
 #[derive(Debug)]
 pub struct NetworkConnection<P: ProtocolFamily> {
     pub options: ConnectionOptions,
@@ -2229,4 +2475,81 @@ mod test {
 
         pinger.join().unwrap();
     }
+
+    #[test]
+    fn test_bandwidth_account() {
+        set_virtual_time_ms(Some(86_400_000 * 100));
+
+        let mut account = BandwidthAccount::new(86400);
+        account.add_tx(Some(StacksMessageID::BlocksInv), 100);
+        account.add_tx(None, 50);
+        account.add_rx(Some(StacksMessageID::GetBlocksInv), 20);
+
+        assert_eq!(account.total.bytes_tx, 150);
+        assert_eq!(account.total.bytes_rx, 20);
+        assert_eq!(
+            account.total.msg_bytes_tx.get(&StacksMessageID::BlocksInv),
+            Some(&100)
+        );
+        assert_eq!(
+            account
+                .total
+                .msg_bytes_rx
+                .get(&StacksMessageID::GetBlocksInv),
+            Some(&20)
+        );
+        assert_eq!(account.get_period_bytes_tx(), 150);
+        assert_eq!(account.get_period_bytes_rx(), 20);
+        assert_eq!(
+            account.get_upload_bandwidth(),
+            150.0 / (BANDWIDTH_POINT_LIFETIME as f64)
+        );
+
+        assert!(account.exceeds_upload_quota(150, 0));
+        assert!(!account.exceeds_upload_quota(151, 0));
+        assert!(!account.exceeds_upload_quota(0, 0));
+        assert!(account.exceeds_upload_quota(0, 1));
+        assert!(account.exceeds_download_quota(20));
+        assert!(!account.exceeds_download_quota(0));
+
+        // samples age out of the ongoing bandwidth estimate
+        set_virtual_time_ms(Some(
+            86_400_000 * 100 + BANDWIDTH_POINT_LIFETIME as u128 * 1000,
+        ));
+        assert_eq!(account.get_upload_bandwidth(), 0.0);
+        assert!(!account.exceeds_upload_quota(0, 1));
+        assert!(account.exceeds_upload_quota(150, 0));
+
+        // a new day resets the period, but not the totals
+        set_virtual_time_ms(Some(86_400_000 * 101));
+        assert!(!account.is_current());
+        assert_eq!(account.get_period_bytes_tx(), 0);
+        assert!(!account.exceeds_upload_quota(150, 0));
+
+        account.add_tx(Some(StacksMessageID::BlocksInv), 10);
+        assert!(account.is_current());
+        assert_eq!(account.get_period_bytes_tx(), 10);
+        assert_eq!(account.total.bytes_tx, 160);
+
+        // only current period stats carry over when merging
+        let mut node_account = BandwidthAccount::new(86400);
+        let mut stale_account = BandwidthAccount::new(86400);
+        stale_account.period_id -= 1;
+        stale_account.period.add_tx(None, 1000);
+        stale_account.total.add_tx(None, 1000);
+
+        node_account.merge(&account);
+        node_account.merge(&stale_account);
+        assert_eq!(node_account.total.bytes_tx, 1160);
+        assert_eq!(node_account.get_period_bytes_tx(), 10);
+        assert_eq!(
+            node_account
+                .total
+                .msg_bytes_tx
+                .get(&StacksMessageID::BlocksInv),
+            Some(&110)
+        );
+
+        set_virtual_time_ms(None);
+    }
 }
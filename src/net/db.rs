@@ -19,7 +19,7 @@ use std::fmt;
 use rusqlite::types::ToSql;
 use rusqlite::Row;
 use rusqlite::Transaction;
-use rusqlite::{Connection, OpenFlags, NO_PARAMS};
+use rusqlite::{Connection, OpenFlags, OptionalExtension, NO_PARAMS};
 
 use std::convert::From;
 use std::convert::TryFrom;
@@ -51,6 +51,7 @@ use net::asn::ASEntry4;
 use net::Neighbor;
 use net::NeighborAddress;
 use net::NeighborKey;
+use net::OnionAddress;
 use net::PeerAddress;
 use net::ServiceFlags;
 
@@ -65,6 +66,9 @@ pub const PEERDB_VERSION: &'static str = "23.0.0.0";
 
 const NUM_SLOTS: usize = 8;
 
+/// Largest prime below 2^32, which is more than any frontier slot (see `peer_slots`)
+const RANDOM_NEIGHBORS_MODULUS: i64 = 4294967291;
+
 impl PeerAddress {
     pub fn to_bin(&self) -> String {
         to_bin(&self.0)
@@ -360,6 +364,16 @@ const PEERDB_SETUP: &'static [&'static str] = &[
     );"#,
 ];
 
+// Maps the stand-in peer addresses of onion services to their full onion addresses.
+// Also applied to existing peer databases when they're opened.
+const PEERDB_ONION_SETUP: &'static [&'static str] = &[r#"
+    CREATE TABLE IF NOT EXISTS onion_addresses(
+        addrbytes TEXT NOT NULL,
+        onion TEXT NOT NULL,
+
+        PRIMARY KEY(addrbytes)
+    );"#];
+
 #[derive(Debug)]
 pub struct PeerDB {
     pub conn: Connection,
@@ -396,6 +410,8 @@ impl PeerDB {
                 .map_err(db_error::SqliteError)?;
         }
 
+        PeerDB::apply_onion_schema(&mut tx)?;
+
         tx.execute(
             "INSERT INTO db_version (version) VALUES (?1)",
             &[&PEERDB_VERSION],
@@ -465,6 +481,14 @@ impl PeerDB {
         }
     }
 
+    fn apply_onion_schema<'a>(tx: &mut Transaction<'a>) -> Result<(), db_error> {
+        for row_text in PEERDB_ONION_SETUP {
+            tx.execute(row_text, NO_PARAMS)
+                .map_err(db_error::SqliteError)?;
+        }
+        Ok(())
+    }
+
     fn reset_denies<'a>(tx: &mut Transaction<'a>) -> Result<(), db_error> {
         tx.execute("UPDATE frontier SET denied = 0", NO_PARAMS)
             .map_err(db_error::SqliteError)?;
@@ -574,6 +598,7 @@ impl PeerDB {
 
             {
                 let mut tx = db.tx_begin()?;
+                PeerDB::apply_onion_schema(&mut tx)?;
                 PeerDB::refresh_allows(&mut tx)?;
                 PeerDB::refresh_denies(&mut tx)?;
                 PeerDB::clear_initial_peers(&mut tx)?;
@@ -770,6 +795,47 @@ impl PeerDB {
         query_row::<Neighbor, _>(conn, &qry, &args)
     }
 
+    /// Remember the full address of an onion service, keyed by its stand-in peer address
+    pub fn set_onion_address<'a>(
+        tx: &mut Transaction<'a>,
+        onion: &OnionAddress,
+    ) -> Result<(), db_error> {
+        let addrbytes = PeerAddress::from_onion(onion);
+        tx.execute(
+            "INSERT OR REPLACE INTO onion_addresses (addrbytes, onion) VALUES (?1, ?2)",
+            &[&addrbytes.to_bin() as &dyn ToSql, &onion.to_hex()],
+        )
+        .map_err(db_error::SqliteError)?;
+
+        Ok(())
+    }
+
+    /// Look up the full address of an onion service from its stand-in peer address
+    pub fn get_onion_address(
+        conn: &DBConn,
+        addrbytes: &PeerAddress,
+    ) -> Result<Option<OnionAddress>, db_error> {
+        if !addrbytes.is_onion() {
+            return Ok(None);
+        }
+
+        let qry = "SELECT onion FROM onion_addresses WHERE addrbytes = ?1";
+        let args = [&addrbytes.to_bin() as &dyn ToSql];
+        let onion_hex_opt: Option<String> = conn
+            .query_row(qry, &args, |row| row.get(0))
+            .optional()
+            .map_err(db_error::SqliteError)?;
+
+        match onion_hex_opt {
+            Some(onion_hex) => {
+                let onion =
+                    OnionAddress::from_hex(&onion_hex).map_err(|_e| db_error::ParseError)?;
+                Ok(Some(onion))
+            }
+            None => Ok(None),
+        }
+    }
+
     /// Get peer by port (used in tests where the IP address doesn't really matter)
     #[cfg(test)]
     pub fn get_peer_by_port(
@@ -1206,13 +1272,16 @@ impl PeerDB {
         Ok(())
     }
 
-    /// Get random neighbors, optionally always including allowed neighbors
-    pub fn get_random_neighbors(
+    /// Get random neighbors, optionally always including allowed neighbors.
+    /// Neighbors are sampled with the given RNG (not with SQLite's RANDOM()), so a seeded RNG
+    /// gives a reproducible sample.
+    pub fn get_random_neighbors<R: Rng>(
         conn: &DBConn,
         network_id: u32,
         count: u32,
         block_height: u64,
         always_include_allowed: bool,
+        rng: &mut R,
     ) -> Result<Vec<Neighbor>, db_error> {
         let mut ret = vec![];
 
@@ -1221,7 +1290,7 @@ impl PeerDB {
 
         if always_include_allowed {
             // always include allowed neighbors, freshness be damned
-            let allow_qry = "SELECT * FROM frontier WHERE network_id = ?1 AND denied < ?2 AND (allowed < 0 OR ?3 < allowed)".to_string();
+            let allow_qry = "SELECT * FROM frontier WHERE network_id = ?1 AND denied < ?2 AND (allowed < 0 OR ?3 < allowed) ORDER BY addrbytes, port".to_string();
             let allow_args: &[&dyn ToSql] =
                 &[&network_id, &u64_to_sql(now_secs)?, &u64_to_sql(now_secs)?];
             let mut allow_rows = query_rows::<Neighbor, _>(conn, &allow_qry, allow_args)?;
@@ -1229,27 +1298,35 @@ impl PeerDB {
             if allow_rows.len() >= (count as usize) {
                 // return a random subset
                 let allow_slice = allow_rows.as_mut_slice();
-                allow_slice.shuffle(&mut thread_rng());
+                allow_slice.shuffle(rng);
                 return Ok(allow_slice[0..(count as usize)].to_vec());
             }
 
             ret.append(&mut allow_rows);
         }
 
-        // fill in with non-allowed, randomly-chosen, fresh peers
+        // fill in with non-allowed, randomly-chosen, fresh peers.  Slots are distinct and
+        // smaller than the prime modulus, so (slot * ?5 + ?6) % ?7 is a random permutation of
+        // them, drawn from rng.
         let random_peers_qry = if always_include_allowed {
             "SELECT * FROM frontier WHERE network_id = ?1 AND last_contact_time >= 0 AND ?2 < expire_block_height AND denied < ?3 AND \
-                 (allowed >= 0 AND allowed <= ?4) ORDER BY RANDOM() LIMIT ?5".to_string()
+                 (allowed >= 0 AND allowed <= ?4) ORDER BY (slot * ?5 + ?6) % ?7 LIMIT ?8".to_string()
         } else {
             "SELECT * FROM frontier WHERE network_id = ?1 AND last_contact_time >= 0 AND ?2 < expire_block_height AND denied < ?3 AND \
-                 (allowed < 0 OR (allowed >= 0 AND allowed <= ?4)) ORDER BY RANDOM() LIMIT ?5".to_string()
+                 (allowed < 0 OR (allowed >= 0 AND allowed <= ?4)) ORDER BY (slot * ?5 + ?6) % ?7 LIMIT ?8".to_string()
         };
 
+        // keep slot * multiplier within an i64
+        let multiplier: i64 = rng.gen_range(1, 1i64 << 31);
+        let offset: i64 = rng.gen_range(0, RANDOM_NEIGHBORS_MODULUS);
         let random_peers_args: &[&dyn ToSql] = &[
             &network_id,
             &u64_to_sql(block_height)?,
             &u64_to_sql(now_secs)?,
             &u64_to_sql(now_secs)?,
+            &multiplier,
+            &offset,
+            &RANDOM_NEIGHBORS_MODULUS,
             &(count - (ret.len() as u32)),
         ];
         let mut random_peers =
@@ -1263,24 +1340,26 @@ impl PeerDB {
     /// -- always include all allowed neighbors
     /// -- never include denied neighbors
     /// -- for neighbors that are neither allowed nor denied, sample them randomly as long as they're fresh.
-    pub fn get_initial_neighbors(
+    pub fn get_initial_neighbors<R: Rng>(
         conn: &DBConn,
         network_id: u32,
         count: u32,
         block_height: u64,
+        rng: &mut R,
     ) -> Result<Vec<Neighbor>, db_error> {
-        PeerDB::get_random_neighbors(conn, network_id, count, block_height, true)
+        PeerDB::get_random_neighbors(conn, network_id, count, block_height, true, rng)
     }
 
     /// Get a randomized set of peers for walking the peer graph.
     /// -- selects peers at random even if not allowed
-    pub fn get_random_walk_neighbors(
+    pub fn get_random_walk_neighbors<R: Rng>(
         conn: &DBConn,
         network_id: u32,
         count: u32,
         block_height: u64,
+        rng: &mut R,
     ) -> Result<Vec<Neighbor>, db_error> {
-        PeerDB::get_random_neighbors(conn, network_id, count, block_height, false)
+        PeerDB::get_random_neighbors(conn, network_id, count, block_height, false, rng)
     }
 
     /// Add an IPv4 <--> ASN mapping
@@ -1354,6 +1433,11 @@ impl PeerDB {
 #[cfg(test)]
 mod test {
     use super::*;
+    use std::collections::HashSet;
+
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
     use net::Neighbor;
     use net::NeighborKey;
     use net::PeerAddress;
@@ -1383,6 +1467,45 @@ mod test {
         assert_eq!(local_peer.services, ServiceFlags::RELAY as u16);
     }
 
+    #[test]
+    fn test_onion_addresses() {
+        let mut db = PeerDB::connect_memory(
+            0x9abcdef0,
+            12345,
+            0,
+            "http://foo.com".into(),
+            &vec![],
+            &vec![],
+        )
+        .unwrap();
+
+        let onion = OnionAddress([0x11; 32]);
+        let addrbytes = PeerAddress::from_onion(&onion);
+
+        assert_eq!(
+            PeerDB::get_onion_address(db.conn(), &addrbytes).unwrap(),
+            None
+        );
+        assert_eq!(
+            PeerDB::get_onion_address(db.conn(), &PeerAddress::from_ipv4(127, 0, 0, 1)).unwrap(),
+            None
+        );
+
+        {
+            let mut tx = db.tx_begin().unwrap();
+            PeerDB::set_onion_address(&mut tx, &onion).unwrap();
+
+            // idempotent
+            PeerDB::set_onion_address(&mut tx, &onion).unwrap();
+            tx.commit().unwrap();
+        }
+
+        assert_eq!(
+            PeerDB::get_onion_address(db.conn(), &addrbytes).unwrap(),
+            Some(onion)
+        );
+    }
+
     #[test]
     fn test_peer_insert_and_retrieval() {
         let neighbor = Neighbor {
@@ -1600,17 +1723,23 @@ mod test {
         )
         .unwrap();
 
-        let n5 = PeerDB::get_initial_neighbors(db.conn(), 0x9abcdef0, 5, 23455).unwrap();
+        let n5 = PeerDB::get_initial_neighbors(db.conn(), 0x9abcdef0, 5, 23455, &mut thread_rng())
+            .unwrap();
         assert!(are_present(&n5, &initial_neighbors));
 
-        let n10 = PeerDB::get_initial_neighbors(db.conn(), 0x9abcdef0, 10, 23455).unwrap();
+        let n10 =
+            PeerDB::get_initial_neighbors(db.conn(), 0x9abcdef0, 10, 23455, &mut thread_rng())
+                .unwrap();
         assert!(are_present(&n10, &initial_neighbors));
 
-        let n20 = PeerDB::get_initial_neighbors(db.conn(), 0x9abcdef0, 20, 23455).unwrap();
+        let n20 =
+            PeerDB::get_initial_neighbors(db.conn(), 0x9abcdef0, 20, 23455, &mut thread_rng())
+                .unwrap();
         assert!(are_present(&initial_neighbors, &n20));
 
         let n15_fresh =
-            PeerDB::get_initial_neighbors(db.conn(), 0x9abcdef0, 15, 23456 + 14).unwrap();
+            PeerDB::get_initial_neighbors(db.conn(), 0x9abcdef0, 15, 23456 + 14, &mut thread_rng())
+                .unwrap();
         assert!(are_present(
             &n15_fresh[10..15].to_vec(),
             &initial_neighbors[10..20].to_vec()
@@ -1631,6 +1760,85 @@ mod test {
         }
     }
 
+    #[test]
+    fn test_random_neighbors_seeded() {
+        let mut initial_neighbors = vec![];
+        for i in 0..20 {
+            initial_neighbors.push(Neighbor {
+                addr: NeighborKey {
+                    peer_version: 0x12345678,
+                    network_id: 0x9abcdef0,
+                    addrbytes: PeerAddress([i as u8; 16]),
+                    port: i,
+                },
+                public_key: Secp256k1PublicKey::from_private(&Secp256k1PrivateKey::new()),
+                expire_block: (i + 23456) as u64,
+                last_contact_time: (1552509642 + (i as u64)) as u64,
+                allowed: 0,
+                denied: -1,
+                asn: (34567 + i) as u32,
+                org: (45678 + i) as u32,
+                in_degree: 1,
+                out_degree: 1,
+            });
+        }
+
+        let db = PeerDB::connect_memory(
+            0x9abcdef0,
+            12345,
+            0,
+            "http://foo.com".into(),
+            &vec![],
+            &initial_neighbors,
+        )
+        .unwrap();
+
+        let mut seen = HashSet::new();
+        for seed in 0..10 {
+            let sample = PeerDB::get_random_neighbors(
+                db.conn(),
+                0x9abcdef0,
+                5,
+                23455,
+                false,
+                &mut StdRng::seed_from_u64(seed),
+            )
+            .unwrap();
+            assert_eq!(sample.len(), 5);
+
+            let keys: HashSet<_> = sample.iter().map(|n| n.addr.clone()).collect();
+            assert_eq!(keys.len(), 5);
+            seen.extend(keys.into_iter());
+
+            // the same seed gives the same sample
+            let resample = PeerDB::get_random_neighbors(
+                db.conn(),
+                0x9abcdef0,
+                5,
+                23455,
+                false,
+                &mut StdRng::seed_from_u64(seed),
+            )
+            .unwrap();
+            assert_eq!(sample, resample);
+        }
+
+        // different seeds give different samples
+        assert!(seen.len() > 5);
+
+        // asking for more than there are gives all of them
+        let all = PeerDB::get_random_neighbors(
+            db.conn(),
+            0x9abcdef0,
+            30,
+            23455,
+            false,
+            &mut StdRng::seed_from_u64(0),
+        )
+        .unwrap();
+        assert_eq!(all.len(), 20);
+    }
+
     #[test]
     fn asn4_insert_lookup() {
         let asn4_table = vec![
@@ -1255,8 +1255,49 @@ impl PeerNetwork {
             let block_urls: HashSet<UrlString> = HashSet::new();
             (&mut neighbors[..]).shuffle(&mut thread_rng());
 
+            // burnchain height at which the block (or the block that produced the microblock
+            // stream) was chosen, which we only need to know if we're talking to pruned peers
+            let mut target_burn_height_opt = None;
+
             let mut requests = VecDeque::new();
             for nk in neighbors.drain(..) {
+                if let Some(pruned_height) = self.get_neighbor_pruned_height(&nk) {
+                    let target_burn_height = match target_burn_height_opt {
+                        Some(h) => h,
+                        None => {
+                            let h = match parent_consensus_hash_opt {
+                                Some(ref parent_consensus_hash) => {
+                                    SortitionDB::get_block_snapshot_consensus(
+                                        sortdb.conn(),
+                                        parent_consensus_hash,
+                                    )?
+                                    .ok_or_else(|| net_error::DBError(db_error::NotFoundError))?
+                                    .block_height
+                                }
+                                None => {
+                                    sortdb.first_block_height
+                                        + start_sortition_height
+                                        + (i as u64)
+                                        + 1
+                                }
+                            };
+                            target_burn_height_opt = Some(h);
+                            h
+                        }
+                    };
+                    if target_burn_height < pruned_height {
+                        debug!(
+                            "{:?}: Will not request {}/{} from pruned peer {:?} (below burn height {})",
+                            &self.local_peer,
+                            &target_consensus_hash,
+                            &target_block_hash,
+                            &nk,
+                            pruned_height
+                        );
+                        continue;
+                    }
+                }
+
                 let data_url = match self.get_data_url(&nk) {
                     Some(url) => url,
                     None => {
@@ -2627,6 +2668,7 @@ pub mod test {
                         chainstate,
                         mempool,
                         None,
+                        None,
                     )
                 })
                 .unwrap();
@@ -56,12 +56,17 @@ use net::ProtocolFamily;
 use net::StacksHttpMessage;
 use net::StacksHttpPreamble;
 use net::StacksMessageCodec;
+use net::TipRequest;
 use net::UnconfirmedTransactionResponse;
 use net::UnconfirmedTransactionStatus;
+use net::BATCH_ENTRIES_MAX;
+use net::BLOCK_HEADERS_PAGE_MAX;
 use net::HTTP_PREAMBLE_MAX_ENCODED_SIZE;
 use net::HTTP_PREAMBLE_MAX_NUM_HEADERS;
 use net::HTTP_REQUEST_ID_RESERVED;
 use net::MAX_MICROBLOCKS_UNCONFIRMED;
+use net::MEMPOOL_TXS_PAGE_MAX;
+use net::{BatchEntriesRequestBody, BatchMapEntryRequest};
 use net::{GetAttachmentResponse, GetAttachmentsInvResponse, PostTransactionRequestBody};
 use net::{MAX_MESSAGE_LEN, MAX_PAYLOAD_LEN};
 
@@ -96,16 +101,34 @@ lazy_static! {
     static ref PATH_GETPOXINFO: Regex = Regex::new(r#"^/v2/pox$"#).unwrap();
     static ref PATH_GETNEIGHBORS: Regex = Regex::new(r#"^/v2/neighbors$"#).unwrap();
     static ref PATH_GETBLOCK: Regex = Regex::new(r#"^/v2/blocks/([0-9a-f]{64})$"#).unwrap();
+    static ref PATH_GETBLOCK_HEADER: Regex =
+        Regex::new(r#"^/v2/blocks/([0-9a-f]{64})/header$"#).unwrap();
+    static ref PATH_GETBLOCK_HEADERS: Regex = Regex::new(r#"^/v2/blocks/headers$"#).unwrap();
     static ref PATH_GETMICROBLOCKS_INDEXED: Regex =
         Regex::new(r#"^/v2/microblocks/([0-9a-f]{64})$"#).unwrap();
     static ref PATH_GETMICROBLOCKS_CONFIRMED: Regex =
         Regex::new(r#"^/v2/microblocks/confirmed/([0-9a-f]{64})$"#).unwrap();
     static ref PATH_GETMICROBLOCKS_UNCONFIRMED: Regex =
         Regex::new(r#"^/v2/microblocks/unconfirmed/([0-9a-f]{64})/([0-9]{1,5})$"#).unwrap();
+    static ref PATH_GETMICROBLOCK_STREAMS: Regex =
+        Regex::new(r#"^/v2/microblocks/streams/([0-9a-f]{64})$"#).unwrap();
     static ref PATH_GETTRANSACTION_UNCONFIRMED: Regex =
         Regex::new(r#"^/v2/transactions/unconfirmed/([0-9a-f]{64})$"#).unwrap();
+    static ref PATH_GETHEADER: Regex = Regex::new(r#"^/v2/headers/([0-9]{1,20})$"#).unwrap();
     static ref PATH_POSTTRANSACTION: Regex = Regex::new(r#"^/v2/transactions$"#).unwrap();
     static ref PATH_POSTMICROBLOCK: Regex = Regex::new(r#"^/v2/microblocks$"#).unwrap();
+    static ref PATH_GET_MEMPOOL_TXS: Regex = Regex::new(&format!(
+        "^/v2/mempool/txs/(?P<address>{})$",
+        *STANDARD_PRINCIPAL_REGEX
+    ))
+    .unwrap();
+    static ref PATH_GET_MEMPOOL_NONCE: Regex = Regex::new(&format!(
+        "^/v2/mempool/nonce/(?P<address>{})$",
+        *STANDARD_PRINCIPAL_REGEX
+    ))
+    .unwrap();
+    static ref PATH_GET_MEMPOOL_FEES: Regex = Regex::new(r#"^/v2/mempool/fees$"#).unwrap();
+    static ref PATH_GET_MINER_ATTEMPTS: Regex = Regex::new(r#"^/v2/miner/attempts$"#).unwrap();
     static ref PATH_GET_ACCOUNT: Regex = Regex::new(&format!(
         "^/v2/accounts/(?P<principal>{})$",
         *PRINCIPAL_DATA_REGEX
@@ -131,6 +154,7 @@ lazy_static! {
         *STANDARD_PRINCIPAL_REGEX, *CONTRACT_NAME_REGEX
     ))
     .unwrap();
+    static ref PATH_POST_BATCH_ENTRIES: Regex = Regex::new("^/v2/batch_entries$").unwrap();
     static ref PATH_GET_TRANSFER_COST: Regex = Regex::new("^/v2/fees/transfer$").unwrap();
     static ref PATH_GET_ATTACHMENTS_INV: Regex = Regex::new("^/v2/attachments/inv$").unwrap();
     static ref PATH_GET_ATTACHMENT: Regex =
@@ -1442,6 +1466,16 @@ impl HttpRequestType {
                 &HttpRequestType::parse_getneighbors,
             ),
             ("GET", &PATH_GETBLOCK, &HttpRequestType::parse_getblock),
+            (
+                "GET",
+                &PATH_GETBLOCK_HEADER,
+                &HttpRequestType::parse_getblock_header,
+            ),
+            (
+                "GET",
+                &PATH_GETBLOCK_HEADERS,
+                &HttpRequestType::parse_getblock_headers,
+            ),
             (
                 "GET",
                 &PATH_GETMICROBLOCKS_INDEXED,
@@ -1457,11 +1491,17 @@ impl HttpRequestType {
                 &PATH_GETMICROBLOCKS_UNCONFIRMED,
                 &HttpRequestType::parse_getmicroblocks_unconfirmed,
             ),
+            (
+                "GET",
+                &PATH_GETMICROBLOCK_STREAMS,
+                &HttpRequestType::parse_getmicroblock_streams,
+            ),
             (
                 "GET",
                 &PATH_GETTRANSACTION_UNCONFIRMED,
                 &HttpRequestType::parse_gettransaction_unconfirmed,
             ),
+            ("GET", &PATH_GETHEADER, &HttpRequestType::parse_getheader),
             (
                 "POST",
                 &PATH_POSTTRANSACTION,
@@ -1477,11 +1517,36 @@ impl HttpRequestType {
                 &PATH_GET_ACCOUNT,
                 &HttpRequestType::parse_get_account,
             ),
+            (
+                "GET",
+                &PATH_GET_MEMPOOL_TXS,
+                &HttpRequestType::parse_get_mempool_txs,
+            ),
+            (
+                "GET",
+                &PATH_GET_MEMPOOL_NONCE,
+                &HttpRequestType::parse_get_mempool_nonce,
+            ),
+            (
+                "GET",
+                &PATH_GET_MEMPOOL_FEES,
+                &HttpRequestType::parse_get_mempool_fees,
+            ),
+            (
+                "GET",
+                &PATH_GET_MINER_ATTEMPTS,
+                &HttpRequestType::parse_get_miner_attempts,
+            ),
             (
                 "POST",
                 &PATH_GET_MAP_ENTRY,
                 &HttpRequestType::parse_get_map_entry,
             ),
+            (
+                "POST",
+                &PATH_POST_BATCH_ENTRIES,
+                &HttpRequestType::parse_get_batch_entries,
+            ),
             (
                 "GET",
                 &PATH_GET_TRANSFER_COST,
@@ -1650,18 +1715,20 @@ impl HttpRequestType {
         !no_proof
     }
 
-    /// get the chain tip optional query argument (`tip`)
+    /// get the chain tip optional query argument (`tip` or `height`)
     /// Take the first value we can parse.
-    fn get_chain_tip_query(query: Option<&str>) -> Option<StacksBlockId> {
+    fn get_chain_tip_query(query: Option<&str>) -> Option<TipRequest> {
         match query {
             Some(query_string) => {
                 for (key, value) in form_urlencoded::parse(query_string.as_bytes()) {
-                    if key != "tip" {
-                        continue;
-                    }
-
-                    if let Ok(tip) = StacksBlockId::from_hex(&value) {
-                        return Some(tip);
+                    if key == "tip" {
+                        if let Ok(tip) = StacksBlockId::from_hex(&value) {
+                            return Some(TipRequest::SpecificTip(tip));
+                        }
+                    } else if key == "height" {
+                        if let Ok(height) = value.parse::<u64>() {
+                            return Some(TipRequest::AtHeight(height));
+                        }
                     }
                 }
                 return None;
@@ -1700,6 +1767,116 @@ impl HttpRequestType {
         ))
     }
 
+    fn parse_get_mempool_txs<R: Read>(
+        _protocol: &mut StacksHttp,
+        preamble: &HttpRequestPreamble,
+        captures: &Captures,
+        query: Option<&str>,
+        _fd: &mut R,
+    ) -> Result<HttpRequestType, net_error> {
+        if preamble.get_content_length() != 0 {
+            return Err(net_error::DeserializeError(
+                "Invalid Http request: expected 0-length body for GetMempoolTxs".to_string(),
+            ));
+        }
+
+        let address = StacksAddress::from_string(&captures["address"])
+            .ok_or_else(|| net_error::DeserializeError("Failed to parse address".into()))?;
+
+        let mut offset = 0;
+        let mut limit = MEMPOOL_TXS_PAGE_MAX;
+
+        if let Some(query) = query {
+            for (key, value) in form_urlencoded::parse(query.as_bytes()) {
+                if key == "offset" {
+                    offset = value.parse::<u64>().map_err(|_e| {
+                        net_error::DeserializeError("Failed to parse offset".to_string())
+                    })?;
+                } else if key == "limit" {
+                    limit = value.parse::<u32>().map_err(|_e| {
+                        net_error::DeserializeError("Failed to parse limit".to_string())
+                    })?;
+                }
+            }
+        }
+
+        if limit == 0 || limit > MEMPOOL_TXS_PAGE_MAX {
+            return Err(net_error::DeserializeError(format!(
+                "Invalid Http request: limit must be between 1 and {}",
+                MEMPOOL_TXS_PAGE_MAX
+            )));
+        }
+
+        Ok(HttpRequestType::GetMempoolTxs(
+            HttpRequestMetadata::from_preamble(preamble),
+            address,
+            offset,
+            limit,
+        ))
+    }
+
+    fn parse_get_mempool_nonce<R: Read>(
+        _protocol: &mut StacksHttp,
+        preamble: &HttpRequestPreamble,
+        captures: &Captures,
+        query: Option<&str>,
+        _fd: &mut R,
+    ) -> Result<HttpRequestType, net_error> {
+        if preamble.get_content_length() != 0 {
+            return Err(net_error::DeserializeError(
+                "Invalid Http request: expected 0-length body for GetMempoolNonce".to_string(),
+            ));
+        }
+
+        let address = StacksAddress::from_string(&captures["address"])
+            .ok_or_else(|| net_error::DeserializeError("Failed to parse address".into()))?;
+
+        let tip = HttpRequestType::get_chain_tip_query(query);
+
+        Ok(HttpRequestType::GetMempoolNonce(
+            HttpRequestMetadata::from_preamble(preamble),
+            address,
+            tip,
+        ))
+    }
+
+    fn parse_get_mempool_fees<R: Read>(
+        _protocol: &mut StacksHttp,
+        preamble: &HttpRequestPreamble,
+        _regex: &Captures,
+        _query: Option<&str>,
+        _fd: &mut R,
+    ) -> Result<HttpRequestType, net_error> {
+        if preamble.get_content_length() != 0 {
+            return Err(net_error::DeserializeError(
+                "Invalid Http request: expected 0-length body for GetMempoolFeeHistogram"
+                    .to_string(),
+            ));
+        }
+
+        Ok(HttpRequestType::GetMempoolFeeHistogram(
+            HttpRequestMetadata::from_preamble(preamble),
+        ))
+    }
+
+    fn parse_get_miner_attempts<R: Read>(
+        _protocol: &mut StacksHttp,
+        preamble: &HttpRequestPreamble,
+        _regex: &Captures,
+        _query: Option<&str>,
+        _fd: &mut R,
+    ) -> Result<HttpRequestType, net_error> {
+        if preamble.get_content_length() != 0 {
+            return Err(net_error::DeserializeError(
+                "Invalid Http request: expected 0-length body for GetMinerAttempts".to_string(),
+            ));
+        }
+
+        Ok(HttpRequestType::GetMinerAttempts(
+            HttpRequestMetadata::from_preamble(preamble),
+        ))
+    }
+
     fn parse_get_map_entry<R: Read>(
         _protocol: &mut StacksHttp,
         preamble: &HttpRequestPreamble,
@@ -1749,6 +1926,75 @@ impl HttpRequestType {
         ))
     }
 
+    fn parse_get_batch_entries<R: Read>(
+        protocol: &mut StacksHttp,
+        preamble: &HttpRequestPreamble,
+        _captures: &Captures,
+        query: Option<&str>,
+        fd: &mut R,
+    ) -> Result<HttpRequestType, net_error> {
+        let content_len = preamble.get_content_length();
+        if !(content_len > 0 && content_len < protocol.maximum_call_argument_size) {
+            return Err(net_error::DeserializeError(format!(
+                "Invalid Http request: invalid body length for GetBatchEntries ({})",
+                content_len
+            )));
+        }
+
+        if preamble.content_type != Some(HttpContentType::JSON) {
+            return Err(net_error::DeserializeError(
+                "Invalid content-type: expected application/json".into(),
+            ));
+        }
+
+        let body: BatchEntriesRequestBody = serde_json::from_reader(fd)
+            .map_err(|_e| net_error::DeserializeError("Failed to parse JSON body".into()))?;
+
+        if body.accounts.len() + body.map_entries.len() > BATCH_ENTRIES_MAX {
+            return Err(net_error::DeserializeError(format!(
+                "Invalid Http request: too many entries for GetBatchEntries (max {})",
+                BATCH_ENTRIES_MAX
+            )));
+        }
+
+        let accounts = body
+            .accounts
+            .iter()
+            .map(|principal| PrincipalData::parse(principal).ok())
+            .collect::<Option<Vec<PrincipalData>>>()
+            .ok_or_else(|| {
+                net_error::DeserializeError("Failed to parse account principal".into())
+            })?;
+
+        let mut map_entries = Vec::with_capacity(body.map_entries.len());
+        for entry in body.map_entries.into_iter() {
+            let contract_addr =
+                StacksAddress::from_string(&entry.contract_address).ok_or_else(|| {
+                    net_error::DeserializeError("Failed to parse contract address".into())
+                })?;
+            let contract_name = ContractName::try_from(entry.contract_name).map_err(|_e| {
+                net_error::DeserializeError("Failed to parse contract name".into())
+            })?;
+            let map_name = ClarityName::try_from(entry.map_name)
+                .map_err(|_e| net_error::DeserializeError("Failed to parse map name".into()))?;
+            let key = Value::try_deserialize_hex_untyped(&entry.key).map_err(|_e| {
+                net_error::DeserializeError("Failed to deserialize key value".into())
+            })?;
+            map_entries.push((contract_addr, contract_name, map_name, key));
+        }
+
+        let with_proof = HttpRequestType::get_proof_query(query);
+        let tip = HttpRequestType::get_chain_tip_query(query);
+
+        Ok(HttpRequestType::GetBatchEntries(
+            HttpRequestMetadata::from_preamble(preamble),
+            accounts,
+            map_entries,
+            tip,
+            with_proof,
+        ))
+    }
+
     fn parse_call_read_only<R: Read>(
         protocol: &mut StacksHttp,
         preamble: &HttpRequestPreamble,
@@ -1887,6 +2133,75 @@ impl HttpRequestType {
         ))
     }
 
+    fn parse_getblock_header<R: Read>(
+        _protocol: &mut StacksHttp,
+        preamble: &HttpRequestPreamble,
+        captures: &Captures,
+        _query: Option<&str>,
+        _fd: &mut R,
+    ) -> Result<HttpRequestType, net_error> {
+        if preamble.get_content_length() != 0 {
+            return Err(net_error::DeserializeError(
+                "Invalid Http request: expected 0-length body for GetBlockHeader".to_string(),
+            ));
+        }
+
+        let block_hash_str = captures
+            .get(1)
+            .ok_or(net_error::DeserializeError(
+                "Failed to match path to block hash group".to_string(),
+            ))?
+            .as_str();
+
+        let block_hash = StacksBlockId::from_hex(block_hash_str)
+            .map_err(|_e| net_error::DeserializeError("Failed to parse block hash".to_string()))?;
+
+        Ok(HttpRequestType::GetBlockHeader(
+            HttpRequestMetadata::from_preamble(preamble),
+            block_hash,
+        ))
+    }
+
+    fn parse_getblock_headers<R: Read>(
+        _protocol: &mut StacksHttp,
+        preamble: &HttpRequestPreamble,
+        _captures: &Captures,
+        query: Option<&str>,
+        _fd: &mut R,
+    ) -> Result<HttpRequestType, net_error> {
+        if preamble.get_content_length() != 0 {
+            return Err(net_error::DeserializeError(
+                "Invalid Http request: expected 0-length body for GetBlockHeaders".to_string(),
+            ));
+        }
+
+        let tip = HttpRequestType::get_chain_tip_query(query);
+        let mut limit = BLOCK_HEADERS_PAGE_MAX;
+
+        if let Some(query) = query {
+            for (key, value) in form_urlencoded::parse(query.as_bytes()) {
+                if key == "limit" {
+                    limit = value.parse::<u32>().map_err(|_e| {
+                        net_error::DeserializeError("Failed to parse limit".to_string())
+                    })?;
+                }
+            }
+        }
+
+        if limit == 0 || limit > BLOCK_HEADERS_PAGE_MAX {
+            return Err(net_error::DeserializeError(format!(
+                "Invalid Http request: limit must be between 1 and {}",
+                BLOCK_HEADERS_PAGE_MAX
+            )));
+        }
+
+        Ok(HttpRequestType::GetBlockHeaders(
+            HttpRequestMetadata::from_preamble(preamble),
+            tip,
+            limit,
+        ))
+    }
+
     fn parse_getmicroblocks_indexed<R: Read>(
         _protocol: &mut StacksHttp,
         preamble: &HttpRequestPreamble,
@@ -1991,6 +2306,35 @@ impl HttpRequestType {
         ))
     }
 
+    fn parse_getmicroblock_streams<R: Read>(
+        _protocol: &mut StacksHttp,
+        preamble: &HttpRequestPreamble,
+        captures: &Captures,
+        _query: Option<&str>,
+        _fd: &mut R,
+    ) -> Result<HttpRequestType, net_error> {
+        if preamble.get_content_length() != 0 {
+            return Err(net_error::DeserializeError(
+                "Invalid Http request: expected 0-length body for GetMicroblockStreams".to_string(),
+            ));
+        }
+
+        let block_hash_str = captures
+            .get(1)
+            .ok_or(net_error::DeserializeError(
+                "Failed to match path to block hash group".to_string(),
+            ))?
+            .as_str();
+
+        let block_hash = StacksBlockId::from_hex(block_hash_str)
+            .map_err(|_e| net_error::DeserializeError("Failed to parse block hash".to_string()))?;
+
+        Ok(HttpRequestType::GetMicroblockStreams(
+            HttpRequestMetadata::from_preamble(preamble),
+            block_hash,
+        ))
+    }
+
     fn parse_gettransaction_unconfirmed<R: Read>(
         _protocol: &mut StacksHttp,
         preamble: &HttpRequestPreamble,
@@ -2027,6 +2371,36 @@ impl HttpRequestType {
         ))
     }
 
+    fn parse_getheader<R: Read>(
+        _protocol: &mut StacksHttp,
+        preamble: &HttpRequestPreamble,
+        regex: &Captures,
+        _query: Option<&str>,
+        _fd: &mut R,
+    ) -> Result<HttpRequestType, net_error> {
+        if preamble.get_content_length() != 0 {
+            return Err(net_error::DeserializeError(
+                "Invalid Http request: expected 0-length body for GetHeaderAtHeight".to_string(),
+            ));
+        }
+
+        let height_str = regex
+            .get(1)
+            .ok_or(net_error::DeserializeError(
+                "Failed to match path to block height group".to_string(),
+            ))?
+            .as_str();
+
+        let height = height_str.parse::<u64>().map_err(|_e| {
+            net_error::DeserializeError("Failed to decode block height".to_string())
+        })?;
+
+        Ok(HttpRequestType::GetHeaderAtHeight(
+            HttpRequestMetadata::from_preamble(preamble),
+            height,
+        ))
+    }
+
     fn parse_posttransaction<R: Read>(
         _protocol: &mut StacksHttp,
         preamble: &HttpRequestPreamble,
@@ -2212,17 +2586,12 @@ impl HttpRequestType {
             ));
         }
 
-        let mut tip = None;
+        let tip = HttpRequestType::get_chain_tip_query(query);
         let mut pages_indexes = HashSet::new();
 
         if let Some(query) = query {
             for (key, value) in form_urlencoded::parse(query.as_bytes()) {
-                if key == "tip" {
-                    tip = match StacksBlockId::from_hex(&value) {
-                        Ok(tip) => Some(tip),
-                        _ => None,
-                    };
-                } else if key == "pages_indexes" {
+                if key == "pages_indexes" {
                     if let Ok(pages_indexes_value) = value.parse::<String>() {
                         for entry in pages_indexes_value.split(",") {
                             if let Ok(page_index) = entry.parse::<u32>() {
@@ -2259,14 +2628,23 @@ impl HttpRequestType {
             HttpRequestType::GetPoxInfo(ref md, _) => md,
             HttpRequestType::GetNeighbors(ref md) => md,
             HttpRequestType::GetBlock(ref md, _) => md,
+            HttpRequestType::GetBlockHeader(ref md, _) => md,
+            HttpRequestType::GetBlockHeaders(ref md, ..) => md,
             HttpRequestType::GetMicroblocksIndexed(ref md, _) => md,
             HttpRequestType::GetMicroblocksConfirmed(ref md, _) => md,
             HttpRequestType::GetMicroblocksUnconfirmed(ref md, _, _) => md,
+            HttpRequestType::GetMicroblockStreams(ref md, _) => md,
             HttpRequestType::GetTransactionUnconfirmed(ref md, _) => md,
+            HttpRequestType::GetHeaderAtHeight(ref md, _) => md,
+            HttpRequestType::GetMempoolTxs(ref md, ..) => md,
+            HttpRequestType::GetMempoolNonce(ref md, ..) => md,
+            HttpRequestType::GetMempoolFeeHistogram(ref md) => md,
+            HttpRequestType::GetMinerAttempts(ref md) => md,
             HttpRequestType::PostTransaction(ref md, _, _) => md,
             HttpRequestType::PostMicroblock(ref md, ..) => md,
             HttpRequestType::GetAccount(ref md, ..) => md,
             HttpRequestType::GetMapEntry(ref md, ..) => md,
+            HttpRequestType::GetBatchEntries(ref md, ..) => md,
             HttpRequestType::GetTransferCost(ref md) => md,
             HttpRequestType::GetContractABI(ref md, ..) => md,
             HttpRequestType::GetContractSrc(ref md, ..) => md,
@@ -2284,14 +2662,23 @@ impl HttpRequestType {
             HttpRequestType::GetPoxInfo(ref mut md, _) => md,
             HttpRequestType::GetNeighbors(ref mut md) => md,
             HttpRequestType::GetBlock(ref mut md, _) => md,
+            HttpRequestType::GetBlockHeader(ref mut md, _) => md,
+            HttpRequestType::GetBlockHeaders(ref mut md, ..) => md,
             HttpRequestType::GetMicroblocksIndexed(ref mut md, _) => md,
             HttpRequestType::GetMicroblocksConfirmed(ref mut md, _) => md,
             HttpRequestType::GetMicroblocksUnconfirmed(ref mut md, _, _) => md,
+            HttpRequestType::GetMicroblockStreams(ref mut md, _) => md,
             HttpRequestType::GetTransactionUnconfirmed(ref mut md, _) => md,
+            HttpRequestType::GetHeaderAtHeight(ref mut md, _) => md,
+            HttpRequestType::GetMempoolTxs(ref mut md, ..) => md,
+            HttpRequestType::GetMempoolNonce(ref mut md, ..) => md,
+            HttpRequestType::GetMempoolFeeHistogram(ref mut md) => md,
+            HttpRequestType::GetMinerAttempts(ref mut md) => md,
             HttpRequestType::PostTransaction(ref mut md, _, _) => md,
             HttpRequestType::PostMicroblock(ref mut md, ..) => md,
             HttpRequestType::GetAccount(ref mut md, ..) => md,
             HttpRequestType::GetMapEntry(ref mut md, ..) => md,
+            HttpRequestType::GetBatchEntries(ref mut md, ..) => md,
             HttpRequestType::GetTransferCost(ref mut md) => md,
             HttpRequestType::GetContractABI(ref mut md, ..) => md,
             HttpRequestType::GetContractSrc(ref mut md, ..) => md,
@@ -2303,9 +2690,12 @@ impl HttpRequestType {
         }
     }
 
-    fn make_query_string(tip_opt: Option<&StacksBlockId>, with_proof: bool) -> String {
-        if let Some(tip) = tip_opt {
-            format!("?tip={}{}", tip, if with_proof { "" } else { "&proof=0" })
+    fn make_query_string(tip_opt: Option<&TipRequest>, with_proof: bool) -> String {
+        let proof_query = if with_proof { "" } else { "&proof=0" };
+        if let Some(TipRequest::SpecificTip(tip)) = tip_opt {
+            format!("?tip={}{}", tip, proof_query)
+        } else if let Some(TipRequest::AtHeight(height)) = tip_opt {
+            format!("?height={}{}", height, proof_query)
         } else if !with_proof {
             format!("?proof=0")
         } else {
@@ -2324,6 +2714,15 @@ impl HttpRequestType {
             HttpRequestType::GetBlock(_md, block_hash) => {
                 format!("/v2/blocks/{}", block_hash.to_hex())
             }
+            HttpRequestType::GetBlockHeader(_md, block_hash) => {
+                format!("/v2/blocks/{}/header", block_hash.to_hex())
+            }
+            HttpRequestType::GetBlockHeaders(_md, tip_opt, limit) => format!(
+                "/v2/blocks/headers{}{}limit={}",
+                HttpRequestType::make_query_string(tip_opt.as_ref(), true),
+                if tip_opt.is_some() { "&" } else { "?" },
+                limit
+            ),
             HttpRequestType::GetMicroblocksIndexed(_md, block_hash) => {
                 format!("/v2/microblocks/{}", block_hash.to_hex())
             }
@@ -2335,9 +2734,26 @@ impl HttpRequestType {
                 block_hash.to_hex(),
                 min_seq
             ),
+            HttpRequestType::GetMicroblockStreams(_md, block_hash) => {
+                format!("/v2/microblocks/streams/{}", block_hash.to_hex())
+            }
             HttpRequestType::GetTransactionUnconfirmed(_md, txid) => {
                 format!("/v2/transactions/unconfirmed/{}", txid)
             }
+            HttpRequestType::GetHeaderAtHeight(_md, height) => {
+                format!("/v2/headers/{}", height)
+            }
+            HttpRequestType::GetMempoolTxs(_md, address, offset, limit) => format!(
+                "/v2/mempool/txs/{}?offset={}&limit={}",
+                address, offset, limit
+            ),
+            HttpRequestType::GetMempoolNonce(_md, address, tip_opt) => format!(
+                "/v2/mempool/nonce/{}{}",
+                address,
+                HttpRequestType::make_query_string(tip_opt.as_ref(), true)
+            ),
+            HttpRequestType::GetMempoolFeeHistogram(_md) => "/v2/mempool/fees".to_string(),
+            HttpRequestType::GetMinerAttempts(_md) => "/v2/miner/attempts".to_string(),
             HttpRequestType::PostTransaction(_md, ..) => "/v2/transactions".to_string(),
             HttpRequestType::PostMicroblock(_md, _, tip_opt) => format!(
                 "/v2/microblocks{}",
@@ -2363,6 +2779,10 @@ impl HttpRequestType {
                 map_name.as_str(),
                 HttpRequestType::make_query_string(tip_opt.as_ref(), *with_proof)
             ),
+            HttpRequestType::GetBatchEntries(_md, _, _, tip_opt, with_proof) => format!(
+                "/v2/batch_entries{}",
+                HttpRequestType::make_query_string(tip_opt.as_ref(), *with_proof)
+            ),
             HttpRequestType::GetTransferCost(_md) => "/v2/fees/transfer".into(),
             HttpRequestType::GetContractABI(_, contract_addr, contract_name, tip_opt) => format!(
                 "/v2/contracts/interface/{}/{}{}",
@@ -2515,11 +2935,52 @@ impl HttpRequestType {
                     &self.request_path(),
                     &md.peer,
                     md.keep_alive,
-                    Some(request_json.as_bytes().len() as u32),
+                    Some(request_json.as_bytes().len() as u32),
+                    Some(&HttpContentType::JSON),
+                    empty_headers,
+                )?;
+                fd.write_all(&request_json.as_bytes())
+                    .map_err(net_error::WriteError)?;
+            }
+            HttpRequestType::GetBatchEntries(md, accounts, map_entries, ..) => {
+                let mut map_entry_requests = vec![];
+                for (contract_addr, contract_name, map_name, key) in map_entries.iter() {
+                    let mut key_bytes = vec![];
+                    key.serialize_write(&mut key_bytes)
+                        .map_err(net_error::WriteError)?;
+                    map_entry_requests.push(BatchMapEntryRequest {
+                        contract_address: contract_addr.to_string(),
+                        contract_name: contract_name.to_string(),
+                        map_name: map_name.to_string(),
+                        key: to_hex(&key_bytes),
+                    });
+                }
+
+                let request_body = BatchEntriesRequestBody {
+                    accounts: accounts.iter().map(|a| a.to_string()).collect(),
+                    map_entries: map_entry_requests,
+                };
+
+                let mut request_body_bytes = vec![];
+                serde_json::to_writer(&mut request_body_bytes, &request_body).map_err(|e| {
+                    net_error::SerializeError(format!(
+                        "Failed to serialize batch entries request to JSON: {:?}",
+                        &e
+                    ))
+                })?;
+
+                HttpRequestPreamble::new_serialized(
+                    fd,
+                    &md.version,
+                    "POST",
+                    &self.request_path(),
+                    &md.peer,
+                    md.keep_alive,
+                    Some(request_body_bytes.len() as u32),
                     Some(&HttpContentType::JSON),
                     empty_headers,
                 )?;
-                fd.write_all(&request_json.as_bytes())
+                fd.write_all(&request_body_bytes)
                     .map_err(net_error::WriteError)?;
             }
             HttpRequestType::CallReadOnlyFunction(
@@ -2812,6 +3273,11 @@ impl HttpResponseType {
             (&PATH_GETPOXINFO, &HttpResponseType::parse_poxinfo),
             (&PATH_GETNEIGHBORS, &HttpResponseType::parse_neighbors),
             (&PATH_GETBLOCK, &HttpResponseType::parse_block),
+            (&PATH_GETBLOCK_HEADER, &HttpResponseType::parse_header_info),
+            (
+                &PATH_GETBLOCK_HEADERS,
+                &HttpResponseType::parse_block_headers,
+            ),
             (
                 &PATH_GETMICROBLOCKS_INDEXED,
                 &HttpResponseType::parse_microblocks,
@@ -2824,16 +3290,41 @@ impl HttpResponseType {
                 &PATH_GETMICROBLOCKS_UNCONFIRMED,
                 &HttpResponseType::parse_microblocks_unconfirmed,
             ),
+            (
+                &PATH_GETMICROBLOCK_STREAMS,
+                &HttpResponseType::parse_microblock_streams,
+            ),
             (
                 &PATH_GETTRANSACTION_UNCONFIRMED,
                 &HttpResponseType::parse_transaction_unconfirmed,
             ),
+            (&PATH_GETHEADER, &HttpResponseType::parse_header_info),
             (&PATH_POSTTRANSACTION, &HttpResponseType::parse_txid),
             (
                 &PATH_POSTMICROBLOCK,
                 &HttpResponseType::parse_microblock_hash,
             ),
             (&PATH_GET_ACCOUNT, &HttpResponseType::parse_get_account),
+            (
+                &PATH_GET_MEMPOOL_TXS,
+                &HttpResponseType::parse_get_mempool_txs,
+            ),
+            (
+                &PATH_GET_MEMPOOL_NONCE,
+                &HttpResponseType::parse_get_mempool_nonce,
+            ),
+            (
+                &PATH_GET_MEMPOOL_FEES,
+                &HttpResponseType::parse_get_mempool_fees,
+            ),
+            (
+                &PATH_GET_MINER_ATTEMPTS,
+                &HttpResponseType::parse_get_miner_attempts,
+            ),
+            (
+                &PATH_POST_BATCH_ENTRIES,
+                &HttpResponseType::parse_get_batch_entries,
+            ),
             (
                 &PATH_GET_CONTRACT_SRC,
                 &HttpResponseType::parse_get_contract_src,
@@ -3007,6 +3498,20 @@ impl HttpResponseType {
         ))
     }
 
+    fn parse_get_batch_entries<R: Read>(
+        _protocol: &mut StacksHttp,
+        request_version: HttpVersion,
+        preamble: &HttpResponsePreamble,
+        fd: &mut R,
+        len_hint: Option<usize>,
+    ) -> Result<HttpResponseType, net_error> {
+        let entries = HttpResponseType::parse_json(preamble, fd, len_hint, MAX_MESSAGE_LEN as u64)?;
+        Ok(HttpResponseType::GetBatchEntries(
+            HttpResponseMetadata::from_preamble(request_version, preamble),
+            entries,
+        ))
+    }
+
     fn parse_get_contract_src<R: Read>(
         _protocol: &mut StacksHttp,
         request_version: HttpVersion,
@@ -3115,6 +3620,108 @@ impl HttpResponseType {
         ))
     }
 
+    fn parse_microblock_streams<R: Read>(
+        _protocol: &mut StacksHttp,
+        request_version: HttpVersion,
+        preamble: &HttpResponsePreamble,
+        fd: &mut R,
+        len_hint: Option<usize>,
+    ) -> Result<HttpResponseType, net_error> {
+        let streams = HttpResponseType::parse_json(preamble, fd, len_hint, MAX_MESSAGE_LEN as u64)?;
+        Ok(HttpResponseType::MicroblockStreams(
+            HttpResponseMetadata::from_preamble(request_version, preamble),
+            streams,
+        ))
+    }
+
+    fn parse_get_mempool_txs<R: Read>(
+        _protocol: &mut StacksHttp,
+        request_version: HttpVersion,
+        preamble: &HttpResponsePreamble,
+        fd: &mut R,
+        len_hint: Option<usize>,
+    ) -> Result<HttpResponseType, net_error> {
+        let txs = HttpResponseType::parse_json(preamble, fd, len_hint, MAX_MESSAGE_LEN as u64)?;
+        Ok(HttpResponseType::MempoolTxs(
+            HttpResponseMetadata::from_preamble(request_version, preamble),
+            txs,
+        ))
+    }
+
+    fn parse_get_mempool_nonce<R: Read>(
+        _protocol: &mut StacksHttp,
+        request_version: HttpVersion,
+        preamble: &HttpResponsePreamble,
+        fd: &mut R,
+        len_hint: Option<usize>,
+    ) -> Result<HttpResponseType, net_error> {
+        let nonce_data =
+            HttpResponseType::parse_json(preamble, fd, len_hint, MAX_MESSAGE_LEN as u64)?;
+        Ok(HttpResponseType::MempoolNonce(
+            HttpResponseMetadata::from_preamble(request_version, preamble),
+            nonce_data,
+        ))
+    }
+
+    fn parse_get_mempool_fees<R: Read>(
+        _protocol: &mut StacksHttp,
+        request_version: HttpVersion,
+        preamble: &HttpResponsePreamble,
+        fd: &mut R,
+        len_hint: Option<usize>,
+    ) -> Result<HttpResponseType, net_error> {
+        let histogram =
+            HttpResponseType::parse_json(preamble, fd, len_hint, MAX_MESSAGE_LEN as u64)?;
+        Ok(HttpResponseType::MempoolFeeHistogram(
+            HttpResponseMetadata::from_preamble(request_version, preamble),
+            histogram,
+        ))
+    }
+
+    fn parse_get_miner_attempts<R: Read>(
+        _protocol: &mut StacksHttp,
+        request_version: HttpVersion,
+        preamble: &HttpResponsePreamble,
+        fd: &mut R,
+        len_hint: Option<usize>,
+    ) -> Result<HttpResponseType, net_error> {
+        let attempts =
+            HttpResponseType::parse_json(preamble, fd, len_hint, MAX_MESSAGE_LEN as u64)?;
+        Ok(HttpResponseType::MinerAttempts(
+            HttpResponseMetadata::from_preamble(request_version, preamble),
+            attempts,
+        ))
+    }
+
+    fn parse_header_info<R: Read>(
+        _protocol: &mut StacksHttp,
+        request_version: HttpVersion,
+        preamble: &HttpResponsePreamble,
+        fd: &mut R,
+        len_hint: Option<usize>,
+    ) -> Result<HttpResponseType, net_error> {
+        let header_info =
+            HttpResponseType::parse_json(preamble, fd, len_hint, MAX_MESSAGE_LEN as u64)?;
+        Ok(HttpResponseType::HeaderInfo(
+            HttpResponseMetadata::from_preamble(request_version, preamble),
+            header_info,
+        ))
+    }
+
+    fn parse_block_headers<R: Read>(
+        _protocol: &mut StacksHttp,
+        request_version: HttpVersion,
+        preamble: &HttpResponsePreamble,
+        fd: &mut R,
+        len_hint: Option<usize>,
+    ) -> Result<HttpResponseType, net_error> {
+        let headers = HttpResponseType::parse_json(preamble, fd, len_hint, MAX_MESSAGE_LEN as u64)?;
+        Ok(HttpResponseType::BlockHeaders(
+            HttpResponseMetadata::from_preamble(request_version, preamble),
+            headers,
+        ))
+    }
+
     fn parse_txid<R: Read>(
         _protocol: &mut StacksHttp,
         request_version: HttpVersion,
@@ -3199,6 +3806,7 @@ impl HttpResponseType {
             402 => "Payment Required",
             403 => "Forbidden",
             404 => "Not Found",
+            429 => "Too Many Requests",
             500 => "Internal Server Error",
             503 => "Service Temporarily Unavailable",
             _ => "Error",
@@ -3240,10 +3848,18 @@ impl HttpResponseType {
             HttpResponseType::TokenTransferCost(ref md, _) => md,
             HttpResponseType::GetMapEntry(ref md, _) => md,
             HttpResponseType::GetAccount(ref md, _) => md,
+            HttpResponseType::GetBatchEntries(ref md, _) => md,
             HttpResponseType::GetContractABI(ref md, _) => md,
             HttpResponseType::GetContractSrc(ref md, _) => md,
             HttpResponseType::CallReadOnlyFunction(ref md, _) => md,
             HttpResponseType::UnconfirmedTransaction(ref md, _) => md,
+            HttpResponseType::HeaderInfo(ref md, _) => md,
+            HttpResponseType::MempoolTxs(ref md, _) => md,
+            HttpResponseType::MempoolNonce(ref md, _) => md,
+            HttpResponseType::MempoolFeeHistogram(ref md, _) => md,
+            HttpResponseType::MinerAttempts(ref md, _) => md,
+            HttpResponseType::MicroblockStreams(ref md, _) => md,
+            HttpResponseType::BlockHeaders(ref md, _) => md,
             HttpResponseType::GetAttachment(ref md, _) => md,
             HttpResponseType::GetAttachmentsInv(ref md, _) => md,
             HttpResponseType::OptionsPreflight(ref md) => md,
@@ -3346,6 +3962,10 @@ impl HttpResponseType {
                 HttpResponsePreamble::ok_JSON_from_md(fd, md)?;
                 HttpResponseType::send_json(protocol, md, fd, map_data)?;
             }
+            HttpResponseType::GetBatchEntries(ref md, ref entries) => {
+                HttpResponsePreamble::ok_JSON_from_md(fd, md)?;
+                HttpResponseType::send_json(protocol, md, fd, entries)?;
+            }
             HttpResponseType::PeerInfo(ref md, ref peer_info) => {
                 HttpResponsePreamble::ok_JSON_from_md(fd, md)?;
                 HttpResponseType::send_json(protocol, md, fd, peer_info)?;
@@ -3446,6 +4066,34 @@ impl HttpResponseType {
                 HttpResponsePreamble::ok_JSON_from_md(fd, md)?;
                 HttpResponseType::send_json(protocol, md, fd, unconfirmed_status)?;
             }
+            HttpResponseType::HeaderInfo(ref md, ref header_info) => {
+                HttpResponsePreamble::ok_JSON_from_md(fd, md)?;
+                HttpResponseType::send_json(protocol, md, fd, header_info)?;
+            }
+            HttpResponseType::MempoolTxs(ref md, ref txs) => {
+                HttpResponsePreamble::ok_JSON_from_md(fd, md)?;
+                HttpResponseType::send_json(protocol, md, fd, txs)?;
+            }
+            HttpResponseType::MempoolNonce(ref md, ref nonce_data) => {
+                HttpResponsePreamble::ok_JSON_from_md(fd, md)?;
+                HttpResponseType::send_json(protocol, md, fd, nonce_data)?;
+            }
+            HttpResponseType::MempoolFeeHistogram(ref md, ref histogram) => {
+                HttpResponsePreamble::ok_JSON_from_md(fd, md)?;
+                HttpResponseType::send_json(protocol, md, fd, histogram)?;
+            }
+            HttpResponseType::MinerAttempts(ref md, ref attempts) => {
+                HttpResponsePreamble::ok_JSON_from_md(fd, md)?;
+                HttpResponseType::send_json(protocol, md, fd, attempts)?;
+            }
+            HttpResponseType::MicroblockStreams(ref md, ref streams) => {
+                HttpResponsePreamble::ok_JSON_from_md(fd, md)?;
+                HttpResponseType::send_json(protocol, md, fd, streams)?;
+            }
+            HttpResponseType::BlockHeaders(ref md, ref headers) => {
+                HttpResponsePreamble::ok_JSON_from_md(fd, md)?;
+                HttpResponseType::send_json(protocol, md, fd, headers)?;
+            }
             HttpResponseType::OptionsPreflight(ref md) => {
                 HttpResponsePreamble::new_serialized(
                     fd,
@@ -3545,13 +4193,22 @@ impl MessageSequence for StacksHttpMessage {
                 HttpRequestType::GetMicroblocksUnconfirmed(_, _, _) => {
                     "HTTP(GetMicroblocksUnconfirmed)"
                 }
+                HttpRequestType::GetMicroblockStreams(_, _) => "HTTP(GetMicroblockStreams)",
                 HttpRequestType::GetTransactionUnconfirmed(_, _) => {
                     "HTTP(GetTransactionUnconfirmed)"
                 }
+                HttpRequestType::GetHeaderAtHeight(_, _) => "HTTP(GetHeaderAtHeight)",
+                HttpRequestType::GetMempoolTxs(..) => "HTTP(GetMempoolTxs)",
+                HttpRequestType::GetMempoolNonce(..) => "HTTP(GetMempoolNonce)",
+                HttpRequestType::GetMempoolFeeHistogram(_) => "HTTP(GetMempoolFeeHistogram)",
+                HttpRequestType::GetMinerAttempts(_) => "HTTP(GetMinerAttempts)",
+                HttpRequestType::GetBlockHeader(_, _) => "HTTP(GetBlockHeader)",
+                HttpRequestType::GetBlockHeaders(..) => "HTTP(GetBlockHeaders)",
                 HttpRequestType::PostTransaction(_, _, _) => "HTTP(PostTransaction)",
                 HttpRequestType::PostMicroblock(..) => "HTTP(PostMicroblock)",
                 HttpRequestType::GetAccount(..) => "HTTP(GetAccount)",
                 HttpRequestType::GetMapEntry(..) => "HTTP(GetMapEntry)",
+                HttpRequestType::GetBatchEntries(..) => "HTTP(GetBatchEntries)",
                 HttpRequestType::GetTransferCost(_) => "HTTP(GetTransferCost)",
                 HttpRequestType::GetContractABI(..) => "HTTP(GetContractABI)",
                 HttpRequestType::GetContractSrc(..) => "HTTP(GetContractSrc)",
@@ -3565,6 +4222,7 @@ impl MessageSequence for StacksHttpMessage {
                 HttpResponseType::TokenTransferCost(_, _) => "HTTP(TokenTransferCost)",
                 HttpResponseType::GetMapEntry(_, _) => "HTTP(GetMapEntry)",
                 HttpResponseType::GetAccount(_, _) => "HTTP(GetAccount)",
+                HttpResponseType::GetBatchEntries(_, _) => "HTTP(GetBatchEntries)",
                 HttpResponseType::GetContractABI(..) => "HTTP(GetContractABI)",
                 HttpResponseType::GetContractSrc(..) => "HTTP(GetContractSrc)",
                 HttpResponseType::CallReadOnlyFunction(..) => "HTTP(CallReadOnlyFunction)",
@@ -3580,6 +4238,13 @@ impl MessageSequence for StacksHttpMessage {
                 HttpResponseType::TransactionID(_, _) => "HTTP(Transaction)",
                 HttpResponseType::MicroblockHash(_, _) => "HTTP(Microblock)",
                 HttpResponseType::UnconfirmedTransaction(_, _) => "HTTP(UnconfirmedTransaction)",
+                HttpResponseType::HeaderInfo(_, _) => "HTTP(HeaderInfo)",
+                HttpResponseType::MempoolTxs(_, _) => "HTTP(MempoolTxs)",
+                HttpResponseType::MempoolNonce(_, _) => "HTTP(MempoolNonce)",
+                HttpResponseType::MempoolFeeHistogram(_, _) => "HTTP(MempoolFeeHistogram)",
+                HttpResponseType::MinerAttempts(_, _) => "HTTP(MinerAttempts)",
+                HttpResponseType::MicroblockStreams(_, _) => "HTTP(MicroblockStreams)",
+                HttpResponseType::BlockHeaders(_, _) => "HTTP(BlockHeaders)",
                 HttpResponseType::OptionsPreflight(_) => "HTTP(OptionsPreflight)",
                 HttpResponseType::BadRequestJSON(..) | HttpResponseType::BadRequest(..) => {
                     "HTTP(400)"
@@ -4097,6 +4762,7 @@ mod test {
     use net::codec::test::check_codec_and_corruption;
     use net::test::*;
     use net::RPCNeighbor;
+    use net::RPCNeighborBandwidth;
     use net::RPCNeighborsInfo;
     use std::error::Error;
 
@@ -5064,6 +5730,43 @@ mod test {
                 http_request_metadata_ip.clone(),
                 StacksBlockId([3u8; 32]),
             ),
+            HttpRequestType::GetHeaderAtHeight(http_request_metadata_dns.clone(), 123),
+            HttpRequestType::GetMempoolTxs(
+                http_request_metadata_ip.clone(),
+                StacksAddress {
+                    version: 26,
+                    bytes: Hash160([0x11; 20]),
+                },
+                100,
+                20,
+            ),
+            HttpRequestType::GetMempoolNonce(
+                http_request_metadata_dns.clone(),
+                StacksAddress {
+                    version: 26,
+                    bytes: Hash160([0x22; 20]),
+                },
+                Some(TipRequest::AtHeight(7)),
+            ),
+            HttpRequestType::GetMempoolFeeHistogram(http_request_metadata_ip.clone()),
+            HttpRequestType::GetMinerAttempts(http_request_metadata_dns.clone()),
+            HttpRequestType::GetBlockHeaders(
+                http_request_metadata_dns.clone(),
+                Some(TipRequest::SpecificTip(StacksBlockId([4u8; 32]))),
+                10,
+            ),
+            HttpRequestType::GetBlockHeader(
+                http_request_metadata_ip.clone(),
+                StacksBlockId([5u8; 32]),
+            ),
+            HttpRequestType::GetMicroblockStreams(
+                http_request_metadata_dns.clone(),
+                StacksBlockId([6u8; 32]),
+            ),
+            HttpRequestType::GetPoxInfo(
+                http_request_metadata_ip.clone(),
+                Some(TipRequest::AtHeight(456)),
+            ),
             HttpRequestType::PostTransaction(
                 http_request_metadata_dns.clone(),
                 make_test_transaction(),
@@ -5114,6 +5817,96 @@ mod test {
                 http_request_metadata_ip.peer.port(),
                 http_request_metadata_ip.keep_alive,
             ),
+            HttpRequestPreamble::new(
+                HttpVersion::Http11,
+                "GET".to_string(),
+                "/v2/headers/123".to_string(),
+                http_request_metadata_dns.peer.hostname(),
+                http_request_metadata_dns.peer.port(),
+                http_request_metadata_dns.keep_alive,
+            ),
+            HttpRequestPreamble::new(
+                HttpVersion::Http11,
+                "GET".to_string(),
+                format!(
+                    "/v2/mempool/txs/{}?offset=100&limit=20",
+                    StacksAddress {
+                        version: 26,
+                        bytes: Hash160([0x11; 20]),
+                    }
+                ),
+                http_request_metadata_ip.peer.hostname(),
+                http_request_metadata_ip.peer.port(),
+                http_request_metadata_ip.keep_alive,
+            ),
+            HttpRequestPreamble::new(
+                HttpVersion::Http11,
+                "GET".to_string(),
+                format!(
+                    "/v2/mempool/nonce/{}?height=7",
+                    StacksAddress {
+                        version: 26,
+                        bytes: Hash160([0x22; 20]),
+                    }
+                ),
+                http_request_metadata_dns.peer.hostname(),
+                http_request_metadata_dns.peer.port(),
+                http_request_metadata_dns.keep_alive,
+            ),
+            HttpRequestPreamble::new(
+                HttpVersion::Http11,
+                "GET".to_string(),
+                "/v2/mempool/fees".to_string(),
+                http_request_metadata_ip.peer.hostname(),
+                http_request_metadata_ip.peer.port(),
+                http_request_metadata_ip.keep_alive,
+            ),
+            HttpRequestPreamble::new(
+                HttpVersion::Http11,
+                "GET".to_string(),
+                "/v2/miner/attempts".to_string(),
+                http_request_metadata_dns.peer.hostname(),
+                http_request_metadata_dns.peer.port(),
+                http_request_metadata_dns.keep_alive,
+            ),
+            HttpRequestPreamble::new(
+                HttpVersion::Http11,
+                "GET".to_string(),
+                format!(
+                    "/v2/blocks/headers?tip={}&limit=10",
+                    StacksBlockId([4u8; 32]).to_hex()
+                ),
+                http_request_metadata_dns.peer.hostname(),
+                http_request_metadata_dns.peer.port(),
+                http_request_metadata_dns.keep_alive,
+            ),
+            HttpRequestPreamble::new(
+                HttpVersion::Http11,
+                "GET".to_string(),
+                format!("/v2/blocks/{}/header", StacksBlockId([5u8; 32]).to_hex()),
+                http_request_metadata_ip.peer.hostname(),
+                http_request_metadata_ip.peer.port(),
+                http_request_metadata_ip.keep_alive,
+            ),
+            HttpRequestPreamble::new(
+                HttpVersion::Http11,
+                "GET".to_string(),
+                format!(
+                    "/v2/microblocks/streams/{}",
+                    StacksBlockId([6u8; 32]).to_hex()
+                ),
+                http_request_metadata_dns.peer.hostname(),
+                http_request_metadata_dns.peer.port(),
+                http_request_metadata_dns.keep_alive,
+            ),
+            HttpRequestPreamble::new(
+                HttpVersion::Http11,
+                "GET".to_string(),
+                "/v2/pox?height=456".to_string(),
+                http_request_metadata_ip.peer.hostname(),
+                http_request_metadata_ip.peer.port(),
+                http_request_metadata_ip.keep_alive,
+            ),
             post_transaction_preamble,
             HttpRequestPreamble::new(
                 HttpVersion::Http11,
@@ -5125,7 +5918,21 @@ mod test {
             ),
         ];
 
-        let expected_http_bodies = vec![vec![], vec![], vec![], tx_body];
+        let expected_http_bodies = vec![
+            vec![],
+            vec![],
+            vec![],
+            vec![],
+            vec![],
+            vec![],
+            vec![],
+            vec![],
+            vec![],
+            vec![],
+            vec![],
+            vec![],
+            tx_body,
+        ];
 
         for (test, (expected_http_preamble, expected_http_body)) in tests.iter().zip(
             expected_http_preambles
@@ -5188,6 +5995,24 @@ mod test {
             );
         }
 
+        let bad_limits = vec![
+            "GET /v2/blocks/headers?limit=0 HTTP/1.1\r\nUser-Agent: stacks/2.0\r\nHost: bad:123\r\n\r\n",
+            "GET /v2/blocks/headers?limit=51 HTTP/1.1\r\nUser-Agent: stacks/2.0\r\nHost: bad:123\r\n\r\n",
+            "GET /v2/blocks/headers?height=1&limit=bad HTTP/1.1\r\nUser-Agent: stacks/2.0\r\nHost: bad:123\r\n\r\n",
+            "GET /v2/mempool/txs/STVN97YYA10MY5F6KQJHKNYJNM24C4A1AT39WRW?limit=0 HTTP/1.1\r\nUser-Agent: stacks/2.0\r\nHost: bad:123\r\n\r\n",
+            "GET /v2/mempool/txs/STVN97YYA10MY5F6KQJHKNYJNM24C4A1AT39WRW?limit=51 HTTP/1.1\r\nUser-Agent: stacks/2.0\r\nHost: bad:123\r\n\r\n",
+            "GET /v2/mempool/txs/STVN97YYA10MY5F6KQJHKNYJNM24C4A1AT39WRW?offset=1&limit=bad HTTP/1.1\r\nUser-Agent: stacks/2.0\r\nHost: bad:123\r\n\r\n",
+        ];
+        for bad_limit in bad_limits {
+            let mut http = StacksHttp::new();
+            let (preamble, offset) = http.read_preamble(bad_limit.as_bytes()).unwrap();
+            let e = http.read_payload(&preamble, &bad_limit.as_bytes()[offset..]);
+            let estr = format!("{:?}", &e);
+
+            assert!(e.is_err(), estr);
+            assert!(e.unwrap_err().to_string().find("limit").is_some(), estr);
+        }
+
         let bad_content_types = vec![
             "POST /v2/transactions HTTP/1.1\r\nUser-Agent: stacks/2.0\r\nHost: bad:123\r\nContent-Length: 1\r\n\r\nb",
         ];
@@ -5217,6 +6042,7 @@ mod test {
                     )
                     .unwrap(),
                     authenticated: true,
+                    bandwidth: None,
                 },
                 RPCNeighbor {
                     network_id: 3,
@@ -5231,6 +6057,18 @@ mod test {
                     )
                     .unwrap(),
                     authenticated: false,
+                    bandwidth: Some(RPCNeighborBandwidth {
+                        bytes_sent: 1000,
+                        bytes_received: 2000,
+                        period_bytes_sent: 100,
+                        period_bytes_received: 200,
+                        upload_bandwidth: 10,
+                        download_bandwidth: 20,
+                        msg_bytes_sent: vec![("BlocksInv".to_string(), 100)].into_iter().collect(),
+                        msg_bytes_received: vec![("GetBlocksInv".to_string(), 200)]
+                            .into_iter()
+                            .collect(),
+                    }),
                 },
             ],
             inbound: vec![],
@@ -6011,30 +6849,36 @@ mod test {
         let query_txt = "tip=7070f213d719143d6045e08fd80f85014a161f8bbd3a42d1251576740826a392";
         assert_eq!(
             HttpRequestType::get_chain_tip_query(Some(query_txt)).unwrap(),
-            StacksBlockId::from_hex(
-                "7070f213d719143d6045e08fd80f85014a161f8bbd3a42d1251576740826a392"
+            TipRequest::SpecificTip(
+                StacksBlockId::from_hex(
+                    "7070f213d719143d6045e08fd80f85014a161f8bbd3a42d1251576740826a392"
+                )
+                .unwrap()
             )
-            .unwrap()
         );
 
         // first parseable tip is taken
         let query_txt_dup = "tip=7070f213d719143d6045e08fd80f85014a161f8bbd3a42d1251576740826a392&tip=03e26bd68a8722f8b3861e2058edcafde094ad059e152754986c3573306698f1";
         assert_eq!(
             HttpRequestType::get_chain_tip_query(Some(query_txt_dup)).unwrap(),
-            StacksBlockId::from_hex(
-                "7070f213d719143d6045e08fd80f85014a161f8bbd3a42d1251576740826a392"
+            TipRequest::SpecificTip(
+                StacksBlockId::from_hex(
+                    "7070f213d719143d6045e08fd80f85014a161f8bbd3a42d1251576740826a392"
+                )
+                .unwrap()
             )
-            .unwrap()
         );
 
         // first parseable tip is taken
         let query_txt_dup = "tip=bad&tip=7070f213d719143d6045e08fd80f85014a161f8bbd3a42d1251576740826a392&tip=03e26bd68a8722f8b3861e2058edcafde094ad059e152754986c3573306698f1";
         assert_eq!(
             HttpRequestType::get_chain_tip_query(Some(query_txt_dup)).unwrap(),
-            StacksBlockId::from_hex(
-                "7070f213d719143d6045e08fd80f85014a161f8bbd3a42d1251576740826a392"
+            TipRequest::SpecificTip(
+                StacksBlockId::from_hex(
+                    "7070f213d719143d6045e08fd80f85014a161f8bbd3a42d1251576740826a392"
+                )
+                .unwrap()
             )
-            .unwrap()
         );
 
         // tip can be skipped
@@ -6050,6 +6894,39 @@ mod test {
             HttpRequestType::get_chain_tip_query(Some(query_txt_none)),
             None
         );
+
+        // tip can be given as a block height
+        let query_txt_height = "height=123&proof=0";
+        assert_eq!(
+            HttpRequestType::get_chain_tip_query(Some(query_txt_height)).unwrap(),
+            TipRequest::AtHeight(123)
+        );
+
+        // first parseable tip or height is taken
+        let query_txt_mixed = "height=bad&height=-1&height=45&tip=7070f213d719143d6045e08fd80f85014a161f8bbd3a42d1251576740826a392";
+        assert_eq!(
+            HttpRequestType::get_chain_tip_query(Some(query_txt_mixed)).unwrap(),
+            TipRequest::AtHeight(45)
+        );
+
+        let query_txt_mixed =
+            "tip=7070f213d719143d6045e08fd80f85014a161f8bbd3a42d1251576740826a392&height=45";
+        assert_eq!(
+            HttpRequestType::get_chain_tip_query(Some(query_txt_mixed)).unwrap(),
+            TipRequest::SpecificTip(
+                StacksBlockId::from_hex(
+                    "7070f213d719143d6045e08fd80f85014a161f8bbd3a42d1251576740826a392"
+                )
+                .unwrap()
+            )
+        );
+
+        // height can be skipped
+        let query_txt_bad = "height=bad";
+        assert_eq!(
+            HttpRequestType::get_chain_tip_query(Some(query_txt_bad)),
+            None
+        );
     }
 
     #[test]
@@ -55,6 +55,7 @@ use chainstate::burn::BlockHeaderHash;
 use chainstate::burn::BlockSnapshot;
 
 use chainstate::stacks::db::StacksChainState;
+use chainstate::stacks::StacksBlockHeader;
 
 use burnchains::Burnchain;
 use burnchains::BurnchainView;
@@ -1207,6 +1208,59 @@ impl InvState {
         self.set_data_available(burnchain, neighbor_key, sortdb, consensus_hash, true)
     }
 
+    /// Set a block as available, given the header that was pushed to us in a BlockHeaders
+    /// message.  Unlike a BlocksAvailable announcement, the header is checked against the
+    /// sortition that selected it (i.e. it must be the winning block and must be consistent with
+    /// the winning block-commit and leader key) before the inv is updated.
+    /// Returns InvalidMessage if the header does not match the sortition.
+    /// Returns NotFoundError if the consensus hash is not recognized, but may be recognized in the
+    /// future.
+    pub fn set_block_header_available(
+        &mut self,
+        burnchain: &Burnchain,
+        neighbor_key: &NeighborKey,
+        sortdb: &SortitionDB,
+        consensus_hash: &ConsensusHash,
+        header: &StacksBlockHeader,
+    ) -> Result<Option<u64>, net_error> {
+        let sn = match SortitionDB::get_block_snapshot_consensus(sortdb.conn(), &consensus_hash)? {
+            Some(sn) => sn,
+            None => {
+                debug!("Unknown consensus hash {}", consensus_hash);
+                return Err(net_error::NotFoundError);
+            }
+        };
+
+        if sn.pox_valid && sn.sortition {
+            let block_hash = header.block_hash();
+            if sn.winning_stacks_block_hash != block_hash {
+                debug!(
+                    "Block header {} does not match winning block {} in {}",
+                    &block_hash, &sn.winning_stacks_block_hash, consensus_hash
+                );
+                return Err(net_error::InvalidMessage);
+            }
+
+            let ic = sortdb.index_conn();
+            let db_handle = SortitionHandleConn::open_reader_consensus(&ic, consensus_hash)?;
+            if StacksChainState::validate_anchored_block_header_burnchain(
+                &db_handle,
+                consensus_hash,
+                header,
+            )?
+            .is_none()
+            {
+                debug!(
+                    "Block header {}/{} is not consistent with its sortition",
+                    consensus_hash, &block_hash
+                );
+                return Err(net_error::InvalidMessage);
+            }
+        }
+
+        self.set_data_available(burnchain, neighbor_key, sortdb, consensus_hash, false)
+    }
+
     /// Invalidate all block inventories at and after a given reward cycle
     pub fn invalidate_block_inventories(&mut self, burnchain: &Burnchain, reward_cycle: u64) {
         for (_, stats) in self.block_stats.iter_mut() {
@@ -1603,6 +1657,19 @@ impl PeerNetwork {
         }
     }
 
+    /// Get the reward cycle at which to start scanning a peer's block inventory.  A pruned peer
+    /// may have discarded the blocks in older reward cycles, so there's no point asking it about
+    /// them.
+    fn get_block_scan_start(&self, nk: &NeighborKey) -> u64 {
+        match self.get_neighbor_pruned_height(nk) {
+            Some(pruned_height) => self
+                .burnchain
+                .block_height_to_reward_cycle(pruned_height)
+                .unwrap_or(0),
+            None => 0,
+        }
+    }
+
     /// Make the next GetBlocksInv for a peer
     fn make_next_getblocksinv(
         &self,
@@ -1636,7 +1703,7 @@ impl PeerNetwork {
             None => {
                 // proceed to block scan
                 debug!("{:?}: cannot make any more GetPoxInv requests for {:?}; proceeding to block inventory scan", &self.local_peer, nk);
-                stats.reset_block_scan(0);
+                stats.reset_block_scan(self.get_block_scan_start(nk));
                 return Ok(());
             }
         };
@@ -1685,7 +1752,7 @@ impl PeerNetwork {
                     .truncate_block_inventories(&self.burnchain, stats.target_pox_reward_cycle);
 
                 // proceed with block scan
-                stats.reset_block_scan(0);
+                stats.reset_block_scan(self.get_block_scan_start(nk));
             }
             // done
             return Ok(true);
@@ -1773,7 +1840,7 @@ impl PeerNetwork {
             }
 
             // proceed to block scan.
-            stats.reset_block_scan(0);
+            stats.reset_block_scan(self.get_block_scan_start(nk));
         } else {
             // continue with PoX scan.
             stats.pox_reward_cycle += pox_inv.bitlen as u64;
@@ -3594,6 +3661,118 @@ mod test {
         })
     }
 
+    #[test]
+    #[ignore]
+    fn test_sync_inv_2_peers_pruned() {
+        with_timeout(600, || {
+            let mut peer_1_config =
+                TestPeerConfig::new("test_sync_inv_2_peers_pruned", 31988, 41988);
+            let mut peer_2_config =
+                TestPeerConfig::new("test_sync_inv_2_peers_pruned", 31989, 41989);
+
+            // peer 2 advertises that it discards old blocks (but still has them all)
+            peer_2_config.connection_opts.pruned = true;
+
+            peer_1_config.add_neighbor(&peer_2_config.to_neighbor());
+            peer_2_config.add_neighbor(&peer_1_config.to_neighbor());
+
+            let mut peer_1 = TestPeer::new(peer_1_config);
+            let mut peer_2 = TestPeer::new(peer_2_config);
+
+            let num_blocks = (GETPOXINV_MAX_BITLEN * 2) as u64;
+            let first_stacks_block_height = {
+                let sn = SortitionDB::get_canonical_burn_chain_tip(
+                    &peer_1.sortdb.as_ref().unwrap().conn(),
+                )
+                .unwrap();
+                sn.block_height + 1
+            };
+
+            for _ in 0..num_blocks {
+                let (burn_ops, stacks_block, microblocks) = peer_2.make_default_tenure();
+
+                peer_1.next_burnchain_block(burn_ops.clone());
+                peer_2.next_burnchain_block(burn_ops.clone());
+
+                peer_1.process_stacks_epoch_at_tip(&stacks_block, &microblocks);
+                peer_2.process_stacks_epoch_at_tip(&stacks_block, &microblocks);
+            }
+
+            let tip_height =
+                SortitionDB::get_canonical_burn_chain_tip(peer_1.sortdb.as_ref().unwrap().conn())
+                    .unwrap()
+                    .block_height;
+            let pruned_height =
+                StacksChainState::get_pruned_peer_horizon(&peer_1.config.burnchain, tip_height)
+                    .unwrap();
+            assert!(pruned_height > first_stacks_block_height);
+
+            let num_unpruned_blocks = first_stacks_block_height + num_blocks - pruned_height;
+
+            let mut inv_1_count = 0;
+            let mut inv_2_count = 0;
+
+            while inv_1_count < num_unpruned_blocks || inv_2_count < num_blocks {
+                let _ = peer_1.step();
+                let _ = peer_2.step();
+
+                inv_1_count = match peer_1.network.inv_state {
+                    Some(ref inv) => inv.get_inv_num_blocks(&peer_2.to_neighbor().addr),
+                    None => 0,
+                };
+
+                inv_2_count = match peer_2.network.inv_state {
+                    Some(ref inv) => inv.get_inv_num_blocks(&peer_1.to_neighbor().addr),
+                    None => 0,
+                };
+            }
+
+            assert_eq!(
+                peer_1
+                    .network
+                    .get_neighbor_pruned_height(&peer_2.to_neighbor().addr),
+                Some(pruned_height)
+            );
+            assert_eq!(
+                peer_2
+                    .network
+                    .get_neighbor_pruned_height(&peer_1.to_neighbor().addr),
+                None
+            );
+
+            // peer 1 never asked peer 2 about the blocks it may have pruned
+            let peer_2_inv = peer_1
+                .network
+                .inv_state
+                .as_ref()
+                .unwrap()
+                .block_stats
+                .get(&peer_2.to_neighbor().addr)
+                .unwrap()
+                .inv
+                .clone();
+            for i in 0..num_blocks {
+                let height = i + first_stacks_block_height;
+                assert_eq!(peer_2_inv.has_ith_block(height), height >= pruned_height);
+            }
+
+            // peer 2 learned about all of peer 1's blocks
+            let peer_1_inv = peer_2
+                .network
+                .inv_state
+                .as_ref()
+                .unwrap()
+                .block_stats
+                .get(&peer_1.to_neighbor().addr)
+                .unwrap()
+                .inv
+                .clone();
+            for i in 0..num_blocks {
+                assert!(peer_1_inv.has_ith_block(i + first_stacks_block_height));
+            }
+        })
+    }
+
     #[test]
     #[ignore]
     fn test_sync_inv_2_peers_stale() {
@@ -31,10 +31,13 @@ pub mod prune;
 pub mod relay;
 pub mod rpc;
 pub mod server;
+#[cfg(test)]
+pub mod sim;
+pub mod socks;
 
 use std::borrow::Borrow;
 use std::cmp::PartialEq;
-use std::collections::{HashMap, HashSet};
+use std::collections::{BTreeMap, HashMap, HashSet};
 use std::convert::From;
 use std::convert::TryFrom;
 use std::error;
@@ -63,6 +66,7 @@ use serde_json;
 use regex::Regex;
 
 use core::mempool::*;
+use core::miner_history::{MinedBlockAttempt, MinedBlockOutcomeCounts};
 
 use burnchains::BurnchainHeaderHash;
 use burnchains::Txid;
@@ -75,12 +79,13 @@ use chainstate::burn::db::sortdb::PoxId;
 
 use chainstate::stacks::db::blocks::MemPoolRejection;
 use chainstate::stacks::{
-    Error as chain_error, StacksAddress, StacksBlock, StacksBlockId, StacksMicroblock,
-    StacksPublicKey, StacksTransaction,
+    Error as chain_error, StacksAddress, StacksBlock, StacksBlockHeader, StacksBlockId,
+    StacksMicroblock, StacksMicroblockHeader, StacksPublicKey, StacksTransaction,
 };
 
 use chainstate::stacks::Error as chainstate_error;
 
+use vm::costs::ExecutionCost;
 use vm::{
     analysis::contract_interface_builder::ContractInterface, types::PrincipalData, ClarityName,
     ContractName, Value,
@@ -107,6 +112,7 @@ use serde::de::Error as de_Error;
 use serde::ser::Error as ser_Error;
 
 use chainstate::stacks::index::Error as marf_error;
+use chainstate::stacks::index::TrieHash;
 use vm::clarity::Error as clarity_error;
 
 use crate::util::hash::Sha256Sum;
@@ -221,6 +227,8 @@ pub enum Error {
     ConnectionCycle,
     /// Requested data not found
     NotFoundError,
+    /// SOCKS proxy failed to connect us to the remote peer
+    ProxyError(String),
 }
 
 /// Enum for passing data for ClientErrors
@@ -303,6 +311,7 @@ impl fmt::Display for Error {
             Error::StaleView => write!(f, "State view is stale"),
             Error::ConnectionCycle => write!(f, "Tried to connect to myself"),
             Error::NotFoundError => write!(f, "Requested data not found"),
+            Error::ProxyError(ref s) => write!(f, "SOCKS proxy error: {}", s),
         }
     }
 }
@@ -361,6 +370,7 @@ impl error::Error for Error {
             Error::StaleView => None,
             Error::ConnectionCycle => None,
             Error::NotFoundError => None,
+            Error::ProxyError(ref _s) => None,
         }
     }
 }
@@ -588,8 +598,40 @@ impl PeerAddress {
     pub fn is_anynet(&self) -> bool {
         self.0 == [0x00; 16] || self == &PeerAddress::from_ipv4(0, 0, 0, 0)
     }
+
+    /// Convert from an onion address.
+    /// The onion service is represented by an address in the OnionCat range fd87:d87e:eb43::/48,
+    /// whose low-order 80 bits are the first 10 bytes of the onion service's public key.  This
+    /// address is only an identifier -- it cannot be dialed directly.  Use the peer DB to map it
+    /// back to the full onion address.
+    pub fn from_onion(onion: &OnionAddress) -> PeerAddress {
+        let mut bytes = [0u8; 16];
+        bytes[0..6].copy_from_slice(&ONION_PEER_ADDRESS_PREFIX);
+        bytes[6..16].copy_from_slice(&onion.as_bytes()[0..10]);
+        PeerAddress(bytes)
+    }
+
+    /// Is this the stand-in address for an onion service?
+    pub fn is_onion(&self) -> bool {
+        self.0[0..6] == ONION_PEER_ADDRESS_PREFIX
+    }
+
+    /// Is this the stand-in address for the given onion service?
+    pub fn is_onion_of(&self, onion: &OnionAddress) -> bool {
+        *self == PeerAddress::from_onion(onion)
+    }
 }
 
+/// Prefix of the IPv6 range that stands in for onion service addresses
+pub const ONION_PEER_ADDRESS_PREFIX: [u8; 6] = [0xfd, 0x87, 0xd8, 0x7e, 0xeb, 0x43];
+
+/// A Tor v3 onion service address (i.e. the service's ed25519 public key)
+pub struct OnionAddress(pub [u8; 32]);
+impl_array_newtype!(OnionAddress, u8, 32);
+impl_array_hexstring_fmt!(OnionAddress);
+impl_byte_array_newtype!(OnionAddress, u8, 32);
+pub const ONION_ADDRESS_ENCODED_SIZE: u32 = 32;
+
 /// A container for public keys (compressed secp256k1 public keys)
 pub struct StacksPublicKeyBuffer(pub [u8; 33]);
 impl_array_newtype!(StacksPublicKeyBuffer, u8, 33);
@@ -748,6 +790,37 @@ pub struct BlocksAvailableData {
     pub available: Vec<(ConsensusHash, BurnchainHeaderHash)>,
 }
 
+/// Block headers pushed ahead of their blocks.  The receiver checks each header against the
+/// sortition that selected it before fetching the block body.
+#[derive(Debug, Clone, PartialEq)]
+pub struct BlockHeadersData {
+    pub headers: Vec<(ConsensusHash, StacksBlockHeader)>,
+}
+
+/// Microblock headers pushed ahead of their microblocks.  The headers must form a contiguous
+/// stream confirmed by the anchored block `index_anchor_block`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct MicroblockHeadersData {
+    pub index_anchor_block: StacksBlockId,
+    pub headers: Vec<StacksMicroblockHeader>,
+}
+
+/// Request for the transactions in a peer's mempool that we don't have.
+/// The txids we already have are summarized in a bloom filter, whose bit indexes for a txid are
+/// derived from sha512/256(seed || txid).
+#[derive(Debug, Clone, PartialEq)]
+pub struct MemPoolSyncData {
+    pub seed: u32,      // random salt for the bloom filter hash functions
+    pub num_hashes: u8, // number of bits set per txid (at most MEMPOOL_SYNC_BLOOM_MAX_HASHES)
+    pub bloom: Vec<u8>, // bloom filter bits.  bloom[i] & (1 << j) != 0 means bit 8*i + j is set
+}
+
+/// Mempool transactions sent in reply to a GetMempool
+#[derive(Debug, Clone, PartialEq)]
+pub struct MemPoolTxsData {
+    pub txs: Vec<StacksTransaction>,
+}
+
 /// A descriptor of a peer
 #[derive(Clone, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize)]
 pub struct NeighborAddress {
@@ -801,6 +874,14 @@ pub struct NeighborsData {
     pub neighbors: Vec<NeighborAddress>,
 }
 
+/// A descriptor of a list of known peers, sent in reply to GetNeighbors to peers that can reach
+/// onion services.  Each onion peer in `neighbors` has its full address in `onions`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct OnionNeighborsData {
+    pub neighbors: Vec<NeighborAddress>,
+    pub onions: Vec<OnionAddress>,
+}
+
 /// Handshake request -- this is the first message sent to a peer.
 /// The remote peer will reply a HandshakeAccept with just a preamble
 /// if the peer accepts.  Otherwise it will get a HandshakeReject with just
@@ -822,6 +903,9 @@ pub struct HandshakeData {
 pub enum ServiceFlags {
     RELAY = 0x01,
     RPC = 0x02,
+    ONION = 0x04,   // this node can connect to onion services
+    HEADERS = 0x08, // this node understands BlockHeaders and MicroblockHeaders pushes
+    PRUNED = 0x10,  // this node discards old block bodies, so it can't serve them
 }
 
 #[derive(Debug, Clone, PartialEq)]
@@ -889,6 +973,11 @@ pub enum StacksMessageType {
     Pong(PongData),
     NatPunchRequest(u32),
     NatPunchReply(NatPunchData),
+    GetMempool(MemPoolSyncData),
+    MemPoolTxs(MemPoolTxsData),
+    OnionNeighbors(OnionNeighborsData),
+    BlockHeaders(BlockHeadersData),
+    MicroblockHeaders(MicroblockHeadersData),
 }
 
 /// Peer address variants
@@ -1025,6 +1114,142 @@ pub struct RPCPoxInfoData {
     pub next_reward_cycle_in: u64,
 }
 
+/// The data we return on GET /v2/headers/{height} and the /v2/blocks header routes
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RPCHeaderInfoData {
+    pub index_block_hash: StacksBlockId,
+    pub block_hash: BlockHeaderHash,
+    pub block_height: u64,
+    pub parent_index_block_hash: StacksBlockId,
+    pub parent_block_hash: BlockHeaderHash,
+    pub parent_microblock_hash: BlockHeaderHash,
+    pub parent_microblock_sequence: u16,
+    /// hash of the last microblock in the parent's stream that this block confirms, if any.
+    /// This is computed from the block header, so it's available for every block, not just the
+    /// chain tip.
+    pub microblock_tail: Option<BlockHeaderHash>,
+    pub index_root: TrieHash,
+    pub state_index_root: TrieHash,
+    pub consensus_hash: ConsensusHash,
+    pub burn_header_hash: String,
+    pub burn_header_height: u32,
+    pub burn_header_timestamp: u64,
+    pub anchored_block_size: u64,
+    /// address of the miner, if known (the boot block has none)
+    pub miner: Option<String>,
+    /// number of transactions in the anchored block, if recorded when this node processed it
+    pub tx_count: Option<u64>,
+    pub execution_cost: ExecutionCost,
+    /// hex-encoded serialized anchored block header
+    pub header: String,
+}
+
+/// A stored microblock, as reported by GET /v2/microblocks/streams/{index_block_hash}
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RPCMicroblockInfo {
+    pub microblock_hash: BlockHeaderHash,
+    pub parent_hash: BlockHeaderHash,
+    pub sequence: u16,
+    pub processed: bool,
+    pub orphaned: bool,
+}
+
+/// One of the (possibly forked) microblock streams an anchored block produced
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RPCMicroblockStream {
+    /// the stream's microblocks, in sequence order
+    pub microblocks: Vec<RPCMicroblockInfo>,
+    /// whether or not the anchored block's canonical child confirmed this stream, or a prefix of it
+    pub confirmed: bool,
+}
+
+/// Two or more distinct microblocks with the same sequence number, signed by the same miner
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RPCMicroblockEquivocation {
+    pub sequence: u16,
+    pub microblock_hashes: Vec<BlockHeaderHash>,
+}
+
+/// A poison-microblock report that was mined against an anchored block's miner
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RPCPoisonMicroblockReport {
+    pub reporter: String,
+    pub sequence: u16,
+}
+
+/// The data we return on GET /v2/microblocks/streams/{index_block_hash}
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RPCMicroblockStreamsData {
+    pub index_block_hash: StacksBlockId,
+    pub block_height: u64,
+    /// whether or not the anchored block is in the canonical fork
+    pub canonical: bool,
+    /// the last microblock of this block's stream that its canonical child confirmed, if any
+    pub confirmed_tail: Option<BlockHeaderHash>,
+    pub confirmed_sequence: Option<u16>,
+    pub streams: Vec<RPCMicroblockStream>,
+    pub equivocations: Vec<RPCMicroblockEquivocation>,
+    /// the poison-microblock report mined in the canonical fork against this block's miner, if any
+    pub poison_report: Option<RPCPoisonMicroblockReport>,
+}
+
+/// A pending transaction, as reported by GET /v2/mempool/txs/{address}
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RPCMempoolTxInfo {
+    pub txid: Txid,
+    pub origin_address: String,
+    pub origin_nonce: u64,
+    pub sponsor_address: String,
+    pub sponsor_nonce: u64,
+    pub tx_fee: u64,
+    pub length: u64,
+    /// when this node accepted the transaction, in seconds since the epoch
+    pub accept_time: u64,
+    /// the chain tip at which the transaction was accepted
+    pub consensus_hash: ConsensusHash,
+    pub block_header_hash: BlockHeaderHash,
+    pub block_height: u64,
+}
+
+/// The data we return on GET /v2/mempool/nonce/{address}
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RPCMempoolNonceData {
+    pub address: String,
+    /// the address's nonce as of the chain tip
+    pub confirmed_nonce: u64,
+    /// the first nonce at or after the confirmed nonce that no pending transaction spends
+    pub next_nonce: u64,
+    /// one more than the highest nonce any pending transaction spends
+    pub mempool_next_nonce: u64,
+}
+
+/// A range of fee rates, inclusive, and how many pending transactions pay a fee rate in it
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RPCFeeRateBucket {
+    pub min_fee_rate: u64,
+    pub max_fee_rate: u64,
+    pub tx_count: u64,
+    pub total_bytes: u64,
+}
+
+/// The data we return on GET /v2/mempool/fees
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RPCMempoolFeeHistogram {
+    pub tx_count: u64,
+    pub total_bytes: u64,
+    /// buckets of fee rates (in microSTX per byte), in ascending order
+    pub buckets: Vec<RPCFeeRateBucket>,
+}
+
+/// The data we return on GET /v2/miner/attempts
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RPCMinerAttemptsData {
+    /// outcomes of every block attempt this node has recorded
+    pub outcomes: MinedBlockOutcomeCounts,
+    /// the most recent block attempts, newest first
+    pub attempts: Vec<MinedBlockAttempt>,
+}
+
 #[derive(Debug, Clone, PartialEq, Copy, Hash)]
 #[repr(u8)]
 pub enum HttpVersion {
@@ -1159,6 +1384,37 @@ pub struct CallReadOnlyRequestBody {
     pub arguments: Vec<String>,
 }
 
+/// A data map entry to look up in a `/v2/batch_entries` request
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct BatchMapEntryRequest {
+    pub contract_address: String,
+    pub contract_name: String,
+    pub map_name: String,
+    /// hex-encoded serialized key
+    pub key: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct BatchEntriesRequestBody {
+    #[serde(default)]
+    pub accounts: Vec<String>,
+    #[serde(default)]
+    pub map_entries: Vec<BatchMapEntryRequest>,
+}
+
+/// Struct given back from a call to `/v2/batch_entries`.  The entries carry no proofs of their
+/// own; instead, a single TrieMerkleMultiProof covers all of them, with one proof per MARF key in
+/// this order: each account's balance and then its nonce, followed by each map entry.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct BatchEntriesResponse {
+    pub accounts: Vec<AccountEntryResponse>,
+    pub map_entries: Vec<MapEntryResponse>,
+    #[serde(rename = "proof")]
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub marf_proof: Option<String>,
+}
+
 /// Items in the NeighborsInfo -- combines NeighborKey and NeighborAddress
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct RPCNeighbor {
@@ -1169,6 +1425,21 @@ pub struct RPCNeighbor {
     pub port: u16,
     pub public_key_hash: Hash160,
     pub authenticated: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub bandwidth: Option<RPCNeighborBandwidth>,
+}
+
+/// Bandwidth used by a connected neighbor, as reported by `/v2/neighbors`
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RPCNeighborBandwidth {
+    pub bytes_sent: u64,
+    pub bytes_received: u64,
+    pub period_bytes_sent: u64,
+    pub period_bytes_received: u64,
+    pub upload_bandwidth: u64,
+    pub download_bandwidth: u64,
+    pub msg_bytes_sent: BTreeMap<String, u64>,
+    pub msg_bytes_received: BTreeMap<String, u64>,
 }
 
 impl RPCNeighbor {
@@ -1180,6 +1451,7 @@ impl RPCNeighbor {
             port: nk.port,
             public_key_hash: pkh,
             authenticated: auth,
+            bandwidth: None,
         }
     }
 }
@@ -1192,32 +1464,44 @@ pub struct RPCNeighborsInfo {
     pub outbound: Vec<RPCNeighbor>,
 }
 
+/// The chain tip that an HTTP request asks to be evaluated against, if not the canonical tip
+#[derive(Debug, Clone, PartialEq)]
+pub enum TipRequest {
+    /// A specific Stacks block, given by its index block hash (`?tip=`)
+    SpecificTip(StacksBlockId),
+    /// The Stacks block at this height in the canonical fork (`?height=`)
+    AtHeight(u64),
+}
+
 /// All HTTP request paths we support, and the arguments they carry in their paths
 #[derive(Debug, Clone, PartialEq)]
 pub enum HttpRequestType {
     GetInfo(HttpRequestMetadata),
-    GetPoxInfo(HttpRequestMetadata, Option<StacksBlockId>),
+    GetPoxInfo(HttpRequestMetadata, Option<TipRequest>),
     GetNeighbors(HttpRequestMetadata),
     GetBlock(HttpRequestMetadata, StacksBlockId),
+    GetBlockHeader(HttpRequestMetadata, StacksBlockId),
+    GetBlockHeaders(HttpRequestMetadata, Option<TipRequest>, u32),
     GetMicroblocksIndexed(HttpRequestMetadata, StacksBlockId),
     GetMicroblocksConfirmed(HttpRequestMetadata, StacksBlockId),
     GetMicroblocksUnconfirmed(HttpRequestMetadata, StacksBlockId, u16),
+    GetMicroblockStreams(HttpRequestMetadata, StacksBlockId),
     GetTransactionUnconfirmed(HttpRequestMetadata, Txid),
+    GetHeaderAtHeight(HttpRequestMetadata, u64),
+    GetMempoolTxs(HttpRequestMetadata, StacksAddress, u64, u32),
+    GetMempoolNonce(HttpRequestMetadata, StacksAddress, Option<TipRequest>),
+    GetMempoolFeeHistogram(HttpRequestMetadata),
+    GetMinerAttempts(HttpRequestMetadata),
     PostTransaction(HttpRequestMetadata, StacksTransaction, Option<Attachment>),
-    PostMicroblock(HttpRequestMetadata, StacksMicroblock, Option<StacksBlockId>),
-    GetAccount(
-        HttpRequestMetadata,
-        PrincipalData,
-        Option<StacksBlockId>,
-        bool,
-    ),
+    PostMicroblock(HttpRequestMetadata, StacksMicroblock, Option<TipRequest>),
+    GetAccount(HttpRequestMetadata, PrincipalData, Option<TipRequest>, bool),
     GetMapEntry(
         HttpRequestMetadata,
         StacksAddress,
         ContractName,
         ClarityName,
         Value,
-        Option<StacksBlockId>,
+        Option<TipRequest>,
         bool,
     ),
     CallReadOnlyFunction(
@@ -1227,25 +1511,32 @@ pub enum HttpRequestType {
         PrincipalData,
         ClarityName,
         Vec<Value>,
-        Option<StacksBlockId>,
+        Option<TipRequest>,
     ),
     GetTransferCost(HttpRequestMetadata),
     GetContractSrc(
         HttpRequestMetadata,
         StacksAddress,
         ContractName,
-        Option<StacksBlockId>,
+        Option<TipRequest>,
         bool,
     ),
     GetContractABI(
         HttpRequestMetadata,
         StacksAddress,
         ContractName,
-        Option<StacksBlockId>,
+        Option<TipRequest>,
+    ),
+    GetBatchEntries(
+        HttpRequestMetadata,
+        Vec<PrincipalData>,
+        Vec<(StacksAddress, ContractName, ClarityName, Value)>,
+        Option<TipRequest>,
+        bool,
     ),
     OptionsPreflight(HttpRequestMetadata, String),
     GetAttachment(HttpRequestMetadata, Hash160),
-    GetAttachmentsInv(HttpRequestMetadata, Option<StacksBlockId>, HashSet<u32>),
+    GetAttachmentsInv(HttpRequestMetadata, Option<TipRequest>, HashSet<u32>),
     /// catch-all for any errors we should surface from parsing
     ClientError(HttpRequestMetadata, ClientError),
 }
@@ -1327,15 +1618,23 @@ pub enum HttpResponseType {
     BlockStream(HttpResponseMetadata),
     Microblocks(HttpResponseMetadata, Vec<StacksMicroblock>),
     MicroblockStream(HttpResponseMetadata),
+    MicroblockStreams(HttpResponseMetadata, RPCMicroblockStreamsData),
     TransactionID(HttpResponseMetadata, Txid),
     MicroblockHash(HttpResponseMetadata, BlockHeaderHash),
     TokenTransferCost(HttpResponseMetadata, u64),
     GetMapEntry(HttpResponseMetadata, MapEntryResponse),
     CallReadOnlyFunction(HttpResponseMetadata, CallReadOnlyResponse),
     GetAccount(HttpResponseMetadata, AccountEntryResponse),
+    GetBatchEntries(HttpResponseMetadata, BatchEntriesResponse),
     GetContractABI(HttpResponseMetadata, ContractInterface),
     GetContractSrc(HttpResponseMetadata, ContractSrcResponse),
     UnconfirmedTransaction(HttpResponseMetadata, UnconfirmedTransactionResponse),
+    HeaderInfo(HttpResponseMetadata, RPCHeaderInfoData),
+    MempoolTxs(HttpResponseMetadata, Vec<RPCMempoolTxInfo>),
+    MempoolNonce(HttpResponseMetadata, RPCMempoolNonceData),
+    MempoolFeeHistogram(HttpResponseMetadata, RPCMempoolFeeHistogram),
+    MinerAttempts(HttpResponseMetadata, RPCMinerAttemptsData),
+    BlockHeaders(HttpResponseMetadata, Vec<RPCHeaderInfoData>),
     GetAttachment(HttpResponseMetadata, GetAttachmentResponse),
     GetAttachmentsInv(HttpResponseMetadata, GetAttachmentsInvResponse),
     OptionsPreflight(HttpResponseMetadata),
@@ -1379,6 +1678,11 @@ pub enum StacksMessageID {
     Pong = 16,
     NatPunchRequest = 17,
     NatPunchReply = 18,
+    GetMempool = 19,
+    MemPoolTxs = 20,
+    OnionNeighbors = 21,
+    BlockHeaders = 22,
+    MicroblockHeaders = 23,
     Reserved = 255,
 }
 
@@ -1475,6 +1779,9 @@ pub const MAX_NEIGHBORS_DATA_LEN: u32 = 128;
 // maximum number of relayers that can be included in a message
 pub const MAX_RELAYERS_LEN: u32 = 16;
 
+// maximum number of mined block attempts reported in an RPCMinerAttemptsData
+pub const MAX_MINER_ATTEMPTS_LEN: u64 = 100;
+
 // number of peers to relay to, depending on outbound or inbound
 pub const MAX_BROADCAST_OUTBOUND_RECEIVERS: usize = 8;
 pub const MAX_BROADCAST_INBOUND_RECEIVERS: usize = 16;
@@ -1498,6 +1805,28 @@ pub const GETPOXINV_MAX_BITLEN: u64 = 8;
 // message.
 pub const BLOCKS_PUSHED_MAX: u32 = 32;
 
+// maximum size of a mempool sync bloom filter, in bytes (enough for ~500,000 txids)
+pub const MEMPOOL_SYNC_BLOOM_MAX_LEN: u32 = 1024 * 1024;
+
+// maximum number of hash functions a mempool sync bloom filter may use (each consumes 4 bytes of
+// the 32-byte sha512/256 digest)
+pub const MEMPOOL_SYNC_BLOOM_MAX_HASHES: u8 = 8;
+
+// bloom filter bits per txid -- with 8 hash functions, this gives a false-positive rate of ~0.06%
+pub const MEMPOOL_SYNC_BLOOM_BITS_PER_TX: u64 = 16;
+
+// maximum number of transactions that can be sent in reply to a GetMempool
+pub const MEMPOOL_SYNC_TXS_MAX: u32 = 1024;
+
+// maximum number of accounts plus data map entries that can be looked up in one GetBatchEntries
+pub const BATCH_ENTRIES_MAX: usize = 128;
+
+// maximum number of block headers that can be sent in reply to a GetBlockHeaders
+pub const BLOCK_HEADERS_PAGE_MAX: u32 = 50;
+
+// maximum number of transactions that can be sent in reply to a GetMempoolTxs
+pub const MEMPOOL_TXS_PAGE_MAX: u32 = 50;
+
 macro_rules! impl_byte_array_message_codec {
     ($thing:ident, $len:expr) => {
         impl ::net::StacksMessageCodec for $thing {
@@ -1524,6 +1853,7 @@ impl_byte_array_message_codec!(BlockHeaderHash, 32);
 impl_byte_array_message_codec!(StacksBlockId, 32);
 impl_byte_array_message_codec!(MessageSignature, 65);
 impl_byte_array_message_codec!(PeerAddress, 16);
+impl_byte_array_message_codec!(OnionAddress, 32);
 impl_byte_array_message_codec!(StacksPublicKeyBuffer, 33);
 
 impl_byte_array_serde!(ConsensusHash);
@@ -1663,8 +1993,9 @@ pub struct NetworkResult {
     pub pushed_blocks: HashMap<NeighborKey, Vec<BlocksData>>, // all blocks pushed to us
     pub pushed_microblocks: HashMap<NeighborKey, Vec<(Vec<RelayData>, MicroblocksData)>>, // all microblocks pushed to us, and the relay hints from the message
     pub uploaded_transactions: Vec<StacksTransaction>, // transactions sent to us by the http server
-    pub uploaded_microblocks: Vec<MicroblocksData>,    // microblocks sent to us by the http server
-    pub uploaded_attachments: Vec<Attachment>,         // attachments sent to us by the http server
+    pub synced_transactions: Vec<StacksTransaction>, // transactions we fetched from a neighbor's mempool
+    pub uploaded_microblocks: Vec<MicroblocksData>,  // microblocks sent to us by the http server
+    pub uploaded_attachments: Vec<Attachment>,       // attachments sent to us by the http server
     pub attachments: Vec<AttachmentInstance>,
     pub num_state_machine_passes: u64,
     pub num_inv_sync_passes: u64,
@@ -1681,6 +2012,7 @@ impl NetworkResult {
             pushed_blocks: HashMap::new(),
             pushed_microblocks: HashMap::new(),
             uploaded_transactions: vec![],
+            synced_transactions: vec![],
             uploaded_microblocks: vec![],
             uploaded_attachments: vec![],
             attachments: vec![],
@@ -1700,7 +2032,9 @@ impl NetworkResult {
     }
 
     pub fn has_transactions(&self) -> bool {
-        self.pushed_transactions.len() > 0 || self.uploaded_transactions.len() > 0
+        self.pushed_transactions.len() > 0
+            || self.uploaded_transactions.len() > 0
+            || self.synced_transactions.len() > 0
     }
 
     pub fn has_attachments(&self) -> bool {
@@ -1712,6 +2046,7 @@ impl NetworkResult {
             .values()
             .flat_map(|pushed_txs| pushed_txs.iter().map(|(_, tx)| tx.clone()))
             .chain(self.uploaded_transactions.iter().map(|x| x.clone()))
+            .chain(self.synced_transactions.iter().map(|x| x.clone()))
             .collect()
     }
 
@@ -1804,9 +2139,11 @@ pub mod test {
     use net::db::*;
     use net::neighbors::*;
     use net::p2p::*;
+    use net::poll::NetworkState;
     use net::poll::*;
     use net::relay::*;
     use net::rpc::RPCHandlerArgs;
+    use net::sim::SimNetwork;
     use net::Error as net_error;
 
     use core::NETWORK_P2P_PORT;
@@ -2087,6 +2424,7 @@ pub mod test {
         pub initial_lockups: Vec<ChainstateAccountLockup>,
         pub spending_account: TestMiner,
         pub setup_code: String,
+        pub sim: Option<SimNetwork>,
     }
 
     impl TestPeerConfig {
@@ -2133,6 +2471,7 @@ pub mod test {
                 initial_lockups: vec![],
                 spending_account: spending_account,
                 setup_code: "".into(),
+                sim: None,
             }
         }
 
@@ -2210,6 +2549,34 @@ pub mod test {
         thread_handle.join().unwrap();
     }
 
+    /// Drive a set of peers in a simulated network: advance the network's virtual clock by
+    /// `tick_ms`, and then step each peer once, in order.  Stop once `done` returns true, or
+    /// after `max_ticks` ticks.  Returns the number of ticks it took, if `done` ever returned
+    /// true.
+    pub fn run_sim_peers<'a, F>(
+        sim: &SimNetwork,
+        peers: &mut Vec<TestPeer<'a>>,
+        tick_ms: u64,
+        max_ticks: usize,
+        mut done: F,
+    ) -> Option<usize>
+    where
+        F: FnMut(&mut Vec<TestPeer<'a>>) -> bool,
+    {
+        for i in 0..max_ticks {
+            sim.advance(tick_ms);
+            for peer in peers.iter_mut() {
+                if let Err(e) = peer.step() {
+                    test_debug!("{:?}: failed to step: {:?}", &peer.network.local_peer, &e);
+                }
+            }
+            if done(peers) {
+                return Some(i + 1);
+            }
+        }
+        None
+    }
+
     pub struct TestPeer<'a> {
         pub config: TestPeerConfig,
         pub network: PeerNetwork,
@@ -2443,7 +2810,24 @@ pub mod test {
                 config.connection_opts.clone(),
             );
 
-            peer_network.bind(&local_addr, &http_local_addr).unwrap();
+            match config.sim {
+                Some(ref sim) => {
+                    let net =
+                        NetworkState::new_sim(config.connection_opts.max_sockets, sim).unwrap();
+
+                    // choose peers with randomness derived from the simulation's seed, so the
+                    // whole run can be replayed
+                    if let Some(seed) = net.sim_rng_seed() {
+                        peer_network.set_rng_seed(seed);
+                    }
+                    peer_network
+                        .bind_network(net, &local_addr, &http_local_addr)
+                        .unwrap();
+                }
+                None => {
+                    peer_network.bind(&local_addr, &http_local_addr).unwrap();
+                }
+            }
             let relayer = Relayer::from_p2p(&mut peer_network);
             let mempool = MemPoolDB::open(false, config.network_id, &chainstate_path).unwrap();
 
@@ -52,7 +52,6 @@ use burnchains::BurnchainView;
 use burnchains::PublicKey;
 
 use rand::prelude::*;
-use rand::thread_rng;
 use util::get_epoch_time_secs;
 use util::hash::*;
 use util::log;
@@ -178,8 +177,7 @@ impl Neighbor {
     /// Pr["P1 points to P2" | "P2 points to P1"] == Pr["P2 points to P1" | "P1 points to P2"].
     /// So, we can estimate the undirected degree as being a random value between the lower and
     /// upper bound.
-    pub fn degree(&self) -> u64 {
-        let mut rng = thread_rng();
+    pub fn degree<R: Rng>(&self, rng: &mut R) -> u64 {
         let min = cmp::min(self.in_degree, self.out_degree);
         let max = cmp::max(self.in_degree, self.out_degree);
         let res = rng.gen_range(min, max + 1) as u64;
@@ -729,18 +727,59 @@ impl NeighborWalk {
     }
 
     /// Select neighbors that are routable, and ignore ones that are not.
+    /// Onion services are only routable if we can reach them through a proxy.
     /// TODO: expand if we ever want to filter by unroutable network class or something
-    fn filter_sensible_neighbors(neighbors: Vec<NeighborAddress>) -> Vec<NeighborAddress> {
+    fn filter_sensible_neighbors(
+        neighbors: Vec<NeighborAddress>,
+        reach_onions: bool,
+    ) -> Vec<NeighborAddress> {
         let mut ret = vec![];
         for neighbor in neighbors.into_iter() {
             if neighbor.addrbytes.is_anynet() {
                 continue;
             }
+            if neighbor.addrbytes.is_onion() && !reach_onions {
+                continue;
+            }
             ret.push(neighbor);
         }
         ret
     }
 
+    /// Handle the list of neighbors cur_neighbor gave us.
+    /// Returns the list of neighbors we need to resolve
+    fn handle_neighbors_reply(
+        &mut self,
+        network: &mut PeerNetwork,
+        network_id: u32,
+        block_height: u64,
+        reported_neighbors: &Vec<NeighborAddress>,
+    ) -> Result<Vec<NeighborAddress>, net_error> {
+        debug!(
+            "{:?}: Got Neighbors from {:?}: {:?}",
+            &self.local_peer, &self.cur_neighbor.addr, reported_neighbors
+        );
+        let neighbors = NeighborWalk::filter_sensible_neighbors(
+            reported_neighbors.clone(),
+            network.connection_opts.socks_proxy.is_some(),
+        );
+        let (mut found, to_resolve) = NeighborWalk::lookup_stale_neighbors(
+            network.peerdb.conn(),
+            network_id,
+            block_height,
+            &neighbors,
+        )?;
+
+        for (_naddr, neighbor) in found.drain() {
+            self.new_frontier
+                .insert(neighbor.addr.clone(), neighbor.clone());
+            self.frontier
+                .insert(neighbor.addr.clone(), neighbor.clone());
+        }
+
+        Ok(to_resolve)
+    }
+
     /// Try to finish the getneighbors request to cur_neighbor
     /// Returns the list of neighbors we need to resolve
     /// Return None if we're not done yet, or haven't started yet.
@@ -777,26 +816,30 @@ impl NeighborWalk {
                 }
                 match message.payload {
                     StacksMessageType::Neighbors(ref data) => {
-                        debug!(
-                            "{:?}: Got Neighbors from {:?}: {:?}",
-                            &self.local_peer, &self.cur_neighbor.addr, data.neighbors
-                        );
-                        let neighbors =
-                            NeighborWalk::filter_sensible_neighbors(data.neighbors.clone());
-                        let (mut found, to_resolve) = NeighborWalk::lookup_stale_neighbors(
-                            network.peerdb.conn(),
+                        let to_resolve = self.handle_neighbors_reply(
+                            network,
                             message.preamble.network_id,
                             block_height,
-                            &neighbors,
+                            &data.neighbors,
                         )?;
-
-                        for (_naddr, neighbor) in found.drain() {
-                            self.new_frontier
-                                .insert(neighbor.addr.clone(), neighbor.clone());
-                            self.frontier
-                                .insert(neighbor.addr.clone(), neighbor.clone());
+                        Ok(Some(to_resolve))
+                    }
+                    StacksMessageType::OnionNeighbors(ref data) => {
+                        if network.connection_opts.socks_proxy.is_some() {
+                            // remember how to reach these onion services
+                            let mut tx = network.peerdb.tx_begin()?;
+                            for onion in data.onions.iter() {
+                                PeerDB::set_onion_address(&mut tx, onion)?;
+                            }
+                            tx.commit()?;
                         }
 
+                        let to_resolve = self.handle_neighbors_reply(
+                            network,
+                            message.preamble.network_id,
+                            block_height,
+                            &data.neighbors,
+                        )?;
                         Ok(Some(to_resolve))
                     }
                     StacksMessageType::Nack(ref data) => {
@@ -850,9 +893,10 @@ impl NeighborWalk {
 
     /// Given a neighbor we tried to insert into the peer database, find one of the existing
     /// neighbors it collided with.  Return its slot in the peer db.
-    fn find_replaced_neighbor_slot(
+    fn find_replaced_neighbor_slot<R: Rng>(
         conn: &DBConn,
         nk: &NeighborKey,
+        rng: &mut R,
     ) -> Result<Option<u32>, net_error> {
         let mut slots = PeerDB::peer_slots(conn, nk.network_id, &nk.addrbytes, nk.port)
             .map_err(net_error::DBError)?;
@@ -862,8 +906,7 @@ impl NeighborWalk {
             return Ok(None);
         }
 
-        let mut rng = thread_rng();
-        slots.shuffle(&mut rng);
+        slots.shuffle(rng);
 
         for slot in slots {
             let peer_opt =
@@ -884,9 +927,10 @@ impl NeighborWalk {
 
     /// Add a neighbor or schedule it to be pinged since it's up for replacement.
     /// Returns (was-new?, neighbor)
-    fn add_or_schedule_replace_neighbor<'a>(
+    fn add_or_schedule_replace_neighbor<'a, R: Rng>(
         &mut self,
         tx: &mut DBTx<'a>,
+        rng: &mut R,
         block_height: u64,
         naddr: &NeighborAddress,
         peer_version: u32,
@@ -922,6 +966,7 @@ impl NeighborWalk {
                     let replaced_neighbor_slot_opt = NeighborWalk::find_replaced_neighbor_slot(
                         tx,
                         &neighbor_from_handshake.addr,
+                        rng,
                     )?;
                     match replaced_neighbor_slot_opt {
                         Some(slot) => {
@@ -1011,6 +1056,7 @@ impl NeighborWalk {
                                 let mut tx = network.peerdb.tx_begin()?;
                                 let (new, neighbor) = self.add_or_schedule_replace_neighbor(
                                     &mut tx,
+                                    &mut network.rng,
                                     block_height,
                                     &naddr,
                                     message.preamble.peer_version,
@@ -1175,8 +1221,21 @@ impl NeighborWalk {
                                     "{:?}: Got Neighbors from {:?}: {:?}",
                                     &self.local_peer, &nkey, &data.neighbors
                                 );
-                                let neighbors =
-                                    NeighborWalk::filter_sensible_neighbors(data.neighbors.clone());
+                                let neighbors = NeighborWalk::filter_sensible_neighbors(
+                                    data.neighbors.clone(),
+                                    network.connection_opts.socks_proxy.is_some(),
+                                );
+                                self.resolved_getneighbors_neighbors.insert(nkey, neighbors);
+                            }
+                            StacksMessageType::OnionNeighbors(ref data) => {
+                                debug!(
+                                    "{:?}: Got OnionNeighbors from {:?}: {:?}",
+                                    &self.local_peer, &nkey, &data.neighbors
+                                );
+                                let neighbors = NeighborWalk::filter_sensible_neighbors(
+                                    data.neighbors.clone(),
+                                    network.connection_opts.socks_proxy.is_some(),
+                                );
                                 self.resolved_getneighbors_neighbors.insert(nkey, neighbors);
                             }
                             StacksMessageType::Nack(ref data) => {
@@ -1271,16 +1330,20 @@ impl NeighborWalk {
     }
 
     /// Pick a random neighbor from the frontier, excluding an optional given neighbor
-    fn pick_random_neighbor(
+    fn pick_random_neighbor<R: Rng>(
         frontier: &HashMap<NeighborKey, Neighbor>,
         exclude: Option<&Neighbor>,
+        rng: &mut R,
     ) -> Option<Neighbor> {
-        let mut rnd = thread_rng();
-
-        let sample = rnd.gen_range(0, frontier.len());
+        let sample = rng.gen_range(0, frontier.len());
         let mut count = 0;
 
-        for (nk, n) in frontier.iter() {
+        // visit the frontier in a fixed order, so the choice depends only on the RNG
+        let mut nks: Vec<&NeighborKey> = frontier.keys().collect();
+        nks.sort();
+
+        for nk in nks.into_iter() {
+            let n = &frontier[nk];
             count += match exclude {
                 None => 1,
                 Some(ref e) => {
@@ -1303,9 +1366,14 @@ impl NeighborWalk {
     /// measure how represented each neighbor's AS is in the peer graph.  We *bias* the sample so
     /// that peers in under-represented ASs are more likely to be walked to than they otherwise
     /// would be if considering only neighbor degrees.
-    fn degree_ratio(peerdb_conn: &DBConn, n1: &Neighbor, n2: &Neighbor) -> f64 {
-        let d1 = n1.degree() as f64;
-        let d2 = n2.degree() as f64;
+    fn degree_ratio<R: Rng>(
+        peerdb_conn: &DBConn,
+        n1: &Neighbor,
+        n2: &Neighbor,
+        rng: &mut R,
+    ) -> f64 {
+        let d1 = n1.degree(rng) as f64;
+        let d2 = n2.degree(rng) as f64;
         let as_d1 = PeerDB::asn_count(peerdb_conn, n1.asn).unwrap_or(1) as f64;
         let as_d2 = PeerDB::asn_count(peerdb_conn, n2.asn).unwrap_or(1) as f64;
         (d1 * as_d2) / (d2 * as_d1)
@@ -1325,15 +1393,13 @@ impl NeighborWalk {
     /// peer's AS's node count to the current peer's AS's node count.
     ///
     /// This method updates self.next_neighbor with a new neighbor to step to, or None to restart.
-    pub fn step(&mut self, peerdb_conn: &DBConn) -> () {
+    pub fn step<R: Rng>(&mut self, peerdb_conn: &DBConn, rng: &mut R) -> () {
         test_debug!(
             "{:?}: execute neighbor step from {:?}",
             &self.local_peer,
             &self.cur_neighbor.addr
         );
 
-        let mut rnd = thread_rng();
-
         // step to a node in cur_neighbor's frontier, per MHRWDA
         let next_neighbor_opt = if self.frontier.len() == 0 {
             // just started the walk, so stay here for now -- we don't yet know the neighbor's
@@ -1345,13 +1411,18 @@ impl NeighborWalk {
                 None
             }
         } else {
-            let next_neighbor = NeighborWalk::pick_random_neighbor(&self.frontier, None)
+            let next_neighbor = NeighborWalk::pick_random_neighbor(&self.frontier, None, rng)
                 .expect("BUG: empty frontier size"); // won't panic since self.frontier.len() > 0
-            let walk_prob: f64 = rnd.gen();
+            let walk_prob: f64 = rng.gen();
             if walk_prob
                 < fmin!(
                     1.0,
-                    NeighborWalk::degree_ratio(peerdb_conn, &self.cur_neighbor, &next_neighbor)
+                    NeighborWalk::degree_ratio(
+                        peerdb_conn,
+                        &self.cur_neighbor,
+                        &next_neighbor,
+                        rng
+                    )
                 )
             {
                 match self.prev_neighbor {
@@ -1369,19 +1440,22 @@ impl NeighborWalk {
                                 let alt_next_neighbor = NeighborWalk::pick_random_neighbor(
                                     &self.frontier,
                                     Some(&prev_neighbor),
+                                    rng,
                                 )
                                 .expect("BUG: empty frontier size");
-                                let alt_prob: f64 = rnd.gen();
+                                let alt_prob: f64 = rng.gen();
 
                                 let cur_to_alt = NeighborWalk::degree_ratio(
                                     peerdb_conn,
                                     &self.cur_neighbor,
                                     &alt_next_neighbor,
+                                    rng,
                                 );
                                 let prev_to_cur = NeighborWalk::degree_ratio(
                                     peerdb_conn,
                                     &prev_neighbor,
                                     &self.cur_neighbor,
+                                    rng,
                                 );
                                 let trans_prob = fmin!(
                                     fmin!(1.0, cur_to_alt * cur_to_alt),
@@ -1485,6 +1559,7 @@ impl NeighborWalk {
                                 let mut tx = network.peerdb.tx_begin()?;
                                 self.add_or_schedule_replace_neighbor(
                                     &mut tx,
+                                    &mut network.rng,
                                     block_height,
                                     &naddr,
                                     message.preamble.peer_version,
@@ -1699,7 +1774,7 @@ impl NeighborWalk {
 impl PeerNetwork {
     /// Get some initial fresh random neighbor(s) to crawl
     pub fn walk_get_random_neighbors(
-        &self,
+        &mut self,
         num_neighbors: u64,
         block_height: u64,
     ) -> Result<Vec<Neighbor>, net_error> {
@@ -1708,6 +1783,7 @@ impl PeerNetwork {
             self.local_peer.network_id,
             num_neighbors as u32,
             block_height,
+            &mut self.rng,
         )
         .map_err(net_error::DBError)?;
 
@@ -1865,24 +1941,19 @@ impl PeerNetwork {
         }
 
         // pick a random inbound conversation
-        let mut idx = thread_rng().gen::<usize>() % self.peers.len();
+        let event_ids = self.sorted_peer_event_ids();
+        let mut idx = self.rng.gen::<usize>() % event_ids.len();
 
         test_debug!(
             "{:?}: try inbound neighbors -- sample out of {}. idx = {}",
             &self.local_peer,
-            self.peers.len(),
+            event_ids.len(),
             idx
         );
 
         for _ in 0..self.walk_pingbacks.len() + 1 {
-            let event_id = match self.peers.keys().skip(idx).next() {
-                Some(eid) => *eid,
-                None => {
-                    idx = 0;
-                    continue;
-                }
-            };
-            idx = (idx + 1) % self.peers.len();
+            let event_id = event_ids[idx];
+            idx = (idx + 1) % event_ids.len();
 
             let convo = self
                 .peers
@@ -1933,21 +2004,18 @@ impl PeerNetwork {
             return Err(net_error::NoSuchNeighbor);
         }
 
-        let idx = thread_rng().gen::<usize>() % self.walk_pingbacks.len();
+        let mut addrs: Vec<NeighborAddress> = self.walk_pingbacks.keys().cloned().collect();
+        addrs.sort();
+        let idx = self.rng.gen::<usize>() % addrs.len();
 
         test_debug!(
             "{:?}: try pingback candidates -- sample out of {}. idx = {}",
             &self.local_peer,
-            self.walk_pingbacks.len(),
+            addrs.len(),
             idx
         );
 
-        let addr = match self.walk_pingbacks.keys().skip(idx).next() {
-            Some(ref addr) => (*addr).clone(),
-            None => {
-                return Err(net_error::NoSuchNeighbor);
-            }
-        };
+        let addr = addrs.swap_remove(idx);
 
         let pb = self.walk_pingbacks.get(&addr).unwrap().clone();
         let nk = NeighborKey::from_neighbor_address(pb.peer_version, pb.network_id, &addr);
@@ -2435,7 +2503,7 @@ impl PeerNetwork {
                 Some(_neighbor) => {
                     // finished calculating this neighbor's in/out degree.
                     // walk to the next neighbor.
-                    walk.step(network.peerdb.conn());
+                    walk.step(network.peerdb.conn(), &mut network.rng);
                     Ok(true)
                 }
             }
@@ -2887,8 +2955,7 @@ impl PeerNetwork {
                             || walk_state_timeout
                         {
                             // consider re-setting the walk state, now that we completed a walk.
-                            let mut rng = thread_rng();
-                            let sample: f64 = rng.gen();
+                            let sample: f64 = self.rng.gen();
                             if walk_timed_out || walk_state_timeout || sample < walk.walk_reset_prob
                             {
                                 true
@@ -2965,6 +3032,7 @@ mod test {
     use net::asn::*;
     use net::chat::*;
     use net::db::*;
+    use net::sim::SimNetwork;
     use net::test::*;
     use util::hash::*;
     use util::sleep_ms;
@@ -3908,6 +3976,9 @@ mod test {
     #[test]
     fn test_step_walk_2_neighbors_plain() {
         with_timeout(600, || {
+            // runs in a simulated network, so it's quick and reproducible
+            let sim = SimNetwork::new(0);
+
             let mut peer_1_config = TestPeerConfig::from_port(32500);
             let mut peer_2_config = TestPeerConfig::from_port(32502);
 
@@ -3918,24 +3989,19 @@ mod test {
             peer_1_config.connection_opts.walk_max_duration = 10;
             peer_2_config.connection_opts.walk_max_duration = 10;
 
+            peer_1_config.sim = Some(sim.clone());
+            peer_2_config.sim = Some(sim.clone());
+
             // peer 1 crawls peer 2, and peer 2 crawls peer 1
             peer_1_config.add_neighbor(&peer_2_config.to_neighbor());
             peer_2_config.add_neighbor(&peer_1_config.to_neighbor());
 
-            let mut peer_1 = TestPeer::new(peer_1_config);
-            let mut peer_2 = TestPeer::new(peer_2_config);
-
-            let mut i = 0;
-            let mut walk_1_count = 0;
-            let mut walk_2_count = 0;
+            let mut peers = vec![TestPeer::new(peer_1_config), TestPeer::new(peer_2_config)];
 
             // NOTE: 2x the max walk duration
-            while walk_1_count < 20 || walk_2_count < 20 {
-                let _ = peer_1.step();
-                let _ = peer_2.step();
-
-                walk_1_count = peer_1.network.walk_total_step_count;
-                walk_2_count = peer_2.network.walk_total_step_count;
+            let i = run_sim_peers(&sim, &mut peers, 100, 100_000, |peers| {
+                let walk_1_count = peers[0].network.walk_total_step_count;
+                let walk_2_count = peers[1].network.walk_total_step_count;
 
                 test_debug!(
                     "peer 1 took {} walk steps; peer 2 took {} walk steps",
@@ -3943,24 +4009,22 @@ mod test {
                     walk_2_count
                 );
 
-                match peer_1.network.walk {
-                    Some(ref w) => {
-                        assert_eq!(w.result.broken_connections.len(), 0);
-                        assert_eq!(w.result.replaced_neighbors.len(), 0);
-                    }
-                    None => {}
-                };
+                for peer in peers.iter() {
+                    match peer.network.walk {
+                        Some(ref w) => {
+                            assert_eq!(w.result.broken_connections.len(), 0);
+                            assert_eq!(w.result.replaced_neighbors.len(), 0);
+                        }
+                        None => {}
+                    };
+                }
 
-                match peer_2.network.walk {
-                    Some(ref w) => {
-                        assert_eq!(w.result.broken_connections.len(), 0);
-                        assert_eq!(w.result.replaced_neighbors.len(), 0);
-                    }
-                    None => {}
-                };
+                walk_1_count >= 20 && walk_2_count >= 20
+            })
+            .expect("peers never finished walking");
 
-                i += 1;
-            }
+            let peer_2 = peers.pop().unwrap();
+            let peer_1 = peers.pop().unwrap();
 
             debug!("Completed walk round {} step(s)", i);
 
@@ -26,6 +26,7 @@ use net::PeerAddress;
 
 use net::*;
 
+use net::connection::BandwidthAccount;
 use net::connection::ConnectionOptions;
 use net::connection::NetworkReplyHandle;
 use net::connection::ReplyHandleHttp;
@@ -39,8 +40,11 @@ use net::relay::RelayerStats;
 use net::download::BlockDownloader;
 
 use net::poll::NetworkPollState;
+use net::poll::NetworkSocket;
 use net::poll::NetworkState;
 
+use net::socks::Socks5Connector;
+
 use net::db::LocalPeer;
 
 use net::neighbors::*;
@@ -57,6 +61,8 @@ use util::db::DBConn;
 use util::db::Error as db_error;
 
 use util::hash::to_hex;
+
+use monitoring;
 use util::secp256k1::Secp256k1PublicKey;
 
 use std::sync::mpsc::sync_channel;
@@ -82,6 +88,7 @@ use burnchains::PublicKey;
 use chainstate::burn::db::sortdb::{BlockHeaderCache, PoxId, SortitionDB, SortitionId};
 
 use chainstate::stacks::db::StacksChainState;
+use chainstate::stacks::Error as chainstate_error;
 
 use chainstate::stacks::{StacksBlockHeader, MAX_BLOCK_LEN, MAX_TRANSACTION_LEN};
 
@@ -89,10 +96,6 @@ use util::get_epoch_time_secs;
 use util::log;
 
 use rand::prelude::*;
-use rand::thread_rng;
-
-use mio;
-use mio::net as mio_net;
 
 use net::inv::*;
 use net::relay::*;
@@ -104,6 +107,14 @@ pub enum NetworkRequest {
     Ban(Vec<NeighborKey>),
     AdvertizeBlocks(BlocksAvailableMap), // announce to all wanting neighbors that we have these blocks
     AdvertizeMicroblocks(BlocksAvailableMap), // announce to all wanting neighbors that we have these confirmed microblock streams
+    AdvertizeBlockHeaders(
+        BlocksAvailableMap,
+        HashMap<ConsensusHash, StacksBlockHeader>,
+    ), // push headers of these blocks to wanting neighbors that accept them
+    AdvertizeMicroblockHeaders(
+        BlocksAvailableMap,
+        HashMap<ConsensusHash, MicroblockHeadersData>,
+    ), // push headers of these confirmed microblock streams to wanting neighbors that accept them
     Relay(NeighborKey, StacksMessage),
     Broadcast(Vec<RelayData>, StacksMessageType),
 }
@@ -162,6 +173,26 @@ impl NetworkHandle {
         self.send_request(req)
     }
 
+    /// Advertize blocks, pushing their headers to peers that accept them
+    pub fn advertize_block_headers(
+        &mut self,
+        blocks: BlocksAvailableMap,
+        headers: HashMap<ConsensusHash, StacksBlockHeader>,
+    ) -> Result<(), net_error> {
+        let req = NetworkRequest::AdvertizeBlockHeaders(blocks, headers);
+        self.send_request(req)
+    }
+
+    /// Advertize microblocks, pushing their headers to peers that accept them
+    pub fn advertize_microblock_headers(
+        &mut self,
+        blocks: BlocksAvailableMap,
+        headers: HashMap<ConsensusHash, MicroblockHeadersData>,
+    ) -> Result<(), net_error> {
+        let req = NetworkRequest::AdvertizeMicroblockHeaders(blocks, headers);
+        self.send_request(req)
+    }
+
     /// Relay a message to a peer via the p2p network thread, expecting no reply.
     /// Called from outside the p2p thread by other threads.
     pub fn relay_signed_message(
@@ -204,6 +235,7 @@ pub enum PeerNetworkWorkState {
     BlockInvSync,
     BlockDownload,
     AntiEntropy,
+    MempoolSync,
     Prune,
 }
 
@@ -220,9 +252,11 @@ pub struct PeerNetwork {
 
     // ongoing p2p conversations (either they reached out to us, or we to them)
     pub peers: PeerMap,
-    pub sockets: HashMap<usize, mio_net::TcpStream>,
+    pub sockets: HashMap<usize, NetworkSocket>,
     pub events: HashMap<NeighborKey, usize>,
-    pub connecting: HashMap<usize, (mio_net::TcpStream, bool, u64)>, // (socket, outbound?, connection sent timestamp)
+    pub connecting: HashMap<usize, (NetworkSocket, bool, u64)>, // (socket, outbound?, connection sent timestamp)
+    proxied_peer_addrs: HashMap<usize, SocketAddr>, // addresses of peers we reached through a proxy, since the socket's peer address is the proxy's
+    proxy_connectors: HashMap<usize, Socks5Connector>, // SOCKS5 handshakes still in progress on connecting sockets
     pub bans: HashSet<usize>,
 
     // ongoing messages the network is sending via the p2p interface (not bound to a specific
@@ -258,6 +292,10 @@ pub struct PeerNetwork {
     pub walk_pingbacks: HashMap<NeighborAddress, NeighborPingback>, // inbound peers for us to try to ping back and add to our frontier, mapped to (peer_version, network_id, timeout, pubkey)
     pub walk_result: NeighborWalkResult, // last successful neighbor walk result
 
+    // source of randomness for choosing peers.  Seeded from entropy, unless a test seeds it so
+    // that a simulated run is reproducible.
+    pub rng: StdRng,
+
     // peer block inventory state
     pub inv_state: Option<InvState>,
 
@@ -319,6 +357,17 @@ pub struct PeerNetwork {
     // can't process yet, but might be able to process on the next chain view update
     pub pending_messages: HashMap<usize, Vec<StacksMessage>>,
 
+    // outstanding GetMempool request, and when we can send the next one
+    mempool_sync_request: Option<(NeighborKey, ReplyHandleP2P)>,
+    pub mempool_sync_deadline: u64,
+
+    // bandwidth used by p2p conversations that have since closed
+    retired_bandwidth: BandwidthAccount,
+
+    // has this node used up its upload or download quota for the current period?
+    pub upload_quota_exhausted: bool,
+    pub download_quota_exhausted: bool,
+
     // fault injection -- force disconnects
     fault_last_disconnect: u64,
 }
@@ -341,9 +390,23 @@ impl PeerNetwork {
             0,
         );
         let pub_ip = connection_opts.public_ip_address.clone();
-        let pub_ip_learned = pub_ip.is_none();
+        // don't go learning our public IP address if we're hiding behind a proxy
+        let pub_ip_learned = pub_ip.is_none() && connection_opts.socks_proxy.is_none();
         local_peer.public_ip_address = pub_ip.clone();
 
+        if connection_opts.socks_proxy.is_some() {
+            // we can dial onion services, so tell our peers about them
+            local_peer.services |= ServiceFlags::ONION as u16;
+        }
+
+        // we can always handle headers-first block announcements
+        local_peer.services |= ServiceFlags::HEADERS as u16;
+
+        if connection_opts.pruned {
+            // we only have recent blocks, so peers shouldn't rely on us for old ones
+            local_peer.services |= ServiceFlags::PRUNED as u16;
+        }
+
         if connection_opts.disable_inbound_handshakes {
             debug!("{:?}: disable inbound handshakes", &local_peer);
         }
@@ -351,6 +414,8 @@ impl PeerNetwork {
             debug!("{:?}: disable inbound neighbor walks", &local_peer);
         }
 
+        let retired_bandwidth = BandwidthAccount::new(connection_opts.bandwidth_quota_period);
+
         PeerNetwork {
             local_peer: local_peer,
             peer_version: peer_version,
@@ -363,6 +428,8 @@ impl PeerNetwork {
             sockets: HashMap::new(),
             events: HashMap::new(),
             connecting: HashMap::new(),
+            proxied_peer_addrs: HashMap::new(),
+            proxy_connectors: HashMap::new(),
             bans: HashSet::new(),
 
             relay_handles: HashMap::new(),
@@ -388,6 +455,8 @@ impl PeerNetwork {
             walk_pingbacks: HashMap::new(),
             walk_result: NeighborWalkResult::new(),
 
+            rng: StdRng::from_entropy(),
+
             inv_state: None,
             pox_id: PoxId::initial(),
             tip_sort_id: SortitionId([0x00; 32]),
@@ -427,14 +496,43 @@ impl PeerNetwork {
 
             pending_messages: HashMap::new(),
 
+            mempool_sync_request: None,
+            mempool_sync_deadline: 0,
+
+            retired_bandwidth: retired_bandwidth,
+            upload_quota_exhausted: false,
+            download_quota_exhausted: false,
+
             fault_last_disconnect: 0,
         }
     }
 
+    /// Re-seed the RNG we use to choose peers, so a (simulated) run can be replayed
+    pub fn set_rng_seed(&mut self, seed: u64) -> () {
+        self.rng = StdRng::seed_from_u64(seed);
+    }
+
+    /// Event IDs of all conversations, in a fixed order, so that picking one at random depends
+    /// only on our RNG.
+    pub fn sorted_peer_event_ids(&self) -> Vec<usize> {
+        let mut event_ids: Vec<usize> = self.peers.keys().cloned().collect();
+        event_ids.sort();
+        event_ids
+    }
+
     /// start serving.
     pub fn bind(&mut self, my_addr: &SocketAddr, http_addr: &SocketAddr) -> Result<(), net_error> {
-        let mut net = NetworkState::new(self.connection_opts.max_sockets)?;
+        let net = NetworkState::new(self.connection_opts.max_sockets)?;
+        self.bind_network(net, my_addr, http_addr)
+    }
 
+    /// start serving on the given network state
+    pub fn bind_network(
+        &mut self,
+        mut net: NetworkState,
+        my_addr: &SocketAddr,
+        http_addr: &SocketAddr,
+    ) -> Result<(), net_error> {
         let p2p_handle = net.bind(my_addr)?;
         let http_handle = net.bind(http_addr)?;
 
@@ -510,7 +608,7 @@ impl PeerNetwork {
     /// Return (number of bytes sent, whether or not there's more to send)
     fn do_saturate_p2p_socket(
         convo: &mut ConversationP2P,
-        client_sock: &mut mio::net::TcpStream,
+        client_sock: &mut NetworkSocket,
         handle: &mut ReplyHandleP2P,
     ) -> Result<(usize, bool), net_error> {
         let mut total_sent = 0;
@@ -736,7 +834,7 @@ impl PeerNetwork {
     /// Count how many connections to a given IP address we have
     pub fn count_ip_connections(
         ipaddr: &SocketAddr,
-        sockets: &HashMap<usize, mio_net::TcpStream>,
+        sockets: &HashMap<usize, NetworkSocket>,
     ) -> u64 {
         let mut ret = 0;
         for (_, socket) in sockets.iter() {
@@ -803,19 +901,71 @@ impl PeerNetwork {
             return Ok(event_id);
         }
 
+        // onion services can only be reached through the proxy, by their full address
+        let proxy_dest = match self.connection_opts.socks_proxy {
+            Some(_) => {
+                if neighbor.addrbytes.is_onion() {
+                    match PeerDB::get_onion_address(self.peerdb.conn(), &neighbor.addrbytes)? {
+                        Some(onion) => Some(onion.to_peer_host(neighbor.port)),
+                        None => {
+                            debug!(
+                                "{:?}: no known onion address for {:?}",
+                                &self.local_peer, neighbor
+                            );
+                            return Err(net_error::NoSuchNeighbor);
+                        }
+                    }
+                } else {
+                    Some(PeerHost::IP(neighbor.addrbytes.clone(), neighbor.port))
+                }
+            }
+            None => {
+                if neighbor.addrbytes.is_onion() {
+                    debug!(
+                        "{:?}: cannot connect to onion service {:?} without a SOCKS proxy",
+                        &self.local_peer, neighbor
+                    );
+                    return Err(net_error::ConnectionError);
+                }
+                None
+            }
+        };
+
         let next_event_id = match self.network {
             None => {
                 test_debug!("{:?}: network not connected", &self.local_peer);
                 return Err(net_error::NotConnected);
             }
             Some(ref mut network) => {
-                let sock = NetworkState::connect(&neighbor.addrbytes.to_socketaddr(neighbor.port))?;
+                // the SOCKS5 handshake is driven from the poll loop once the proxy connection is
+                // up, so we never block the p2p thread on a slow proxy
+                let (sock, connector) =
+                    match (self.connection_opts.socks_proxy.as_ref(), proxy_dest) {
+                        (Some(proxy), Some(dest)) => (
+                            network.connect_socket(proxy)?,
+                            Some(Socks5Connector::new(&dest)?),
+                        ),
+                        (..) => (
+                            network
+                                .connect_socket(&neighbor.addrbytes.to_socketaddr(neighbor.port))?,
+                            None,
+                        ),
+                    };
                 let hint_event_id = network.next_event_id()?;
                 let registered_event_id =
                     network.register(self.p2p_network_handle, hint_event_id, &sock)?;
 
                 self.connecting
                     .insert(registered_event_id, (sock, true, get_epoch_time_secs()));
+                if let Some(connector) = connector {
+                    self.proxy_connectors.insert(registered_event_id, connector);
+                }
+                if self.connection_opts.socks_proxy.is_some() {
+                    self.proxied_peer_addrs.insert(
+                        registered_event_id,
+                        neighbor.addrbytes.to_socketaddr(neighbor.port),
+                    );
+                }
                 registered_event_id
             }
         };
@@ -972,6 +1122,26 @@ impl PeerNetwork {
                 }
                 Ok(())
             }
+            NetworkRequest::AdvertizeBlockHeaders(blocks, headers) => {
+                if !(cfg!(test) && self.connection_opts.disable_block_advertisement) {
+                    if self.connection_opts.headers_first_push {
+                        self.advertize_block_headers(blocks, headers)?;
+                    } else {
+                        self.advertize_blocks(blocks)?;
+                    }
+                }
+                Ok(())
+            }
+            NetworkRequest::AdvertizeMicroblockHeaders(mblocks, headers) => {
+                if !(cfg!(test) && self.connection_opts.disable_block_advertisement) {
+                    if self.connection_opts.headers_first_push {
+                        self.advertize_microblock_headers(mblocks, headers)?;
+                    } else {
+                        self.advertize_microblocks(mblocks)?;
+                    }
+                }
+                Ok(())
+            }
             NetworkRequest::Relay(neighbor_key, msg) => self
                 .relay_signed_message(&neighbor_key, msg)
                 .and_then(|_| Ok(())),
@@ -1314,10 +1484,14 @@ impl PeerNetwork {
     fn register_peer(
         &mut self,
         event_id: usize,
-        socket: mio_net::TcpStream,
+        socket: NetworkSocket,
         outbound: bool,
     ) -> Result<(), net_error> {
-        let client_addr = match socket.peer_addr() {
+        let peer_addr_res = match self.proxied_peer_addrs.remove(&event_id) {
+            Some(addr) => Ok(addr),
+            None => socket.peer_addr(),
+        };
+        let client_addr = match peer_addr_res {
             Ok(addr) => addr,
             Err(e) => {
                 debug!(
@@ -1420,8 +1594,25 @@ impl PeerNetwork {
         self.peers.get(&event_id)
     }
 
+    /// Get the burnchain height below which a neighbor may have discarded the bodies of the blocks
+    /// chosen there.  Returns None if the neighbor keeps all of its blocks (or if nothing is old
+    /// enough to have been pruned yet).
+    pub fn get_neighbor_pruned_height(&self, neighbor_key: &NeighborKey) -> Option<u64> {
+        let pruned = match self.get_convo(neighbor_key) {
+            Some(convo) => (convo.peer_services & (ServiceFlags::PRUNED as u16)) != 0,
+            None => false,
+        };
+        if !pruned {
+            return None;
+        }
+        StacksChainState::get_pruned_peer_horizon(
+            &self.burnchain,
+            self.chain_view.burn_block_height,
+        )
+    }
+
     /// Deregister a socket from our p2p network instance.
-    fn deregister_socket(&mut self, event_id: usize, socket: mio_net::TcpStream) -> () {
+    fn deregister_socket(&mut self, event_id: usize, socket: NetworkSocket) -> () {
         match self.network {
             Some(ref mut network) => {
                 let _ = network.deregister(event_id, &socket);
@@ -1472,8 +1663,12 @@ impl PeerNetwork {
         }
 
         self.relay_handles.remove(&event_id);
-        self.peers.remove(&event_id);
+        if let Some(convo) = self.peers.remove(&event_id) {
+            self.retired_bandwidth.merge(&convo.stats.bandwidth);
+        }
         self.pending_messages.remove(&event_id);
+        self.proxied_peer_addrs.remove(&event_id);
+        self.proxy_connectors.remove(&event_id);
     }
 
     /// Deregister by neighbor key
@@ -1596,7 +1791,7 @@ impl PeerNetwork {
         header_cache: &mut BlockHeaderCache,
         chain_view: &BurnchainView,
         event_id: usize,
-        client_sock: &mut mio_net::TcpStream,
+        client_sock: &mut NetworkSocket,
         convo: &mut ConversationP2P,
     ) -> Result<(Vec<StacksMessage>, bool), net_error> {
         // get incoming bytes and update the state of this conversation.
@@ -1669,7 +1864,31 @@ impl PeerNetwork {
 
     /// Process any newly-connecting sockets
     fn process_connecting_sockets(&mut self, poll_state: &mut NetworkPollState) -> () {
+        let mut failed_proxy_events = vec![];
         for event_id in poll_state.ready.iter() {
+            if let Some(mut connector) = self.proxy_connectors.remove(event_id) {
+                // still talking to the SOCKS5 proxy -- advance the handshake as far as the
+                // socket allows, and only register the peer once the proxy has connected us.
+                let res = match self.connecting.get_mut(event_id) {
+                    Some(entry) => connector.try_advance(&mut entry.0),
+                    None => continue,
+                };
+                match res {
+                    Ok(true) => {}
+                    Ok(false) => {
+                        self.proxy_connectors.insert(*event_id, connector);
+                        continue;
+                    }
+                    Err(e) => {
+                        debug!(
+                            "{:?}: SOCKS5 handshake failed on event {}: {:?}",
+                            &self.local_peer, event_id, &e
+                        );
+                        failed_proxy_events.push(*event_id);
+                        continue;
+                    }
+                }
+            }
             if self.connecting.contains_key(event_id) {
                 let (socket, outbound, _) = self.connecting.remove(event_id).unwrap();
                 let sock_str = format!("{:?}", &socket);
@@ -1686,6 +1905,9 @@ impl PeerNetwork {
                 }
             }
         }
+        for event_id in failed_proxy_events.into_iter() {
+            self.deregister_peer(event_id);
+        }
     }
 
     /// Process sockets that are ready, but specifically inbound or outbound only.
@@ -1766,6 +1988,65 @@ impl PeerNetwork {
         (to_remove, unhandled)
     }
 
+    /// Get the bandwidth used by this node -- over p2p and HTTP, past and present conversations
+    pub fn get_bandwidth_account(&self) -> BandwidthAccount {
+        let mut account = self.retired_bandwidth.clone();
+        for (_, convo) in self.peers.iter() {
+            account.merge(&convo.stats.bandwidth);
+        }
+        account.merge(&self.http.get_bandwidth_account());
+        account
+    }
+
+    /// Determine whether or not this node has used up its upload or download quota for the
+    /// current period, and tell our conversations.
+    fn refresh_bandwidth_quotas(&mut self) -> () {
+        let account = self.get_bandwidth_account();
+        monitoring::update_bandwidth_period_gauges(
+            account.get_period_bytes_tx(),
+            account.get_period_bytes_rx(),
+        );
+
+        let upload_quota_exhausted =
+            account.exceeds_upload_quota(self.connection_opts.max_upload_per_period, 0);
+        let download_quota_exhausted =
+            account.exceeds_download_quota(self.connection_opts.max_download_per_period);
+
+        if upload_quota_exhausted != self.upload_quota_exhausted {
+            info!(
+                "{:?}: Upload quota {} ({} of {} bytes sent this period)",
+                &self.local_peer,
+                if upload_quota_exhausted {
+                    "exhausted"
+                } else {
+                    "restored"
+                },
+                account.get_period_bytes_tx(),
+                self.connection_opts.max_upload_per_period
+            );
+        }
+        if download_quota_exhausted != self.download_quota_exhausted {
+            info!(
+                "{:?}: Download quota {} ({} of {} bytes received this period)",
+                &self.local_peer,
+                if download_quota_exhausted {
+                    "exhausted"
+                } else {
+                    "restored"
+                },
+                account.get_period_bytes_rx(),
+                self.connection_opts.max_download_per_period
+            );
+        }
+
+        self.upload_quota_exhausted = upload_quota_exhausted;
+        self.download_quota_exhausted = download_quota_exhausted;
+        self.http.upload_quota_exhausted = upload_quota_exhausted;
+        for (_, convo) in self.peers.iter_mut() {
+            convo.upload_quota_exhausted = upload_quota_exhausted;
+        }
+    }
+
     /// Get stats for a neighbor
     pub fn get_neighbor_stats(&self, nk: &NeighborKey) -> Option<NeighborStats> {
         match self.events.get(&nk) {
@@ -2093,16 +2374,11 @@ impl PeerNetwork {
         debug!("{:?}: begin obtaining public IP address", &self.local_peer);
 
         // pick a random outbound conversation to one of the initial neighbors
-        let mut idx = thread_rng().gen::<usize>() % self.peers.len();
-        for _ in 0..self.peers.len() + 1 {
-            let event_id = match self.peers.keys().skip(idx).next() {
-                Some(eid) => *eid,
-                None => {
-                    idx = 0;
-                    continue;
-                }
-            };
-            idx = (idx + 1) % self.peers.len();
+        let event_ids = self.sorted_peer_event_ids();
+        let mut idx = self.rng.gen::<usize>() % event_ids.len();
+        for _ in 0..event_ids.len() + 1 {
+            let event_id = event_ids[idx];
+            idx = (idx + 1) % event_ids.len();
 
             if let Some(convo) = self.peers.get_mut(&event_id) {
                 if !convo.is_authenticated() || !convo.is_outbound() {
@@ -2120,7 +2396,7 @@ impl PeerNetwork {
 
                 debug!("Ask {:?} for my IP address", &convo);
 
-                let nonce = thread_rng().gen::<u32>();
+                let nonce = self.rng.gen::<u32>();
                 let natpunch_request = convo
                     .sign_message(
                         &self.chain_view,
@@ -2894,12 +3170,241 @@ impl PeerNetwork {
         Ok(())
     }
 
+    /// Pick a random authenticated outbound neighbor to synchronize our mempool with
+    fn find_mempool_sync_neighbor(&mut self) -> Option<NeighborKey> {
+        let mut candidates: Vec<NeighborKey> = self
+            .peers
+            .values()
+            .filter(|convo| convo.is_outbound() && convo.is_authenticated())
+            .map(|convo| convo.to_neighbor_key())
+            .collect();
+
+        if candidates.len() == 0 {
+            return None;
+        }
+
+        // sort first, so the choice depends only on our RNG
+        candidates.sort();
+        let idx = self.rng.gen::<usize>() % candidates.len();
+        Some(candidates[idx].clone())
+    }
+
+    /// Ask a neighbor for the transactions in its mempool that we don't have, and collect them
+    /// into the network result so the relayer can store them.
+    /// Return true if this pass of the mempool sync is done (or was not needed).
+    /// Return false if we're still waiting for a reply.
+    fn do_mempool_sync(
+        &mut self,
+        mempool: &MemPoolDB,
+        network_result: &mut NetworkResult,
+    ) -> Result<bool, net_error> {
+        if self.connection_opts.disable_mempool_sync {
+            return Ok(true);
+        }
+
+        let (nk, mut request) = match self.mempool_sync_request.take() {
+            Some((nk, request)) => (nk, request),
+            None => {
+                if self.mempool_sync_deadline > get_epoch_time_secs() {
+                    // not time yet
+                    return Ok(true);
+                }
+                self.mempool_sync_deadline =
+                    get_epoch_time_secs() + self.connection_opts.mempool_sync_interval;
+
+                let nk = match self.find_mempool_sync_neighbor() {
+                    Some(nk) => nk,
+                    None => {
+                        debug!(
+                            "{:?}: No outbound neighbors to synchronize mempool with",
+                            &self.local_peer
+                        );
+                        return Ok(true);
+                    }
+                };
+
+                let seed = self.rng.gen::<u32>();
+                let sync_data = MemPoolDB::make_mempool_sync_data(mempool.conn(), seed)?;
+
+                debug!(
+                    "{:?}: Send GetMempool to {:?} ({} bloom bytes)",
+                    &self.local_peer,
+                    &nk,
+                    sync_data.bloom.len()
+                );
+
+                let msg = self.sign_for_peer(&nk, StacksMessageType::GetMempool(sync_data))?;
+                let request = self.send_message(&nk, msg, self.connection_opts.timeout)?;
+                (nk, request)
+            }
+        };
+
+        self.saturate_p2p_socket(request.get_event_id(), &mut request)?;
+
+        match request.try_send_recv() {
+            Ok(message) => match message.payload {
+                StacksMessageType::MemPoolTxs(mempool_txs) => {
+                    debug!(
+                        "{:?}: Got {} mempool transaction(s) from {:?}",
+                        &self.local_peer,
+                        mempool_txs.txs.len(),
+                        &nk
+                    );
+                    network_result
+                        .synced_transactions
+                        .extend(mempool_txs.txs.into_iter());
+                    Ok(true)
+                }
+                StacksMessageType::Nack(nack_data) => {
+                    debug!(
+                        "{:?}: Remote neighbor {:?} nack'ed our GetMempool: NACK code {}",
+                        &self.local_peer, &nk, nack_data.error_code
+                    );
+                    Ok(true)
+                }
+                _ => {
+                    debug!(
+                        "{:?}: Remote neighbor {:?} sent an unexpected reply of '{}' to GetMempool",
+                        &self.local_peer,
+                        &nk,
+                        message.get_message_name()
+                    );
+                    Ok(true)
+                }
+            },
+            Err(req_res) => match req_res {
+                Ok(same_req) => {
+                    // still waiting
+                    self.mempool_sync_request = Some((nk, same_req));
+                    Ok(false)
+                }
+                Err(e) => {
+                    debug!(
+                        "{:?}: Failed to get mempool transactions from {:?}: {:?}",
+                        &self.local_peer, &nk, &e
+                    );
+                    Ok(true)
+                }
+            },
+        }
+    }
+
+    /// Reply to a GetMempool request with the transactions in our mempool that the remote peer
+    /// doesn't have.
+    fn handle_getmempool(
+        &mut self,
+        mempool: &MemPoolDB,
+        event_id: usize,
+        preamble: &Preamble,
+        sync_data: &MemPoolSyncData,
+    ) -> Result<(), net_error> {
+        let throttled = match self.peers.get(&event_id) {
+            Some(convo) => convo.is_upload_throttled(),
+            None => {
+                return Err(net_error::PeerNotConnected);
+            }
+        };
+        if throttled {
+            debug!(
+                "{:?}: Throttle GetMempool from event {}: bandwidth quota exceeded",
+                &self.local_peer, event_id
+            );
+            let mut reply_handle = match self.peers.get_mut(&event_id) {
+                Some(convo) => {
+                    let reply = convo.sign_reply(
+                        &self.chain_view,
+                        &self.local_peer.private_key,
+                        StacksMessageType::Nack(NackData::new(NackErrorCodes::Throttled)),
+                        preamble.seq,
+                    )?;
+                    convo.relay_signed_message(reply)?
+                }
+                None => {
+                    return Err(net_error::PeerNotConnected);
+                }
+            };
+            let (num_sent, flushed) = self.saturate_p2p_socket(event_id, &mut reply_handle)?;
+            if num_sent > 0 || !flushed {
+                self.add_relay_handle(event_id, reply_handle);
+            }
+            return Ok(());
+        }
+
+        let txs = MemPoolDB::find_missing_transactions(
+            mempool.conn(),
+            sync_data,
+            self.connection_opts.mempool_sync_max_txs,
+            (MAX_PAYLOAD_LEN as u64) / 2,
+        )?;
+
+        debug!(
+            "{:?}: Reply {} mempool transaction(s) to GetMempool from event {}",
+            &self.local_peer,
+            txs.len(),
+            event_id
+        );
+
+        let mut reply_handle = match self.peers.get_mut(&event_id) {
+            Some(convo) => {
+                let reply = convo.sign_reply(
+                    &self.chain_view,
+                    &self.local_peer.private_key,
+                    StacksMessageType::MemPoolTxs(MemPoolTxsData { txs }),
+                    preamble.seq,
+                )?;
+                convo.relay_signed_message(reply)?
+            }
+            None => {
+                return Err(net_error::PeerNotConnected);
+            }
+        };
+
+        let (num_sent, flushed) = self.saturate_p2p_socket(event_id, &mut reply_handle)?;
+        if num_sent > 0 || !flushed {
+            // keep trying to send
+            self.add_relay_handle(event_id, reply_handle);
+        }
+        Ok(())
+    }
+
+    /// Handle all GetMempool requests forwarded to us from our conversations.
+    /// Return the remaining unsolicited messages.
+    fn handle_getmempool_requests(
+        &mut self,
+        mempool: &MemPoolDB,
+        unsolicited: HashMap<usize, Vec<StacksMessage>>,
+    ) -> HashMap<usize, Vec<StacksMessage>> {
+        let mut remaining = HashMap::new();
+        for (event_id, messages) in unsolicited.into_iter() {
+            let mut others = vec![];
+            for message in messages.into_iter() {
+                if let StacksMessageType::GetMempool(ref sync_data) = message.payload {
+                    if let Err(e) =
+                        self.handle_getmempool(mempool, event_id, &message.preamble, sync_data)
+                    {
+                        debug!(
+                            "{:?}: Failed to handle GetMempool from event {}: {:?}",
+                            &self.local_peer, event_id, &e
+                        );
+                    }
+                } else {
+                    others.push(message);
+                }
+            }
+            if others.len() > 0 {
+                remaining.insert(event_id, others);
+            }
+        }
+        remaining
+    }
+
     /// Do the actual work in the state machine.
     /// Return true if we need to prune connections.
     fn do_network_work(
         &mut self,
         sortdb: &SortitionDB,
         chainstate: &mut StacksChainState,
+        mempool: &MemPoolDB,
         dns_client_opt: &mut Option<&mut DNSClient>,
         download_backpressure: bool,
         network_result: &mut NetworkResult,
@@ -2937,7 +3442,7 @@ impl PeerNetwork {
                     // synchronize peer block inventories
                     let (inv_done, inv_throttled) = self.do_network_inv_sync(sortdb)?;
                     if inv_done {
-                        if !download_backpressure {
+                        if !download_backpressure && !self.download_quota_exhausted {
                             // proceed to get blocks, if we're not backpressured
                             self.work_state = PeerNetworkWorkState::BlockDownload;
                         } else {
@@ -2989,17 +3494,48 @@ impl PeerNetwork {
                     }
                 }
                 PeerNetworkWorkState::AntiEntropy => {
-                    match self.try_push_local_data(sortdb, chainstate) {
-                        Ok(_) => {}
-                        Err(e) => {
-                            debug!(
-                                "{:?}: Failed to push local data: {:?}",
-                                &self.local_peer, &e
-                            );
-                        }
-                    };
+                    if self.upload_quota_exhausted {
+                        debug!(
+                            "{:?}: Upload quota exhausted; not pushing local data",
+                            &self.local_peer
+                        );
+                    } else {
+                        match self.try_push_local_data(sortdb, chainstate) {
+                            Ok(_) => {}
+                            Err(e) => {
+                                debug!(
+                                    "{:?}: Failed to push local data: {:?}",
+                                    &self.local_peer, &e
+                                );
+                            }
+                        };
+                    }
 
-                    self.work_state = PeerNetworkWorkState::Prune;
+                    self.work_state = PeerNetworkWorkState::MempoolSync;
+                }
+                PeerNetworkWorkState::MempoolSync => {
+                    if self.download_quota_exhausted {
+                        debug!(
+                            "{:?}: Download quota exhausted; not synchronizing mempool",
+                            &self.local_peer
+                        );
+                        self.work_state = PeerNetworkWorkState::Prune;
+                    } else {
+                        // fetch transactions we're missing from a neighbor's mempool
+                        match self.do_mempool_sync(mempool, network_result) {
+                            Ok(true) => {
+                                self.work_state = PeerNetworkWorkState::Prune;
+                            }
+                            Ok(false) => {}
+                            Err(e) => {
+                                debug!(
+                                    "{:?}: Failed to synchronize mempool: {:?}",
+                                    &self.local_peer, &e
+                                );
+                                self.work_state = PeerNetworkWorkState::Prune;
+                            }
+                        }
+                    }
                 }
                 PeerNetworkWorkState::Prune => {
                     // did one pass
@@ -3182,6 +3718,8 @@ impl PeerNetwork {
     }
 
     /// Update a peer's inventory state to indicate that the given block is available.
+    /// If the block's header was pushed to us, then it will be validated against its sortition
+    /// first.
     /// If updated, return the sortition height of the bit in the inv that was set.
     fn handle_unsolicited_inv_update(
         &mut self,
@@ -3189,6 +3727,7 @@ impl PeerNetwork {
         event_id: usize,
         outbound_neighbor_key: &NeighborKey,
         consensus_hash: &ConsensusHash,
+        header: Option<&StacksBlockHeader>,
         microblocks: bool,
     ) -> Result<Option<u64>, net_error> {
         let block_sortition_height = match self.inv_state {
@@ -3200,6 +3739,14 @@ impl PeerNetwork {
                         sortdb,
                         consensus_hash,
                     )
+                } else if let Some(header) = header {
+                    inv.set_block_header_available(
+                        &self.burnchain,
+                        outbound_neighbor_key,
+                        sortdb,
+                        consensus_hash,
+                        header,
+                    )
                 } else {
                     inv.set_block_available(
                         &self.burnchain,
@@ -3268,8 +3815,8 @@ impl PeerNetwork {
     fn buffer_data_message(&mut self, event_id: usize, msg: StacksMessage) -> () {
         if let Some(msgs) = self.pending_messages.get_mut(&event_id) {
             // check limits:
-            // at most 1 BlocksAvailable
-            // at most 1 MicroblocksAvailable
+            // at most 1 BlocksAvailable (or BlockHeaders)
+            // at most 1 MicroblocksAvailable (or MicroblockHeaders)
             // at most 1 BlocksData
             // at most $self.connection_opts.max_buffered_microblocks MicroblocksDatas
             let mut blocks_available = 0;
@@ -3278,10 +3825,11 @@ impl PeerNetwork {
             let mut microblocks_data = 0;
             for msg in msgs.iter() {
                 match &msg.payload {
-                    StacksMessageType::BlocksAvailable(_) => {
+                    StacksMessageType::BlocksAvailable(_) | StacksMessageType::BlockHeaders(_) => {
                         blocks_available += 1;
                     }
-                    StacksMessageType::MicroblocksAvailable(_) => {
+                    StacksMessageType::MicroblocksAvailable(_)
+                    | StacksMessageType::MicroblockHeaders(_) => {
                         microblocks_available += 1;
                     }
                     StacksMessageType::Blocks(_) => {
@@ -3294,24 +3842,35 @@ impl PeerNetwork {
                 }
             }
 
-            if let StacksMessageType::BlocksAvailable(_) = &msg.payload {
-                if blocks_available >= self.connection_opts.max_buffered_blocks_available {
-                    debug!(
-                        "{:?}: Drop BlocksAvailable from event {} -- already have {} buffered",
-                        &self.local_peer, event_id, blocks_available
-                    );
-                    return;
+            match &msg.payload {
+                StacksMessageType::BlocksAvailable(_) | StacksMessageType::BlockHeaders(_) => {
+                    if blocks_available >= self.connection_opts.max_buffered_blocks_available {
+                        debug!(
+                            "{:?}: Drop {} from event {} -- already have {} buffered",
+                            &self.local_peer,
+                            msg.payload.get_message_name(),
+                            event_id,
+                            blocks_available
+                        );
+                        return;
+                    }
                 }
-            }
-            if let StacksMessageType::MicroblocksAvailable(_) = &msg.payload {
-                if microblocks_available >= self.connection_opts.max_buffered_microblocks_available
-                {
-                    debug!(
-                        "{:?}: Drop MicroblocksAvailable from event {} -- already have {} buffered",
-                        &self.local_peer, event_id, microblocks_available
-                    );
-                    return;
+                StacksMessageType::MicroblocksAvailable(_)
+                | StacksMessageType::MicroblockHeaders(_) => {
+                    if microblocks_available
+                        >= self.connection_opts.max_buffered_microblocks_available
+                    {
+                        debug!(
+                            "{:?}: Drop {} from event {} -- already have {} buffered",
+                            &self.local_peer,
+                            msg.payload.get_message_name(),
+                            event_id,
+                            microblocks_available
+                        );
+                        return;
+                    }
                 }
+                _ => {}
             }
             if let StacksMessageType::Blocks(_) = &msg.payload {
                 if blocks_data >= self.connection_opts.max_buffered_blocks {
@@ -3379,6 +3938,7 @@ impl PeerNetwork {
                 event_id,
                 &outbound_neighbor_key,
                 consensus_hash,
+                None,
                 false,
             ) {
                 Ok(Some(bsh)) => bsh,
@@ -3446,6 +4006,7 @@ impl PeerNetwork {
                 event_id,
                 &outbound_neighbor_key,
                 consensus_hash,
+                None,
                 true,
             ) {
                 Ok(Some(bsh)) => bsh,
@@ -3479,6 +4040,204 @@ impl PeerNetwork {
         to_buffer
     }
 
+    /// Handle unsolicited BlockHeaders.
+    /// Validate each header against the sortition that selected it, and if it checks out, update
+    /// our inv for this peer and have the downloader fetch the block body.  Peers that send
+    /// headers that don't match their sortitions get banned.
+    /// Mask errors.
+    /// Return whether or not we need to buffer this message
+    fn handle_unsolicited_BlockHeaders(
+        &mut self,
+        sortdb: &SortitionDB,
+        event_id: usize,
+        new_headers: &BlockHeadersData,
+        buffer: bool,
+    ) -> bool {
+        let outbound_neighbor_key = match self.find_outbound_neighbor(event_id) {
+            Some(onk) => onk,
+            None => {
+                return false;
+            }
+        };
+
+        debug!(
+            "{:?}: Process BlockHeaders from {:?} with {} entries",
+            &self.local_peer,
+            outbound_neighbor_key,
+            new_headers.headers.len()
+        );
+
+        let mut to_buffer = false;
+        for (consensus_hash, header) in new_headers.headers.iter() {
+            let block_sortition_height = match self.handle_unsolicited_inv_update(
+                sortdb,
+                event_id,
+                &outbound_neighbor_key,
+                consensus_hash,
+                Some(header),
+                false,
+            ) {
+                Ok(Some(bsh)) => bsh,
+                Ok(None) => {
+                    continue;
+                }
+                Err(net_error::NotFoundError) => {
+                    if buffer {
+                        debug!("{:?}: Will buffer BlockHeaders for {} until the next burnchain view update", &self.local_peer, &consensus_hash);
+                        to_buffer = true;
+                    }
+                    continue;
+                }
+                Err(e) => {
+                    info!(
+                        "{:?}: Failed to handle BlockHeaders({}/{}) from {}: {:?}",
+                        &self.local_peer,
+                        &consensus_hash,
+                        &header.block_hash(),
+                        &outbound_neighbor_key,
+                        &e
+                    );
+                    continue;
+                }
+            };
+
+            // have the downloader request this block if it's new
+            match self.block_downloader {
+                Some(ref mut downloader) => {
+                    downloader.hint_block_sortition_height_available(block_sortition_height);
+                }
+                None => {}
+            }
+        }
+
+        to_buffer
+    }
+
+    /// Handle unsolicited MicroblockHeaders.
+    /// Validate the headers against the anchored block that produced them, and if they check
+    /// out, update our inv for this peer and have the downloader fetch the confirmed microblock
+    /// stream.  Peers that send headers that don't belong to the anchored block's stream get
+    /// banned.
+    /// Mask errors.
+    /// Return whether or not we need to buffer this message
+    fn handle_unsolicited_MicroblockHeaders(
+        &mut self,
+        sortdb: &SortitionDB,
+        chainstate: &StacksChainState,
+        event_id: usize,
+        new_headers: &MicroblockHeadersData,
+        buffer: bool,
+    ) -> bool {
+        let outbound_neighbor_key = match self.find_outbound_neighbor(event_id) {
+            Some(onk) => onk,
+            None => {
+                return false;
+            }
+        };
+
+        debug!(
+            "{:?}: Process MicroblockHeaders from {:?} for {} with {} entries",
+            &self.local_peer,
+            outbound_neighbor_key,
+            &new_headers.index_anchor_block,
+            new_headers.headers.len()
+        );
+
+        // do we have the associated anchored block?
+        let (consensus_hash, block_hash) =
+            match chainstate.get_block_header_hashes(&new_headers.index_anchor_block) {
+                Ok(Some(hashes)) => hashes,
+                Ok(None) => {
+                    if buffer {
+                        debug!(
+                            "{:?}: Will buffer MicroblockHeaders({})",
+                            &self.local_peer, &new_headers.index_anchor_block
+                        );
+                    }
+                    return buffer;
+                }
+                Err(e) => {
+                    warn!(
+                        "{:?}: Failed to get header hashes for {:?}: {:?}",
+                        &self.local_peer, &new_headers.index_anchor_block, &e
+                    );
+                    return false;
+                }
+            };
+
+        match chainstate.validate_microblock_headers(
+            &consensus_hash,
+            &block_hash,
+            &new_headers.headers,
+        ) {
+            Ok(true) => {}
+            Ok(false) => {
+                info!(
+                    "Peer {:?} sent invalid microblock headers for {}",
+                    &outbound_neighbor_key, &new_headers.index_anchor_block
+                );
+                self.bans.insert(event_id);
+
+                if let Some(outbound_event_id) = self.events.get(&outbound_neighbor_key) {
+                    self.bans.insert(*outbound_event_id);
+                }
+                return false;
+            }
+            Err(chainstate_error::NoSuchBlockError) => {
+                if buffer {
+                    debug!(
+                        "{:?}: Will buffer MicroblockHeaders({})",
+                        &self.local_peer, &new_headers.index_anchor_block
+                    );
+                }
+                return buffer;
+            }
+            Err(e) => {
+                warn!(
+                    "{:?}: Failed to validate microblock headers for {:?}: {:?}",
+                    &self.local_peer, &new_headers.index_anchor_block, &e
+                );
+                return false;
+            }
+        }
+
+        let mblock_sortition_height = match self.handle_unsolicited_inv_update(
+            sortdb,
+            event_id,
+            &outbound_neighbor_key,
+            &consensus_hash,
+            None,
+            true,
+        ) {
+            Ok(Some(bsh)) => bsh,
+            Ok(None) => {
+                return false;
+            }
+            Err(net_error::NotFoundError) => {
+                if buffer {
+                    debug!("{:?}: Will buffer MicroblockHeaders for {} until the next burnchain view update", &self.local_peer, &consensus_hash);
+                }
+                return buffer;
+            }
+            Err(e) => {
+                info!(
+                    "{:?}: Failed to handle MicroblockHeaders({}/{}) from {}: {:?}",
+                    &self.local_peer, &consensus_hash, &block_hash, &outbound_neighbor_key, &e
+                );
+                return false;
+            }
+        };
+
+        // have the downloader request this stream if it's new
+        match self.block_downloader {
+            Some(ref mut downloader) => {
+                downloader.hint_microblock_sortition_height_available(mblock_sortition_height);
+            }
+            None => {}
+        }
+        false
+    }
+
     /// Handle unsolicited BlocksData.
     /// Don't (yet) validate the data, but do update our inv for the peer that sent it, if we have
     /// an outbound connection to that peer.  Accept the blocks data either way if it corresponds
@@ -3583,6 +4342,7 @@ impl PeerNetwork {
                     event_id,
                     &outbound_neighbor_key,
                     &sn.consensus_hash,
+                    None,
                     false,
                 );
             }
@@ -3698,6 +4458,21 @@ impl PeerNetwork {
                 );
                 (to_buffer, false)
             }
+            StacksMessageType::BlockHeaders(ref new_headers) => {
+                let to_buffer =
+                    self.handle_unsolicited_BlockHeaders(sortdb, event_id, new_headers, buffer);
+                (to_buffer, false)
+            }
+            StacksMessageType::MicroblockHeaders(ref new_headers) => {
+                let to_buffer = self.handle_unsolicited_MicroblockHeaders(
+                    sortdb,
+                    chainstate,
+                    event_id,
+                    new_headers,
+                    buffer,
+                );
+                (to_buffer, false)
+            }
             StacksMessageType::Blocks(ref new_blocks) => {
                 // update inv state for this peer
                 let to_buffer =
@@ -3717,6 +4492,14 @@ impl PeerNetwork {
                 // only forward to the relayer if we don't need to buffer it.
                 (to_buffer, true)
             }
+            StacksMessageType::MemPoolTxs(_) => {
+                // only ever sent in reply to our GetMempool requests
+                debug!(
+                    "{:?}: Drop unsolicited MemPoolTxs from event {}",
+                    &self.local_peer, event_id
+                );
+                (false, false)
+            }
             _ => (false, true),
         }
     }
@@ -3860,13 +4643,11 @@ impl PeerNetwork {
 
                     if self.walk_pingbacks.len() > MAX_NEIGHBORS_DATA_LEN as usize {
                         // drop one at random
-                        let idx = thread_rng().gen::<usize>() % self.walk_pingbacks.len();
-                        let drop_addr = match self.walk_pingbacks.keys().skip(idx).next() {
-                            Some(ref addr) => (*addr).clone(),
-                            None => {
-                                continue;
-                            }
-                        };
+                        let mut addrs: Vec<NeighborAddress> =
+                            self.walk_pingbacks.keys().cloned().collect();
+                        addrs.sort();
+                        let idx = self.rng.gen::<usize>() % addrs.len();
+                        let drop_addr = addrs.swap_remove(idx);
 
                         debug!("{:?}: drop pingback {:?}", &self.local_peer, drop_addr);
                         self.walk_pingbacks.remove(&drop_addr);
@@ -3916,10 +4697,12 @@ impl PeerNetwork {
         }
     }
 
-    /// Get the local peer from the peer DB, but also preserve the public IP address
+    /// Get the local peer from the peer DB, but also preserve the public IP address and the
+    /// services we advertise (which depend on our configuration, not the DB)
     pub fn load_local_peer(&self) -> Result<LocalPeer, net_error> {
         let mut lp = PeerDB::get_local_peer(&self.peerdb.conn())?;
         lp.public_ip_address = self.local_peer.public_ip_address.clone();
+        lp.services = self.local_peer.services;
         Ok(lp)
     }
 
@@ -3973,6 +4756,7 @@ impl PeerNetwork {
         network_result: &mut NetworkResult,
         sortdb: &SortitionDB,
         chainstate: &mut StacksChainState,
+        mempool: &MemPoolDB,
         mut dns_client_opt: Option<&mut DNSClient>,
         download_backpressure: bool,
         mut poll_state: NetworkPollState,
@@ -4011,6 +4795,7 @@ impl PeerNetwork {
             );
             self.deregister_peer(error_event);
         }
+        let unsolicited_messages = self.handle_getmempool_requests(mempool, unsolicited_messages);
         let unhandled_messages =
             self.handle_unsolicited_messages(sortdb, chainstate, unsolicited_messages, true)?;
         network_result.consume_unsolicited(unhandled_messages);
@@ -4024,6 +4809,7 @@ impl PeerNetwork {
         let do_prune = self.do_network_work(
             sortdb,
             chainstate,
+            mempool,
             &mut dns_client_opt,
             download_backpressure,
             network_result,
@@ -4113,6 +4899,7 @@ impl PeerNetwork {
         consensus_hash: &ConsensusHash,
         block_hash: &BlockHeaderHash,
         tx: StacksTransaction,
+        event_observer: Option<&dyn MemPoolEventDispatcher>,
     ) -> bool {
         let txid = tx.txid();
         if mempool.has_tx(&txid) {
@@ -4120,7 +4907,8 @@ impl PeerNetwork {
             return false;
         }
 
-        if let Err(e) = mempool.submit(chainstate, consensus_hash, block_hash, &tx) {
+        if let Err(e) = mempool.submit(chainstate, consensus_hash, block_hash, &tx, event_observer)
+        {
             info!("Reject transaction {}: {:?}", txid, &e;
                   "txid" => %txid
             );
@@ -4138,6 +4926,7 @@ impl PeerNetwork {
         chainstate: &mut StacksChainState,
         sortdb: &SortitionDB,
         network_result: &mut NetworkResult,
+        event_observer: Option<&dyn MemPoolEventDispatcher>,
     ) -> Result<(), net_error> {
         let (canonical_consensus_hash, canonical_block_hash) =
             SortitionDB::get_canonical_stacks_chain_tip_hash(sortdb.conn())?;
@@ -4154,6 +4943,7 @@ impl PeerNetwork {
                     &canonical_consensus_hash,
                     &canonical_block_hash,
                     tx.clone(),
+                    event_observer,
                 ) {
                     if let Some(ref mut new_tx_data) = ret.get_mut(&nk) {
                         new_tx_data.push((relayers, tx));
@@ -4166,6 +4956,21 @@ impl PeerNetwork {
 
         // (HTTP-uploaded transactions are already in the mempool)
 
+        // transactions fetched from our neighbors' mempools
+        let synced_txs = mem::replace(&mut network_result.synced_transactions, vec![]);
+        for tx in synced_txs.into_iter() {
+            if PeerNetwork::store_transaction(
+                mempool,
+                chainstate,
+                &canonical_consensus_hash,
+                &canonical_block_hash,
+                tx.clone(),
+                event_observer,
+            ) {
+                network_result.synced_transactions.push(tx);
+            }
+        }
+
         network_result.pushed_transactions.extend(ret);
         Ok(())
     }
@@ -4225,6 +5030,8 @@ impl PeerNetwork {
             }
         }
 
+        self.refresh_bandwidth_quotas();
+
         PeerNetwork::with_network_state(self, |ref mut network, ref mut network_state| {
             let http_stacks_msgs = network.http.run(
                 network_state,
@@ -4246,6 +5053,7 @@ impl PeerNetwork {
             &mut network_result,
             sortdb,
             chainstate,
+            mempool,
             dns_client_opt,
             download_backpressure,
             p2p_poll_state,
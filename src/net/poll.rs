@@ -19,6 +19,9 @@ use net::Neighbor;
 use net::NeighborKey;
 use net::PeerAddress;
 
+#[cfg(test)]
+use net::sim::{SimEndpoint, SimNetwork, SimSocket};
+
 use util::db::DBConn;
 use util::db::Error as db_error;
 
@@ -52,8 +55,63 @@ pub const NUM_NEIGHBORS: u32 = 32;
 
 const SERVER: Token = mio::Token(0);
 
+/// A connected (or connecting) socket, as handed out by the poller.  This is either a real TCP
+/// socket, or (in tests) a socket in a simulated network.
+#[derive(Debug)]
+pub enum NetworkSocket {
+    Tcp(mio_net::TcpStream),
+    #[cfg(test)]
+    Sim(SimSocket),
+}
+
+impl NetworkSocket {
+    pub fn peer_addr(&self) -> io::Result<SocketAddr> {
+        match *self {
+            NetworkSocket::Tcp(ref sock) => sock.peer_addr(),
+            #[cfg(test)]
+            NetworkSocket::Sim(ref sock) => sock.peer_addr(),
+        }
+    }
+
+    pub fn shutdown(&self, how: Shutdown) -> io::Result<()> {
+        match *self {
+            NetworkSocket::Tcp(ref sock) => sock.shutdown(how),
+            #[cfg(test)]
+            NetworkSocket::Sim(ref sock) => sock.shutdown(how),
+        }
+    }
+}
+
+impl Read for NetworkSocket {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        match *self {
+            NetworkSocket::Tcp(ref mut sock) => sock.read(buf),
+            #[cfg(test)]
+            NetworkSocket::Sim(ref mut sock) => sock.read(buf),
+        }
+    }
+}
+
+impl Write for NetworkSocket {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match *self {
+            NetworkSocket::Tcp(ref mut sock) => sock.write(buf),
+            #[cfg(test)]
+            NetworkSocket::Sim(ref mut sock) => sock.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match *self {
+            NetworkSocket::Tcp(ref mut sock) => sock.flush(),
+            #[cfg(test)]
+            NetworkSocket::Sim(ref mut sock) => sock.flush(),
+        }
+    }
+}
+
 pub struct NetworkPollState {
-    pub new: HashMap<usize, mio_net::TcpStream>,
+    pub new: HashMap<usize, NetworkSocket>,
     pub ready: Vec<usize>,
 }
 
@@ -66,11 +124,19 @@ impl NetworkPollState {
     }
 }
 
+// a server's listening socket
+#[derive(Debug)]
+enum NetworkListener {
+    Tcp(mio_net::TcpListener),
+    #[cfg(test)]
+    Sim,
+}
+
 // state for a single network server
 #[derive(Debug)]
 pub struct NetworkServerState {
     addr: SocketAddr,
-    server_socket: mio_net::TcpListener,
+    server_socket: NetworkListener,
     server_event: mio::Token,
 }
 
@@ -83,6 +149,8 @@ pub struct NetworkState {
     servers: Vec<NetworkServerState>,
     count: usize,
     event_map: HashMap<usize, usize>, // map socket events to their registered server socket (including server sockets)
+    #[cfg(test)]
+    sim: Option<SimEndpoint>, // if set, this is a node in a simulated network
 }
 
 impl NetworkState {
@@ -101,9 +169,27 @@ impl NetworkState {
             servers: vec![],
             count: 1,
             event_map: HashMap::new(),
+            #[cfg(test)]
+            sim: None,
         })
     }
 
+    /// Instantiate network state for a node in a simulated network.  Sockets bound, connected,
+    /// and polled by this network state live entirely in memory, and are subject to the
+    /// simulated network's virtual clock, latency, packet loss, and partitions.
+    #[cfg(test)]
+    pub fn new_sim(event_capacity: usize, sim: &SimNetwork) -> Result<NetworkState, net_error> {
+        let mut ns = NetworkState::new(event_capacity)?;
+        ns.sim = Some(sim.new_endpoint());
+        Ok(ns)
+    }
+
+    /// If this is a node in a simulated network, get the seed for its own randomness
+    #[cfg(test)]
+    pub fn sim_rng_seed(&self) -> Option<u64> {
+        self.sim.as_ref().map(|sim| sim.rng_seed())
+    }
+
     pub fn num_events(&self) -> usize {
         self.event_map.len()
     }
@@ -144,23 +230,40 @@ impl NetworkState {
         }
     }
 
+    fn bind_listener(&mut self, addr: &SocketAddr) -> Result<NetworkListener, net_error> {
+        #[cfg(test)]
+        {
+            if let Some(ref mut sim) = self.sim {
+                sim.bind(addr)?;
+                return Ok(NetworkListener::Sim);
+            }
+        }
+        NetworkState::bind_address(addr).map(NetworkListener::Tcp)
+    }
+
     /// Bind to the given socket address.
     /// Returns the handle to the poll state, used to key network poll events.
     pub fn bind(&mut self, addr: &SocketAddr) -> Result<usize, net_error> {
-        let server = NetworkState::bind_address(addr)?;
+        let server = self.bind_listener(addr)?;
         let next_server_event = self.next_event_id()?;
 
-        self.poll
-            .register(
-                &server,
-                mio::Token(next_server_event),
-                Ready::all(),
-                PollOpt::edge(),
-            )
-            .map_err(|e| {
-                error!("Failed to register server socket: {:?}", &e);
-                net_error::BindError
-            })?;
+        match server {
+            NetworkListener::Tcp(ref listener) => {
+                self.poll
+                    .register(
+                        listener,
+                        mio::Token(next_server_event),
+                        Ready::all(),
+                        PollOpt::edge(),
+                    )
+                    .map_err(|e| {
+                        error!("Failed to register server socket: {:?}", &e);
+                        net_error::BindError
+                    })?;
+            }
+            #[cfg(test)]
+            NetworkListener::Sim => {}
+        }
 
         let network_server = NetworkServerState {
             addr: addr.clone(),
@@ -187,7 +290,7 @@ impl NetworkState {
         &mut self,
         server_event_id: usize,
         hint_event_id: usize,
-        sock: &mio_net::TcpStream,
+        sock: &NetworkSocket,
     ) -> Result<usize, net_error> {
         let hint_event_id = hint_event_id % (self.event_capacity + self.servers.len());
         if let Some(x) = self.event_map.get(&server_event_id) {
@@ -221,15 +324,32 @@ impl NetworkState {
             )
         );
 
-        self.poll
-            .register(sock, mio::Token(event_id), Ready::all(), PollOpt::edge())
-            .map_err(|e| {
-                error!(
-                    "Failed to register socket on server {} event ID {} ({}): {:?}",
-                    server_event_id, event_id, hint_event_id, &e
-                );
-                net_error::RegisterError
-            })?;
+        match *sock {
+            NetworkSocket::Tcp(ref tcp_sock) => {
+                self.poll
+                    .register(
+                        tcp_sock,
+                        mio::Token(event_id),
+                        Ready::all(),
+                        PollOpt::edge(),
+                    )
+                    .map_err(|e| {
+                        error!(
+                            "Failed to register socket on server {} event ID {} ({}): {:?}",
+                            server_event_id, event_id, hint_event_id, &e
+                        );
+                        net_error::RegisterError
+                    })?;
+            }
+            #[cfg(test)]
+            NetworkSocket::Sim(ref sim_sock) => match self.sim {
+                Some(ref mut sim) => sim.register(sim_sock, event_id),
+                None => {
+                    error!("Cannot register simulated socket {:?}", sim_sock);
+                    return Err(net_error::RegisterError);
+                }
+            },
+        }
 
         self.event_map.insert(event_id, server_event_id);
         test_debug!(
@@ -245,20 +365,26 @@ impl NetworkState {
     }
 
     /// Deregister a socket event
-    pub fn deregister(
-        &mut self,
-        event_id: usize,
-        sock: &mio_net::TcpStream,
-    ) -> Result<(), net_error> {
+    pub fn deregister(&mut self, event_id: usize, sock: &NetworkSocket) -> Result<(), net_error> {
         assert!(
             self.event_map.contains_key(&event_id),
             "BUG: no such socket {}",
             event_id
         );
-        self.poll.deregister(sock).map_err(|e| {
-            error!("Failed to deregister socket {}: {:?}", event_id, &e);
-            net_error::RegisterError
-        })?;
+        match *sock {
+            NetworkSocket::Tcp(ref tcp_sock) => {
+                self.poll.deregister(tcp_sock).map_err(|e| {
+                    error!("Failed to deregister socket {}: {:?}", event_id, &e);
+                    net_error::RegisterError
+                })?;
+            }
+            #[cfg(test)]
+            NetworkSocket::Sim(ref sim_sock) => {
+                if let Some(ref mut sim) = self.sim {
+                    sim.deregister(sim_sock);
+                }
+            }
+        }
 
         self.event_map.remove(&event_id);
 
@@ -316,12 +442,32 @@ impl NetworkState {
     /// Connect to a remote peer, but don't register it with the poll handle.
     /// The underlying connect(2) is _asynchronous_, so the caller will need to register it with a
     /// poll handle and wait for it to be connected.
-    pub fn connect(addr: &SocketAddr) -> Result<mio_net::TcpStream, net_error> {
+    pub fn connect(addr: &SocketAddr) -> Result<NetworkSocket, net_error> {
         let stream = mio_net::TcpStream::connect(addr).map_err(|_e| {
             test_debug!("Failed to convert to mio stream: {:?}", &_e);
             net_error::ConnectionError
         })?;
 
+        NetworkState::set_socket_options(&stream)?;
+
+        test_debug!("New socket connected to {:?}: {:?}", addr, &stream);
+        Ok(NetworkSocket::Tcp(stream))
+    }
+
+    /// Connect to a remote peer on this network, but don't register it with the poll handle.
+    /// This is the same as connect(), except that if this is a node in a simulated network,
+    /// the connection is made within the simulated network.
+    pub fn connect_socket(&mut self, addr: &SocketAddr) -> Result<NetworkSocket, net_error> {
+        #[cfg(test)]
+        {
+            if let Some(ref mut sim) = self.sim {
+                return Ok(NetworkSocket::Sim(sim.connect(addr)));
+            }
+        }
+        NetworkState::connect(addr)
+    }
+
+    fn set_socket_options(stream: &mio_net::TcpStream) -> Result<(), net_error> {
         // set some helpful defaults
         // Don't go crazy on TIME_WAIT states; have them all die after 5 seconds
         stream
@@ -352,13 +498,19 @@ impl NetworkState {
             stream.set_recv_buffer_size(32).unwrap();
         }
 
-        test_debug!("New socket connected to {:?}: {:?}", addr, &stream);
-        Ok(stream)
+        Ok(())
     }
 
     /// Poll all server sockets.
     /// Returns a map between network server handles (returned by bind()) and their new polling state
     pub fn poll(&mut self, timeout: u64) -> Result<HashMap<usize, NetworkPollState>, net_error> {
+        #[cfg(test)]
+        {
+            if self.sim.is_some() {
+                return Ok(self.poll_sim());
+            }
+        }
+
         self.events.clear();
         self.poll
             .poll(&mut self.events, Some(Duration::from_millis(timeout)))
@@ -390,8 +542,14 @@ impl NetworkState {
                         usize::from(token)
                     ));
 
+                    let listener = match server.server_socket {
+                        NetworkListener::Tcp(ref listener) => listener,
+                        #[cfg(test)]
+                        NetworkListener::Sim => unreachable!(),
+                    };
+
                     loop {
-                        let (client_sock, _client_addr) = match server.server_socket.accept() {
+                        let (client_sock, _client_addr) = match listener.accept() {
                             Ok((client_sock, client_addr)) => (client_sock, client_addr),
                             Err(e) => match e.kind() {
                                 ErrorKind::WouldBlock => {
@@ -427,7 +585,9 @@ impl NetworkState {
                             &server.server_socket,
                             &client_sock
                         );
-                        poll_state.new.insert(next_event_id, client_sock);
+                        poll_state
+                            .new
+                            .insert(next_event_id, NetworkSocket::Tcp(client_sock));
                     }
 
                     break;
@@ -461,6 +621,72 @@ impl NetworkState {
 
         Ok(poll_states)
     }
+
+    /// Poll a simulated network node.  This never blocks -- the simulated network's clock only
+    /// advances when the test driving it says so.
+    #[cfg(test)]
+    fn poll_sim(&mut self) -> HashMap<usize, NetworkPollState> {
+        let mut poll_states = HashMap::new();
+        for server in self.servers.iter() {
+            let server_event_id = usize::from(server.server_event);
+            poll_states.insert(server_event_id, NetworkPollState::new());
+        }
+
+        let (accepted, ready) = match self.sim {
+            Some(ref mut sim) => sim.poll(),
+            None => unreachable!(),
+        };
+
+        let mut new_events = HashSet::new();
+        for (server_addr, client_sock) in accepted.into_iter() {
+            let server_event_id = match self.servers.iter().find(|s| s.addr == server_addr) {
+                Some(server) => usize::from(server.server_event),
+                None => {
+                    warn!("No server bound to {:?}", &server_addr);
+                    continue;
+                }
+            };
+
+            let next_event_id = match self.make_next_event_id(self.count, &new_events) {
+                Ok(eid) => eid,
+                Err(_e) => {
+                    info!(
+                        "Too many peers, closing {:?} (events: {}, in-flight: {}, capacity: {})",
+                        &client_sock,
+                        self.event_map.len(),
+                        new_events.len(),
+                        self.event_capacity
+                    );
+                    let _ = client_sock.shutdown(Shutdown::Both);
+                    continue;
+                }
+            };
+
+            self.count = (next_event_id + 1) % (self.event_capacity + self.servers.len());
+            new_events.insert(next_event_id);
+
+            if let Some(poll_state) = poll_states.get_mut(&server_event_id) {
+                poll_state
+                    .new
+                    .insert(next_event_id, NetworkSocket::Sim(client_sock));
+            }
+        }
+
+        for event_id in ready.into_iter() {
+            match self.event_map.get(&event_id) {
+                Some(server_event_id) => {
+                    if let Some(poll_state) = poll_states.get_mut(server_event_id) {
+                        poll_state.ready.push(event_id);
+                    }
+                }
+                None => {
+                    warn!("Surreptitious readiness event {}", event_id);
+                }
+            }
+        }
+
+        poll_states
+    }
 }
 
 #[cfg(test)]
@@ -47,7 +47,6 @@ use util::get_epoch_time_secs;
 use util::log;
 
 use rand::prelude::*;
-use rand::thread_rng;
 
 impl PeerNetwork {
     /// Find out which organizations have which of our outbound neighbors.
@@ -120,7 +119,11 @@ impl PeerNetwork {
     /// likely to be up for X more seconds, so we only really want to distinguish between nodes that
     /// have wildly different uptimes.
     /// Within uptime buckets, sort by health.
-    fn compare_neighbor_uptime_health(stats1: &NeighborStats, stats2: &NeighborStats) -> Ordering {
+    fn compare_neighbor_uptime_health<R: Rng>(
+        stats1: &NeighborStats,
+        stats2: &NeighborStats,
+        rng: &mut R,
+    ) -> Ordering {
         let now = get_epoch_time_secs();
         let uptime_1 = (now - stats1.first_contact_time) as f64;
         let uptime_2 = (now - stats2.first_contact_time) as f64;
@@ -147,7 +150,6 @@ impl PeerNetwork {
         }
 
         // flip a coin
-        let mut rng = thread_rng();
         if rng.next_u32() % 2 == 0 {
             return Ordering::Less;
         } else {
@@ -157,16 +159,19 @@ impl PeerNetwork {
     }
 
     /// Sample an org based on its weight
-    fn sample_org_by_neighbor_count(org_weights: &HashMap<u32, usize>) -> u32 {
-        let mut rng = thread_rng();
+    fn sample_org_by_neighbor_count<R: Rng>(org_weights: &HashMap<u32, usize>, rng: &mut R) -> u32 {
         let mut total = 0;
         for (_, count) in org_weights.iter() {
             total += count;
         }
 
+        // visit the orgs in a fixed order, so the choice depends only on the RNG
+        let mut orgs: Vec<(&u32, &usize)> = org_weights.iter().collect();
+        orgs.sort();
+
         let sample = rng.gen_range(0, total);
         let mut offset = 0;
-        for (org, count) in org_weights.iter() {
+        for (org, count) in orgs.into_iter() {
             if *count == 0 {
                 continue;
             }
@@ -210,8 +215,9 @@ impl PeerNetwork {
             match org_neighbors.get_mut(&org) {
                 None => {}
                 Some(ref mut neighbor_infos) => {
+                    let rng = &mut self.rng;
                     neighbor_infos.sort_by(|&(ref _nk1, ref stats1), &(ref _nk2, ref stats2)| {
-                        PeerNetwork::compare_neighbor_uptime_health(stats1, stats2)
+                        PeerNetwork::compare_neighbor_uptime_health(stats1, stats2, rng)
                     });
                 }
             }
@@ -291,7 +297,8 @@ impl PeerNetwork {
                 break;
             }
 
-            let prune_org = PeerNetwork::sample_org_by_neighbor_count(&weighted_sample);
+            let prune_org =
+                PeerNetwork::sample_org_by_neighbor_count(&weighted_sample, &mut self.rng);
 
             match org_neighbors.get_mut(&prune_org) {
                 None => {
@@ -1019,13 +1019,109 @@ impl Relayer {
         Ok(ret)
     }
 
+    /// Load the headers of the blocks in a set of available blocks, so they can be pushed to
+    /// peers that accept headers-first announcements.  Blocks whose headers can't be loaded are
+    /// left out.
+    pub fn load_block_headers_data(
+        sortdb: &SortitionDB,
+        chainstate: &StacksChainState,
+        available: &BlocksAvailableMap,
+    ) -> Result<HashMap<ConsensusHash, StacksBlockHeader>, net_error> {
+        let mut ret = HashMap::new();
+        for (_, (_, ch)) in available.iter() {
+            let sn = match SortitionDB::get_block_snapshot_consensus(sortdb.conn(), ch)? {
+                Some(sn) => sn,
+                None => {
+                    continue;
+                }
+            };
+
+            match StacksChainState::load_block_header(
+                &chainstate.blocks_path,
+                ch,
+                &sn.winning_stacks_block_hash,
+            ) {
+                Ok(Some(header)) => {
+                    ret.insert(ch.clone(), header);
+                }
+                Ok(None) => {}
+                Err(e) => {
+                    debug!(
+                        "Failed to load block header {}/{}: {:?}",
+                        ch, &sn.winning_stacks_block_hash, &e
+                    );
+                }
+            }
+        }
+        Ok(ret)
+    }
+
+    /// Load the microblock headers of the confirmed microblock streams in a set of available
+    /// streams, so they can be pushed to peers that accept headers-first announcements.  Streams
+    /// whose headers can't be loaded, or that are too long to fit into one MicroblockHeaders
+    /// message, are left out.
+    pub fn load_microblock_headers_data(
+        sortdb: &SortitionDB,
+        chainstate: &StacksChainState,
+        available: &BlocksAvailableMap,
+    ) -> Result<HashMap<ConsensusHash, MicroblockHeadersData>, net_error> {
+        let mut ret = HashMap::new();
+        for (_, (_, ch)) in available.iter() {
+            let sn = match SortitionDB::get_block_snapshot_consensus(sortdb.conn(), ch)? {
+                Some(sn) => sn,
+                None => {
+                    continue;
+                }
+            };
+
+            let index_anchor_block =
+                StacksBlockHeader::make_index_block_hash(ch, &sn.winning_stacks_block_hash);
+            let microblocks = match StacksChainState::load_descendant_staging_microblock_stream(
+                &chainstate.db(),
+                &index_anchor_block,
+                0,
+                u16::MAX,
+            ) {
+                Ok(Some(microblocks)) => microblocks,
+                Ok(None) => {
+                    continue;
+                }
+                Err(e) => {
+                    debug!(
+                        "Failed to load microblock stream for {}: {:?}",
+                        &index_anchor_block, &e
+                    );
+                    continue;
+                }
+            };
+
+            if microblocks.len() == 0 || microblocks.len() > MAX_MICROBLOCKS_UNCONFIRMED {
+                continue;
+            }
+
+            ret.insert(
+                ch.clone(),
+                MicroblockHeadersData {
+                    index_anchor_block,
+                    headers: microblocks
+                        .into_iter()
+                        .map(|mblock| mblock.header)
+                        .collect(),
+                },
+            );
+        }
+        Ok(ret)
+    }
+
     /// Store all new transactions we received, and return the list of transactions that we need to
-    /// forward (as well as their relay hints).  Also, garbage-collect the mempool.
+    /// forward (as well as their relay hints).  Also, garbage-collect the mempool.  Transactions
+    /// that get dropped along the way are announced to the event observer.
     fn process_transactions(
         network_result: &mut NetworkResult,
         sortdb: &SortitionDB,
         chainstate: &mut StacksChainState,
         mempool: &mut MemPoolDB,
+        event_observer: Option<&dyn MemPoolEventDispatcher>,
     ) -> Result<Vec<(Vec<RelayData>, StacksTransaction)>, net_error> {
         let chain_height = match chainstate.get_stacks_chain_tip(sortdb)? {
             Some(tip) => tip.height,
@@ -1038,8 +1134,13 @@ impl Relayer {
             }
         };
 
-        if let Err(e) = PeerNetwork::store_transactions(mempool, chainstate, sortdb, network_result)
-        {
+        if let Err(e) = PeerNetwork::store_transactions(
+            mempool,
+            chainstate,
+            sortdb,
+            network_result,
+            event_observer,
+        ) {
             warn!("Failed to store transactions: {:?}", &e);
         }
 
@@ -1068,7 +1169,7 @@ impl Relayer {
                 min_height
             );
             MemPoolDB::garbage_collect(&mut mempool_tx, min_height)?;
-            mempool_tx.commit()?;
+            mempool_tx.commit_and_announce(event_observer)?;
         }
 
         Ok(ret)
@@ -1181,6 +1282,7 @@ impl Relayer {
         chainstate: &mut StacksChainState,
         mempool: &mut MemPoolDB,
         coord_comms: Option<&CoordinatorChannels>,
+        event_observer: Option<&dyn MemPoolEventDispatcher>,
     ) -> Result<ProcessedNetReceipts, net_error> {
         match Relayer::process_new_blocks(network_result, sortdb, chainstate, coord_comms) {
             Ok((new_blocks, new_confirmed_microblocks, new_microblocks, bad_block_neighbors)) => {
@@ -1197,11 +1299,13 @@ impl Relayer {
                     }
                 }
 
-                // have the p2p thread tell our neighbors about newly-discovered blocks
+                // have the p2p thread tell our neighbors about newly-discovered blocks.  Neighbors
+                // that accept headers-first announcements get the blocks' headers.
                 let available = Relayer::load_blocks_available_data(sortdb, new_blocks)?;
                 if available.len() > 0 {
                     debug!("{:?}: Blocks available: {}", &_local_peer, available.len());
-                    if let Err(e) = self.p2p.advertize_blocks(available) {
+                    let headers = Relayer::load_block_headers_data(sortdb, chainstate, &available)?;
+                    if let Err(e) = self.p2p.advertize_block_headers(available, headers) {
                         warn!("Failed to advertize new blocks: {:?}", &e);
                     }
                 }
@@ -1215,7 +1319,15 @@ impl Relayer {
                         &_local_peer,
                         mblocks_available.len()
                     );
-                    if let Err(e) = self.p2p.advertize_microblocks(mblocks_available) {
+                    let headers = Relayer::load_microblock_headers_data(
+                        sortdb,
+                        chainstate,
+                        &mblocks_available,
+                    )?;
+                    if let Err(e) = self
+                        .p2p
+                        .advertize_microblock_headers(mblocks_available, headers)
+                    {
                         warn!("Failed to advertize new confirmed microblocks: {:?}", &e);
                     }
                 }
@@ -1252,7 +1364,13 @@ impl Relayer {
             &_local_peer,
             network_result.pushed_transactions.len()
         );
-        let new_txs = Relayer::process_transactions(network_result, sortdb, chainstate, mempool)?;
+        let new_txs = Relayer::process_transactions(
+            network_result,
+            sortdb,
+            chainstate,
+            mempool,
+            event_observer,
+        )?;
 
         if new_txs.len() > 0 {
             debug!(
@@ -1272,6 +1390,13 @@ impl Relayer {
             }
         }
 
+        // transactions we synchronized from our neighbors' mempools are new to us, but not to our
+        // neighbors, so don't forward them.
+        for tx in network_result.synced_transactions.iter() {
+            debug!("{:?}: Synced tx {}", &_local_peer, &tx.txid());
+            mempool_txs_added.push(tx.clone());
+        }
+
         let receipts = ProcessedNetReceipts { mempool_txs_added };
 
         // finally, refresh the unconfirmed chainstate, if need be
@@ -1384,15 +1509,15 @@ impl PeerNetwork {
         }
     }
 
-    /// Announce blocks that we have to an outbound peer that doesn't have them.
-    /// Only advertize blocks and microblocks we have that the outbound peer doesn't.
-    fn advertize_to_outbound_peer(
+    /// Find the blocks or microblocks we have that an outbound peer doesn't, based on the inv
+    /// state we synchronized from it.
+    fn find_outbound_peer_wanted(
         &mut self,
         recipient: &NeighborKey,
         available: &BlocksAvailableMap,
         microblocks: bool,
-    ) -> Result<(), net_error> {
-        let wanted = PeerNetwork::with_inv_state(self, |_network, inv_state| {
+    ) -> Result<Vec<(ConsensusHash, BurnchainHeaderHash)>, net_error> {
+        PeerNetwork::with_inv_state(self, |_network, inv_state| {
             let mut wanted: Vec<(ConsensusHash, BurnchainHeaderHash)> = vec![];
             if let Some(stats) = inv_state.block_stats.get(recipient) {
                 for (bhh, (block_height, ch)) in available.iter() {
@@ -1416,7 +1541,18 @@ impl PeerNetwork {
                 }
             }
             Ok(wanted)
-        })?;
+        })
+    }
+
+    /// Announce blocks that we have to an outbound peer that doesn't have them.
+    /// Only advertize blocks and microblocks we have that the outbound peer doesn't.
+    fn advertize_to_outbound_peer(
+        &mut self,
+        recipient: &NeighborKey,
+        available: &BlocksAvailableMap,
+        microblocks: bool,
+    ) -> Result<(), net_error> {
+        let wanted = self.find_outbound_peer_wanted(recipient, available, microblocks)?;
 
         if microblocks {
             self.advertize_to_peer(recipient, &wanted, |payload| {
@@ -1530,6 +1666,213 @@ impl PeerNetwork {
         Ok(())
     }
 
+    /// Does this peer understand BlockHeaders and MicroblockHeaders pushes?
+    fn peer_accepts_headers(&self, recipient: &NeighborKey) -> bool {
+        match self.events.get(recipient) {
+            Some(event_id) => match self.peers.get(event_id) {
+                Some(convo) => (convo.peer_services & (ServiceFlags::HEADERS as u16)) != 0,
+                None => false,
+            },
+            None => false,
+        }
+    }
+
+    /// Find the blocks or microblocks a recipient should hear about.  Outbound peers only hear
+    /// about the ones they don't have; inbound peers hear about all of them.
+    fn find_peer_wanted(
+        &mut self,
+        recipient: &NeighborKey,
+        available: &BlocksAvailableMap,
+        outbound: bool,
+        microblocks: bool,
+    ) -> Result<Vec<(ConsensusHash, BurnchainHeaderHash)>, net_error> {
+        if outbound {
+            self.find_outbound_peer_wanted(recipient, available, microblocks)
+        } else {
+            let mut wanted = vec![];
+            for (burn_header_hash, (_, consensus_hash)) in available.iter() {
+                wanted.push(((*consensus_hash).clone(), (*burn_header_hash).clone()));
+            }
+            Ok(wanted)
+        }
+    }
+
+    /// Push the headers of blocks that we have to a peer, so it can validate them before it
+    /// fetches the blocks.  Blocks whose headers we don't have are announced with
+    /// BlocksAvailable instead.
+    fn push_block_headers_to_peer(
+        &mut self,
+        recipient: &NeighborKey,
+        available: &BlocksAvailableMap,
+        headers: &HashMap<ConsensusHash, StacksBlockHeader>,
+        outbound: bool,
+    ) -> Result<(), net_error> {
+        let (with_headers, without_headers): (Vec<_>, Vec<_>) = self
+            .find_peer_wanted(recipient, available, outbound, false)?
+            .into_iter()
+            .partition(|(ch, _)| headers.contains_key(ch));
+
+        self.advertize_to_peer(recipient, &with_headers, |payload| {
+            let mut data = BlockHeadersData::new();
+            for (ch, _) in payload.available.into_iter() {
+                let header = headers
+                    .get(&ch)
+                    .expect("BUG: partitioned on having a header")
+                    .clone();
+                data.headers.push((ch, header));
+            }
+            StacksMessageType::BlockHeaders(data)
+        });
+        self.advertize_to_peer(recipient, &without_headers, |payload| {
+            StacksMessageType::BlocksAvailable(payload)
+        });
+        Ok(())
+    }
+
+    /// Push the headers of confirmed microblock streams that we have to a peer, so it can validate
+    /// them before it fetches the streams.  Streams whose headers we don't have are announced
+    /// with MicroblocksAvailable instead.
+    fn push_microblock_headers_to_peer(
+        &mut self,
+        recipient: &NeighborKey,
+        available: &BlocksAvailableMap,
+        headers: &HashMap<ConsensusHash, MicroblockHeadersData>,
+        outbound: bool,
+    ) -> Result<(), net_error> {
+        let (with_headers, without_headers): (Vec<_>, Vec<_>) = self
+            .find_peer_wanted(recipient, available, outbound, true)?
+            .into_iter()
+            .partition(|(ch, _)| headers.contains_key(ch));
+
+        // one stream per message
+        for (ch, _) in with_headers.iter() {
+            let payload = StacksMessageType::MicroblockHeaders(
+                headers
+                    .get(ch)
+                    .expect("BUG: partitioned on having headers")
+                    .clone(),
+            );
+            let message = match self.sign_for_peer(recipient, payload) {
+                Ok(m) => m,
+                Err(e) => {
+                    warn!(
+                        "{:?}: Failed to sign for {:?}: {:?}",
+                        &self.local_peer, recipient, &e
+                    );
+                    continue;
+                }
+            };
+
+            // absorb errors
+            let _ = self.relay_signed_message(recipient, message).map_err(|e| {
+                warn!(
+                    "{:?}: Failed to push microblock headers for {} to {:?}: {:?}",
+                    &self.local_peer, ch, recipient, &e
+                );
+                e
+            });
+        }
+        self.advertize_to_peer(recipient, &without_headers, |payload| {
+            StacksMessageType::MicroblocksAvailable(payload)
+        });
+        Ok(())
+    }
+
+    /// Announce blocks that we have to a subset of inbound and outbound peers, pushing the
+    /// blocks' headers to the ones that accept headers-first announcements.  The other peers get
+    /// a BlocksAvailable message, just like in advertize_blocks().
+    pub fn advertize_block_headers(
+        &mut self,
+        availability_data: BlocksAvailableMap,
+        headers: HashMap<ConsensusHash, StacksBlockHeader>,
+    ) -> Result<(), net_error> {
+        let (mut outbound_recipients, mut inbound_recipients) =
+            self.find_block_recipients(&availability_data)?;
+        debug!(
+            "{:?}: Advertize {} blocks ({} headers) to {} inbound peers, {} outbound peers",
+            &self.local_peer,
+            availability_data.len(),
+            headers.len(),
+            outbound_recipients.len(),
+            inbound_recipients.len()
+        );
+
+        for recipient in outbound_recipients.drain(..) {
+            if self.peer_accepts_headers(&recipient) {
+                debug!(
+                    "{:?}: Push block headers to outbound peer {}",
+                    &self.local_peer, &recipient
+                );
+                self.push_block_headers_to_peer(&recipient, &availability_data, &headers, true)?;
+            } else {
+                self.advertize_to_outbound_peer(&recipient, &availability_data, false)?;
+            }
+        }
+        for recipient in inbound_recipients.drain(..) {
+            if self.peer_accepts_headers(&recipient) {
+                debug!(
+                    "{:?}: Push block headers to inbound peer {}",
+                    &self.local_peer, &recipient
+                );
+                self.push_block_headers_to_peer(&recipient, &availability_data, &headers, false)?;
+            } else {
+                self.advertize_to_inbound_peer(&recipient, &availability_data, |payload| {
+                    StacksMessageType::BlocksAvailable(payload)
+                })?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Announce confirmed microblocks that we have to a subset of inbound and outbound peers,
+    /// pushing the streams' headers to the ones that accept headers-first announcements.  The other
+    /// peers get a MicroblocksAvailable message, just like in advertize_microblocks().
+    pub fn advertize_microblock_headers(
+        &mut self,
+        availability_data: BlocksAvailableMap,
+        headers: HashMap<ConsensusHash, MicroblockHeadersData>,
+    ) -> Result<(), net_error> {
+        let (mut outbound_recipients, mut inbound_recipients) =
+            self.find_block_recipients(&availability_data)?;
+        debug!("{:?}: Advertize {} confirmed microblock streams ({} with headers) to {} inbound peers, {} outbound peers", &self.local_peer, availability_data.len(), headers.len(), outbound_recipients.len(), inbound_recipients.len());
+
+        for recipient in outbound_recipients.drain(..) {
+            if self.peer_accepts_headers(&recipient) {
+                debug!(
+                    "{:?}: Push microblock headers to outbound peer {}",
+                    &self.local_peer, &recipient
+                );
+                self.push_microblock_headers_to_peer(
+                    &recipient,
+                    &availability_data,
+                    &headers,
+                    true,
+                )?;
+            } else {
+                self.advertize_to_outbound_peer(&recipient, &availability_data, true)?;
+            }
+        }
+        for recipient in inbound_recipients.drain(..) {
+            if self.peer_accepts_headers(&recipient) {
+                debug!(
+                    "{:?}: Push microblock headers to inbound peer {}",
+                    &self.local_peer, &recipient
+                );
+                self.push_microblock_headers_to_peer(
+                    &recipient,
+                    &availability_data,
+                    &headers,
+                    false,
+                )?;
+            } else {
+                self.advertize_to_inbound_peer(&recipient, &availability_data, |payload| {
+                    StacksMessageType::MicroblocksAvailable(payload)
+                })?;
+            }
+        }
+        Ok(())
+    }
+
     /// Update accounting information for relayed messages from a network result.
     /// This influences selecting next-hop neighbors to get data from us.
     pub fn update_relayer_stats(&mut self, network_result: &NetworkResult) -> () {
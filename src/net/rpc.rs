@@ -24,7 +24,10 @@ use std::io::{Read, Seek, SeekFrom, Write};
 use std::net::SocketAddr;
 
 use core::mempool::*;
+use core::miner_history::{MinedBlockOutcomeCounts, MinerHistoryDB};
+use core::EMPTY_MICROBLOCK_PARENT_HASH;
 use net::atlas::{AtlasDB, Attachment, MAX_ATTACHMENT_INV_PAGES_PER_REQUEST};
+use net::connection::BandwidthAccount;
 use net::connection::ConnectionHttp;
 use net::connection::ConnectionOptions;
 use net::connection::ReplyHandleHttp;
@@ -49,17 +52,26 @@ use net::StacksHttp;
 use net::StacksHttpMessage;
 use net::StacksMessageCodec;
 use net::StacksMessageType;
+use net::TipRequest;
 use net::UnconfirmedTransactionResponse;
 use net::UnconfirmedTransactionStatus;
 use net::UrlString;
 use net::HTTP_REQUEST_ID_RESERVED;
 use net::MAX_NEIGHBORS_DATA_LEN;
 use net::{
-    AccountEntryResponse, AttachmentPage, CallReadOnlyResponse, ContractSrcResponse,
-    GetAttachmentResponse, GetAttachmentsInvResponse, MapEntryResponse,
+    AccountEntryResponse, AttachmentPage, BatchEntriesResponse, CallReadOnlyResponse,
+    ContractSrcResponse, GetAttachmentResponse, GetAttachmentsInvResponse, MapEntryResponse,
 };
-use net::{RPCNeighbor, RPCNeighborsInfo};
-use net::{RPCPeerInfoData, RPCPoxInfoData};
+use net::{RPCFeeRateBucket, RPCMempoolFeeHistogram, RPCMempoolNonceData, RPCMempoolTxInfo};
+use net::{RPCHeaderInfoData, RPCPeerInfoData, RPCPoxInfoData};
+use net::{
+    RPCMicroblockEquivocation, RPCMicroblockInfo, RPCMicroblockStream, RPCMicroblockStreamsData,
+    RPCPoisonMicroblockReport,
+};
+use net::{RPCMinerAttemptsData, MAX_MINER_ATTEMPTS_LEN};
+use net::{RPCNeighbor, RPCNeighborBandwidth, RPCNeighborsInfo};
+use std::cmp;
+use std::collections::BTreeMap;
 use std::collections::HashMap;
 use std::collections::HashSet;
 use std::collections::VecDeque;
@@ -73,8 +85,10 @@ use chainstate::burn::db::sortdb::SortitionDB;
 use chainstate::burn::BlockHeaderHash;
 use chainstate::burn::ConsensusHash;
 use chainstate::stacks::db::{
-    blocks::MINIMUM_TX_FEE_RATE_PER_BYTE, BlockStreamData, StacksChainState,
+    blocks::{StagingMicroblock, MINIMUM_TX_FEE_RATE_PER_BYTE},
+    BlockStreamData, StacksChainState, StacksHeaderInfo,
 };
+use chainstate::stacks::index::proofs::TrieMerkleMultiProof;
 use chainstate::stacks::Error as chain_error;
 use chainstate::stacks::*;
 use monitoring;
@@ -110,6 +124,7 @@ pub const STREAM_CHUNK_SIZE: u64 = 4096;
 pub struct RPCHandlerArgs<'a> {
     pub exit_at_block_height: Option<&'a u64>,
     pub genesis_chainstate_hash: Sha256Sum,
+    pub event_observer: Option<&'a dyn MemPoolEventDispatcher>,
 }
 
 pub struct ConversationHttp {
@@ -139,6 +154,12 @@ pub struct ConversationHttp {
     pending_request: Option<ReplyHandleHttp>,
     pending_response: Option<HttpResponseType>,
     pending_error_response: Option<HttpResponseType>,
+
+    // bytes sent and received on this conversation
+    bandwidth: BandwidthAccount,
+
+    // set by the HttpPeer when this client (or this node) has used up its upload quota
+    pub upload_quota_exhausted: bool,
 }
 
 impl fmt::Display for ConversationHttp {
@@ -327,6 +348,338 @@ impl RPCPoxInfoData {
     }
 }
 
+impl RPCHeaderInfoData {
+    pub fn from_db(
+        chainstate: &StacksChainState,
+        header_info: &StacksHeaderInfo,
+    ) -> Result<RPCHeaderInfoData, net_error> {
+        let header = &header_info.anchored_header;
+        let index_block_hash = header_info.index_block_hash();
+        let block_hash = header.block_hash();
+
+        let parent_index_block_hash =
+            StacksChainState::get_parent_block_id(chainstate.db(), &index_block_hash)?
+                .ok_or_else(|| net_error::DBError(db_error::NotFoundError))?;
+
+        let execution_cost =
+            StacksChainState::get_stacks_block_anchored_cost(chainstate.db(), &index_block_hash)?
+                .ok_or_else(|| net_error::DBError(db_error::NotFoundError))?;
+
+        let miner = StacksChainState::get_miner_info(
+            chainstate.db(),
+            &header_info.consensus_hash,
+            &block_hash,
+        )?
+        .map(|miner_info| miner_info.address.to_string());
+
+        // recorded when the block was processed, so it's known even if the block was pruned
+        let tx_count =
+            StacksChainState::get_stacks_block_tx_count(chainstate.db(), &index_block_hash)?;
+
+        // the tail of the parent's microblock stream that this block confirms, if any
+        let microblock_tail = if header.parent_microblock == EMPTY_MICROBLOCK_PARENT_HASH {
+            None
+        } else {
+            Some(header.parent_microblock.clone())
+        };
+
+        Ok(RPCHeaderInfoData {
+            index_block_hash,
+            block_hash,
+            block_height: header_info.block_height,
+            parent_index_block_hash,
+            parent_block_hash: header.parent_block.clone(),
+            parent_microblock_hash: header.parent_microblock.clone(),
+            parent_microblock_sequence: header.parent_microblock_sequence,
+            microblock_tail,
+            index_root: header_info.index_root.clone(),
+            state_index_root: header.state_index_root.clone(),
+            consensus_hash: header_info.consensus_hash.clone(),
+            burn_header_hash: header_info.burn_header_hash.to_hex(),
+            burn_header_height: header_info.burn_header_height,
+            burn_header_timestamp: header_info.burn_header_timestamp,
+            anchored_block_size: header_info.anchored_block_size,
+            miner,
+            tx_count,
+            execution_cost,
+            header: to_hex(&header.serialize_to_vec()),
+        })
+    }
+}
+
+impl RPCMicroblockStreamsData {
+    /// Load up every microblock stream stored for the given anchored block, and work out which
+    /// one the canonical fork confirmed, where the miner equivocated, and whether or not the
+    /// miner got caught.  Returns Ok(None) if the anchored block is not known.
+    pub fn from_db(
+        sortdb: &SortitionDB,
+        chainstate: &mut StacksChainState,
+        index_block_hash: &StacksBlockId,
+    ) -> Result<Option<RPCMicroblockStreamsData>, net_error> {
+        let header_info = match StacksChainState::get_stacks_block_header_info_by_index_block_hash(
+            chainstate.db(),
+            index_block_hash,
+        )? {
+            Some(header_info) => header_info,
+            None => {
+                return Ok(None);
+            }
+        };
+        let block_height = header_info.block_height;
+
+        let canonical =
+            match chainstate.get_canonical_block_header_at_height(sortdb, block_height)? {
+                Some(canonical_header) => canonical_header.index_block_hash() == *index_block_hash,
+                None => false,
+            };
+
+        // the canonical child (if there is one) tells us which stream got confirmed
+        let (confirmed_tail, confirmed_sequence) =
+            match chainstate.get_canonical_block_header_at_height(sortdb, block_height + 1)? {
+                Some(ref child_header) if canonical => {
+                    let child_header_block = &child_header.anchored_header;
+                    if child_header_block.parent_microblock == EMPTY_MICROBLOCK_PARENT_HASH {
+                        (None, None)
+                    } else {
+                        (
+                            Some(child_header_block.parent_microblock.clone()),
+                            Some(child_header_block.parent_microblock_sequence),
+                        )
+                    }
+                }
+                _ => (None, None),
+            };
+
+        let microblocks =
+            StacksChainState::get_staging_microblocks_info(chainstate.db(), index_block_hash)?;
+        let (streams, equivocations) =
+            RPCMicroblockStreamsData::make_streams(&microblocks, confirmed_tail.as_ref());
+
+        // poison-microblock reports are keyed to the height at which the miner's microblock
+        // public key hash was used, and only count in the canonical fork
+        let poison_report = if canonical {
+            let tip = match chainstate.get_stacks_chain_tip(sortdb)? {
+                Some(tip) => StacksBlockHeader::make_index_block_hash(
+                    &tip.consensus_hash,
+                    &tip.anchored_block_hash,
+                ),
+                None => {
+                    return Err(net_error::DBError(db_error::NotFoundError));
+                }
+            };
+            let pubkey_hash_height = header_info.anchored_header.total_work.work as u32;
+            chainstate
+                .maybe_read_only_clarity_tx(&sortdb.index_conn(), &tip, |clarity_tx| {
+                    clarity_tx.with_clarity_db_readonly(|clarity_db| {
+                        clarity_db.get_microblock_poison_report(pubkey_hash_height)
+                    })
+                })?
+                .and_then(|report_opt| report_opt)
+                .map(|(reporter, sequence)| RPCPoisonMicroblockReport {
+                    reporter: PrincipalData::Standard(reporter).to_string(),
+                    sequence,
+                })
+        } else {
+            None
+        };
+
+        Ok(Some(RPCMicroblockStreamsData {
+            index_block_hash: index_block_hash.clone(),
+            block_height,
+            canonical,
+            confirmed_tail,
+            confirmed_sequence,
+            streams,
+            equivocations,
+            poison_report,
+        }))
+    }
+
+    /// Split a set of stored microblocks into the distinct streams they form.  Each stream is a
+    /// path from a microblock whose parent is not in the set to a microblock with no children.
+    /// A stream is confirmed if it contains the given confirmed tail.  Also reports each sequence
+    /// number at which two or more distinct microblocks were stored.
+    pub fn make_streams(
+        microblocks: &[StagingMicroblock],
+        confirmed_tail: Option<&BlockHeaderHash>,
+    ) -> (Vec<RPCMicroblockStream>, Vec<RPCMicroblockEquivocation>) {
+        let mut by_hash: HashMap<BlockHeaderHash, &StagingMicroblock> = HashMap::new();
+        for mblock in microblocks.iter() {
+            by_hash.insert(mblock.microblock_hash.clone(), mblock);
+        }
+
+        let mut children: HashMap<BlockHeaderHash, Vec<&StagingMicroblock>> = HashMap::new();
+        let mut roots = vec![];
+        for mblock in microblocks.iter() {
+            if by_hash.contains_key(&mblock.parent_hash) {
+                children
+                    .entry(mblock.parent_hash.clone())
+                    .or_insert(vec![])
+                    .push(mblock);
+            } else {
+                roots.push(mblock);
+            }
+        }
+
+        let mut streams = vec![];
+        let mut frontier: Vec<Vec<&StagingMicroblock>> =
+            roots.into_iter().map(|root| vec![root]).rev().collect();
+        while let Some(path) = frontier.pop() {
+            let last = path[path.len() - 1];
+            match children.get(&last.microblock_hash) {
+                Some(next) => {
+                    for child in next.iter().rev() {
+                        let mut next_path = path.clone();
+                        next_path.push(child);
+                        frontier.push(next_path);
+                    }
+                }
+                None => {
+                    let confirmed = match confirmed_tail {
+                        Some(tail) => path.iter().any(|mblock| mblock.microblock_hash == *tail),
+                        None => false,
+                    };
+                    streams.push(RPCMicroblockStream {
+                        microblocks: path
+                            .into_iter()
+                            .map(|mblock| RPCMicroblockInfo {
+                                microblock_hash: mblock.microblock_hash.clone(),
+                                parent_hash: mblock.parent_hash.clone(),
+                                sequence: mblock.sequence,
+                                processed: mblock.processed,
+                                orphaned: mblock.orphaned,
+                            })
+                            .collect(),
+                        confirmed,
+                    });
+                }
+            }
+        }
+
+        // microblocks are sorted by sequence, so equal sequences are adjacent
+        let mut equivocations: Vec<RPCMicroblockEquivocation> = vec![];
+        for mblock in microblocks.iter() {
+            match equivocations.last_mut() {
+                Some(ref mut last) if last.sequence == mblock.sequence => {
+                    if !last.microblock_hashes.contains(&mblock.microblock_hash) {
+                        last.microblock_hashes.push(mblock.microblock_hash.clone());
+                    }
+                    continue;
+                }
+                _ => {}
+            }
+            equivocations.push(RPCMicroblockEquivocation {
+                sequence: mblock.sequence,
+                microblock_hashes: vec![mblock.microblock_hash.clone()],
+            });
+        }
+        equivocations.retain(|equivocation| equivocation.microblock_hashes.len() > 1);
+
+        (streams, equivocations)
+    }
+}
+
+impl From<MemPoolTxMetadata> for RPCMempoolTxInfo {
+    fn from(md: MemPoolTxMetadata) -> RPCMempoolTxInfo {
+        RPCMempoolTxInfo {
+            txid: md.txid,
+            origin_address: md.origin_address.to_string(),
+            origin_nonce: md.origin_nonce,
+            sponsor_address: md.sponsor_address.to_string(),
+            sponsor_nonce: md.sponsor_nonce,
+            tx_fee: md.tx_fee,
+            length: md.len,
+            accept_time: md.accept_time,
+            consensus_hash: md.consensus_hash,
+            block_header_hash: md.block_header_hash,
+            block_height: md.block_height,
+        }
+    }
+}
+
+impl RPCMempoolFeeHistogram {
+    /// Get the histogram of the mempool's fee rates.  Computing it reads the whole mempool, so
+    /// it's cached until the mempool changes.
+    pub fn from_mempool(mempool: &mut MemPoolDB) -> Result<RPCMempoolFeeHistogram, net_error> {
+        let change_count = MemPoolDB::get_change_count(mempool.conn())?;
+        if let Some(histogram) = mempool.get_cached_fee_histogram(change_count) {
+            return Ok(histogram.clone());
+        }
+
+        let histogram = RPCMempoolFeeHistogram::from_fees_and_lengths(
+            MemPoolDB::get_fees_and_lengths(mempool.conn())?,
+        );
+        mempool.set_cached_fee_histogram(change_count, histogram.clone());
+        Ok(histogram)
+    }
+
+    /// Bucket the given fee rates.  Each bucket spans a power of two, i.e. [1, 1], [2, 3],
+    /// [4, 7], and so on, with a separate bucket for a fee rate of 0.  Only non-empty buckets
+    /// are reported.
+    fn from_fees_and_lengths(fees_and_lengths: Vec<(u64, u64)>) -> RPCMempoolFeeHistogram {
+        let mut buckets: BTreeMap<u64, RPCFeeRateBucket> = BTreeMap::new();
+        let mut tx_count = 0;
+        let mut total_bytes = 0;
+        for (tx_fee, length) in fees_and_lengths.into_iter() {
+            let fee_rate = if length > 0 { tx_fee / length } else { tx_fee };
+            let (min_fee_rate, max_fee_rate) = if fee_rate == 0 {
+                (0, 0)
+            } else {
+                let min_fee_rate = 1u64 << (63 - fee_rate.leading_zeros());
+                (min_fee_rate, min_fee_rate + (min_fee_rate - 1))
+            };
+
+            let bucket = buckets
+                .entry(min_fee_rate)
+                .or_insert_with(|| RPCFeeRateBucket {
+                    min_fee_rate,
+                    max_fee_rate,
+                    tx_count: 0,
+                    total_bytes: 0,
+                });
+            bucket.tx_count += 1;
+            bucket.total_bytes += length;
+
+            tx_count += 1;
+            total_bytes += length;
+        }
+
+        RPCMempoolFeeHistogram {
+            tx_count,
+            total_bytes,
+            buckets: buckets.into_iter().map(|(_, bucket)| bucket).collect(),
+        }
+    }
+}
+
+impl RPCMinerAttemptsData {
+    /// Load this node's most recent mined block attempts from the miner history db in the
+    /// chainstate directory.  A node that has never mined has no history.
+    pub fn from_chainstate_path(chainstate_path: &str) -> Result<RPCMinerAttemptsData, net_error> {
+        let history = match MinerHistoryDB::open(chainstate_path, false) {
+            Ok(history) => history,
+            Err(db_error::NotFoundError) => {
+                return Ok(RPCMinerAttemptsData {
+                    outcomes: MinedBlockOutcomeCounts {
+                        won: 0,
+                        lost: 0,
+                        pending: 0,
+                    },
+                    attempts: vec![],
+                });
+            }
+            Err(e) => {
+                return Err(net_error::DBError(e));
+            }
+        };
+
+        Ok(RPCMinerAttemptsData {
+            outcomes: MinerHistoryDB::get_outcome_counts(history.conn())?,
+            attempts: MinerHistoryDB::get_recent_attempts(history.conn(), MAX_MINER_ATTEMPTS_LEN)?,
+        })
+    }
+}
+
 impl RPCNeighborsInfo {
     /// Load neighbor address information from the peer network
     pub fn from_p2p(
@@ -341,6 +694,7 @@ impl RPCNeighborsInfo {
             MAX_NEIGHBORS_DATA_LEN,
             chain_view.burn_block_height,
             false,
+            &mut thread_rng(),
         )
         .map_err(net_error::DBError)?;
 
@@ -360,18 +714,18 @@ impl RPCNeighborsInfo {
         for (_, convo) in peers.iter() {
             let nk = convo.to_neighbor_key();
             let naddr = convo.to_neighbor_address();
+            let mut neighbor = RPCNeighbor::from_neighbor_key_and_pubkh(
+                nk,
+                naddr.public_key_hash,
+                convo.is_authenticated(),
+            );
+            neighbor.bandwidth = Some(RPCNeighborBandwidth::from_bandwidth_account(
+                &convo.stats.bandwidth,
+            ));
             if convo.is_outbound() {
-                outbound.push(RPCNeighbor::from_neighbor_key_and_pubkh(
-                    nk,
-                    naddr.public_key_hash,
-                    convo.is_authenticated(),
-                ));
+                outbound.push(neighbor);
             } else {
-                inbound.push(RPCNeighbor::from_neighbor_key_and_pubkh(
-                    nk,
-                    naddr.public_key_hash,
-                    convo.is_authenticated(),
-                ));
+                inbound.push(neighbor);
             }
         }
 
@@ -383,6 +737,31 @@ impl RPCNeighborsInfo {
     }
 }
 
+impl RPCNeighborBandwidth {
+    pub fn from_bandwidth_account(account: &BandwidthAccount) -> RPCNeighborBandwidth {
+        RPCNeighborBandwidth {
+            bytes_sent: account.total.bytes_tx,
+            bytes_received: account.total.bytes_rx,
+            period_bytes_sent: account.get_period_bytes_tx(),
+            period_bytes_received: account.get_period_bytes_rx(),
+            upload_bandwidth: account.get_upload_bandwidth() as u64,
+            download_bandwidth: account.get_download_bandwidth() as u64,
+            msg_bytes_sent: account
+                .total
+                .msg_bytes_tx
+                .iter()
+                .map(|(msg_id, num_bytes)| (format!("{:?}", msg_id), *num_bytes))
+                .collect(),
+            msg_bytes_received: account
+                .total
+                .msg_bytes_rx
+                .iter()
+                .map(|(msg_id, num_bytes)| (format!("{:?}", msg_id), *num_bytes))
+                .collect(),
+        }
+    }
+}
+
 impl ConversationHttp {
     pub fn new(
         network_id: u32,
@@ -414,6 +793,8 @@ impl ConversationHttp {
             last_request_timestamp: 0,
             last_response_timestamp: 0,
             connection_time: get_epoch_time_secs(),
+            bandwidth: BandwidthAccount::new(conn_opts.bandwidth_quota_period),
+            upload_quota_exhausted: false,
         }
     }
 
@@ -1053,6 +1434,144 @@ impl ConversationHttp {
         response.send(http, fd).map(|_| ())
     }
 
+    /// Handle a GET for a page of the pending transactions an address originates or sponsors.
+    /// The response will be synchronously written to the fd.
+    fn handle_get_mempool_txs<W: Write>(
+        http: &mut StacksHttp,
+        fd: &mut W,
+        req: &HttpRequestType,
+        mempool: &MemPoolDB,
+        address: &StacksAddress,
+        offset: u64,
+        limit: u32,
+    ) -> Result<(), net_error> {
+        let response_metadata = HttpResponseMetadata::from(req);
+        let response =
+            match MemPoolDB::get_txs_for_address(mempool.conn(), address, offset, limit as u64) {
+                Ok(txs) => HttpResponseType::MempoolTxs(
+                    response_metadata,
+                    txs.into_iter().map(RPCMempoolTxInfo::from).collect(),
+                ),
+                Err(e) => {
+                    warn!(
+                        "Failed to load mempool transactions for {}: {:?}",
+                        address, &e
+                    );
+                    HttpResponseType::ServerError(
+                        response_metadata,
+                        format!("Failed to load mempool transactions for {}", address),
+                    )
+                }
+            };
+        response.send(http, fd).map(|_| ())
+    }
+
+    /// Handle a GET for the next nonce an address can use, given the current chain tip and the
+    /// address's pending transactions.
+    /// The response will be synchronously written to the fd.
+    fn handle_get_mempool_nonce<W: Write>(
+        http: &mut StacksHttp,
+        fd: &mut W,
+        req: &HttpRequestType,
+        sortdb: &SortitionDB,
+        chainstate: &mut StacksChainState,
+        tip: &StacksBlockId,
+        mempool: &MemPoolDB,
+        address: &StacksAddress,
+    ) -> Result<(), net_error> {
+        let response_metadata = HttpResponseMetadata::from(req);
+        let principal = address.to_account_principal();
+        let confirmed_nonce_opt =
+            match chainstate.maybe_read_only_clarity_tx(&sortdb.index_conn(), tip, |clarity_tx| {
+                clarity_tx
+                    .with_clarity_db_readonly(|clarity_db| clarity_db.get_account_nonce(&principal))
+            }) {
+                Ok(nonce_opt) => nonce_opt,
+                Err(e) => {
+                    warn!("Failed to load the nonce of {}: {:?}", address, &e);
+                    None
+                }
+            };
+
+        let confirmed_nonce = match confirmed_nonce_opt {
+            Some(nonce) => nonce,
+            None => {
+                let response =
+                    HttpResponseType::NotFound(response_metadata, "Chain tip not found".into());
+                return response.send(http, fd).map(|_| ());
+            }
+        };
+
+        let response =
+            match MemPoolDB::get_next_chained_nonce(mempool.conn(), address, confirmed_nonce)
+                .and_then(|next_nonce| {
+                    MemPoolDB::get_next_nonce_for_address(mempool.conn(), address)
+                        .map(|mempool_next_nonce| (next_nonce, mempool_next_nonce))
+                }) {
+                Ok((next_nonce, mempool_next_nonce)) => HttpResponseType::MempoolNonce(
+                    response_metadata,
+                    RPCMempoolNonceData {
+                        address: address.to_string(),
+                        confirmed_nonce,
+                        next_nonce,
+                        mempool_next_nonce: cmp::max(confirmed_nonce, mempool_next_nonce),
+                    },
+                ),
+                Err(e) => {
+                    warn!("Failed to load mempool nonces for {}: {:?}", address, &e);
+                    HttpResponseType::ServerError(
+                        response_metadata,
+                        format!("Failed to load mempool nonces for {}", address),
+                    )
+                }
+            };
+        response.send(http, fd).map(|_| ())
+    }
+
+    /// Handle a GET for a histogram of the fee rates of the transactions in the mempool.
+    /// The response will be synchronously written to the fd.
+    fn handle_get_mempool_fees<W: Write>(
+        http: &mut StacksHttp,
+        fd: &mut W,
+        req: &HttpRequestType,
+        mempool: &mut MemPoolDB,
+    ) -> Result<(), net_error> {
+        let response_metadata = HttpResponseMetadata::from(req);
+        let response = match RPCMempoolFeeHistogram::from_mempool(mempool) {
+            Ok(histogram) => HttpResponseType::MempoolFeeHistogram(response_metadata, histogram),
+            Err(e) => {
+                warn!("Failed to load mempool fee rates: {:?}", &e);
+                HttpResponseType::ServerError(
+                    response_metadata,
+                    "Failed to load mempool fee rates".to_string(),
+                )
+            }
+        };
+        response.send(http, fd).map(|_| ())
+    }
+
+    /// Handle a GET for this node's recent mined block attempts.
+    /// The response will be synchronously written to the fd.
+    fn handle_get_miner_attempts<W: Write>(
+        http: &mut StacksHttp,
+        fd: &mut W,
+        req: &HttpRequestType,
+        chainstate: &StacksChainState,
+    ) -> Result<(), net_error> {
+        let response_metadata = HttpResponseMetadata::from(req);
+        let response = match RPCMinerAttemptsData::from_chainstate_path(&chainstate.root_path) {
+            Ok(attempts) => HttpResponseType::MinerAttempts(response_metadata, attempts),
+            Err(e) => {
+                warn!("Failed to load mined block attempts: {:?}", &e);
+                HttpResponseType::ServerError(
+                    response_metadata,
+                    "Failed to load mined block attempts".to_string(),
+                )
+            }
+        };
+        response.send(http, fd).map(|_| ())
+    }
+
     /// Handle a GET on a smart contract's data map, given the current chain tip.  Optionally
     /// supplies a MARF proof for the value.
     fn handle_get_map_entry<W: Write>(
@@ -1111,6 +1630,104 @@ impl ConversationHttp {
         response.send(http, fd).map(|_| ())
     }
 
+    /// Handle a POST to look up several accounts and data map entries at once, given the current
+    /// chain tip.  Optionally supplies a single MARF multi-proof for all of the MARF keys read.
+    fn handle_get_batch_entries<W: Write>(
+        http: &mut StacksHttp,
+        fd: &mut W,
+        req: &HttpRequestType,
+        sortdb: &SortitionDB,
+        chainstate: &mut StacksChainState,
+        tip: &StacksBlockId,
+        accounts: &[PrincipalData],
+        map_entries: &[(StacksAddress, ContractName, ClarityName, Value)],
+        with_proof: bool,
+    ) -> Result<(), net_error> {
+        let response_metadata = HttpResponseMetadata::from(req);
+
+        let response =
+            match chainstate.maybe_read_only_clarity_tx(&sortdb.index_conn(), tip, |clarity_tx| {
+                clarity_tx.with_clarity_db_readonly(|clarity_db| {
+                    let burn_block_height = clarity_db.get_current_burnchain_block_height() as u64;
+                    let mut proofs = vec![];
+
+                    let mut account_entries = Vec::with_capacity(accounts.len());
+                    for account in accounts.iter() {
+                        let key = ClarityDatabase::make_key_for_account_balance(account);
+                        let (balance, balance_proof) = clarity_db
+                            .get_with_proof::<STXBalance>(&key)
+                            .map(|(a, b)| (a, Some(b)))
+                            .unwrap_or_else(|| (STXBalance::zero(), None));
+                        proofs.push(balance_proof);
+
+                        let key = ClarityDatabase::make_key_for_account_nonce(account);
+                        let (nonce, nonce_proof) = clarity_db
+                            .get_with_proof(&key)
+                            .map(|(a, b)| (a, Some(b)))
+                            .unwrap_or_else(|| (0, None));
+                        proofs.push(nonce_proof);
+
+                        let unlocked =
+                            balance.get_available_balance_at_burn_block(burn_block_height);
+                        let (locked, unlock_height) =
+                            balance.get_locked_balance_at_burn_block(burn_block_height);
+
+                        account_entries.push(AccountEntryResponse {
+                            balance: format!("0x{}", to_hex(&unlocked.to_be_bytes())),
+                            locked: format!("0x{}", to_hex(&locked.to_be_bytes())),
+                            unlock_height,
+                            nonce,
+                            balance_proof: None,
+                            nonce_proof: None,
+                        });
+                    }
+
+                    let mut map_entry_data = Vec::with_capacity(map_entries.len());
+                    for (contract_addr, contract_name, map_name, key) in map_entries.iter() {
+                        let contract_identifier = QualifiedContractIdentifier::new(
+                            contract_addr.clone().into(),
+                            contract_name.clone(),
+                        );
+                        let key = ClarityDatabase::make_key_for_data_map_entry(
+                            &contract_identifier,
+                            map_name,
+                            key,
+                        );
+                        let (value, marf_proof) = clarity_db
+                            .get_with_proof::<Value>(&key)
+                            .map(|(a, b)| (a, Some(b)))
+                            .unwrap_or_else(|| (Value::none(), None));
+                        proofs.push(marf_proof);
+
+                        map_entry_data.push(MapEntryResponse {
+                            data: format!("0x{}", value.serialize()),
+                            marf_proof: None,
+                        });
+                    }
+
+                    let marf_proof = if with_proof {
+                        let multi_proof = TrieMerkleMultiProof::from_proofs(&proofs);
+                        Some(format!("0x{}", multi_proof.to_hex()))
+                    } else {
+                        None
+                    };
+
+                    BatchEntriesResponse {
+                        accounts: account_entries,
+                        map_entries: map_entry_data,
+                        marf_proof,
+                    }
+                })
+            }) {
+                Ok(Some(data)) => HttpResponseType::GetBatchEntries(response_metadata, data),
+                Ok(None) | Err(_) => {
+                    HttpResponseType::NotFound(response_metadata, "Chain tip not found".into())
+                }
+            };
+
+        response.send(http, fd).map(|_| ())
+    }
+
     /// Handle a POST to run a read-only function call with the given parameters on the given chain
     /// tip.  Returns the result of the function call.  Returns a CallReadOnlyResponse on success.
     fn handle_readonly_function_call<W: Write>(
@@ -1409,34 +2026,184 @@ impl ConversationHttp {
         return response.send(http, fd).map(|_| ());
     }
 
-    /// Load up the canonical Stacks chain tip.  Note that this is subject to both burn chain block
-    /// Stacks block availability -- different nodes with different partial replicas of the Stacks chain state
-    /// will return different values here.
-    /// tip_opt is given by the HTTP request as the optional query parameter for the chain tip
-    /// hash.  It will be None if there was no paramter given.
-    /// The order of chain tips this method prefers is as follows:
-    /// * tip_opt, if it's Some(..),
-    /// * the unconfirmed canonical stacks chain tip, if initialized
-    /// * the confirmed canonical stacks chain tip
-    fn handle_load_stacks_chain_tip<W: Write>(
+    /// Handle a GET for the header of the block at the given height in the canonical fork.
+    /// The response will be synchronously written to the fd.
+    fn handle_getheader<W: Write>(
         http: &mut StacksHttp,
         fd: &mut W,
         req: &HttpRequestType,
-        tip_opt: Option<&StacksBlockId>,
         sortdb: &SortitionDB,
         chainstate: &StacksChainState,
-    ) -> Result<Option<StacksBlockId>, net_error> {
-        match tip_opt {
-            Some(tip) => Ok(Some(*tip).clone()),
-            None => match chainstate.get_stacks_chain_tip(sortdb)? {
-                Some(tip) => Ok(Some(StacksBlockHeader::make_index_block_hash(
-                    &tip.consensus_hash,
-                    &tip.anchored_block_hash,
-                ))),
-                None => {
-                    let response_metadata = HttpResponseMetadata::from(req);
-                    warn!("Failed to load Stacks chain tip");
-                    let response = HttpResponseType::ServerError(
+        height: u64,
+    ) -> Result<(), net_error> {
+        let response_metadata = HttpResponseMetadata::from(req);
+        let response = match chainstate
+            .get_canonical_block_header_at_height(sortdb, height)
+            .map_err(net_error::from)
+            .and_then(|header_info_opt| match header_info_opt {
+                Some(header_info) => RPCHeaderInfoData::from_db(chainstate, &header_info).map(Some),
+                None => Ok(None),
+            }) {
+            Ok(Some(header_data)) => HttpResponseType::HeaderInfo(response_metadata, header_data),
+            Ok(None) => HttpResponseType::NotFound(
+                response_metadata,
+                format!("No canonical Stacks block at height {}", height),
+            ),
+            Err(e) => {
+                warn!("Failed to load header at height {}: {:?}", height, &e);
+                HttpResponseType::ServerError(
+                    response_metadata,
+                    format!("Failed to load header at height {}", height),
+                )
+            }
+        };
+        response.send(http, fd).map(|_| ())
+    }
+
+    /// Handle a GET for the header of the block with the given index block hash.
+    /// The response will be synchronously written to the fd.
+    fn handle_getblock_header<W: Write>(
+        http: &mut StacksHttp,
+        fd: &mut W,
+        req: &HttpRequestType,
+        chainstate: &StacksChainState,
+        index_block_hash: &StacksBlockId,
+    ) -> Result<(), net_error> {
+        let response_metadata = HttpResponseMetadata::from(req);
+        let response = match StacksChainState::get_stacks_block_header_info_by_index_block_hash(
+            chainstate.db(),
+            index_block_hash,
+        )
+        .map_err(net_error::from)
+        .and_then(|header_info_opt| match header_info_opt {
+            Some(header_info) => RPCHeaderInfoData::from_db(chainstate, &header_info).map(Some),
+            None => Ok(None),
+        }) {
+            Ok(Some(header_data)) => HttpResponseType::HeaderInfo(response_metadata, header_data),
+            Ok(None) => HttpResponseType::NotFound(
+                response_metadata,
+                format!("No such block {}", index_block_hash),
+            ),
+            Err(e) => {
+                warn!("Failed to load header of {}: {:?}", index_block_hash, &e);
+                HttpResponseType::ServerError(
+                    response_metadata,
+                    format!("Failed to load header of {}", index_block_hash),
+                )
+            }
+        };
+        response.send(http, fd).map(|_| ())
+    }
+
+    /// Handle a GET for the microblock streams stored for the given anchored block.
+    /// The response will be synchronously written to the fd.
+    fn handle_getmicroblock_streams<W: Write>(
+        http: &mut StacksHttp,
+        fd: &mut W,
+        req: &HttpRequestType,
+        sortdb: &SortitionDB,
+        chainstate: &mut StacksChainState,
+        index_block_hash: &StacksBlockId,
+    ) -> Result<(), net_error> {
+        let response_metadata = HttpResponseMetadata::from(req);
+        let response = match RPCMicroblockStreamsData::from_db(sortdb, chainstate, index_block_hash)
+        {
+            Ok(Some(streams)) => HttpResponseType::MicroblockStreams(response_metadata, streams),
+            Ok(None) => HttpResponseType::NotFound(
+                response_metadata,
+                format!("No such block {}", index_block_hash),
+            ),
+            Err(e) => {
+                warn!(
+                    "Failed to load microblock streams of {}: {:?}",
+                    index_block_hash, &e
+                );
+                HttpResponseType::ServerError(
+                    response_metadata,
+                    format!("Failed to load microblock streams of {}", index_block_hash),
+                )
+            }
+        };
+        response.send(http, fd).map(|_| ())
+    }
+
+    /// Handle a GET for a page of block headers, starting at the given block and walking back
+    /// through its ancestors.
+    /// The response will be synchronously written to the fd.
+    fn handle_getblock_headers<W: Write>(
+        http: &mut StacksHttp,
+        fd: &mut W,
+        req: &HttpRequestType,
+        chainstate: &StacksChainState,
+        tip: &StacksBlockId,
+        limit: u32,
+    ) -> Result<(), net_error> {
+        let response_metadata = HttpResponseMetadata::from(req);
+        let response =
+            match StacksChainState::get_ancestor_block_headers(chainstate.db(), tip, limit)
+                .map_err(net_error::from)
+                .and_then(|headers| {
+                    headers
+                        .iter()
+                        .map(|header_info| RPCHeaderInfoData::from_db(chainstate, header_info))
+                        .collect::<Result<Vec<_>, _>>()
+                }) {
+                Ok(ref headers) if headers.len() == 0 => {
+                    HttpResponseType::NotFound(response_metadata, format!("No such block {}", tip))
+                }
+                Ok(headers) => HttpResponseType::BlockHeaders(response_metadata, headers),
+                Err(e) => {
+                    warn!("Failed to load headers from {}: {:?}", tip, &e);
+                    HttpResponseType::ServerError(
+                        response_metadata,
+                        format!("Failed to load headers from {}", tip),
+                    )
+                }
+            };
+        response.send(http, fd).map(|_| ())
+    }
+
+    /// Load up the canonical Stacks chain tip.  Note that this is subject to both burn chain block
+    /// Stacks block availability -- different nodes with different partial replicas of the Stacks chain state
+    /// will return different values here.
+    /// tip_opt is given by the HTTP request as the optional query parameter for the chain tip
+    /// hash or height.  It will be None if there was no paramter given.
+    /// The order of chain tips this method prefers is as follows:
+    /// * tip_opt, if it's Some(..) -- a height is resolved against the canonical fork,
+    /// * the unconfirmed canonical stacks chain tip, if initialized
+    /// * the confirmed canonical stacks chain tip
+    fn handle_load_stacks_chain_tip<W: Write>(
+        http: &mut StacksHttp,
+        fd: &mut W,
+        req: &HttpRequestType,
+        tip_opt: Option<&TipRequest>,
+        sortdb: &SortitionDB,
+        chainstate: &StacksChainState,
+    ) -> Result<Option<StacksBlockId>, net_error> {
+        match tip_opt {
+            Some(TipRequest::SpecificTip(tip)) => Ok(Some(*tip).clone()),
+            Some(TipRequest::AtHeight(height)) => {
+                match chainstate.get_canonical_block_header_at_height(sortdb, *height)? {
+                    Some(header_info) => Ok(Some(header_info.index_block_hash())),
+                    None => {
+                        let response_metadata = HttpResponseMetadata::from(req);
+                        let response = HttpResponseType::NotFound(
+                            response_metadata,
+                            format!("No canonical Stacks block at height {}", height),
+                        );
+                        response.send(http, fd).and_then(|_| Ok(None))
+                    }
+                }
+            }
+            None => match chainstate.get_stacks_chain_tip(sortdb)? {
+                Some(tip) => Ok(Some(StacksBlockHeader::make_index_block_hash(
+                    &tip.consensus_hash,
+                    &tip.anchored_block_hash,
+                ))),
+                None => {
+                    let response_metadata = HttpResponseMetadata::from(req);
+                    warn!("Failed to load Stacks chain tip");
+                    let response = HttpResponseType::ServerError(
                         response_metadata,
                         format!("Failed to load Stacks chain tip"),
                     );
@@ -1450,17 +2217,28 @@ impl ConversationHttp {
         http: &mut StacksHttp,
         fd: &mut W,
         req: &HttpRequestType,
-        tip_opt: Option<&StacksBlockId>,
+        tip_opt: Option<&TipRequest>,
         sortdb: &SortitionDB,
         chainstate: &StacksChainState,
     ) -> Result<Option<(ConsensusHash, BlockHeaderHash)>, net_error> {
         match tip_opt {
-            Some(tip) => match chainstate.get_block_header_hashes(&tip)? {
+            Some(TipRequest::SpecificTip(tip)) => match chainstate.get_block_header_hashes(&tip)? {
                 Some((ch, bl)) => {
                     return Ok(Some((ch, bl)));
                 }
                 None => {}
             },
+            Some(TipRequest::AtHeight(height)) => {
+                match chainstate.get_canonical_block_header_at_height(sortdb, *height)? {
+                    Some(header_info) => {
+                        return Ok(Some((
+                            header_info.consensus_hash,
+                            header_info.anchored_header.block_hash(),
+                        )));
+                    }
+                    None => {}
+                }
+            }
             None => match chainstate.get_stacks_chain_tip(sortdb)? {
                 Some(tip) => {
                     return Ok(Some((tip.consensus_hash, tip.anchored_block_hash)));
@@ -1492,6 +2270,7 @@ impl ConversationHttp {
         tx: StacksTransaction,
         atlasdb: &mut AtlasDB,
         attachment: Option<Attachment>,
+        event_observer: Option<&dyn MemPoolEventDispatcher>,
     ) -> Result<bool, net_error> {
         let txid = tx.txid();
         let response_metadata = HttpResponseMetadata::from(req);
@@ -1501,7 +2280,13 @@ impl ConversationHttp {
                 false,
             )
         } else {
-            match mempool.submit(chainstate, &consensus_hash, &block_hash, &tx) {
+            match mempool.submit(
+                chainstate,
+                &consensus_hash,
+                &block_hash,
+                &tx,
+                event_observer,
+            ) {
                 Ok(_) => (
                     HttpResponseType::TransactionID(response_metadata, txid),
                     true,
@@ -1601,6 +2386,24 @@ impl ConversationHttp {
         let keep_alive = req.metadata().keep_alive;
         let mut ret = None;
 
+        if self.upload_quota_exhausted {
+            debug!(
+                "{:?}: Bandwidth quota exceeded; throttling HTTP request from {:?}",
+                &self, &self.peer_addr
+            );
+            let response_metadata = HttpResponseMetadata::from(&req);
+            let response = HttpResponseType::Error(
+                response_metadata,
+                429,
+                "Bandwidth quota exceeded".to_string(),
+            );
+            response
+                .send(&mut self.connection.protocol, &mut reply)
+                .map(|_| ())?;
+            self.reply_streams.push_back((reply, None, keep_alive));
+            return Ok(None);
+        }
+
         let stream_opt = match req {
             HttpRequestType::GetInfo(ref _md) => {
                 ConversationHttp::handle_getinfo(
@@ -1657,6 +2460,47 @@ impl ConversationHttp {
                     chainstate,
                 )?
             }
+            HttpRequestType::GetMicroblockStreams(ref _md, ref index_block_hash) => {
+                ConversationHttp::handle_getmicroblock_streams(
+                    &mut self.connection.protocol,
+                    &mut reply,
+                    &req,
+                    sortdb,
+                    chainstate,
+                    index_block_hash,
+                )?;
+                None
+            }
+            HttpRequestType::GetBlockHeader(ref _md, ref index_block_hash) => {
+                ConversationHttp::handle_getblock_header(
+                    &mut self.connection.protocol,
+                    &mut reply,
+                    &req,
+                    chainstate,
+                    index_block_hash,
+                )?;
+                None
+            }
+            HttpRequestType::GetBlockHeaders(ref _md, ref tip_opt, ref limit) => {
+                if let Some(tip) = ConversationHttp::handle_load_stacks_chain_tip(
+                    &mut self.connection.protocol,
+                    &mut reply,
+                    &req,
+                    tip_opt.as_ref(),
+                    sortdb,
+                    chainstate,
+                )? {
+                    ConversationHttp::handle_getblock_headers(
+                        &mut self.connection.protocol,
+                        &mut reply,
+                        &req,
+                        chainstate,
+                        &tip,
+                        *limit,
+                    )?;
+                }
+                None
+            }
             HttpRequestType::GetMicroblocksIndexed(ref _md, ref index_head_hash) => {
                 ConversationHttp::handle_getmicroblocks_indexed(
                     &mut self.connection.protocol,
@@ -1687,6 +2531,58 @@ impl ConversationHttp {
                 *min_seq,
                 chainstate,
             )?,
+            HttpRequestType::GetMempoolTxs(ref _md, ref address, offset, limit) => {
+                ConversationHttp::handle_get_mempool_txs(
+                    &mut self.connection.protocol,
+                    &mut reply,
+                    &req,
+                    mempool,
+                    address,
+                    offset,
+                    limit,
+                )?;
+                None
+            }
+            HttpRequestType::GetMempoolNonce(ref _md, ref address, ref tip_opt) => {
+                if let Some(tip) = ConversationHttp::handle_load_stacks_chain_tip(
+                    &mut self.connection.protocol,
+                    &mut reply,
+                    &req,
+                    tip_opt.as_ref(),
+                    sortdb,
+                    chainstate,
+                )? {
+                    ConversationHttp::handle_get_mempool_nonce(
+                        &mut self.connection.protocol,
+                        &mut reply,
+                        &req,
+                        sortdb,
+                        chainstate,
+                        &tip,
+                        mempool,
+                        address,
+                    )?;
+                }
+                None
+            }
+            HttpRequestType::GetMempoolFeeHistogram(ref _md) => {
+                ConversationHttp::handle_get_mempool_fees(
+                    &mut self.connection.protocol,
+                    &mut reply,
+                    &req,
+                    mempool,
+                )?;
+                None
+            }
+            HttpRequestType::GetMinerAttempts(ref _md) => {
+                ConversationHttp::handle_get_miner_attempts(
+                    &mut self.connection.protocol,
+                    &mut reply,
+                    &req,
+                    chainstate,
+                )?;
+                None
+            }
             HttpRequestType::GetTransactionUnconfirmed(ref _md, ref txid) => {
                 ConversationHttp::handle_gettransaction_unconfirmed(
                     &mut self.connection.protocol,
@@ -1698,6 +2594,17 @@ impl ConversationHttp {
                 )?;
                 None
             }
+            HttpRequestType::GetHeaderAtHeight(ref _md, ref height) => {
+                ConversationHttp::handle_getheader(
+                    &mut self.connection.protocol,
+                    &mut reply,
+                    &req,
+                    sortdb,
+                    chainstate,
+                    *height,
+                )?;
+                None
+            }
             HttpRequestType::GetAccount(ref _md, ref principal, ref tip_opt, ref with_proof) => {
                 if let Some(tip) = ConversationHttp::handle_load_stacks_chain_tip(
                     &mut self.connection.protocol,
@@ -1753,6 +2660,35 @@ impl ConversationHttp {
                 }
                 None
             }
+            HttpRequestType::GetBatchEntries(
+                ref _md,
+                ref accounts,
+                ref map_entries,
+                ref tip_opt,
+                ref with_proof,
+            ) => {
+                if let Some(tip) = ConversationHttp::handle_load_stacks_chain_tip(
+                    &mut self.connection.protocol,
+                    &mut reply,
+                    &req,
+                    tip_opt.as_ref(),
+                    sortdb,
+                    chainstate,
+                )? {
+                    ConversationHttp::handle_get_batch_entries(
+                        &mut self.connection.protocol,
+                        &mut reply,
+                        &req,
+                        sortdb,
+                        chainstate,
+                        &tip,
+                        accounts,
+                        map_entries,
+                        *with_proof,
+                    )?;
+                }
+                None
+            }
             HttpRequestType::GetTransferCost(ref _md) => {
                 ConversationHttp::handle_token_transfer_cost(
                     &mut self.connection.protocol,
@@ -1865,6 +2801,7 @@ impl ConversationHttp {
                             tx.clone(),
                             atlasdb,
                             attachment.clone(),
+                            handler_opts.event_observer,
                         )?;
                         if accepted {
                             // forward to peer network
@@ -2214,6 +3151,11 @@ impl ConversationHttp {
         self.connection_time
     }
 
+    /// How many bytes have we sent and received on this conversation?
+    pub fn get_bandwidth(&self) -> &BandwidthAccount {
+        &self.bandwidth
+    }
+
     /// Make progress on in-flight requests and replies.
     /// Returns the list of transactions we'll need to forward to the peer network
     pub fn chat(
@@ -2307,6 +3249,8 @@ impl ConversationHttp {
             total_recv += nrecv;
             if nrecv > 0 {
                 self.last_request_timestamp = get_epoch_time_secs();
+                self.bandwidth.add_rx(None, nrecv as u64);
+                monitoring::increment_http_bytes_received_counter(nrecv as u64);
             } else {
                 break;
             }
@@ -2336,6 +3280,8 @@ impl ConversationHttp {
             total_sz += sz;
             if sz > 0 {
                 self.last_response_timestamp = get_epoch_time_secs();
+                self.bandwidth.add_tx(None, sz as u64);
+                monitoring::increment_http_bytes_sent_counter(sz as u64);
             } else {
                 break;
             }
@@ -2349,7 +3295,7 @@ impl ConversationHttp {
     }
 
     /// Make a new getinfo request to this endpoint
-    pub fn new_getpoxinfo(&self, tip_opt: Option<StacksBlockId>) -> HttpRequestType {
+    pub fn new_getpoxinfo(&self, tip_opt: Option<TipRequest>) -> HttpRequestType {
         HttpRequestType::GetPoxInfo(
             HttpRequestMetadata::from_host(self.peer_host.clone()),
             tip_opt,
@@ -2369,6 +3315,32 @@ impl ConversationHttp {
         )
     }
 
+    /// Make a new request for a block's header
+    pub fn new_getblock_header(&self, index_block_hash: StacksBlockId) -> HttpRequestType {
+        HttpRequestType::GetBlockHeader(
+            HttpRequestMetadata::from_host(self.peer_host.clone()),
+            index_block_hash,
+        )
+    }
+
+    /// Make a new request for the microblock streams an anchored block produced
+    pub fn new_getmicroblock_streams(&self, index_block_hash: StacksBlockId) -> HttpRequestType {
+        HttpRequestType::GetMicroblockStreams(
+            HttpRequestMetadata::from_host(self.peer_host.clone()),
+            index_block_hash,
+        )
+    }
+
+    /// Make a new request for a page of block headers, starting at the given tip (or the
+    /// canonical chain tip) and walking back through its ancestors
+    pub fn new_getblock_headers(&self, tip_opt: Option<TipRequest>, limit: u32) -> HttpRequestType {
+        HttpRequestType::GetBlockHeaders(
+            HttpRequestMetadata::from_host(self.peer_host.clone()),
+            tip_opt,
+            limit,
+        )
+    }
+
     /// Make a new get-microblocks request to this endpoint
     pub fn new_getmicroblocks_indexed(
         &self,
@@ -2412,51 +3384,100 @@ impl ConversationHttp {
         )
     }
 
-    /// Make a new post-transaction request
-    pub fn new_post_transaction(&self, tx: StacksTransaction) -> HttpRequestType {
-        HttpRequestType::PostTransaction(
+    /// Make a new request for the header of the canonical block at a given height
+    pub fn new_getheader(&self, height: u64) -> HttpRequestType {
+        HttpRequestType::GetHeaderAtHeight(
             HttpRequestMetadata::from_host(self.peer_host.clone()),
-            tx,
-            None,
+            height,
         )
     }
 
-    /// Make a new post-microblock request
-    pub fn new_post_microblock(
+    /// Make a new request for a page of the pending transactions an address originates or
+    /// sponsors
+    pub fn new_get_mempool_txs(
         &self,
-        mblock: StacksMicroblock,
-        tip_opt: Option<StacksBlockId>,
+        address: StacksAddress,
+        offset: u64,
+        limit: u32,
     ) -> HttpRequestType {
-        HttpRequestType::PostMicroblock(
+        HttpRequestType::GetMempoolTxs(
             HttpRequestMetadata::from_host(self.peer_host.clone()),
-            mblock,
-            tip_opt,
+            address,
+            offset,
+            limit,
         )
     }
 
-    /// Make a new request for an account
-    pub fn new_getaccount(
+    /// Make a new request for the next nonce an address can use
+    pub fn new_get_mempool_nonce(
         &self,
-        principal: PrincipalData,
-        tip_opt: Option<StacksBlockId>,
-        with_proof: bool,
+        address: StacksAddress,
+        tip_opt: Option<TipRequest>,
     ) -> HttpRequestType {
-        HttpRequestType::GetAccount(
+        HttpRequestType::GetMempoolNonce(
             HttpRequestMetadata::from_host(self.peer_host.clone()),
-            principal,
+            address,
             tip_opt,
-            with_proof,
         )
     }
 
-    /// Make a new request for a data map
-    pub fn new_getmapentry(
+    /// Make a new request for a histogram of the mempool's fee rates
+    pub fn new_get_mempool_fees(&self) -> HttpRequestType {
+        HttpRequestType::GetMempoolFeeHistogram(HttpRequestMetadata::from_host(
+            self.peer_host.clone(),
+        ))
+    }
+
+    /// Make a new request for this node's recent mined block attempts
+    pub fn new_get_miner_attempts(&self) -> HttpRequestType {
+        HttpRequestType::GetMinerAttempts(HttpRequestMetadata::from_host(self.peer_host.clone()))
+    }
+
+    /// Make a new post-transaction request
+    pub fn new_post_transaction(&self, tx: StacksTransaction) -> HttpRequestType {
+        HttpRequestType::PostTransaction(
+            HttpRequestMetadata::from_host(self.peer_host.clone()),
+            tx,
+            None,
+        )
+    }
+
+    /// Make a new post-microblock request
+    pub fn new_post_microblock(
+        &self,
+        mblock: StacksMicroblock,
+        tip_opt: Option<TipRequest>,
+    ) -> HttpRequestType {
+        HttpRequestType::PostMicroblock(
+            HttpRequestMetadata::from_host(self.peer_host.clone()),
+            mblock,
+            tip_opt,
+        )
+    }
+
+    /// Make a new request for an account
+    pub fn new_getaccount(
+        &self,
+        principal: PrincipalData,
+        tip_opt: Option<TipRequest>,
+        with_proof: bool,
+    ) -> HttpRequestType {
+        HttpRequestType::GetAccount(
+            HttpRequestMetadata::from_host(self.peer_host.clone()),
+            principal,
+            tip_opt,
+            with_proof,
+        )
+    }
+
+    /// Make a new request for a data map
+    pub fn new_getmapentry(
         &self,
         contract_addr: StacksAddress,
         contract_name: ContractName,
         map_name: ClarityName,
         key: Value,
-        tip_opt: Option<StacksBlockId>,
+        tip_opt: Option<TipRequest>,
         with_proof: bool,
     ) -> HttpRequestType {
         HttpRequestType::GetMapEntry(
@@ -2470,12 +3491,29 @@ impl ConversationHttp {
         )
     }
 
+    /// Make a new request for several accounts and data map entries at once
+    pub fn new_getbatchentries(
+        &self,
+        accounts: Vec<PrincipalData>,
+        map_entries: Vec<(StacksAddress, ContractName, ClarityName, Value)>,
+        tip_opt: Option<TipRequest>,
+        with_proof: bool,
+    ) -> HttpRequestType {
+        HttpRequestType::GetBatchEntries(
+            HttpRequestMetadata::from_host(self.peer_host.clone()),
+            accounts,
+            map_entries,
+            tip_opt,
+            with_proof,
+        )
+    }
+
     /// Make a new request to get a contract's source
     pub fn new_getcontractsrc(
         &self,
         contract_addr: StacksAddress,
         contract_name: ContractName,
-        tip_opt: Option<StacksBlockId>,
+        tip_opt: Option<TipRequest>,
         with_proof: bool,
     ) -> HttpRequestType {
         HttpRequestType::GetContractSrc(
@@ -2492,7 +3530,7 @@ impl ConversationHttp {
         &self,
         contract_addr: StacksAddress,
         contract_name: ContractName,
-        tip_opt: Option<StacksBlockId>,
+        tip_opt: Option<TipRequest>,
     ) -> HttpRequestType {
         HttpRequestType::GetContractABI(
             HttpRequestMetadata::from_host(self.peer_host.clone()),
@@ -2510,7 +3548,7 @@ impl ConversationHttp {
         sender: PrincipalData,
         function_name: ClarityName,
         function_args: Vec<Value>,
-        tip_opt: Option<StacksBlockId>,
+        tip_opt: Option<TipRequest>,
     ) -> HttpRequestType {
         HttpRequestType::CallReadOnlyFunction(
             HttpRequestMetadata::from_host(self.peer_host.clone()),
@@ -2526,7 +3564,7 @@ impl ConversationHttp {
     /// Make a new request for attachment inventory page
     pub fn new_getattachmentsinv(
         &self,
-        tip_opt: Option<StacksBlockId>,
+        tip_opt: Option<TipRequest>,
         pages_indexes: HashSet<u32>,
     ) -> HttpRequestType {
         HttpRequestType::GetAttachmentsInv(
@@ -2557,10 +3595,12 @@ mod test {
     use chainstate::stacks::db::blocks::test::*;
     use chainstate::stacks::db::BlockStreamData;
     use chainstate::stacks::db::StacksChainState;
+    use chainstate::stacks::miner::test::make_user_stacks_transfer;
     use chainstate::stacks::miner::*;
     use chainstate::stacks::test::*;
     use chainstate::stacks::Error as chain_error;
     use chainstate::stacks::*;
+    use core::miner_history::*;
 
     use address::*;
 
@@ -3861,7 +4901,7 @@ mod test {
                     StacksAddress::from_string("ST2DS4MSWSGJ3W9FBC6BVT0Y92S345HY8N3T6AV7R")
                         .unwrap(),
                     "hello-world".try_into().unwrap(),
-                    Some(unconfirmed_tip),
+                    Some(TipRequest::SpecificTip(unconfirmed_tip)),
                     false,
                 )
             },
@@ -3920,6 +4960,661 @@ mod test {
         );
     }
 
+    #[test]
+    fn test_rpc_get_account_at_height() {
+        test_rpc(
+            "test_rpc_get_account_at_height",
+            40200,
+            40201,
+            50200,
+            50201,
+            |ref mut peer_client,
+             ref mut convo_client,
+             ref mut peer_server,
+             ref mut convo_server| {
+                // before the block with the coinbase and contract was mined
+                convo_client.new_getaccount(
+                    StacksAddress::from_string("ST2DS4MSWSGJ3W9FBC6BVT0Y92S345HY8N3T6AV7R")
+                        .unwrap()
+                        .to_account_principal(),
+                    Some(TipRequest::AtHeight(0)),
+                    false,
+                )
+            },
+            |ref http_request, ref http_response, ref mut peer_client, ref mut peer_server| {
+                let req_md = http_request.metadata().clone();
+                match http_response {
+                    HttpResponseType::GetAccount(response_md, data) => {
+                        assert_eq!(data.nonce, 0);
+                        true
+                    }
+                    _ => {
+                        error!("Invalid response; {:?}", &http_response);
+                        false
+                    }
+                }
+            },
+        );
+    }
+
+    #[test]
+    fn test_rpc_get_account_above_tip_height() {
+        test_rpc(
+            "test_rpc_get_account_above_tip_height",
+            40210,
+            40211,
+            50210,
+            50211,
+            |ref mut peer_client,
+             ref mut convo_client,
+             ref mut peer_server,
+             ref mut convo_server| {
+                convo_client.new_getaccount(
+                    StacksAddress::from_string("ST2DS4MSWSGJ3W9FBC6BVT0Y92S345HY8N3T6AV7R")
+                        .unwrap()
+                        .to_account_principal(),
+                    Some(TipRequest::AtHeight(100)),
+                    false,
+                )
+            },
+            |ref http_request, ref http_response, ref mut peer_client, ref mut peer_server| {
+                let req_md = http_request.metadata().clone();
+                match http_response {
+                    HttpResponseType::NotFound(response_md, msg) => {
+                        assert_eq!(msg, "No canonical Stacks block at height 100");
+                        true
+                    }
+                    _ => {
+                        error!("Invalid response; {:?}", &http_response);
+                        false
+                    }
+                }
+            },
+        );
+    }
+
+    #[test]
+    fn test_rpc_get_header_at_height() {
+        test_rpc(
+            "test_rpc_get_header_at_height",
+            40220,
+            40221,
+            50220,
+            50221,
+            |ref mut peer_client,
+             ref mut convo_client,
+             ref mut peer_server,
+             ref mut convo_server| convo_client.new_getheader(1),
+            |ref http_request, ref http_response, ref mut peer_client, ref mut peer_server| {
+                let req_md = http_request.metadata().clone();
+                let sortdb = peer_server.sortdb.take().unwrap();
+                let tip = peer_server
+                    .chainstate()
+                    .get_stacks_chain_tip(&sortdb)
+                    .unwrap()
+                    .unwrap();
+                peer_server.sortdb = Some(sortdb);
+
+                match http_response {
+                    HttpResponseType::HeaderInfo(response_md, data) => {
+                        assert_eq!(data.block_height, 1);
+                        assert_eq!(data.consensus_hash, tip.consensus_hash);
+                        assert_eq!(data.block_hash, tip.anchored_block_hash);
+                        assert_eq!(
+                            data.index_block_hash,
+                            StacksBlockHeader::make_index_block_hash(
+                                &tip.consensus_hash,
+                                &tip.anchored_block_hash
+                            )
+                        );
+
+                        let header = StacksBlockHeader::consensus_deserialize(
+                            &mut &hex_bytes(&data.header).unwrap()[..],
+                        )
+                        .unwrap();
+                        assert_eq!(header.block_hash(), data.block_hash);
+                        assert_eq!(header.parent_block, data.parent_block_hash);
+                        true
+                    }
+                    _ => {
+                        error!("Invalid response; {:?}", &http_response);
+                        false
+                    }
+                }
+            },
+        );
+    }
+
+    #[test]
+    fn test_rpc_get_header_above_tip_height() {
+        test_rpc(
+            "test_rpc_get_header_above_tip_height",
+            40230,
+            40231,
+            50230,
+            50231,
+            |ref mut peer_client,
+             ref mut convo_client,
+             ref mut peer_server,
+             ref mut convo_server| convo_client.new_getheader(2),
+            |ref http_request, ref http_response, ref mut peer_client, ref mut peer_server| {
+                let req_md = http_request.metadata().clone();
+                match http_response {
+                    HttpResponseType::NotFound(response_md, msg) => {
+                        assert_eq!(msg, "No canonical Stacks block at height 2");
+                        true
+                    }
+                    _ => {
+                        error!("Invalid response; {:?}", &http_response);
+                        false
+                    }
+                }
+            },
+        );
+    }
+
+    #[test]
+    fn test_rpc_get_block_headers() {
+        test_rpc(
+            "test_rpc_get_block_headers",
+            40240,
+            40241,
+            50240,
+            50241,
+            |ref mut peer_client,
+             ref mut convo_client,
+             ref mut peer_server,
+             ref mut convo_server| convo_client.new_getblock_headers(None, 10),
+            |ref http_request, ref http_response, ref mut peer_client, ref mut peer_server| {
+                let req_md = http_request.metadata().clone();
+                let sortdb = peer_server.sortdb.take().unwrap();
+                let tip = peer_server
+                    .chainstate()
+                    .get_stacks_chain_tip(&sortdb)
+                    .unwrap()
+                    .unwrap();
+                peer_server.sortdb = Some(sortdb);
+
+                match http_response {
+                    HttpResponseType::BlockHeaders(response_md, headers) => {
+                        // the block with the coinbase and contract, and the boot block
+                        assert_eq!(headers.len(), 2);
+                        assert_eq!(headers[0].block_height, 1);
+                        assert_eq!(headers[0].block_hash, tip.anchored_block_hash);
+                        assert_eq!(headers[0].tx_count, Some(2));
+                        assert!(headers[0].miner.is_some());
+                        assert_eq!(headers[1].block_height, 0);
+                        assert_eq!(headers[1].miner, None);
+                        assert_eq!(
+                            headers[0].parent_index_block_hash,
+                            headers[1].index_block_hash
+                        );
+                        true
+                    }
+                    _ => {
+                        error!("Invalid response; {:?}", &http_response);
+                        false
+                    }
+                }
+            },
+        );
+    }
+
+    #[test]
+    fn test_rpc_get_block_header() {
+        test_rpc(
+            "test_rpc_get_block_header",
+            40250,
+            40251,
+            50250,
+            50251,
+            |ref mut peer_client,
+             ref mut convo_client,
+             ref mut peer_server,
+             ref mut convo_server| {
+                let sortdb = peer_server.sortdb.take().unwrap();
+                let tip = peer_server
+                    .chainstate()
+                    .get_stacks_chain_tip(&sortdb)
+                    .unwrap()
+                    .unwrap();
+                peer_server.sortdb = Some(sortdb);
+                convo_client.new_getblock_header(StacksBlockHeader::make_index_block_hash(
+                    &tip.consensus_hash,
+                    &tip.anchored_block_hash,
+                ))
+            },
+            |ref http_request, ref http_response, ref mut peer_client, ref mut peer_server| {
+                let req_md = http_request.metadata().clone();
+                match (http_request, http_response) {
+                    (
+                        HttpRequestType::GetBlockHeader(_, index_block_hash),
+                        HttpResponseType::HeaderInfo(response_md, data),
+                    ) => {
+                        assert_eq!(data.index_block_hash, *index_block_hash);
+                        assert_eq!(data.block_height, 1);
+                        assert_eq!(data.tx_count, Some(2));
+                        assert!(data.execution_cost.runtime > 0);
+                        true
+                    }
+                    _ => {
+                        error!("Invalid response; {:?}", &http_response);
+                        false
+                    }
+                }
+            },
+        );
+    }
+
+    #[test]
+    fn test_rpc_get_microblock_streams() {
+        test_rpc(
+            "test_rpc_get_microblock_streams",
+            40260,
+            40261,
+            50260,
+            50261,
+            |ref mut peer_client,
+             ref mut convo_client,
+             ref mut peer_server,
+             ref mut convo_server| {
+                let sortdb = peer_server.sortdb.take().unwrap();
+                let tip = peer_server
+                    .chainstate()
+                    .get_stacks_chain_tip(&sortdb)
+                    .unwrap()
+                    .unwrap();
+                peer_server.sortdb = Some(sortdb);
+                convo_client.new_getmicroblock_streams(StacksBlockHeader::make_index_block_hash(
+                    &tip.consensus_hash,
+                    &tip.anchored_block_hash,
+                ))
+            },
+            |ref http_request, ref http_response, ref mut peer_client, ref mut peer_server| {
+                let req_md = http_request.metadata().clone();
+                match (http_request, http_response) {
+                    (
+                        HttpRequestType::GetMicroblockStreams(_, index_block_hash),
+                        HttpResponseType::MicroblockStreams(response_md, data),
+                    ) => {
+                        assert_eq!(data.index_block_hash, *index_block_hash);
+                        assert_eq!(data.block_height, 1);
+                        assert!(data.canonical);
+
+                        // the one-microblock stream is still unconfirmed
+                        assert_eq!(data.confirmed_tail, None);
+                        assert_eq!(data.confirmed_sequence, None);
+                        assert_eq!(data.streams.len(), 1);
+                        assert_eq!(data.streams[0].microblocks.len(), 1);
+                        assert_eq!(data.streams[0].microblocks[0].sequence, 0);
+                        assert!(!data.streams[0].confirmed);
+                        assert_eq!(data.equivocations.len(), 0);
+                        assert_eq!(data.poison_report, None);
+                        true
+                    }
+                    _ => {
+                        error!("Invalid response; {:?}", &http_response);
+                        false
+                    }
+                }
+            },
+        );
+    }
+
+    #[test]
+    fn test_rpc_get_microblock_streams_not_found() {
+        test_rpc(
+            "test_rpc_get_microblock_streams_not_found",
+            40270,
+            40271,
+            50270,
+            50271,
+            |ref mut peer_client,
+             ref mut convo_client,
+             ref mut peer_server,
+             ref mut convo_server| {
+                convo_client.new_getmicroblock_streams(StacksBlockId([0x22; 32]))
+            },
+            |ref http_request, ref http_response, ref mut peer_client, ref mut peer_server| {
+                let req_md = http_request.metadata().clone();
+                match http_response {
+                    HttpResponseType::NotFound(_, msg) => true,
+                    _ => {
+                        error!("Invalid response; {:?}", &http_response);
+                        false
+                    }
+                }
+            },
+        );
+    }
+
+    /// Put a chain of two token transfers from STVN97YYA10MY5F6KQJHKNYJNM24C4A1AT39WRW into the
+    /// server's mempool, with nonces 0 and 1.
+    fn make_mempool_tx_chain(peer_server: &mut TestPeer) -> StacksAddress {
+        let privk = StacksPrivateKey::from_hex(
+            "94c319327cc5cd04da7147d32d836eb2e4c44f4db39aa5ede7314a761183d0c701",
+        )
+        .unwrap();
+        let addr = StacksAddress::from_string("STVN97YYA10MY5F6KQJHKNYJNM24C4A1AT39WRW").unwrap();
+        let recipient = StacksAddress::from_string("ST2DS4MSWSGJ3W9FBC6BVT0Y92S345HY8N3T6AV7R")
+            .unwrap()
+            .to_account_principal();
+
+        let (consensus_hash, block_hash) = SortitionDB::get_canonical_stacks_chain_tip_hash(
+            peer_server.sortdb.as_ref().unwrap().conn(),
+        )
+        .unwrap();
+
+        let mut mempool = peer_server.mempool.take().unwrap();
+        for (nonce, tx_fee) in [(0, 1000), (1, 4000)].iter() {
+            let tx = make_user_stacks_transfer(&privk, *nonce, *tx_fee, &recipient, 123);
+            let mut tx_bytes = vec![];
+            tx.consensus_serialize(&mut tx_bytes).unwrap();
+            mempool
+                .submit_raw(
+                    peer_server.chainstate(),
+                    &consensus_hash,
+                    &block_hash,
+                    tx_bytes,
+                )
+                .unwrap();
+        }
+        peer_server.mempool = Some(mempool);
+        addr
+    }
+
+    #[test]
+    #[ignore]
+    fn test_rpc_get_mempool_txs() {
+        test_rpc(
+            "test_rpc_get_mempool_txs",
+            40280,
+            40281,
+            50280,
+            50281,
+            |ref mut peer_client,
+             ref mut convo_client,
+             ref mut peer_server,
+             ref mut convo_server| {
+                let addr = make_mempool_tx_chain(peer_server);
+                convo_client.new_get_mempool_txs(addr, 0, MEMPOOL_TXS_PAGE_MAX)
+            },
+            |ref http_request, ref http_response, ref mut peer_client, ref mut peer_server| {
+                let req_md = http_request.metadata().clone();
+                match http_response {
+                    HttpResponseType::MempoolTxs(_, txs) => {
+                        assert_eq!(txs.len(), 2);
+                        for (i, tx) in txs.iter().enumerate() {
+                            assert_eq!(
+                                tx.origin_address,
+                                "STVN97YYA10MY5F6KQJHKNYJNM24C4A1AT39WRW"
+                            );
+                            assert_eq!(tx.origin_nonce, i as u64);
+                        }
+                        assert_eq!(txs[0].tx_fee, 1000);
+                        assert_eq!(txs[1].tx_fee, 4000);
+                        true
+                    }
+                    _ => {
+                        error!("Invalid response; {:?}", &http_response);
+                        false
+                    }
+                }
+            },
+        );
+    }
+
+    #[test]
+    #[ignore]
+    fn test_rpc_get_mempool_nonce() {
+        test_rpc(
+            "test_rpc_get_mempool_nonce",
+            40290,
+            40291,
+            50290,
+            50291,
+            |ref mut peer_client,
+             ref mut convo_client,
+             ref mut peer_server,
+             ref mut convo_server| {
+                let addr = make_mempool_tx_chain(peer_server);
+                convo_client.new_get_mempool_nonce(addr, None)
+            },
+            |ref http_request, ref http_response, ref mut peer_client, ref mut peer_server| {
+                let req_md = http_request.metadata().clone();
+                match http_response {
+                    HttpResponseType::MempoolNonce(_, data) => {
+                        assert_eq!(data.address, "STVN97YYA10MY5F6KQJHKNYJNM24C4A1AT39WRW");
+                        assert_eq!(data.confirmed_nonce, 0);
+                        assert_eq!(data.next_nonce, 2);
+                        assert_eq!(data.mempool_next_nonce, 2);
+                        true
+                    }
+                    _ => {
+                        error!("Invalid response; {:?}", &http_response);
+                        false
+                    }
+                }
+            },
+        );
+    }
+
+    #[test]
+    #[ignore]
+    fn test_rpc_get_mempool_fees() {
+        test_rpc(
+            "test_rpc_get_mempool_fees",
+            40300,
+            40301,
+            50300,
+            50301,
+            |ref mut peer_client,
+             ref mut convo_client,
+             ref mut peer_server,
+             ref mut convo_server| {
+                make_mempool_tx_chain(peer_server);
+                convo_client.new_get_mempool_fees()
+            },
+            |ref http_request, ref http_response, ref mut peer_client, ref mut peer_server| {
+                let req_md = http_request.metadata().clone();
+                match http_response {
+                    HttpResponseType::MempoolFeeHistogram(_, histogram) => {
+                        assert_eq!(histogram.tx_count, 2);
+                        let bucket_count: u64 =
+                            histogram.buckets.iter().map(|bucket| bucket.tx_count).sum();
+                        assert_eq!(bucket_count, 2);
+                        for bucket in histogram.buckets.iter() {
+                            assert!(bucket.min_fee_rate <= bucket.max_fee_rate);
+                        }
+                        for pair in histogram.buckets.windows(2) {
+                            assert!(pair[0].max_fee_rate < pair[1].min_fee_rate);
+                        }
+                        true
+                    }
+                    _ => {
+                        error!("Invalid response; {:?}", &http_response);
+                        false
+                    }
+                }
+            },
+        );
+    }
+
+    #[test]
+    #[ignore]
+    fn test_rpc_get_miner_attempts() {
+        test_rpc(
+            "test_rpc_get_miner_attempts",
+            40310,
+            40311,
+            50310,
+            50311,
+            |ref mut peer_client,
+             ref mut convo_client,
+             ref mut peer_server,
+             ref mut convo_server| {
+                let chainstate_path = peer_server.chainstate().root_path.clone();
+                let mut history = MinerHistoryDB::open(&chainstate_path, true).unwrap();
+                for i in 0..3 {
+                    let attempt = MinedBlockAttempt {
+                        attempt_id: 0,
+                        burn_block_height: 100 + i,
+                        burn_header_hash: BurnchainHeaderHash([i as u8; 32]),
+                        parent_consensus_hash: ConsensusHash([0x01; 20]),
+                        parent_block_hash: BlockHeaderHash([0x02; 32]),
+                        block_hash: BlockHeaderHash([0x10 + i as u8; 32]),
+                        stacks_block_height: 10 + i,
+                        attempt: 1,
+                        num_txs: 1,
+                        num_considered: 1,
+                        skipped_tx_counts: vec![("ExceedsBudget".to_string(), 1)]
+                            .into_iter()
+                            .collect(),
+                        skipped_txs: vec![SkippedTx {
+                            txid: Txid([0x20 + i as u8; 32]),
+                            reason: TxSkipReason::ExceedsBudget,
+                        }],
+                        execution_cost: ExecutionCost::zero(),
+                        block_size: 200,
+                        total_fees: 0,
+                        burn_fee: 1000,
+                        outcome: MinedBlockOutcome::Pending,
+                        attempt_time: 1000 + i,
+                    };
+                    history.insert_attempt(&attempt).unwrap();
+                }
+                history
+                    .resolve_sortition(102, Some(&BlockHeaderHash([0x11; 32])))
+                    .unwrap();
+                convo_client.new_get_miner_attempts()
+            },
+            |ref http_request, ref http_response, ref mut peer_client, ref mut peer_server| {
+                let req_md = http_request.metadata().clone();
+                match http_response {
+                    HttpResponseType::MinerAttempts(_, data) => {
+                        assert_eq!(
+                            data.outcomes,
+                            MinedBlockOutcomeCounts {
+                                won: 1,
+                                lost: 1,
+                                pending: 1
+                            }
+                        );
+                        assert_eq!(data.attempts.len(), 3);
+                        assert_eq!(data.attempts[0].burn_block_height, 102);
+                        assert_eq!(data.attempts[0].outcome, MinedBlockOutcome::Pending);
+                        assert_eq!(data.attempts[1].outcome, MinedBlockOutcome::Won);
+                        assert_eq!(
+                            data.attempts[2].skipped_txs[0].reason,
+                            TxSkipReason::ExceedsBudget
+                        );
+                        assert_eq!(
+                            data.attempts[2].skipped_tx_counts.get("ExceedsBudget"),
+                            Some(&1)
+                        );
+                        true
+                    }
+                    _ => {
+                        error!("Invalid response; {:?}", &http_response);
+                        false
+                    }
+                }
+            },
+        );
+    }
+
+    fn make_staging_microblock(
+        hash: u8,
+        parent_hash: u8,
+        sequence: u16,
+        orphaned: bool,
+    ) -> StagingMicroblock {
+        StagingMicroblock {
+            consensus_hash: ConsensusHash([0x01; 20]),
+            anchored_block_hash: BlockHeaderHash([0x02; 32]),
+            microblock_hash: BlockHeaderHash([hash; 32]),
+            parent_hash: BlockHeaderHash([parent_hash; 32]),
+            sequence,
+            processed: !orphaned,
+            orphaned,
+            block_data: vec![],
+        }
+    }
+
+    #[test]
+    fn test_make_microblock_streams() {
+        // no microblocks at all
+        let (streams, equivocations) = RPCMicroblockStreamsData::make_streams(&[], None);
+        assert_eq!(streams.len(), 0);
+        assert_eq!(equivocations.len(), 0);
+
+        // 0x02 is the anchored block.
+        // The miner forked its stream at sequence 1, and again at sequence 2 of one of the forks:
+        //
+        //   0x10 -+- 0x11 --- 0x12
+        //         |
+        //         +- 0x21 -+- 0x22
+        //                  |
+        //                  +- 0x32
+        let microblocks = vec![
+            make_staging_microblock(0x10, 0x02, 0, false),
+            make_staging_microblock(0x11, 0x10, 1, false),
+            make_staging_microblock(0x21, 0x10, 1, true),
+            make_staging_microblock(0x12, 0x11, 2, false),
+            make_staging_microblock(0x22, 0x21, 2, true),
+            make_staging_microblock(0x32, 0x21, 2, true),
+        ];
+
+        let (streams, equivocations) = RPCMicroblockStreamsData::make_streams(
+            &microblocks,
+            Some(&BlockHeaderHash([0x11; 32])),
+        );
+
+        let stream_hashes: Vec<Vec<u8>> = streams
+            .iter()
+            .map(|stream| {
+                stream
+                    .microblocks
+                    .iter()
+                    .map(|mblock| mblock.microblock_hash.0[0])
+                    .collect()
+            })
+            .collect();
+        assert_eq!(
+            stream_hashes,
+            vec![
+                vec![0x10, 0x11, 0x12],
+                vec![0x10, 0x21, 0x22],
+                vec![0x10, 0x21, 0x32]
+            ]
+        );
+
+        // only the stream through the confirmed tail is confirmed
+        let confirmed: Vec<bool> = streams.iter().map(|stream| stream.confirmed).collect();
+        assert_eq!(confirmed, vec![true, false, false]);
+
+        assert_eq!(
+            equivocations,
+            vec![
+                RPCMicroblockEquivocation {
+                    sequence: 1,
+                    microblock_hashes: vec![
+                        BlockHeaderHash([0x11; 32]),
+                        BlockHeaderHash([0x21; 32])
+                    ],
+                },
+                RPCMicroblockEquivocation {
+                    sequence: 2,
+                    microblock_hashes: vec![
+                        BlockHeaderHash([0x12; 32]),
+                        BlockHeaderHash([0x22; 32]),
+                        BlockHeaderHash([0x32; 32])
+                    ],
+                },
+            ]
+        );
+    }
+
     #[test]
     #[ignore]
     fn test_rpc_get_account_unconfirmed() {
@@ -3944,7 +5639,7 @@ mod test {
                     StacksAddress::from_string("ST2DS4MSWSGJ3W9FBC6BVT0Y92S345HY8N3T6AV7R")
                         .unwrap()
                         .to_account_principal(),
-                    Some(unconfirmed_tip),
+                    Some(TipRequest::SpecificTip(unconfirmed_tip)),
                     false,
                 )
             },
@@ -4019,6 +5714,90 @@ mod test {
         );
     }
 
+    #[test]
+    fn test_rpc_get_batch_entries() {
+        test_rpc(
+            "test_rpc_get_batch_entries",
+            40190,
+            40191,
+            50190,
+            50191,
+            |ref mut peer_client,
+             ref mut convo_client,
+             ref mut peer_server,
+             ref mut convo_server| {
+                let principal =
+                    StacksAddress::from_string("ST2DS4MSWSGJ3W9FBC6BVT0Y92S345HY8N3T6AV7R")
+                        .unwrap()
+                        .to_account_principal();
+                let empty_principal = StacksAddress {
+                    version: C32_ADDRESS_VERSION_TESTNET_SINGLESIG,
+                    bytes: Hash160([0xee; 20]),
+                }
+                .to_account_principal();
+                convo_client.new_getbatchentries(
+                    vec![principal.clone(), empty_principal],
+                    vec![(
+                        StacksAddress::from_string("ST2DS4MSWSGJ3W9FBC6BVT0Y92S345HY8N3T6AV7R")
+                            .unwrap(),
+                        "hello-world".try_into().unwrap(),
+                        "unit-map".try_into().unwrap(),
+                        Value::Tuple(
+                            TupleData::from_data(vec![(
+                                "account".into(),
+                                Value::Principal(principal),
+                            )])
+                            .unwrap(),
+                        ),
+                    )],
+                    None,
+                    true,
+                )
+            },
+            |ref http_request, ref http_response, ref mut peer_client, ref mut peer_server| {
+                let req_md = http_request.metadata().clone();
+                match http_response {
+                    HttpResponseType::GetBatchEntries(response_md, data) => {
+                        assert_eq!(data.accounts.len(), 2);
+                        assert_eq!(data.accounts[0].nonce, 2);
+                        let balance =
+                            u128::from_str_radix(&data.accounts[0].balance[2..], 16).unwrap();
+                        assert_eq!(balance, 1000000000);
+                        assert_eq!(data.accounts[1].nonce, 0);
+                        assert!(data.accounts[0].balance_proof.is_none());
+
+                        assert_eq!(data.map_entries.len(), 1);
+                        assert_eq!(
+                            Value::try_deserialize_hex_untyped(&data.map_entries[0].data).unwrap(),
+                            Value::some(Value::Tuple(
+                                TupleData::from_data(vec![("units".into(), Value::Int(123))])
+                                    .unwrap()
+                            ))
+                            .unwrap()
+                        );
+
+                        // one proof per MARF key, and none for the account that doesn't exist
+                        let proof_hex = data.marf_proof.as_ref().unwrap();
+                        let multi_proof: TrieMerkleMultiProof<StacksBlockId> =
+                            TrieMerkleMultiProof::consensus_deserialize(
+                                &mut &hex_bytes(&proof_hex[2..]).unwrap()[..],
+                            )
+                            .unwrap();
+                        assert_eq!(multi_proof.proofs.len(), 5);
+                        for i in 0..5 {
+                            assert_eq!(multi_proof.get_proof(i).is_some(), i != 2 && i != 3);
+                        }
+                        true
+                    }
+                    _ => {
+                        error!("Invalid response; {:?}", &http_response);
+                        false
+                    }
+                }
+            },
+        );
+    }
+
     #[test]
     #[ignore]
     fn test_rpc_get_map_entry_unconfirmed() {
@@ -4052,7 +5831,7 @@ mod test {
                         TupleData::from_data(vec![("account".into(), Value::Principal(principal))])
                             .unwrap(),
                     ),
-                    Some(unconfirmed_tip),
+                    Some(TipRequest::SpecificTip(unconfirmed_tip)),
                     false,
                 )
             },
@@ -4139,7 +5918,7 @@ mod test {
                     StacksAddress::from_string("ST2DS4MSWSGJ3W9FBC6BVT0Y92S345HY8N3T6AV7R")
                         .unwrap(),
                     "hello-world-unconfirmed".try_into().unwrap(),
-                    Some(unconfirmed_tip),
+                    Some(TipRequest::SpecificTip(unconfirmed_tip)),
                 )
             },
             |ref http_request, ref http_response, ref mut peer_client, ref mut peer_server| {
@@ -4228,7 +6007,7 @@ mod test {
                         .to_account_principal(),
                     "ro-test".try_into().unwrap(),
                     vec![],
-                    Some(unconfirmed_tip),
+                    Some(TipRequest::SpecificTip(unconfirmed_tip)),
                 )
             },
             |ref http_request, ref http_response, ref mut peer_client, ref mut peer_server| {
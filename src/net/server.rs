@@ -47,8 +47,6 @@ use chainstate::stacks::db::StacksChainState;
 use burnchains::Burnchain;
 use burnchains::BurnchainView;
 
-use mio::net as mio_net;
-
 use util::get_epoch_time_secs;
 
 use core::mempool::*;
@@ -60,13 +58,13 @@ pub struct HttpPeer {
 
     // ongoing http conversations (either they reached out to us, or we to them)
     pub peers: HashMap<usize, ConversationHttp>,
-    pub sockets: HashMap<usize, mio_net::TcpStream>,
+    pub sockets: HashMap<usize, NetworkSocket>,
 
     // outbound connections that are pending connection
     pub connecting: HashMap<
         usize,
         (
-            mio_net::TcpStream,
+            NetworkSocket,
             Option<UrlString>,
             Option<HttpRequestType>,
             u64,
@@ -81,6 +79,13 @@ pub struct HttpPeer {
 
     // connection options
     pub connection_opts: ConnectionOptions,
+
+    // bandwidth used by HTTP conversations that have since closed, in total and per remote host
+    retired_bandwidth: BandwidthAccount,
+    client_bandwidth: HashMap<PeerAddress, BandwidthAccount>,
+
+    // set by the PeerNetwork when this node has used up its upload quota
+    pub upload_quota_exhausted: bool,
 }
 
 impl HttpPeer {
@@ -91,6 +96,7 @@ impl HttpPeer {
         conn_opts: ConnectionOptions,
         server_handle: usize,
     ) -> HttpPeer {
+        let retired_bandwidth = BandwidthAccount::new(conn_opts.bandwidth_quota_period);
         HttpPeer {
             network_id: network_id,
             chain_view: chain_view,
@@ -102,6 +108,10 @@ impl HttpPeer {
 
             burnchain: burnchain,
             connection_opts: conn_opts,
+
+            retired_bandwidth: retired_bandwidth,
+            client_bandwidth: HashMap::new(),
+            upload_quota_exhausted: false,
         }
     }
 
@@ -130,10 +140,7 @@ impl HttpPeer {
     pub fn get_conversation_and_socket(
         &mut self,
         event_id: usize,
-    ) -> (
-        Option<&mut ConversationHttp>,
-        Option<&mut mio::net::TcpStream>,
-    ) {
+    ) -> (Option<&mut ConversationHttp>, Option<&mut NetworkSocket>) {
         (
             self.peers.get_mut(&event_id),
             self.sockets.get_mut(&event_id),
@@ -161,7 +168,7 @@ impl HttpPeer {
             return Err(net_error::AlreadyConnected(event_id, http_nk));
         }
 
-        let sock = NetworkState::connect(&addr)?;
+        let sock = network_state.connect_socket(&addr)?;
         let hint_event_id = network_state.next_event_id()?;
         let next_event_id =
             network_state.register(self.http_server_handle, hint_event_id, &sock)?;
@@ -228,7 +235,7 @@ impl HttpPeer {
         network_state: &mut NetworkState,
         chainstate: &mut StacksChainState,
         event_id: usize,
-        mut socket: mio_net::TcpStream,
+        mut socket: NetworkSocket,
         outbound_url: Option<UrlString>,
         initial_request: Option<HttpRequestType>,
     ) -> Result<(), net_error> {
@@ -297,7 +304,9 @@ impl HttpPeer {
 
     /// Deregister a socket/event pair
     pub fn deregister_http(&mut self, network_state: &mut NetworkState, event_id: usize) -> () {
-        self.peers.remove(&event_id);
+        if let Some(convo) = self.peers.remove(&event_id) {
+            self.retire_bandwidth(&convo);
+        }
 
         match self.sockets.remove(&event_id) {
             None => {}
@@ -313,6 +322,61 @@ impl HttpPeer {
         }
     }
 
+    /// Remember the bandwidth a closing conversation used
+    fn retire_bandwidth(&mut self, convo: &ConversationHttp) -> () {
+        let bandwidth_quota_period = self.connection_opts.bandwidth_quota_period;
+        self.retired_bandwidth.merge(convo.get_bandwidth());
+        self.client_bandwidth
+            .entry(PeerAddress::from_socketaddr(convo.get_peer_addr()))
+            .or_insert_with(|| BandwidthAccount::new(bandwidth_quota_period))
+            .merge(convo.get_bandwidth());
+
+        // forget hosts that haven't been active this period
+        self.client_bandwidth
+            .retain(|_, account| account.is_current());
+    }
+
+    /// Get the bandwidth used by all HTTP conversations, past and present
+    pub fn get_bandwidth_account(&self) -> BandwidthAccount {
+        let mut account = self.retired_bandwidth.clone();
+        for (_, convo) in self.peers.iter() {
+            account.merge(convo.get_bandwidth());
+        }
+        account
+    }
+
+    /// Get the bandwidth used by all HTTP conversations with a given remote host, past and present
+    pub fn get_client_bandwidth_account(&self, addrbytes: &PeerAddress) -> BandwidthAccount {
+        let mut account = self
+            .client_bandwidth
+            .get(addrbytes)
+            .cloned()
+            .unwrap_or_else(|| BandwidthAccount::new(self.connection_opts.bandwidth_quota_period));
+        for (_, convo) in self.peers.iter() {
+            if PeerAddress::from_socketaddr(convo.get_peer_addr()) == *addrbytes {
+                account.merge(convo.get_bandwidth());
+            }
+        }
+        account
+    }
+
+    /// Should we refuse to serve requests from this remote host?
+    fn is_upload_throttled(&self, addrbytes: &PeerAddress) -> bool {
+        if self.upload_quota_exhausted {
+            return true;
+        }
+        if self.connection_opts.max_peer_upload_per_period == 0
+            && self.connection_opts.max_peer_upload_bandwidth == 0
+        {
+            return false;
+        }
+        self.get_client_bandwidth_account(addrbytes)
+            .exceeds_upload_quota(
+                self.connection_opts.max_peer_upload_per_period,
+                self.connection_opts.max_peer_upload_bandwidth,
+            )
+    }
+
     /// Remove slow/unresponsive peers
     fn disconnect_unresponsive(&mut self, network_state: &mut NetworkState) -> () {
         let now = get_epoch_time_secs();
@@ -354,7 +418,7 @@ impl HttpPeer {
     /// Saturate a conversation's socket -- either sends the whole request, or fills the socket
     /// buffer.
     pub fn saturate_http_socket(
-        client_sock: &mut mio::net::TcpStream,
+        client_sock: &mut NetworkSocket,
         convo: &mut ConversationHttp,
         chainstate: &mut StacksChainState,
     ) -> Result<(), net_error> {
@@ -438,7 +502,7 @@ impl HttpPeer {
         chainstate: &mut StacksChainState,
         mempool: &mut MemPoolDB,
         event_id: usize,
-        client_sock: &mut mio_net::TcpStream,
+        client_sock: &mut NetworkSocket,
         convo: &mut ConversationHttp,
         handler_args: &RPCHandlerArgs,
     ) -> Result<(bool, Vec<StacksMessageType>), net_error> {
@@ -602,6 +666,13 @@ impl HttpPeer {
                 continue;
             }
 
+            let upload_throttled = match self.peers.get(event_id) {
+                Some(convo) => {
+                    self.is_upload_throttled(&PeerAddress::from_socketaddr(convo.get_peer_addr()))
+                }
+                None => false,
+            };
+
             let client_sock_opt = self.sockets.get_mut(&event_id);
             if client_sock_opt.is_none() {
                 test_debug!("No such socket event {}", event_id);
@@ -614,6 +685,7 @@ impl HttpPeer {
                 Some(ref mut convo) => {
                     // activity on a http socket
                     test_debug!("Process HTTP data from {:?}", convo);
+                    convo.upload_quota_exhausted = upload_throttled;
                     match HttpPeer::process_http_conversation(
                         &self.chain_view,
                         peers,
@@ -0,0 +1,1226 @@
+// Copyright (C) 2013-2020 Blockstack PBC, a public benefit corporation
+// Copyright (C) 2020 Stacks Open Internet Foundation
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <http://www.gnu.org/licenses/>.
+
+// In-memory network simulator for tests.
+//
+// A `SimNetwork` stands in for the operating system's TCP stack.  Each `NetworkState` created
+// with `NetworkState::new_sim()` is a node in the simulated network: its listening sockets,
+// outbound connections, and poll events all live in memory.  Nothing happens in the simulated
+// network until the test advances its virtual clock, at which point segments whose delivery
+// time has come are delivered.  Segments can be delayed (latency and jitter), lost and
+// retransmitted (packet loss), or held back indefinitely (partitions).  Byte streams are
+// never reordered or corrupted, just as with TCP.
+//
+// All randomness is drawn from a seeded RNG, and all state is visited in a fixed order, so a
+// simulation run is reproducible from its seed.  The simulated network also drives the
+// thread's virtual wall-clock time (see `util::set_virtual_time_ms()`), so all timeouts in the
+// peer network are measured in simulated time.  A simulation must be driven from a single
+// thread.
+
+use std::collections::BTreeMap;
+use std::collections::BTreeSet;
+use std::collections::HashMap;
+use std::collections::VecDeque;
+use std::fmt;
+use std::io;
+use std::io::ErrorKind;
+use std::io::Read;
+use std::io::Write;
+use std::net::IpAddr;
+use std::net::Ipv4Addr;
+use std::net::Ipv6Addr;
+use std::net::Shutdown;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::sync::Mutex;
+
+use rand::Rng;
+use rand::SeedableRng;
+use rand_chacha::ChaCha20Rng;
+
+use net::Error as net_error;
+
+use util::set_virtual_time_ms;
+
+/// Virtual time at which every simulation starts (in milliseconds since the epoch)
+pub const SIM_START_TIME_MS: u128 = 1_600_000_000_000;
+
+/// Default one-way latency between two nodes
+pub const SIM_DEFAULT_LATENCY_MS: u64 = 50;
+
+/// Minimum time before a lost segment gets retransmitted
+pub const SIM_MIN_RETRANSMIT_MS: u64 = 200;
+
+/// First ephemeral port handed out to outbound connections
+const SIM_FIRST_EPHEMERAL_PORT: u16 = 40000;
+
+#[derive(Debug, Clone, PartialEq)]
+enum SimSegmentKind {
+    /// connection request to a listening address
+    Syn(SocketAddr),
+    /// connection accepted
+    SynAck,
+    /// stream data
+    Data(Vec<u8>),
+    /// end of stream
+    Fin,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct SimSegment {
+    deliver_at: u128,
+    kind: SimSegmentKind,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum SimSocketStatus {
+    Connecting,
+    Connected,
+    Refused,
+}
+
+#[derive(Debug)]
+struct SimSocketState {
+    node: usize,
+    local_addr: SocketAddr,
+    peer_addr: Option<SocketAddr>,
+    /// ID of the socket on the other end, once known
+    peer: Option<u64>,
+    status: SimSocketStatus,
+    /// if the connection gets refused, this is when the sender finds out
+    refused_at: Option<u128>,
+    /// bytes delivered to us, but not yet read
+    recv_buf: VecDeque<u8>,
+    remote_closed: bool,
+    local_closed: bool,
+    /// segments sent by us, but not yet delivered
+    outbox: VecDeque<SimSegment>,
+}
+
+struct SimNetworkState {
+    now_ms: u128,
+    seed: u64,
+    rng: ChaCha20Rng,
+    latency_ms: u64,
+    jitter_ms: u64,
+    link_latency_ms: HashMap<(usize, usize), u64>,
+    loss_rate: f64,
+    /// if set, maps each node to its partition.  Nodes in different partitions can't talk.
+    partitions: Option<HashMap<usize, usize>>,
+    node_ips: Vec<IpAddr>,
+    listeners: HashMap<SocketAddr, usize>,
+    sockets: BTreeMap<u64, SimSocketState>,
+    next_socket_id: u64,
+    next_port: u16,
+    /// per-node queue of newly-accepted sockets (and the addresses they were accepted on)
+    accepted: HashMap<usize, Vec<(SocketAddr, u64)>>,
+    /// per-node set of sockets that have become readable or writeable since the last poll
+    ready: HashMap<usize, BTreeSet<u64>>,
+    segments_sent: u64,
+    segments_lost: u64,
+}
+
+impl SimNetworkState {
+    fn latency(&mut self, node_1: usize, node_2: usize) -> u64 {
+        let key = if node_1 < node_2 {
+            (node_1, node_2)
+        } else {
+            (node_2, node_1)
+        };
+        let latency = *self.link_latency_ms.get(&key).unwrap_or(&self.latency_ms);
+        if self.jitter_ms > 0 {
+            latency + self.rng.gen_range(0, self.jitter_ms + 1)
+        } else {
+            latency
+        }
+    }
+
+    fn retransmit_timeout(&mut self, node_1: usize, node_2: usize) -> u64 {
+        let rtt = 2 * self.latency(node_1, node_2);
+        if rtt > SIM_MIN_RETRANSMIT_MS {
+            rtt
+        } else {
+            SIM_MIN_RETRANSMIT_MS
+        }
+    }
+
+    fn is_reachable(&self, node_1: usize, node_2: usize) -> bool {
+        match self.partitions {
+            None => true,
+            Some(ref partitions) => partitions.get(&node_1) == partitions.get(&node_2),
+        }
+    }
+
+    /// Find the node listening on `addr`, as well as the address it bound (which may be the
+    /// wildcard address for `addr`'s port).
+    fn find_listener(&self, addr: &SocketAddr) -> Option<(SocketAddr, usize)> {
+        if let Some(node) = self.listeners.get(addr) {
+            return Some((addr.clone(), *node));
+        }
+        let wildcard_ip = match addr.ip() {
+            IpAddr::V4(_) => IpAddr::V4(Ipv4Addr::UNSPECIFIED),
+            IpAddr::V6(_) => IpAddr::V6(Ipv6Addr::UNSPECIFIED),
+        };
+        let wildcard = SocketAddr::new(wildcard_ip, addr.port());
+        self.listeners.get(&wildcard).map(|node| (wildcard, *node))
+    }
+
+    fn mark_ready(&mut self, socket_id: u64) -> () {
+        let node = match self.sockets.get(&socket_id) {
+            Some(sock) => sock.node,
+            None => {
+                return;
+            }
+        };
+        self.ready
+            .entry(node)
+            .or_insert_with(BTreeSet::new)
+            .insert(socket_id);
+    }
+
+    fn new_socket(
+        &mut self,
+        node: usize,
+        local_addr: SocketAddr,
+        peer_addr: Option<SocketAddr>,
+        status: SimSocketStatus,
+    ) -> u64 {
+        let socket_id = self.next_socket_id;
+        self.next_socket_id += 1;
+        self.sockets.insert(
+            socket_id,
+            SimSocketState {
+                node: node,
+                local_addr: local_addr,
+                peer_addr: peer_addr,
+                peer: None,
+                status: status,
+                refused_at: None,
+                recv_buf: VecDeque::new(),
+                remote_closed: false,
+                local_closed: false,
+                outbox: VecDeque::new(),
+            },
+        );
+        socket_id
+    }
+
+    /// Queue up a segment on a socket, to be delivered after the link's latency.  Segments on
+    /// the same socket are always delivered in order.
+    fn send_segment(&mut self, socket_id: u64, dest_node: usize, kind: SimSegmentKind) -> () {
+        let node = match self.sockets.get(&socket_id) {
+            Some(sock) => sock.node,
+            None => {
+                return;
+            }
+        };
+        let latency = self.latency(node, dest_node);
+        let now_ms = self.now_ms;
+        if let Some(sock) = self.sockets.get_mut(&socket_id) {
+            let earliest = sock.outbox.back().map(|seg| seg.deliver_at).unwrap_or(0);
+            let deliver_at = now_ms + (latency as u128);
+            sock.outbox.push_back(SimSegment {
+                deliver_at: if deliver_at > earliest {
+                    deliver_at
+                } else {
+                    earliest
+                },
+                kind: kind,
+            });
+        }
+        self.segments_sent += 1;
+    }
+
+    /// Which node is the head segment of this socket's outbox going to?
+    fn dest_node(&self, socket_id: u64) -> Option<usize> {
+        let sock = self.sockets.get(&socket_id)?;
+        match sock.outbox.front()?.kind {
+            SimSegmentKind::Syn(ref addr) => self.find_listener(addr).map(|(_, node)| node),
+            _ => sock
+                .peer
+                .and_then(|peer_id| self.sockets.get(&peer_id))
+                .map(|peer| peer.node),
+        }
+    }
+
+    fn connect(&mut self, node: usize, addr: &SocketAddr) -> u64 {
+        let port = self.next_port;
+        self.next_port = self.next_port.wrapping_add(1);
+        if self.next_port < SIM_FIRST_EPHEMERAL_PORT {
+            self.next_port = SIM_FIRST_EPHEMERAL_PORT;
+        }
+
+        let local_addr = SocketAddr::new(self.node_ips[node].clone(), port);
+        let socket_id = self.new_socket(node, local_addr, None, SimSocketStatus::Connecting);
+
+        // if nothing is listening, the connection will get refused by the (unreachable) host.
+        let dest_node = self
+            .find_listener(addr)
+            .map(|(_, node)| node)
+            .unwrap_or(node);
+        self.send_segment(socket_id, dest_node, SimSegmentKind::Syn(addr.clone()));
+        socket_id
+    }
+
+    fn write(&mut self, socket_id: u64, buf: &[u8]) -> io::Result<usize> {
+        let (status, local_closed, dest_node) = match self.sockets.get(&socket_id) {
+            Some(sock) => (
+                sock.status,
+                sock.local_closed,
+                sock.peer
+                    .and_then(|peer_id| self.sockets.get(&peer_id))
+                    .map(|peer| peer.node),
+            ),
+            None => {
+                return Err(io::Error::from(ErrorKind::NotConnected));
+            }
+        };
+        if local_closed {
+            return Err(io::Error::from(ErrorKind::BrokenPipe));
+        }
+        match status {
+            SimSocketStatus::Connecting => Err(io::Error::from(ErrorKind::WouldBlock)),
+            SimSocketStatus::Refused => Err(io::Error::from(ErrorKind::ConnectionRefused)),
+            SimSocketStatus::Connected => match dest_node {
+                Some(dest_node) => {
+                    if buf.len() > 0 {
+                        self.send_segment(socket_id, dest_node, SimSegmentKind::Data(buf.to_vec()));
+                    }
+                    Ok(buf.len())
+                }
+                None => {
+                    // the other end is gone
+                    Err(io::Error::from(ErrorKind::ConnectionReset))
+                }
+            },
+        }
+    }
+
+    fn read(&mut self, socket_id: u64, buf: &mut [u8]) -> io::Result<usize> {
+        let sock = match self.sockets.get_mut(&socket_id) {
+            Some(sock) => sock,
+            None => {
+                return Err(io::Error::from(ErrorKind::NotConnected));
+            }
+        };
+        if sock.recv_buf.len() > 0 {
+            let mut nr = 0;
+            while nr < buf.len() {
+                match sock.recv_buf.pop_front() {
+                    Some(byte) => {
+                        buf[nr] = byte;
+                        nr += 1;
+                    }
+                    None => {
+                        break;
+                    }
+                }
+            }
+            return Ok(nr);
+        }
+        match sock.status {
+            SimSocketStatus::Refused => Err(io::Error::from(ErrorKind::ConnectionRefused)),
+            SimSocketStatus::Connecting => Err(io::Error::from(ErrorKind::WouldBlock)),
+            SimSocketStatus::Connected => {
+                if sock.remote_closed || sock.local_closed {
+                    Ok(0)
+                } else {
+                    Err(io::Error::from(ErrorKind::WouldBlock))
+                }
+            }
+        }
+    }
+
+    fn close(&mut self, socket_id: u64) -> () {
+        let (status, local_closed, dest_node) = match self.sockets.get(&socket_id) {
+            Some(sock) => (
+                sock.status,
+                sock.local_closed,
+                sock.peer
+                    .and_then(|peer_id| self.sockets.get(&peer_id))
+                    .map(|peer| peer.node),
+            ),
+            None => {
+                return;
+            }
+        };
+        if local_closed {
+            return;
+        }
+        if let Some(sock) = self.sockets.get_mut(&socket_id) {
+            sock.local_closed = true;
+        }
+        match (status, dest_node) {
+            (SimSocketStatus::Connected, Some(dest_node)) => {
+                self.send_segment(socket_id, dest_node, SimSegmentKind::Fin);
+            }
+            _ => {
+                // never connected, or the other end is gone already
+                self.sockets.remove(&socket_id);
+            }
+        }
+    }
+
+    fn deliver_segment(&mut self, socket_id: u64, segment: SimSegment) -> () {
+        let (node, local_addr, peer) = match self.sockets.get(&socket_id) {
+            Some(sock) => (sock.node, sock.local_addr.clone(), sock.peer),
+            None => {
+                return;
+            }
+        };
+        match segment.kind {
+            SimSegmentKind::Syn(addr) => match self.find_listener(&addr) {
+                Some((bound_addr, dest_node)) => {
+                    let server_socket_id = self.new_socket(
+                        dest_node,
+                        addr.clone(),
+                        Some(local_addr),
+                        SimSocketStatus::Connected,
+                    );
+                    if let Some(server_sock) = self.sockets.get_mut(&server_socket_id) {
+                        server_sock.peer = Some(socket_id);
+                    }
+                    self.accepted
+                        .entry(dest_node)
+                        .or_insert_with(Vec::new)
+                        .push((bound_addr, server_socket_id));
+                    self.send_segment(server_socket_id, node, SimSegmentKind::SynAck);
+                }
+                None => {
+                    let latency = self.latency(node, node) as u128;
+                    let now_ms = self.now_ms;
+                    if let Some(sock) = self.sockets.get_mut(&socket_id) {
+                        sock.refused_at = Some(now_ms + latency);
+                    }
+                }
+            },
+            SimSegmentKind::SynAck => {
+                let peer_id = match peer {
+                    Some(peer_id) => peer_id,
+                    None => {
+                        return;
+                    }
+                };
+                let delivered = match self.sockets.get_mut(&peer_id) {
+                    Some(peer_sock) => {
+                        if peer_sock.status == SimSocketStatus::Connecting {
+                            peer_sock.status = SimSocketStatus::Connected;
+                            peer_sock.peer = Some(socket_id);
+                            peer_sock.peer_addr = Some(local_addr);
+                            true
+                        } else {
+                            false
+                        }
+                    }
+                    None => false,
+                };
+                if delivered {
+                    self.mark_ready(peer_id);
+                } else {
+                    // the connecting socket went away before it heard back
+                    self.close(socket_id);
+                }
+            }
+            SimSegmentKind::Data(bytes) => {
+                if let Some(peer_id) = peer {
+                    if let Some(peer_sock) = self.sockets.get_mut(&peer_id) {
+                        peer_sock.recv_buf.extend(bytes.into_iter());
+                    }
+                    self.mark_ready(peer_id);
+                }
+            }
+            SimSegmentKind::Fin => {
+                if let Some(peer_id) = peer {
+                    if let Some(peer_sock) = self.sockets.get_mut(&peer_id) {
+                        peer_sock.remote_closed = true;
+                    }
+                    self.mark_ready(peer_id);
+                }
+            }
+        }
+    }
+
+    /// When is the next segment (or connection refusal) due?
+    fn next_delivery_time(&self) -> Option<u128> {
+        let mut next = None;
+        for (_, sock) in self.sockets.iter() {
+            let times = [
+                sock.outbox.front().map(|seg| seg.deliver_at),
+                sock.refused_at,
+            ];
+            for time_opt in times.iter() {
+                if let Some(time) = time_opt {
+                    next = match next {
+                        Some(next_time) if next_time <= *time => Some(next_time),
+                        _ => Some(*time),
+                    };
+                }
+            }
+        }
+        next
+    }
+
+    /// Advance the clock to `time_ms`, delivering everything that comes due on the way in the
+    /// order in which it comes due.
+    fn advance_to(&mut self, time_ms: u128) -> () {
+        loop {
+            match self.next_delivery_time() {
+                Some(next_time) if next_time <= time_ms => {
+                    if next_time > self.now_ms {
+                        self.now_ms = next_time;
+                    }
+                    self.deliver();
+                }
+                _ => {
+                    break;
+                }
+            }
+        }
+        self.now_ms = time_ms;
+    }
+
+    /// Deliver all segments (and connection refusals) that are due by now.
+    fn deliver(&mut self) -> () {
+        let now_ms = self.now_ms;
+        let socket_ids: Vec<u64> = self.sockets.keys().cloned().collect();
+        for socket_id in socket_ids.into_iter() {
+            // connection refused?
+            let refused = match self.sockets.get_mut(&socket_id) {
+                Some(sock) => match sock.refused_at {
+                    Some(refused_at) if refused_at <= now_ms => {
+                        sock.refused_at = None;
+                        sock.status = SimSocketStatus::Refused;
+                        true
+                    }
+                    _ => false,
+                },
+                None => false,
+            };
+            if refused {
+                self.mark_ready(socket_id);
+            }
+
+            loop {
+                let (node, deliver_at) = match self.sockets.get(&socket_id) {
+                    Some(sock) => match sock.outbox.front() {
+                        Some(seg) => (sock.node, seg.deliver_at),
+                        None => {
+                            break;
+                        }
+                    },
+                    None => {
+                        break;
+                    }
+                };
+                if deliver_at > now_ms {
+                    break;
+                }
+
+                let dest_node = self.dest_node(socket_id);
+                let reachable = match dest_node {
+                    Some(dest_node) => self.is_reachable(node, dest_node),
+                    None => true,
+                };
+                let lost =
+                    reachable && self.loss_rate > 0.0 && self.rng.gen::<f64>() < self.loss_rate;
+
+                if !reachable || lost {
+                    // try again later
+                    if lost {
+                        self.segments_lost += 1;
+                    }
+                    let rto = self.retransmit_timeout(node, dest_node.unwrap_or(node)) as u128;
+                    if let Some(seg) = self
+                        .sockets
+                        .get_mut(&socket_id)
+                        .and_then(|sock| sock.outbox.front_mut())
+                    {
+                        seg.deliver_at = now_ms + rto;
+                    }
+                    continue;
+                }
+
+                let segment = match self
+                    .sockets
+                    .get_mut(&socket_id)
+                    .and_then(|sock| sock.outbox.pop_front())
+                {
+                    Some(seg) => seg,
+                    None => {
+                        break;
+                    }
+                };
+                self.deliver_segment(socket_id, segment);
+            }
+
+            // forget closed sockets once everything they sent has been delivered
+            let done = match self.sockets.get(&socket_id) {
+                Some(sock) => sock.local_closed && sock.outbox.len() == 0,
+                None => false,
+            };
+            if done {
+                self.sockets.remove(&socket_id);
+            }
+        }
+    }
+}
+
+/// Handle to a simulated network.  Clones share the same network.
+#[derive(Clone)]
+pub struct SimNetwork {
+    state: Arc<Mutex<SimNetworkState>>,
+}
+
+impl fmt::Debug for SimNetwork {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let state = self.state.lock().unwrap();
+        write!(
+            f,
+            "SimNetwork(now={}, nodes={}, sockets={})",
+            state.now_ms,
+            state.node_ips.len(),
+            state.sockets.len()
+        )
+    }
+}
+
+impl SimNetwork {
+    /// Make a new simulated network, whose randomness (jitter and packet loss) is derived
+    /// entirely from `seed`.  This sets the calling thread's virtual clock.
+    pub fn new(seed: u64) -> SimNetwork {
+        let state = SimNetworkState {
+            now_ms: SIM_START_TIME_MS,
+            seed: seed,
+            rng: ChaCha20Rng::seed_from_u64(seed),
+            latency_ms: SIM_DEFAULT_LATENCY_MS,
+            jitter_ms: 0,
+            link_latency_ms: HashMap::new(),
+            loss_rate: 0.0,
+            partitions: None,
+            node_ips: vec![],
+            listeners: HashMap::new(),
+            sockets: BTreeMap::new(),
+            next_socket_id: 0,
+            next_port: SIM_FIRST_EPHEMERAL_PORT,
+            accepted: HashMap::new(),
+            ready: HashMap::new(),
+            segments_sent: 0,
+            segments_lost: 0,
+        };
+        set_virtual_time_ms(Some(SIM_START_TIME_MS));
+        SimNetwork {
+            state: Arc::new(Mutex::new(state)),
+        }
+    }
+
+    /// Add a node to the network.  Nodes are numbered in the order in which they are added.
+    pub fn new_endpoint(&self) -> SimEndpoint {
+        let mut state = self.state.lock().unwrap();
+        let node = state.node_ips.len();
+        state.node_ips.push(IpAddr::V4(Ipv4Addr::new(127, 0, 0, 1)));
+        SimEndpoint {
+            node: node,
+            network: self.clone(),
+            events: HashMap::new(),
+        }
+    }
+
+    /// Current virtual time, in milliseconds since the epoch
+    pub fn now_ms(&self) -> u128 {
+        self.state.lock().unwrap().now_ms
+    }
+
+    /// Advance the virtual clock, and deliver everything that is due.
+    pub fn advance(&self, ms: u64) -> () {
+        let mut state = self.state.lock().unwrap();
+        let time_ms = state.now_ms + (ms as u128);
+        state.advance_to(time_ms);
+        set_virtual_time_ms(Some(state.now_ms));
+    }
+
+    /// Set the one-way latency of every link, and the maximum random jitter to add to it.
+    pub fn set_latency(&self, latency_ms: u64, jitter_ms: u64) -> () {
+        let mut state = self.state.lock().unwrap();
+        state.latency_ms = latency_ms;
+        state.jitter_ms = jitter_ms;
+    }
+
+    /// Set the one-way latency of the link between two nodes
+    pub fn set_link_latency(&self, node_1: usize, node_2: usize, latency_ms: u64) -> () {
+        let key = if node_1 < node_2 {
+            (node_1, node_2)
+        } else {
+            (node_2, node_1)
+        };
+        self.state
+            .lock()
+            .unwrap()
+            .link_latency_ms
+            .insert(key, latency_ms);
+    }
+
+    /// Set the probability that any given segment gets lost (and has to be retransmitted)
+    pub fn set_loss_rate(&self, loss_rate: f64) -> () {
+        assert!(loss_rate >= 0.0 && loss_rate < 1.0);
+        self.state.lock().unwrap().loss_rate = loss_rate;
+    }
+
+    /// Split the network into the given groups of nodes.  Nodes in different groups cannot reach
+    /// one another; nodes not listed in any group can only reach each other.  Traffic between
+    /// groups is held back (not dropped), so it gets through once the partition heals unless
+    /// the connection times out first.
+    pub fn partition(&self, groups: &[Vec<usize>]) -> () {
+        let mut partitions = HashMap::new();
+        for (i, group) in groups.iter().enumerate() {
+            for node in group.iter() {
+                partitions.insert(*node, i);
+            }
+        }
+        self.state.lock().unwrap().partitions = Some(partitions);
+    }
+
+    /// Undo a partition
+    pub fn heal(&self) -> () {
+        self.state.lock().unwrap().partitions = None;
+    }
+
+    /// Number of nodes in the network
+    pub fn num_nodes(&self) -> usize {
+        self.state.lock().unwrap().node_ips.len()
+    }
+
+    /// Which node is listening on this address?
+    pub fn get_node(&self, addr: &SocketAddr) -> Option<usize> {
+        self.state
+            .lock()
+            .unwrap()
+            .find_listener(addr)
+            .map(|(_, node)| node)
+    }
+
+    /// How many segments have been sent, and how many of those were lost (at least once)?
+    pub fn get_segment_stats(&self) -> (u64, u64) {
+        let state = self.state.lock().unwrap();
+        (state.segments_sent, state.segments_lost)
+    }
+}
+
+/// A node's view of the simulated network, held by its `NetworkState`
+pub struct SimEndpoint {
+    node: usize,
+    network: SimNetwork,
+    /// map socket IDs to the poll event IDs they were registered with
+    events: HashMap<u64, usize>,
+}
+
+impl fmt::Debug for SimEndpoint {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "SimEndpoint(node={})", self.node)
+    }
+}
+
+impl SimEndpoint {
+    pub fn node(&self) -> usize {
+        self.node
+    }
+
+    /// Seed for the node's own randomness (e.g. choosing peers), derived from the simulation's
+    /// seed.  Each node gets a different one, and drawing it doesn't disturb the network's RNG.
+    pub fn rng_seed(&self) -> u64 {
+        let seed = self.network.state.lock().unwrap().seed;
+        let mut rng = ChaCha20Rng::seed_from_u64(seed);
+        rng.set_stream((self.node as u64) + 1);
+        rng.gen()
+    }
+
+    pub fn bind(&mut self, addr: &SocketAddr) -> Result<(), net_error> {
+        let mut state = self.network.state.lock().unwrap();
+        if state.listeners.contains_key(addr) {
+            debug!("Simulated address {:?} is already bound", addr);
+            return Err(net_error::BindError);
+        }
+        state.listeners.insert(addr.clone(), self.node);
+        if !addr.ip().is_unspecified() {
+            state.node_ips[self.node] = addr.ip();
+        }
+        Ok(())
+    }
+
+    pub fn connect(&mut self, addr: &SocketAddr) -> SimSocket {
+        let socket_id = self.network.state.lock().unwrap().connect(self.node, addr);
+        SimSocket {
+            id: socket_id,
+            network: self.network.clone(),
+        }
+    }
+
+    /// Start reporting readiness events for this socket.  Like registering a socket with
+    /// epoll, this reports the socket as ready right away, in case data arrived before it
+    /// was registered.
+    pub fn register(&mut self, sock: &SimSocket, event_id: usize) -> () {
+        self.events.insert(sock.id, event_id);
+        self.network.state.lock().unwrap().mark_ready(sock.id);
+    }
+
+    pub fn deregister(&mut self, sock: &SimSocket) -> () {
+        self.events.remove(&sock.id);
+    }
+
+    /// Get the newly-accepted sockets (and the addresses they were accepted on), and the event
+    /// IDs of all registered sockets that have become ready since the last poll.
+    pub fn poll(&mut self) -> (Vec<(SocketAddr, SimSocket)>, Vec<usize>) {
+        let mut state = self.network.state.lock().unwrap();
+        let accepted = state
+            .accepted
+            .remove(&self.node)
+            .unwrap_or(vec![])
+            .into_iter()
+            .map(|(addr, socket_id)| {
+                (
+                    addr,
+                    SimSocket {
+                        id: socket_id,
+                        network: self.network.clone(),
+                    },
+                )
+            })
+            .collect();
+
+        let ready = state
+            .ready
+            .remove(&self.node)
+            .unwrap_or(BTreeSet::new())
+            .into_iter()
+            .filter_map(|socket_id| self.events.get(&socket_id).cloned())
+            .collect();
+
+        (accepted, ready)
+    }
+}
+
+/// A connected (or connecting) socket in a simulated network.  Dropping it closes it.
+pub struct SimSocket {
+    id: u64,
+    network: SimNetwork,
+}
+
+impl fmt::Debug for SimSocket {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "SimSocket({})", self.id)
+    }
+}
+
+impl SimSocket {
+    pub fn peer_addr(&self) -> io::Result<SocketAddr> {
+        let state = self.network.state.lock().unwrap();
+        match state.sockets.get(&self.id) {
+            Some(sock) => match (sock.status, sock.peer_addr.as_ref()) {
+                (SimSocketStatus::Connected, Some(addr)) => Ok(addr.clone()),
+                _ => Err(io::Error::from(ErrorKind::NotConnected)),
+            },
+            None => Err(io::Error::from(ErrorKind::NotConnected)),
+        }
+    }
+
+    pub fn shutdown(&self, _how: Shutdown) -> io::Result<()> {
+        self.network.state.lock().unwrap().close(self.id);
+        Ok(())
+    }
+}
+
+impl Read for SimSocket {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.network.state.lock().unwrap().read(self.id, buf)
+    }
+}
+
+impl Write for SimSocket {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.network.state.lock().unwrap().write(self.id, buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl Drop for SimSocket {
+    fn drop(&mut self) {
+        if let Ok(mut state) = self.network.state.lock() {
+            state.close(self.id);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use net::poll::*;
+    use net::test::*;
+
+    use util::get_epoch_time_ms;
+    use util::get_epoch_time_secs;
+
+    fn recv_all(sock: &mut SimSocket) -> Vec<u8> {
+        let mut ret = vec![];
+        let mut buf = [0u8; 7];
+        loop {
+            match sock.read(&mut buf) {
+                Ok(0) => {
+                    break;
+                }
+                Ok(nr) => {
+                    ret.extend_from_slice(&buf[0..nr]);
+                }
+                Err(e) => {
+                    assert_eq!(e.kind(), ErrorKind::WouldBlock);
+                    break;
+                }
+            }
+        }
+        ret
+    }
+
+    fn sim_connect(
+        sim: &SimNetwork,
+        client: &mut SimEndpoint,
+        server: &mut SimEndpoint,
+        addr: &SocketAddr,
+    ) -> (SimSocket, SimSocket) {
+        let client_sock = client.connect(addr);
+        client.register(&client_sock, 1);
+        client.poll();
+
+        let mut accepted = vec![];
+        for _ in 0..1000 {
+            sim.advance(10);
+            let (mut new_socks, _) = server.poll();
+            accepted.append(&mut new_socks);
+            if client_sock.peer_addr().is_ok() {
+                break;
+            }
+        }
+        assert_eq!(accepted.len(), 1);
+        let (server_addr, server_sock) = accepted.pop().unwrap();
+        assert_eq!(server_addr, *addr);
+        assert_eq!(client_sock.peer_addr().unwrap(), *addr);
+        assert_eq!(client.poll().1, vec![1]);
+        (client_sock, server_sock)
+    }
+
+    #[test]
+    fn sim_virtual_clock() {
+        let sim = SimNetwork::new(0);
+        assert_eq!(get_epoch_time_ms(), SIM_START_TIME_MS);
+        assert_eq!(get_epoch_time_secs(), (SIM_START_TIME_MS / 1000) as u64);
+
+        sim.advance(1500);
+        assert_eq!(sim.now_ms(), SIM_START_TIME_MS + 1500);
+        assert_eq!(get_epoch_time_ms(), SIM_START_TIME_MS + 1500);
+
+        set_virtual_time_ms(None);
+        assert!(get_epoch_time_ms() != SIM_START_TIME_MS + 1500);
+    }
+
+    #[test]
+    fn sim_connect_send_recv() {
+        let sim = SimNetwork::new(0);
+        sim.set_latency(100, 0);
+
+        let mut server = sim.new_endpoint();
+        let mut client = sim.new_endpoint();
+        let addr: SocketAddr = "127.0.0.1:20444".parse().unwrap();
+        server.bind(&addr).unwrap();
+        assert_eq!(sim.get_node(&addr), Some(0));
+
+        // can't bind twice
+        assert_eq!(server.bind(&addr), Err(net_error::BindError));
+
+        let (mut client_sock, mut server_sock) = sim_connect(&sim, &mut client, &mut server, &addr);
+        server.register(&server_sock, 2);
+
+        // inbound socket knows the client's address
+        assert!(server_sock.peer_addr().unwrap().port() >= SIM_FIRST_EPHEMERAL_PORT);
+
+        // data arrives after exactly one latency period
+        client_sock.write_all(b"hello ").unwrap();
+        client_sock.write_all(b"world").unwrap();
+        server.poll();
+
+        sim.advance(99);
+        assert_eq!(server.poll().1, Vec::<usize>::new());
+        assert_eq!(recv_all(&mut server_sock), Vec::<u8>::new());
+
+        sim.advance(1);
+        assert_eq!(server.poll().1, vec![2]);
+        assert_eq!(recv_all(&mut server_sock), b"hello world".to_vec());
+
+        // closing is seen as EOF on the other side
+        drop(client_sock);
+        sim.advance(100);
+        assert_eq!(server.poll().1, vec![2]);
+        let mut buf = [0u8; 1];
+        assert_eq!(server_sock.read(&mut buf).unwrap(), 0);
+    }
+
+    #[test]
+    fn sim_connection_refused() {
+        let sim = SimNetwork::new(0);
+        let mut client = sim.new_endpoint();
+        let addr: SocketAddr = "127.0.0.1:20444".parse().unwrap();
+
+        let mut sock = client.connect(&addr);
+        client.register(&sock, 1);
+        client.poll();
+
+        sim.advance(1000);
+        assert_eq!(client.poll().1, vec![1]);
+        assert!(sock.peer_addr().is_err());
+
+        let mut buf = [0u8; 1];
+        assert_eq!(
+            sock.read(&mut buf).unwrap_err().kind(),
+            ErrorKind::ConnectionRefused
+        );
+        assert_eq!(
+            sock.write(&buf).unwrap_err().kind(),
+            ErrorKind::ConnectionRefused
+        );
+    }
+
+    #[test]
+    fn sim_partition_and_heal() {
+        let sim = SimNetwork::new(0);
+        sim.set_latency(10, 0);
+
+        let mut server = sim.new_endpoint();
+        let mut client = sim.new_endpoint();
+        let addr: SocketAddr = "127.0.0.1:20444".parse().unwrap();
+        server.bind(&addr).unwrap();
+
+        let (mut client_sock, mut server_sock) = sim_connect(&sim, &mut client, &mut server, &addr);
+
+        sim.partition(&[vec![0], vec![1]]);
+
+        // nothing gets through while partitioned...
+        client_sock.write_all(b"partitioned").unwrap();
+        for _ in 0..100 {
+            sim.advance(100);
+            assert_eq!(recv_all(&mut server_sock), Vec::<u8>::new());
+        }
+
+        // ...and new connections don't get through either
+        let connecting_sock = client.connect(&addr);
+        sim.advance(1000);
+        assert_eq!(server.poll().0.len(), 0);
+        assert!(connecting_sock.peer_addr().is_err());
+
+        // but it all arrives once the partition heals
+        sim.heal();
+        sim.advance(1000);
+        assert_eq!(recv_all(&mut server_sock), b"partitioned".to_vec());
+        assert_eq!(server.poll().0.len(), 1);
+        assert!(connecting_sock.peer_addr().is_ok());
+    }
+
+    #[test]
+    fn sim_lossy_link_is_reliable_and_deterministic() {
+        let run = |seed: u64| -> (Vec<u8>, u128, (u64, u64)) {
+            let sim = SimNetwork::new(seed);
+            sim.set_latency(20, 30);
+            sim.set_loss_rate(0.3);
+
+            let mut server = sim.new_endpoint();
+            let mut client = sim.new_endpoint();
+            let addr: SocketAddr = "127.0.0.1:20444".parse().unwrap();
+            server.bind(&addr).unwrap();
+
+            let (mut client_sock, mut server_sock) =
+                sim_connect(&sim, &mut client, &mut server, &addr);
+
+            let mut expected = vec![];
+            for i in 0..100u8 {
+                client_sock.write_all(&[i, i, i]).unwrap();
+                expected.extend_from_slice(&[i, i, i]);
+            }
+
+            let mut received = vec![];
+            let mut done_at = 0;
+            for _ in 0..1000 {
+                sim.advance(10);
+                received.append(&mut recv_all(&mut server_sock));
+                if received.len() == expected.len() {
+                    done_at = sim.now_ms();
+                    break;
+                }
+            }
+
+            // stream arrived intact and in order
+            assert_eq!(received, expected);
+            (received, done_at, sim.get_segment_stats())
+        };
+
+        let (data_1, done_at_1, stats_1) = run(1234);
+        let (data_2, done_at_2, stats_2) = run(1234);
+        let (_, done_at_3, stats_3) = run(5678);
+
+        assert_eq!(data_1, data_2);
+        assert_eq!(done_at_1, done_at_2);
+        assert_eq!(stats_1, stats_2);
+        assert!(stats_1.1 > 0);
+
+        // a different seed gives a different run
+        assert!(done_at_1 != done_at_3 || stats_1 != stats_3);
+    }
+
+    #[test]
+    fn sim_network_state_poll() {
+        let sim = SimNetwork::new(0);
+        let mut ns_1 = NetworkState::new_sim(100, &sim).unwrap();
+        let mut ns_2 = NetworkState::new_sim(100, &sim).unwrap();
+
+        let addr_1: SocketAddr = "127.0.0.1:20444".parse().unwrap();
+        let addr_2: SocketAddr = "127.0.0.1:20445".parse().unwrap();
+        let server_1 = ns_1.bind(&addr_1).unwrap();
+        let server_2 = ns_2.bind(&addr_2).unwrap();
+
+        let sock = ns_2.connect_socket(&addr_1).unwrap();
+        let client_event = ns_2.register(server_2, 10, &sock).unwrap();
+
+        sim.advance(SIM_DEFAULT_LATENCY_MS);
+        let mut poll_states_1 = ns_1.poll(0).unwrap();
+        let poll_state_1 = poll_states_1.get_mut(&server_1).unwrap();
+        assert_eq!(poll_state_1.new.len(), 1);
+
+        let (hint_event_id, server_sock) = poll_state_1.new.drain().next().unwrap();
+        let server_event = ns_1
+            .register(server_1, hint_event_id, &server_sock)
+            .unwrap();
+        assert_eq!(
+            ns_1.poll(0).unwrap().get(&server_1).unwrap().ready,
+            vec![server_event]
+        );
+
+        sim.advance(SIM_DEFAULT_LATENCY_MS);
+        assert_eq!(
+            ns_2.poll(0).unwrap().get(&server_2).unwrap().ready,
+            vec![client_event]
+        );
+        assert_eq!(sock.peer_addr().unwrap(), addr_1);
+
+        ns_2.deregister(client_event, &sock).unwrap();
+        sim.advance(SIM_DEFAULT_LATENCY_MS);
+        assert_eq!(
+            ns_1.poll(0).unwrap().get(&server_1).unwrap().ready,
+            vec![server_event]
+        );
+    }
+
+    #[test]
+    fn sim_peers_walk_and_partition() {
+        let sim = SimNetwork::new(0);
+
+        let mut peer_1_config = TestPeerConfig::from_port(32700);
+        let mut peer_2_config = TestPeerConfig::from_port(32702);
+        for config in [&mut peer_1_config, &mut peer_2_config].iter_mut() {
+            config.sim = Some(sim.clone());
+            config.connection_opts.heartbeat = 10;
+            config.connection_opts.neighbor_request_timeout = 10;
+        }
+
+        peer_1_config.add_neighbor(&peer_2_config.to_neighbor());
+
+        let mut peers = vec![TestPeer::new(peer_1_config), TestPeer::new(peer_2_config)];
+        let neighbor_2 = peers[1].to_neighbor();
+
+        // peer 1 finds peer 2, and handshakes with it
+        let ticks = run_sim_peers(&sim, &mut peers, 100, 10_000, |peers| {
+            peers[0].network.walk_total_step_count > 0
+                && peers[0]
+                    .network
+                    .get_neighbor_stats(&neighbor_2.addr)
+                    .map(|stats| stats.last_handshake_time > 0)
+                    .unwrap_or(false)
+        })
+        .expect("peer 1 never talked to peer 2");
+
+        debug!("Peers connected after {} ticks", ticks);
+        let stats = peers[0]
+            .network
+            .get_neighbor_stats(&neighbor_2.addr)
+            .unwrap();
+        assert!(stats.last_handshake_time > 0);
+        assert!(stats.last_handshake_time <= get_epoch_time_secs());
+
+        // cut them off from each other, and they eventually give up on one another
+        sim.partition(&[vec![0], vec![1]]);
+        run_sim_peers(&sim, &mut peers, 100, 10_000, |peers| {
+            peers[0]
+                .network
+                .get_neighbor_stats(&neighbor_2.addr)
+                .is_none()
+        })
+        .expect("peer 1 never noticed the partition");
+    }
+
+    #[test]
+    fn sim_peers_walk_is_reproducible() {
+        // per-tick trace of the peers' walk and connection state, and the network's traffic
+        let run = |seed: u64| -> Vec<(u64, u64, u64, u64, usize, usize, (u64, u64))> {
+            let sim = SimNetwork::new(seed);
+            sim.set_latency(20, 30);
+            sim.set_loss_rate(0.1);
+
+            let mut peer_1_config = TestPeerConfig::from_port(32704);
+            let mut peer_2_config = TestPeerConfig::from_port(32706);
+            for config in [&mut peer_1_config, &mut peer_2_config].iter_mut() {
+                config.sim = Some(sim.clone());
+                config.allowed = -1;
+                config.connection_opts.walk_max_duration = 10;
+            }
+
+            peer_1_config.add_neighbor(&peer_2_config.to_neighbor());
+            peer_2_config.add_neighbor(&peer_1_config.to_neighbor());
+
+            let mut peers = vec![TestPeer::new(peer_1_config), TestPeer::new(peer_2_config)];
+            let mut trace = vec![];
+            run_sim_peers(&sim, &mut peers, 100, 1000, |peers| {
+                trace.push((
+                    peers[0].network.walk_total_step_count,
+                    peers[1].network.walk_total_step_count,
+                    peers[0].network.walk_count,
+                    peers[1].network.walk_count,
+                    peers[0].network.peers.len(),
+                    peers[1].network.peers.len(),
+                    sim.get_segment_stats(),
+                ));
+                false
+            });
+            trace
+        };
+
+        let trace_1 = run(1234);
+        let trace_2 = run(1234);
+
+        // the peers actually walked
+        let last = trace_1.last().unwrap().clone();
+        assert!(last.0 > 0 && last.1 > 0);
+        assert!(last.2 > 0 && last.3 > 0);
+
+        assert_eq!(trace_1, trace_2);
+    }
+}
@@ -0,0 +1,819 @@
+// Copyright (C) 2013-2020 Blockstack PBC, a public benefit corporation
+// Copyright (C) 2020 Stacks Open Internet Foundation
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <http://www.gnu.org/licenses/>.
+
+use std::io;
+use std::io::{Read, Write};
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr, TcpStream};
+use std::time::Duration;
+
+use net::Error as net_error;
+use net::OnionAddress;
+use net::PeerAddress;
+use net::PeerHost;
+
+use sha3::{Digest, Sha3_256};
+
+use util::log;
+
+const SOCKS5_VERSION: u8 = 0x05;
+const SOCKS5_AUTH_NONE: u8 = 0x00;
+const SOCKS5_AUTH_NO_ACCEPTABLE: u8 = 0xff;
+
+const SOCKS5_CMD_CONNECT: u8 = 0x01;
+const SOCKS5_CMD_TOR_RESOLVE: u8 = 0xf0; // Tor extension: resolve a name without connecting
+
+const SOCKS5_ATYP_IPV4: u8 = 0x01;
+const SOCKS5_ATYP_DOMAIN: u8 = 0x03;
+const SOCKS5_ATYP_IPV6: u8 = 0x04;
+
+const SOCKS5_REPLY_SUCCEEDED: u8 = 0x00;
+
+const ONION_V3_VERSION: u8 = 0x03;
+const ONION_V3_HOSTNAME_LEN: usize = 56;
+const ONION_SUFFIX: &'static str = ".onion";
+
+const BASE32_ALPHABET: &'static [u8; 32] = b"abcdefghijklmnopqrstuvwxyz234567";
+
+/// Human-readable SOCKS5 reply codes (RFC 1928, section 6)
+fn socks5_reply_str(code: u8) -> &'static str {
+    match code {
+        0x01 => "general SOCKS server failure",
+        0x02 => "connection not allowed by ruleset",
+        0x03 => "network unreachable",
+        0x04 => "host unreachable",
+        0x05 => "connection refused",
+        0x06 => "TTL expired",
+        0x07 => "command not supported",
+        0x08 => "address type not supported",
+        _ => "unknown error",
+    }
+}
+
+/// Encode a SOCKS5 request for the given command and destination
+fn socks5_request(cmd: u8, dest: &PeerHost) -> Result<Vec<u8>, net_error> {
+    let mut req = vec![SOCKS5_VERSION, cmd, 0x00];
+    let port = match dest {
+        PeerHost::DNS(ref host, ref port) => {
+            // let the proxy resolve the name, so we don't leak it to our local resolver
+            if host.len() == 0 || host.len() > 255 {
+                return Err(net_error::ProxyError(format!(
+                    "Invalid host name length: {}",
+                    host.len()
+                )));
+            }
+            req.push(SOCKS5_ATYP_DOMAIN);
+            req.push(host.len() as u8);
+            req.extend_from_slice(host.as_bytes());
+            *port
+        }
+        PeerHost::IP(ref addrbytes, ref port) => {
+            if addrbytes.is_onion() {
+                // this is a stand-in address, not a real one
+                return Err(net_error::ProxyError(
+                    "Cannot connect to an onion stand-in address".to_string(),
+                ));
+            }
+            match addrbytes.ipv4_octets() {
+                Some(octets) => {
+                    req.push(SOCKS5_ATYP_IPV4);
+                    req.extend_from_slice(&octets);
+                }
+                None => {
+                    req.push(SOCKS5_ATYP_IPV6);
+                    req.extend_from_slice(addrbytes.as_bytes());
+                }
+            }
+            *port
+        }
+    };
+    req.extend_from_slice(&port.to_be_bytes());
+    Ok(req)
+}
+
+fn socks5_read<S: Read>(stream: &mut S, buf: &mut [u8]) -> Result<(), net_error> {
+    stream
+        .read_exact(buf)
+        .map_err(|e| net_error::ProxyError(format!("Failed to read from proxy: {:?}", &e)))
+}
+
+fn socks5_write<S: Write>(stream: &mut S, buf: &[u8]) -> Result<(), net_error> {
+    stream
+        .write_all(buf)
+        .and_then(|_| stream.flush())
+        .map_err(|e| net_error::ProxyError(format!("Failed to write to proxy: {:?}", &e)))
+}
+
+/// Check the proxy's reply to our method negotiation.
+/// Only the no-authentication method is supported.
+fn socks5_check_method_reply(reply: &[u8]) -> Result<(), net_error> {
+    if reply[0] != SOCKS5_VERSION {
+        return Err(net_error::ProxyError(format!(
+            "Unsupported SOCKS version {}",
+            reply[0]
+        )));
+    }
+    if reply[1] != SOCKS5_AUTH_NONE {
+        if reply[1] == SOCKS5_AUTH_NO_ACCEPTABLE {
+            return Err(net_error::ProxyError(
+                "Proxy requires authentication".to_string(),
+            ));
+        }
+        return Err(net_error::ProxyError(format!(
+            "Unsupported SOCKS authentication method {}",
+            reply[1]
+        )));
+    }
+    Ok(())
+}
+
+/// Work out how long the proxy's reply to our request is, given its first few bytes.
+/// Returns Ok(None) if we don't have enough of the reply yet to tell.
+/// Fails as soon as the reply is known to be an error.
+fn socks5_reply_len(reply: &[u8], dest: &PeerHost) -> Result<Option<usize>, net_error> {
+    if reply.len() < 4 {
+        return Ok(None);
+    }
+    if reply[0] != SOCKS5_VERSION {
+        return Err(net_error::ProxyError(format!(
+            "Unsupported SOCKS version {}",
+            reply[0]
+        )));
+    }
+    if reply[1] != SOCKS5_REPLY_SUCCEEDED {
+        return Err(net_error::ProxyError(format!(
+            "Proxy could not reach {}: {}",
+            dest,
+            socks5_reply_str(reply[1])
+        )));
+    }
+
+    // header, then the bound address, then the bound port
+    match reply[3] {
+        SOCKS5_ATYP_IPV4 => Ok(Some(4 + 4 + 2)),
+        SOCKS5_ATYP_IPV6 => Ok(Some(4 + 16 + 2)),
+        SOCKS5_ATYP_DOMAIN => {
+            if reply.len() < 5 {
+                Ok(None)
+            } else {
+                Ok(Some(4 + 1 + (reply[4] as usize) + 2))
+            }
+        }
+        atyp => Err(net_error::ProxyError(format!(
+            "Unsupported SOCKS address type {}",
+            atyp
+        ))),
+    }
+}
+
+/// Decode the proxy's complete reply to our request.
+/// Returns the address bound by the proxy.
+fn socks5_parse_reply(reply: &[u8], dest: &PeerHost) -> Result<PeerHost, net_error> {
+    let len = match socks5_reply_len(reply, dest)? {
+        Some(len) if len <= reply.len() => len,
+        _ => {
+            return Err(net_error::ProxyError(
+                "Truncated reply from proxy".to_string(),
+            ));
+        }
+    };
+    let port = u16::from_be_bytes([reply[len - 2], reply[len - 1]]);
+
+    let bound_host = match reply[3] {
+        SOCKS5_ATYP_IPV4 => {
+            let mut octets = [0u8; 4];
+            octets.copy_from_slice(&reply[4..8]);
+            PeerHost::IP(
+                PeerAddress::from_ip(&IpAddr::V4(Ipv4Addr::from(octets))),
+                port,
+            )
+        }
+        SOCKS5_ATYP_IPV6 => {
+            let mut octets = [0u8; 16];
+            octets.copy_from_slice(&reply[4..20]);
+            PeerHost::IP(
+                PeerAddress::from_ip(&IpAddr::V6(Ipv6Addr::from(octets))),
+                port,
+            )
+        }
+        _ => {
+            let name = String::from_utf8(reply[5..(len - 2)].to_vec()).map_err(|_e| {
+                net_error::ProxyError("Proxy replied an invalid host name".to_string())
+            })?;
+            PeerHost::DNS(name, port)
+        }
+    };
+    Ok(bound_host)
+}
+
+/// Carry out a SOCKS5 exchange on an already-connected, blocking stream to the proxy.
+/// Only the no-authentication method is supported.
+/// Returns the address bound by the proxy on success.
+pub fn socks5_handshake<S: Read + Write>(
+    stream: &mut S,
+    cmd: u8,
+    dest: &PeerHost,
+) -> Result<PeerHost, net_error> {
+    let request = socks5_request(cmd, dest)?;
+
+    // method negotiation
+    socks5_write(stream, &[SOCKS5_VERSION, 0x01, SOCKS5_AUTH_NONE])?;
+
+    let mut method_reply = [0u8; 2];
+    socks5_read(stream, &mut method_reply)?;
+    socks5_check_method_reply(&method_reply)?;
+
+    // request
+    socks5_write(stream, &request)?;
+
+    // the reply's length depends on the type of the bound address, so read it a bit at a time
+    let mut reply = vec![0u8; 4];
+    socks5_read(stream, &mut reply)?;
+    let len = loop {
+        match socks5_reply_len(&reply, dest)? {
+            Some(len) => break len,
+            None => {
+                let mut next = [0u8; 1];
+                socks5_read(stream, &mut next)?;
+                reply.push(next[0]);
+            }
+        }
+    };
+
+    let start = reply.len();
+    reply.resize(len, 0);
+    socks5_read(stream, &mut reply[start..])?;
+
+    socks5_parse_reply(&reply, dest)
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Socks5ConnectorState {
+    Greeting,
+    Request,
+    Connected,
+}
+
+/// A SOCKS5 CONNECT exchange with a proxy, carried out over a non-blocking socket a little at a
+/// time as the socket becomes ready.  This lets the p2p network connect to peers through a proxy
+/// without stalling on a slow one.
+#[derive(Debug)]
+pub struct Socks5Connector {
+    dest: PeerHost,
+    state: Socks5ConnectorState,
+    request: Vec<u8>,
+    outbuf: Vec<u8>,
+    inbuf: Vec<u8>,
+}
+
+impl Socks5Connector {
+    pub fn new(dest: &PeerHost) -> Result<Socks5Connector, net_error> {
+        let request = socks5_request(SOCKS5_CMD_CONNECT, dest)?;
+        Ok(Socks5Connector {
+            dest: dest.clone(),
+            state: Socks5ConnectorState::Greeting,
+            request: request,
+            outbuf: vec![SOCKS5_VERSION, 0x01, SOCKS5_AUTH_NONE],
+            inbuf: vec![],
+        })
+    }
+
+    /// Has the proxy connected us to the destination?
+    pub fn is_connected(&self) -> bool {
+        self.state == Socks5ConnectorState::Connected
+    }
+
+    /// Send and receive as much of the exchange as the stream allows without blocking.
+    /// Returns Ok(true) once the proxy has connected us to the destination, and Ok(false) if we
+    /// need to wait for the stream to become ready again.
+    pub fn try_advance<S: Read + Write>(&mut self, stream: &mut S) -> Result<bool, net_error> {
+        loop {
+            if self.is_connected() {
+                return Ok(true);
+            }
+
+            // send whatever we still owe the proxy
+            while self.outbuf.len() > 0 {
+                match stream.write(&self.outbuf) {
+                    Ok(0) => {
+                        return Err(net_error::ProxyError(
+                            "Proxy closed the connection".to_string(),
+                        ));
+                    }
+                    Ok(num_sent) => {
+                        self.outbuf.drain(0..num_sent);
+                    }
+                    Err(ref e) if e.kind() == io::ErrorKind::WouldBlock => {
+                        return Ok(false);
+                    }
+                    Err(e) => {
+                        return Err(net_error::ProxyError(format!(
+                            "Failed to write to proxy: {:?}",
+                            &e
+                        )));
+                    }
+                }
+            }
+
+            // never read past the end of the proxy's reply.  If we don't know its length yet, we
+            // know that it is at least 5 bytes.
+            let needed = match self.state {
+                Socks5ConnectorState::Greeting => Some(2),
+                _ => socks5_reply_len(&self.inbuf, &self.dest)?,
+            };
+            let want = needed.unwrap_or(5);
+            if needed.is_none() || self.inbuf.len() < want {
+                let mut buf = vec![0u8; want - self.inbuf.len()];
+                match stream.read(&mut buf) {
+                    Ok(0) => {
+                        return Err(net_error::ProxyError(
+                            "Proxy closed the connection".to_string(),
+                        ));
+                    }
+                    Ok(num_read) => {
+                        self.inbuf.extend_from_slice(&buf[0..num_read]);
+                        continue;
+                    }
+                    Err(ref e) if e.kind() == io::ErrorKind::WouldBlock => {
+                        return Ok(false);
+                    }
+                    Err(e) => {
+                        return Err(net_error::ProxyError(format!(
+                            "Failed to read from proxy: {:?}",
+                            &e
+                        )));
+                    }
+                }
+            }
+
+            // have the whole reply
+            match self.state {
+                Socks5ConnectorState::Greeting => {
+                    socks5_check_method_reply(&self.inbuf)?;
+                    self.inbuf.clear();
+                    self.outbuf = self.request.clone();
+                    self.state = Socks5ConnectorState::Request;
+                }
+                _ => {
+                    socks5_parse_reply(&self.inbuf, &self.dest)?;
+                    self.inbuf.clear();
+                    self.state = Socks5ConnectorState::Connected;
+                }
+            }
+        }
+    }
+}
+
+/// Open a connection to the proxy, and give it the given timeout for its replies.
+fn socks5_open(proxy: &SocketAddr, timeout_ms: u64) -> Result<TcpStream, net_error> {
+    let timeout = Duration::from_millis(timeout_ms);
+    let stream = TcpStream::connect_timeout(proxy, timeout).map_err(|e| {
+        debug!("Failed to connect to SOCKS proxy {:?}: {:?}", proxy, &e);
+        net_error::ConnectionError
+    })?;
+
+    stream
+        .set_read_timeout(Some(timeout))
+        .and_then(|_| stream.set_write_timeout(Some(timeout)))
+        .map_err(|e| {
+            debug!("Failed to set timeouts on SOCKS proxy socket: {:?}", &e);
+            net_error::SocketError
+        })?;
+
+    Ok(stream)
+}
+
+/// Resolve a host name through the SOCKS5 proxy at `proxy`, using Tor's RESOLVE extension.
+pub fn socks5_resolve(
+    proxy: &SocketAddr,
+    host: &str,
+    timeout_ms: u64,
+) -> Result<PeerAddress, net_error> {
+    let mut stream = socks5_open(proxy, timeout_ms)?;
+    match socks5_handshake(
+        &mut stream,
+        SOCKS5_CMD_TOR_RESOLVE,
+        &PeerHost::DNS(host.to_string(), 0),
+    )? {
+        PeerHost::IP(addrbytes, _) => Ok(addrbytes),
+        PeerHost::DNS(..) => Err(net_error::LookupError(format!(
+            "Proxy did not resolve {} to an IP address",
+            host
+        ))),
+    }
+}
+
+fn base32_encode(data: &[u8]) -> String {
+    let mut ret = String::with_capacity((data.len() * 8 + 4) / 5);
+    let mut buf: u32 = 0;
+    let mut bits = 0;
+    for byte in data.iter() {
+        buf = (buf << 8) | (*byte as u32);
+        bits += 8;
+        while bits >= 5 {
+            bits -= 5;
+            ret.push(BASE32_ALPHABET[((buf >> bits) & 0x1f) as usize] as char);
+        }
+    }
+    if bits > 0 {
+        ret.push(BASE32_ALPHABET[((buf << (5 - bits)) & 0x1f) as usize] as char);
+    }
+    ret
+}
+
+fn base32_decode(s: &str) -> Option<Vec<u8>> {
+    let mut ret = Vec::with_capacity(s.len() * 5 / 8);
+    let mut buf: u32 = 0;
+    let mut bits = 0;
+    for c in s.bytes() {
+        let val = BASE32_ALPHABET.iter().position(|a| *a == c)? as u32;
+        buf = (buf << 5) | val;
+        bits += 5;
+        if bits >= 8 {
+            bits -= 8;
+            ret.push(((buf >> bits) & 0xff) as u8);
+        }
+    }
+    Some(ret)
+}
+
+impl OnionAddress {
+    /// Two-byte checksum of a v3 onion address (rend-spec-v3, section 6)
+    fn checksum(pubkey: &[u8; 32]) -> [u8; 2] {
+        let mut hasher = Sha3_256::new();
+        hasher.input(b".onion checksum");
+        hasher.input(&pubkey[..]);
+        hasher.input(&[ONION_V3_VERSION]);
+        let digest = hasher.result();
+        [digest[0], digest[1]]
+    }
+
+    /// Parse a v3 onion host name, with or without the trailing ".onion"
+    pub fn from_hostname(hostname: &str) -> Option<OnionAddress> {
+        let hostname = hostname.to_lowercase();
+        let label = if hostname.ends_with(ONION_SUFFIX) {
+            &hostname[0..(hostname.len() - ONION_SUFFIX.len())]
+        } else {
+            &hostname[..]
+        };
+
+        if label.len() != ONION_V3_HOSTNAME_LEN {
+            return None;
+        }
+
+        let bytes = base32_decode(label)?;
+        if bytes.len() != 35 || bytes[34] != ONION_V3_VERSION {
+            return None;
+        }
+
+        let mut pubkey = [0u8; 32];
+        pubkey.copy_from_slice(&bytes[0..32]);
+        if OnionAddress::checksum(&pubkey) != [bytes[32], bytes[33]] {
+            return None;
+        }
+
+        Some(OnionAddress(pubkey))
+    }
+
+    /// Encode as a v3 onion host name, including the trailing ".onion"
+    pub fn to_hostname(&self) -> String {
+        let mut bytes = Vec::with_capacity(35);
+        bytes.extend_from_slice(&self.0);
+        bytes.extend_from_slice(&OnionAddress::checksum(&self.0));
+        bytes.push(ONION_V3_VERSION);
+        format!("{}{}", base32_encode(&bytes), ONION_SUFFIX)
+    }
+
+    /// Get the host to hand to a proxy in order to reach this onion service
+    pub fn to_peer_host(&self, port: u16) -> PeerHost {
+        PeerHost::DNS(self.to_hostname(), port)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::cmp;
+    use std::collections::VecDeque;
+    use std::io;
+    use std::io::Cursor;
+
+    /// A fake proxy connection: reads come from a canned reply, and writes are recorded.
+    struct MockProxyStream {
+        replies: Cursor<Vec<u8>>,
+        sent: Vec<u8>,
+    }
+
+    impl MockProxyStream {
+        fn new(replies: Vec<u8>) -> MockProxyStream {
+            MockProxyStream {
+                replies: Cursor::new(replies),
+                sent: vec![],
+            }
+        }
+    }
+
+    impl Read for MockProxyStream {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            self.replies.read(buf)
+        }
+    }
+
+    impl Write for MockProxyStream {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.sent.extend_from_slice(buf);
+            Ok(buf.len())
+        }
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    /// A fake non-blocking proxy connection: the proxy's replies arrive in chunks with a
+    /// would-block in between each one, and writes go through one byte at a time.
+    struct NonBlockingProxyStream {
+        chunks: VecDeque<Vec<u8>>,
+        read_blocked: bool,
+        write_blocked: bool,
+        sent: Vec<u8>,
+    }
+
+    impl NonBlockingProxyStream {
+        fn new(chunks: Vec<Vec<u8>>) -> NonBlockingProxyStream {
+            NonBlockingProxyStream {
+                chunks: chunks.into_iter().collect(),
+                read_blocked: true,
+                write_blocked: true,
+                sent: vec![],
+            }
+        }
+    }
+
+    impl Read for NonBlockingProxyStream {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            self.read_blocked = !self.read_blocked;
+            if self.read_blocked {
+                return Err(io::Error::from(io::ErrorKind::WouldBlock));
+            }
+            let mut chunk = match self.chunks.pop_front() {
+                Some(chunk) => chunk,
+                None => {
+                    return Ok(0);
+                }
+            };
+            let num_read = cmp::min(buf.len(), chunk.len());
+            buf[0..num_read].copy_from_slice(&chunk[0..num_read]);
+            if num_read < chunk.len() {
+                self.chunks.push_front(chunk.split_off(num_read));
+                self.read_blocked = true;
+            }
+            Ok(num_read)
+        }
+    }
+
+    impl Write for NonBlockingProxyStream {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.write_blocked = !self.write_blocked;
+            if self.write_blocked {
+                return Err(io::Error::from(io::ErrorKind::WouldBlock));
+            }
+            self.sent.push(buf[0]);
+            Ok(1)
+        }
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    /// Drive a connector until it connects or fails, counting how many times it had to wait.
+    fn run_connector(
+        connector: &mut Socks5Connector,
+        stream: &mut NonBlockingProxyStream,
+    ) -> Result<usize, net_error> {
+        let mut num_waits = 0;
+        while !connector.try_advance(stream)? {
+            num_waits += 1;
+            assert!(num_waits < 1000);
+        }
+        Ok(num_waits)
+    }
+
+    #[test]
+    fn onion_hostname_codec() {
+        let hostname = "duckduckgogg42xjoc72x3sjasowoarfbgcmvfimaftt6twagswzczad.onion";
+        let onion = OnionAddress::from_hostname(hostname).unwrap();
+        assert_eq!(
+            onion,
+            OnionAddress::from_hex(
+                "1d04a1d04a338c6e6ae970bfabee49049d6702250984ca950c01673f4ec034ad"
+            )
+            .unwrap()
+        );
+        assert_eq!(onion.to_hostname(), hostname);
+
+        // suffix and case are optional
+        assert_eq!(
+            OnionAddress::from_hostname(&hostname[0..56].to_uppercase()),
+            Some(onion.clone())
+        );
+
+        // bad checksum
+        assert!(OnionAddress::from_hostname(
+            "euckduckgogg42xjoc72x3sjasowoarfbgcmvfimaftt6twagswzczad.onion"
+        )
+        .is_none());
+
+        // bad version
+        assert!(OnionAddress::from_hostname(
+            "duckduckgogg42xjoc72x3sjasowoarfbgcmvfimaftt6twagswzczaa.onion"
+        )
+        .is_none());
+
+        // bad length
+        assert!(OnionAddress::from_hostname("duckduckgogg42xj.onion").is_none());
+
+        // bad alphabet
+        assert!(OnionAddress::from_hostname(
+            "duckduckgogg42xjoc72x3sjasowoarfbgcmvfimaftt6twagswzcza1.onion"
+        )
+        .is_none());
+
+        // stand-in address
+        let addrbytes = PeerAddress::from_onion(&onion);
+        assert!(addrbytes.is_onion());
+        assert!(addrbytes.is_onion_of(&onion));
+        assert!(!addrbytes.is_ipv4());
+        assert!(!PeerAddress::from_ipv4(127, 0, 0, 1).is_onion());
+    }
+
+    #[test]
+    fn socks5_connect_domain() {
+        let mut stream = MockProxyStream::new(vec![
+            // method selection
+            0x05, 0x00, // reply: succeeded, bound to 127.0.0.1:8080
+            0x05, 0x00, 0x00, 0x01, 0x7f, 0x00, 0x00, 0x01, 0x1f, 0x90,
+        ]);
+        let dest = PeerHost::DNS("example.com".to_string(), 20444);
+        let bound = socks5_handshake(&mut stream, SOCKS5_CMD_CONNECT, &dest).unwrap();
+        assert_eq!(
+            bound,
+            PeerHost::IP(PeerAddress::from_ipv4(127, 0, 0, 1), 8080)
+        );
+
+        let mut expected = vec![
+            // method selection
+            0x05, 0x01, 0x00, // connect request
+            0x05, 0x01, 0x00, 0x03, 0x0b,
+        ];
+        expected.extend_from_slice(b"example.com");
+        expected.extend_from_slice(&[0x4f, 0xdc]);
+        assert_eq!(stream.sent, expected);
+    }
+
+    #[test]
+    fn socks5_connect_ip() {
+        let mut stream = MockProxyStream::new(vec![
+            0x05, 0x00, 0x05, 0x00, 0x00, 0x01, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+        ]);
+        let dest = PeerHost::IP(PeerAddress::from_ipv4(1, 2, 3, 4), 20444);
+        socks5_handshake(&mut stream, SOCKS5_CMD_CONNECT, &dest).unwrap();
+        assert_eq!(
+            stream.sent,
+            vec![0x05, 0x01, 0x00, 0x05, 0x01, 0x00, 0x01, 0x01, 0x02, 0x03, 0x04, 0x4f, 0xdc]
+        );
+
+        // stand-in onion addresses can't be dialed
+        let mut stream = MockProxyStream::new(vec![]);
+        let dest = PeerHost::IP(PeerAddress::from_onion(&OnionAddress([0x11; 32])), 20444);
+        match socks5_handshake(&mut stream, SOCKS5_CMD_CONNECT, &dest) {
+            Err(net_error::ProxyError(_)) => {}
+            x => panic!("Expected proxy error, got {:?}", &x),
+        }
+        assert_eq!(stream.sent.len(), 0);
+    }
+
+    #[test]
+    fn socks5_connect_failures() {
+        // proxy wants authentication
+        let mut stream = MockProxyStream::new(vec![0x05, 0xff]);
+        let dest = PeerHost::DNS("example.com".to_string(), 20444);
+        match socks5_handshake(&mut stream, SOCKS5_CMD_CONNECT, &dest) {
+            Err(net_error::ProxyError(_)) => {}
+            x => panic!("Expected proxy error, got {:?}", &x),
+        }
+
+        // connection refused
+        let mut stream = MockProxyStream::new(vec![
+            0x05, 0x00, 0x05, 0x05, 0x00, 0x01, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+        ]);
+        match socks5_handshake(&mut stream, SOCKS5_CMD_CONNECT, &dest) {
+            Err(net_error::ProxyError(_)) => {}
+            x => panic!("Expected proxy error, got {:?}", &x),
+        }
+
+        // proxy hung up
+        let mut stream = MockProxyStream::new(vec![0x05, 0x00, 0x05]);
+        match socks5_handshake(&mut stream, SOCKS5_CMD_CONNECT, &dest) {
+            Err(net_error::ProxyError(_)) => {}
+            x => panic!("Expected proxy error, got {:?}", &x),
+        }
+    }
+
+    #[test]
+    fn socks5_connector_split_replies() {
+        // reply bound to a domain name, split at awkward places
+        let dest = PeerHost::DNS("example.com".to_string(), 20444);
+        let mut stream = NonBlockingProxyStream::new(vec![
+            vec![0x05],
+            vec![0x00, 0x05, 0x00],
+            vec![0x00, 0x03],
+            vec![0x09, b'l', b'o', b'c'],
+            vec![b'a', b'l', b'h', b'o', b's', b't', 0x1f],
+            vec![0x90],
+        ]);
+        let mut connector = Socks5Connector::new(&dest).unwrap();
+        assert!(!connector.is_connected());
+
+        let num_waits = run_connector(&mut connector, &mut stream).unwrap();
+        assert!(num_waits > 0);
+        assert!(connector.is_connected());
+
+        let mut expected = vec![0x05, 0x01, 0x00, 0x05, 0x01, 0x00, 0x03, 0x0b];
+        expected.extend_from_slice(b"example.com");
+        expected.extend_from_slice(&[0x4f, 0xdc]);
+        assert_eq!(stream.sent, expected);
+
+        // the connector didn't consume anything past the proxy's reply
+        assert_eq!(stream.chunks.len(), 0);
+
+        // a connected connector has nothing more to do
+        assert!(connector.try_advance(&mut stream).unwrap());
+    }
+
+    #[test]
+    fn socks5_connector_leaves_peer_data() {
+        // the first bytes from the peer arrive right behind the proxy's reply
+        let dest = PeerHost::IP(PeerAddress::from_ipv4(1, 2, 3, 4), 20444);
+        let mut stream = NonBlockingProxyStream::new(vec![vec![
+            0x05, 0x00, 0x05, 0x00, 0x00, 0x01, 0x7f, 0x00, 0x00, 0x01, 0x1f, 0x90, 0xaa, 0xbb,
+        ]]);
+        let mut connector = Socks5Connector::new(&dest).unwrap();
+        run_connector(&mut connector, &mut stream).unwrap();
+        assert!(connector.is_connected());
+        assert_eq!(stream.chunks, vec![vec![0xaa, 0xbb]]);
+    }
+
+    #[test]
+    fn socks5_connector_failures() {
+        let dest = PeerHost::DNS("example.com".to_string(), 20444);
+
+        // proxy wants authentication
+        let mut stream = NonBlockingProxyStream::new(vec![vec![0x05, 0xff]]);
+        let mut connector = Socks5Connector::new(&dest).unwrap();
+        match run_connector(&mut connector, &mut stream) {
+            Err(net_error::ProxyError(_)) => {}
+            x => panic!("Expected proxy error, got {:?}", &x),
+        }
+
+        // host unreachable
+        let mut stream = NonBlockingProxyStream::new(vec![
+            vec![0x05, 0x00],
+            vec![0x05, 0x04, 0x00, 0x01, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00],
+        ]);
+        let mut connector = Socks5Connector::new(&dest).unwrap();
+        match run_connector(&mut connector, &mut stream) {
+            Err(net_error::ProxyError(_)) => {}
+            x => panic!("Expected proxy error, got {:?}", &x),
+        }
+        assert!(!connector.is_connected());
+
+        // proxy hung up partway through its reply
+        let mut stream =
+            NonBlockingProxyStream::new(vec![vec![0x05, 0x00], vec![0x05, 0x00, 0x00]]);
+        let mut connector = Socks5Connector::new(&dest).unwrap();
+        match run_connector(&mut connector, &mut stream) {
+            Err(net_error::ProxyError(_)) => {}
+            x => panic!("Expected proxy error, got {:?}", &x),
+        }
+        assert!(!connector.is_connected());
+
+        // stand-in onion addresses can't be dialed
+        let dest = PeerHost::IP(PeerAddress::from_onion(&OnionAddress([0x11; 32])), 20444);
+        match Socks5Connector::new(&dest) {
+            Err(net_error::ProxyError(_)) => {}
+            x => panic!("Expected proxy error, got {:?}", &x),
+        }
+    }
+}
@@ -29,13 +29,42 @@ pub mod strings;
 pub mod uint;
 pub mod vrf;
 
+#[cfg(test)]
+use std::cell::Cell;
 use std::error;
 use std::fmt;
 use std::thread;
 use std::time;
 use std::time::{SystemTime, UNIX_EPOCH};
 
+#[cfg(test)]
+thread_local! {
+    /// Virtual wall-clock time, in milliseconds since the epoch.  When set, this thread reads the
+    /// time from here instead of from the system clock (used by the network simulator).
+    static VIRTUAL_TIME_MS: Cell<Option<u128>> = Cell::new(None);
+}
+
+/// Set (or clear) this thread's virtual wall-clock time.
+#[cfg(test)]
+pub fn set_virtual_time_ms(time_ms: Option<u128>) -> () {
+    VIRTUAL_TIME_MS.with(|t| t.set(time_ms));
+}
+
+#[cfg(test)]
+fn get_virtual_time_ms() -> Option<u128> {
+    VIRTUAL_TIME_MS.with(|t| t.get())
+}
+
+#[cfg(not(test))]
+#[inline]
+fn get_virtual_time_ms() -> Option<u128> {
+    None
+}
+
 pub fn get_epoch_time_secs() -> u64 {
+    if let Some(time_ms) = get_virtual_time_ms() {
+        return (time_ms / 1000) as u64;
+    }
     let start = SystemTime::now();
     let since_the_epoch = start
         .duration_since(UNIX_EPOCH)
@@ -44,6 +73,9 @@ pub fn get_epoch_time_secs() -> u64 {
 }
 
 pub fn get_epoch_time_ms() -> u128 {
+    if let Some(time_ms) = get_virtual_time_ms() {
+        return time_ms;
+    }
     let start = SystemTime::now();
     let since_the_epoch = start
         .duration_since(UNIX_EPOCH)
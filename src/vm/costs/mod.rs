@@ -1033,6 +1033,29 @@ impl ExecutionCost {
             || self.read_length > other.read_length
     }
 
+    /// Returns the largest fraction of `limit` consumed by any one dimension of this cost,
+    ///  in parts per `resolution`.  A zero dimension of `limit` is treated as 1.
+    pub fn proportion_largest_dimension(&self, limit: &ExecutionCost, resolution: u64) -> u64 {
+        let proportion = |cost: u64, limit: u64| -> u128 {
+            (cost as u128) * (resolution as u128) / (cmp::max(limit, 1) as u128)
+        };
+        let largest = [
+            proportion(self.runtime, limit.runtime),
+            proportion(self.write_length, limit.write_length),
+            proportion(self.write_count, limit.write_count),
+            proportion(self.read_count, limit.read_count),
+            proportion(self.read_length, limit.read_length),
+        ]
+        .iter()
+        .fold(0, |largest, p| cmp::max(largest, *p));
+
+        if largest > u64::max_value() as u128 {
+            u64::max_value()
+        } else {
+            largest as u64
+        }
+    }
+
     pub fn max_cost(first: ExecutionCost, second: ExecutionCost) -> ExecutionCost {
         Self {
             runtime: first.runtime.max(second.runtime),
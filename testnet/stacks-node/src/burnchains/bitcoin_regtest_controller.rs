@@ -61,7 +61,45 @@ pub struct BitcoinRegtestController {
     burnchain_config: Option<Burnchain>,
     last_utxos: Vec<UTXO>,
     last_tx_len: u64,
-    min_relay_fee: u64, // satoshis/byte
+    min_relay_fee: u64, // satoshis per 1000 bytes
+    burn_tip_height: u64,
+    ongoing_block_commit: Option<OngoingBlockCommit>,
+}
+
+/// The last block-commit this miner sent, kept around so that it can be re-sent with a higher
+/// fee if it gets stuck in the mempool.
+#[derive(Clone)]
+struct OngoingBlockCommit {
+    payload: LeaderBlockCommitOp,
+    utxos: Vec<UTXO>,
+    txid: Sha256dHash,
+    tx_fee: u64,
+    tx_len: u64,
+    /// height of the burnchain tip when the commit was first sent.  The commit is for the
+    /// sortition of the next burnchain block.
+    burn_height: u64,
+    sent_at: Instant,
+    num_rbf: u64,
+}
+
+impl OngoingBlockCommit {
+    /// fee rate paid by the transaction, in satoshis per byte (rounded up)
+    fn fee_rate(&self) -> u64 {
+        if self.tx_len == 0 {
+            return self.tx_fee;
+        }
+        (self.tx_fee + self.tx_len - 1) / self.tx_len
+    }
+
+    /// fee a transaction replacing this one has to pay, if it raises the fee rate by
+    /// `fee_rate_increment` satoshis per byte.  Per BIP-125, the replacement also has to pay for
+    /// its own bytes at the minimum relay fee (`min_relay_fee`, in satoshis per 1000 bytes) on
+    /// top of what this transaction paid.
+    fn replacement_fee(&self, fee_rate_increment: u64, min_relay_fee: u64) -> u64 {
+        let bumped_fee = (self.fee_rate() + fee_rate_increment) * self.tx_len;
+        let min_fee = self.tx_fee + (self.tx_len * min_relay_fee + 999) / 1000;
+        cmp::max(bumped_fee, min_fee)
+    }
 }
 
 const DUST_UTXO_LIMIT: u64 = 5500;
@@ -123,6 +161,8 @@ impl BitcoinRegtestController {
             last_utxos: vec![],
             last_tx_len: 0,
             min_relay_fee: 1024, // TODO: learn from bitcoind
+            burn_tip_height: 0,
+            ongoing_block_commit: None,
         }
     }
 
@@ -160,6 +200,8 @@ impl BitcoinRegtestController {
             last_utxos: vec![],
             last_tx_len: 0,
             min_relay_fee: 1024, // TODO: learn from bitcoind
+            burn_tip_height: 0,
+            ongoing_block_commit: None,
         }
     }
 
@@ -346,6 +388,7 @@ impl BitcoinRegtestController {
         };
 
         self.chain_tip = Some(burnchain_tip.clone());
+        self.set_burn_tip_height(burnchain_tip.block_snapshot.block_height);
         debug!("Done receiving blocks");
 
         Ok((burnchain_tip, burnchain_height))
@@ -559,7 +602,8 @@ impl BitcoinRegtestController {
 
         tx.output.push(identifier_output);
 
-        self.finalize_tx(&mut tx, DUST_UTXO_LIMIT, utxos, signer, attempt)?;
+        let tx_fee = self.tx_fee_for_attempt(attempt);
+        self.finalize_tx(&mut tx, DUST_UTXO_LIMIT, utxos, signer, tx_fee)?;
 
         increment_btc_ops_sent_counter();
 
@@ -660,7 +704,8 @@ impl BitcoinRegtestController {
         tx.output
             .push(payload.recipient.to_bitcoin_tx_out(DUST_UTXO_LIMIT));
 
-        self.finalize_tx(&mut tx, DUST_UTXO_LIMIT, utxos, signer, 1)?;
+        let tx_fee = self.tx_fee_for_attempt(1);
+        self.finalize_tx(&mut tx, DUST_UTXO_LIMIT, utxos, signer, tx_fee)?;
 
         increment_btc_ops_sent_counter();
 
@@ -710,7 +755,8 @@ impl BitcoinRegtestController {
         tx.output = vec![consensus_output];
         tx.output.push(payload.output.to_bitcoin_tx_out(output_amt));
 
-        self.finalize_tx(&mut tx, output_amt, utxos, signer, 1)?;
+        let tx_fee = self.tx_fee_for_attempt(1);
+        self.finalize_tx(&mut tx, output_amt, utxos, signer, tx_fee)?;
 
         increment_btc_ops_sent_counter();
 
@@ -730,8 +776,51 @@ impl BitcoinRegtestController {
     ) -> Option<Transaction> {
         let public_key = signer.get_public_key();
 
-        let (mut tx, utxos) = self.prepare_tx(&public_key, payload.burn_fee, attempt)?;
+        let (tx, utxos) = self.prepare_tx(&public_key, payload.burn_fee, attempt)?;
+
+        let mut tx_fee = self.tx_fee_for_attempt(attempt);
+        if attempt > 1 {
+            if let Some(ref ongoing) = self.ongoing_block_commit {
+                // we're replacing our last block-commit, which may have already been re-sent at
+                // a higher fee than this attempt would pay.
+                tx_fee = cmp::max(tx_fee, ongoing.replacement_fee(0, self.min_relay_fee));
+            }
+        }
+
+        let tx =
+            self.finalize_leader_block_commit_tx(tx, &payload, utxos.clone(), signer, tx_fee)?;
+
+        self.ongoing_block_commit = Some(OngoingBlockCommit {
+            payload: payload.clone(),
+            utxos,
+            txid: tx.txid(),
+            tx_fee,
+            tx_len: self.last_tx_len,
+            burn_height: self.burn_tip_height,
+            sent_at: Instant::now(),
+            num_rbf: 0,
+        });
+
+        increment_btc_ops_sent_counter();
+
+        info!(
+            "Miner node: submitting leader_block_commit op for {} - {}",
+            &payload.block_header_hash,
+            public_key.to_hex()
+        );
+
+        Some(tx)
+    }
 
+    /// Add the block-commit outputs to `tx`, and sign it so it spends `utxos` and pays `tx_fee`.
+    fn finalize_leader_block_commit_tx(
+        &mut self,
+        mut tx: Transaction,
+        payload: &LeaderBlockCommitOp,
+        utxos: Vec<UTXO>,
+        signer: &mut BurnchainOpSigner,
+        tx_fee: u64,
+    ) -> Option<Transaction> {
         // Serialize the payload
         let op_bytes = {
             let mut buffer = vec![];
@@ -774,18 +863,140 @@ impl BitcoinRegtestController {
             payload.burn_fee + sunset_burn,
             utxos,
             signer,
-            attempt,
+            tx_fee,
         )?;
 
+        Some(tx)
+    }
+
+    /// Record the height of the current burnchain tip.  Our ongoing block-commit was sent for
+    /// the sortition of the block after the tip at the time; once the tip moves past that, the
+    /// sortition is closed -- the commit either made it in or can no longer win -- so we stop
+    /// tracking it.
+    pub fn set_burn_tip_height(&mut self, burn_tip_height: u64) {
+        self.burn_tip_height = burn_tip_height;
+        let closed = match self.ongoing_block_commit {
+            Some(ref ongoing) => ongoing.burn_height < burn_tip_height,
+            None => false,
+        };
+        if closed {
+            debug!("Sortition for our ongoing block-commit has closed; no longer tracking it");
+            self.ongoing_block_commit = None;
+        }
+    }
+
+    /// Re-send our last block-commit at a higher fee rate if it has gone unconfirmed for longer
+    /// than `burnchain.block_commit_rbf_timeout_secs`.  The replacement spends the same UTXOs
+    /// and carries the same payload, so it commits to the same block in the same sortition.
+    /// A commit that has already been mined is forgotten instead of replaced.
+    /// Returns true if a replacement transaction was sent.
+    pub fn rbf_ongoing_block_commit(&mut self, signer: &mut BurnchainOpSigner) -> bool {
+        let ongoing = match self.ongoing_block_commit {
+            Some(ref ongoing) => ongoing.clone(),
+            None => return false,
+        };
+
+        if ongoing.sent_at.elapsed().as_secs() < self.config.burnchain.block_commit_rbf_timeout_secs
+        {
+            return false;
+        }
+
+        match BitcoinRPCRequest::get_transaction_confirmations(&self.config, &ongoing.txid) {
+            Ok(0) => {}
+            Ok(confirmations) => {
+                // either mined, or (if negative) conflicting with a mined transaction
+                debug!(
+                    "Block-commit {} has {} confirmations; no longer tracking it",
+                    &ongoing.txid, confirmations
+                );
+                self.ongoing_block_commit = None;
+                return false;
+            }
+            Err(e) => {
+                warn!(
+                    "Failed to check whether block-commit {} is confirmed: {:?}",
+                    &ongoing.txid, e
+                );
+                return false;
+            }
+        }
+
+        let (tx, tx_fee) = match self.build_rbf_block_commit_tx(&ongoing, signer) {
+            Some(x) => x,
+            None => return false,
+        };
+        let txid = tx.txid();
+
+        if !self.send_transaction(SerializedTx::new(tx)) {
+            warn!(
+                "Failed to re-send block-commit {} as {}",
+                &ongoing.txid, &txid
+            );
+            return false;
+        }
+
         increment_btc_ops_sent_counter();
 
+        let replacement = OngoingBlockCommit {
+            txid,
+            tx_fee,
+            tx_len: self.last_tx_len,
+            sent_at: Instant::now(),
+            num_rbf: ongoing.num_rbf + 1,
+            ..ongoing.clone()
+        };
+
         info!(
-            "Miner node: submitting leader_block_commit op for {} - {}",
-            &payload.block_header_hash,
-            public_key.to_hex()
+            "Miner node: re-sent stuck leader_block_commit op for {}",
+            &ongoing.payload.block_header_hash;
+            "old_txid" => %ongoing.txid,
+            "new_txid" => %replacement.txid,
+            "old_fee_rate" => ongoing.fee_rate(),
+            "new_fee_rate" => replacement.fee_rate(),
+            "rbf_count" => replacement.num_rbf
         );
 
-        Some(tx)
+        self.ongoing_block_commit = Some(replacement);
+        true
+    }
+
+    /// Build a transaction that replaces `ongoing`: same payload, same UTXOs, and a fee rate
+    /// `burnchain.rbf_fee_increment` satoshis per byte higher.  Returns the transaction and the
+    /// fee it pays, or None if the new fee rate would exceed `burnchain.max_rbf_fee_rate`.
+    fn build_rbf_block_commit_tx(
+        &mut self,
+        ongoing: &OngoingBlockCommit,
+        signer: &mut BurnchainOpSigner,
+    ) -> Option<(Transaction, u64)> {
+        let tx_fee =
+            ongoing.replacement_fee(self.config.burnchain.rbf_fee_increment, self.min_relay_fee);
+        let fee_rate = OngoingBlockCommit {
+            tx_fee,
+            ..ongoing.clone()
+        }
+        .fee_rate();
+        if fee_rate > self.config.burnchain.max_rbf_fee_rate {
+            debug!(
+                "Not re-sending block-commit {}: fee rate {} would exceed the maximum of {}",
+                &ongoing.txid, fee_rate, self.config.burnchain.max_rbf_fee_rate
+            );
+            return None;
+        }
+
+        let tx = Transaction {
+            input: vec![],
+            output: vec![],
+            version: 1,
+            lock_time: 0,
+        };
+        let tx = self.finalize_leader_block_commit_tx(
+            tx,
+            &ongoing.payload,
+            ongoing.utxos.clone(),
+            signer,
+            tx_fee,
+        )?;
+        Some((tx, tx_fee))
     }
 
     fn prepare_tx(
@@ -825,22 +1036,25 @@ impl BitcoinRegtestController {
         Some((transaction, utxos))
     }
 
+    /// Transaction fee to pay on the given attempt at sending an operation.  Each attempt after
+    /// the first replaces the last one (RBF), so it has to pay more.
+    fn tx_fee_for_attempt(&self, attempt: u64) -> u64 {
+        self.config.burnchain.burnchain_op_tx_fee
+            + ((attempt.saturating_sub(1) * self.last_tx_len * self.min_relay_fee) / 1000)
+    }
+
     fn finalize_tx(
         &mut self,
         tx: &mut Transaction,
         total_spent: u64,
         mut utxos: Vec<UTXO>,
         signer: &mut BurnchainOpSigner,
-        attempt: u64,
+        tx_fee: u64,
     ) -> Option<()> {
         // spend UTXOs in decreasing order
         utxos.sort_by(|u1, u2| u1.amount.cmp(&u2.amount));
         utxos.reverse();
 
-        // RBF
-        let tx_fee = self.config.burnchain.burnchain_op_tx_fee
-            + ((attempt.saturating_sub(1) * self.last_tx_len * self.min_relay_fee) / 1000);
-
         let public_key = signer.get_public_key();
         let mut total_consumed = 0;
 
@@ -866,7 +1080,10 @@ impl BitcoinRegtestController {
             return None;
         }
         let value = total_consumed - total_spent - tx_fee;
-        debug!("Payments value: {:?}, total_consumed: {:?}, total_spent: {:?}, tx_fee: {:?}, attempt: {:?}", value, total_consumed, total_spent, tx_fee, attempt);
+        debug!(
+            "Payments value: {:?}, total_consumed: {:?}, total_spent: {:?}, tx_fee: {:?}",
+            value, total_consumed, total_spent, tx_fee
+        );
         if value >= DUST_UTXO_LIMIT {
             let change_output = BitcoinAddress::to_p2pkh_tx_out(&change_address_hash, value);
             tx.output.push(change_output);
@@ -1121,6 +1338,8 @@ impl BurnchainController for BitcoinRegtestController {
         op_signer: &mut BurnchainOpSigner,
         attempt: u64,
     ) -> bool {
+        // if this block-commit doesn't make it out, our last one is still the one in flight
+        let ongoing_block_commit = self.ongoing_block_commit.clone();
         let transaction = match operation {
             BlockstackOperationType::LeaderBlockCommit(payload) => {
                 self.build_leader_block_commit_tx(payload, op_signer, attempt)
@@ -1142,10 +1361,17 @@ impl BurnchainController for BitcoinRegtestController {
 
         let transaction = match transaction {
             Some(tx) => SerializedTx::new(tx),
-            _ => return false,
+            _ => {
+                self.ongoing_block_commit = ongoing_block_commit;
+                return false;
+            }
         };
 
-        self.send_transaction(transaction)
+        if !self.send_transaction(transaction) {
+            self.ongoing_block_commit = ongoing_block_commit;
+            return false;
+        }
+        true
     }
 
     #[cfg(test)]
@@ -1439,6 +1665,36 @@ impl BitcoinRPCRequest {
         Ok(())
     }
 
+    /// Number of confirmations of the wallet transaction `txid`: 0 while it is in the mempool,
+    /// and negative if it conflicts with a transaction that was mined.
+    pub fn get_transaction_confirmations(config: &Config, txid: &Sha256dHash) -> RPCResult<i64> {
+        let include_watchonly = true;
+        let payload = BitcoinRPCRequest {
+            method: "gettransaction".to_string(),
+            params: vec![txid.to_string().into(), include_watchonly.into()],
+            id: "stacks".to_string(),
+            jsonrpc: "2.0".to_string(),
+        };
+
+        let json_resp = BitcoinRPCRequest::send(&config, payload)?;
+
+        if let Some(e) = json_resp.get("error") {
+            if !e.is_null() {
+                return Err(RPCError::Bitcoind(json_resp.to_string()));
+            }
+        }
+        json_resp
+            .get("result")
+            .and_then(|result| result.get("confirmations"))
+            .and_then(|confirmations| confirmations.as_i64())
+            .ok_or_else(|| {
+                RPCError::Parsing(format!(
+                    "Bitcoin RPC: no confirmations in gettransaction response: {}",
+                    json_resp
+                ))
+            })
+    }
+
     pub fn import_public_key(config: &Config, public_key: &Secp256k1PublicKey) -> RPCResult<()> {
         let rescan = true;
         let label = "";
@@ -1531,3 +1787,219 @@ impl BitcoinRPCRequest {
         Ok(payload)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use stacks::address::AddressHashMode;
+    use stacks::burnchains::{BurnchainSigner, Txid};
+    use stacks::chainstate::burn::{BlockHeaderHash, VRFSeed};
+    use stacks::chainstate::stacks::{StacksAddress, StacksPublicKey};
+    use stacks::util::secp256k1::Secp256k1PrivateKey;
+
+    fn make_controller() -> BitcoinRegtestController {
+        let mut config = Config::default();
+        config.burnchain.mode = "neon".to_string();
+        BitcoinRegtestController::new_dummy(config)
+    }
+
+    fn make_utxo(seed: u8, amount: u64, public_key: &Secp256k1PublicKey) -> UTXO {
+        UTXO {
+            txid: Sha256dHash::from_data(&[seed]),
+            vout: seed as u32,
+            script_pub_key: BitcoinAddress::to_p2pkh_tx_out(
+                &Hash160::from_data(&public_key.to_bytes()),
+                0,
+            )
+            .script_pubkey,
+            amount,
+        }
+    }
+
+    fn make_block_commit(public_key: &Secp256k1PublicKey) -> LeaderBlockCommitOp {
+        LeaderBlockCommitOp {
+            sunset_burn: 0,
+            block_header_hash: BlockHeaderHash([0x22; 32]),
+            burn_fee: 20000,
+            input: (Txid([0; 32]), 0),
+            apparent_sender: BurnchainSigner {
+                hash_mode: AddressHashMode::SerializeP2PKH,
+                num_sigs: 1,
+                public_keys: vec![StacksPublicKey::from_slice(&public_key.to_bytes()).unwrap()],
+            },
+            key_block_ptr: 1,
+            key_vtxindex: 1,
+            memo: vec![],
+            new_seed: VRFSeed([0x33; 32]),
+            parent_block_ptr: 2,
+            parent_vtxindex: 1,
+            vtxindex: 0,
+            txid: Txid([0u8; 32]),
+            block_height: 0,
+            burn_header_hash: BurnchainHeaderHash::zero(),
+            burn_parent_modulus: 0,
+            commit_outs: vec![
+                StacksAddress::burn_address(false),
+                StacksAddress::burn_address(false),
+            ],
+        }
+    }
+
+    /// Sign and record a block-commit the way `build_leader_block_commit_tx` does, without
+    /// asking bitcoind for UTXOs.
+    fn send_block_commit(
+        controller: &mut BitcoinRegtestController,
+        secret_key: &Secp256k1PrivateKey,
+        utxos: Vec<UTXO>,
+        tx_fee: u64,
+    ) -> Transaction {
+        let mut signer = BurnchainOpSigner::new(secret_key.clone(), false);
+        let payload = make_block_commit(&signer.get_public_key());
+        let tx = Transaction {
+            input: vec![],
+            output: vec![],
+            version: 1,
+            lock_time: 0,
+        };
+        let tx = controller
+            .finalize_leader_block_commit_tx(tx, &payload, utxos.clone(), &mut signer, tx_fee)
+            .unwrap();
+        controller.ongoing_block_commit = Some(OngoingBlockCommit {
+            payload,
+            utxos,
+            txid: tx.txid(),
+            tx_fee,
+            tx_len: controller.last_tx_len,
+            burn_height: controller.burn_tip_height,
+            sent_at: Instant::now(),
+            num_rbf: 0,
+        });
+        tx
+    }
+
+    #[test]
+    fn test_block_commit_replacement_fee() {
+        let secret_key = Secp256k1PrivateKey::new();
+        let public_key = Secp256k1PublicKey::from_private(&secret_key);
+        let ongoing = OngoingBlockCommit {
+            payload: make_block_commit(&public_key),
+            utxos: vec![],
+            txid: Sha256dHash::from_data(&[0]),
+            tx_fee: 10000,
+            tx_len: 300,
+            burn_height: 0,
+            sent_at: Instant::now(),
+            num_rbf: 0,
+        };
+
+        // 10000 sats over 300 bytes rounds up to 34 sats/byte
+        assert_eq!(ongoing.fee_rate(), 34);
+        // bumping the fee rate by 5 sats/byte pays 39 * 300 sats ...
+        assert_eq!(ongoing.replacement_fee(5, 1024), 11700);
+        // ... but a replacement always pays for its bytes at the minimum relay fee, which is
+        // quoted per 1000 bytes
+        assert_eq!(ongoing.replacement_fee(0, 1024), 10000 + 308);
+        assert_eq!(ongoing.replacement_fee(1, 10000), 10000 + 3000);
+    }
+
+    #[test]
+    fn test_rbf_block_commit_reuses_utxos() {
+        let mut controller = make_controller();
+        let secret_key = Secp256k1PrivateKey::new();
+        let public_key = Secp256k1PublicKey::from_private(&secret_key);
+        let utxos = vec![
+            make_utxo(1, 50000, &public_key),
+            make_utxo(2, 100000, &public_key),
+        ];
+
+        let tx = send_block_commit(&mut controller, &secret_key, utxos.clone(), 10000);
+        let ongoing = controller.ongoing_block_commit.clone().unwrap();
+        assert_eq!(
+            ongoing.tx_len,
+            SerializedTx::new(tx.clone()).bytes.len() as u64
+        );
+
+        let mut signer = BurnchainOpSigner::new(secret_key.clone(), false);
+        let (replacement, tx_fee) = controller
+            .build_rbf_block_commit_tx(&ongoing, &mut signer)
+            .unwrap();
+
+        assert_eq!(
+            tx_fee,
+            ongoing.replacement_fee(
+                controller.config.burnchain.rbf_fee_increment,
+                controller.min_relay_fee
+            )
+        );
+        assert!(tx_fee > ongoing.tx_fee);
+        assert_ne!(replacement.txid(), tx.txid());
+
+        // same inputs, so the replacement conflicts with (and replaces) the original
+        let outpoints = |tx: &Transaction| -> Vec<OutPoint> {
+            tx.input.iter().map(|input| input.previous_output).collect()
+        };
+        assert_eq!(outpoints(&replacement), outpoints(&tx));
+        for outpoint in outpoints(&replacement).iter() {
+            assert!(utxos
+                .iter()
+                .any(|utxo| utxo.txid == outpoint.txid && utxo.vout == outpoint.vout));
+        }
+        for input in replacement.input.iter() {
+            assert_eq!(input.sequence, 0xFFFFFFFD);
+        }
+
+        // same payload and burn outputs; only the change pays for the higher fee
+        assert_eq!(replacement.output[..3], tx.output[..3]);
+        let total_in: u64 = outpoints(&replacement)
+            .iter()
+            .map(|outpoint| {
+                utxos
+                    .iter()
+                    .find(|utxo| utxo.txid == outpoint.txid && utxo.vout == outpoint.vout)
+                    .unwrap()
+                    .amount
+            })
+            .sum();
+        let total_out: u64 = replacement.output.iter().map(|out| out.value).sum();
+        assert_eq!(total_in - total_out, tx_fee);
+
+        // no replacement once the fee rate would pass the configured maximum
+        controller.config.burnchain.max_rbf_fee_rate = ongoing.fee_rate();
+        let mut signer = BurnchainOpSigner::new(secret_key.clone(), false);
+        assert!(controller
+            .build_rbf_block_commit_tx(&ongoing, &mut signer)
+            .is_none());
+    }
+
+    #[test]
+    fn test_clear_block_commit_when_sortition_closes() {
+        let mut controller = make_controller();
+        let secret_key = Secp256k1PrivateKey::new();
+        let public_key = Secp256k1PublicKey::from_private(&secret_key);
+        let utxos = vec![make_utxo(1, 100000, &public_key)];
+
+        controller.set_burn_tip_height(100);
+        send_block_commit(&mut controller, &secret_key, utxos, 10000);
+        assert_eq!(
+            controller
+                .ongoing_block_commit
+                .as_ref()
+                .unwrap()
+                .burn_height,
+            100
+        );
+
+        // still the same burnchain tip -- the commit's sortition is open
+        controller.set_burn_tip_height(100);
+        assert!(controller.ongoing_block_commit.is_some());
+
+        // the block the commit was meant for has been processed
+        controller.set_burn_tip_height(101);
+        assert!(controller.ongoing_block_commit.is_none());
+
+        // nothing to re-send
+        let mut signer = BurnchainOpSigner::new(secret_key.clone(), false);
+        assert!(!controller.rbf_ongoing_block_commit(&mut signer));
+    }
+}
@@ -1,3 +1,4 @@
+use std::cmp;
 use std::convert::TryInto;
 use std::fs;
 use std::net::{SocketAddr, ToSocketAddrs};
@@ -5,9 +6,22 @@ use std::net::{SocketAddr, ToSocketAddrs};
 use rand::RngCore;
 
 use stacks::burnchains::bitcoin::BitcoinNetworkType;
-use stacks::burnchains::{MagicBytes, BLOCKSTACK_MAGIC_MAINNET};
+use stacks::burnchains::{Address, MagicBytes, BLOCKSTACK_MAGIC_MAINNET};
+use stacks::chainstate::burn::bidding::{
+    BurnBidBudget, BurnBidStrategy, ExpectedValueBurnBid, FixedBurnBid, MatchAverageBurnBid,
+};
+use stacks::chainstate::burn::operations::leader_block_commit::OUTPUTS_PER_COMMIT;
+use stacks::chainstate::stacks::index::storage::DEFAULT_NODE_CACHE_CAPACITY;
+use stacks::chainstate::stacks::miner::{
+    ContractAllowlistTxSelector, FeeRateTxSelector, FifoTxSelector, MinerTxSelector,
+    PriorityAddressTxSelector,
+};
+use stacks::chainstate::stacks::StacksAddress;
+use stacks::core::mempool::MemPoolSettings;
+use stacks::core::MIN_PRUNE_REWARD_CYCLES;
 use stacks::net::connection::ConnectionOptions;
-use stacks::net::{Neighbor, NeighborKey, PeerAddress};
+use stacks::net::socks;
+use stacks::net::{Neighbor, NeighborKey, OnionAddress, PeerAddress};
 use stacks::util::hash::{hex_bytes, to_hex};
 use stacks::util::secp256k1::Secp256k1PrivateKey;
 use stacks::util::secp256k1::Secp256k1PublicKey;
@@ -20,7 +34,8 @@ pub const TESTNET_PEER_VERSION: u32 = 0xfacade01;
 pub const MAINNET_CHAIN_ID: u32 = 0x00000001;
 pub const MAINNET_PEER_VERSION: u32 = 0x18000000;
 
-const MINIMUM_DUST_FEE: u64 = 5500;
+/// The least a burnchain transaction output can carry without being dust
+pub const MINIMUM_DUST_FEE: u64 = 5500;
 
 #[derive(Clone, Deserialize, Default)]
 pub struct ConfigFile {
@@ -30,6 +45,8 @@ pub struct ConfigFile {
     pub events_observer: Option<Vec<EventObserverConfigFile>>,
     pub connection_options: Option<ConnectionOptionsFile>,
     pub block_limit: Option<BlockLimitFile>,
+    pub mempool: Option<MemPoolConfigFile>,
+    pub miner: Option<MinerConfigFile>,
 }
 
 #[derive(Clone, Deserialize, Default)]
@@ -84,6 +101,107 @@ mod tests {
             "STRYYQQ9M8KAF4NS7WNZQYY59X93XEKR31JP64CP"
         );
     }
+
+    #[test]
+    fn should_load_miner_tx_selection_toml() {
+        let config = Config::from_config_file(ConfigFile::from_str(
+            r#"
+            [miner]
+            tx_selection = "fifo"
+            allowed_contracts = ["STB44HYPYAT2BB2QE513NSP81HTMYWBJP02HPGK6.payouts"]
+            priority_addresses = ["ST11NJTTKGVT6D1HY4NJRVQWMQM7TVAR091EJ8P2Y"]
+            "#,
+        ));
+        assert_eq!(config.miner.tx_selection, MinerTxSelection::Fifo);
+        assert_eq!(
+            config.miner.allowed_contracts,
+            Some(vec![QualifiedContractIdentifier::parse(
+                "STB44HYPYAT2BB2QE513NSP81HTMYWBJP02HPGK6.payouts"
+            )
+            .unwrap()])
+        );
+        assert_eq!(
+            config.miner.priority_addresses,
+            vec![StacksAddress::from_string("ST11NJTTKGVT6D1HY4NJRVQWMQM7TVAR091EJ8P2Y").unwrap()]
+        );
+
+        let config = Config::from_config_file(ConfigFile::from_str(""));
+        assert_eq!(config.miner.tx_selection, MinerTxSelection::FeeRate);
+        assert!(config.miner.allowed_contracts.is_none());
+        assert_eq!(config.miner.priority_addresses.len(), 0);
+    }
+
+    #[test]
+    fn should_load_miner_burn_bid_toml() {
+        let config = Config::from_config_file(ConfigFile::from_str(
+            r#"
+            [burnchain]
+            burn_fee_cap = 30000
+
+            [miner]
+            burn_bid_strategy = "expected_value"
+            sats_per_stx = 50
+            burn_bid_window = 6
+            min_burn_fee = 5000
+            burn_bid_dry_run = true
+            "#,
+        ));
+        assert_eq!(
+            config.miner.burn_bid,
+            MinerBurnBid::ExpectedValue { sats_per_stx: 50 }
+        );
+        assert_eq!(config.miner.burn_bid_window, 6);
+        assert!(config.miner.burn_bid_dry_run);
+        // the configured minimum is too low to give both PoX outputs the dust limit
+        let budget = config
+            .miner
+            .burn_bid_budget(config.burnchain.burn_fee_cap, 2);
+        assert_eq!(budget.min_burn, 11000);
+        assert_eq!(budget.max_burn, 30000);
+        let budget = config
+            .miner
+            .burn_bid_budget(config.burnchain.burn_fee_cap, 1);
+        assert_eq!(budget.min_burn, 5500);
+
+        let config = Config::from_config_file(ConfigFile::from_str(
+            r#"
+            [miner]
+            burn_bid_strategy = "match_average"
+            "#,
+        ));
+        assert_eq!(
+            config.miner.burn_bid,
+            MinerBurnBid::MatchAverage { bump_pct: 10 }
+        );
+
+        let config = Config::from_config_file(ConfigFile::from_str(""));
+        assert_eq!(config.miner.burn_bid, MinerBurnBid::Fixed);
+        assert_eq!(config.miner.burn_bid_window, 10);
+        assert!(!config.miner.burn_bid_dry_run);
+        assert_eq!(config.miner.min_burn_fee, 11000);
+        // the minimum never exceeds the cap
+        assert_eq!(config.miner.burn_bid_budget(8000, 2).min_burn, 8000);
+    }
+
+    #[test]
+    fn should_load_block_commit_rbf_toml() {
+        let config = Config::from_config_file(ConfigFile::from_str(
+            r#"
+            [burnchain]
+            block_commit_rbf_timeout_secs = 30
+            rbf_fee_increment = 10
+            max_rbf_fee_rate = 300
+            "#,
+        ));
+        assert_eq!(config.burnchain.block_commit_rbf_timeout_secs, 30);
+        assert_eq!(config.burnchain.rbf_fee_increment, 10);
+        assert_eq!(config.burnchain.max_rbf_fee_rate, 300);
+
+        let config = Config::from_config_file(ConfigFile::from_str(""));
+        assert_eq!(config.burnchain.block_commit_rbf_timeout_secs, 120);
+        assert_eq!(config.burnchain.rbf_fee_increment, 5);
+        assert_eq!(config.burnchain.max_rbf_fee_rate, 150);
+    }
 }
 
 impl ConfigFile {
@@ -362,6 +480,8 @@ pub struct Config {
     pub events_observers: Vec<EventObserverConfig>,
     pub connection_options: ConnectionOptions,
     pub block_limit: ExecutionCost,
+    pub mempool: MemPoolSettings,
+    pub miner: MinerConfig,
 }
 
 lazy_static! {
@@ -428,6 +548,7 @@ impl Config {
                     p2p_bind: node.p2p_bind.unwrap_or(default_node_config.p2p_bind),
                     p2p_address: node.p2p_address.unwrap_or(rpc_bind.clone()),
                     bootstrap_node: None,
+                    onion_addresses: vec![],
                     deny_nodes: vec![],
                     data_url: match node.data_url {
                         Some(data_url) => data_url,
@@ -457,6 +578,10 @@ impl Config {
                         .pox_sync_sample_secs
                         .unwrap_or(default_node_config.pox_sync_sample_secs),
                     use_test_genesis_chainstate: node.use_test_genesis_chainstate,
+                    prune_reward_cycles: node.prune_reward_cycles,
+                    marf_node_cache_bytes: node
+                        .marf_node_cache_bytes
+                        .unwrap_or(default_node_config.marf_node_cache_bytes),
                 };
                 (node_config, node.bootstrap_node, node.deny_nodes)
             }
@@ -538,6 +663,15 @@ impl Config {
                     poll_time_secs: burnchain
                         .poll_time_secs
                         .unwrap_or(default_burnchain_config.poll_time_secs),
+                    block_commit_rbf_timeout_secs: burnchain
+                        .block_commit_rbf_timeout_secs
+                        .unwrap_or(default_burnchain_config.block_commit_rbf_timeout_secs),
+                    rbf_fee_increment: burnchain
+                        .rbf_fee_increment
+                        .unwrap_or(default_burnchain_config.rbf_fee_increment),
+                    max_rbf_fee_rate: burnchain
+                        .max_rbf_fee_rate
+                        .unwrap_or(default_burnchain_config.max_rbf_fee_rate),
                 }
             }
             None => default_burnchain_config,
@@ -558,7 +692,28 @@ impl Config {
             panic!("Config is missing the setting `burnchain.local_mining_public_key` (mandatory for helium)")
         }
 
-        node.set_bootstrap_node(bootstrap_node, burnchain.chain_id, burnchain.peer_version);
+        // the bootstrap node must be resolved through the proxy, if there is one
+        let socks_proxy = config_file.connection_options.as_ref().and_then(|opts| {
+            opts.socks_proxy.as_ref().map(|proxy| {
+                (
+                    proxy
+                        .parse::<SocketAddr>()
+                        .expect("Setting connection_options.socks_proxy is not a socket address"),
+                    opts.socks_proxy_timeout.unwrap_or_else(|| {
+                        HELIUM_DEFAULT_CONNECTION_OPTIONS
+                            .socks_proxy_timeout
+                            .clone()
+                    }),
+                )
+            })
+        });
+
+        node.set_bootstrap_node(
+            bootstrap_node,
+            burnchain.chain_id,
+            burnchain.peer_version,
+            socks_proxy,
+        );
         if let Some(deny_nodes) = deny_nodes {
             node.set_deny_nodes(deny_nodes, burnchain.chain_id, burnchain.peer_version);
         }
@@ -611,7 +766,7 @@ impl Config {
             _ => (),
         };
 
-        let connection_options = match config_file.connection_options {
+        let mut connection_options = match config_file.connection_options {
             Some(opts) => {
                 let ip_addr = match opts.public_ip_address {
                     Some(public_ip_address) => {
@@ -619,7 +774,17 @@ impl Config {
                         debug!("addr.parse {:?}", addr);
                         Some((PeerAddress::from_socketaddr(&addr), addr.port()))
                     }
-                    None => None,
+                    None => match opts.public_onion_address {
+                        Some(public_onion_address) => {
+                            let (onion, port) =
+                                NodeConfig::parse_onion_address(&public_onion_address).expect(
+                                    "Setting connection_options.public_onion_address is not an onion address",
+                                );
+                            node.onion_addresses.push(onion.clone());
+                            Some((PeerAddress::from_onion(&onion), port))
+                        }
+                        None => None,
+                    },
                 };
                 let mut read_only_call_limit = HELIUM_DEFAULT_CONNECTION_OPTIONS
                     .read_only_call_limit
@@ -736,13 +901,81 @@ impl Config {
                     public_ip_address: ip_addr,
                     disable_inbound_walks: opts.disable_inbound_walks.unwrap_or(false),
                     disable_inbound_handshakes: opts.disable_inbound_handshakes.unwrap_or(false),
+                    mempool_sync_interval: opts.mempool_sync_interval.unwrap_or_else(|| {
+                        HELIUM_DEFAULT_CONNECTION_OPTIONS
+                            .mempool_sync_interval
+                            .clone()
+                    }),
+                    min_getmempool_interval: opts.min_getmempool_interval.unwrap_or_else(|| {
+                        HELIUM_DEFAULT_CONNECTION_OPTIONS
+                            .min_getmempool_interval
+                            .clone()
+                    }),
+                    disable_mempool_sync: opts.disable_mempool_sync.unwrap_or(false),
                     force_disconnect_interval: opts.force_disconnect_interval,
+                    socks_proxy: socks_proxy.map(|(proxy, _)| proxy),
+                    socks_proxy_timeout: opts.socks_proxy_timeout.unwrap_or_else(|| {
+                        HELIUM_DEFAULT_CONNECTION_OPTIONS
+                            .socks_proxy_timeout
+                            .clone()
+                    }),
+                    bandwidth_quota_period: opts.bandwidth_quota_period.unwrap_or_else(|| {
+                        HELIUM_DEFAULT_CONNECTION_OPTIONS
+                            .bandwidth_quota_period
+                            .clone()
+                    }),
+                    max_peer_upload_per_period: opts.max_peer_upload_per_period.unwrap_or_else(
+                        || {
+                            HELIUM_DEFAULT_CONNECTION_OPTIONS
+                                .max_peer_upload_per_period
+                                .clone()
+                        },
+                    ),
+                    max_peer_download_per_period: opts.max_peer_download_per_period.unwrap_or_else(
+                        || {
+                            HELIUM_DEFAULT_CONNECTION_OPTIONS
+                                .max_peer_download_per_period
+                                .clone()
+                        },
+                    ),
+                    max_peer_upload_bandwidth: opts.max_peer_upload_bandwidth.unwrap_or_else(
+                        || {
+                            HELIUM_DEFAULT_CONNECTION_OPTIONS
+                                .max_peer_upload_bandwidth
+                                .clone()
+                        },
+                    ),
+                    max_upload_per_period: opts.max_upload_per_period.unwrap_or_else(|| {
+                        HELIUM_DEFAULT_CONNECTION_OPTIONS
+                            .max_upload_per_period
+                            .clone()
+                    }),
+                    max_download_per_period: opts.max_download_per_period.unwrap_or_else(|| {
+                        HELIUM_DEFAULT_CONNECTION_OPTIONS
+                            .max_download_per_period
+                            .clone()
+                    }),
+                    headers_first_push: opts.headers_first_push.unwrap_or_else(|| {
+                        HELIUM_DEFAULT_CONNECTION_OPTIONS.headers_first_push.clone()
+                    }),
                     ..ConnectionOptions::default()
                 }
             }
             None => HELIUM_DEFAULT_CONNECTION_OPTIONS.clone(),
         };
 
+        // a pruned node can't serve old blocks, so it needs to tell its peers
+        connection_options.pruned = node.prune_reward_cycles.is_some();
+        if let Some(prune_reward_cycles) = node.prune_reward_cycles {
+            // peers assume a pruned node keeps at least this much history
+            if prune_reward_cycles < MIN_PRUNE_REWARD_CYCLES {
+                panic!(
+                    "Setting node.prune_reward_cycles must be at least {}",
+                    MIN_PRUNE_REWARD_CYCLES
+                );
+            }
+        }
+
         let block_limit = if burnchain.mode == "mainnet" || burnchain.mode == "xenon" {
             MAINNET_BLOCK_LIMIT.clone()
         } else {
@@ -766,6 +999,86 @@ impl Config {
             }
         };
 
+        let mempool = match config_file.mempool {
+            Some(opts) => {
+                let default = MemPoolSettings::default();
+                MemPoolSettings {
+                    replace_by_fee_min_bump_pct: opts
+                        .replace_by_fee_min_bump_pct
+                        .unwrap_or(default.replace_by_fee_min_bump_pct),
+                    max_tx_count: opts.max_tx_count.unwrap_or(default.max_tx_count),
+                    max_bytes: opts.max_bytes.unwrap_or(default.max_bytes),
+                    min_relay_fee_rate: opts
+                        .min_relay_fee_rate
+                        .unwrap_or(default.min_relay_fee_rate),
+                }
+            }
+            None => MemPoolSettings::default(),
+        };
+
+        let miner = match config_file.miner {
+            Some(opts) => {
+                let tx_selection = match opts.tx_selection {
+                    Some(tx_selection) => match tx_selection.as_str() {
+                        "fee_rate" => MinerTxSelection::FeeRate,
+                        "fifo" => MinerTxSelection::Fifo,
+                        _ => panic!(
+                            "Invalid miner.tx_selection -- expected \"fee_rate\" or \"fifo\""
+                        ),
+                    },
+                    None => MinerTxSelection::FeeRate,
+                };
+                let default = MinerConfig::default();
+                let burn_bid = match opts.burn_bid_strategy {
+                    Some(strategy) => match strategy.as_str() {
+                        "fixed" => MinerBurnBid::Fixed,
+                        "match_average" => MinerBurnBid::MatchAverage {
+                            bump_pct: opts.burn_bid_bump_pct.unwrap_or(10),
+                        },
+                        "expected_value" => MinerBurnBid::ExpectedValue {
+                            sats_per_stx: opts.sats_per_stx.expect(
+                                "Setting miner.burn_bid_strategy = \"expected_value\" requires miner.sats_per_stx",
+                            ),
+                        },
+                        _ => panic!(
+                            "Invalid miner.burn_bid_strategy -- expected \"fixed\", \"match_average\", or \"expected_value\""
+                        ),
+                    },
+                    None => MinerBurnBid::Fixed,
+                };
+                let allowed_contracts = opts.allowed_contracts.map(|contracts| {
+                    contracts
+                        .iter()
+                        .map(|contract| {
+                            QualifiedContractIdentifier::parse(contract).expect(
+                                "Setting miner.allowed_contracts must contain contract identifiers",
+                            )
+                        })
+                        .collect()
+                });
+                let priority_addresses = opts
+                    .priority_addresses
+                    .unwrap_or(vec![])
+                    .iter()
+                    .map(|addr| {
+                        StacksAddress::from_string(addr).expect(
+                            "Setting miner.priority_addresses must contain Stacks addresses",
+                        )
+                    })
+                    .collect();
+                MinerConfig {
+                    tx_selection,
+                    allowed_contracts,
+                    priority_addresses,
+                    burn_bid,
+                    burn_bid_window: opts.burn_bid_window.unwrap_or(default.burn_bid_window),
+                    min_burn_fee: opts.min_burn_fee.unwrap_or(default.min_burn_fee),
+                    burn_bid_dry_run: opts.burn_bid_dry_run.unwrap_or(default.burn_bid_dry_run),
+                }
+            }
+            None => MinerConfig::default(),
+        };
+
         Config {
             node,
             burnchain,
@@ -773,6 +1086,8 @@ impl Config {
             events_observers,
             connection_options,
             block_limit,
+            mempool,
+            miner,
         }
     }
 
@@ -797,6 +1112,19 @@ impl Config {
         )
     }
 
+    pub fn get_burnchain_db_file_path(&self) -> String {
+        let dir_name = if self.burnchain.mode.as_str() == "mocknet" {
+            "mocknet".to_string()
+        } else {
+            let (network, _) = self.burnchain.get_bitcoin_network();
+            network
+        };
+        format!(
+            "{}/burnchain/db/{}/{}/burnchain.db",
+            self.node.working_dir, self.burnchain.chain, dir_name
+        )
+    }
+
     pub fn get_chainstate_path(&self) -> String {
         format!("{}/chainstate/", self.node.working_dir)
     }
@@ -858,6 +1186,8 @@ impl std::default::Default for Config {
             events_observers: vec![],
             connection_options,
             block_limit,
+            mempool: MemPoolSettings::default(),
+            miner: MinerConfig::default(),
         }
     }
 }
@@ -883,6 +1213,12 @@ pub struct BurnchainConfig {
     pub burnchain_op_tx_fee: u64,
     pub process_exit_at_block_height: Option<u64>,
     pub poll_time_secs: u64,
+    /// how long to wait for a block-commit to confirm before re-sending it with a higher fee
+    pub block_commit_rbf_timeout_secs: u64,
+    /// how much to raise a stuck block-commit's fee rate by, in satoshis per byte
+    pub rbf_fee_increment: u64,
+    /// the highest fee rate a block-commit will be re-sent at, in satoshis per byte
+    pub max_rbf_fee_rate: u64,
 }
 
 impl BurnchainConfig {
@@ -907,6 +1243,9 @@ impl BurnchainConfig {
             burnchain_op_tx_fee: MINIMUM_DUST_FEE,
             process_exit_at_block_height: None,
             poll_time_secs: 10, // TODO: this is a testnet specific value.
+            block_commit_rbf_timeout_secs: 120,
+            rbf_fee_increment: 5,
+            max_rbf_fee_rate: 150,
         }
     }
 
@@ -957,6 +1296,9 @@ pub struct BurnchainConfigFile {
     pub burnchain_op_tx_fee: Option<u64>,
     pub process_exit_at_block_height: Option<u64>,
     pub poll_time_secs: Option<u64>,
+    pub block_commit_rbf_timeout_secs: Option<u64>,
+    pub rbf_fee_increment: Option<u64>,
+    pub max_rbf_fee_rate: Option<u64>,
 }
 
 #[derive(Clone, Debug, Default)]
@@ -970,6 +1312,7 @@ pub struct NodeConfig {
     pub p2p_address: String,
     pub local_peer_seed: Vec<u8>,
     pub bootstrap_node: Option<Neighbor>,
+    pub onion_addresses: Vec<OnionAddress>,
     pub deny_nodes: Vec<Neighbor>,
     pub miner: bool,
     pub mine_microblocks: bool,
@@ -979,6 +1322,9 @@ pub struct NodeConfig {
     pub prometheus_bind: Option<String>,
     pub pox_sync_sample_secs: u64,
     pub use_test_genesis_chainstate: Option<bool>,
+    pub prune_reward_cycles: Option<u64>,
+    /// size, in bytes, of the trie node cache of each MARF the node opens
+    pub marf_node_cache_bytes: usize,
 }
 
 impl NodeConfig {
@@ -1008,6 +1354,7 @@ impl NodeConfig {
             data_url: format!("http://127.0.0.1:{}", rpc_port),
             p2p_address: format!("127.0.0.1:{}", rpc_port),
             bootstrap_node: None,
+            onion_addresses: vec![],
             deny_nodes: vec![],
             local_peer_seed: local_peer_seed.to_vec(),
             miner: false,
@@ -1018,6 +1365,8 @@ impl NodeConfig {
             prometheus_bind: None,
             pox_sync_sample_secs: 30,
             use_test_genesis_chainstate: None,
+            prune_reward_cycles: None,
+            marf_node_cache_bytes: DEFAULT_NODE_CACHE_CAPACITY,
         }
     }
 
@@ -1054,11 +1403,28 @@ impl NodeConfig {
         }
     }
 
+    /// Parse a `<name>.onion:<port>` string.
+    fn parse_onion_address(addr: &str) -> Option<(OnionAddress, u16)> {
+        let comps: Vec<&str> = addr.rsplitn(2, ":").collect();
+        match comps[..] {
+            [port, host] => {
+                let port = port.parse::<u16>().ok()?;
+                let onion = OnionAddress::from_hostname(host)?;
+                Some((onion, port))
+            }
+            _ => None,
+        }
+    }
+
+    /// Set the bootstrap node from a `<pubkey>@<host>:<port>` string.  If `socks_proxy` is given,
+    /// the host name is resolved through the proxy (so no DNS queries leak from this node), and
+    /// onion hosts are permitted.
     pub fn set_bootstrap_node(
         &mut self,
         bootstrap_node: Option<String>,
         chain_id: u32,
         peer_version: u32,
+        socks_proxy: Option<(SocketAddr, u64)>,
     ) {
         if let Some(bootstrap_node) = bootstrap_node {
             let comps: Vec<&str> = bootstrap_node.split("@").collect();
@@ -1067,10 +1433,45 @@ impl NodeConfig {
                     let mut pubk = Secp256k1PublicKey::from_hex(public_key).unwrap();
                     pubk.set_compressed(true);
 
-                    let mut addrs_iter = peer_addr.to_socket_addrs().unwrap();
-                    let sock_addr = addrs_iter.next().unwrap();
-                    let neighbor =
-                        NodeConfig::default_neighbor(sock_addr, pubk, chain_id, peer_version);
+                    let (addrbytes, port) = match socks_proxy {
+                        Some((proxy, timeout_ms)) => {
+                            if let Some((onion, port)) = NodeConfig::parse_onion_address(peer_addr)
+                            {
+                                self.onion_addresses.push(onion.clone());
+                                (PeerAddress::from_onion(&onion), port)
+                            } else {
+                                let comps: Vec<&str> = peer_addr.rsplitn(2, ":").collect();
+                                match comps[..] {
+                                    [port, host] => {
+                                        let port = port
+                                            .parse::<u16>()
+                                            .expect("Bootstrap node port is not a number");
+                                        let addrbytes = socks::socks5_resolve(
+                                            &proxy, host, timeout_ms,
+                                        )
+                                        .expect(&format!(
+                                            "Failed to resolve {} via SOCKS proxy {}",
+                                            host, &proxy
+                                        ));
+                                        (addrbytes, port)
+                                    }
+                                    _ => panic!("Bootstrap node has no port: {}", peer_addr),
+                                }
+                            }
+                        }
+                        None => {
+                            let mut addrs_iter = peer_addr.to_socket_addrs().unwrap();
+                            let sock_addr = addrs_iter.next().unwrap();
+                            (PeerAddress::from_socketaddr(&sock_addr), sock_addr.port())
+                        }
+                    };
+
+                    let neighbor = NodeConfig::default_neighbor(
+                        addrbytes.to_socketaddr(port),
+                        pubk,
+                        chain_id,
+                        peer_version,
+                    );
                     self.bootstrap_node = Some(neighbor);
                 }
                 _ => {}
@@ -1132,6 +1533,19 @@ pub struct ConnectionOptionsFile {
     pub disable_inbound_walks: Option<bool>,
     pub disable_inbound_handshakes: Option<bool>,
     pub force_disconnect_interval: Option<u64>,
+    pub mempool_sync_interval: Option<u64>,
+    pub min_getmempool_interval: Option<u64>,
+    pub disable_mempool_sync: Option<bool>,
+    pub socks_proxy: Option<String>,
+    pub socks_proxy_timeout: Option<u64>,
+    pub public_onion_address: Option<String>,
+    pub bandwidth_quota_period: Option<u64>,
+    pub max_peer_upload_per_period: Option<u64>,
+    pub max_peer_download_per_period: Option<u64>,
+    pub max_peer_upload_bandwidth: Option<u64>,
+    pub max_upload_per_period: Option<u64>,
+    pub max_download_per_period: Option<u64>,
+    pub headers_first_push: Option<bool>,
 }
 
 #[derive(Clone, Default, Deserialize)]
@@ -1143,6 +1557,125 @@ pub struct BlockLimitFile {
     pub runtime: Option<u64>,
 }
 
+#[derive(Clone, Default, Deserialize)]
+pub struct MinerConfigFile {
+    pub tx_selection: Option<String>,
+    pub allowed_contracts: Option<Vec<String>>,
+    pub priority_addresses: Option<Vec<String>>,
+    pub burn_bid_strategy: Option<String>,
+    pub burn_bid_window: Option<u64>,
+    pub burn_bid_bump_pct: Option<u64>,
+    pub sats_per_stx: Option<u64>,
+    pub min_burn_fee: Option<u64>,
+    pub burn_bid_dry_run: Option<bool>,
+}
+
+/// How the miner orders the transactions it mines
+#[derive(Clone, Debug, PartialEq)]
+pub enum MinerTxSelection {
+    /// highest fee per unit of block budget first
+    FeeRate,
+    /// first come, first served
+    Fifo,
+}
+
+/// How the miner decides how much to commit to each block
+#[derive(Clone, Debug, PartialEq)]
+pub enum MinerBurnBid {
+    /// always commit `burnchain.burn_fee_cap`
+    Fixed,
+    /// outbid the other miners' recent average block-commit by `bump_pct` percent
+    MatchAverage { bump_pct: u64 },
+    /// commit whatever maximizes expected profit, valuing one STX at `sats_per_stx`
+    ExpectedValue { sats_per_stx: u64 },
+}
+
+#[derive(Clone, Debug)]
+pub struct MinerConfig {
+    pub tx_selection: MinerTxSelection,
+    /// if set, the only contract-calls the miner will mine are calls to these contracts
+    pub allowed_contracts: Option<Vec<QualifiedContractIdentifier>>,
+    /// transactions originated or sponsored by these addresses are always mined first
+    pub priority_addresses: Vec<StacksAddress>,
+    pub burn_bid: MinerBurnBid,
+    /// how many recent burnchain blocks to consider when choosing a bid
+    pub burn_bid_window: u64,
+    /// the least the miner will commit to a block; `burnchain.burn_fee_cap` is the most.  It's
+    /// raised if need be so that no block-commit output is dust.
+    pub min_burn_fee: u64,
+    /// if set, log the chosen bid but commit `burnchain.burn_fee_cap` anyway
+    pub burn_bid_dry_run: bool,
+}
+
+impl Default for MinerConfig {
+    fn default() -> MinerConfig {
+        MinerConfig {
+            tx_selection: MinerTxSelection::FeeRate,
+            allowed_contracts: None,
+            priority_addresses: vec![],
+            burn_bid: MinerBurnBid::Fixed,
+            burn_bid_window: 10,
+            min_burn_fee: MINIMUM_DUST_FEE * (OUTPUTS_PER_COMMIT as u64),
+            burn_bid_dry_run: false,
+        }
+    }
+}
+
+impl MinerConfig {
+    /// Instantiate the transaction selection policy this config describes
+    pub fn make_tx_selector(&self) -> Box<dyn MinerTxSelector> {
+        let mut selector: Box<dyn MinerTxSelector> = match self.tx_selection {
+            MinerTxSelection::FeeRate => Box::new(FeeRateTxSelector),
+            MinerTxSelection::Fifo => Box::new(FifoTxSelector),
+        };
+        if let Some(ref allowed_contracts) = self.allowed_contracts {
+            selector = Box::new(ContractAllowlistTxSelector::new(
+                allowed_contracts.iter().cloned().collect(),
+                selector,
+            ));
+        }
+        if self.priority_addresses.len() > 0 {
+            selector = Box::new(PriorityAddressTxSelector::new(
+                self.priority_addresses.iter().cloned().collect(),
+                selector,
+            ));
+        }
+        selector
+    }
+
+    /// Instantiate the burn-fee bidding strategy this config describes
+    pub fn make_burn_bid_strategy(&self, burn_fee_cap: u64) -> Box<dyn BurnBidStrategy> {
+        match self.burn_bid {
+            MinerBurnBid::Fixed => Box::new(FixedBurnBid {
+                amount: burn_fee_cap,
+            }),
+            MinerBurnBid::MatchAverage { bump_pct } => Box::new(MatchAverageBurnBid { bump_pct }),
+            MinerBurnBid::ExpectedValue { sats_per_stx } => Box::new(ExpectedValueBurnBid {
+                burn_per_stx: sats_per_stx,
+            }),
+        }
+    }
+
+    /// The range of burn fees the miner may commit to a block with `num_outputs` block-commit
+    /// outputs.  The least it commits gives every output at least the dust limit, unless that's
+    /// more than `burn_fee_cap`.
+    pub fn burn_bid_budget(&self, burn_fee_cap: u64, num_outputs: u64) -> BurnBidBudget {
+        let min_burn = cmp::max(self.min_burn_fee, MINIMUM_DUST_FEE * num_outputs);
+        BurnBidBudget {
+            min_burn: cmp::min(min_burn, burn_fee_cap),
+            max_burn: burn_fee_cap,
+        }
+    }
+}
+
+#[derive(Clone, Default, Deserialize)]
+pub struct MemPoolConfigFile {
+    pub replace_by_fee_min_bump_pct: Option<u64>,
+    pub max_tx_count: Option<u64>,
+    pub max_bytes: Option<u64>,
+    pub min_relay_fee_rate: Option<u64>,
+}
+
 #[derive(Clone, Deserialize, Default)]
 pub struct NodeConfigFile {
     pub name: Option<String>,
@@ -1163,6 +1696,8 @@ pub struct NodeConfigFile {
     pub prometheus_bind: Option<String>,
     pub pox_sync_sample_secs: Option<u64>,
     pub use_test_genesis_chainstate: Option<bool>,
+    pub prune_reward_cycles: Option<u64>,
+    pub marf_node_cache_bytes: Option<usize>,
 }
 
 #[derive(Clone, Deserialize, Default)]
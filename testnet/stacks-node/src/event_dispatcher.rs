@@ -1,6 +1,7 @@
 use stacks::chainstate::coordinator::BlockEventDispatcher;
 use stacks::chainstate::stacks::db::StacksHeaderInfo;
 use stacks::chainstate::stacks::StacksBlock;
+use stacks::core::mempool::{MemPoolDropReason, MemPoolEventDispatcher};
 use stacks::net::atlas::AttachmentInstance;
 use std::collections::hash_map::Entry;
 use std::thread::sleep;
@@ -42,6 +43,7 @@ const STATUS_RESP_NOT_COMMITTED: &str = "abort_by_response";
 const STATUS_RESP_POST_CONDITION: &str = "abort_by_post_condition";
 
 pub const PATH_MEMPOOL_TX_SUBMIT: &str = "new_mempool_tx";
+pub const PATH_MEMPOOL_TX_DROP: &str = "drop_mempool_tx";
 pub const PATH_BURN_BLOCK_SUBMIT: &str = "new_burn_block";
 pub const PATH_BLOCK_PROCESSED: &str = "new_block";
 pub const PATH_ATTACHMENT_PROCESSED: &str = "attachments/new";
@@ -120,6 +122,21 @@ impl EventObserver {
         serde_json::Value::Array(raw_txs)
     }
 
+    fn make_dropped_mempool_txs_payload(
+        txids: Vec<Txid>,
+        reason: MemPoolDropReason,
+    ) -> serde_json::Value {
+        let dropped_txids: Vec<_> = txids
+            .into_iter()
+            .map(|txid| serde_json::Value::String(format!("0x{}", &txid)))
+            .collect();
+
+        json!({
+            "dropped_txids": serde_json::Value::Array(dropped_txids),
+            "reason": reason.to_string(),
+        })
+    }
+
     fn make_new_burn_block_payload(
         burn_block: &BurnchainHeaderHash,
         burn_block_height: u64,
@@ -203,6 +220,10 @@ impl EventObserver {
         self.send_payload(payload, PATH_MEMPOOL_TX_SUBMIT);
     }
 
+    fn send_dropped_mempool_txs(&self, payload: &serde_json::Value) {
+        self.send_payload(payload, PATH_MEMPOOL_TX_DROP);
+    }
+
     fn send_new_burn_block(&self, payload: &serde_json::Value) {
         self.send_payload(payload, PATH_BURN_BLOCK_SUBMIT);
     }
@@ -307,6 +328,12 @@ impl BlockEventDispatcher for EventDispatcher {
     }
 }
 
+impl MemPoolEventDispatcher for EventDispatcher {
+    fn mempool_txs_dropped(&self, txids: Vec<Txid>, reason: MemPoolDropReason) {
+        self.process_dropped_mempool_txs(txids, reason)
+    }
+}
+
 impl EventDispatcher {
     pub fn new() -> EventDispatcher {
         EventDispatcher {
@@ -520,6 +547,28 @@ impl EventDispatcher {
         }
     }
 
+    pub fn process_dropped_mempool_txs(&self, txids: Vec<Txid>, reason: MemPoolDropReason) {
+        // lazily assemble payload only if we have observers
+        let interested_observers: Vec<_> = self
+            .registered_observers
+            .iter()
+            .enumerate()
+            .filter(|(obs_id, _observer)| {
+                self.mempool_observers_lookup.contains(&(*obs_id as u16))
+                    || self.any_event_observers_lookup.contains(&(*obs_id as u16))
+            })
+            .collect();
+        if interested_observers.len() < 1 {
+            return;
+        }
+
+        let payload = EventObserver::make_dropped_mempool_txs_payload(txids, reason);
+
+        for (_, observer) in interested_observers.iter() {
+            observer.send_dropped_mempool_txs(&payload);
+        }
+    }
+
     pub fn process_new_attachments(&self, attachments: &Vec<AttachmentInstance>) {
         let interested_observers: Vec<_> = self.registered_observers.iter().enumerate().collect();
         if interested_observers.len() < 1 {
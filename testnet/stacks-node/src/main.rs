@@ -43,6 +43,9 @@ pub use self::run_loop::{helium, neon};
 pub use self::tenure::Tenure;
 
 use pico_args::Arguments;
+use stacks::chainstate::stacks::db::check;
+use stacks::chainstate::stacks::db::snapshot;
+use stacks::chainstate::stacks::index::storage;
 use std::env;
 
 use std::convert::TryInto;
@@ -112,6 +115,14 @@ fn main() {
             println!("==> {}", config_path);
             ConfigFile::from_path(&config_path)
         }
+        "snapshot" => {
+            run_snapshot(args);
+            return;
+        }
+        "check-chainstate" => {
+            run_check_chainstate(args);
+            return;
+        }
         "version" => {
             println!(
                 "{}",
@@ -136,6 +147,8 @@ fn main() {
     debug!("connection configuration {:?}", &conf.connection_options);
     debug!("block_limit {:?}", &conf.block_limit);
 
+    storage::set_node_cache_capacity(conf.node.marf_node_cache_bytes);
+
     let num_round: u64 = 0; // Infinite number of rounds
 
     if conf.burnchain.mode == "helium" || conf.burnchain.mode == "mocknet" {
@@ -156,6 +169,128 @@ fn main() {
     }
 }
 
+/// Export or import a chainstate snapshot for the node described by a config file.
+fn run_snapshot(mut args: Arguments) {
+    let action = args.subcommand().unwrap().unwrap_or_default();
+    let config_path: String = args.value_from_str("--config").unwrap();
+    let conf = Config::from_config_file(ConfigFile::from_path(&config_path));
+
+    let res = match action.as_str() {
+        "export" => {
+            let output_dir: String = args.value_from_str("--output").unwrap();
+            args.finish().unwrap();
+            snapshot::export_snapshot(
+                conf.is_mainnet(),
+                conf.burnchain.chain_id,
+                &conf.get_chainstate_path(),
+                &conf.get_burn_db_file_path(),
+                &output_dir,
+            )
+        }
+        "import" => {
+            let input_dir: String = args.value_from_str("--input").unwrap();
+            args.finish().unwrap();
+            snapshot::import_snapshot(
+                conf.is_mainnet(),
+                conf.burnchain.chain_id,
+                &input_dir,
+                &conf.get_chainstate_path(),
+                &conf.get_burn_db_file_path(),
+                &conf.get_burnchain_db_file_path(),
+            )
+        }
+        _ => {
+            print_help();
+            return;
+        }
+    };
+
+    match res {
+        Ok(manifest) => {
+            println!(
+                "Snapshot at {}/{} (height {}, MARF root {})",
+                &manifest.tip_consensus_hash,
+                &manifest.tip_block_hash,
+                manifest.tip_height,
+                &manifest.marf_root_hash
+            );
+        }
+        Err(e) => {
+            eprintln!("Snapshot {} failed: {}", &action, &e);
+            process::exit(1);
+        }
+    }
+}
+
+/// Check the chainstate of the node described by a config file, and optionally repair it.
+fn run_check_chainstate(mut args: Arguments) {
+    let config_path: String = args.value_from_str("--config").unwrap();
+    let repair = args.contains("--repair");
+    args.finish().unwrap();
+    let conf = Config::from_config_file(ConfigFile::from_path(&config_path));
+
+    let report = match check::check_chainstate(
+        conf.is_mainnet(),
+        conf.burnchain.chain_id,
+        &conf.get_chainstate_path(),
+        &conf.get_burn_db_file_path(),
+        conf.node.prune_reward_cycles.is_some(),
+    ) {
+        Ok(report) => report,
+        Err(e) => {
+            eprintln!("Chainstate check failed: {}", &e);
+            process::exit(1);
+        }
+    };
+
+    println!(
+        "Checked {} blocks and {} accounts up to {} (height {})",
+        report.blocks_checked, report.accounts_checked, &report.tip, report.tip_height
+    );
+    if report.pending_blocks > 0 {
+        println!(
+            "{} blocks above the tip are waiting to be processed",
+            report.pending_blocks
+        );
+    }
+    for problem in report.problems.iter() {
+        println!("{}", problem);
+    }
+    if report.is_ok() {
+        println!("No problems found");
+        return;
+    }
+
+    if !repair {
+        eprintln!(
+            "Found {} problems; run with --repair to roll back to the last good block",
+            report.problems.len()
+        );
+        process::exit(1);
+    }
+
+    match check::repair_chainstate(
+        conf.is_mainnet(),
+        conf.burnchain.chain_id,
+        &conf.get_chainstate_path(),
+        &report,
+    ) {
+        Ok(repair) => {
+            println!(
+                "Rolled back to {} (height {}): {} blocks will be processed again and {} downloaded again when the node starts",
+                &repair.last_good_block,
+                repair.last_good_height,
+                repair.reprocess_blocks,
+                repair.refetch_blocks
+            );
+        }
+        Err(e) => {
+            eprintln!("Chainstate repair failed: {}", &e);
+            process::exit(1);
+        }
+    }
+}
+
 fn print_help() {
     let argv: Vec<_> = env::args().collect();
 
@@ -190,6 +325,26 @@ start\t\tStart a node with a config of your own. Can be used for joining a netwo
 \t\tExample:
 \t\t  stacks-node start --config=/path/to/config.toml
 
+snapshot\tExport or import a snapshot of the chainstate, for bootstrapping a node without replaying the chain.
+\t\tThe node must be stopped during import. Block bodies and the burnchain DB are not included.
+\t\tThe snapshot is checked against the node's own burnchain DB, which must already be synced past
+\t\tthe snapshot's burn block; the node's sortition DB and chainstate must not exist yet.
+\t\tArguments:
+\t\t  export|import: what to do.
+\t\t  --config: path of the node's config.
+\t\t  --output: (export) directory to write the snapshot to. Must not exist.
+\t\t  --input: (import) directory to read the snapshot from.
+\t\tExample:
+\t\t  stacks-node snapshot export --config=/path/to/config.toml --output=/path/to/snapshot
+
+check-chainstate\tCheck the integrity of the node's chainstate along the canonical chain. The node must be stopped.
+\t\tArguments:
+\t\t  --config: path of the node's config.
+\t\t  --repair: roll the chainstate back to the last good block, so the node processes the
+\t\t            rest of the chain again when it next starts.
+\t\tExample:
+\t\t  stacks-node check-chainstate --config=/path/to/config.toml --repair
+
 version\t\tDisplay information about the current version and our release cycle.
 
 help\t\tDisplay this help.
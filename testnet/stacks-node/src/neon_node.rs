@@ -1,5 +1,5 @@
 use super::{BurnchainController, BurnchainTip, Config, EventDispatcher, Keychain};
-use crate::config::HELIUM_BLOCK_LIMIT;
+use crate::config::{HELIUM_BLOCK_LIMIT, MINIMUM_DUST_FEE};
 use crate::run_loop::RegisteredKey;
 use std::collections::HashMap;
 
@@ -12,6 +12,7 @@ use std::sync::{Arc, Mutex};
 use std::{thread, thread::JoinHandle};
 
 use stacks::burnchains::{Burnchain, BurnchainHeaderHash, BurnchainParameters, Txid};
+use stacks::chainstate::burn::bidding::{split_commit_burn, BurnBidStats};
 use stacks::chainstate::burn::db::sortdb::{SortitionDB, SortitionId};
 use stacks::chainstate::burn::operations::{
     leader_block_commit::{RewardSetInfo, BURN_BLOCK_MINED_AT_MODULUS},
@@ -23,13 +24,17 @@ use stacks::chainstate::stacks::db::unconfirmed::UnconfirmedTxMap;
 use stacks::chainstate::stacks::db::{ChainStateBootData, ClarityTx, StacksChainState};
 use stacks::chainstate::stacks::Error as ChainstateError;
 use stacks::chainstate::stacks::StacksPublicKey;
-use stacks::chainstate::stacks::{miner::StacksMicroblockBuilder, StacksBlockBuilder};
+use stacks::chainstate::stacks::{
+    miner::{MinerTxSelector, StacksMicroblockBuilder},
+    StacksBlockBuilder,
+};
 use stacks::chainstate::stacks::{
     CoinbasePayload, StacksAddress, StacksBlock, StacksBlockHeader, StacksMicroblock,
     StacksTransaction, StacksTransactionSigner, TransactionAnchorMode, TransactionPayload,
     TransactionVersion,
 };
 use stacks::core::mempool::MemPoolDB;
+use stacks::core::miner_history::{MinedBlockAttempt, MinerHistoryDB};
 use stacks::net::{
     atlas::{AtlasConfig, AtlasDB, AttachmentInstance},
     db::{LocalPeer, PeerDB},
@@ -57,7 +62,11 @@ use stacks::core::FIRST_BURNCHAIN_CONSENSUS_HASH;
 use stacks::chainstate::coordinator::comm::CoordinatorChannels;
 use stacks::chainstate::coordinator::{get_next_recipients, OnChainRewardSetProvider};
 
-use stacks::monitoring::{increment_stx_blocks_mined_counter, update_active_miners_count_gauge};
+use stacks::monitoring::{
+    increment_miner_block_attempt_outcomes_counter, increment_miner_block_attempts_counter,
+    increment_miner_txs_skipped_counter, increment_stx_blocks_mined_counter,
+    update_active_miners_count_gauge,
+};
 
 pub const RELAYER_MAX_BUFFER: usize = 100;
 
@@ -274,12 +283,81 @@ fn inner_generate_block_commit_op(
     })
 }
 
+/// Decide how much to commit to `anchored_block`, based on the competing block-commits in the
+/// last few sortitions and on what the block is worth.  The bid never exceeds `burn_fee_cap`,
+/// and is otherwise enough to keep each of the block-commit's `num_outputs` outputs from being
+/// dust.  In dry-run mode, the chosen bid is only logged and `burn_fee_cap` is committed
+/// instead.
+fn choose_burn_fee(
+    config: &Config,
+    burn_db: &SortitionDB,
+    burnchain: &Burnchain,
+    burn_block: &BlockSnapshot,
+    sender: &BurnchainSigner,
+    anchored_block: &StacksBlock,
+    burn_fee_cap: u64,
+    num_outputs: u64,
+) -> u64 {
+    let stats = match BurnBidStats::from_sortitions(
+        &burn_db.index_conn(),
+        burn_block,
+        config.miner.burn_bid_window,
+        sender,
+    ) {
+        Ok(stats) => stats,
+        Err(e) => {
+            warn!(
+                "Failed to load recent block-commits; bidding burn_fee_cap: {:?}",
+                &e
+            );
+            return burn_fee_cap;
+        }
+    };
+
+    let tx_fees: u128 = anchored_block
+        .txs
+        .iter()
+        .map(|tx| tx.get_tx_fee() as u128)
+        .sum();
+    let coinbase = StacksChainState::get_coinbase_reward(
+        burn_block.block_height + 1,
+        burnchain.first_block_height,
+    );
+    let expected_reward = coinbase + tx_fees;
+
+    let strategy = config.miner.make_burn_bid_strategy(burn_fee_cap);
+    let bid = config
+        .miner
+        .burn_bid_budget(burn_fee_cap, num_outputs)
+        .clamp(strategy.choose_bid(&stats, expected_reward));
+    let burn_fee = if config.miner.burn_bid_dry_run {
+        burn_fee_cap
+    } else {
+        bid
+    };
+
+    info!(
+        "Burn bid";
+        "strategy" => ?config.miner.burn_bid,
+        "bid" => bid,
+        "burn_fee" => burn_fee,
+        "dry_run" => config.miner.burn_bid_dry_run,
+        "expected_reward" => expected_reward,
+        "num_blocks" => stats.num_blocks,
+        "num_contested" => stats.num_contested,
+        "avg_others_block_burn" => stats.avg_others_block_burn(),
+        "avg_others_commit_burn" => stats.avg_others_commit_burn()
+    );
+    burn_fee
+}
+
 /// Mine and broadcast a single microblock, unconditionally.
 fn mine_one_microblock(
     microblock_state: &mut MicroblockMinerState,
     sortdb: &SortitionDB,
     chainstate: &mut StacksChainState,
     mempool: &MemPoolDB,
+    tx_selector: &dyn MinerTxSelector,
 ) -> Result<StacksMicroblock, NetError> {
     debug!(
         "Try to mine one microblock off of {}/{} (at seq {})",
@@ -308,7 +386,11 @@ fn mine_one_microblock(
                 }
             };
 
-        let mblock = microblock_miner.mine_next_microblock(mempool, &microblock_state.miner_key)?;
+        let mblock = microblock_miner.mine_next_microblock_with_selector(
+            mempool,
+            &microblock_state.miner_key,
+            tx_selector,
+        )?;
 
         info!("Minted microblock with {} transactions", mblock.txs.len());
 
@@ -404,6 +486,7 @@ fn try_mine_microblock(
                             sortdb,
                             chainstate,
                             &mem_pool,
+                            &*config.miner.make_tx_selector(),
                         ) {
                             Ok(microblock) => {
                                 // will need to relay this
@@ -546,6 +629,7 @@ fn spawn_peer(
     mut sync_comms: PoxSyncWatchdogComms,
     attachments_rx: Receiver<HashSet<AttachmentInstance>>,
     unconfirmed_txs: Arc<Mutex<UnconfirmedTxMap>>,
+    event_dispatcher: EventDispatcher,
 ) -> Result<JoinHandle<()>, NetError> {
     let burn_db_path = config.get_burn_db_file_path();
     let stacks_chainstate_path = config.get_chainstate_path();
@@ -564,10 +648,11 @@ fn spawn_peer(
     )
     .map_err(|e| NetError::ChainstateError(e.to_string()))?;
 
-    let mut mem_pool = MemPoolDB::open(
+    let mut mem_pool = MemPoolDB::open_with_settings(
         is_mainnet,
         config.burnchain.chain_id,
         &stacks_chainstate_path,
+        config.mempool.clone(),
     )
     .map_err(NetError::DBError)?;
 
@@ -581,6 +666,7 @@ fn spawn_peer(
                 exit_at_block_height: exit_at_block_height.as_ref(),
                 genesis_chainstate_hash: Sha256Sum::from_hex(stx_genesis::GENESIS_CHAINSTATE_HASH)
                     .unwrap(),
+                event_observer: Some(&event_dispatcher),
                 ..RPCHandlerArgs::default()
             };
 
@@ -730,8 +816,49 @@ fn spawn_miner_relayer(
     )
     .map_err(|e| NetError::ChainstateError(e.to_string()))?;
 
-    let mut mem_pool = MemPoolDB::open(is_mainnet, chain_id, &stacks_chainstate_path)
-        .map_err(NetError::DBError)?;
+    let mut mem_pool = MemPoolDB::open_with_settings(
+        is_mainnet,
+        chain_id,
+        &stacks_chainstate_path,
+        config.mempool.clone(),
+    )
+    .map_err(NetError::DBError)?;
+
+    // the mempool outlives the node process, so before mining from it, drop whatever can no
+    // longer be mined at the chain tip we're starting from
+    match SortitionDB::get_canonical_stacks_chain_tip_hash(sortdb.conn()).and_then(
+        |(consensus_hash, block_hash)| {
+            mem_pool.revalidate(
+                &mut chainstate,
+                &consensus_hash,
+                &block_hash,
+                Some(&event_dispatcher),
+            )
+        },
+    ) {
+        Ok(stats) => {
+            info!("Revalidated mempool";
+                  "checked" => stats.num_checked,
+                  "kept" => stats.num_kept,
+                  "dropped" => stats.dropped.len());
+        }
+        Err(e) => {
+            warn!("Failed to revalidate mempool: {:?}", &e);
+        }
+    }
+
+    // record what we mine, so operators can see why our blocks lose or are light
+    let mut miner_history = if config.node.miner {
+        match MinerHistoryDB::open(&stacks_chainstate_path, true) {
+            Ok(history) => Some(history),
+            Err(e) => {
+                warn!("Failed to open miner history db: {:?}", &e);
+                None
+            }
+        }
+    } else {
+        None
+    };
 
     let mut last_mined_blocks: HashMap<
         BurnchainHeaderHash,
@@ -759,6 +886,7 @@ fn spawn_miner_relayer(
                             &mut chainstate,
                             &mut mem_pool,
                             Some(&coord_comms),
+                            Some(&event_dispatcher),
                         )
                         .expect("BUG: failure processing network results");
 
@@ -892,6 +1020,9 @@ fn spawn_miner_relayer(
                         .remove(&burn_header_hash)
                         .unwrap_or_default();
 
+                    // stop tracking a block-commit whose sortition has closed
+                    bitcoin_controller.set_burn_tip_height(last_burn_block.block_height);
+
                     let last_mined_block_opt = InitializedNeonNode::relayer_run_tenure(
                         &config,
                         registered_key,
@@ -904,6 +1035,7 @@ fn spawn_miner_relayer(
                         burn_fee_cap,
                         &mut bitcoin_controller,
                         &last_mined_blocks_vec.iter().map(|(blk, _)| blk).collect(),
+                        miner_history.as_mut(),
                     );
                     if let Some((last_mined_block, microblock_privkey)) = last_mined_block_opt {
                         if last_mined_blocks_vec.len() == 0 {
@@ -911,6 +1043,11 @@ fn spawn_miner_relayer(
                             bump_processed_counter(&blocks_processed);
                         }
                         last_mined_blocks_vec.push((last_mined_block, microblock_privkey));
+                    } else if last_mined_blocks_vec.len() > 0 {
+                        // we already sent a block-commit in this burnchain block, and it can
+                        // still win this sortition.  Bump its fee if it's stuck.
+                        bitcoin_controller
+                            .rbf_ongoing_block_commit(&mut keychain.generate_op_signer());
                     }
                     last_mined_blocks.insert(burn_header_hash, last_mined_blocks_vec);
                 }
@@ -1056,6 +1193,9 @@ impl InitializedNeonNode {
                 )
                 .unwrap();
             }
+            for onion in config.node.onion_addresses.iter() {
+                PeerDB::set_onion_address(&mut tx, onion).unwrap();
+            }
             tx.commit().unwrap();
         }
         let atlasdb = AtlasDB::connect(atlas_config, &config.get_atlas_db_path(), true).unwrap();
@@ -1094,7 +1234,7 @@ impl InitializedNeonNode {
             config.get_burn_db_file_path(),
             config.get_chainstate_path(),
             relay_recv,
-            event_dispatcher,
+            event_dispatcher.clone(),
             blocks_processed.clone(),
             burnchain,
             coord_comms.clone(),
@@ -1113,6 +1253,7 @@ impl InitializedNeonNode {
             sync_comms,
             attachments_rx,
             shared_unconfirmed_txs.clone(),
+            event_dispatcher,
         )
         .expect("Failed to initialize mine/relay thread");
 
@@ -1204,6 +1345,7 @@ impl InitializedNeonNode {
         burn_fee_cap: u64,
         bitcoin_controller: &mut BitcoinRegtestController,
         last_mined_blocks: &Vec<&AssembledAnchorBlock>,
+        miner_history: Option<&mut MinerHistoryDB>,
     ) -> Option<(AssembledAnchorBlock, Secp256k1PrivateKey)> {
         let (
             mut stacks_parent_header,
@@ -1527,6 +1669,7 @@ impl InitializedNeonNode {
                     &parent_consensus_hash,
                     &stacks_parent_header.anchored_header.block_hash(),
                     &poison_microblock_tx,
+                    None,
                 ) {
                     warn!(
                         "Detected but failed to mine poison-microblock transaction: {:?}",
@@ -1536,23 +1679,25 @@ impl InitializedNeonNode {
             }
         }
 
-        let (anchored_block, _, _) = match StacksBlockBuilder::build_anchored_block(
-            chain_state,
-            &burn_db.index_conn(),
-            mem_pool,
-            &stacks_parent_header,
-            parent_block_total_burn,
-            vrf_proof.clone(),
-            mblock_pubkey_hash,
-            &coinbase_tx,
-            HELIUM_BLOCK_LIMIT.clone(),
-        ) {
-            Ok(block) => block,
-            Err(e) => {
-                error!("Failure mining anchored block: {}", e);
-                return None;
-            }
-        };
+        let (anchored_block, consumed, size, report) =
+            match StacksBlockBuilder::build_anchored_block_with_report(
+                chain_state,
+                &burn_db.index_conn(),
+                mem_pool,
+                &stacks_parent_header,
+                parent_block_total_burn,
+                vrf_proof.clone(),
+                mblock_pubkey_hash,
+                &coinbase_tx,
+                HELIUM_BLOCK_LIMIT.clone(),
+                &*config.miner.make_tx_selector(),
+            ) {
+                Ok(block) => block,
+                Err(e) => {
+                    error!("Failure mining anchored block: {}", e);
+                    return None;
+                }
+            };
 
         info!(
             "{} block assembled: {}, with {} txs, attempt {}",
@@ -1581,9 +1726,6 @@ impl InitializedNeonNode {
             }
         };
 
-        let sunset_burn = burnchain.expected_sunset_burn(burn_block.block_height + 1, burn_fee_cap);
-        let rest_commit = burn_fee_cap - sunset_burn;
-
         let commit_outs = if burn_block.block_height + 1 < burnchain.pox_constants.sunset_end
             && !burnchain.is_in_prepare_phase(burn_block.block_height + 1)
         {
@@ -1592,6 +1734,37 @@ impl InitializedNeonNode {
             vec![StacksAddress::burn_address(config.is_mainnet())]
         };
 
+        let burn_fee = choose_burn_fee(
+            config,
+            burn_db,
+            burnchain,
+            &burn_block,
+            &keychain.get_burnchain_signer(),
+            &anchored_block,
+            burn_fee_cap,
+            commit_outs.len() as u64,
+        );
+        let sunset_burn = burnchain.expected_sunset_burn(burn_block.block_height + 1, burn_fee);
+
+        // the outputs each get the same share of what's left, and it can't be dust
+        let rest_commit = match split_commit_burn(
+            burn_fee - sunset_burn,
+            commit_outs.len() as u64,
+            MINIMUM_DUST_FEE,
+        ) {
+            Some(rest_commit) => rest_commit,
+            None => {
+                warn!(
+                    "Burn fee {} (less sunset burn {}) is too low to commit at least {} to each of {} outputs",
+                    burn_fee,
+                    sunset_burn,
+                    MINIMUM_DUST_FEE,
+                    commit_outs.len()
+                );
+                return None;
+            }
+        };
+
         // let's commit
         let op = inner_generate_block_commit_op(
             keychain.get_burnchain_signer(),
@@ -1621,6 +1794,28 @@ impl InitializedNeonNode {
             return None;
         }
 
+        increment_miner_block_attempts_counter();
+        for (_, reason) in report.skipped.iter() {
+            increment_miner_txs_skipped_counter(reason.name());
+        }
+        if let Some(miner_history) = miner_history {
+            let mined_attempt = MinedBlockAttempt::new(
+                burn_block.block_height,
+                burn_block.burn_header_hash.clone(),
+                parent_consensus_hash.clone(),
+                &anchored_block,
+                stacks_parent_header.block_height + 1,
+                attempt,
+                report,
+                consumed,
+                size,
+                burn_fee,
+            );
+            if let Err(e) = miner_history.insert_attempt(&mined_attempt) {
+                warn!("Failed to record mined block attempt: {:?}", &e);
+            }
+        }
+
         Some((
             AssembledAnchorBlock {
                 parent_consensus_hash: parent_consensus_hash,
@@ -1632,6 +1827,34 @@ impl InitializedNeonNode {
         ))
     }
 
+    /// Record whether or not our pending mined block attempts won the given sortition
+    fn resolve_mined_block_attempts(&self, block_snapshot: &BlockSnapshot) {
+        let winning_block_hash = if block_snapshot.sortition {
+            Some(&block_snapshot.winning_stacks_block_hash)
+        } else {
+            None
+        };
+        let resolved = MinerHistoryDB::open(&self.config.get_chainstate_path(), true).and_then(
+            |mut history| {
+                history.resolve_sortition(block_snapshot.block_height, winning_block_hash)
+            },
+        );
+        match resolved {
+            Ok(attempts) => {
+                for attempt in attempts.iter() {
+                    debug!("Mined block attempt resolved";
+                           "block_hash" => %attempt.block_hash,
+                           "burn_height" => attempt.burn_block_height,
+                           "outcome" => %attempt.outcome);
+                    increment_miner_block_attempt_outcomes_counter(attempt.outcome.as_str());
+                }
+            }
+            Err(e) => {
+                warn!("Failed to resolve mined block attempts: {:?}", &e);
+            }
+        }
+    }
+
     /// Process a state coming from the burnchain, by extracting the validated KeyRegisterOp
     /// and inspecting if a sortition was won.
     /// `ibd`: boolean indicating whether or not we are in the initial block download
@@ -1708,6 +1931,10 @@ impl InitializedNeonNode {
             }
         }
 
+        if self.is_miner {
+            self.resolve_mined_block_attempts(&block_snapshot);
+        }
+
         // no-op on UserBurnSupport ops are not supported / produced at this point.
         self.last_burn_block = Some(block_snapshot);
 
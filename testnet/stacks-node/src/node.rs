@@ -22,7 +22,7 @@ use stacks::chainstate::stacks::{
     StacksTransaction, StacksTransactionSigner, TransactionAnchorMode, TransactionPayload,
     TransactionVersion,
 };
-use stacks::core::mempool::MemPoolDB;
+use stacks::core::mempool::{MemPoolDB, MemPoolSettings};
 use stacks::net::{
     atlas::{AtlasConfig, AtlasDB},
     db::PeerDB,
@@ -155,6 +155,7 @@ fn spawn_peer(
     exit_at_block_height: Option<u64>,
     genesis_chainstate_hash: Sha256Sum,
     poll_timeout: u64,
+    mempool_settings: MemPoolSettings,
 ) -> Result<JoinHandle<()>, NetError> {
     this.bind(p2p_sock, rpc_sock).unwrap();
     let server_thread = thread::spawn(move || {
@@ -183,8 +184,12 @@ fn spawn_peer(
                     }
                 };
 
-            let mut mem_pool = match MemPoolDB::open(is_mainnet, chain_id, &stacks_chainstate_path)
-            {
+            let mut mem_pool = match MemPoolDB::open_with_settings(
+                is_mainnet,
+                chain_id,
+                &stacks_chainstate_path,
+                mempool_settings.clone(),
+            ) {
                 Ok(x) => x,
                 Err(e) => {
                     warn!("Error while connecting to mempool db in peer loop: {}", e);
@@ -438,6 +443,9 @@ impl Node {
                 )
                 .unwrap();
             }
+            for onion in self.config.node.onion_addresses.iter() {
+                PeerDB::set_onion_address(&mut tx, onion).unwrap();
+            }
             tx.commit().unwrap();
         }
         let atlas_config = AtlasConfig::default();
@@ -473,6 +481,7 @@ impl Node {
             exit_at_block_height,
             Sha256Sum::from_hex(stx_genesis::GENESIS_CHAINSTATE_HASH).unwrap(),
             1000,
+            self.config.mempool.clone(),
         )
         .unwrap();
 
@@ -606,10 +615,11 @@ impl Node {
             },
         };
 
-        let mem_pool = MemPoolDB::open(
+        let mem_pool = MemPoolDB::open_with_settings(
             self.config.is_mainnet(),
             self.config.burnchain.chain_id,
             &self.chain_state.root_path,
+            self.config.mempool.clone(),
         )
         .expect("FATAL: failed to open mempool");
 
@@ -214,7 +214,7 @@ impl RunLoop {
             get_bulk_initial_names: Some(Box::new(|| get_names(USE_TEST_GENESIS_CHAINSTATE))),
         };
 
-        let (chain_state_db, receipts) = StacksChainState::open_and_exec(
+        let (mut chain_state_db, receipts) = StacksChainState::open_and_exec(
             mainnet,
             chainid,
             &chainstate_path,
@@ -224,8 +224,12 @@ impl RunLoop {
         .unwrap();
         coordinator_dispatcher.dispatch_boot_receipts(receipts);
 
+        // the coordinator discards old block bodies as the burnchain advances, if so configured
+        chain_state_db.prune_reward_cycles = self.config.node.prune_reward_cycles;
+
         let atlas_config = AtlasConfig::default();
         let moved_atlas_config = atlas_config.clone();
+        let mempool_settings = self.config.mempool.clone();
 
         thread::Builder::new()
             .name("chains-coordinator".to_string())
@@ -237,6 +241,7 @@ impl RunLoop {
                     &mut coordinator_dispatcher,
                     coordinator_receivers,
                     moved_atlas_config,
+                    mempool_settings,
                 );
             })
             .unwrap();
@@ -1659,6 +1659,7 @@ fn atlas_integration_test() {
         Some(bootstrap_node_url),
         conf_follower_node.burnchain.chain_id,
         conf_follower_node.burnchain.peer_version,
+        None,
     );
     conf_follower_node.node.miner = false;
     conf_follower_node